@@ -1,3 +1,4 @@
+use std::collections::HashSet;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
@@ -11,21 +12,29 @@ use tokio_util::sync::CancellationToken;
 
 use brokers::broker::{Broker, ChannelMessageBroker};
 use brokers::exchange::Exchange;
+use brokers::fees::FeeTier;
 use brokers::plugin::gather_plugins;
 use brokers::types::MarketEventEnvelope;
-use brokers::types::{MarketChannel, MarketChannelTopic};
+use brokers::types::{MarketChannel, MarketChannelTopic, MarketChannelType, SecurityType};
 use brokers::Brokerages;
 use db::{get_or_create, DbOptions};
 use strategy::driver::{StratProviderRef, Strategy, StrategyInitContext};
-use strategy::prelude::{GenericDriver, GenericDriverOptions, PortfolioOptions};
-use trading::engine::mock_engine;
+use strategy::prelude::{GenericDriver, GenericDriverOptions, PortfolioOptions, PositionMode, StrategyDriverSettings};
+use strategy::settings::StrategyDriverOptions;
+use trading::capital::SharedCapital;
+use trading::engine::{mock_engine, mock_engine_with_api, TradingEngine};
 use util::compress::Compression;
-use util::time::DateRange;
+use util::time::{utc_at_midnight, DateRange};
 
+use crate::checkpoint::{CheckpointStore, Checkpointer};
 use crate::config::BacktestConfig;
-use crate::dataset::{DatasetCatalog, DatasetReader};
+use crate::dataset::{DatasetCatalog, DatasetReader, MarketEventDatasetType};
 use crate::error::*;
-use crate::report::{BacktestReport, GlobalReport, ReportConfig};
+use crate::fill::{FillModel, SimulatedBrokerage};
+use crate::incremental::{first_new_day, PreviousRun};
+use crate::manifest::{code_version, partition_checksums, sha256_hex, RunManifest, MANIFEST_FILE};
+use crate::report::{record_benchmark, BacktestReport, BenchmarkSeries, GlobalReport, ReportConfig,
+                    BENCHMARK_QUEUE_SIZE};
 use crate::runner::BacktestRunner;
 
 pub(crate) async fn init_brokerages(xchs: &[Exchange]) {
@@ -33,24 +42,44 @@ pub(crate) async fn init_brokerages(xchs: &[Exchange]) {
     Brokerages::load_pair_registries(&exchange_apis).await.unwrap();
 }
 
+/// The channels of a runner, and if set the funding rates of the futures it trades so that portfolios settle funding
+async fn runner_channels(runner: &BacktestRunner, with_funding: bool) -> HashSet<MarketChannel> {
+    let mut channels = runner.channels().await;
+    if with_funding {
+        let funding_channels: Vec<MarketChannel> = channels
+            .iter()
+            .filter(|c| c.symbol.r#type == SecurityType::Future)
+            .map(|c| {
+                MarketChannel::builder()
+                    .symbol(c.symbol.clone())
+                    .r#type(MarketChannelType::FundingRate)
+                    .build()
+            })
+            .collect();
+        channels.extend(funding_channels);
+    }
+    channels
+}
+
 async fn build_msg_broker(
     runners: &[Arc<RwLock<BacktestRunner>>],
+    with_funding: bool,
 ) -> ChannelMessageBroker<MarketChannelTopic, MarketEventEnvelope> {
     let mut broker = ChannelMessageBroker::new();
     for runner in runners {
         let runner = runner.read().await;
-        for channel in runner.channels().await {
+        for channel in runner_channels(&runner, with_funding).await {
             broker.register(channel.into(), runner.event_sink());
         }
     }
     broker
 }
 
-async fn get_channels(runners: &[Arc<RwLock<BacktestRunner>>]) -> Vec<MarketChannel> {
+async fn get_channels(runners: &[Arc<RwLock<BacktestRunner>>], with_funding: bool) -> Vec<MarketChannel> {
     let mut channels = vec![];
     for runner in runners {
         let runner = runner.read().await;
-        channels.extend(runner.channels().await);
+        channels.extend(runner_channels(&runner, with_funding).await);
     }
     channels
 }
@@ -76,6 +105,15 @@ pub struct Backtest {
     stop_token: CancellationToken,
     report_conf: ReportConfig,
     period: DateRange,
+    replay_speed: Option<f64>,
+    fill_simulation: Option<Arc<SimulatedBrokerage>>,
+    shared_capital: Option<Arc<SharedCapital>>,
+    /// What the results of the run depend on, partitions are added as they are known
+    manifest: RunManifest,
+    verify_manifest: Option<PathBuf>,
+    checkpointer: Option<Checkpointer>,
+    /// The run continued by an incremental backtest
+    previous_run: Option<PreviousRun>,
 }
 
 impl Backtest {
@@ -83,11 +121,216 @@ impl Backtest {
     ///
     /// if copying strats and spawning runners fail
     pub async fn try_new(conf: &BacktestConfig) -> Result<Self> {
-        let output_path = conf.output_dir();
+        check_replay_speed(conf)?;
         let all_strategy_settings = conf.all_strategy_settings().await;
         let db_conf = conf.db_conf();
-        let mock_engine = Arc::new(mock_engine(db_conf.path.clone(), &[Exchange::Binance]));
+        let mut backtest = Self::try_new_with(
+            conf,
+            all_strategy_settings,
+            conf.period.as_range(),
+            conf.output_dir(),
+            db_conf.clone(),
+        )
+        .await?;
+        backtest.checkpointer = checkpointer(conf, &db_conf, false)?;
+        Ok(backtest)
+    }
+
+    /// Resume a backtest from the last checkpoints of its strategies, the database of the configuration is kept
+    ///
+    /// # Errors
+    ///
+    /// The configuration has no checkpoint settings or database path
+    pub async fn try_resume(conf: &BacktestConfig) -> Result<Self> {
+        check_replay_speed(conf)?;
+        let db_path = conf
+            .db_path
+            .clone()
+            .ok_or_else(|| anyhow!("resuming a backtest requires a db path"))?;
+        let db_conf = conf.db_conf_at(db_path);
+        let checkpointer = checkpointer(conf, &db_conf, true)?.ok_or_else(|| anyhow!("missing checkpoint settings"))?;
+        let checkpoints = checkpointer.store.all()?;
+        let all_strategy_settings = conf.all_strategy_settings().await;
+        let period = conf.period.as_range();
+        let mut backtest = Self::try_new_with(conf, all_strategy_settings, period, conf.output_dir(), db_conf).await?;
+        // Events are read again from the day of the earliest checkpoint, or from the start for strategies without one
+        let mut from = period.1;
+        for runner in &backtest.runners {
+            let key = runner.read().await.key().await;
+            from = from.min(checkpoints.get(&key).map_or(period.0, |c| c.at));
+        }
+        backtest.period = DateRange::by_day(utc_at_midnight(from).max(period.0), period.1);
+        info!(from = %backtest.period.0, checkpoints = checkpoints.len(), "resuming backtest");
+        backtest.checkpointer = Some(checkpointer);
+        Ok(backtest)
+    }
+
+    /// A backtest of strategies over a period, with the data and runner settings of a configuration
+    pub(crate) async fn try_new_with(
+        conf: &BacktestConfig,
+        all_strategy_settings: Vec<StrategyDriverSettings>,
+        period: DateRange,
+        output_path: PathBuf,
+        db_conf: DbOptions<PathBuf>,
+    ) -> Result<Self> {
+        let seed = conf.seed.unwrap_or_else(rand::random);
+        let fill = conf.fill.clone().unwrap_or_default();
+        check_fill_model(fill.model)?;
+        let flat_fees = FeeTier {
+            maker: conf.fees,
+            taker: conf.fees,
+        };
+        let brokerage =
+            Arc::new(SimulatedBrokerage::new(&fill, flat_fees, seed).with_fee_schedules(conf.fee_schedules.clone()));
+        let (engine, order_manager) = mock_engine_with_api(db_conf.path.clone(), brokerage.clone());
+        brokerage.set_order_manager(order_manager.recipient());
+        let fill_simulation = conf.fill.is_some().then_some(brokerage);
+        Self::try_new_with_engine(
+            conf,
+            all_strategy_settings,
+            period,
+            output_path,
+            db_conf,
+            engine,
+            fill_simulation,
+            seed,
+        )
+        .await
+    }
+
+    /// A backtest of strategies trading through the order manager of the live driver, with the default mock
+    /// brokerage instead of the simulated one, orders of dry mode strategies are simulated by the order manager
+    pub(crate) async fn try_new_live(
+        conf: &BacktestConfig,
+        all_strategy_settings: Vec<StrategyDriverSettings>,
+        period: DateRange,
+        output_path: PathBuf,
+        db_conf: DbOptions<PathBuf>,
+    ) -> Result<Self> {
+        let seed = conf.seed.unwrap_or_else(rand::random);
+        let engine = mock_engine(db_conf.path.clone(), &[Exchange::Binance]);
+        Self::try_new_with_engine(
+            conf,
+            all_strategy_settings,
+            period,
+            output_path,
+            db_conf,
+            engine,
+            None,
+            seed,
+        )
+        .await
+    }
+
+    /// Continue the latest run of a configuration on the days it did not process, appending to its reports, the
+    /// database of the configuration is kept, a full backtest is run if there is no previous run
+    ///
+    /// # Errors
+    ///
+    /// The configuration has no database path, its reports are compressed with an algorithm that cannot be
+    /// appended to, strategies changed since the latest run, or datasets of days it processed changed
+    pub async fn try_incremental(conf: &BacktestConfig) -> Result<Self> {
+        check_replay_speed(conf)?;
+        let report_dir = conf.output_dir().join("latest");
+        let Ok(previous) = RunManifest::load(report_dir.join(MANIFEST_FILE)) else {
+            info!("no previous run to continue, running a full backtest");
+            return Self::try_new(conf).await;
+        };
+        if !conf.report.compression.is_appendable() {
+            return Err(anyhow!(
+                "reports compressed with {:?} cannot be appended to",
+                conf.report.compression.algorithm
+            )
+            .into());
+        }
+        let db_path = conf
+            .db_path
+            .clone()
+            .ok_or_else(|| anyhow!("an incremental backtest requires a db path"))?;
+        let all_strategy_settings = conf.all_strategy_settings().await;
+        let period = conf.period.as_range();
+        let mut backtest = Self::try_new_with(
+            conf,
+            all_strategy_settings,
+            period,
+            conf.output_dir(),
+            conf.db_conf_at(db_path),
+        )
+        .await?;
+        if backtest.manifest.strategies_hash != previous.strategies_hash {
+            return Err(anyhow!("strategies changed since the previous run, run a full backtest").into());
+        }
+        let with_funding = backtest
+            .dataset
+            .catalog
+            .get(MarketEventDatasetType::FundingRates)
+            .is_some();
+        let channels = get_channels(&backtest.runners, with_funding).await;
+        let mut day_dirs = vec![];
+        for day in period {
+            day_dirs.push((
+                day,
+                backtest
+                    .dataset
+                    .partition_dirs(&channels, DateRange::by_day(day, day))
+                    .await,
+            ));
+        }
+        let days = tokio::task::spawn_blocking(move || {
+            day_dirs
+                .into_iter()
+                .map(|(day, dirs)| Ok((day, partition_checksums(&dirs)?)))
+                .collect::<Result<Vec<_>>>()
+        })
+        .await
+        .map_err(|e| anyhow!(e))??;
+        // Events of the last day processed by the previous run are skipped until the end of the previous run
+        let from = first_new_day(&previous, &days)?.unwrap_or_else(|| utc_at_midnight(period.1));
+        info!(from = %from, previous_to = %previous.to, "continuing the previous run");
+        backtest.period = DateRange::by_day(from, period.1);
+        backtest.previous_run = Some(PreviousRun {
+            report_dir: std::fs::canonicalize(&report_dir)?,
+            manifest: previous,
+        });
+        Ok(backtest)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn try_new_with_engine(
+        conf: &BacktestConfig,
+        all_strategy_settings: Vec<StrategyDriverSettings>,
+        period: DateRange,
+        output_path: PathBuf,
+        db_conf: DbOptions<PathBuf>,
+        mut mock_engine: TradingEngine,
+        fill_simulation: Option<Arc<SimulatedBrokerage>>,
+        seed: u64,
+    ) -> Result<Self> {
+        // Portfolios anticipate the fees of the schedule of each exchange
+        for (xch, tier) in &conf.fee_schedules {
+            mock_engine.exchange_manager.set_fee_tier(*xch, *tier);
+        }
+        let shared_capital = conf.shared_capital.as_ref().map(|s| Arc::new(SharedCapital::new(s)));
+        mock_engine.shared_capital = shared_capital.clone();
+        let mock_engine = Arc::new(mock_engine);
+        // Strategies drawing from a shared pool hold as much cash as the pool, and are sized by their budget
+        let all_strategy_settings: Vec<StrategyDriverSettings> = match &conf.shared_capital {
+            Some(settings) => all_strategy_settings
+                .into_iter()
+                .map(|s| with_initial_cash(s, settings.capital))
+                .collect(),
+            None => all_strategy_settings,
+        };
         let stop_token = CancellationToken::new();
+        let manifest = RunManifest {
+            config_hash: conf.config_hash.clone(),
+            strategies_hash: strategies_hash(&all_strategy_settings)?,
+            code_version: code_version(),
+            seed,
+            from: period.0,
+            to: period.1,
+            partitions: vec![],
+        };
         let runners: Vec<_> = tokio_stream::iter(all_strategy_settings)
             .map(|s| {
                 BacktestRunner::spawn_with_conf(
@@ -106,12 +349,19 @@ impl Backtest {
         Ok(Self {
             stop_token,
             runners,
-            period: conf.period.as_range(),
+            period,
             output_dir: output_path,
             dataset: DatasetReader {
-                catalog: DatasetCatalog::default_basedir(conf.coindata_cache_dir()),
+                catalog: conf.dataset_catalog()?,
             },
             report_conf: conf.report.clone(),
+            replay_speed: conf.replay_speed,
+            fill_simulation,
+            shared_capital,
+            manifest,
+            verify_manifest: conf.verify_manifest.clone(),
+            checkpointer: None,
+            previous_run: None,
         })
     }
 
@@ -119,8 +369,34 @@ impl Backtest {
     ///
     /// Writing the global report fails
     pub async fn run(&mut self) -> Result<GlobalReport> {
-        let broker = build_msg_broker(&self.runners).await;
-        let channels = get_channels(&self.runners).await;
+        let with_funding = self.dataset.catalog.get(MarketEventDatasetType::FundingRates).is_some();
+        let mut broker = build_msg_broker(&self.runners, with_funding).await;
+        let mut channels = get_channels(&self.runners, with_funding).await;
+        let mut benchmark_recorders = vec![];
+        for benchmark in &self.report_conf.benchmarks {
+            let channel = benchmark.channel();
+            let (events_tx, events_rx) = tokio::sync::mpsc::channel(BENCHMARK_QUEUE_SIZE);
+            broker.register(MarketChannelTopic::from(&channel), events_tx);
+            if !channels.contains(&channel) {
+                channels.push(channel);
+            }
+            benchmark_recorders.push(tokio::spawn(record_benchmark(
+                BenchmarkSeries::new(benchmark.name()),
+                events_rx,
+                self.stop_token.clone(),
+            )));
+        }
+        let partition_dirs = self.dataset.partition_dirs(&channels, self.period).await;
+        self.manifest.partitions = tokio::task::spawn_blocking(move || partition_checksums(&partition_dirs))
+            .await
+            .map_err(|e| anyhow!(e))??;
+        if let Some(previous) = &self.previous_run {
+            self.manifest.continue_from(&previous.manifest);
+        }
+        if let Some(path) = &self.verify_manifest {
+            self.manifest.verify_datasets(&RunManifest::load(path)?)?;
+        }
+        info!(seed = self.manifest.seed, "running backtest");
         // Start runners
         let (reports_tx, mut reports_rx) = tokio::sync::mpsc::unbounded_channel();
         let mut global_report = GlobalReport::new_with(
@@ -128,10 +404,21 @@ impl Backtest {
             self.report_conf.parallelism,
             self.report_conf.compression,
         );
+        if let Some(previous) = &self.previous_run {
+            global_report.output_dir = previous.report_dir.clone();
+        }
         let num_runners = self.spawn_runners(&global_report, reports_tx).await;
         // Read input datasets
         let before_read = Instant::now();
-        self.dataset.stream_with_broker(&channels, &broker, self.period).await?;
+        self.dataset
+            .stream_with_broker(
+                &channels,
+                &broker,
+                self.period,
+                self.replay_speed,
+                self.fill_simulation.as_deref(),
+            )
+            .await?;
         self.stop_token.cancel();
         let elapsed = before_read.elapsed();
         info!(
@@ -148,8 +435,16 @@ impl Backtest {
                 break;
             }
         }
+        let benchmarks: Vec<BenchmarkSeries> = futures::future::join_all(benchmark_recorders)
+            .await
+            .into_iter()
+            .filter_map(std::result::Result::ok)
+            .collect();
+        global_report.compare_with_benchmarks(&benchmarks);
         info!("Writing reports...");
+        global_report.shared_capital = self.shared_capital.as_ref().map(|pool| pool.snapshot());
         global_report.write().await.unwrap();
+        self.manifest.write(&global_report.output_dir)?;
 
         tokio::time::sleep(Duration::from_secs(1)).await;
         Ok(global_report)
@@ -190,9 +485,13 @@ impl Backtest {
             let output_dir = global_report.output_dir.clone();
             let compression = self.report_conf.compression;
             let stop_token = self.stop_token.clone();
+            let checkpointer = self.checkpointer.clone();
+            let continue_after = self.previous_run.as_ref().map(|r| r.manifest.to);
             tokio::task::spawn(async move {
                 let mut runner = runner.write().await;
-                let report = runner.run(output_dir, compression, stop_token).await;
+                let report = runner
+                    .run(output_dir, compression, stop_token, checkpointer, continue_after)
+                    .await;
                 reports_tx.send(report).unwrap();
             });
         }
@@ -200,6 +499,59 @@ impl Backtest {
     }
 }
 
+fn check_replay_speed(conf: &BacktestConfig) -> Result<()> {
+    match conf.replay_speed.filter(|s| *s <= 0.0) {
+        Some(speed) => Err(Error::AnyhowError(anyhow!(
+            "replay speed must be positive, got {}",
+            speed
+        ))),
+        None => Ok(()),
+    }
+}
+
+fn check_fill_model(model: FillModel) -> Result<()> {
+    match model {
+        FillModel::VolumeParticipation { participation } if !(participation > 0.0 && participation <= 1.0) => {
+            Err(Error::AnyhowError(anyhow!(
+                "fill participation must be within (0, 1], got {}",
+                participation
+            )))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Checkpoints of the strategies in the database of the backtest, if configured
+fn checkpointer(conf: &BacktestConfig, db_conf: &DbOptions<PathBuf>, resume: bool) -> Result<Option<Checkpointer>> {
+    conf.checkpoint
+        .as_ref()
+        .map(|settings| {
+            Ok(Checkpointer {
+                store: CheckpointStore::new(db_conf)?,
+                interval: settings.interval,
+                resume,
+            })
+        })
+        .transpose()
+}
+
+/// Sha256 of the types and options of strategies
+fn strategies_hash(settings: &[StrategyDriverSettings]) -> Result<String> {
+    let strategies: Vec<(&String, &serde_json::Value)> = settings
+        .iter()
+        .map(|s| (&s.strat.strat_type, &s.strat.options))
+        .collect();
+    Ok(sha256_hex(&serde_json::to_vec(&strategies)?))
+}
+
+/// Strategy settings with the initial cash of their portfolio replaced
+fn with_initial_cash(mut settings: StrategyDriverSettings, initial_quote_cash: f64) -> StrategyDriverSettings {
+    match &mut settings.driver {
+        StrategyDriverOptions::Generic(options) => options.portfolio.initial_quote_cash = initial_quote_cash,
+    }
+    settings
+}
+
 async fn build_runner(
     report_sample_freq: Option<chrono::Duration>,
     provider: StratProviderRef,
@@ -220,9 +572,29 @@ async fn build_runner(
         portfolio: PortfolioOptions {
             fees_rate: fees_rate.unwrap_or(0.001),
             initial_quote_cash: starting_cash.unwrap_or(100.0),
+            target_volatility: None,
+            volatility_model: None,
+            drawdown_throttle: None,
+            position_mode: PositionMode::default(),
+            simulate_funding: true,
+            simulate_marks: true,
+            risk_limits: None,
         },
         start_trading: None,
         dry_mode: None,
+        max_signal_age: None,
+        maintenance_pause: None,
+        mark_to_market_interval: None,
+        funding_poll_interval: None,
+        require_confirmation: None,
+        observe: None,
+        shadow: None,
+        position_sizer: None,
+        schedule: None,
+        quoting: None,
+        signal_only: None,
+        execution: None,
+        trailing_stop: None,
     };
     let channels = <dyn Strategy>::channels(strat.as_ref());
     for channel in &channels {
@@ -252,7 +624,9 @@ async fn start_bt(
     let stop_token_a = stop_token.clone();
     tokio::task::spawn_local(async move {
         let mut runner = runner_ref.write().await;
-        let report = runner.run(test_results_dir, Compression::none(), stop_token_a).await;
+        let report = runner
+            .run(test_results_dir, Compression::none(), stop_token_a, None, None)
+            .await;
         report.finish().await.unwrap();
         tx.send(report).await.unwrap();
     });
@@ -269,16 +643,19 @@ pub async fn backtest_with_range<'a>(
     data_catalog: Option<DatasetCatalog>,
 ) -> Result<BacktestReport> {
     let runner_ref = build_runner(None, provider, starting_cash, fees_rate).await;
-    let broker = build_msg_broker(&[runner_ref.clone()]).await;
-    let channels = get_channels(&[runner_ref.clone()]).await;
+    let catalog = data_catalog.unwrap_or_else(DatasetCatalog::default_prod);
+    let with_funding = catalog.get(MarketEventDatasetType::FundingRates).is_some();
+    let broker = build_msg_broker(&[runner_ref.clone()], with_funding).await;
+    let channels = get_channels(&[runner_ref.clone()], with_funding).await;
 
     let local = tokio::task::LocalSet::new();
     local
         .run_until(async move {
             let (mut rx, stop_token) = start_bt(test_name, runner_ref).await;
-            let catalog = data_catalog.unwrap_or_else(DatasetCatalog::default_prod);
             let dataset = DatasetReader { catalog };
-            dataset.stream_with_broker(&channels, &broker, dt_range).await?;
+            dataset
+                .stream_with_broker(&channels, &broker, dt_range, None, None)
+                .await?;
             stop_token.cancel();
             rx.recv()
                 .await
@@ -373,14 +750,27 @@ mod test {
     use strategy::models::io::SerializedModel;
     use util::time::DateRange;
 
+    use crate::fill::FillModel;
     use crate::report::BacktestReport;
     use crate::{backtest_with_range, load_market_events, load_market_events_df, DatasetCatalog};
 
+    use super::check_fill_model;
+
     fn init() {
         util::test::init_test_env();
         register_pair_default(Exchange::Binance, "BTCUSDT", "BTC_USDT");
     }
 
+    #[test]
+    fn fill_participation_is_a_fraction() {
+        for participation in [0.1, 1.0] {
+            assert!(check_fill_model(FillModel::VolumeParticipation { participation }).is_ok());
+        }
+        for participation in [0.0, -0.5, 1.5, f64::NAN] {
+            assert!(check_fill_model(FillModel::VolumeParticipation { participation }).is_err());
+        }
+    }
+
     fn default_trades_range() -> DateRange {
         let dt = DateTime::from_utc(
             NaiveDate::from_ymd_opt(2022, 1, 22)
@@ -0,0 +1,180 @@
+//! Checkpoints of long backtests : the state of each strategy is periodically saved with the time of the last event
+//! it processed, so that a backtest that stopped can be resumed from there instead of restarting from the beginning
+//! of its period.
+//!
+//! Checkpoints hold a snapshot of the database of the strategy taken with the cursor, on resume the database is
+//! rolled back to it so that positions opened after the checkpoint are discarded, and the portfolio and the models
+//! persisted by the strategy are reloaded from it. The value and pnl of the portfolio are then restored.
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use chrono::{DateTime, Duration, Utc};
+
+use db::{get_or_create, DbOptions, Snapshot, Storage, StorageExt};
+use strategy::driver::StrategyDriver;
+use strategy::models::io::SerializedModel;
+use strategy::query::{DataQuery, DataResult, MutableField, Mutation, PortfolioSnapshot, StateFieldMutation};
+
+use crate::error::*;
+
+const CHECKPOINTS_DB: &str = "checkpoints";
+const CHECKPOINTS_TABLE: &str = "checkpoints";
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct CheckpointSettings {
+    /// Event time between two checkpoints of a strategy
+    #[serde(
+        deserialize_with = "util::ser::string_duration_chrono",
+        serialize_with = "util::ser::encode_duration_str"
+    )]
+    pub interval: Duration,
+}
+
+/// The state of a strategy after an event
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Checkpoint {
+    /// Time of the last event processed by the strategy, its cursor in the datasets
+    pub at: DateTime<Utc>,
+    pub portfolio: PortfolioSnapshot,
+    pub models: SerializedModel,
+    /// The database of the strategy after the event, if it has one
+    #[serde(default)]
+    pub db: Option<Snapshot>,
+}
+
+/// Checkpoints by strategy key
+#[derive(Debug, Clone)]
+pub(crate) struct CheckpointStore {
+    db: Arc<dyn Storage>,
+}
+
+impl CheckpointStore {
+    pub(crate) fn new(db_conf: &DbOptions<PathBuf>) -> Result<Self> {
+        let db = get_or_create(db_conf, CHECKPOINTS_DB, vec![CHECKPOINTS_TABLE.to_string()]);
+        db.ensure_table(CHECKPOINTS_TABLE)?;
+        Ok(Self { db })
+    }
+
+    pub(crate) fn save(&self, key: &str, checkpoint: &Checkpoint) -> Result<()> {
+        Ok(self.db.put(CHECKPOINTS_TABLE, key, checkpoint)?)
+    }
+
+    pub(crate) fn load(&self, key: &str) -> Result<Option<Checkpoint>> {
+        match self.db.get(CHECKPOINTS_TABLE, key) {
+            Err(db::Error::NotFound(_)) => Ok(None),
+            r => Ok(Some(r?)),
+        }
+    }
+
+    pub(crate) fn all(&self) -> Result<BTreeMap<String, Checkpoint>> {
+        Ok(self
+            .db
+            .get_all::<Checkpoint>(CHECKPOINTS_TABLE)?
+            .into_iter()
+            .map(|(k, v)| (String::from_utf8_lossy(&k).to_string(), v))
+            .collect())
+    }
+}
+
+/// Saves the checkpoints of strategy runners, and restores them when resuming
+#[derive(Debug, Clone)]
+pub(crate) struct Checkpointer {
+    pub(crate) store: CheckpointStore,
+    pub(crate) interval: Duration,
+    pub(crate) resume: bool,
+}
+
+impl Checkpointer {
+    /// Restore the database and portfolio of a strategy from its last checkpoint, if resuming
+    ///
+    /// returns: the time of the last event processed by the strategy, later events are processed
+    pub(crate) fn restore(&self, key: &str, driver: &mut dyn StrategyDriver) -> Result<Option<DateTime<Utc>>> {
+        if !self.resume {
+            return Ok(None);
+        }
+        let Some(checkpoint) = self.store.load(key)? else {
+            return Ok(None);
+        };
+        if let Some(snapshot) = checkpoint.db.as_ref() {
+            driver
+                .restore(snapshot)
+                .map_err(|e| anyhow!("failed to restore the database of {} : {}", key, e))?;
+        }
+        for (field, value) in [
+            (MutableField::ValueStrat, checkpoint.portfolio.value),
+            (MutableField::Pnl, checkpoint.portfolio.pnl),
+        ] {
+            driver
+                .mutate(Mutation::State(StateFieldMutation { field, value }))
+                .map_err(|e| anyhow!("failed to restore the portfolio of {} : {}", key, e))?;
+        }
+        info!(key = %key, at = %checkpoint.at, "resuming from checkpoint");
+        Ok(Some(checkpoint.at))
+    }
+
+    /// Whether a checkpoint is due at `at`, since the last one
+    pub(crate) fn is_due(&self, last: DateTime<Utc>, at: DateTime<Utc>) -> bool { at - last >= self.interval }
+
+    /// Save the current state of a strategy, after the event at `at`
+    pub(crate) async fn save(&self, key: &str, at: DateTime<Utc>, driver: &mut dyn StrategyDriver) -> Result<()> {
+        let models = match driver.query(DataQuery::Models).await {
+            Ok(DataResult::Models(models)) => models,
+            _ => vec![],
+        };
+        let portfolio = match driver.query(DataQuery::Indicators).await {
+            Ok(DataResult::Indicators(snapshot)) => snapshot,
+            _ => return Err(anyhow!("failed to snapshot the portfolio of {}", key).into()),
+        };
+        let db = driver
+            .snapshot()
+            .map_err(|e| anyhow!("failed to snapshot the database of {} : {}", key, e))?;
+        self.store.save(key, &Checkpoint {
+            at,
+            portfolio,
+            models,
+            db,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{Duration, TimeZone, Utc};
+
+    use db::DbOptions;
+    use strategy::query::PortfolioSnapshot;
+
+    use super::{Checkpoint, CheckpointStore, Checkpointer};
+
+    #[test]
+    fn save_and_load_checkpoints() {
+        let dir = util::test::test_dir();
+        let store = CheckpointStore::new(&DbOptions::new(dir.path().to_path_buf())).unwrap();
+        assert!(store.load("strat").unwrap().is_none());
+        let at = Utc.with_ymd_and_hms(2022, 1, 1, 12, 0, 0).unwrap();
+        let checkpoint = Checkpoint {
+            at,
+            portfolio: PortfolioSnapshot {
+                pnl: 110.0,
+                value: 110.0,
+                ..PortfolioSnapshot::default()
+            },
+            models: vec![("apo".to_string(), Some(serde_json::json!(0.5)))],
+            db: None,
+        };
+        store.save("strat", &checkpoint).unwrap();
+        let loaded = store.load("strat").unwrap().unwrap();
+        assert_eq!(loaded.at, at);
+        assert_eq!(loaded.portfolio, checkpoint.portfolio);
+        assert_eq!(store.all().unwrap().len(), 1);
+        let checkpointer = Checkpointer {
+            store,
+            interval: Duration::hours(1),
+            resume: true,
+        };
+        assert!(!checkpointer.is_due(at, at + Duration::minutes(59)));
+        assert!(checkpointer.is_due(at, at + Duration::hours(1)));
+    }
+}
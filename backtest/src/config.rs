@@ -1,20 +1,29 @@
 use chrono::Duration;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::ops::Sub;
 use std::path::{Path, PathBuf};
 
 use ::config::{Config, File};
 use brokers::exchange::Exchange;
+use brokers::fees::FeeTier;
 use chrono::{NaiveDateTime, TimeZone, Utc};
 use typed_builder::TypedBuilder;
 
 use db::{DbEngineOptions, DbOptions, RocksDbOptions};
 use strategy::settings::StrategyCopySettings;
 use strategy::settings::StrategyDriverSettings;
+use trading::capital::SharedCapitalSettings;
 use util::test::test_dir;
 use util::time::{utc_at_midnight, DateRange};
 
 use crate::backtest::init_brokerages;
+use crate::checkpoint::CheckpointSettings;
+use crate::dataset::DatasetCatalog;
+use crate::fill::FillSettings;
+use crate::manifest::sha256_hex;
+use crate::s3::{S3Settings, S3_SCHEME};
+use crate::sweep::SweepSettings;
+use crate::walk_forward::WalkForwardSettings;
 
 use crate::error::*;
 use crate::report::ReportConfig;
@@ -56,10 +65,19 @@ pub struct BacktestConfig {
     pub db_path: Option<PathBuf>,
     pub strats: Vec<StrategyDriverSettings>,
     pub strat_copy: Option<StrategyCopySettings>,
+    /// Fees rate of exchanges without a fee schedule
     pub fees: f64,
+    /// Maker and taker fees rates of orders, by exchange
+    #[builder(default)]
+    #[serde(default)]
+    pub fee_schedules: HashMap<Exchange, FeeTier>,
     pub period: Period,
+    /// A local directory, or an `s3://bucket/prefix` url read with the `s3` settings
     pub coindata_cache_dir: Option<PathBuf>,
     #[builder(default, setter(strip_option))]
+    #[serde(default)]
+    pub s3: Option<S3Settings>,
+    #[builder(default, setter(strip_option))]
     pub sql_override: Option<String>,
     #[builder(default, setter(strip_option))]
     pub output_dir: Option<PathBuf>,
@@ -68,6 +86,45 @@ pub struct BacktestConfig {
     pub runner_queue_size: Option<usize>,
     #[serde(deserialize_with = "util::ser::string_duration_opt")]
     pub report_sample_rate: Option<std::time::Duration>,
+    /// Replay events paced by the time between them, this many times faster than real time (e.g. 1.0 for real time,
+    /// 10.0 for 10x), instead of as fast as possible
+    #[builder(default, setter(strip_option))]
+    #[serde(default)]
+    pub replay_speed: Option<f64>,
+    /// Optimize strategy options over rolling windows of the period, see [`crate::walk_forward()`]
+    #[builder(default, setter(strip_option))]
+    #[serde(default)]
+    pub walk_forward: Option<WalkForwardSettings>,
+    /// Backtest every combination of a grid of strategy options, see [`crate::sweep()`]
+    #[builder(default, setter(strip_option))]
+    #[serde(default)]
+    pub sweep: Option<SweepSettings>,
+    /// Simulate the execution of orders with a fill model instead of filling them as soon as they are passed,
+    /// orders of strategies in dry mode are never passed to the brokerage and are not simulated
+    #[builder(default, setter(strip_option))]
+    #[serde(default)]
+    pub fill: Option<FillSettings>,
+    /// Strategies draw from a single capital pool split by an allocation policy, instead of each trading the
+    /// initial cash of its portfolio
+    #[builder(default, setter(strip_option))]
+    #[serde(default)]
+    pub shared_capital: Option<SharedCapitalSettings>,
+    /// Seed of the random generators of the backtest, a random seed is used and recorded in the manifest if unset
+    #[builder(default, setter(strip_option))]
+    #[serde(default)]
+    pub seed: Option<u64>,
+    /// The manifest of a previous run, the backtest fails if the datasets it reads changed since
+    #[builder(default, setter(strip_option))]
+    #[serde(default)]
+    pub verify_manifest: Option<PathBuf>,
+    /// Periodically save the state of strategies in the database, so that the backtest can be resumed
+    #[builder(default, setter(strip_option))]
+    #[serde(default)]
+    pub checkpoint: Option<CheckpointSettings>,
+    /// Sha256 of the configuration, if loaded from a file
+    #[builder(default)]
+    #[serde(skip)]
+    pub(crate) config_hash: Option<String>,
 }
 
 impl BacktestConfig {
@@ -80,8 +137,14 @@ impl BacktestConfig {
             .set_override("__config_file", config_file_name)?
             .build()?;
 
+        let mut content: serde_json::Value = s.clone().try_deserialize()?;
+        if let Some(map) = content.as_object_mut() {
+            map.remove("__config_file");
+        }
         // You can deserialize (and thus freeze) the entire configuration as
-        s.try_deserialize().map_err(Into::into)
+        let mut conf: Self = s.try_deserialize()?;
+        conf.config_hash = Some(sha256_hex(content.to_string().as_bytes()));
+        Ok(conf)
     }
 
     pub fn output_dir(&self) -> PathBuf {
@@ -100,14 +163,24 @@ impl BacktestConfig {
         db_path
     }
 
-    pub fn db_conf(&self) -> DbOptions<PathBuf> {
-        let db_path = self.db_path();
+    pub fn db_conf(&self) -> DbOptions<PathBuf> { self.db_conf_at(self.db_path()) }
+
+    /// Database options with the engine options of the configuration, at another path
+    pub fn db_conf_at(&self, db_path: PathBuf) -> DbOptions<PathBuf> {
         self.db_conf.as_ref().map_or_else(
             || default_db_conf(db_path.clone()),
             |eo| DbOptions::new_with_options(db_path.clone(), eo.clone()),
         )
     }
 
+    /// The fee schedule of the exchange, or the flat fees rate
+    pub(crate) fn fee_tier(&self, xch: Exchange) -> FeeTier {
+        self.fee_schedules.get(&xch).copied().unwrap_or(FeeTier {
+            maker: self.fees,
+            taker: self.fees,
+        })
+    }
+
     //pub fn sample_rate(&self) -> Duration { Duration::from_std(parse(&self.input_sample_rate).unwrap()).unwrap() }
 
     pub(crate) fn coindata_cache_dir(&self) -> PathBuf {
@@ -116,6 +189,14 @@ impl BacktestConfig {
             .unwrap_or_else(|| Path::new(&std::env::var("TRADAI_DATA_CACHE_DIR").unwrap()).to_path_buf())
     }
 
+    pub(crate) fn dataset_catalog(&self) -> Result<DatasetCatalog> {
+        let base_dir = self.coindata_cache_dir();
+        match base_dir.to_str() {
+            Some(url) if url.starts_with(S3_SCHEME) => DatasetCatalog::s3(url, &self.s3.clone().unwrap_or_default()),
+            _ => Ok(DatasetCatalog::default_basedir(base_dir)),
+        }
+    }
+
     pub(crate) async fn all_strategy_settings(&self) -> Vec<StrategyDriverSettings> {
         let mut all_strategy_settings: Vec<StrategyDriverSettings> = vec![];
         all_strategy_settings.extend_from_slice(self.strats.as_slice());
@@ -7,12 +7,14 @@ use datafusion::datasource::file_format::csv::CsvFormat;
 use datafusion::datasource::file_format::parquet::ParquetFormat;
 use datafusion::datasource::file_format::FileFormat;
 use datafusion::datasource::listing::ListingOptions;
+use datafusion::execution::runtime_env::{RuntimeConfig, RuntimeEnv};
 use datafusion::logical_expr::Literal;
 use datafusion::physical_plan::coalesce_batches::concat_batches;
 use datafusion::prelude::*;
 use ext::ResultExt;
 use futures::{Stream, StreamExt};
 use itertools::Itertools;
+use once_cell::sync::OnceCell;
 use std::collections::HashSet;
 use std::fmt::Debug;
 use std::path::Path;
@@ -98,7 +100,16 @@ pub fn new_context() -> ballista::context::BallistaContext { ballista::context::
     not(any(feature = "remote_execution", feature = "standalone_execution")),
     all(feature = "remote_execution", feature = "standalone_execution")
 ))]
-pub fn new_context() -> SessionContext { SessionContext::new() }
+pub fn new_context() -> SessionContext { SessionContext::with_config_rt(SessionConfig::new(), runtime_env()) }
+
+static RUNTIME_ENV: OnceCell<Arc<RuntimeEnv>> = OnceCell::new();
+
+/// The runtime shared by all contexts, which holds the object stores registered for remote datasets
+pub fn runtime_env() -> Arc<RuntimeEnv> {
+    RUNTIME_ENV
+        .get_or_init(|| Arc::new(RuntimeEnv::new(RuntimeConfig::new()).expect("datafusion runtime")))
+        .clone()
+}
 
 /// Utility method to give default listing options from a format and partitions
 pub fn listing_options(format: String, partition: Vec<(&str, String)>) -> ListingOptions {
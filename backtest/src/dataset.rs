@@ -1,6 +1,7 @@
 use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
 use std::pin::Pin;
+use std::str::FromStr;
 use std::time::Instant;
 
 use chrono::{DateTime, Duration, Timelike, Utc};
@@ -8,16 +9,25 @@ use datafusion::arrow::record_batch::RecordBatch;
 use futures::future::BoxFuture;
 use futures::{Stream, StreamExt};
 
+use crate::datasources::funding_rates::{funding_rates_df, funding_rates_stream};
+use crate::datasources::open_interest::{open_interest_df, open_interest_stream};
 use crate::datasources::orderbook::{flat_orderbooks_stream, raw_orderbooks_df, raw_orderbooks_stream,
                                     sampled_orderbooks_df, sampled_orderbooks_stream};
 use brokers::broker::{AsyncBroker, ChannelMessageBroker};
 use brokers::prelude::{Exchange, Pair};
 use brokers::types::MarketChannelTopic;
 use brokers::types::{MarketChannel, MarketChannelType, MarketEventEnvelope, SecurityType};
+use stats::kline::Resolution;
 use util::time::{utc_at_midnight, DateRange};
 
-use crate::datasources::trades::{candles_df, candles_stream, trades_df, trades_stream};
+use crate::datasources::klines::{klines_df, klines_resolution, klines_stream};
+use crate::datasources::quotes::{quotes_df, quotes_stream};
+use crate::datasources::trades::{candles_df, candles_stream, recorded_candles_stream, recorded_trades_df,
+                                 recorded_trades_stream, trades_df, trades_stream};
 use crate::error::*;
+use crate::fill::SimulatedBrokerage;
+use crate::replay::ReplayClock;
+use crate::s3::{self, S3Settings, S3_SCHEME};
 
 // TODO: There should be some other way to load table definitions, maybe a json file or a data catalog format
 
@@ -52,6 +62,17 @@ impl DatasetCatalog {
         )
     }
 
+    /// The default datasets under an `s3://bucket/prefix` url, partitions are listed and read from the object store
+    /// as they are queried
+    ///
+    /// # Errors
+    ///
+    /// The url is not an s3 url, or the store cannot be built from the settings
+    pub fn s3(base_url: &str, settings: &S3Settings) -> Result<Self> {
+        settings.register(base_url)?;
+        Ok(Self::default_basedir(PathBuf::from(base_url.trim_end_matches('/'))))
+    }
+
     #[cfg(test)]
     pub fn default_test() -> Self { Self::default_formats(util::test::test_data_dir(), util::test::test_data_dir()) }
 
@@ -70,19 +91,161 @@ impl DatasetCatalog {
         datasets.insert(MarketEventDatasetType::OrderbooksRaw, TableDef {
             name: "order_books",
             format: DataFormat::Avro,
+            base_dir: base_data24_dir.clone(),
+        });
+        datasets.insert(MarketEventDatasetType::Quotes, TableDef {
+            name: "quotes",
+            format: DataFormat::Avro,
+            base_dir: base_data24_dir.clone(),
+        });
+        datasets.insert(MarketEventDatasetType::OpenInterest, TableDef {
+            name: "open_interests",
+            format: DataFormat::Avro,
+            base_dir: base_data24_dir.clone(),
+        });
+        datasets.insert(MarketEventDatasetType::FundingRates, TableDef {
+            name: "funding_rates",
+            format: DataFormat::Avro,
             base_dir: base_data24_dir,
         });
         datasets.insert(MarketEventDatasetType::Trades, TableDef {
             name: "trades",
             format: DataFormat::Parquet,
+            base_dir: base_data_dir.clone(),
+        });
+        datasets.insert(MarketEventDatasetType::Candles, TableDef {
+            name: "candles",
+            format: DataFormat::Parquet,
             base_dir: base_data_dir,
         });
         DatasetCatalog { catalog: datasets }
     }
+
+    /// Datasets of the market events recorded by the file logger, in the format it writes, so that recorded data can
+    /// be replayed without conversion
+    ///
+    /// # Arguments
+    ///
+    /// * `base_dir`: the base directory of the file logger
+    /// * `format`: the format of the file logger, either avro or parquet
+    ///
+    /// # Errors
+    ///
+    /// Recordings of the former logger layout could not be migrated, see [`migrate_recorded_layout`]
+    pub fn recorded(base_dir: PathBuf, format: DataFormat) -> Result<DatasetCatalog> {
+        let migrated = migrate_recorded_layout(&base_dir)?;
+        if migrated > 0 {
+            info!(base_dir = ?base_dir, files = migrated, "migrated recordings to the dataset layout");
+        }
+        let mut datasets = HashMap::new();
+        for (ds_type, name) in [
+            (MarketEventDatasetType::OrderbooksRaw, "order_books"),
+            (MarketEventDatasetType::RecordedTrades, "trades"),
+            (MarketEventDatasetType::Quotes, "quotes"),
+            (MarketEventDatasetType::OpenInterest, "open_interests"),
+        ] {
+            datasets.insert(ds_type, TableDef {
+                name,
+                format: format.clone(),
+                base_dir: base_dir.clone(),
+            });
+        }
+        Ok(DatasetCatalog { catalog: datasets })
+    }
+}
+
+/// Move the recordings of the former file logger layout, `{Exchange}/{channel}/pr={pair}/dt={date}`, to the layout of
+/// datasets, `chan={channel}/xch={exchange}/pr={pair}/dt={date}`, files already present in the new layout are kept
+///
+/// Returns the number of files moved, remote base directories are left as is
+///
+/// # Errors
+///
+/// A directory cannot be listed or a file cannot be moved
+pub fn migrate_recorded_layout(base_dir: &Path) -> Result<usize> {
+    if !base_dir.is_dir() {
+        return Ok(0);
+    }
+    let mut moved = 0;
+    for entry in std::fs::read_dir(base_dir)? {
+        let xch_dir = entry?.path();
+        let Some(xch) = xch_dir
+            .file_name()
+            .and_then(|name| name.to_str())
+            .filter(|name| !name.contains('='))
+            .and_then(|name| Exchange::from_str(&name.to_lowercase()).ok())
+        else {
+            continue;
+        };
+        if !xch_dir.is_dir() {
+            continue;
+        }
+        for entry in std::fs::read_dir(&xch_dir)? {
+            let chan_dir = entry?.path();
+            let Some(channel) = chan_dir.file_name().and_then(|name| name.to_str()) else {
+                continue;
+            };
+            let target_dir = base_dir.join(format!("chan={}", channel)).join(format!("xch={}", xch));
+            for file in files_under(&chan_dir)? {
+                let Ok(relative) = file.strip_prefix(&chan_dir) else {
+                    continue;
+                };
+                let target = target_dir.join(relative);
+                if target.exists() {
+                    warn!(file = ?file, "a recording already exists in the dataset layout, keeping both");
+                    continue;
+                }
+                if let Some(parent) = target.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                std::fs::rename(&file, &target)?;
+                moved += 1;
+            }
+        }
+        remove_empty_dirs(&xch_dir)?;
+    }
+    Ok(moved)
+}
+
+fn files_under(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = vec![];
+    if !dir.is_dir() {
+        return Ok(files);
+    }
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            files.extend(files_under(&path)?);
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(files)
+}
+
+/// Remove `dir` and its subdirectories if they do not contain any file
+fn remove_empty_dirs(dir: &Path) -> Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            remove_empty_dirs(&path)?;
+        }
+    }
+    if std::fs::read_dir(dir)?.next().is_none() {
+        std::fs::remove_dir(dir)?;
+    }
+    Ok(())
 }
 
 pub type PartitionSet = HashSet<(PathBuf, Vec<(&'static str, String)>)>;
 
+/// The directory of a partition, e.g. `base_dir/xch=binance/dt=20220101`
+pub(crate) fn partition_dir(base_dir: &Path, partitions: &[(&'static str, String)]) -> PathBuf {
+    partitions
+        .iter()
+        .fold(base_dir.to_path_buf(), |dir, (k, v)| dir.join(format!("{}={}", k, v)))
+}
+
 #[derive(Debug)]
 pub struct Dataset {
     pub channel: MarketChannel,
@@ -97,13 +260,37 @@ pub struct DatasetReader {
 }
 
 impl DatasetReader {
-    fn datasets<'a, I>(&self, channels: I, dt: DateTime<Utc>) -> Vec<Dataset>
+    /// Whether candles of the channel were downloaded for this day, remote datasets are listed in their object store
+    async fn has_klines(&self, channel: &MarketChannel, dt: DateTime<Utc>) -> bool {
+        let Some(table_def) = self.catalog.get(MarketEventDatasetType::Candles) else {
+            return false;
+        };
+        let (base_dir, partitions) = MarketEventDatasetType::Candles.partition(
+            table_def.base_dir.clone(),
+            dt,
+            channel.symbol.xch,
+            &channel.symbol.value,
+            Some(channel.symbol.r#type),
+            Some(klines_resolution(channel.resolution)),
+        );
+        let dir = partition_dir(&base_dir, &partitions);
+        match dir.to_str().filter(|url| url.starts_with(S3_SCHEME)) {
+            Some(url) => s3::dir_exists(url).await.unwrap_or_else(|e| {
+                warn!(dir = url, error = %e, "could not list candles, reading trades instead");
+                false
+            }),
+            None => dir.exists(),
+        }
+    }
+
+    async fn datasets<'a, I>(&self, channels: I, dt: DateTime<Utc>) -> Vec<Dataset>
     where
         I: Iterator<Item = &'a MarketChannel>,
     {
         let mut datasets = vec![];
         for channel in channels {
-            let ds_type = match channel.r#type {
+            let has_klines = channel.r#type == MarketChannelType::Candles && self.has_klines(channel, dt).await;
+            let mut ds_type = match channel.r#type {
                 MarketChannelType::Orderbooks => {
                     match channel.tick_rate.or(channel.resolution.map(|r| r.as_duration())) {
                         Some(tr) => {
@@ -118,10 +305,34 @@ impl DatasetReader {
                         None => MarketEventDatasetType::OrderbooksRaw,
                     }
                 }
+                // Downloaded candles are read as is, otherwise candles are aggregated from trades
+                MarketChannelType::Candles if has_klines => MarketEventDatasetType::Candles,
+                MarketChannelType::Trades | MarketChannelType::Candles
+                    if self.catalog.get(MarketEventDatasetType::RecordedTrades).is_some() =>
+                {
+                    MarketEventDatasetType::RecordedTrades
+                }
                 MarketChannelType::Trades | MarketChannelType::Candles => MarketEventDatasetType::Trades,
-                _ => unimplemented!(),
+                MarketChannelType::Quotes => MarketEventDatasetType::Quotes,
+                MarketChannelType::OpenInterest => MarketEventDatasetType::OpenInterest,
+                MarketChannelType::FundingRate => MarketEventDatasetType::FundingRates,
+                _ => {
+                    warn!(channel = ?channel, "no dataset for this channel type");
+                    continue;
+                }
+            };
+            // Recordings only have raw order books, which are sampled when read
+            if matches!(
+                ds_type,
+                MarketEventDatasetType::OrderbooksBySecond | MarketEventDatasetType::OrderbooksByMinute
+            ) && self.catalog.get(ds_type).is_none()
+            {
+                ds_type = MarketEventDatasetType::OrderbooksRaw;
+            }
+            let Some(table_def) = self.catalog.get(ds_type) else {
+                warn!(channel = ?channel, dataset = ?ds_type, "dataset missing from the catalog, skipping channel");
+                continue;
             };
-            let table_def = self.catalog.get(ds_type).unwrap();
             let mut partitions = HashSet::new();
             partitions.insert(ds_type.partition(
                 table_def.base_dir.clone(),
@@ -129,6 +340,7 @@ impl DatasetReader {
                 channel.symbol.xch,
                 &channel.symbol.value,
                 Some(channel.symbol.r#type),
+                Some(klines_resolution(channel.resolution)),
             ));
             datasets.push(Dataset {
                 channel: channel.clone(),
@@ -149,7 +361,7 @@ impl DatasetReader {
     where
         I: Iterator<Item = &'a MarketChannel>,
     {
-        let datasets = self.datasets(channels, utc_at_midnight(lower_dt));
+        let datasets = self.datasets(channels, utc_at_midnight(lower_dt)).await;
         let lower_dt = (lower_dt.num_seconds_from_midnight() != 0).then(|| lower_dt);
         let stream: Pin<Box<dyn Stream<Item = MarketEventEnvelope>>> =
             Box::pin(futures::stream::select_all(datasets.iter().map(|ds| {
@@ -191,6 +403,48 @@ impl DatasetReader {
                         )),
                         _ => unimplemented!(),
                     },
+                    // Only trade and candle channels are read from recorded trades
+                    MarketEventDatasetType::RecordedTrades => match ds.channel.r#type {
+                        MarketChannelType::Candles => Box::pin(recorded_candles_stream(
+                            partitions,
+                            input_format,
+                            lower_dt,
+                            upper_dt,
+                            ds.channel.resolution,
+                            ds.channel.tick_rate,
+                        )),
+                        _ => Box::pin(recorded_trades_stream(
+                            partitions,
+                            input_format,
+                            lower_dt,
+                            upper_dt,
+                            ds.channel.tick_rate,
+                        )),
+                    },
+                    MarketEventDatasetType::Candles => {
+                        Box::pin(klines_stream(partitions, input_format, lower_dt, upper_dt))
+                    }
+                    MarketEventDatasetType::Quotes => Box::pin(quotes_stream(
+                        partitions,
+                        input_format,
+                        lower_dt,
+                        upper_dt,
+                        ds.channel.tick_rate,
+                    )),
+                    MarketEventDatasetType::OpenInterest => Box::pin(open_interest_stream(
+                        partitions,
+                        input_format,
+                        lower_dt,
+                        upper_dt,
+                        ds.channel.tick_rate,
+                    )),
+                    MarketEventDatasetType::FundingRates => Box::pin(funding_rates_stream(
+                        partitions,
+                        input_format,
+                        lower_dt,
+                        upper_dt,
+                        ds.channel.tick_rate,
+                    )),
                 };
                 inner
             })));
@@ -206,10 +460,10 @@ impl DatasetReader {
     where
         I: Iterator<Item = &'a MarketChannel>,
     {
-        let datasets = self.datasets(channels, utc_at_midnight(lower_dt));
+        let datasets = self.datasets(channels, utc_at_midnight(lower_dt)).await;
         let lower_dt = (lower_dt.num_seconds_from_midnight() != 0).then(|| lower_dt);
         let rb = futures::future::try_join_all(datasets.iter().map(|ds| {
-            let input_format = ds.r#type.default_format().to_string();
+            let input_format = ds.format.to_string();
             let partitions = ds.partitions.clone();
             let fut: BoxFuture<Result<RecordBatch>> = match ds.r#type {
                 MarketEventDatasetType::OrderbooksByMinute | MarketEventDatasetType::OrderbooksBySecond => Box::pin(
@@ -246,6 +500,46 @@ impl DatasetReader {
                     )),
                     _ => unimplemented!(),
                 },
+                MarketEventDatasetType::RecordedTrades => match ds.channel.r#type {
+                    // Candles only use the price and time of trades
+                    MarketChannelType::Candles => Box::pin(candles_df(
+                        partitions,
+                        input_format,
+                        lower_dt,
+                        upper_dt,
+                        ds.channel.resolution,
+                        ds.channel.tick_rate,
+                    )),
+                    _ => Box::pin(recorded_trades_df(
+                        partitions,
+                        input_format,
+                        lower_dt,
+                        upper_dt,
+                        ds.channel.tick_rate,
+                    )),
+                },
+                MarketEventDatasetType::Candles => Box::pin(klines_df(partitions, input_format, lower_dt, upper_dt)),
+                MarketEventDatasetType::Quotes => Box::pin(quotes_df(
+                    partitions,
+                    input_format,
+                    lower_dt,
+                    upper_dt,
+                    ds.channel.tick_rate,
+                )),
+                MarketEventDatasetType::OpenInterest => Box::pin(open_interest_df(
+                    partitions,
+                    input_format,
+                    lower_dt,
+                    upper_dt,
+                    ds.channel.tick_rate,
+                )),
+                MarketEventDatasetType::FundingRates => Box::pin(funding_rates_df(
+                    partitions,
+                    input_format,
+                    lower_dt,
+                    upper_dt,
+                    ds.channel.tick_rate,
+                )),
                 _ => unimplemented!(),
             };
             fut
@@ -254,18 +548,43 @@ impl DatasetReader {
         Ok(rb)
     }
 
+    /// Broadcast the market events of the period, as fast as possible or paced by the time between events when a
+    /// replay speed is set, each event fills the resting orders of the simulated brokerage before it is broadcast
     pub async fn stream_with_broker(
         &self,
         channels: &[MarketChannel],
         broker: &ChannelMessageBroker<MarketChannelTopic, MarketEventEnvelope>,
         period: DateRange,
+        replay_speed: Option<f64>,
+        fills: Option<&SimulatedBrokerage>,
     ) -> Result<()> {
+        let mut clock = replay_speed.map(ReplayClock::new);
         for dt in period {
             let now = Instant::now();
-            let stream = self
+            let mut stream = self
                 .read_channels_to_stream(channels.iter(), dt, period.upper_bound_in_range())
                 .await;
-            stream.for_each(|event| AsyncBroker::broadcast(broker, event)).await;
+            match clock.as_mut() {
+                Some(clock) => {
+                    while let Some(event) = stream.next().await {
+                        clock.wait_for(event.e.time()).await;
+                        if let Some(fills) = fills {
+                            fills.on_market_event(&event);
+                        }
+                        AsyncBroker::broadcast(broker, event).await;
+                    }
+                }
+                None => {
+                    stream
+                        .for_each(|event| {
+                            if let Some(fills) = fills {
+                                fills.on_market_event(&event);
+                            }
+                            AsyncBroker::broadcast(broker, event)
+                        })
+                        .await
+                }
+            }
             let elapsed = now.elapsed();
             info!(
                 "Processed dt={} in {}.{}s",
@@ -277,6 +596,21 @@ impl DatasetReader {
         Ok(())
     }
 
+    /// The directories of the partitions read for channels over a period
+    pub(crate) async fn partition_dirs(&self, channels: &[MarketChannel], period: DateRange) -> Vec<PathBuf> {
+        let mut dirs = vec![];
+        for dt in period {
+            dirs.extend(
+                self.datasets(channels.iter(), dt)
+                    .await
+                    .into_iter()
+                    .flat_map(|ds| ds.partitions.into_iter())
+                    .map(|(base_dir, partitions)| partition_dir(&base_dir, &partitions)),
+            );
+        }
+        dirs
+    }
+
     pub async fn read_all_events(
         &self,
         channels: &[MarketChannel],
@@ -317,6 +651,16 @@ pub enum MarketEventDatasetType {
     OrderbooksFlat,
     /// Trades
     Trades,
+    /// Trades recorded by the file logger
+    RecordedTrades,
+    /// Candles downloaded from exchanges
+    Candles,
+    /// Best bid and offer quotes
+    Quotes,
+    /// Open interest of futures
+    OpenInterest,
+    /// Funding rates of perpetual contracts
+    FundingRates,
 }
 
 impl MarketEventDatasetType {
@@ -328,6 +672,8 @@ impl MarketEventDatasetType {
     /// * `date`: a date
     /// * `xch`: an exchange
     /// * `pair`: a market pair
+    /// * `sec_type`: the security type of the pair
+    /// * `resolution`: the resolution of candles, only used by candles
     ///
     /// returns: (String, Vec<(String, String), Global>) the base directory and partitions
     ///
@@ -343,6 +689,7 @@ impl MarketEventDatasetType {
         xch: Exchange,
         pair: &Pair,
         sec_type: Option<SecurityType>,
+        resolution: Option<Resolution>,
     ) -> (PathBuf, Vec<(&'static str, String)>) {
         let dt_par = date.format("%Y%m%d").to_string();
         let asset_str = sec_type.map(|sc| sc.short()).unwrap_or("");
@@ -371,16 +718,38 @@ impl MarketEventDatasetType {
                 ("sym", pair.to_string()),
                 ("dt", dt_par),
             ]),
-        }
-    }
-
-    pub(crate) fn default_format(&self) -> DataFormat {
-        match self {
-            MarketEventDatasetType::OrderbooksByMinute
-            | MarketEventDatasetType::OrderbooksBySecond
-            | MarketEventDatasetType::OrderbooksRaw => DataFormat::Avro,
-            MarketEventDatasetType::OrderbooksFlat => DataFormat::Csv,
-            MarketEventDatasetType::Trades => DataFormat::Parquet,
+            MarketEventDatasetType::RecordedTrades => (base_dir.join("chan=trades"), vec![
+                ("xch", xch.to_string()),
+                ("pr", pair.to_string()),
+                ("dt", dt_par),
+            ]),
+            MarketEventDatasetType::Candles => (base_dir.join("chan=candles"), vec![
+                ("xch", xch.to_string()),
+                ("ast", asset_str.to_string()),
+                ("sym", pair.to_string()),
+                (
+                    "res",
+                    resolution
+                        .map(|r| format!("{}{}", r.units, r.time_unit.as_ref()))
+                        .unwrap_or_default(),
+                ),
+                ("dt", dt_par),
+            ]),
+            MarketEventDatasetType::Quotes => (base_dir.join("chan=quotes"), vec![
+                ("xch", xch.to_string()),
+                ("pr", pair.to_string()),
+                ("dt", dt_par),
+            ]),
+            MarketEventDatasetType::OpenInterest => (base_dir.join("chan=open_interests"), vec![
+                ("xch", xch.to_string()),
+                ("pr", pair.to_string()),
+                ("dt", dt_par),
+            ]),
+            MarketEventDatasetType::FundingRates => (base_dir.join("chan=funding_rates"), vec![
+                ("xch", xch.to_string()),
+                ("pr", pair.to_string()),
+                ("dt", dt_par),
+            ]),
         }
     }
 }
@@ -406,3 +775,235 @@ impl ToString for DataFormat {
         .to_string()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::fs::File;
+    use std::path::{Path, PathBuf};
+    use std::rc::Rc;
+    use std::sync::Arc;
+
+    use actix::SyncArbiter;
+    use chrono::{DateTime, Duration, NaiveDate, Utc};
+    use datafusion::arrow::array::{ArrayRef, Float64Array, Int32Array, Int64Array, StringArray};
+    use datafusion::arrow::datatypes::{DataType, Field, Schema};
+    use datafusion::arrow::record_batch::RecordBatch;
+    use datafusion::datasource::object_store::ObjectStoreUrl;
+    use datafusion::parquet::arrow::ArrowWriter;
+    use object_store::memory::InMemory;
+    use object_store::path::Path as ObjectPath;
+    use object_store::ObjectStore;
+
+    use brokers::exchange::Exchange;
+    use brokers::types::{MarketChannel, MarketChannelType, MarketEvent, MarketEventEnvelope, SecurityType, Symbol,
+                         TradeType};
+    use logging::prelude::{AvroFileActor, FileActorOptions, FileFormat, MarketEventPartitioner};
+    use stats::kline::{Resolution, TimeUnit};
+    use util::time::{utc_at_midnight, DateRange};
+
+    use super::{partition_dir, DataFormat, DatasetCatalog, DatasetReader, MarketEventDatasetType};
+    use crate::datafusion_util::runtime_env;
+    use crate::load_market_events;
+
+    fn test_day() -> DateTime<Utc> {
+        DateTime::from_utc(
+            NaiveDate::from_ymd_opt(2022, 1, 22)
+                .unwrap()
+                .and_hms_opt(0, 0, 0)
+                .unwrap(),
+            Utc,
+        )
+    }
+
+    fn trades_channel() -> MarketChannel {
+        MarketChannel::builder()
+            .symbol(Symbol::new("BTC_USDT".into(), SecurityType::Crypto, Exchange::Binance))
+            .r#type(MarketChannelType::Trades)
+            .build()
+    }
+
+    /// Ten trades a second apart from `dt`, in the schema written by the file logger
+    fn write_trades(path: &Path, dt: DateTime<Utc>) {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("event_ms", DataType::Int64, false),
+            Field::new("pair", DataType::Utf8, false),
+            Field::new("amount", DataType::Float64, false),
+            Field::new("price", DataType::Float64, false),
+            Field::new("tt", DataType::Int32, false),
+        ]));
+        let start = dt.timestamp_millis();
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(Int64Array::from_iter_values((0..10).map(|i| start + i * 1000))),
+            Arc::new(StringArray::from_iter_values((0..10).map(|_| "BTC_USDT"))),
+            Arc::new(Float64Array::from_iter_values((0..10).map(|_| 0.1))),
+            Arc::new(Float64Array::from_iter_values((0..10).map(f64::from))),
+            Arc::new(Int32Array::from_iter_values((0..10).map(|i| i % 2))),
+        ];
+        let batch = RecordBatch::try_new(schema.clone(), columns).unwrap();
+        let mut writer = ArrowWriter::try_new(File::create(path).unwrap(), schema, None).unwrap();
+        writer.write(&batch).unwrap();
+        writer.close().unwrap();
+    }
+
+    #[actix_rt::test]
+    async fn read_recorded_trades() {
+        util::test::init_test_env();
+        let test_dir = util::test::test_dir();
+        let dt = test_day();
+        // The layout written by the file logger
+        let (base_dir, partitions) = MarketEventDatasetType::RecordedTrades.partition(
+            test_dir.path().to_path_buf(),
+            dt,
+            Exchange::Binance,
+            &"BTC_USDT".into(),
+            None,
+            None,
+        );
+        let dir = partition_dir(&base_dir, &partitions);
+        std::fs::create_dir_all(&dir).unwrap();
+        write_trades(&dir.join("trades.parquet"), dt);
+
+        let events = load_market_events(
+            vec![trades_channel()],
+            DateRange::by_day(dt, dt + Duration::hours(23)),
+            Some(DatasetCatalog::recorded(test_dir.path().to_path_buf(), DataFormat::Parquet).unwrap()),
+        )
+        .await
+        .unwrap();
+        assert_eq!(events.len(), 10);
+        assert!(events.iter().all(|e| matches!(e.e, MarketEvent::Trade(_))));
+        assert_eq!(events.last().unwrap().e.price(), 9.0);
+    }
+
+    #[actix_rt::test]
+    async fn klines_are_listed_in_object_stores() {
+        let resolution = Resolution::new(TimeUnit::Minute, 15);
+        let (base_dir, partitions) = MarketEventDatasetType::Candles.partition(
+            PathBuf::from("data"),
+            test_day(),
+            Exchange::Binance,
+            &"BTC_USDT".into(),
+            Some(SecurityType::Crypto),
+            Some(resolution),
+        );
+        let file = partition_dir(&base_dir, &partitions).join("klines.parquet");
+        let store = InMemory::new();
+        store
+            .put(&ObjectPath::from(file.to_str().unwrap()), b"candles".to_vec().into())
+            .await
+            .unwrap();
+        runtime_env().register_object_store(
+            ObjectStoreUrl::parse("s3://klines-test").unwrap().as_ref(),
+            Arc::new(store),
+        );
+        let reader = DatasetReader {
+            catalog: DatasetCatalog::default_basedir(PathBuf::from("s3://klines-test")),
+        };
+        let candles = MarketChannel::builder()
+            .symbol(Symbol::new("BTC_USDT".into(), SecurityType::Crypto, Exchange::Binance))
+            .r#type(MarketChannelType::Candles)
+            .resolution(Some(resolution))
+            .build();
+        assert!(reader.has_klines(&candles, test_day()).await);
+        assert!(!reader.has_klines(&candles, test_day() + Duration::days(1)).await);
+        let datasets = reader.datasets([candles].iter(), test_day()).await;
+        assert_eq!(datasets[0].r#type, MarketEventDatasetType::Candles);
+    }
+
+    #[actix_rt::test]
+    async fn read_legacy_recordings() {
+        util::test::init_test_env();
+        let test_dir = util::test::test_dir();
+        let dt = test_day();
+        // The former layout of the file logger
+        let legacy_dir = test_dir.path().join("Binance/trades/pr=BTC_USDT/dt=20220122");
+        std::fs::create_dir_all(&legacy_dir).unwrap();
+        write_trades(&legacy_dir.join("trades.parquet"), dt);
+
+        let catalog = DatasetCatalog::recorded(test_dir.path().to_path_buf(), DataFormat::Parquet).unwrap();
+        assert!(!test_dir.path().join("Binance").exists());
+        assert!(test_dir
+            .path()
+            .join("chan=trades/xch=binance/pr=BTC_USDT/dt=20220122/trades.parquet")
+            .exists());
+        let events = load_market_events(
+            vec![trades_channel()],
+            DateRange::by_day(dt, dt + Duration::hours(23)),
+            Some(catalog),
+        )
+        .await
+        .unwrap();
+        assert_eq!(events.len(), 10);
+    }
+
+    #[actix_rt::test]
+    async fn channels_missing_from_recordings_are_skipped() {
+        util::test::init_test_env();
+        let test_dir = util::test::test_dir();
+        let dt = test_day();
+        let (base_dir, partitions) = MarketEventDatasetType::RecordedTrades.partition(
+            test_dir.path().to_path_buf(),
+            dt,
+            Exchange::Binance,
+            &"BTC_USDT".into(),
+            None,
+            None,
+        );
+        let dir = partition_dir(&base_dir, &partitions);
+        std::fs::create_dir_all(&dir).unwrap();
+        write_trades(&dir.join("trades.parquet"), dt);
+
+        let funding_rates = MarketChannel::builder()
+            .symbol(Symbol::new("BTC_USDT".into(), SecurityType::Future, Exchange::Binance))
+            .r#type(MarketChannelType::FundingRate)
+            .build();
+        let events = load_market_events(
+            vec![trades_channel(), funding_rates],
+            DateRange::by_day(dt, dt + Duration::hours(23)),
+            Some(DatasetCatalog::recorded(test_dir.path().to_path_buf(), DataFormat::Parquet).unwrap()),
+        )
+        .await
+        .unwrap();
+        assert_eq!(events.len(), 10);
+    }
+
+    #[actix_rt::test]
+    async fn read_avro_recordings() {
+        util::test::init_test_env();
+        let test_dir = util::test::test_dir();
+        // Writers of past days expire right away, so record today
+        let dt = utc_at_midnight(Utc::now());
+        let base_dir = test_dir.path().to_str().unwrap().to_string();
+        let logger = SyncArbiter::start(1, move || {
+            AvroFileActor::new(&FileActorOptions {
+                base_dir: base_dir.clone(),
+                max_file_size: 100_000,
+                max_file_time: Duration::hours(1),
+                partitioner: Rc::new(MarketEventPartitioner::new(Duration::hours(1))),
+                format: FileFormat::Avro,
+            })
+        });
+        for i in 0..10 {
+            let trade = MarketEventEnvelope::trade_event(
+                Symbol::new("BTC_USDT".into(), SecurityType::Crypto, Exchange::Binance),
+                dt.timestamp_millis() + i64::from(i) * 1000,
+                f64::from(i),
+                0.1,
+                TradeType::Buy,
+                None,
+            );
+            logger.send(Arc::new(trade)).await.unwrap().unwrap();
+        }
+
+        let events = load_market_events(
+            vec![trades_channel()],
+            DateRange::by_day(dt, dt + Duration::hours(23)),
+            Some(DatasetCatalog::recorded(test_dir.path().to_path_buf(), DataFormat::Avro).unwrap()),
+        )
+        .await
+        .unwrap();
+        assert_eq!(events.len(), 10);
+        assert!(events.iter().all(|e| matches!(e.e, MarketEvent::Trade(_))));
+        assert_eq!(events.last().unwrap().e.price(), 9.0);
+    }
+}
@@ -0,0 +1,121 @@
+use brokers::prelude::*;
+use brokers::types::{Candle, SecurityType, Symbol};
+use chrono::{DateTime, TimeZone, Utc};
+use datafusion::arrow;
+use datafusion::arrow::array::{Array, Float64Array, StructArray, TimestampMillisecondArray, UInt16DictionaryArray,
+                               UInt64Array};
+use datafusion::arrow::record_batch::RecordBatch;
+use futures::{Stream, StreamExt};
+use stats::kline::Resolution;
+use stats::kline::TimeUnit::Minute;
+use std::collections::HashSet;
+use std::fmt::Debug;
+use std::path::Path;
+use std::str::FromStr;
+use tracing::Level;
+
+use crate::datafusion_util::{get_col_as, multitables_as_df, multitables_as_stream, print_struct_schema,
+                             string_partition};
+use crate::datasources::{event_ms_where_clause, join_where_clause};
+
+const KLINES_TABLE_NAME: &str = "candles";
+
+/// The resolution of candles read from channels that do not specify one
+pub(crate) fn klines_resolution(resolution: Option<Resolution>) -> Resolution {
+    resolution.unwrap_or_else(|| Resolution::new(Minute, 15))
+}
+
+fn klines_sql_query(lower_dt: Option<DateTime<Utc>>, upper_dt: Option<DateTime<Utc>>) -> String {
+    format!("select xch, sym, ast, to_timestamp_millis(start_ms) as start_ts, to_timestamp_millis(end_ms) as end_ts, open, high, low, close, volume, quote_volume, trade_count from {table} {where} order by start_ms asc", table = KLINES_TABLE_NAME, where = join_where_clause(event_ms_where_clause("start_ms", upper_dt, lower_dt)))
+}
+
+/// Read partitions of downloaded candles
+pub fn klines_stream<P: 'static + AsRef<Path> + Debug>(
+    table_paths: HashSet<(P, Vec<(&'static str, String)>)>,
+    format: String,
+    lower_dt: Option<DateTime<Utc>>,
+    upper_dt: Option<DateTime<Utc>>,
+) -> impl Stream<Item = MarketEventEnvelope> + 'static {
+    multitables_as_stream(
+        table_paths,
+        format,
+        Some(KLINES_TABLE_NAME.to_string()),
+        klines_sql_query(lower_dt, upper_dt),
+    )
+    .map(events_from_klines)
+    .flatten()
+}
+
+/// Read partitions of downloaded candles as a recordbatch
+pub async fn klines_df<P: 'static + AsRef<Path> + Debug>(
+    table_paths: HashSet<(P, Vec<(&'static str, String)>)>,
+    format: String,
+    lower_dt: Option<DateTime<Utc>>,
+    upper_dt: Option<DateTime<Utc>>,
+) -> crate::error::Result<RecordBatch> {
+    let batch = multitables_as_df(
+        table_paths,
+        format,
+        Some(KLINES_TABLE_NAME.to_string()),
+        klines_sql_query(lower_dt, upper_dt),
+    )
+    .await?;
+    if tracing::enabled!(Level::TRACE) {
+        trace!("klines = {:?}", arrow::util::pretty::print_batches(&[batch.clone()]));
+    }
+    Ok(batch)
+}
+
+/// Expects a record batch with the following schema :
+/// `start_ts` : `TimestampMillisecond`
+/// `end_ts` : `TimestampMillisecond`
+/// open, high, low, close, volume, `quote_volume` : f64
+/// `trade_count` : u64
+/// xch, sym, ast : String
+fn events_from_klines(record_batch: RecordBatch) -> impl Stream<Item = MarketEventEnvelope> + 'static {
+    let sa: StructArray = record_batch.into();
+    stream! {
+        print_struct_schema(&sa, "klines");
+        let start_ts_col = get_col_as::<TimestampMillisecondArray>(&sa, "start_ts");
+        let end_ts_col = get_col_as::<TimestampMillisecondArray>(&sa, "end_ts");
+        let open_col = get_col_as::<Float64Array>(&sa, "open");
+        let high_col = get_col_as::<Float64Array>(&sa, "high");
+        let low_col = get_col_as::<Float64Array>(&sa, "low");
+        let close_col = get_col_as::<Float64Array>(&sa, "close");
+        let volume_col = get_col_as::<Float64Array>(&sa, "volume");
+        let quote_volume_col = get_col_as::<Float64Array>(&sa, "quote_volume");
+        let trade_count_col = get_col_as::<UInt64Array>(&sa, "trade_count");
+        let sym_col = get_col_as::<UInt16DictionaryArray>(&sa, "sym");
+        let xch_col = get_col_as::<UInt16DictionaryArray>(&sa, "xch");
+        let ast_col = get_col_as::<UInt16DictionaryArray>(&sa, "ast");
+
+        for i in 0..sa.len() {
+            let sym_str = string_partition(sym_col, i).unwrap();
+
+            let xch_str = string_partition(xch_col, i).unwrap();
+            let xchg = Exchange::from_str(&xch_str).unwrap_or_else(|_| panic!("wrong xchg {}", xch_str));
+
+            let ast_str = string_partition(ast_col, i).unwrap();
+            let ast = SecurityType::from_str(&ast_str).unwrap_or_else(|_| panic!("wrong security type {}", ast_str));
+
+            let end_time = Utc.timestamp_millis_opt(end_ts_col.value(i)).unwrap();
+            yield MarketEventEnvelope::new(
+                Symbol::new(sym_str.clone().into(), ast, xchg),
+                MarketEvent::TradeCandle(Candle {
+                    event_time: end_time,
+                    pair: sym_str.into(),
+                    start_time: Utc.timestamp_millis_opt(start_ts_col.value(i)).unwrap(),
+                    end_time,
+                    open: open_col.value(i),
+                    high: high_col.value(i),
+                    low: low_col.value(i),
+                    close: close_col.value(i),
+                    volume: volume_col.value(i),
+                    quote_volume: quote_volume_col.value(i),
+                    trade_count: trade_count_col.value(i),
+                    is_final: true,
+                }),
+            );
+        }
+    }
+}
@@ -1,6 +1,10 @@
 use chrono::{DateTime, Utc};
 
+pub mod funding_rates;
+pub mod klines;
+pub mod open_interest;
 pub mod orderbook;
+pub mod quotes;
 pub mod trades;
 
 pub(crate) fn event_ms_where_clause(
@@ -0,0 +1,111 @@
+use brokers::prelude::Exchange;
+use chrono::{DateTime, Duration, Utc};
+use datafusion::arrow;
+use datafusion::arrow::array::{Array, Float64Array, StringArray, StructArray, TimestampMillisecondArray,
+                               UInt16DictionaryArray};
+use datafusion::arrow::record_batch::RecordBatch;
+use std::collections::HashSet;
+use std::fmt::Debug;
+use std::path::Path;
+use std::str::FromStr;
+
+use brokers::prelude::{MarketEvent, MarketEventEnvelope};
+use brokers::types::{OpenInterest, SecurityType, Symbol};
+use futures::StreamExt;
+use tokio_stream::Stream;
+use tracing::Level;
+
+use crate::datafusion_util::{get_col_as, multitables_as_df, multitables_as_stream, print_struct_schema,
+                             string_partition};
+use crate::datasources::{event_ms_where_clause, join_where_clause};
+
+const OPEN_INTEREST_TABLE_NAME: &str = "open_interests";
+
+fn open_interest_sql_query(
+    lower_dt: Option<DateTime<Utc>>,
+    upper_dt: Option<DateTime<Utc>>,
+    tick_rate: Option<Duration>,
+) -> String {
+    let table = if let Some(tr) = tick_rate {
+        format!("(select * from (select *,ROW_NUMBER() OVER (PARTITION BY event_ms / {sample_rate} order by event_ms asc) as row_num from {table}) as t1 where row_num = 1) as t2", table = OPEN_INTEREST_TABLE_NAME, sample_rate = tr.num_milliseconds())
+    } else {
+        OPEN_INTEREST_TABLE_NAME.to_string()
+    };
+    format!("select xch, pair, to_timestamp_millis(event_ms) as event_ts, open_interest from {table} {where} order by event_ms asc", table = table, where = join_where_clause(event_ms_where_clause("event_ms", upper_dt, lower_dt)))
+}
+
+/// Read partitions as open interest
+pub fn open_interest_stream<P: 'static + AsRef<Path> + Debug>(
+    table_paths: HashSet<(P, Vec<(&'static str, String)>)>,
+    format: String,
+    lower_dt: Option<DateTime<Utc>>,
+    upper_dt: Option<DateTime<Utc>>,
+    tick_rate: Option<Duration>,
+) -> impl Stream<Item = MarketEventEnvelope> + 'static {
+    multitables_as_stream(
+        table_paths,
+        format,
+        Some(OPEN_INTEREST_TABLE_NAME.to_string()),
+        open_interest_sql_query(lower_dt, upper_dt, tick_rate),
+    )
+    .map(events_from_open_interest)
+    .flatten()
+}
+
+/// Read partitions as an open interest recordbatch
+pub async fn open_interest_df<P: 'static + AsRef<Path> + Debug>(
+    table_paths: HashSet<(P, Vec<(&'static str, String)>)>,
+    format: String,
+    lower_dt: Option<DateTime<Utc>>,
+    upper_dt: Option<DateTime<Utc>>,
+    tick_rate: Option<Duration>,
+) -> crate::error::Result<RecordBatch> {
+    let batch = multitables_as_df(
+        table_paths,
+        format,
+        Some(OPEN_INTEREST_TABLE_NAME.to_string()),
+        open_interest_sql_query(lower_dt, upper_dt, tick_rate),
+    )
+    .await?;
+    if tracing::enabled!(Level::TRACE) {
+        trace!(
+            "open_interest = {:?}",
+            arrow::util::pretty::print_batches(&[batch.clone()])
+        );
+    }
+    Ok(batch)
+}
+
+/// Expects a record batch with the following schema :
+/// `open_interest` : f64
+/// `event_ts` : `TimestampMillisecond`
+/// pair : String
+/// xch : String
+fn events_from_open_interest(record_batch: RecordBatch) -> impl Stream<Item = MarketEventEnvelope> + 'static {
+    let sa: StructArray = record_batch.into();
+    stream! {
+        print_struct_schema(&sa, "open_interest");
+
+        let open_interest_col = get_col_as::<Float64Array>(&sa, "open_interest");
+        let event_ms_col = get_col_as::<TimestampMillisecondArray>(&sa, "event_ts");
+        let pair_col = get_col_as::<StringArray>(&sa, "pair");
+        let xch_col = get_col_as::<UInt16DictionaryArray>(&sa, "xch");
+
+        for i in 0..sa.len() {
+            let ts = event_ms_col.value(i);
+            let pair = pair_col.value(i);
+
+            let xch_str = string_partition(xch_col, i).unwrap();
+            let xchg = Exchange::from_str(&xch_str).unwrap_or_else(|_| panic!("wrong xchg {}", xch_str));
+
+            yield MarketEventEnvelope::new(
+                Symbol::new(pair.into(), SecurityType::Crypto, xchg),
+                MarketEvent::OpenInterest(OpenInterest {
+                    event_ms: ts,
+                    pair: pair.into(),
+                    open_interest: open_interest_col.value(i),
+                }),
+            );
+        }
+    }
+}
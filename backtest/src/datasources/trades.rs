@@ -4,8 +4,8 @@ use crate::datasources::{event_ms_where_clause, join_where_clause};
 use brokers::prelude::*;
 use brokers::types::{Candle, SecurityType, Symbol};
 use chrono::{DateTime, Duration, Utc};
-use datafusion::arrow::array::{Array, BooleanArray, Float64Array, StructArray, TimestampMillisecondArray,
-                               UInt16DictionaryArray};
+use datafusion::arrow::array::{Array, BooleanArray, Float64Array, Int32Array, StringArray, StructArray,
+                               TimestampMillisecondArray, UInt16DictionaryArray};
 use datafusion::arrow::record_batch::RecordBatch;
 use futures::{Stream, StreamExt};
 use stats::kline::Resolution;
@@ -17,6 +17,8 @@ use std::path::Path;
 use std::str::FromStr;
 use tracing::Level;
 
+const TRADES_TABLE_NAME: &str = "trades";
+
 /// Read partitions as trades
 pub fn candles_stream<P: 'static + AsRef<Path> + Debug>(
     table_paths: HashSet<(P, Vec<(&'static str, String)>)>,
@@ -25,9 +27,35 @@ pub fn candles_stream<P: 'static + AsRef<Path> + Debug>(
     upper_dt: Option<DateTime<Utc>>,
     resolution: Option<Resolution>,
     tick_rate: Option<Duration>,
+) -> impl Stream<Item = MarketEventEnvelope> {
+    candles_from_trades(
+        trades_stream(table_paths, format, lower_dt, upper_dt, tick_rate),
+        resolution,
+    )
+}
+
+/// Read partitions of trades recorded by the file logger as candles
+pub fn recorded_candles_stream<P: 'static + AsRef<Path> + Debug>(
+    table_paths: HashSet<(P, Vec<(&'static str, String)>)>,
+    format: String,
+    lower_dt: Option<DateTime<Utc>>,
+    upper_dt: Option<DateTime<Utc>>,
+    resolution: Option<Resolution>,
+    tick_rate: Option<Duration>,
+) -> impl Stream<Item = MarketEventEnvelope> {
+    candles_from_trades(
+        recorded_trades_stream(table_paths, format, lower_dt, upper_dt, tick_rate),
+        resolution,
+    )
+}
+
+/// Aggregate a stream of trades into candles
+fn candles_from_trades<S: Stream<Item = MarketEventEnvelope>>(
+    trades: S,
+    resolution: Option<Resolution>,
 ) -> impl Stream<Item = MarketEventEnvelope> {
     let resolution = resolution.unwrap_or_else(|| Resolution::new(Minute, 15));
-    trades_stream(table_paths, format, lower_dt, upper_dt, tick_rate).scan(
+    trades.scan(
         stats::kline::Kline::new(resolution, 2_usize.pow(16)),
         |kl, msg: MarketEventEnvelope| {
             let event_time = msg.e.time();
@@ -57,18 +85,29 @@ pub fn candles_stream<P: 'static + AsRef<Path> + Debug>(
     // })
 }
 
+fn sampled_table(table_name: &str, tick_rate: Option<Duration>) -> String {
+    if let Some(tr) = tick_rate {
+        format!("(select * from (select *,ROW_NUMBER() OVER (PARTITION BY event_ms / {sample_rate} order by event_ms asc) as row_num from {table}) as t1 where row_num = 1) as t2", table = table_name, sample_rate = tr.num_milliseconds())
+    } else {
+        table_name.to_string()
+    }
+}
+
 fn trades_sql_query(
     table_name: String,
     lower_dt: Option<DateTime<Utc>>,
     upper_dt: Option<DateTime<Utc>>,
     tick_rate: Option<Duration>,
 ) -> String {
-    let table = if let Some(tr) = tick_rate {
-        format!("(select * from (select *,ROW_NUMBER() OVER (PARTITION BY event_ms / {sample_rate} order by event_ms asc) as row_num from {table}) as t1 where row_num = 1) as t2", table = &table_name, sample_rate = tr.num_milliseconds())
-    } else {
-        table_name.clone()
-    };
-    format!("select xch, to_timestamp_millis(event_ms) as event_ts, sym, ast, price, qty, quote_qty, is_buyer_maker from {table} {where} order by event_ms asc", table = table, where = join_where_clause(event_ms_where_clause("event_ms", upper_dt, lower_dt)))
+    format!("select xch, to_timestamp_millis(event_ms) as event_ts, sym, ast, price, qty, quote_qty, is_buyer_maker from {table} {where} order by event_ms asc", table = sampled_table(&table_name, tick_rate), where = join_where_clause(event_ms_where_clause("event_ms", upper_dt, lower_dt)))
+}
+
+fn recorded_trades_sql_query(
+    lower_dt: Option<DateTime<Utc>>,
+    upper_dt: Option<DateTime<Utc>>,
+    tick_rate: Option<Duration>,
+) -> String {
+    format!("select xch, pair, to_timestamp_millis(event_ms) as event_ts, price, amount, tt from {table} {where} order by event_ms asc", table = sampled_table(TRADES_TABLE_NAME, tick_rate), where = join_where_clause(event_ms_where_clause("event_ms", upper_dt, lower_dt)))
 }
 
 /// Read partitions as trades
@@ -90,6 +129,48 @@ pub fn trades_stream<P: 'static + AsRef<Path> + Debug>(
     .flatten()
 }
 
+/// Read partitions of trades recorded by the file logger
+pub fn recorded_trades_stream<P: 'static + AsRef<Path> + Debug>(
+    table_paths: HashSet<(P, Vec<(&'static str, String)>)>,
+    format: String,
+    lower_dt: Option<DateTime<Utc>>,
+    upper_dt: Option<DateTime<Utc>>,
+    tick_rate: Option<Duration>,
+) -> impl Stream<Item = MarketEventEnvelope> + 'static {
+    multitables_as_stream(
+        table_paths,
+        format,
+        Some(TRADES_TABLE_NAME.to_string()),
+        recorded_trades_sql_query(lower_dt, upper_dt, tick_rate),
+    )
+    .map(events_from_recorded_trades)
+    .flatten()
+}
+
+/// Read partitions of trades recorded by the file logger as a recordbatch
+pub async fn recorded_trades_df<P: 'static + AsRef<Path> + Debug>(
+    table_paths: HashSet<(P, Vec<(&'static str, String)>)>,
+    format: String,
+    lower_dt: Option<DateTime<Utc>>,
+    upper_dt: Option<DateTime<Utc>>,
+    tick_rate: Option<Duration>,
+) -> crate::error::Result<RecordBatch> {
+    let batch = multitables_as_df(
+        table_paths,
+        format,
+        Some(TRADES_TABLE_NAME.to_string()),
+        recorded_trades_sql_query(lower_dt, upper_dt, tick_rate),
+    )
+    .await?;
+    if tracing::enabled!(Level::TRACE) {
+        trace!(
+            "recorded trades = {:?}",
+            datafusion::arrow::util::pretty::print_batches(&[batch.clone()])
+        );
+    }
+    Ok(batch)
+}
+
 /// Read partitions as trades
 pub async fn trades_df<P: 'static + AsRef<Path> + Debug>(
     table_paths: HashSet<(P, Vec<(&'static str, String)>)>,
@@ -157,12 +238,47 @@ fn events_from_trades(record_batch: RecordBatch) -> impl Stream<Item = MarketEve
                 price,
                 qty,
                 is_buyer_maker.into(),
+                Some(TradeType::aggressor(is_buyer_maker)),
             );
 
         }
     }
 }
 
+/// Expects a record batch with the following schema :
+/// price, amount : f64
+/// tt : i32
+/// `event_ts` : `TimestampMillisecond`
+/// pair : String
+/// xch : String
+fn events_from_recorded_trades(record_batch: RecordBatch) -> impl Stream<Item = MarketEventEnvelope> + 'static {
+    let sa: StructArray = record_batch.into();
+
+    stream! {
+        print_struct_schema(&sa, "recorded trades");
+        let price_col = get_col_as::<Float64Array>(&sa, "price");
+        let amount_col = get_col_as::<Float64Array>(&sa, "amount");
+        let tt_col = get_col_as::<Int32Array>(&sa, "tt");
+        let event_ms_col = get_col_as::<TimestampMillisecondArray>(&sa, "event_ts");
+        let pair_col = get_col_as::<StringArray>(&sa, "pair");
+        let xch_col = get_col_as::<UInt16DictionaryArray>(&sa, "xch");
+
+        for i in 0..sa.len() {
+            let xch_str = string_partition(xch_col, i).unwrap();
+            let xchg = Exchange::from_str(&xch_str).unwrap_or_else(|_| panic!("wrong xchg {}", xch_str));
+
+            yield MarketEventEnvelope::trade_event(
+                Symbol::new(pair_col.value(i).into(), SecurityType::Crypto, xchg),
+                event_ms_col.value(i),
+                price_col.value(i),
+                amount_col.value(i),
+                TradeType::from(i64::from(tt_col.value(i))),
+                None,
+            );
+        }
+    }
+}
+
 /// Read trades partitions as candles
 pub async fn candles_df<P: 'static + AsRef<Path> + Debug>(
     table_paths: HashSet<(P, Vec<(&'static str, String)>)>,
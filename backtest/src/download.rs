@@ -0,0 +1,237 @@
+//! Downloads historical candles from the REST api of exchanges into the candles dataset of a catalog, so that
+//! backtests can run without third party data.
+
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use chrono::{DateTime, Duration, Utc};
+use datafusion::arrow::array::{ArrayRef, Float64Array, Int64Array, UInt64Array};
+use datafusion::arrow::datatypes::{DataType, Field, Schema};
+use datafusion::arrow::record_batch::RecordBatch;
+use datafusion::parquet::arrow::ArrowWriter;
+
+use brokers::prelude::*;
+use brokers::types::{Candle, SecurityType};
+use stats::kline::Resolution;
+use util::time::{utc_at_midnight, DateRange};
+
+use crate::dataset::{partition_dir, DatasetCatalog, MarketEventDatasetType};
+use crate::error::*;
+
+/// Each day of candles is written to a single file of its partition
+const KLINES_FILE: &str = "candles.parquet";
+
+/// Downloads the candles of a pair one day at a time, days that were already downloaded are skipped so that an
+/// interrupted download resumes where it stopped
+pub struct CandlesDownloader {
+    api: Arc<dyn Brokerage>,
+    catalog: DatasetCatalog,
+    /// Pause between two requests, on top of the rate limits of the api
+    request_interval: std::time::Duration,
+}
+
+impl CandlesDownloader {
+    pub fn new(api: Arc<dyn Brokerage>, catalog: DatasetCatalog) -> Self {
+        Self {
+            api,
+            catalog,
+            request_interval: std::time::Duration::ZERO,
+        }
+    }
+
+    #[must_use]
+    pub fn with_request_interval(mut self, request_interval: std::time::Duration) -> Self {
+        self.request_interval = request_interval;
+        self
+    }
+
+    /// Download the candles of every day from `from` to `to` included, days that are not over yet are skipped
+    ///
+    /// # Arguments
+    ///
+    /// * `pair`: the pair of the candles
+    /// * `sec_type`: the security type of the pair
+    /// * `resolution`: the interval of each candle
+    /// * `from`: the first day to download
+    /// * `to`: the last day to download
+    ///
+    /// returns: Result<Vec<DateTime<Utc>>, Error> the days that were written
+    pub async fn download(
+        &self,
+        pair: &Pair,
+        sec_type: SecurityType,
+        resolution: Resolution,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<DateTime<Utc>>> {
+        let mut written = vec![];
+        for dt in DateRange::by_day(utc_at_midnight(from), utc_at_midnight(to)) {
+            let day_end = dt + Duration::days(1);
+            if day_end > Utc::now() {
+                break;
+            }
+            let file = self.day_file(pair, sec_type, resolution, dt)?;
+            if file.exists() {
+                debug!(pair = %pair, dt = %dt, "candles already downloaded");
+                continue;
+            }
+            let candles = self.fetch_candles(pair, resolution, dt, day_end).await?;
+            write_candles(&file, &candles)?;
+            info!(xch = %self.api.exchange(), pair = %pair, dt = %dt, candles = candles.len(), "downloaded candles");
+            written.push(dt);
+        }
+        Ok(written)
+    }
+
+    /// The file holding the candles of a day
+    fn day_file(
+        &self,
+        pair: &Pair,
+        sec_type: SecurityType,
+        resolution: Resolution,
+        dt: DateTime<Utc>,
+    ) -> Result<PathBuf> {
+        let table_def = self
+            .catalog
+            .get(MarketEventDatasetType::Candles)
+            .ok_or_else(|| anyhow!("no candles dataset in catalog"))?;
+        let (base_dir, partitions) = MarketEventDatasetType::Candles.partition(
+            table_def.base_dir.clone(),
+            dt,
+            self.api.exchange(),
+            pair,
+            Some(sec_type),
+            Some(resolution),
+        );
+        Ok(partition_dir(&base_dir, &partitions).join(KLINES_FILE))
+    }
+
+    /// Page through the candles opened between `start` and `end`, exchanges cap the candles returned by a request
+    async fn fetch_candles(
+        &self,
+        pair: &Pair,
+        resolution: Resolution,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Vec<Candle>> {
+        let mut candles: Vec<Candle> = vec![];
+        let mut page_start = start;
+        while page_start < end {
+            if !candles.is_empty() && !self.request_interval.is_zero() {
+                tokio::time::sleep(self.request_interval).await;
+            }
+            let page = self.api.candles(pair.clone(), resolution, page_start, end).await?;
+            let Some(last) = page.last() else {
+                break;
+            };
+            page_start = resolution.add(last.start_time);
+            let last_start = candles.last().map(|c| c.start_time);
+            candles.extend(
+                page.into_iter()
+                    .filter(|c| c.start_time < end && last_start.map_or(true, |l| c.start_time > l)),
+            );
+        }
+        Ok(candles)
+    }
+}
+
+/// Write the candles of a day as a parquet file, through a temporary file so that a file only exists once complete
+fn write_candles(file: &Path, candles: &[Candle]) -> Result<()> {
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("start_ms", DataType::Int64, false),
+        Field::new("end_ms", DataType::Int64, false),
+        Field::new("open", DataType::Float64, false),
+        Field::new("high", DataType::Float64, false),
+        Field::new("low", DataType::Float64, false),
+        Field::new("close", DataType::Float64, false),
+        Field::new("volume", DataType::Float64, false),
+        Field::new("quote_volume", DataType::Float64, false),
+        Field::new("trade_count", DataType::UInt64, false),
+    ]));
+    let f64_col =
+        |f: fn(&Candle) -> f64| -> ArrayRef { Arc::new(Float64Array::from_iter_values(candles.iter().map(f))) };
+    let batch = RecordBatch::try_new(schema.clone(), vec![
+        Arc::new(Int64Array::from_iter_values(
+            candles.iter().map(|c| c.start_time.timestamp_millis()),
+        )),
+        Arc::new(Int64Array::from_iter_values(
+            candles.iter().map(|c| c.end_time.timestamp_millis()),
+        )),
+        f64_col(|c| c.open),
+        f64_col(|c| c.high),
+        f64_col(|c| c.low),
+        f64_col(|c| c.close),
+        f64_col(|c| c.volume),
+        f64_col(|c| c.quote_volume),
+        Arc::new(UInt64Array::from_iter_values(candles.iter().map(|c| c.trade_count))),
+    ])?;
+    if let Some(dir) = file.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let tmp_file = file.with_extension("parquet.tmp");
+    let mut writer = ArrowWriter::try_new(File::create(&tmp_file)?, schema, None)?;
+    writer.write(&batch)?;
+    writer.close()?;
+    std::fs::rename(tmp_file, file)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::ops::Add;
+    use std::sync::Arc;
+
+    use chrono::{DateTime, Duration, NaiveDate, Utc};
+
+    use brokers::api::MockBrokerage;
+    use brokers::exchange::Exchange;
+    use brokers::types::{MarketChannel, MarketChannelType, MarketEvent, SecurityType, Symbol};
+    use stats::kline::{Resolution, TimeUnit};
+    use util::time::DateRange;
+
+    use crate::{load_market_events, CandlesDownloader, DatasetCatalog};
+
+    #[actix_rt::test]
+    async fn download_candles() {
+        util::test::init_test_env();
+        let test_dir = util::test::test_dir();
+        let catalog = DatasetCatalog::default_basedir(test_dir.path().to_path_buf());
+        let downloader = CandlesDownloader::new(Arc::new(MockBrokerage::default()), catalog.clone());
+        let dt = DateTime::from_utc(
+            NaiveDate::from_ymd_opt(2022, 1, 22)
+                .unwrap()
+                .and_hms_opt(0, 0, 0)
+                .unwrap(),
+            Utc,
+        );
+        let resolution = Resolution::new(TimeUnit::Minute, 1);
+        let pair = "BTC_USDT".into();
+        let written = downloader
+            .download(&pair, SecurityType::Crypto, resolution, dt, dt.add(Duration::days(1)))
+            .await
+            .unwrap();
+        assert_eq!(written, vec![dt, dt.add(Duration::days(1))]);
+        // Downloaded days are skipped
+        let written = downloader
+            .download(&pair, SecurityType::Crypto, resolution, dt, dt.add(Duration::days(1)))
+            .await
+            .unwrap();
+        assert!(written.is_empty());
+
+        let events = load_market_events(
+            vec![MarketChannel::builder()
+                .symbol(Symbol::new(pair, SecurityType::Crypto, Exchange::Binance))
+                .r#type(MarketChannelType::Candles)
+                .resolution(Some(resolution))
+                .build()],
+            DateRange::by_day(dt, dt.add(Duration::days(1))),
+            Some(catalog),
+        )
+        .await
+        .unwrap();
+        // More candles than a single request returns
+        assert_eq!(events.len(), 2 * 24 * 60);
+        assert!(events.iter().all(|e| matches!(e.e, MarketEvent::TradeCandle(_))));
+    }
+}
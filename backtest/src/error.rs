@@ -1,6 +1,7 @@
 use config::ConfigError;
 use datafusion::arrow::error::ArrowError;
 use datafusion::error::DataFusionError;
+use datafusion::parquet::errors::ParquetError;
 use trading::book::BookError;
 
 #[derive(thiserror::Error, Debug)]
@@ -19,6 +20,14 @@ pub enum Error {
     AnyhowError(#[from] anyhow::Error),
     #[error("json error {0}")]
     Json(#[from] serde_json::Error),
+    #[error("parquet error")]
+    ParquetError(#[from] ParquetError),
+    #[error("broker error {0}")]
+    BrokerError(#[from] brokers::error::Error),
+    #[error("object store error {0}")]
+    ObjectStoreError(#[from] object_store::Error),
+    #[error("db error {0}")]
+    DbError(#[from] db::Error),
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
@@ -0,0 +1,547 @@
+//! Simulated execution of orders : orders passed to the brokerage of a backtest rest until later market events fill
+//! them according to a fill model, fills are then reported to the order manager as account events.
+//!
+//! Market events are passed to the brokerage by the replay loop before they are broadcast to the strategies, so that
+//! fills follow the order of the replayed events.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+
+use actix::Recipient;
+use chrono::{DateTime, Duration, Utc};
+use once_cell::sync::OnceCell;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use stats::kline::Resolution;
+
+use brokers::api::{Brokerage, MockBrokerage};
+use brokers::error::Result as BrokerResult;
+use brokers::exchange::Exchange;
+use brokers::fees::FeeTier;
+use brokers::pair::PairConf;
+use brokers::types::*;
+
+/// How resting orders are filled by market events
+#[derive(Debug, Default, Clone, Copy, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum FillModel {
+    /// Orders are filled entirely at their price as soon as they are active
+    #[default]
+    Immediate,
+    /// Market orders are filled at the best opposite price, limit orders at their price once the best opposite price
+    /// crosses it
+    TopOfBookCross,
+    /// Like [`FillModel::TopOfBookCross`], but each event only fills a fraction of the volume traded or quoted at the
+    /// best opposite price, so large orders are partially filled over several events
+    VolumeParticipation {
+        /// Fraction of the available volume an order can take, between 0 and 1
+        participation: f64,
+    },
+}
+
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct FillSettings {
+    #[serde(default)]
+    pub model: FillModel,
+    /// Delay after which orders are active, from the time of the event the order was passed on
+    #[serde(
+        default,
+        deserialize_with = "util::ser::string_duration_chrono_opt",
+        serialize_with = "util::ser::encode_duration_str_opt"
+    )]
+    pub latency: Option<Duration>,
+}
+
+/// The best prices of a pair, and the volume available at them
+#[derive(Debug, Clone, Copy)]
+struct Liquidity {
+    bid: f64,
+    ask: f64,
+    /// Volume a sell order can take
+    bid_qty: Option<f64>,
+    /// Volume a buy order can take
+    ask_qty: Option<f64>,
+}
+
+impl Liquidity {
+    fn from_event(e: &MarketEvent) -> Option<Self> {
+        match e {
+            MarketEvent::Trade(t) => Some(Self {
+                bid: t.price,
+                ask: t.price,
+                bid_qty: Some(t.amount),
+                ask_qty: Some(t.amount),
+            }),
+            MarketEvent::Quote(q) => Some(Self {
+                bid: q.bid,
+                ask: q.ask,
+                bid_qty: Some(q.bid_qty),
+                ask_qty: Some(q.ask_qty),
+            }),
+            MarketEvent::Orderbook(ob) => {
+                let (bid, bid_qty) = *ob.bids.first()?;
+                let (ask, ask_qty) = *ob.asks.first()?;
+                Some(Self {
+                    bid,
+                    ask,
+                    bid_qty: Some(bid_qty),
+                    ask_qty: Some(ask_qty),
+                })
+            }
+            MarketEvent::TradeCandle(c) => Some(Self {
+                bid: c.close,
+                ask: c.close,
+                bid_qty: Some(c.volume),
+                ask_qty: Some(c.volume),
+            }),
+            MarketEvent::BookCandle(c) => Some(Self {
+                bid: c.bid.close,
+                ask: c.ask.close,
+                bid_qty: None,
+                ask_qty: None,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// An order passed to the brokerage and not yet entirely filled
+#[derive(Debug, Clone)]
+struct RestingOrder {
+    request: AddOrderRequest,
+    /// The order cannot be filled by events before this time
+    active_at: DateTime<Utc>,
+    filled_qty: f64,
+    filled_quote_qty: f64,
+}
+
+impl RestingOrder {
+    fn qty(&self) -> f64 { self.request.quantity.unwrap_or(0.0) }
+
+    fn remaining(&self) -> f64 { (self.qty() - self.filled_qty).max(0.0) }
+
+    /// The price and quantity of the next fill of the order
+    fn fill(&self, model: FillModel, liquidity: &Liquidity) -> Option<(f64, f64)> {
+        let (best, available) = match self.request.side {
+            TradeType::Buy => (liquidity.ask, liquidity.ask_qty),
+            TradeType::Sell => (liquidity.bid, liquidity.bid_qty),
+        };
+        let limit = self.request.price.filter(|_| is_limit(self.request.order_type));
+        if let FillModel::Immediate = model {
+            return Some((limit.unwrap_or(best), self.remaining()));
+        }
+        let price = match limit {
+            Some(limit) => {
+                let crossed = match self.request.side {
+                    TradeType::Buy => best <= limit,
+                    TradeType::Sell => best >= limit,
+                };
+                if !crossed {
+                    return None;
+                }
+                limit
+            }
+            None => best,
+        };
+        let qty = match model {
+            FillModel::VolumeParticipation { participation } => {
+                available.map_or(0.0, |v| v * participation).min(self.remaining())
+            }
+            _ => self.remaining(),
+        };
+        (qty > 0.0).then_some((price, qty))
+    }
+}
+
+fn is_limit(order_type: OrderType) -> bool { matches!(order_type, OrderType::Limit | OrderType::LimitMaker) }
+
+#[derive(Debug)]
+struct SimulationState {
+    /// Resting orders by arrival, so that orders are filled in the order they were passed
+    orders: BTreeMap<u64, RestingOrder>,
+    next_seq: u64,
+    /// The time of the last market event
+    last_event_time: Option<DateTime<Utc>>,
+    /// Generates the ids of submissions, seeded so that runs are reproducible
+    ids: StdRng,
+}
+
+impl SimulationState {
+    fn next_id(&mut self) -> String { uuid::Builder::from_random_bytes(self.ids.gen()).into_uuid().to_string() }
+}
+
+/// A mock brokerage which fills market and limit orders following a [`FillModel`] as market events are replayed,
+/// other order types are filled immediately, fills pay the maker or taker rate of the fee schedule of the exchange
+/// of the order
+#[derive(Debug)]
+pub struct SimulatedBrokerage {
+    inner: MockBrokerage,
+    model: FillModel,
+    latency: Duration,
+    /// Fees of exchanges without a fee schedule
+    fees: FeeTier,
+    fee_schedules: HashMap<Exchange, FeeTier>,
+    state: Mutex<SimulationState>,
+    order_manager: OnceCell<Recipient<AccountEventEnveloppe>>,
+}
+
+impl SimulatedBrokerage {
+    pub fn new(settings: &FillSettings, fees: FeeTier, seed: u64) -> Self {
+        Self {
+            inner: MockBrokerage::default(),
+            model: settings.model,
+            latency: settings.latency.unwrap_or_else(Duration::zero),
+            fees,
+            fee_schedules: HashMap::default(),
+            state: Mutex::new(SimulationState {
+                orders: BTreeMap::default(),
+                next_seq: 0,
+                last_event_time: None,
+                ids: StdRng::seed_from_u64(seed),
+            }),
+            order_manager: OnceCell::new(),
+        }
+    }
+
+    /// Fill the orders of each exchange with the fees of its schedule
+    #[must_use]
+    pub fn with_fee_schedules(mut self, fee_schedules: HashMap<Exchange, FeeTier>) -> Self {
+        self.fee_schedules = fee_schedules;
+        self
+    }
+
+    fn fee_tier_of(&self, xch: Exchange) -> FeeTier { self.fee_schedules.get(&xch).copied().unwrap_or(self.fees) }
+
+    /// Whether orders are filled as they are passed, like the default mock brokerage
+    fn fills_on_submission(&self) -> bool { matches!(self.model, FillModel::Immediate) && self.latency.is_zero() }
+
+    /// Set the order manager which receives fills
+    pub fn set_order_manager(&self, recipient: Recipient<AccountEventEnveloppe>) {
+        if self.order_manager.set(recipient).is_err() {
+            warn!("the order manager of the simulated brokerage is already set");
+        }
+    }
+
+    /// Fill the active orders of the pair of the event, and report fills to the order manager
+    pub fn on_market_event(&self, event: &MarketEventEnvelope) {
+        let updates = {
+            let mut state = self.state.lock().unwrap();
+            let time = event.e.time();
+            state.last_event_time = Some(time);
+            let Some(liquidity) = Liquidity::from_event(&event.e) else {
+                return;
+            };
+            let mut updates = vec![];
+            state.orders.retain(|_, order| {
+                if order.request.pair != event.symbol.value || order.active_at > time {
+                    return true;
+                }
+                let Some((price, qty)) = order.fill(self.model, &liquidity) else {
+                    return true;
+                };
+                order.filled_qty += qty;
+                order.filled_quote_qty += price * qty;
+                updates.push((order.request.xch, self.fill_update(order, price, qty, time)));
+                order.remaining() > 0.0
+            });
+            updates
+        };
+        let Some(order_manager) = self.order_manager.get() else {
+            return;
+        };
+        for (xchg, update) in updates {
+            order_manager.do_send(AccountEventEnveloppe {
+                xchg,
+                event: AccountEvent::OrderUpdate(update),
+                account_type: AccountType::Spot,
+                account: None,
+            });
+        }
+    }
+
+    fn fill_update(&self, order: &RestingOrder, price: f64, qty: f64, time: DateTime<Utc>) -> OrderUpdate {
+        let request = &order.request;
+        let pair = request.pair.to_string();
+        let (base_asset, quote_asset) = pair.split_once('_').unwrap_or((&pair, &pair));
+        let fees_rate = self.fee_tier_of(request.xch).rate(Some(request.order_type));
+        let (commission, commission_asset) = match request.side {
+            TradeType::Sell => (price * qty * fees_rate, quote_asset),
+            TradeType::Buy => (qty * fees_rate, base_asset),
+        };
+        let new_status = if order.remaining() > 0.0 {
+            OrderStatus::PartiallyFilled
+        } else {
+            OrderStatus::Filled
+        };
+        OrderUpdate {
+            enforcement: request.enforcement.unwrap_or(OrderEnforcement::GTC),
+            side: request.side,
+            orig_order_id: Some(request.order_id.clone()),
+            order_id: 0,
+            symbol: pair.clone(),
+            timestamp: time.timestamp_millis() as u64,
+            new_status,
+            orig_status: OrderStatus::New,
+            is_on_the_book: new_status == OrderStatus::PartiallyFilled,
+            qty: order.qty(),
+            quote_qty: order.qty() * price,
+            price: request.price.unwrap_or(price),
+            stop_price: request.stop_price.unwrap_or(0.0),
+            iceberg_qty: request.iceberg_qty.unwrap_or(0.0),
+            commission,
+            commission_asset: Some(commission_asset.to_string()),
+            last_executed_qty: qty,
+            cummulative_filled_qty: order.filled_qty,
+            last_executed_price: price,
+            cummulative_quote_asset_transacted_qty: order.filled_quote_qty,
+            last_quote_asset_transacted_qty: price * qty,
+            quote_order_qty: request.quote_order_qty.unwrap_or(0.0),
+            rejection_reason: None,
+        }
+    }
+}
+
+#[async_trait]
+impl Brokerage for SimulatedBrokerage {
+    async fn ticker(&self, pair: Pair) -> BrokerResult<Ticker> { self.inner.ticker(pair).await }
+
+    async fn tickers(&self, pairs: Vec<Pair>) -> BrokerResult<HashMap<Pair, f64>> { self.inner.tickers(pairs).await }
+
+    async fn orderbook(&self, pair: Pair) -> BrokerResult<Orderbook> { self.inner.orderbook(pair).await }
+
+    async fn add_order(&self, order: AddOrderRequest) -> BrokerResult<OrderSubmission> {
+        let mut submission = order.simulate_submission(self.fee_tier_of(order.xch).rate(Some(order.order_type)));
+        let mut state = self.state.lock().unwrap();
+        submission.id = state.next_id();
+        let is_simulated = matches!(order.order_type, OrderType::Market) || is_limit(order.order_type);
+        if self.fills_on_submission() || !is_simulated {
+            return Ok(submission);
+        }
+        let active_at = state.last_event_time.unwrap_or_else(util::time::now) + self.latency;
+        let seq = state.next_seq;
+        state.next_seq += 1;
+        state.orders.insert(seq, RestingOrder {
+            request: order,
+            active_at,
+            filled_qty: 0.0,
+            filled_quote_qty: 0.0,
+        });
+        Ok(OrderSubmission {
+            status: OrderStatus::New,
+            executed_qty: 0.0,
+            cummulative_quote_qty: 0.0,
+            trades: vec![],
+            ..submission
+        })
+    }
+
+    async fn amend_order(&self, order: AmendOrderRequest) -> BrokerResult<OrderSubmission> {
+        let mut state = self.state.lock().unwrap();
+        let id = state.next_id();
+        let amended = state
+            .orders
+            .iter_mut()
+            .find(|(_, resting)| resting.request.order_id == order.order_id);
+        if let Some((seq, resting)) = amended {
+            if let Some(price) = order.price {
+                resting.request.price = Some(price);
+            }
+            if let Some(qty) = order.quantity {
+                resting.request.quantity = Some(qty);
+            }
+            // Orders amended down to their filled quantity are done
+            if resting.remaining() <= 0.0 {
+                let seq = *seq;
+                state.orders.remove(&seq);
+            }
+        }
+        Ok(order.acknowledge(id))
+    }
+
+    async fn cancel_order(&self, id: String, pair: Pair, asset_type: AssetType) -> BrokerResult<()> {
+        self.state
+            .lock()
+            .unwrap()
+            .orders
+            .retain(|_, order| order.request.order_id != id);
+        self.inner.cancel_order(id, pair, asset_type).await
+    }
+
+    async fn account_balances(&self) -> BrokerResult<AccountPosition> { self.inner.account_balances().await }
+
+    async fn cancel_all_after(&self, timeout: std::time::Duration) -> BrokerResult<()> {
+        self.inner.cancel_all_after(timeout).await
+    }
+
+    async fn fee_tier(&self) -> BrokerResult<FeeTier> { Ok(self.fee_tier_of(self.inner.exchange())) }
+
+    async fn borrow(&self, loan: MarginLoanRequest) -> BrokerResult<String> { self.inner.borrow(loan).await }
+
+    async fn repay(&self, loan: MarginLoanRequest) -> BrokerResult<String> { self.inner.repay(loan).await }
+
+    async fn get_order(&self, id: String, pair: Pair, asset_type: AssetType) -> BrokerResult<Order> {
+        self.inner.get_order(id, pair, asset_type).await
+    }
+
+    async fn pairs(&self) -> BrokerResult<Vec<PairConf>> { self.inner.pairs().await }
+
+    fn exchange(&self) -> Exchange { self.inner.exchange() }
+
+    fn uses_account(&self) -> bool { self.inner.uses_account() }
+
+    fn capabilities(&self) -> ExchangeCapabilities { self.inner.capabilities() }
+
+    async fn margin_interest_rate(&self, symbol: MarketSymbol) -> BrokerResult<InterestRate> {
+        self.inner.margin_interest_rate(symbol).await
+    }
+
+    async fn candles(
+        &self,
+        pair: Pair,
+        resolution: Resolution,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> BrokerResult<Vec<Candle>> {
+        self.inner.candles(pair, resolution, start, end).await
+    }
+
+    async fn funding_payments(&self, since: DateTime<Utc>) -> BrokerResult<Vec<FundingPayment>> {
+        self.inner.funding_payments(since).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use chrono::{TimeZone, Utc};
+
+    use brokers::api::Brokerage;
+    use brokers::exchange::Exchange;
+    use brokers::fees::FeeTier;
+    use brokers::types::{AddOrderRequest, AmendOrderRequest, OrderType, TradeType};
+
+    use super::{FillModel, FillSettings, Liquidity, RestingOrder, SimulatedBrokerage};
+
+    fn resting_order(side: TradeType, order_type: OrderType, price: f64, qty: f64) -> RestingOrder {
+        RestingOrder {
+            request: AddOrderRequest {
+                side,
+                order_type,
+                price: Some(price),
+                quantity: Some(qty),
+                ..AddOrderRequest::default()
+            },
+            active_at: Utc.timestamp_millis_opt(0).unwrap(),
+            filled_qty: 0.0,
+            filled_quote_qty: 0.0,
+        }
+    }
+
+    fn book(bid: f64, ask: f64, qty: f64) -> Liquidity {
+        Liquidity {
+            bid,
+            ask,
+            bid_qty: Some(qty),
+            ask_qty: Some(qty),
+        }
+    }
+
+    #[test]
+    fn fill_models() {
+        let buy = resting_order(TradeType::Buy, OrderType::Limit, 100.0, 2.0);
+        assert_eq!(
+            buy.fill(FillModel::Immediate, &book(110.0, 111.0, 1.0)),
+            Some((100.0, 2.0))
+        );
+        // Limit orders wait for the book to cross their price
+        assert_eq!(buy.fill(FillModel::TopOfBookCross, &book(100.5, 101.0, 1.0)), None);
+        assert_eq!(
+            buy.fill(FillModel::TopOfBookCross, &book(99.0, 99.5, 1.0)),
+            Some((100.0, 2.0))
+        );
+        // Market orders take the best opposite price
+        let sell = resting_order(TradeType::Sell, OrderType::Market, 0.0, 2.0);
+        assert_eq!(
+            sell.fill(FillModel::TopOfBookCross, &book(99.0, 99.5, 1.0)),
+            Some((99.0, 2.0))
+        );
+        let participation = FillModel::VolumeParticipation { participation: 0.5 };
+        assert_eq!(sell.fill(participation, &book(99.0, 99.5, 1.0)), Some((99.0, 0.5)));
+        let mut partially_filled = sell.clone();
+        partially_filled.filled_qty = 1.8;
+        let (_, qty) = partially_filled.fill(participation, &book(99.0, 99.5, 1.0)).unwrap();
+        assert!((qty - 0.2).abs() < 1e-9);
+        // Events without volume do not fill participating orders
+        let no_volume = Liquidity {
+            bid_qty: None,
+            ..book(99.0, 99.5, 1.0)
+        };
+        assert_eq!(sell.fill(participation, &no_volume), None);
+    }
+
+    #[tokio::test]
+    async fn amended_orders_rest_at_their_new_price() {
+        let settings = FillSettings {
+            model: FillModel::TopOfBookCross,
+            latency: None,
+        };
+        let brokerage = SimulatedBrokerage::new(&settings, FeeTier { maker: 0.0, taker: 0.0 }, 0);
+        brokerage
+            .add_order(AddOrderRequest {
+                order_id: "quote".to_string(),
+                side: TradeType::Buy,
+                order_type: OrderType::Limit,
+                price: Some(100.0),
+                quantity: Some(2.0),
+                ..AddOrderRequest::default()
+            })
+            .await
+            .unwrap();
+        brokerage
+            .amend_order(AmendOrderRequest {
+                order_id: "quote".to_string(),
+                price: Some(101.0),
+                quantity: Some(1.0),
+                ..AmendOrderRequest::default()
+            })
+            .await
+            .unwrap();
+        {
+            let state = brokerage.state.lock().unwrap();
+            let resting = state.orders.values().next().unwrap();
+            assert_eq!(resting.request.price, Some(101.0));
+            assert_eq!(resting.qty(), 1.0);
+            assert_eq!(
+                resting.fill(FillModel::TopOfBookCross, &book(100.0, 100.5, 5.0)),
+                Some((101.0, 1.0))
+            );
+        }
+        // Amending an order down to nothing removes it
+        brokerage
+            .amend_order(AmendOrderRequest {
+                order_id: "quote".to_string(),
+                quantity: Some(0.0),
+                ..AmendOrderRequest::default()
+            })
+            .await
+            .unwrap();
+        assert!(brokerage.state.lock().unwrap().orders.is_empty());
+    }
+
+    #[test]
+    fn fees_follow_the_exchange_of_orders() {
+        let flat = FeeTier {
+            maker: 0.001,
+            taker: 0.001,
+        };
+        let kraken = FeeTier {
+            maker: 0.0016,
+            taker: 0.0026,
+        };
+        let brokerage = SimulatedBrokerage::new(&FillSettings::default(), flat, 0)
+            .with_fee_schedules(HashMap::from([(Exchange::Kraken, kraken)]));
+        assert_eq!(brokerage.fee_tier_of(Exchange::Kraken), kraken);
+        assert_eq!(brokerage.fee_tier_of(Exchange::Binance), flat);
+    }
+}
@@ -0,0 +1,97 @@
+//! Incremental backtests : the latest run of a configuration is continued on the days it did not process, the days
+//! it processed are found with the partitions recorded in its manifest.
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use chrono::{DateTime, Duration, Utc};
+
+use crate::error::*;
+use crate::manifest::{PartitionChecksum, RunManifest};
+
+/// A previous run continued by an incremental backtest
+#[derive(Debug, Clone)]
+pub(crate) struct PreviousRun {
+    /// Report directory of the run, reports of the continued run are appended to its reports
+    pub(crate) report_dir: PathBuf,
+    pub(crate) manifest: RunManifest,
+}
+
+/// The first day to replay, days before it were entirely processed by the previous run from the same partitions
+///
+/// returns: none if every day was processed
+///
+/// # Errors
+///
+/// The partitions of a day entirely processed by the previous run changed, strategies cannot be rewound to replay it
+pub(crate) fn first_new_day(
+    previous: &RunManifest,
+    days: &[(DateTime<Utc>, Vec<PartitionChecksum>)],
+) -> Result<Option<DateTime<Utc>>> {
+    let processed: BTreeMap<&str, &Option<String>> = previous
+        .partitions
+        .iter()
+        .map(|p| (p.path.as_str(), &p.checksum))
+        .collect();
+    for (day, partitions) in days {
+        let fully_processed = *day + Duration::days(1) <= previous.to;
+        let unchanged = partitions
+            .iter()
+            .all(|p| processed.get(p.path.as_str()) == Some(&&p.checksum));
+        match (fully_processed, unchanged) {
+            (true, true) => continue,
+            (true, false) => {
+                return Err(anyhow!(
+                    "datasets of {} changed since the previous run, run a full backtest",
+                    day.format("%Y-%m-%d")
+                )
+                .into())
+            }
+            (false, _) => return Ok(Some(*day)),
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{Duration, TimeZone, Utc};
+
+    use crate::manifest::{PartitionChecksum, RunManifest};
+
+    use super::first_new_day;
+
+    #[test]
+    fn replay_new_days_only() {
+        let day0 = Utc.with_ymd_and_hms(2022, 1, 1, 0, 0, 0).unwrap();
+        let day = |i: i64| day0 + Duration::days(i);
+        let partition = |i: i64, checksum: &str| PartitionChecksum {
+            path: format!("dt={}", day(i).format("%Y%m%d")),
+            checksum: Some(checksum.to_string()),
+        };
+        let previous = RunManifest {
+            config_hash: None,
+            strategies_hash: String::new(),
+            code_version: String::new(),
+            seed: 0,
+            from: day(0),
+            // The last day was processed until noon
+            to: day(2) + Duration::hours(12),
+            partitions: vec![partition(0, "a"), partition(1, "b"), partition(2, "c")],
+        };
+        let days = |third: &str| {
+            vec![
+                (day(0), vec![partition(0, "a")]),
+                (day(1), vec![partition(1, "b")]),
+                (day(2), vec![partition(2, third)]),
+                (day(3), vec![partition(3, "d")]),
+            ]
+        };
+        assert_eq!(first_new_day(&previous, &days("c")).unwrap(), Some(day(2)));
+        assert_eq!(first_new_day(&previous, &days("c2")).unwrap(), Some(day(2)));
+        assert_eq!(first_new_day(&previous, &days("c")[..2]).unwrap(), None);
+        let mut changed = days("c");
+        changed[1].1 = vec![partition(1, "b2")];
+        assert!(first_new_day(&previous, &changed).is_err());
+    }
+}
@@ -51,16 +51,35 @@ extern crate strum_macros;
 // // TODO: https://github.com/rust-lang/rust/issues/47384
 
 mod backtest;
+mod checkpoint;
 mod config;
 mod datafusion_util;
 mod dataset;
 mod datasources;
+mod download;
 mod error;
+mod fill;
+mod incremental;
+mod manifest;
+mod parity;
+mod replay;
 pub mod report;
 mod runner;
+mod s3;
+mod sweep;
+mod walk_forward;
 
 pub use crate::{backtest::*,
+                checkpoint::{Checkpoint, CheckpointSettings},
                 config::*,
-                dataset::{DataFormat, DatasetCatalog, DatasetReader, MarketEventDatasetType},
-                error::*};
+                dataset::{migrate_recorded_layout, DataFormat, DatasetCatalog, DatasetReader, MarketEventDatasetType},
+                download::CandlesDownloader,
+                error::*,
+                fill::{FillModel, FillSettings, SimulatedBrokerage},
+                manifest::{PartitionChecksum, RunManifest},
+                parity::{parity, Divergence, ParityOrder, ParityReport, StrategyParity},
+                replay::ReplayClock,
+                s3::S3Settings,
+                sweep::{sweep, Objective, ParamSensitivity, SweepReport, SweepResult, SweepSettings},
+                walk_forward::{walk_forward, WalkForwardReport, WalkForwardSettings, WalkForwardWindow}};
 pub use datafusion::arrow::record_batch::RecordBatch;
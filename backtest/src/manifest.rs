@@ -0,0 +1,181 @@
+//! Reproducibility manifests : every backtest records what its results depend on (configuration, datasets, code
+//! version and seed), so that a run can be reproduced later and compared with other runs.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use sha2::{Digest, Sha256};
+
+use crate::error::*;
+use crate::s3::S3_SCHEME;
+
+pub(crate) const MANIFEST_FILE: &str = "manifest.json";
+
+/// A dataset partition read by a backtest
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct PartitionChecksum {
+    pub path: String,
+    /// Sha256 of the files of the partition, none if the partition does not exist or is remote
+    pub checksum: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RunManifest {
+    /// Sha256 of the configuration, if loaded from a file
+    pub config_hash: Option<String>,
+    /// Sha256 of the settings of the backtested strategies
+    pub strategies_hash: String,
+    /// Version of the backtest crate, with the git revision it was built from if known
+    pub code_version: String,
+    /// Seed of the random generators of the backtest
+    pub seed: u64,
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    pub partitions: Vec<PartitionChecksum>,
+}
+
+impl RunManifest {
+    /// Load a manifest written by a previous run
+    ///
+    /// # Errors
+    ///
+    /// The file does not exist or is not a manifest
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Ok(serde_json::from_reader(BufReader::new(File::open(path)?))?)
+    }
+
+    pub(crate) fn write(&self, output_dir: &Path) -> Result<()> {
+        std::fs::create_dir_all(output_dir)?;
+        serde_json::to_writer_pretty(File::create(output_dir.join(MANIFEST_FILE))?, self)?;
+        Ok(())
+    }
+
+    /// Continue a previous run, its partitions were also processed
+    pub(crate) fn continue_from(&mut self, previous: &RunManifest) {
+        self.from = previous.from;
+        let mut partitions: BTreeMap<String, PartitionChecksum> = previous
+            .partitions
+            .iter()
+            .map(|p| (p.path.clone(), p.clone()))
+            .collect();
+        partitions.extend(self.partitions.drain(..).map(|p| (p.path.clone(), p)));
+        self.partitions = partitions.into_values().collect();
+    }
+
+    /// Check that the partitions of this run are those of a previous run, with the same content
+    ///
+    /// # Errors
+    ///
+    /// A partition is missing from the previous manifest, or its content changed
+    pub fn verify_datasets(&self, previous: &RunManifest) -> Result<()> {
+        let previous: BTreeMap<&str, &Option<String>> = previous
+            .partitions
+            .iter()
+            .map(|p| (p.path.as_str(), &p.checksum))
+            .collect();
+        let changed: Vec<&str> = self
+            .partitions
+            .iter()
+            .filter(|p| previous.get(p.path.as_str()) != Some(&&p.checksum))
+            .map(|p| p.path.as_str())
+            .collect();
+        if changed.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow!("datasets changed since the manifest : {}", changed.join(", ")).into())
+        }
+    }
+}
+
+pub(crate) fn code_version() -> String {
+    match option_env!("BUILD_GIT_SHA") {
+        Some(sha) => format!("{}+{}", env!("CARGO_PKG_VERSION"), sha),
+        None => env!("CARGO_PKG_VERSION").to_string(),
+    }
+}
+
+pub(crate) fn sha256_hex(content: &[u8]) -> String { format!("{:x}", Sha256::digest(content)) }
+
+/// Checksums of the files of partition directories, sorted by path
+///
+/// # Errors
+///
+/// A file of a partition cannot be read
+pub(crate) fn partition_checksums(dirs: &[PathBuf]) -> Result<Vec<PartitionChecksum>> {
+    let mut checksums = dirs
+        .iter()
+        .map(|dir| {
+            let path = dir.to_string_lossy().to_string();
+            let checksum = if path.starts_with(S3_SCHEME) || !dir.exists() {
+                None
+            } else {
+                Some(dir_checksum(dir)?)
+            };
+            Ok(PartitionChecksum { path, checksum })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    checksums.sort_by(|a, b| a.path.cmp(&b.path));
+    checksums.dedup();
+    Ok(checksums)
+}
+
+/// Sha256 of the relative paths and contents of the files under a directory, in path order
+fn dir_checksum(dir: &Path) -> Result<String> {
+    let mut files = vec![];
+    list_files(dir, &mut files)?;
+    files.sort();
+    let mut hasher = Sha256::new();
+    for file in files {
+        hasher.update(file.strip_prefix(dir).unwrap_or(&file).to_string_lossy().as_bytes());
+        std::io::copy(&mut File::open(&file)?, &mut hasher)?;
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+fn list_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    if dir.is_file() {
+        files.push(dir.to_path_buf());
+        return Ok(());
+    }
+    for entry in std::fs::read_dir(dir)? {
+        list_files(&entry?.path(), files)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::Utc;
+
+    use super::{partition_checksums, RunManifest};
+
+    #[test]
+    fn detect_changed_datasets() {
+        let dir = util::test::test_dir();
+        let partition = dir.path().join("xch=Binance").join("dt=20220101");
+        std::fs::create_dir_all(&partition).unwrap();
+        std::fs::write(partition.join("part-0.avro"), b"trades").unwrap();
+        let manifest = |partitions| RunManifest {
+            config_hash: None,
+            strategies_hash: String::new(),
+            code_version: String::new(),
+            seed: 0,
+            from: Utc::now(),
+            to: Utc::now(),
+            partitions,
+        };
+        let dirs = vec![partition.clone(), dir.path().join("missing")];
+        let previous = manifest(partition_checksums(&dirs).unwrap());
+        assert!(previous.partitions.iter().any(|p| p.checksum.is_none()));
+        assert!(manifest(partition_checksums(&dirs).unwrap())
+            .verify_datasets(&previous)
+            .is_ok());
+        std::fs::write(partition.join("part-0.avro"), b"more trades").unwrap();
+        assert!(manifest(partition_checksums(&dirs).unwrap())
+            .verify_datasets(&previous)
+            .is_err());
+    }
+}
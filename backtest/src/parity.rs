@@ -0,0 +1,226 @@
+//! Live/backtest parity : the same strategies are run over the same recorded events through the live driver path,
+//! where orders are simulated by the order manager in dry mode, and through the backtest path with its simulated
+//! brokerage, then the orders of both runs are compared to catch divergences specific to an environment.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+
+use strategy::settings::{StrategyDriverOptions, StrategyDriverSettings};
+use strategy::types::StratEvent;
+use trading::position::{OperationKind, PositionKind};
+use trading::types::TradeKind;
+use util::time::TimedData;
+
+use crate::backtest::Backtest;
+use crate::config::BacktestConfig;
+use crate::error::*;
+use crate::report::GlobalReport;
+
+const PARITY_REPORT_FILE: &str = "parity.json";
+
+/// Relative difference of quantities and prices under which orders of both runs are the same
+const PARITY_TOLERANCE: f64 = 1e-6;
+
+/// An order passed by a strategy, as reported by a position summary
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ParityOrder {
+    pub at: DateTime<Utc>,
+    pub op: OperationKind,
+    pub pos: PositionKind,
+    pub side: TradeKind,
+    pub pair: String,
+    pub qty: f64,
+    pub price: f64,
+}
+
+impl ParityOrder {
+    fn matches(&self, other: &ParityOrder) -> bool {
+        self.at == other.at
+            && self.op == other.op
+            && self.pos == other.pos
+            && self.side == other.side
+            && self.pair == other.pair
+            && close(self.qty, other.qty)
+            && close(self.price, other.price)
+    }
+}
+
+fn close(a: f64, b: f64) -> bool { (a - b).abs() <= PARITY_TOLERANCE * a.abs().max(b.abs()).max(1.0) }
+
+/// Orders at the same index that differ between both runs, an order missing from one run is none
+#[derive(Debug, Clone, Serialize)]
+pub struct Divergence {
+    pub index: usize,
+    pub live: Option<ParityOrder>,
+    pub backtest: Option<ParityOrder>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct StrategyParity {
+    pub live_orders: usize,
+    pub backtest_orders: usize,
+    pub divergences: Vec<Divergence>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ParityReport {
+    /// Parity of each strategy by key
+    pub strategies: BTreeMap<String, StrategyParity>,
+}
+
+impl ParityReport {
+    pub fn divergences(&self) -> usize { self.strategies.values().map(|s| s.divergences.len()).sum() }
+
+    fn write(&self, output_dir: &Path) -> Result<()> {
+        std::fs::create_dir_all(output_dir)?;
+        serde_json::to_writer_pretty(File::create(output_dir.join(PARITY_REPORT_FILE))?, self)?;
+        Ok(())
+    }
+}
+
+/// Run the strategies of a configuration through the live driver path and the backtest path simultaneously, and
+/// compare their orders
+///
+/// # Errors
+///
+/// One of the runs fails, or its reports cannot be read
+pub async fn parity(conf: &BacktestConfig) -> Result<ParityReport> {
+    let strategies = conf.all_strategy_settings().await;
+    let period = conf.period.as_range();
+    let output_dir = conf.output_dir().join("parity");
+    let db_path = conf.db_path();
+    let mut backtest = Backtest::try_new_with(
+        conf,
+        strategies.clone(),
+        period,
+        output_dir.join("backtest"),
+        conf.db_conf_at(db_path.join("backtest")),
+    )
+    .await?;
+    let mut live = Backtest::try_new_live(
+        conf,
+        strategies.into_iter().map(dry_mode).collect(),
+        period,
+        output_dir.join("live"),
+        conf.db_conf_at(db_path.join("live")),
+    )
+    .await?;
+    let (live_report, backtest_report) = futures::try_join!(live.run(), backtest.run())?;
+    let report = ParityReport {
+        strategies: diff_reports(&orders_by_key(&live_report)?, &orders_by_key(&backtest_report)?),
+    };
+    report.write(&output_dir)?;
+    Ok(report)
+}
+
+/// Simulate all orders of a strategy in the order manager
+fn dry_mode(mut settings: StrategyDriverSettings) -> StrategyDriverSettings {
+    match &mut settings.driver {
+        StrategyDriverOptions::Generic(options) => options.dry_mode = Some(true),
+    }
+    settings
+}
+
+fn orders_by_key(report: &GlobalReport) -> Result<BTreeMap<String, Vec<ParityOrder>>> {
+    report
+        .reports
+        .iter()
+        .map(|r| Ok((r.key.clone(), orders(&r.strat_events()?))))
+        .collect()
+}
+
+fn orders(events: &[TimedData<StratEvent>]) -> Vec<ParityOrder> {
+    events
+        .iter()
+        .filter_map(|e| match &e.value {
+            StratEvent::PositionSummary(summary) => Some(ParityOrder {
+                at: summary.op.at,
+                op: summary.op.op,
+                pos: summary.op.pos,
+                side: summary.trade.side.clone(),
+                pair: summary.trade.pair.clone(),
+                qty: summary.trade.qty,
+                price: summary.trade.price,
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
+fn diff_reports(
+    live: &BTreeMap<String, Vec<ParityOrder>>,
+    backtest: &BTreeMap<String, Vec<ParityOrder>>,
+) -> BTreeMap<String, StrategyParity> {
+    let empty = vec![];
+    live.keys()
+        .chain(backtest.keys())
+        .map(|key| {
+            let live = live.get(key).unwrap_or(&empty);
+            let backtest = backtest.get(key).unwrap_or(&empty);
+            (key.clone(), diff_orders(live, backtest))
+        })
+        .collect()
+}
+
+fn diff_orders(live: &[ParityOrder], backtest: &[ParityOrder]) -> StrategyParity {
+    let divergences = (0..live.len().max(backtest.len()))
+        .filter_map(|index| {
+            let (l, b) = (live.get(index), backtest.get(index));
+            match (l, b) {
+                (Some(l), Some(b)) if l.matches(b) => None,
+                _ => Some(Divergence {
+                    index,
+                    live: l.cloned(),
+                    backtest: b.cloned(),
+                }),
+            }
+        })
+        .collect();
+    StrategyParity {
+        live_orders: live.len(),
+        backtest_orders: backtest.len(),
+        divergences,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{Duration, TimeZone, Utc};
+
+    use trading::position::{OperationKind, PositionKind};
+    use trading::types::TradeKind;
+
+    use super::{diff_orders, ParityOrder};
+
+    #[test]
+    fn diff_live_and_backtest_orders() {
+        let t0 = Utc.with_ymd_and_hms(2022, 1, 1, 0, 0, 0).unwrap();
+        let order = |minutes: i64, op: OperationKind, side: TradeKind, price: f64| ParityOrder {
+            at: t0 + Duration::minutes(minutes),
+            op,
+            pos: PositionKind::Long,
+            side,
+            pair: "BTC_USDT".to_string(),
+            qty: 0.1,
+            price,
+        };
+        let live = vec![
+            order(0, OperationKind::Open, TradeKind::Buy, 40000.0),
+            order(5, OperationKind::Close, TradeKind::Sell, 41000.0),
+        ];
+        let backtest = vec![
+            order(0, OperationKind::Open, TradeKind::Buy, 40000.000001),
+            order(5, OperationKind::Close, TradeKind::Sell, 40900.0),
+            order(9, OperationKind::Open, TradeKind::Buy, 40000.0),
+        ];
+        let parity = diff_orders(&live, &backtest);
+        assert_eq!(parity.live_orders, 2);
+        assert_eq!(parity.backtest_orders, 3);
+        let indices: Vec<usize> = parity.divergences.iter().map(|d| d.index).collect();
+        assert_eq!(indices, vec![1, 2]);
+        assert!(parity.divergences[1].live.is_none());
+    }
+}
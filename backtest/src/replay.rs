@@ -0,0 +1,69 @@
+//! Paced replay of market events, events are delivered with the (scaled) delays observed between them instead of
+//! as fast as possible.
+
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+
+/// Paces replayed events by scaling the delays between their times, starting at the first replayed event and
+/// running `speed` times faster than the wall clock
+#[derive(Debug, Clone)]
+pub struct ReplayClock {
+    speed: f64,
+    /// The first replayed event time, and when it was replayed
+    origin: Option<(DateTime<Utc>, Instant)>,
+}
+
+impl ReplayClock {
+    /// # Panics
+    ///
+    /// if the speed is not strictly positive
+    pub fn new(speed: f64) -> Self {
+        assert!(speed > 0.0, "replay speed must be positive, got {}", speed);
+        Self { speed, origin: None }
+    }
+
+    /// How long to wait at `now` before replaying an event of this time, the first event starts the clock
+    fn delay(&mut self, event_time: DateTime<Utc>, now: Instant) -> Duration {
+        let (origin_time, origin_at) = *self.origin.get_or_insert((event_time, now));
+        let since_origin = (event_time - origin_time).num_milliseconds().max(0) as f64 / self.speed;
+        let due = origin_at + Duration::from_secs_f64(since_origin / 1000.0);
+        due.saturating_duration_since(now)
+    }
+
+    /// Wait until an event of this time is due
+    pub async fn wait_for(&mut self, event_time: DateTime<Utc>) {
+        let delay = self.delay(event_time, Instant::now());
+        if !delay.is_zero() {
+            tokio::time::sleep(delay).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use chrono::{TimeZone, Utc};
+
+    use super::ReplayClock;
+
+    #[test]
+    fn scale_inter_event_delays() {
+        let mut clock = ReplayClock::new(10.0);
+        let start = Instant::now();
+        let t0 = Utc.timestamp_millis_opt(1_000_000).unwrap();
+        assert_eq!(clock.delay(t0, start), Duration::ZERO);
+        assert_eq!(
+            clock.delay(t0 + chrono::Duration::seconds(10), start),
+            Duration::from_secs(1)
+        );
+        // Late events are replayed immediately
+        assert_eq!(
+            clock.delay(t0 + chrono::Duration::seconds(10), start + Duration::from_secs(2)),
+            Duration::ZERO
+        );
+        // Out of order events do not wait
+        assert_eq!(clock.delay(t0 - chrono::Duration::seconds(1), start), Duration::ZERO);
+    }
+}
@@ -0,0 +1,191 @@
+//! Buy and hold benchmarks : the prices of benchmark markets are recorded while events are replayed, then the pnl
+//! of each strategy is compared with holding the benchmark over the same period.
+
+use chrono::{DateTime, Duration, DurationRound, Utc};
+use tokio::sync::mpsc::Receiver;
+use tokio_util::sync::CancellationToken;
+
+use brokers::exchange::Exchange;
+use brokers::types::{MarketChannel, MarketChannelType, MarketEventEnvelope, Pair, SecurityType, Symbol};
+
+/// Market events of a benchmark buffered until recorded
+pub(crate) const BENCHMARK_QUEUE_SIZE: usize = 1000;
+
+/// Benchmark prices are recorded at most once per this period, the last price of each period is kept
+const BENCHMARK_RESOLUTION_MINUTES: i64 = 1;
+
+/// A market held from the start to the end of the backtest
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct BenchmarkSettings {
+    pub xch: Exchange,
+    pub pair: Pair,
+}
+
+impl BenchmarkSettings {
+    pub fn name(&self) -> String { format!("{}_{}", self.xch, self.pair) }
+
+    /// The trades of the benchmark market
+    pub(crate) fn channel(&self) -> MarketChannel {
+        MarketChannel::builder()
+            .symbol(Symbol::new(self.pair.clone(), SecurityType::Crypto, self.xch))
+            .r#type(MarketChannelType::Trades)
+            .build()
+    }
+}
+
+/// Prices of a benchmark, in time order
+#[derive(Debug, Clone, Default)]
+pub struct BenchmarkSeries {
+    pub name: String,
+    prices: Vec<(DateTime<Utc>, f64)>,
+}
+
+impl BenchmarkSeries {
+    pub fn new(name: String) -> Self { Self { name, prices: vec![] } }
+
+    pub(crate) fn push(&mut self, ts: DateTime<Utc>, price: f64) {
+        if price <= 0.0 {
+            return;
+        }
+        let bucket = |ts: DateTime<Utc>| {
+            ts.duration_trunc(Duration::minutes(BENCHMARK_RESOLUTION_MINUTES))
+                .unwrap_or(ts)
+        };
+        match self.prices.last_mut() {
+            Some(last) if bucket(last.0) == bucket(ts) => *last = (ts, price),
+            Some(last) if last.0 > ts => {}
+            _ => self.prices.push((ts, price)),
+        }
+    }
+
+    /// The last price at or before `ts`
+    fn price_at(&self, ts: DateTime<Utc>) -> Option<f64> {
+        let idx = self.prices.partition_point(|(price_ts, _)| *price_ts <= ts);
+        (idx > 0).then(|| self.prices[idx - 1].1)
+    }
+
+    /// Compare a pnl series with holding the benchmark over the same times
+    pub fn compare(&self, pnl: &[(DateTime<Utc>, f64)]) -> Option<BenchmarkStats> {
+        let aligned: Vec<(f64, f64)> = pnl
+            .iter()
+            .filter_map(|(ts, value)| self.price_at(*ts).map(|price| (*value, price)))
+            .collect();
+        let (first, last) = (aligned.first()?, aligned.last()?);
+        if first.0 <= 0.0 {
+            return None;
+        }
+        let strategy_return = last.0 / first.0 - 1.0;
+        let benchmark_return = last.1 / first.1 - 1.0;
+        let returns: Vec<(f64, f64)> = aligned
+            .windows(2)
+            .filter(|w| w[0].0 > 0.0)
+            .map(|w| (w[1].0 / w[0].0 - 1.0, w[1].1 / w[0].1 - 1.0))
+            .collect();
+        let (alpha, beta, correlation) = regression(&returns);
+        Some(BenchmarkStats {
+            benchmark: self.name.clone(),
+            strategy_return,
+            benchmark_return,
+            relative_performance: strategy_return - benchmark_return,
+            alpha,
+            beta,
+            correlation,
+        })
+    }
+}
+
+/// Performance of a strategy relative to a benchmark
+#[derive(Debug, Clone, Serialize)]
+pub struct BenchmarkStats {
+    pub benchmark: String,
+    pub strategy_return: f64,
+    pub benchmark_return: f64,
+    /// Return of the strategy in excess of the benchmark return
+    pub relative_performance: f64,
+    /// Mean return of the strategy not explained by the benchmark, per period between snapshots
+    pub alpha: f64,
+    /// Sensitivity of the strategy returns to the benchmark returns
+    pub beta: f64,
+    /// Correlation of the strategy returns with the benchmark returns
+    pub correlation: f64,
+}
+
+/// Alpha, beta and correlation of (strategy, benchmark) returns
+fn regression(returns: &[(f64, f64)]) -> (f64, f64, f64) {
+    if returns.len() < 2 {
+        return (0.0, 0.0, 0.0);
+    }
+    let n = returns.len() as f64;
+    let mean_s = returns.iter().map(|r| r.0).sum::<f64>() / n;
+    let mean_b = returns.iter().map(|r| r.1).sum::<f64>() / n;
+    let (mut cov, mut var_s, mut var_b) = (0.0, 0.0, 0.0);
+    for (s, b) in returns {
+        cov += (s - mean_s) * (b - mean_b);
+        var_s += (s - mean_s).powi(2);
+        var_b += (b - mean_b).powi(2);
+    }
+    let beta = if var_b > f64::EPSILON { cov / var_b } else { 0.0 };
+    let correlation = if var_b > f64::EPSILON && var_s > f64::EPSILON {
+        cov / (var_s * var_b).sqrt()
+    } else {
+        0.0
+    };
+    (mean_s - beta * mean_b, beta, correlation)
+}
+
+/// Record the prices of a benchmark until the backtest stops, buffered events are recorded before stopping
+pub(crate) async fn record_benchmark(
+    mut series: BenchmarkSeries,
+    mut events: Receiver<MarketEventEnvelope>,
+    stop_token: CancellationToken,
+) -> BenchmarkSeries {
+    loop {
+        tokio::select! {
+            biased;
+            event = events.recv() => match event {
+                Some(event) => series.push(event.e.time(), event.e.vwap()),
+                None => break,
+            },
+            _ = stop_token.cancelled() => {
+                while let Ok(event) = events.try_recv() {
+                    series.push(event.e.time(), event.e.vwap());
+                }
+                break;
+            }
+        }
+    }
+    series
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{Duration, TimeZone, Utc};
+
+    use super::BenchmarkSeries;
+
+    #[test]
+    fn compare_with_benchmark() {
+        let t0 = Utc.with_ymd_and_hms(2022, 1, 1, 0, 0, 0).unwrap();
+        let mut series = BenchmarkSeries::new("Binance_BTC_USDT".to_string());
+        let prices = [100.0, 110.0, 99.0, 118.8];
+        for (i, price) in prices.iter().enumerate() {
+            series.push(t0 + Duration::hours(i as i64), *price);
+            // Later prices of the same minute replace earlier ones
+            series.push(t0 + Duration::hours(i as i64) + Duration::seconds(30), *price);
+        }
+        assert_eq!(series.prices.len(), 4);
+        // A strategy with twice the returns of the benchmark
+        let pnl: Vec<_> = [100.0, 120.0, 96.0, 134.4]
+            .iter()
+            .enumerate()
+            .map(|(i, pnl)| (t0 + Duration::hours(i as i64) + Duration::minutes(5), *pnl))
+            .collect();
+        let stats = series.compare(&pnl).unwrap();
+        assert!((stats.benchmark_return - 0.188).abs() < 1e-9);
+        assert!((stats.strategy_return - 0.344).abs() < 1e-9);
+        assert!((stats.relative_performance - 0.156).abs() < 1e-9);
+        assert!((stats.beta - 2.0).abs() < 1e-9);
+        assert!(stats.alpha.abs() < 1e-9);
+        assert!((stats.correlation - 1.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,173 @@
+//! Static charts of the equity curve, drawdown and exposure of a strategy, written as SVG files next to the
+//! interactive reports so that they can be embedded in documents or viewed without javascript.
+
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+
+use strategy::query::PortfolioSnapshot;
+use util::time::TimedData;
+
+use crate::error::Result;
+
+const CHARTS_DIR: &str = "charts";
+const CHARTS_HTML_FILE: &str = "charts.html";
+const WIDTH: f64 = 800.0;
+const HEIGHT: f64 = 300.0;
+const MARGIN: f64 = 50.0;
+/// Charts are downsampled to at most this many points
+const MAX_POINTS: usize = 2000;
+
+type Line = Vec<(DateTime<Utc>, f64)>;
+
+/// The equity curve, drawdown from the peak equity and exposure of snapshots
+fn chart_lines(snapshots: &[TimedData<PortfolioSnapshot>]) -> Vec<(&'static str, Line)> {
+    let mut peak = f64::MIN;
+    let mut drawdown = Vec::with_capacity(snapshots.len());
+    for s in snapshots {
+        peak = peak.max(s.value.pnl);
+        drawdown.push((s.ts, if peak > 0.0 { (s.value.pnl - peak) / peak } else { 0.0 }));
+    }
+    vec![
+        ("equity", snapshots.iter().map(|s| (s.ts, s.value.pnl)).collect()),
+        ("drawdown", drawdown),
+        // The part of the equity held in positions, negative when short, cash is the value of the portfolio
+        (
+            "exposure",
+            snapshots
+                .iter()
+                .map(|s| {
+                    let exposure = if s.value.pnl > 0.0 {
+                        1.0 - s.value.value / s.value.pnl
+                    } else {
+                        0.0
+                    };
+                    (s.ts, exposure)
+                })
+                .collect(),
+        ),
+    ]
+}
+
+/// A line chart of a time series as an SVG document
+fn line_chart_svg(title: &str, line: &[(DateTime<Utc>, f64)]) -> String {
+    let step = (line.len() / MAX_POINTS).max(1);
+    let points: Vec<&(DateTime<Utc>, f64)> = line.iter().step_by(step).chain(line.last()).collect();
+    let mut svg = format!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}" font-family="sans-serif" font-size="11">"#,
+        w = WIDTH,
+        h = HEIGHT
+    );
+    let _ = write!(
+        svg,
+        r#"<rect width="100%" height="100%" fill="white"/><text x="{}" y="20" font-size="14">{}</text>"#,
+        MARGIN, title
+    );
+    if let (Some(first), Some(last)) = (points.first(), points.last()) {
+        let (min, max) = points
+            .iter()
+            .fold((f64::MAX, f64::MIN), |(min, max), (_, v)| (min.min(*v), max.max(*v)));
+        let span = if max - min > f64::EPSILON { max - min } else { 1.0 };
+        let (t0, t1) = (first.0.timestamp_millis(), last.0.timestamp_millis());
+        let duration = (t1 - t0).max(1) as f64;
+        let x = |ts: DateTime<Utc>| MARGIN + (ts.timestamp_millis() - t0) as f64 / duration * (WIDTH - 2.0 * MARGIN);
+        let y = |v: f64| HEIGHT - MARGIN - (v - min) / span * (HEIGHT - 2.0 * MARGIN);
+        let polyline = points
+            .iter()
+            .map(|(ts, v)| format!("{:.1},{:.1}", x(*ts), y(*v)))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let _ = write!(
+            svg,
+            r##"<polyline fill="none" stroke="#1f77b4" stroke-width="1.5" points="{}"/>"##,
+            polyline
+        );
+        let _ = write!(
+            svg,
+            r##"<line x1="{m}" y1="{b}" x2="{r}" y2="{b}" stroke="#888"/><line x1="{m}" y1="{m}" x2="{m}" y2="{b}" stroke="#888"/>"##,
+            m = MARGIN,
+            b = HEIGHT - MARGIN,
+            r = WIDTH - MARGIN
+        );
+        let _ = write!(
+            svg,
+            r#"<text x="2" y="{}">{:.4}</text><text x="2" y="{}">{:.4}</text>"#,
+            y(max) + 4.0,
+            max,
+            y(min) + 4.0,
+            min
+        );
+        let _ = write!(
+            svg,
+            r#"<text x="{}" y="{}">{}</text><text x="{}" y="{}" text-anchor="end">{}</text>"#,
+            MARGIN,
+            HEIGHT - MARGIN + 16.0,
+            first.0.format("%Y-%m-%d %H:%M"),
+            WIDTH - MARGIN,
+            HEIGHT - MARGIN + 16.0,
+            last.0.format("%Y-%m-%d %H:%M")
+        );
+    }
+    svg.push_str("</svg>");
+    svg
+}
+
+/// Write the equity, drawdown and exposure charts of snapshots under `report_dir`, with a page linking them
+///
+/// returns: the written chart files
+pub(crate) fn write_charts(report_dir: &Path, snapshots: &[TimedData<PortfolioSnapshot>]) -> Result<Vec<PathBuf>> {
+    let charts_dir = report_dir.join(CHARTS_DIR);
+    std::fs::create_dir_all(&charts_dir)?;
+    let mut files = vec![];
+    let mut html = String::from("<html><body>");
+    for (name, line) in chart_lines(snapshots) {
+        let file = charts_dir.join(format!("{}.svg", name));
+        std::fs::write(&file, line_chart_svg(name, &line))?;
+        let _ = write!(
+            html,
+            r#"<div><img src="{}/{}.svg" alt="{}"/></div>"#,
+            CHARTS_DIR, name, name
+        );
+        files.push(file);
+    }
+    html.push_str("</body></html>");
+    std::fs::write(report_dir.join(CHARTS_HTML_FILE), html)?;
+    Ok(files)
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{Duration, TimeZone, Utc};
+
+    use strategy::query::PortfolioSnapshot;
+    use util::time::TimedData;
+
+    use super::{chart_lines, write_charts};
+
+    #[test]
+    fn equity_drawdown_and_exposure() {
+        let t0 = Utc.with_ymd_and_hms(2022, 1, 1, 0, 0, 0).unwrap();
+        let snapshots: Vec<_> = [(100.0, 100.0), (120.0, 20.0), (90.0, 90.0)]
+            .iter()
+            .enumerate()
+            .map(|(i, (pnl, value))| TimedData {
+                ts: t0 + Duration::hours(i as i64),
+                value: PortfolioSnapshot {
+                    pnl: *pnl,
+                    value: *value,
+                    ..PortfolioSnapshot::default()
+                },
+            })
+            .collect();
+        let lines = chart_lines(&snapshots);
+        assert_eq!(lines[1].1[2].1, -0.25);
+        assert!((lines[2].1[1].1 - 5.0 / 6.0).abs() < 1e-9);
+        let dir = util::test::test_dir();
+        let files = write_charts(dir.path(), &snapshots).unwrap();
+        assert_eq!(files.len(), 3);
+        let svg = std::fs::read_to_string(&files[0]).unwrap();
+        assert!(svg.starts_with("<svg") && svg.contains("<polyline"));
+        assert!(dir.path().join("charts.html").exists());
+    }
+}
@@ -1,3 +1,4 @@
+use std::collections::BTreeMap;
 use std::path::{Path, PathBuf};
 
 use futures::StreamExt;
@@ -6,16 +7,23 @@ use plotly::common::Font;
 use plotly::layout::{GridPattern, LayoutGrid, Legend, RowOrder};
 use plotly::{Layout, Plot};
 
+use trading::capital::SharedCapitalSnapshot;
 use util::compress::Compression;
 use util::time::now_str;
 
 use super::single::BacktestReport;
+use super::{BenchmarkSeries, BenchmarkStats};
+
+const SHARED_CAPITAL_REPORT_FILE: &str = "shared_capital.json";
+const BENCHMARKS_REPORT_FILE: &str = "benchmarks.json";
 
 pub struct GlobalReport {
     pub reports: Vec<BacktestReport>,
     pub output_dir: PathBuf,
     pub base_dir: PathBuf,
     parallelism: usize,
+    /// Final state of the capital pool shared by strategies, if any
+    pub shared_capital: Option<SharedCapitalSnapshot>,
 }
 
 impl GlobalReport {
@@ -28,6 +36,7 @@ impl GlobalReport {
             base_dir: output_dir,
             output_dir: output_dir_path,
             parallelism: parallelism.unwrap_or_else(num_cpus::get),
+            shared_capital: None,
         }
     }
 
@@ -64,6 +73,48 @@ impl GlobalReport {
             "report_increase_ratio.html",
             self.report_by_pnl_increase_ratio(10),
         );
+        if let Some(snapshot) = &self.shared_capital {
+            self.write_shared_capital_report(&report_dir, snapshot);
+        }
+        self.write_benchmarks_report(&report_dir);
+    }
+
+    /// Compare the pnl of every strategy with the benchmarks
+    pub(crate) fn compare_with_benchmarks(&mut self, benchmarks: &[BenchmarkSeries]) {
+        for report in &mut self.reports {
+            report.compare_with_benchmarks(benchmarks);
+        }
+    }
+
+    fn write_benchmarks_report<P: AsRef<Path>>(&self, report_dir: P) {
+        let by_strategy: BTreeMap<&str, &[BenchmarkStats]> = self
+            .reports
+            .iter()
+            .filter(|r| !r.benchmarks().is_empty())
+            .map(|r| (r.key.as_str(), r.benchmarks()))
+            .collect();
+        if by_strategy.is_empty() {
+            return;
+        }
+        let out_file = report_dir.as_ref().join(BENCHMARKS_REPORT_FILE);
+        let written = std::fs::File::create(&out_file)
+            .map_err(|e| e.to_string())
+            .and_then(|file| serde_json::to_writer_pretty(file, &by_strategy).map_err(|e| e.to_string()));
+        if let Err(e) = written {
+            error!(err = %e, file = ?out_file, "failed to write benchmarks report");
+        }
+    }
+
+    fn write_shared_capital_report<P: AsRef<Path>>(&self, report_dir: P, snapshot: &SharedCapitalSnapshot) {
+        let out_file = report_dir.as_ref().join(SHARED_CAPITAL_REPORT_FILE);
+        match std::fs::File::create(&out_file) {
+            Ok(file) => {
+                if let Err(e) = serde_json::to_writer_pretty(file, snapshot) {
+                    error!(err = %e, "failed to write shared capital report");
+                }
+            }
+            Err(e) => error!(err = %e, file = ?out_file, "failed to create shared capital report"),
+        }
     }
 
     fn symlink_dir(&mut self, report_dir: PathBuf) -> std::result::Result<(), Box<dyn std::error::Error>> {
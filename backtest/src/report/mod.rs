@@ -1,6 +1,7 @@
 use chrono::{DateTime, Utc};
 use plotly::{Candlestick, Plot, Scatter};
 
+pub use benchmark::{BenchmarkSettings, BenchmarkStats};
 use brokers::types::Candle;
 pub use global::GlobalReport;
 pub use logger::StreamWriterLogger;
@@ -9,6 +10,10 @@ pub use single::BacktestReport;
 use util::compress::Compression;
 use util::time::{utc_zero, TimedData};
 
+pub(crate) use self::benchmark::{record_benchmark, BenchmarkSeries, BENCHMARK_QUEUE_SIZE};
+
+mod benchmark;
+mod charts;
 mod global;
 mod logger;
 mod registry;
@@ -18,6 +23,9 @@ mod single;
 pub struct ReportConfig {
     pub parallelism: Option<usize>,
     pub compression: Compression,
+    /// Buy and hold benchmarks the pnl of strategies is compared with
+    #[serde(default)]
+    pub benchmarks: Vec<BenchmarkSettings>,
     // #[serde(deserialize_with = "util::ser::decode_duration_str")]
     // pub sample_rate: Duration,
 }
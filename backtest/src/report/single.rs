@@ -8,6 +8,7 @@ use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 use brokers::types::Candle;
+use chrono::{DateTime, Utc};
 use ext::ResultExt;
 use itertools::Itertools;
 use plotly::layout::{GridPattern, LayoutGrid};
@@ -17,6 +18,7 @@ use tokio::sync::mpsc::UnboundedSender;
 use tokio::task;
 
 use stats::Next;
+use strategy::feature_store::FeatureDataset;
 use strategy::query::PortfolioSnapshot;
 use strategy::types::StratEvent;
 use trading::types::MarketStat;
@@ -25,7 +27,7 @@ use util::ser::{write_as_seq, NdJsonSerde, StreamSerializerWriter};
 
 use crate::error::Result;
 
-use super::TimedData;
+use super::{BenchmarkSeries, BenchmarkStats, TimedData};
 
 #[derive(Serialize, Deserialize, Clone)]
 pub struct BacktestReportMiscStats {
@@ -35,6 +37,23 @@ pub struct BacktestReportMiscStats {
     pub count: u64,
     pub loss_count: u64,
     pub last_pnl: Option<f64>,
+    /// Mean of pnl changes
+    #[serde(default)]
+    pub pnl_change_mean: f64,
+    /// Sum of squared deviations of pnl changes from their mean
+    #[serde(default)]
+    pnl_change_m2: f64,
+    #[serde(default)]
+    pub peak_pnl: Option<f64>,
+    /// Largest drop of the pnl from its peak
+    #[serde(default)]
+    pub max_drawdown: f64,
+    /// Fees paid up to the last snapshot
+    #[serde(default)]
+    pub fees: f64,
+    /// Funding received up to the last snapshot, negative when paid
+    #[serde(default)]
+    pub funding: f64,
 }
 
 impl BacktestReportMiscStats {
@@ -47,10 +66,33 @@ impl BacktestReportMiscStats {
                     self.loss_count += 1;
                 }
                 self.pnl_inc_ratio = (self.count * self.count) as f64 / (self.loss_count + 1) as f64;
+                let change = new_pnl - last_pnl;
+                let delta = change - self.pnl_change_mean;
+                self.pnl_change_mean += delta / self.count as f64;
+                self.pnl_change_m2 += delta * (change - self.pnl_change_mean);
             }
         }
+        let peak_pnl = self.peak_pnl.map_or(new_pnl, |peak| peak.max(new_pnl));
+        self.max_drawdown = self.max_drawdown.max(peak_pnl - new_pnl);
+        self.peak_pnl = Some(peak_pnl);
         self.last_pnl = Some(new_pnl);
     }
+
+    /// The last pnl before fees and funding
+    pub fn gross_pnl(&self) -> Option<f64> { self.last_pnl.map(|pnl| pnl + self.fees - self.funding) }
+
+    /// Mean of pnl changes over their standard deviation, not annualized
+    pub fn sharpe_ratio(&self) -> f64 {
+        if self.count < 2 {
+            return 0.0;
+        }
+        let std_dev = (self.pnl_change_m2 / (self.count - 1) as f64).sqrt();
+        if std_dev <= f64::EPSILON {
+            0.0
+        } else {
+            self.pnl_change_mean / std_dev
+        }
+    }
 }
 
 impl Default for BacktestReportMiscStats {
@@ -62,6 +104,12 @@ impl Default for BacktestReportMiscStats {
             count: 0,
             loss_count: 0,
             last_pnl: None,
+            pnl_change_mean: 0.0,
+            pnl_change_m2: 0.0,
+            peak_pnl: None,
+            max_drawdown: 0.0,
+            fees: 0.0,
+            funding: 0.0,
         }
     }
 }
@@ -87,6 +135,11 @@ pub struct BacktestReport {
     pub(crate) last_ptf_snapshot: Option<TimedData<PortfolioSnapshot>>,
     pub(crate) misc_stats: BacktestReportMiscStats,
     pub(crate) compression: Compression,
+    /// Pnl of the pushed snapshots, compared with benchmarks once the backtest is over
+    #[serde(skip)]
+    pub(crate) pnl_series: Vec<(DateTime<Utc>, f64)>,
+    /// Performance relative to each benchmark
+    pub(crate) benchmarks: Vec<BenchmarkStats>,
 }
 
 impl Debug for BacktestReport {
@@ -103,44 +156,65 @@ const SNAPSHOTS_FILE: &str = "snapshots.json";
 const MARKET_STATS_FILE: &str = "market_stats.json";
 const STRAT_EVENTS_FILE: &str = "strat_events.json";
 const CANDLES_FILE: &str = "candles.json";
+const FEATURES_FILE: &str = "features.csv";
 const REPORT_FILE: &str = "report.json";
 const REPORT_HTML_FILE: &str = "report.html";
 const TRADEVIEW_HTML_FILE: &str = "tradeview.html";
 
+fn stream_writer<T>(
+    out_file: PathBuf,
+    compression: Compression,
+    append: bool,
+) -> Arc<StreamSerializerWriter<T, NdJsonSerde>>
+where
+    T: 'static + serde::de::DeserializeOwned + serde::Serialize + Debug + Send,
+{
+    let writer = StreamSerializerWriter::new_with_compression(out_file, compression);
+    Arc::new(if append { writer.appending() } else { writer })
+}
+
 impl BacktestReport {
     /// Create a new backtest report
     /// Call [`start`] to enable writing data to files
     /// [`start`]: `fn@self::BacktestReport::start`
     pub fn new<P: AsRef<Path>>(base_output_dir: P, key: String, compression: Compression) -> Self {
+        Self::new_with(base_output_dir, key, compression, false)
+    }
+
+    /// Continue a report written by a previous run, values are appended to its files and its statistics
+    /// include the previous snapshots
+    pub(crate) fn new_appending<P: AsRef<Path>>(base_output_dir: P, key: String, compression: Compression) -> Self {
+        let mut report = Self::new_with(base_output_dir, key, compression, true);
+        if compression.wrap_ext(&report.snapshots_ss.out_file).exists() {
+            match report.snapshots_ss.read_all() {
+                Ok(snapshots) => {
+                    for snapshot in snapshots {
+                        report.record_snapshot(snapshot);
+                    }
+                }
+                Err(e) => error!(key = %report.key, err = %e, "failed to read previous snapshots"),
+            }
+        }
+        report
+    }
+
+    fn new_with<P: AsRef<Path>>(base_output_dir: P, key: String, compression: Compression, append: bool) -> Self {
         let report_dir = base_output_dir.as_ref().to_path_buf().join(key.clone());
         Self {
             output_dir: report_dir.clone(),
-            model_ss: Arc::new(StreamSerializerWriter::new_with_compression(
-                report_dir.join(MODEL_FILE),
-                compression,
-            )),
-            snapshots_ss: Arc::new(StreamSerializerWriter::new_with_compression(
-                report_dir.join(SNAPSHOTS_FILE),
-                compression,
-            )),
-            market_stats_ss: Arc::new(StreamSerializerWriter::new_with_compression(
-                report_dir.join(MARKET_STATS_FILE),
-                compression,
-            )),
-            events_ss: Arc::new(StreamSerializerWriter::new_with_compression(
-                report_dir.join(STRAT_EVENTS_FILE),
-                compression,
-            )),
-            candles_ss: Arc::new(StreamSerializerWriter::new_with_compression(
-                report_dir.join(CANDLES_FILE),
-                compression,
-            )),
+            model_ss: stream_writer(report_dir.join(MODEL_FILE), compression, append),
+            snapshots_ss: stream_writer(report_dir.join(SNAPSHOTS_FILE), compression, append),
+            market_stats_ss: stream_writer(report_dir.join(MARKET_STATS_FILE), compression, append),
+            events_ss: stream_writer(report_dir.join(STRAT_EVENTS_FILE), compression, append),
+            candles_ss: stream_writer(report_dir.join(CANDLES_FILE), compression, append),
             misc_stats: BacktestReportMiscStats::default(),
             key,
             failures: Default::default(),
             execution_hist: HashMap::default(),
             last_ptf_snapshot: None,
             compression,
+            pnl_series: vec![],
+            benchmarks: vec![],
         }
     }
 
@@ -152,8 +226,15 @@ impl BacktestReport {
     /// Push a portfolio snapshot to the report
     pub(crate) fn push_snapshot(&mut self, v: TimedData<PortfolioSnapshot>) {
         self.snapshots_ss.push(v).unwrap();
+        self.record_snapshot(v);
+    }
+
+    fn record_snapshot(&mut self, v: TimedData<PortfolioSnapshot>) {
         // Only compute stddev if values change
         self.misc_stats.update(v.value.pnl);
+        self.misc_stats.fees = v.value.fees;
+        self.misc_stats.funding = v.value.funding;
+        self.pnl_series.push((v.ts, v.value.pnl));
         self.last_ptf_snapshot = Some(v);
     }
 
@@ -188,6 +269,17 @@ impl BacktestReport {
     /// Read miscellaneous stats
     pub fn misc_stats(&self) -> &BacktestReportMiscStats { &self.misc_stats }
 
+    /// Performance relative to benchmarks
+    pub fn benchmarks(&self) -> &[BenchmarkStats] { &self.benchmarks }
+
+    /// Compare the pnl of the strategy with holding each benchmark
+    pub(crate) fn compare_with_benchmarks(&mut self, benchmarks: &[BenchmarkSeries]) {
+        self.benchmarks = benchmarks
+            .iter()
+            .filter_map(|benchmark| benchmark.compare(&self.pnl_series))
+            .collect();
+    }
+
     /// Get report events as a dataframe
     pub fn events_df(&self, table: &str) -> Result<Vec<ArrayRef>> {
         let batch_size = 2048;
@@ -236,6 +328,14 @@ impl BacktestReport {
         Ok(())
     }
 
+    /// Write the features recorded by the strategy as a csv dataset
+    pub(crate) fn write_features(&self, dataset: &FeatureDataset) -> Result<()> {
+        let file = std::fs::File::create(self.output_dir.join(FEATURES_FILE))?;
+        dataset
+            .write_csv(std::io::BufWriter::new(file))
+            .map_err(|e| anyhow!("failed to write features : {}", e).into())
+    }
+
     /// Finish writing the report
     pub async fn finish(&self) -> Result<()> {
         let report_dir = self.output_dir.clone();
@@ -251,6 +351,7 @@ impl BacktestReport {
     pub fn write_html(&mut self) {
         self.write_html_report();
         self.write_html_tradeview();
+        self.write_charts();
         self.write_custom_reports();
     }
 
@@ -274,10 +375,23 @@ impl BacktestReport {
 
     fn write_html_tradeview(&self) -> String { self.write_plot(self.tradeview_plot(), TRADEVIEW_HTML_FILE) }
 
+    /// Write the equity curve, drawdown and exposure charts as SVG files, linked from `charts.html`
+    fn write_charts(&self) {
+        let written = self
+            .snapshots_ss
+            .read_all()
+            .err_into()
+            .and_then(|snapshots| super::charts::write_charts(&self.output_dir, &snapshots));
+        if let Err(e) = written {
+            tracing::error!(err = %e, key = %self.key, "failed to write charts");
+        }
+    }
+
     pub async fn reload<P: AsRef<Path>>(key: &str, path: P, report_compression: Compression) -> Self {
         let report = BacktestReport::new(path.as_ref(), key.to_string(), report_compression);
         task::spawn_blocking(move || {
             report.write_html_report();
+            report.write_charts();
             report
         })
         .await
@@ -305,8 +419,11 @@ impl BacktestReport {
         if let Ok(snapshots) = self.snapshots_ss.read_all() {
             super::draw_lines(&mut plot, trace_offset, snapshots.as_slice(), vec![
                 ("pnl", vec![|i| i.pnl]),
+                ("gross vs net pnl", vec![|i| i.pnl + i.fees - i.funding, |i| i.pnl]),
                 ("value", vec![|i| i.value]),
                 ("return", vec![|i| i.current_return]),
+                ("funding", vec![|i| i.funding]),
+                ("fees", vec![|i| i.fees]),
             ]);
             trace_offset += 1;
         }
@@ -3,6 +3,7 @@ use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::Instant;
 
+use chrono::{DateTime, Utc};
 use tokio::sync::mpsc::{channel, Receiver, Sender};
 use tokio::sync::{Mutex, RwLock};
 use tokio::task;
@@ -16,7 +17,7 @@ use db::DbOptions;
 use strategy::driver::StrategyDriver;
 use strategy::event::{close_events, open_events};
 use strategy::models::Sampler;
-use strategy::plugin::plugin_registry;
+use strategy::plugin::{find_plugin, plugin_registry};
 use strategy::prelude::StrategyDriverSettings;
 use strategy::query::{DataQuery, DataResult};
 use strategy::types::{OperationEvent, PositionSummary, StratEvent, TradeEvent};
@@ -25,6 +26,7 @@ use util::compress::Compression;
 use util::time::{set_mock_time, utc_zero, TimedData};
 use util::trace::{display_hist_percentiles, microtime_histogram, microtime_percentiles};
 
+use crate::checkpoint::Checkpointer;
 use crate::report::{BacktestReport, StreamWriterLogger};
 
 const DEFAULT_RUNNER_SINK_SIZE: usize = 1000;
@@ -66,8 +68,9 @@ impl BacktestRunner {
         let logger2 = logger.clone();
         let strategy_driver = task::spawn_blocking(move || {
             debug!("plugin_registry() = {:?}", plugin_registry());
-            let plugin = plugin_registry().get(settings.strat.strat_type.as_str()).unwrap();
-            strategy::settings::from_driver_settings(plugin, &db_conf, &settings, engine, Some(logger.clone())).unwrap()
+            let plugin = find_plugin(plugin_registry(), settings.strat.strat_type.as_str()).unwrap();
+            strategy::settings::from_driver_settings(&plugin, &db_conf, &settings, engine, Some(logger.clone()))
+                .unwrap()
         })
         .await
         .unwrap();
@@ -106,11 +109,15 @@ impl BacktestRunner {
 
     pub(crate) fn event_sink(&self) -> Sender<MarketEventEnvelope> { self.events_sink.clone() }
 
+    pub(crate) async fn key(&self) -> String { self.driver.lock().await.key().await }
+
     pub(crate) async fn run<P: AsRef<Path>>(
         &mut self,
         output_dir: P,
         report_compression: Compression,
         stop_token: CancellationToken,
+        checkpointer: Option<Checkpointer>,
+        continue_after: Option<DateTime<Utc>>,
     ) -> BacktestReport {
         let key = {
             let strategy = self.driver.lock().await;
@@ -118,7 +125,12 @@ impl BacktestRunner {
         };
 
         // Start report
-        let mut report = BacktestReport::new(output_dir, key.clone(), report_compression);
+        // Continuing a previous run appends to its report
+        let mut report = if continue_after.is_some() {
+            BacktestReport::new_appending(output_dir, key.clone(), report_compression)
+        } else {
+            BacktestReport::new(output_dir, key.clone(), report_compression)
+        };
         report.start().await.unwrap();
         let mut execution_hist = microtime_histogram();
 
@@ -134,6 +146,17 @@ impl BacktestRunner {
         });
         // Main loop
         let mut driver = self.driver.lock().await;
+        // Events up to the checkpoint of a resumed strategy, or processed by the continued run, are skipped
+        let resumed_at = match checkpointer.as_ref().map(|c| c.restore(&key, &mut **driver)) {
+            Some(Ok(at)) => at,
+            Some(Err(e)) => {
+                error!(key = %key, err = %e, "failed to restore checkpoint");
+                None
+            }
+            None => None,
+        }
+        .or(continue_after);
+        let mut last_checkpoint = resumed_at;
         'main: loop {
             tokio::select! {
                 biased;
@@ -144,8 +167,14 @@ impl BacktestRunner {
                     }
                     let start = Instant::now();
                     let market_event = market_event.unwrap();
+                    if resumed_at.map_or(false, |at| market_event.e.time() <= at) {
+                        continue 'main;
+                    }
                     set_mock_time(market_event.e.time());
                     driver.on_market_event(&market_event).await.unwrap();
+                    if driver.check_risk(market_event.e.time()).await {
+                        driver.stop_trading().unwrap();
+                    }
                     // If there is an ongoing operation, resolve orders
                     let mut tries = 0;
                     'resolve: loop {
@@ -167,7 +196,7 @@ impl BacktestRunner {
                     if let MarketEvent::Trade(_) = &market_event.e {
                         report.push_market_stat(TimedData::new(market_event.e.time(), (&market_event.e).into()));
                     }
-                    if let MarketEvent::Orderbook(_) = &market_event.e {
+                    if let MarketEvent::Orderbook(_) | MarketEvent::Quote(_) = &market_event.e {
                         report.push_market_stat(TimedData::new(market_event.e.time(), (&market_event.e).into()));
                     }
                     if let MarketEvent::TradeCandle(candle) = &market_event.e {
@@ -184,7 +213,7 @@ impl BacktestRunner {
                             report.push_market_stat(TimedData::new(market_event.e.time(), (&market_event.e).into()));
                         }
                     }
-                    if matches!(&market_event.e, MarketEvent::TradeCandle(Candle { is_final: true, .. }) | MarketEvent::BookCandle(BookCandle { is_final: true, .. }) | MarketEvent::Trade(_) | MarketEvent::Orderbook(_)) {
+                    if matches!(&market_event.e, MarketEvent::TradeCandle(Candle { is_final: true, .. }) | MarketEvent::BookCandle(BookCandle { is_final: true, .. }) | MarketEvent::Trade(_) | MarketEvent::Orderbook(_) | MarketEvent::Quote(_)) {
                         match driver.query(DataQuery::Models).await {
                             Ok(DataResult::Models(models)) => report
                                 .push_model(TimedData::new(market_event.e.time(), models.into_iter().collect())),
@@ -201,6 +230,16 @@ impl BacktestRunner {
                             }
                         }
                     }
+                    if let Some(checkpointer) = &checkpointer {
+                        let at = market_event.e.time();
+                        let last = *last_checkpoint.get_or_insert(at);
+                        if checkpointer.is_due(last, at) {
+                            if let Err(e) = checkpointer.save(&key, at, &mut **driver).await {
+                                error!(key = %key, err = %e, "failed to save checkpoint");
+                            }
+                            last_checkpoint = Some(at);
+                        }
+                    }
                     execution_hist += start.elapsed().as_nanos() as u64;
                 },
                 _ = stop_token.cancelled() => {
@@ -210,6 +249,15 @@ impl BacktestRunner {
             }
         }
 
+        match driver.query(DataQuery::Features).await {
+            Ok(DataResult::Features(dataset)) if !dataset.is_empty() => {
+                if let Err(e) = report.write_features(&dataset) {
+                    error!(key = %key, err = %e, "failed to write features");
+                }
+            }
+            Ok(_) => {}
+            Err(e) => error!(key = %key, err = %e, "failed to query features"),
+        }
         info!(
             "{} event loop stats : {}",
             report.key,
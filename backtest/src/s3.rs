@@ -0,0 +1,88 @@
+//! Reads datasets from s3 compatible object stores, partitions are listed and record batches are streamed by the
+//! query engine so that datasets are never downloaded as a whole.
+
+use std::sync::Arc;
+
+use datafusion::datasource::object_store::ObjectStoreUrl;
+use futures::StreamExt;
+use object_store::aws::AmazonS3Builder;
+use object_store::path::Path as ObjectPath;
+
+use crate::datafusion_util::runtime_env;
+use crate::error::*;
+
+pub(crate) const S3_SCHEME: &str = "s3://";
+
+/// Credentials and location of an s3 compatible storage, unset values are read from the environment
+/// (`AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY`, `AWS_DEFAULT_REGION`, `AWS_ENDPOINT`)
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct S3Settings {
+    pub region: Option<String>,
+    /// The endpoint of storages other than aws, e.g. `https://nyc3.digitaloceanspaces.com`
+    pub endpoint: Option<String>,
+    /// A profile of the aws config and credentials files, used when no keys are set
+    pub profile: Option<String>,
+    pub access_key_id: Option<String>,
+    pub secret_access_key: Option<String>,
+}
+
+impl S3Settings {
+    /// Register the bucket of an `s3://bucket/prefix` url so that datasets under it can be read
+    ///
+    /// # Errors
+    ///
+    /// The url is not an s3 url, or the store cannot be built from the settings
+    pub(crate) fn register(&self, url: &str) -> Result<()> {
+        let bucket = bucket_name(url).ok_or_else(|| anyhow!("not an s3 url {}", url))?;
+        let mut builder = AmazonS3Builder::from_env().with_bucket_name(bucket);
+        if let Some(region) = &self.region {
+            builder = builder.with_region(region);
+        }
+        if let Some(endpoint) = &self.endpoint {
+            builder = builder.with_endpoint(endpoint);
+        }
+        match (&self.access_key_id, &self.secret_access_key, &self.profile) {
+            (Some(key_id), Some(secret), _) => {
+                builder = builder.with_access_key_id(key_id).with_secret_access_key(secret);
+            }
+            (_, _, Some(profile)) => builder = builder.with_profile(profile),
+            _ => {}
+        }
+        let store_url = ObjectStoreUrl::parse(format!("{}{}", S3_SCHEME, bucket))?;
+        runtime_env().register_object_store(store_url.as_ref(), Arc::new(builder.build()?));
+        Ok(())
+    }
+}
+
+/// Whether objects are stored under the `s3://bucket/prefix` directory, in the store registered for the bucket
+///
+/// # Errors
+///
+/// The url is not an s3 url, no store is registered for the bucket or objects cannot be listed
+pub(crate) async fn dir_exists(url: &str) -> Result<bool> {
+    let bucket = bucket_name(url).ok_or_else(|| anyhow!("not an s3 url {}", url))?;
+    let store = runtime_env().object_store(ObjectStoreUrl::parse(format!("{}{}", S3_SCHEME, bucket))?)?;
+    let prefix = ObjectPath::from(url[S3_SCHEME.len() + bucket.len()..].trim_matches('/'));
+    let mut objects = store.list(Some(&prefix)).await?;
+    Ok(objects.next().await.transpose()?.is_some())
+}
+
+/// The bucket of an `s3://bucket/prefix` url
+pub(crate) fn bucket_name(url: &str) -> Option<&str> {
+    url.strip_prefix(S3_SCHEME)
+        .and_then(|path| path.split('/').next())
+        .filter(|bucket| !bucket.is_empty())
+}
+
+#[cfg(test)]
+mod test {
+    use super::bucket_name;
+
+    #[test]
+    fn parse_bucket_name() {
+        assert_eq!(bucket_name("s3://btcfeed/data24"), Some("btcfeed"));
+        assert_eq!(bucket_name("s3://btcfeed"), Some("btcfeed"));
+        assert_eq!(bucket_name("s3:///data24"), None);
+        assert_eq!(bucket_name("/tmp/data24"), None);
+    }
+}
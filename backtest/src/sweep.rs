@@ -0,0 +1,268 @@
+//! Parameter sweeps : strategies are replicated for every combination of a grid of option values, each combination is
+//! backtested on its own so that replicas do not share strategy keys, databases or reports.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use itertools::Itertools;
+use serde_json::{Map, Value};
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+
+use db::DbOptions;
+use strategy::prelude::StrategyDriverSettings;
+
+use crate::backtest::Backtest;
+use crate::config::BacktestConfig;
+use crate::error::*;
+use crate::report::BacktestReport;
+
+const SWEEP_REPORT_FILE: &str = "sweep.json";
+
+/// What the best options of a sweep maximize
+#[derive(Debug, Default, Clone, Copy, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Objective {
+    /// Mean pnl change over its standard deviation
+    #[default]
+    Sharpe,
+    /// Last pnl
+    Pnl,
+    /// Smallest maximum drawdown of the pnl
+    Drawdown,
+}
+
+impl Objective {
+    /// The mean score of strategy reports, higher is better
+    pub(crate) fn score(self, reports: &[BacktestReport]) -> f64 {
+        if reports.is_empty() {
+            return f64::MIN;
+        }
+        let total: f64 = reports
+            .iter()
+            .map(|r| match self {
+                Objective::Sharpe => r.misc_stats.sharpe_ratio(),
+                Objective::Pnl => r.misc_stats.last_pnl.unwrap_or(0.0),
+                Objective::Drawdown => -r.misc_stats.max_drawdown,
+            })
+            .sum();
+        total / reports.len() as f64
+    }
+}
+
+#[derive(Deserialize)]
+pub struct SweepSettings {
+    /// Values of strategy options to sweep, options of nested objects are separated by dots (e.g. `conf.stop_loss`)
+    pub grid: BTreeMap<String, Vec<Value>>,
+    #[serde(default)]
+    pub objective: Objective,
+    /// Backtests running at the same time, defaults to the number of cores
+    pub parallelism: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SweepResult {
+    pub params: BTreeMap<String, Value>,
+    /// Score of the objective
+    pub score: f64,
+    pub pnl: f64,
+    pub sharpe: f64,
+    pub max_drawdown: f64,
+}
+
+/// Mean score of the backtests sharing a value of a parameter
+#[derive(Debug, Serialize)]
+pub struct ParamSensitivity {
+    pub value: Value,
+    pub mean_score: f64,
+    pub count: usize,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct SweepReport {
+    /// Results ranked by score, best first
+    pub ranking: Vec<SweepResult>,
+    /// For each parameter, the mean score of each of its values
+    pub sensitivity: BTreeMap<String, Vec<ParamSensitivity>>,
+}
+
+impl SweepReport {
+    fn new(mut ranking: Vec<SweepResult>) -> Self {
+        ranking.sort_by(|a, b| b.score.total_cmp(&a.score));
+        let mut sensitivity: BTreeMap<String, Vec<ParamSensitivity>> = BTreeMap::new();
+        for path in ranking.iter().flat_map(|r| r.params.keys()).unique() {
+            let by_value = ranking
+                .iter()
+                .filter_map(|r| r.params.get(path).map(|v| (v.to_string(), (v, r.score))))
+                .into_group_map();
+            let values = by_value
+                .into_values()
+                .map(|scores| ParamSensitivity {
+                    value: scores[0].0.clone(),
+                    mean_score: scores.iter().map(|(_, s)| s).sum::<f64>() / scores.len() as f64,
+                    count: scores.len(),
+                })
+                .sorted_by(|a, b| b.mean_score.total_cmp(&a.mean_score))
+                .collect();
+            sensitivity.insert(path.clone(), values);
+        }
+        Self { ranking, sensitivity }
+    }
+
+    fn write(&self, output_dir: &Path) -> Result<()> {
+        std::fs::create_dir_all(output_dir)?;
+        serde_json::to_writer_pretty(File::create(output_dir.join(SWEEP_REPORT_FILE))?, self)?;
+        Ok(())
+    }
+}
+
+/// Backtest the strategies of a configuration with every combination of the sweep grid, concurrently
+///
+/// # Errors
+///
+/// The configuration has no sweep settings, or a backtest fails
+pub async fn sweep(conf: Arc<BacktestConfig>) -> Result<SweepReport> {
+    let settings = conf.sweep.as_ref().ok_or_else(|| anyhow!("missing sweep settings"))?;
+    let strategies = conf.all_strategy_settings().await;
+    let candidates = grid_candidates(&settings.grid);
+    let output_dir = conf.output_dir().join("sweep");
+    let db_path = conf.db_path();
+    info!("Sweeping {} parameter combinations", candidates.len());
+    // Each candidate runs on its own thread and actor system, at most `parallelism` at the same time
+    let permits = Arc::new(Semaphore::new(settings.parallelism.unwrap_or_else(num_cpus::get)));
+    let mut running = JoinSet::new();
+    for (i, params) in candidates.into_iter().enumerate() {
+        let permit = permits.clone().acquire_owned().await.map_err(|e| anyhow!(e))?;
+        let strategies = strategies.iter().map(|s| with_params(s, &params)).collect();
+        let output_dir = output_dir.join(format!("candidate_{}", i));
+        let db_conf = conf.db_conf_at(db_path.join(format!("candidate_{}", i)));
+        let conf = conf.clone();
+        let objective = settings.objective;
+        running.spawn_blocking(move || {
+            let _permit = permit;
+            actix::System::new().block_on(run_candidate(&conf, objective, strategies, params, output_dir, db_conf))
+        });
+    }
+    let mut results = vec![];
+    while let Some(result) = running.join_next().await {
+        results.push(result.map_err(|e| anyhow!(e))??);
+    }
+    let report = SweepReport::new(results);
+    report.write(&output_dir)?;
+    Ok(report)
+}
+
+/// Backtest the strategies of a grid candidate
+async fn run_candidate(
+    conf: &BacktestConfig,
+    objective: Objective,
+    strategies: Vec<StrategyDriverSettings>,
+    params: BTreeMap<String, Value>,
+    output_dir: PathBuf,
+    db_conf: DbOptions<PathBuf>,
+) -> Result<SweepResult> {
+    let mut backtest = Backtest::try_new_with(conf, strategies, conf.period.as_range(), output_dir, db_conf).await?;
+    let report = backtest.run().await?;
+    let result = SweepResult {
+        score: objective.score(&report.reports),
+        pnl: Objective::Pnl.score(&report.reports),
+        sharpe: Objective::Sharpe.score(&report.reports),
+        max_drawdown: -Objective::Drawdown.score(&report.reports),
+        params,
+    };
+    info!(params = ?result.params, score = result.score, "swept parameters");
+    Ok(result)
+}
+
+/// Every combination of the values of the grid
+pub(crate) fn grid_candidates(grid: &BTreeMap<String, Vec<Value>>) -> Vec<BTreeMap<String, Value>> {
+    if grid.is_empty() {
+        return vec![BTreeMap::new()];
+    }
+    grid.iter()
+        .map(|(path, values)| values.iter().map(move |v| (path.clone(), v.clone())))
+        .multi_cartesian_product()
+        .map(|params| params.into_iter().collect())
+        .collect()
+}
+
+/// Strategy settings with options replaced by those of a grid candidate
+pub(crate) fn with_params(
+    settings: &StrategyDriverSettings,
+    params: &BTreeMap<String, Value>,
+) -> StrategyDriverSettings {
+    let mut settings = settings.clone();
+    for (path, value) in params {
+        set_option(&mut settings.strat.options, path, value.clone());
+    }
+    settings
+}
+
+/// Set an option at a dot separated path, creating missing objects
+fn set_option(options: &mut Value, path: &str, value: Value) {
+    let mut target = options;
+    for key in path.split('.') {
+        if !target.is_object() {
+            *target = Value::Object(Map::new());
+        }
+        let Value::Object(map) = target else { unreachable!() };
+        target = map.entry(key).or_insert(Value::Null);
+    }
+    *target = value;
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use serde_json::json;
+
+    use super::{grid_candidates, set_option, SweepReport, SweepResult};
+
+    #[test]
+    fn sweep_grid() {
+        let grid = BTreeMap::from([
+            ("conf.stop_loss".to_string(), vec![json!(-0.1), json!(-0.05)]),
+            ("short_window_size".to_string(), vec![json!(50), json!(100), json!(200)]),
+        ]);
+        let candidates = grid_candidates(&grid);
+        assert_eq!(candidates.len(), 6);
+        let mut options = json!({"short_window_size": 10, "conf": {"stop_gain": 0.1}});
+        for (path, value) in &candidates[0] {
+            set_option(&mut options, path, value.clone());
+        }
+        assert_eq!(
+            options,
+            json!({"short_window_size": 50, "conf": {"stop_gain": 0.1, "stop_loss": -0.1}})
+        );
+    }
+
+    #[test]
+    fn rank_and_sensitivity() {
+        let result = |window: i64, stop_loss: f64, score: f64| SweepResult {
+            params: BTreeMap::from([
+                ("window".to_string(), json!(window)),
+                ("stop_loss".to_string(), json!(stop_loss)),
+            ]),
+            score,
+            pnl: 0.0,
+            sharpe: 0.0,
+            max_drawdown: 0.0,
+        };
+        let report = SweepReport::new(vec![
+            result(50, -0.1, 1.0),
+            result(100, -0.1, 3.0),
+            result(50, -0.05, 2.0),
+            result(100, -0.05, 6.0),
+        ]);
+        assert_eq!(report.ranking[0].score, 6.0);
+        assert_eq!(report.ranking[3].score, 1.0);
+        let window = &report.sensitivity["window"];
+        assert_eq!(window[0].value, json!(100));
+        assert_eq!(window[0].mean_score, 4.5);
+        assert_eq!(window[0].count, 2);
+        assert_eq!(window[1].mean_score, 1.5);
+    }
+}
@@ -0,0 +1,175 @@
+//! Walk-forward optimization : over rolling windows of the backtest period, strategy options are picked by a sweep
+//! over a grid of values on a training window, then evaluated on the window that follows it.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Duration, Utc};
+use serde_json::Value;
+
+use strategy::prelude::StrategyDriverSettings;
+use util::time::DateRange;
+
+use crate::backtest::Backtest;
+use crate::config::BacktestConfig;
+use crate::error::*;
+use crate::sweep::{grid_candidates, with_params, Objective};
+
+const WALK_FORWARD_REPORT_FILE: &str = "walk_forward.json";
+
+#[derive(Deserialize)]
+pub struct WalkForwardSettings {
+    /// Length of training windows
+    #[serde(deserialize_with = "util::ser::string_duration_chrono")]
+    pub train: Duration,
+    /// Length of test windows, windows roll forward by this length
+    #[serde(deserialize_with = "util::ser::string_duration_chrono")]
+    pub test: Duration,
+    /// Values of strategy options to sweep, options of nested objects are separated by dots (e.g. `conf.stop_loss`)
+    pub grid: BTreeMap<String, Vec<Value>>,
+    #[serde(default)]
+    pub objective: Objective,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WalkForwardWindow {
+    pub train_from: DateTime<Utc>,
+    pub train_to: DateTime<Utc>,
+    pub test_from: DateTime<Utc>,
+    pub test_to: DateTime<Utc>,
+    /// The best options of the training window
+    pub params: BTreeMap<String, Value>,
+    pub train_score: f64,
+    pub test_score: f64,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct WalkForwardReport {
+    pub windows: Vec<WalkForwardWindow>,
+    /// Mean score of test windows
+    pub test_score: f64,
+}
+
+impl WalkForwardReport {
+    fn write(&self, output_dir: &Path) -> Result<()> {
+        std::fs::create_dir_all(output_dir)?;
+        serde_json::to_writer_pretty(File::create(output_dir.join(WALK_FORWARD_REPORT_FILE))?, self)?;
+        Ok(())
+    }
+}
+
+/// Run the walk-forward optimization of the strategies of a configuration
+///
+/// # Errors
+///
+/// The configuration has no walk forward settings, or a backtest fails
+pub async fn walk_forward(conf: &BacktestConfig) -> Result<WalkForwardReport> {
+    let settings = conf
+        .walk_forward
+        .as_ref()
+        .ok_or_else(|| anyhow!("missing walk forward settings"))?;
+    let strategies = conf.all_strategy_settings().await;
+    let candidates = grid_candidates(&settings.grid);
+    let period = conf.period.as_range();
+    let output_dir = conf.output_dir().join("walk_forward");
+    let mut report = WalkForwardReport::default();
+    for (i, (train, test)) in windows(period.0, period.1, settings.train, settings.test)
+        .into_iter()
+        .enumerate()
+    {
+        let window_dir = output_dir.join(format!("window_{}", i));
+        let mut best: Option<(&BTreeMap<String, Value>, f64)> = None;
+        for (j, params) in candidates.iter().enumerate() {
+            let score = run_window(
+                conf,
+                &strategies,
+                params,
+                train,
+                window_dir.join("train").join(format!("candidate_{}", j)),
+                settings.objective,
+            )
+            .await?;
+            if best.map_or(true, |(_, best_score)| score > best_score) {
+                best = Some((params, score));
+            }
+        }
+        let Some((params, train_score)) = best else {
+            continue;
+        };
+        let test_score = run_window(
+            conf,
+            &strategies,
+            params,
+            test,
+            window_dir.join("test"),
+            settings.objective,
+        )
+        .await?;
+        info!(window = i, params = ?params, train_score, test_score, "walk forward window");
+        report.windows.push(WalkForwardWindow {
+            train_from: train.0,
+            train_to: train.1,
+            test_from: test.0,
+            test_to: test.1,
+            params: params.clone(),
+            train_score,
+            test_score,
+        });
+    }
+    if !report.windows.is_empty() {
+        report.test_score = report.windows.iter().map(|w| w.test_score).sum::<f64>() / report.windows.len() as f64;
+    }
+    report.write(&output_dir)?;
+    Ok(report)
+}
+
+/// Backtest the strategies with options over a window, and score their reports
+async fn run_window(
+    conf: &BacktestConfig,
+    strategies: &[StrategyDriverSettings],
+    params: &BTreeMap<String, Value>,
+    period: DateRange,
+    output_dir: PathBuf,
+    objective: Objective,
+) -> Result<f64> {
+    let strategies = strategies.iter().map(|s| with_params(s, params)).collect();
+    let mut backtest = Backtest::try_new_with(conf, strategies, period, output_dir, conf.db_conf()).await?;
+    let report = backtest.run().await?;
+    Ok(objective.score(&report.reports))
+}
+
+/// Rolling (train, test) windows within `from` and `to`, each test window follows its training window
+fn windows(from: DateTime<Utc>, to: DateTime<Utc>, train: Duration, test: Duration) -> Vec<(DateRange, DateRange)> {
+    let mut windows = vec![];
+    let mut start = from;
+    while start + train + test <= to {
+        let split = start + train;
+        windows.push((window(start, split), window(split, split + test)));
+        start = start + test;
+    }
+    windows
+}
+
+/// The days from `from` to `to` excluded
+fn window(from: DateTime<Utc>, to: DateTime<Utc>) -> DateRange {
+    DateRange::by_day(from, to - Duration::milliseconds(1))
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{Duration, TimeZone, Utc};
+
+    use super::windows;
+
+    #[test]
+    fn rolling_windows() {
+        let from = Utc.with_ymd_and_hms(2022, 1, 1, 0, 0, 0).unwrap();
+        let windows = windows(from, from + Duration::days(10), Duration::days(4), Duration::days(2));
+        assert_eq!(windows.len(), 3);
+        let (train, test) = windows[1];
+        assert_eq!(train.0, from + Duration::days(2));
+        assert_eq!(test.0, from + Duration::days(6));
+        assert_eq!(test.1, from + Duration::days(8) - Duration::milliseconds(1));
+    }
+}
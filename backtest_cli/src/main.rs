@@ -3,6 +3,8 @@ extern crate tracing;
 #[macro_use]
 extern crate futures;
 
+use std::sync::Arc;
+
 use backtest::{Backtest, BacktestConfig};
 use futures::FutureExt;
 use structopt::StructOpt;
@@ -12,8 +14,18 @@ use tradai_python::script_strat;
 
 #[derive(StructOpt, Debug)]
 enum BacktestCmd {
-    Run,
+    Run {
+        /// Continue from the last checkpoint of a previous run
+        #[structopt(long)]
+        resume: bool,
+        /// Only replay the days not processed by the latest run, appending to its reports
+        #[structopt(long, conflicts_with = "resume")]
+        incremental: bool,
+    },
     GenReport,
+    WalkForward,
+    Sweep,
+    Parity,
 }
 
 #[derive(StructOpt, Debug)]
@@ -46,9 +58,18 @@ async fn run_main() -> anyhow::Result<()> {
 
     let opts = BacktestCliOptions::from_args();
     let conf = BacktestConfig::new(opts.config)?;
-    match opts.cmd.unwrap_or(BacktestCmd::Run) {
-        BacktestCmd::Run => {
-            let mut bt = Backtest::try_new(&conf).await?;
+    match opts.cmd.unwrap_or(BacktestCmd::Run {
+        resume: false,
+        incremental: false,
+    }) {
+        BacktestCmd::Run { resume, incremental } => {
+            let mut bt = if resume {
+                Backtest::try_resume(&conf).await?
+            } else if incremental {
+                Backtest::try_incremental(&conf).await?
+            } else {
+                Backtest::try_new(&conf).await?
+            };
             select! {
                 r = bt.run().fuse() => {
                     r?;
@@ -63,6 +84,31 @@ async fn run_main() -> anyhow::Result<()> {
         BacktestCmd::GenReport => {
             Backtest::gen_report(&conf).await;
         }
+        BacktestCmd::WalkForward => {
+            let report = backtest::walk_forward(&conf).await?;
+            info!(
+                "Walk forward finished with {} windows, test score {}.",
+                report.windows.len(),
+                report.test_score
+            );
+        }
+        BacktestCmd::Sweep => {
+            let report = backtest::sweep(Arc::new(conf)).await?;
+            if let Some(best) = report.ranking.first() {
+                info!(
+                    "Sweep finished, best parameters {:?} with score {}.",
+                    best.params, best.score
+                );
+            }
+        }
+        BacktestCmd::Parity => {
+            let report = backtest::parity(&conf).await?;
+            info!(
+                "Parity finished for {} strategies with {} divergences.",
+                report.strategies.len(),
+                report.divergences()
+            );
+        }
     }
     Ok(())
 }
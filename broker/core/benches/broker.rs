@@ -51,6 +51,7 @@ fn default_event() -> MarketEventEnvelope {
             amount: 100.0,
             price: 1000.0,
             tt: TradeType::default(),
+            aggressor: None,
         }),
     )
 }
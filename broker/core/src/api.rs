@@ -1,7 +1,13 @@
+use std::collections::HashMap;
 use std::fmt::Debug;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use stats::kline::Resolution;
 
 use crate::error::*;
 use crate::exchange::Exchange;
+use crate::fees::FeeTier;
 use crate::pair::PairConf;
 use crate::types::*;
 pub use mock::*;
@@ -11,6 +17,12 @@ pub trait Brokerage: Debug + Send + Sync {
     /// Return a Ticker for the Pair specified.
     async fn ticker(&self, pair: Pair) -> Result<Ticker>;
 
+    /// Return the last traded price of each of the specified pairs, in a single request when the exchange allows it.
+    /// Pairs unknown to the exchange are absent from the result.
+    async fn tickers(&self, _pairs: Vec<Pair>) -> Result<HashMap<Pair, f64>> {
+        return Err(Error::BrokerFeatureNotImplemented);
+    }
+
     /// Return an Orderbook for the specified Pair.
     async fn orderbook(&self, pair: Pair) -> Result<Orderbook>;
 
@@ -22,17 +34,55 @@ pub trait Brokerage: Debug + Send + Sync {
     ///
     /// A good practice is to store the return type (OrderInfo) somewhere since it can later be used
     /// to modify or cancel the order.
-    #[allow(irrefutable_let_patterns)]
+    ///
+    /// Order lists and batches are placed with [`Brokerage::add_oco_order`] and [`Brokerage::add_orders`] since they
+    /// result in several submissions.
     async fn order(&self, order: OrderQuery) -> Result<OrderSubmission> {
         order.validate()?;
-        if let OrderQuery::AddOrder(req) = order {
-            return self.add_order(req).await;
+        match order {
+            OrderQuery::AddOrder(req) => self.add_order(req).await,
+            OrderQuery::AmendOrder(req) => self.amend_order(req).await,
+            _ => Err(Error::InvalidArguments),
         }
-        unimplemented!()
     }
 
     async fn add_order(&self, order: AddOrderRequest) -> Result<OrderSubmission>;
 
+    /// Change the price or quantity of a resting order in place, returning the submission of the amended order
+    async fn amend_order(&self, _order: AmendOrderRequest) -> Result<OrderSubmission> {
+        return Err(Error::BrokerFeatureNotImplemented);
+    }
+
+    /// Cancel a resting order by its client order id
+    async fn cancel_order(&self, _id: String, _pair: Pair, _asset_type: AssetType) -> Result<()> {
+        return Err(Error::BrokerFeatureNotImplemented);
+    }
+
+    /// Arm the dead man's switch of the account, every resting order is canceled by the exchange unless this is
+    /// called again within `timeout`, a zero timeout disarms it.
+    /// Only exchanges with an account wide timer support it.
+    async fn cancel_all_after(&self, _timeout: Duration) -> Result<()> {
+        return Err(Error::BrokerFeatureNotImplemented);
+    }
+
+    /// The maker and taker fees rates of the current tier of the account, which depends on its trading volume
+    async fn fee_tier(&self) -> Result<FeeTier> { return Err(Error::BrokerFeatureNotImplemented); }
+
+    /// Place a one-cancels-other order, returning the submission of each order of the list
+    async fn add_oco_order(&self, _order: OcoOrderRequest) -> Result<Vec<OrderSubmission>> {
+        return Err(Error::BrokerFeatureNotImplemented);
+    }
+
+    /// Place several independent orders, in a single request if the exchange allows it, one after the other otherwise.
+    /// Every order succeeds or fails on its own, the results are in the order of the requests.
+    async fn add_orders(&self, orders: Vec<AddOrderRequest>) -> Result<Vec<Result<OrderSubmission>>> {
+        let mut submissions = Vec::with_capacity(orders.len());
+        for order in orders {
+            submissions.push(self.add_order(order).await);
+        }
+        Ok(submissions)
+    }
+
     /// Retrieve the current amounts of all the currencies that the account holds
     /// The amounts returned are available (not used to open an order)
     async fn account_balances(&self) -> Result<AccountPosition>;
@@ -48,6 +98,16 @@ pub trait Brokerage: Debug + Send + Sync {
         return Err(Error::BrokerFeatureNotImplemented);
     }
 
+    /// Borrow an asset on a margin account, returning the id of the loan transaction
+    async fn borrow(&self, _loan: MarginLoanRequest) -> Result<String> {
+        return Err(Error::BrokerFeatureNotImplemented);
+    }
+
+    /// Repay a margin loan, returning the id of the repayment transaction
+    async fn repay(&self, _loan: MarginLoanRequest) -> Result<String> {
+        return Err(Error::BrokerFeatureNotImplemented);
+    }
+
     /// Retrieve the current amounts of all the currencies that the account holds
     /// The amounts returned are available (not used to open an order)
     async fn get_order(&self, id: String, pair: Pair, asset_type: AssetType) -> Result<Order>;
@@ -61,6 +121,9 @@ pub trait Brokerage: Debug + Send + Sync {
     /// Whether or not the API is using account features
     fn uses_account(&self) -> bool;
 
+    /// What the exchange supports, to validate configurations before trading
+    fn capabilities(&self) -> ExchangeCapabilities { ExchangeCapabilities::default() }
+
     /// Get the current margin interest rate
     ///
     /// # Arguments
@@ -73,30 +136,100 @@ pub trait Brokerage: Debug + Send + Sync {
     }
 
     async fn trade_history(&self, _pair: Pair) -> Result<Vec<Trade>> { return Err(Error::BrokerFeatureNotImplemented); }
+
+    /// Get the historical candles of a pair, oldest first
+    ///
+    /// # Arguments
+    ///
+    /// * `pair`: the pair for which to fetch candles
+    /// * `resolution`: the interval of each candle
+    /// * `start`: candles opened before this time are not returned
+    /// * `end`: candles opened at or after this time are not returned
+    ///
+    /// returns: Result<Vec<Candle>, Error>, exchanges cap the number of candles returned by a single call
+    /// so the range may only be partially covered
+    async fn candles(
+        &self,
+        _pair: Pair,
+        _resolution: Resolution,
+        _start: DateTime<Utc>,
+        _end: DateTime<Utc>,
+    ) -> Result<Vec<Candle>> {
+        return Err(Error::BrokerFeatureNotImplemented);
+    }
+
+    /// Get the operational status of the exchange, exchanges that do not report it are always considered normal
+    async fn system_status(&self) -> Result<SystemStatus> { Ok(SystemStatus::Normal) }
+
+    /// Get the trades executed by the account for a pair, with the commission actually paid
+    ///
+    /// # Arguments
+    ///
+    /// * `pair`: the pair for which to fetch account trades
+    /// * `since`: if set, only trades executed at or after this time are returned
+    ///
+    /// returns: Result<Vec<TradeFill>, Error>
+    async fn my_trades(&self, _pair: Pair, _since: Option<DateTime<Utc>>) -> Result<Vec<TradeFill>> {
+        return Err(Error::BrokerFeatureNotImplemented);
+    }
+
+    /// Retrieve the funding paid or received by the perpetual contract positions of the account at or after `since`
+    async fn funding_payments(&self, _since: DateTime<Utc>) -> Result<Vec<FundingPayment>> {
+        return Err(Error::BrokerFeatureNotImplemented);
+    }
 }
 
 mod mock {
     use crate::api::Brokerage;
     use crate::error::*;
     use crate::exchange::Exchange;
+    use crate::fees::FeeTier;
     use crate::pair::PairConf;
     use crate::types::*;
-    use chrono::Utc;
+    use chrono::{DateTime, Utc};
+    use stats::kline::Resolution;
+    use std::collections::HashMap;
     use uuid::Uuid;
 
     #[derive(Debug)]
     pub struct MockBrokerage {
         flat_interest_rate: f64,
         flat_fees: f64,
+        prices: HashMap<Pair, f64>,
+        funding_payments: Vec<FundingPayment>,
     }
 
     const DEFAULT_HOURLY_INTEREST_RATE: f64 = 0.02 / 24.0;
+    /// Maximum number of candles returned by [`Brokerage::candles`], so that callers have to paginate
+    pub const MOCK_CANDLES_LIMIT: usize = 500;
 
     impl Default for MockBrokerage {
         fn default() -> Self {
             Self {
                 flat_interest_rate: DEFAULT_HOURLY_INTEREST_RATE,
                 flat_fees: 0.001,
+                prices: HashMap::new(),
+                funding_payments: vec![],
+            }
+        }
+    }
+
+    impl MockBrokerage {
+        /// A mock brokerage quoting `prices` in [`Brokerage::tickers`]
+        #[must_use]
+        pub fn with_prices(prices: HashMap<Pair, f64>) -> Self {
+            Self {
+                prices,
+                ..Self::default()
+            }
+        }
+
+        /// A mock brokerage reporting `funding_payments` in [`Brokerage::funding_payments`]
+        #[must_use]
+        pub fn with_funding_payments(funding_payments: Vec<FundingPayment>) -> Self {
+            Self {
+                funding_payments,
+                ..Self::default()
             }
         }
     }
@@ -114,6 +247,13 @@ mod mock {
     impl Brokerage for MockBrokerage {
         async fn ticker(&self, _pair: Pair) -> Result<Ticker> { unimplemented!() }
 
+        async fn tickers(&self, pairs: Vec<Pair>) -> Result<HashMap<Pair, f64>> {
+            Ok(pairs
+                .into_iter()
+                .filter_map(|pair| self.prices.get(&pair).map(|price| (pair, *price)))
+                .collect())
+        }
+
         async fn orderbook(&self, _pair: Pair) -> Result<Orderbook> { unimplemented!() }
 
         async fn add_order(&self, o: AddOrderRequest) -> Result<OrderSubmission> {
@@ -134,8 +274,41 @@ mod mock {
             Ok(info)
         }
 
+        async fn amend_order(&self, o: AmendOrderRequest) -> Result<OrderSubmission> {
+            Ok(o.acknowledge(Uuid::new_v4().to_string()))
+        }
+
+        async fn cancel_order(&self, id: String, _pair: Pair, _asset_type: AssetType) -> Result<()> {
+            trace!("cancel order : {}", &id);
+            Ok(())
+        }
+
         async fn account_balances(&self) -> Result<AccountPosition> { unimplemented!() }
 
+        async fn cancel_all_after(&self, timeout: std::time::Duration) -> Result<()> {
+            trace!("cancel all orders after : {:?}", timeout);
+            Ok(())
+        }
+
+        async fn fee_tier(&self) -> Result<FeeTier> {
+            Ok(FeeTier {
+                maker: self.flat_fees,
+                taker: self.flat_fees,
+            })
+        }
+
+        async fn borrow(&self, loan: MarginLoanRequest) -> Result<String> {
+            loan.validate()?;
+            trace!("borrow : {:?}", &loan);
+            Ok(Uuid::new_v4().to_string())
+        }
+
+        async fn repay(&self, loan: MarginLoanRequest) -> Result<String> {
+            loan.validate()?;
+            trace!("repay : {:?}", &loan);
+            Ok(Uuid::new_v4().to_string())
+        }
+
         async fn get_order(&self, id: String, _pair: Pair, _asset_type: AssetType) -> Result<Order> {
             trace!("get order : {}", &id);
             let info = Order {
@@ -168,6 +341,69 @@ mod mock {
 
         fn uses_account(&self) -> bool { false }
 
+        /// Everything is supported
+        fn capabilities(&self) -> ExchangeCapabilities {
+            ExchangeCapabilities {
+                order_types: vec![
+                    OrderType::Limit,
+                    OrderType::Market,
+                    OrderType::StopLoss,
+                    OrderType::StopLossLimit,
+                    OrderType::TakeProfit,
+                    OrderType::TakeProfitLimit,
+                    OrderType::LimitMaker,
+                    OrderType::TrailingStop,
+                ],
+                post_only: true,
+                margin: true,
+                oco: true,
+                amend: true,
+                dead_man_switch: Some((1, 86400)),
+                ..ExchangeCapabilities::default()
+            }
+        }
+
+        /// Flat candles at the quoted price of the pair, or 1.0
+        async fn candles(
+            &self,
+            pair: Pair,
+            resolution: Resolution,
+            start: DateTime<Utc>,
+            end: DateTime<Utc>,
+        ) -> Result<Vec<Candle>> {
+            let price = self.prices.get(&pair).copied().unwrap_or(1.0);
+            let mut candles = vec![];
+            let mut start_time = start;
+            while start_time < end && candles.len() < MOCK_CANDLES_LIMIT {
+                let end_time = resolution.add(start_time) - chrono::Duration::milliseconds(1);
+                candles.push(Candle {
+                    event_time: end_time,
+                    pair: pair.clone(),
+                    start_time,
+                    end_time,
+                    open: price,
+                    high: price,
+                    low: price,
+                    close: price,
+                    volume: 1.0,
+                    quote_volume: price,
+                    trade_count: 1,
+                    is_final: true,
+                });
+                start_time = resolution.add(start_time);
+            }
+            Ok(candles)
+        }
+
+        async fn funding_payments(&self, since: DateTime<Utc>) -> Result<Vec<FundingPayment>> {
+            Ok(self
+                .funding_payments
+                .iter()
+                .filter(|payment| payment.time_ms >= since.timestamp_millis())
+                .cloned()
+                .collect())
+        }
+
         #[allow(clippy::cast_sign_loss)]
         async fn margin_interest_rate(&self, symbol: MarketSymbol) -> Result<InterestRate> {
             Ok(InterestRate {
@@ -1,5 +1,7 @@
 use std::convert::Infallible;
+use std::future::Future;
 use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicI64, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 
@@ -14,7 +16,8 @@ use awc::{error::WsProtocolError,
 use backoff::backoff::Backoff;
 use backoff::ExponentialBackoff;
 use bytes::Bytes;
-use chrono::{DateTime, TimeZone, Utc};
+use chrono::{DateTime, Utc};
+use futures::future::{FutureExt, LocalBoxFuture};
 use futures::stream::{SplitSink, StreamExt};
 use futures::Stream;
 use prometheus::default_registry;
@@ -22,9 +25,10 @@ use tokio::time;
 use url::Url;
 
 use crate::error::*;
+use crate::streaming_api::WsReconnectOptions;
 use crate::types::AccountEventEnveloppe;
 
-use super::metrics::{WsCommEvent, WsStreamLifecycleEvent, WsStreamMetrics};
+use super::metrics::{WsCommEvent, WsConnectionState, WsStreamLifecycleEvent, WsStreamMetrics};
 
 pub type WsFramedSink = SplitSink<Framed<BoxedSocket, Codec>, Message>;
 
@@ -35,7 +39,7 @@ pub struct DefaultWsActor {
     pub url: Url,
     pub name: String,
     metrics: WsStreamMetrics,
-    stale_after: Option<Duration>,
+    options: WsReconnectOptions,
     last_msg_at: DateTime<Utc>,
 }
 
@@ -43,12 +47,14 @@ pub struct DefaultWsActor {
 pub trait WsHandler {
     /// Handle incoming messages
     fn handle_in(&self, w: &mut SinkWrite<Message, WsFramedSink>, msg: Bytes);
-    /// Additional actions after the stream has started
+    /// Additional actions after the stream has (re)connected, such as subscribing to channels
     fn handle_started(&self, w: &mut SinkWrite<Message, WsFramedSink>);
     /// Additional actions to be done upon closing the socket
     async fn handle_closed(&self) {}
-    /// An opportunity to make a scheduled action to keep the socket alive
+    /// An opportunity to make a scheduled action to keep the socket alive, the socket restarts if this fails
     async fn handle_keep_alive(&self) -> Result<()> { Ok(()) }
+    /// The url to reconnect to when the socket restarts, if it changed since the socket was created
+    fn url(&self) -> Option<Url> { None }
 }
 
 #[derive(Message)]
@@ -68,6 +74,7 @@ impl Actor for DefaultWsActor {
     fn stopped(&mut self, ctx: &mut Self::Context) {
         info!(name = %self.name, "websocket stopped");
         self.metrics.lifecycle_event(WsStreamLifecycleEvent::Stopped);
+        self.metrics.connection_state(WsConnectionState::Disconnected);
         let handler = self.handler.clone();
         async move { handler.handle_closed().await }.into_actor(self).spawn(ctx);
     }
@@ -75,10 +82,14 @@ impl Actor for DefaultWsActor {
 
 impl actix::Supervised for DefaultWsActor {
     fn restarting(&mut self, ctx: &mut <Self as Actor>::Context) {
+        if let Some(url) = self.handler.url() {
+            self.url = url;
+        }
         let url = self.url.clone();
         let client = new_ws_client(url.to_string());
         info!(name = %self.name, "websocket restarting");
         self.metrics.lifecycle_event(WsStreamLifecycleEvent::Restarting);
+        self.metrics.connection_state(WsConnectionState::Connecting);
         client
             .into_actor(self)
             .map(move |res, act, ctx| match res {
@@ -87,6 +98,7 @@ impl actix::Supervised for DefaultWsActor {
                     let (sink, stream) = client.split();
                     DefaultWsActor::add_stream(stream, ctx);
                     act.conn_backoff.reset();
+                    act.last_msg_at = Utc::now();
                     act.inner = SinkWrite::new(sink, ctx);
                 }
                 Err(err) => {
@@ -104,21 +116,20 @@ impl actix::Supervised for DefaultWsActor {
 }
 
 impl DefaultWsActor {
+    /// Connects to `wss_url`, then reconnects and calls [`WsHandler::handle_started`] again
+    /// whenever the socket closes or stops answering heartbeats
+    ///
     /// # Errors
     ///
     /// If the websocket connection cannot be established
     pub async fn new(
         name: &'static str,
         wss_url: Url,
-        conn_timeout: Option<Duration>,
-        stale_after: Option<Duration>,
+        options: WsReconnectOptions,
         handler: Arc<dyn WsHandler>,
     ) -> Result<Addr<DefaultWsActor>> {
         let name = name.to_string();
-        let mut conn_backoff = ExponentialBackoff {
-            max_elapsed_time: conn_timeout,
-            ..ExponentialBackoff::default()
-        };
+        let mut conn_backoff = options.backoff();
 
         let c;
         loop {
@@ -156,18 +167,17 @@ impl DefaultWsActor {
                 conn_backoff,
                 name: name.clone(),
                 metrics: WsStreamMetrics::for_name(default_registry(), &name),
-                stale_after,
-                last_msg_at: Utc.timestamp_millis_opt(i64::MAX).unwrap(),
+                options,
+                last_msg_at: Utc::now(),
             }
         }))
     }
 
     /// The websocket will be considered 'stale' after
     fn stale_check(&self, ctx: &mut Context<Self>) {
-        if self.stale_after.is_none() {
+        let Some(stale_after) = self.options.stale_after else {
             return;
-        }
-        let stale_after = self.stale_after.unwrap();
+        };
         ctx.run_later(stale_after, move |act, ctx| {
             act.metrics.stale(
                 (act.last_msg_at.timestamp_millis() + stale_after.as_millis() as i64) < Utc::now().timestamp_millis(),
@@ -176,8 +186,24 @@ impl DefaultWsActor {
         });
     }
 
+    /// Whether no frame was received within the heartbeat timeout
+    fn heartbeat_expired(&self) -> bool {
+        self.options.heartbeat_timeout.map_or(false, |timeout| {
+            Utc::now().signed_duration_since(self.last_msg_at) > chrono::Duration::from_std(timeout).unwrap()
+        })
+    }
+
     fn hb(&self, ctx: &mut Context<Self>) {
-        ctx.run_later(Duration::new(25, 0), |act, ctx| {
+        ctx.run_later(self.options.heartbeat_interval, |act, ctx| {
+            if act.heartbeat_expired() {
+                warn!(
+                    name = %act.name, last_msg_at = %act.last_msg_at,
+                    "restarting socket because heartbeats timed out"
+                );
+                act.metrics.comm_event(WsCommEvent::HeartbeatTimeout);
+                ctx.stop();
+                return;
+            }
             if act.inner.write(Message::Ping(Bytes::from_static(b""))).is_err() {
                 act.metrics.comm_event(WsCommEvent::ConnClosed);
             }
@@ -189,9 +215,9 @@ impl DefaultWsActor {
         let handler = self.handler.clone();
         let keep_alive = async move { handler.handle_keep_alive().await }
             .into_actor(self)
-            .map_err(|e, act, _ctx| {
+            .map_err(|e, act, ctx| {
                 error!(name = %act.name, "restarting stocket because it failed to stay alive {}", e);
-                //ctx.stop();
+                ctx.stop();
             })
             .map(|_, _, _| ());
 
@@ -255,18 +281,188 @@ impl StreamHandler<std::result::Result<Frame, WsProtocolError>> for DefaultWsAct
     fn started(&mut self, _ctx: &mut Context<Self>) {
         info!(name = %self.name, "websocket connected");
         self.metrics.lifecycle_event(WsStreamLifecycleEvent::Connected);
-        //self.handler.write().unwrap().handle_started(&mut self.inner);
+        self.metrics.connection_state(WsConnectionState::Connected);
+        self.handler.handle_started(&mut self.inner);
     }
 
     fn finished(&mut self, ctx: &mut Context<Self>) {
         info!(name = %self.name, "websocket finished");
         self.metrics.lifecycle_event(WsStreamLifecycleEvent::Finished);
+        self.metrics.connection_state(WsConnectionState::Disconnected);
         ctx.stop();
     }
 }
 
 impl actix::io::WriteHandler<WsProtocolError> for DefaultWsActor {}
 
+/// Activity of a stream whose socket is managed by its own client, see [`ClientSupervisor`]
+#[derive(Debug)]
+pub struct StreamActivity {
+    last_msg_ms: AtomicI64,
+    active: AtomicBool,
+}
+
+impl StreamActivity {
+    #[must_use]
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            last_msg_ms: AtomicI64::new(Utc::now().timestamp_millis()),
+            active: AtomicBool::new(true),
+        })
+    }
+
+    /// Record that a message was received
+    pub fn touch(&self) { self.last_msg_ms.store(Utc::now().timestamp_millis(), Ordering::Relaxed); }
+
+    /// Whether the client is still the supervised one, the messages of replaced clients must be ignored
+    pub fn is_active(&self) -> bool { self.active.load(Ordering::Relaxed) }
+
+    fn deactivate(&self) { self.active.store(false, Ordering::Relaxed); }
+
+    /// Time elapsed since the last message
+    fn idle(&self) -> Duration {
+        let elapsed = Utc::now().timestamp_millis() - self.last_msg_ms.load(Ordering::Relaxed);
+        Duration::from_millis(u64::try_from(elapsed).unwrap_or(0))
+    }
+}
+
+type ClientConnector<A> = Box<dyn Fn(Arc<StreamActivity>) -> LocalBoxFuture<'static, Result<Addr<A>>>>;
+
+/// Keeps a client actor that manages its own socket, such as a SignalR hub, connected with the same
+/// [`WsReconnectOptions`] as [`DefaultWsActor`] : the client is replaced, with backoff, when it stops or when
+/// it received no message within the heartbeat timeout
+pub struct ClientSupervisor<A: Actor> {
+    connect: ClientConnector<A>,
+    client: Option<(Addr<A>, Arc<StreamActivity>)>,
+    conn_backoff: ExponentialBackoff,
+    options: WsReconnectOptions,
+    name: String,
+    metrics: WsStreamMetrics,
+    reconnecting: bool,
+}
+
+impl<A: Actor> Actor for ClientSupervisor<A> {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        self.metrics.lifecycle_event(WsStreamLifecycleEvent::Started);
+        self.metrics.connection_state(WsConnectionState::Connected);
+        self.hb(ctx);
+        self.stale_check(ctx);
+    }
+
+    fn stopped(&mut self, _ctx: &mut Self::Context) {
+        info!(name = %self.name, "client supervisor stopped");
+        if let Some((_, activity)) = self.client.take() {
+            activity.deactivate();
+        }
+        self.metrics.lifecycle_event(WsStreamLifecycleEvent::Stopped);
+        self.metrics.connection_state(WsConnectionState::Disconnected);
+    }
+}
+
+impl<A: Actor> ClientSupervisor<A> {
+    /// Connects a client with `connect`, which is called again with a new [`StreamActivity`] whenever the client
+    /// has to be replaced
+    ///
+    /// # Errors
+    ///
+    /// If the first client cannot be connected
+    pub async fn start<F, Fut>(name: &'static str, options: WsReconnectOptions, connect: F) -> Result<Addr<Self>>
+    where
+        F: Fn(Arc<StreamActivity>) -> Fut + 'static,
+        Fut: Future<Output = Result<Addr<A>>> + 'static,
+    {
+        let connect: ClientConnector<A> = Box::new(move |activity| connect(activity).boxed_local());
+        let mut conn_backoff = options.backoff();
+        let client = loop {
+            let activity = StreamActivity::new();
+            match connect(activity.clone()).await {
+                Ok(addr) => break (addr, activity),
+                Err(e) => match conn_backoff.next_backoff() {
+                    Some(backoff) => {
+                        info!(name = %name, err = %e, "failed to connect, retrying in {}ms", backoff.as_millis());
+                        time::sleep(backoff).await;
+                    }
+                    None => return Err(Error::BackoffConnectionTimeout(format!("{}", e))),
+                },
+            }
+        };
+        conn_backoff.max_elapsed_time = None;
+        conn_backoff.reset();
+        Ok(ClientSupervisor {
+            connect,
+            client: Some(client),
+            conn_backoff,
+            options,
+            name: name.to_string(),
+            metrics: WsStreamMetrics::for_name(default_registry(), name),
+            reconnecting: false,
+        }
+        .start())
+    }
+
+    /// Whether the client stopped or received no message within the heartbeat timeout
+    fn client_expired(&self) -> bool {
+        self.client.as_ref().map_or(true, |(addr, activity)| {
+            !addr.connected() || self.options.heartbeat_timeout.map_or(false, |timeout| activity.idle() > timeout)
+        })
+    }
+
+    fn stale_check(&self, ctx: &mut Context<Self>) {
+        let Some(stale_after) = self.options.stale_after else {
+            return;
+        };
+        ctx.run_later(stale_after, move |act, ctx| {
+            let stale = act
+                .client
+                .as_ref()
+                .map_or(true, |(_, activity)| activity.idle() > stale_after);
+            act.metrics.stale(stale);
+            act.stale_check(ctx);
+        });
+    }
+
+    fn hb(&self, ctx: &mut Context<Self>) {
+        ctx.run_later(self.options.heartbeat_interval, |act, ctx| {
+            if !act.reconnecting && act.client_expired() {
+                warn!(name = %act.name, "restarting client because heartbeats timed out");
+                act.metrics.comm_event(WsCommEvent::HeartbeatTimeout);
+                act.reconnect(ctx);
+            }
+            act.hb(ctx);
+        });
+    }
+
+    fn reconnect(&mut self, ctx: &mut Context<Self>) {
+        if let Some((_, activity)) = self.client.take() {
+            activity.deactivate();
+        }
+        self.reconnecting = true;
+        self.metrics.lifecycle_event(WsStreamLifecycleEvent::Restarting);
+        self.metrics.connection_state(WsConnectionState::Connecting);
+        let activity = StreamActivity::new();
+        (self.connect)(activity.clone())
+            .into_actor(self)
+            .map(move |res, act, ctx| match res {
+                Ok(addr) => {
+                    info!(name = %act.name, "client reconnected");
+                    act.client = Some((addr, activity));
+                    act.conn_backoff.reset();
+                    act.reconnecting = false;
+                    act.metrics.connection_state(WsConnectionState::Connected);
+                }
+                Err(e) => {
+                    error!(name = %act.name, err = %e, "client failed to connect");
+                    let backoff = act.conn_backoff.next_backoff().unwrap_or(act.options.max_backoff);
+                    act.metrics.conn_backoff(backoff.as_secs_f64());
+                    ctx.run_later(backoff, |act, ctx| act.reconnect(ctx));
+                }
+            })
+            .spawn(ctx);
+    }
+}
+
 #[derive(Message)]
 #[rtype(result = "()")]
 pub struct Ping;
@@ -82,33 +82,53 @@ impl Brokerages {
         plugin.new_private_stream(ctx).await
     }
 
-    /// # Panics
+    /// # Errors
     ///
-    /// will panic if no key is found in the keys file for the exchange
+    /// If the exchange has no accounts, or the credentials are missing
+    pub fn credentials_for(exchange: Exchange, path: PathBuf) -> Result<Box<dyn Credentials>> {
+        Self::credentials_for_account(exchange, None, path)
+    }
+
+    /// Credentials of a named account, keyed as `account_<exchange>_<account>` in the keys file,
+    /// the main account is used if `account` is `None`
     ///
     /// # Errors
     ///
-    /// If the credentials are missing
-    pub fn credentials_for(exchange: Exchange, path: PathBuf) -> Result<Box<dyn Credentials>> {
+    /// If the exchange has no accounts, or the credentials are missing
+    pub fn credentials_for_account(
+        exchange: Exchange,
+        account: Option<&str>,
+        path: PathBuf,
+    ) -> Result<Box<dyn Credentials>> {
         let all_creds = BasicCredentials::new_from_file(path)?;
+        let account_key = Self::account_key(exchange, account)?;
+        all_creds
+            .get(&account_key)
+            .map(|b| dyn_clone::clone_box(b.as_ref()))
+            .ok_or(Error::MissingCredentials(account_key))
+    }
 
-        let account_key = match exchange {
+    fn account_key(exchange: Exchange, account: Option<&str>) -> Result<String> {
+        let main_key = match exchange {
             Exchange::Bitstamp => "account_bitstamp",
             Exchange::Bittrex => "account_bittrex",
             Exchange::Binance => "account_binance",
-            _ => panic!(),
+            Exchange::Okx => "account_okx",
+            Exchange::Bybit => "account_bybit",
+            Exchange::Kraken => "account_kraken",
+            _ => return Err(Error::InvalidExchange(exchange.to_string())),
         };
-        all_creds
-            .get(account_key)
-            .map(|b| dyn_clone::clone_box(b.as_ref()))
-            .ok_or_else(|| Error::MissingCredentials(account_key.to_string()))
+        Ok(match account {
+            Some(name) => format!("{}_{}", main_key, name),
+            None => main_key.to_string(),
+        })
     }
 
     /// # Errors
     ///
     /// If the registry fails to refresh
     pub async fn load_pair_registry(xch: &Exchange, api: &'_ dyn Brokerage) -> Result<()> {
-        refresh_pairs(xch, api).await
+        refresh_pairs(xch, api).await.map(|_| ())
     }
 
     /// # Errors
@@ -124,3 +144,29 @@ impl Brokerages {
         .collect()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::Brokerages;
+    use crate::exchange::Exchange;
+
+    #[test]
+    fn account_keys() {
+        assert_eq!(
+            Brokerages::account_key(Exchange::Binance, None).unwrap(),
+            "account_binance"
+        );
+        assert_eq!(
+            Brokerages::account_key(Exchange::Binance, Some("hedge")).unwrap(),
+            "account_binance_hedge"
+        );
+        assert_eq!(
+            Brokerages::account_key(Exchange::Kraken, None).unwrap(),
+            "account_kraken"
+        );
+        assert!(matches!(
+            Brokerages::account_key(Exchange::Poloniex, None),
+            Err(crate::error::Error::InvalidExchange(_))
+        ));
+    }
+}
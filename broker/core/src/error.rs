@@ -29,6 +29,8 @@ pub enum Error {
     BadParse,
     #[error("Host could not be reached: {0}.")]
     ServiceUnavailable(String),
+    #[error("Exchange is under maintenance.")]
+    ExchangeMaintenance,
     #[error("The informations provided do not allow authentication.")]
     BadCredentials,
     #[error("The credentials could not be found for account {0}")]
@@ -92,6 +94,10 @@ pub enum Error {
     InvalidQty,
     #[error("Invalid price")]
     InvalidPrice,
+    #[error("Invalid iceberg quantity: {0}")]
+    InvalidIcebergQty(String),
+    #[error("Invalid order list: {0}")]
+    InvalidOrderList(String),
     #[error("Not found")]
     NotFound,
     #[error("Unsupported account type")]
@@ -100,6 +106,12 @@ pub enum Error {
     BrokerFeatureNotImplemented,
     #[error("Cannot perform {0} on {1}")]
     InvalidOperation(String, String),
+    #[error("Unsupported by the exchange: {0}")]
+    UnsupportedCapability(String),
+    #[error("Unsupported channel: {0}")]
+    UnsupportedChannel(String),
+    #[error("No api for account {1:?} on {0}")]
+    AccountNotFound(Exchange, Option<String>),
 }
 
 impl PartialEq for Error {
@@ -15,6 +15,10 @@ pub enum Exchange {
     Coinbase,
     #[strum(serialize = "binance")]
     Binance,
+    #[strum(serialize = "okx")]
+    Okx,
+    #[strum(serialize = "bybit")]
+    Bybit,
 }
 
 impl Exchange {
@@ -1,5 +1,6 @@
 use crate::types::{AssetType, OrderType};
 use std::fmt::Debug;
+use std::sync::{Arc, RwLock};
 
 pub struct Fee(pub f64, pub String);
 
@@ -14,8 +15,88 @@ pub struct FlatFeeProvider {
     symbol: String,
 }
 
+impl FlatFeeProvider {
+    pub fn new(flat_fee: f64, symbol: &str) -> Self {
+        Self {
+            flat_fee,
+            symbol: symbol.to_string(),
+        }
+    }
+}
+
 impl FeeProvider for FlatFeeProvider {
     fn get_rate(&self, _asset_type: Option<AssetType>, _order_type: Option<OrderType>) -> Fee {
         Fee(self.flat_fee, self.symbol.clone())
     }
 }
+
+/// Maker and taker fees rates of the tier of an account
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct FeeTier {
+    pub maker: f64,
+    pub taker: f64,
+}
+
+impl FeeTier {
+    /// The maker rate for orders resting in the book, the taker rate otherwise
+    pub fn rate(&self, order_type: Option<OrderType>) -> f64 {
+        if order_type.map_or(false, |o| o.is_maker()) {
+            self.maker
+        } else {
+            self.taker
+        }
+    }
+}
+
+/// Serves the fee tier last fetched from the exchange for the account, and the rates of the exchange
+/// provider until a tier is fetched
+#[derive(Debug)]
+pub struct TieredFeeProvider {
+    fallback: Arc<dyn FeeProvider>,
+    tier: RwLock<Option<FeeTier>>,
+}
+
+impl TieredFeeProvider {
+    pub fn new(fallback: Arc<dyn FeeProvider>) -> Self {
+        Self {
+            fallback,
+            tier: RwLock::new(None),
+        }
+    }
+
+    /// The fee tier fetched from the exchange, if any
+    pub fn tier(&self) -> Option<FeeTier> { *self.tier.read().unwrap() }
+
+    pub fn set_tier(&self, tier: FeeTier) { *self.tier.write().unwrap() = Some(tier); }
+}
+
+impl FeeProvider for TieredFeeProvider {
+    fn get_rate(&self, asset_type: Option<AssetType>, order_type: Option<OrderType>) -> Fee {
+        let fallback = self.fallback.get_rate(asset_type, order_type);
+        match self.tier() {
+            Some(tier) => Fee(tier.rate(order_type), fallback.1),
+            None => fallback,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use crate::fees::{FeeProvider, FeeTier, FlatFeeProvider, TieredFeeProvider};
+    use crate::types::OrderType;
+
+    #[test]
+    fn tiered_rates() {
+        let provider = TieredFeeProvider::new(Arc::new(FlatFeeProvider::new(0.001, "USDT")));
+        assert_eq!(provider.get_rate(None, Some(OrderType::Limit)).0, 0.001);
+        provider.set_tier(FeeTier {
+            maker: 0.0002,
+            taker: 0.0004,
+        });
+        assert_eq!(provider.get_rate(None, Some(OrderType::Limit)).0, 0.0002);
+        assert_eq!(provider.get_rate(None, Some(OrderType::Market)).0, 0.0004);
+        assert_eq!(provider.get_rate(None, None).1, "USDT");
+    }
+}
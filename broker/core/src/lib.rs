@@ -14,6 +14,8 @@
 //! - [x] Bittrex
 //! - [x] Gdax
 //! - [x] Binance
+//! - [x] OKX
+//! - [x] Bybit
 //!
 //! ### N.B.:
 //! - The library expects pair configurations to be loaded in the registry before doing any trading, see PairRegistry
@@ -74,12 +76,14 @@ pub mod error;
 pub mod exchange;
 pub mod fees;
 pub mod json_util;
+pub mod maintenance;
 pub mod manager;
 pub mod margin_interest_rates;
 pub mod metrics;
 pub mod metrics_util;
 pub mod pair;
 pub mod plugin;
+pub mod ratelimit;
 pub mod settings;
 pub mod streaming_api;
 pub mod types;
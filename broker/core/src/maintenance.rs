@@ -0,0 +1,62 @@
+//! Tracks exchanges under maintenance, so that trading can be paused rather than failing requests
+//! being retried until the exchange is back.
+
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+
+use crate::exchange::Exchange;
+
+#[derive(Default, Debug, Clone)]
+pub struct MaintenanceRegistry {
+    /// When the maintenance window is expected to end for each exchange
+    windows: Arc<DashMap<Exchange, DateTime<Utc>>>,
+}
+
+impl MaintenanceRegistry {
+    /// Mark the exchange as under maintenance until `until`, unless a later end was already known
+    pub fn start(&self, xchg: Exchange, until: DateTime<Utc>) {
+        let mut end = self.windows.entry(xchg).or_insert(until);
+        if *end < until {
+            *end = until;
+        }
+    }
+
+    /// Mark the exchange as back from maintenance
+    pub fn end(&self, xchg: Exchange) { self.windows.remove(&xchg); }
+
+    /// Whether the exchange is under maintenance at `at`, windows that have ended are cleared
+    pub fn is_under_maintenance(&self, xchg: Exchange, at: DateTime<Utc>) -> bool {
+        self.windows.remove_if(&xchg, |_, until| *until <= at);
+        self.windows.contains_key(&xchg)
+    }
+
+    /// When the maintenance window of the exchange is expected to end
+    pub fn until(&self, xchg: Exchange) -> Option<DateTime<Utc>> { self.windows.get(&xchg).map(|until| *until) }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::Duration;
+
+    use crate::exchange::Exchange;
+    use crate::maintenance::MaintenanceRegistry;
+
+    #[test]
+    fn maintenance_window() {
+        let registry = MaintenanceRegistry::default();
+        let now = chrono::Utc::now();
+        assert!(!registry.is_under_maintenance(Exchange::Binance, now));
+        registry.start(Exchange::Binance, now + Duration::minutes(5));
+        registry.start(Exchange::Binance, now + Duration::minutes(1));
+        assert_eq!(registry.until(Exchange::Binance), Some(now + Duration::minutes(5)));
+        assert!(registry.is_under_maintenance(Exchange::Binance, now));
+        assert!(!registry.is_under_maintenance(Exchange::Kraken, now));
+        assert!(!registry.is_under_maintenance(Exchange::Binance, now + Duration::minutes(5)));
+        assert_eq!(registry.until(Exchange::Binance), None);
+        registry.start(Exchange::Binance, now + Duration::minutes(5));
+        registry.end(Exchange::Binance);
+        assert!(!registry.is_under_maintenance(Exchange::Binance, now));
+    }
+}
@@ -6,22 +6,32 @@ use std::sync::Arc;
 use crate::api::{Brokerage, MockBrokerage};
 use crate::brokerages::Brokerages;
 use crate::credential::{BasicCredentials, Credentials};
-use crate::error::Result;
+use crate::currency::USDT;
+use crate::error::{Error, Result};
 use crate::exchange::Exchange;
-use crate::fees::FeeProvider;
+use crate::fees::{FeeProvider, FeeTier, FlatFeeProvider, TieredFeeProvider};
+use crate::maintenance::MaintenanceRegistry;
 use crate::plugin::get_exchange_plugin;
+use crate::ratelimit::default_rate_limiter;
 use crate::settings::BrokerSettings;
 use crate::types::{AssetType, OrderType};
 
 pub type BrokerageRegistry = DashMap<Exchange, Arc<dyn Brokerage>>;
-pub type FeesProviderRegistry = DashMap<Exchange, Arc<dyn FeeProvider>>;
+pub type FeesProviderRegistry = DashMap<Exchange, Arc<TieredFeeProvider>>;
+/// Apis of named subaccounts, the main account of each exchange lives in [`BrokerageRegistry`]
+pub type AccountBrokerageRegistry = DashMap<(Exchange, String), Arc<dyn Brokerage>>;
+/// Fee providers of named subaccounts, which have their own fee tier
+pub type AccountFeesProviderRegistry = DashMap<(Exchange, String), Arc<TieredFeeProvider>>;
 
 pub type BrokerageManagerRef = Arc<BrokerageManager>;
 
 #[derive(Default, Debug, Clone)]
 pub struct BrokerageManager {
     exchange_apis: BrokerageRegistry,
+    account_apis: AccountBrokerageRegistry,
     fees_providers: FeesProviderRegistry,
+    account_fees_providers: AccountFeesProviderRegistry,
+    maintenance: MaintenanceRegistry,
 }
 
 impl BrokerageManager {
@@ -32,7 +42,10 @@ impl BrokerageManager {
     pub fn new_with_reg(exchange_apis: BrokerageRegistry) -> Self {
         Self {
             exchange_apis,
+            account_apis: Default::default(),
             fees_providers: Default::default(),
+            account_fees_providers: Default::default(),
+            maintenance: Default::default(),
         }
     }
 
@@ -46,6 +59,26 @@ impl BrokerageManager {
         self.exchange_apis.get(&xchg).map(|v| v.value().clone()).unwrap()
     }
 
+    /// The api of a named account, or the main account api if `account` is `None`
+    #[must_use]
+    pub fn get_account_api(&self, xchg: Exchange, account: Option<&str>) -> Option<Arc<dyn Brokerage>> {
+        match account {
+            None => self.get_api(xchg),
+            Some(name) => self
+                .account_apis
+                .get(&(xchg, name.to_string()))
+                .map(|v| v.value().clone()),
+        }
+    }
+
+    /// # Errors
+    ///
+    /// if no api was built for this account
+    pub fn account_api(&self, xchg: Exchange, account: Option<&str>) -> Result<Arc<dyn Brokerage>> {
+        self.get_account_api(xchg, account)
+            .ok_or_else(|| Error::AccountNotFound(xchg, account.map(ToString::to_string)))
+    }
+
     pub fn remove_api(&mut self, xchg: Exchange) -> Option<Arc<dyn Brokerage>> {
         self.exchange_apis.remove(&xchg).map(|v| v.1)
     }
@@ -53,16 +86,39 @@ impl BrokerageManager {
     #[must_use]
     pub fn exchange_apis(&self) -> &BrokerageRegistry { &self.exchange_apis }
 
+    /// Exchanges currently under maintenance
+    #[must_use]
+    pub fn maintenance(&self) -> &MaintenanceRegistry { &self.maintenance }
+
     /// # Panics
     ///
     /// if any of the exchange apis cannot be built
     pub async fn build_exchange_apis(&self, exchanges: Arc<HashMap<Exchange, BrokerSettings>>, keys_path: PathBuf) {
         for (xch, conf) in exchanges.iter() {
+            for (endpoint, limit) in &conf.rate_limits {
+                default_rate_limiter().register(*xch, endpoint, *limit);
+            }
             let xch_api = self
                 .build_exchange_api(keys_path.clone(), xch, conf.use_test)
                 .await
                 .unwrap();
             self.exchange_apis.insert(*xch, xch_api);
+            // The configured fees are served until the fee tier of the account is fetched, or if it never is
+            let configured_fees = || {
+                Arc::new(TieredFeeProvider::new(Arc::new(FlatFeeProvider::new(
+                    conf.fees, USDT.value,
+                ))))
+            };
+            self.fees_providers.insert(*xch, configured_fees());
+            for account in &conf.accounts {
+                let account_api = self
+                    .build_account_exchange_api(keys_path.clone(), xch, Some(account), conf.use_test)
+                    .await
+                    .unwrap();
+                self.account_apis.insert((*xch, account.clone()), account_api);
+                self.account_fees_providers
+                    .insert((*xch, account.clone()), configured_fees());
+            }
         }
     }
 
@@ -81,7 +137,21 @@ impl BrokerageManager {
         xch: &Exchange,
         use_test_servers: bool,
     ) -> Result<Arc<dyn Brokerage>> {
-        let creds = Brokerages::credentials_for(*xch, keys_path)?;
+        self.build_account_exchange_api(keys_path, xch, None, use_test_servers)
+            .await
+    }
+
+    /// # Errors
+    ///
+    /// if credentials of the account cannot be acquired or the api is not properly configured
+    pub async fn build_account_exchange_api(
+        &self,
+        keys_path: PathBuf,
+        xch: &Exchange,
+        account: Option<&str>,
+        use_test_servers: bool,
+    ) -> Result<Arc<dyn Brokerage>> {
+        let creds = Brokerages::credentials_for_account(*xch, account, keys_path)?;
         self.new_exchange_with_options(*xch, creds, use_test_servers).await
     }
 
@@ -117,10 +187,84 @@ impl BrokerageManager {
 
     pub fn new_fee_provider(&self, exchange: Exchange, conf: serde_json::Value) -> Result<()> {
         let plugin = get_exchange_plugin(exchange)?;
-        self.fees_providers.insert(exchange, plugin.new_fees_provider(conf)?);
+        let provider = plugin.new_fees_provider(conf)?;
+        self.fees_providers
+            .insert(exchange, Arc::new(TieredFeeProvider::new(provider)));
         Ok(())
     }
 
+    #[must_use]
+    pub fn get_fees_provider(&self, exchange: Exchange) -> Option<Arc<dyn FeeProvider>> {
+        self.fees_providers
+            .get(&exchange)
+            .map(|v| v.value().clone() as Arc<dyn FeeProvider>)
+    }
+
+    /// The fee provider of a named account, or of the main account if `account` is `None`
+    #[must_use]
+    pub fn get_account_fees_provider(&self, exchange: Exchange, account: Option<&str>) -> Option<Arc<dyn FeeProvider>> {
+        match account {
+            None => self.get_fees_provider(exchange),
+            Some(name) => self
+                .account_fees_providers
+                .get(&(exchange, name.to_string()))
+                .map(|v| v.value().clone() as Arc<dyn FeeProvider>),
+        }
+    }
+
+    /// Serve fixed maker and taker rates for the exchange instead of those of its fee provider, e.g. the fee schedule
+    /// of a backtest
+    pub fn set_fee_tier(&self, exchange: Exchange, tier: FeeTier) {
+        let provider = TieredFeeProvider::new(Arc::new(FlatFeeProvider::new(tier.taker, USDT.value)));
+        provider.set_tier(tier);
+        self.fees_providers.insert(exchange, Arc::new(provider));
+    }
+
+    /// Fetch the fee tier of the account from the exchange, its rates are served by the fee provider from then on
+    ///
+    /// # Errors
+    ///
+    /// If the exchange has no api or fee provider, or the fee tier cannot be fetched
+    pub async fn refresh_fee_tier(&self, exchange: Exchange) -> Result<FeeTier> {
+        self.refresh_account_fee_tier(exchange, None).await
+    }
+
+    /// Fetch the fee tier of a named account, or of the main account if `account` is `None`
+    ///
+    /// # Errors
+    ///
+    /// If the account has no api or fee provider, or the fee tier cannot be fetched
+    pub async fn refresh_account_fee_tier(&self, exchange: Exchange, account: Option<&str>) -> Result<FeeTier> {
+        let api = self.account_api(exchange, account)?;
+        let provider = match account {
+            None => self.fees_providers.get(&exchange).map(|v| v.value().clone()),
+            Some(name) => self
+                .account_fees_providers
+                .get(&(exchange, name.to_string()))
+                .map(|v| v.value().clone()),
+        }
+        .ok_or(Error::BrokerNotLoaded)?;
+        let tier = api.fee_tier().await?;
+        provider.set_tier(tier);
+        Ok(tier)
+    }
+
+    /// Fetch the fee tiers of the main account and of every named account of the exchange
+    pub async fn refresh_fee_tiers(&self, exchange: Exchange) -> Vec<(Option<String>, Result<FeeTier>)> {
+        let accounts: Vec<String> = self
+            .account_fees_providers
+            .iter()
+            .filter(|e| e.key().0 == exchange)
+            .map(|e| e.key().1.clone())
+            .collect();
+        let mut tiers = vec![(None, self.refresh_fee_tier(exchange).await)];
+        for account in accounts {
+            let tier = self.refresh_account_fee_tier(exchange, Some(&account)).await;
+            tiers.push((Some(account), tier));
+        }
+        tiers
+    }
+
     pub fn get_fees_rate(
         &self,
         exchange: Exchange,
@@ -132,3 +276,62 @@ impl BrokerageManager {
             .map(|v| v.value().get_rate(asset_type, order_type).0)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use crate::api::MockBrokerage;
+    use crate::exchange::Exchange;
+    use crate::fees::{FeeProvider, FlatFeeProvider, TieredFeeProvider};
+    use crate::manager::BrokerageManager;
+    use crate::types::OrderType;
+
+    #[tokio::test]
+    async fn refresh_fee_tier() {
+        let manager = BrokerageManager::new();
+        manager.build_mock_exchange_apis(&[Exchange::Binance]);
+        assert!(manager.refresh_fee_tier(Exchange::Binance).await.is_err());
+        manager.fees_providers.insert(
+            Exchange::Binance,
+            Arc::new(TieredFeeProvider::new(Arc::new(FlatFeeProvider::new(0.002, "USDT")))),
+        );
+        assert_eq!(
+            manager.get_fees_rate(Exchange::Binance, None, Some(OrderType::Market)),
+            Some(0.002)
+        );
+        let tier = manager.refresh_fee_tier(Exchange::Binance).await.unwrap();
+        assert_eq!(
+            manager.get_fees_rate(Exchange::Binance, None, Some(OrderType::Market)),
+            Some(tier.taker)
+        );
+    }
+
+    #[tokio::test]
+    async fn refresh_account_fee_tiers() {
+        let manager = BrokerageManager::new();
+        manager.build_mock_exchange_apis(&[Exchange::Binance]);
+        let configured = || Arc::new(TieredFeeProvider::new(Arc::new(FlatFeeProvider::new(0.002, "USDT"))));
+        manager.fees_providers.insert(Exchange::Binance, configured());
+        manager.account_apis.insert(
+            (Exchange::Binance, "sub".to_string()),
+            Arc::new(MockBrokerage::default()),
+        );
+        manager
+            .account_fees_providers
+            .insert((Exchange::Binance, "sub".to_string()), configured());
+        let account_rate = || {
+            manager
+                .get_account_fees_provider(Exchange::Binance, Some("sub"))
+                .unwrap()
+                .get_rate(None, Some(OrderType::Market))
+                .0
+        };
+        assert_eq!(account_rate(), 0.002);
+        let tiers = manager.refresh_fee_tiers(Exchange::Binance).await;
+        assert_eq!(tiers.len(), 2);
+        assert_eq!(tiers[1].0.as_deref(), Some("sub"));
+        let tier = tiers[1].1.as_ref().unwrap();
+        assert_eq!(account_rate(), tier.taker);
+    }
+}
@@ -23,6 +23,8 @@ pub struct ExchangeMetrics {
     low_ask: GaugeVec,
     in_unsupported_pair: CounterVec,
     subscription_failures: IntCounterVec,
+    checksum_mismatches: IntCounterVec,
+    candle_gaps: IntCounterVec,
 }
 
 impl ExchangeMetrics {
@@ -59,6 +61,24 @@ impl ExchangeMetrics {
             labels
         )
         .unwrap();
+        let checksum_mismatch_vec = register_int_counter_vec!(
+            opts!(
+                "orderbook_checksum_mismatches",
+                "Total number of times a streamed order book failed its checksum and was snapshotted again.",
+                const_labels
+            ),
+            labels
+        )
+        .unwrap();
+        let candle_gap_vec = register_int_counter_vec!(
+            opts!(
+                "candle_gaps",
+                "Total number of candles missed by the stream and fetched again.",
+                const_labels
+            ),
+            labels
+        )
+        .unwrap();
         let stream_reconnect_vec = register_counter_vec!(
             opts!(
                 "stream_reconnects",
@@ -92,6 +112,8 @@ impl ExchangeMetrics {
             low_ask: low_ask_vec,
             in_unsupported_pair,
             subscription_failures: subscription_failure_vec,
+            checksum_mismatches: checksum_mismatch_vec,
+            candle_gaps: candle_gap_vec,
         }
     }
 
@@ -111,6 +133,14 @@ impl ExchangeMetrics {
         self.in_unsupported_pair.with_label_values(&[pair, channel]).inc();
     }
 
+    pub fn checksum_mismatch(&self, pair: &str, channel: &str) {
+        self.checksum_mismatches.with_label_values(&[pair, channel]).inc();
+    }
+
+    pub fn candle_gap(&self, pair: &str, channel: &str, missing: u64) {
+        self.candle_gaps.with_label_values(&[pair, channel]).inc_by(missing);
+    }
+
     pub fn stream_reconnected(&self) { self.stream_reconnects.with_label_values(&[]).inc(); }
 
     pub fn top_bid(&self, price: f64, _volume: f64, pair: &str, channel: &str) {
@@ -166,16 +196,27 @@ pub enum WsCommEvent {
     CloseRecv,
     #[strum(serialize = "conn_closed")]
     ConnClosed,
+    #[strum(serialize = "heartbeat_timeout")]
+    HeartbeatTimeout,
     #[strum(serialize = "unhandled_recv")]
     Unhandled,
 }
 
+/// Connection state of a websocket, reported as the value of the `connection_state` gauge
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WsConnectionState {
+    Disconnected = 0,
+    Connecting = 1,
+    Connected = 2,
+}
+
 #[derive(Clone)]
 pub struct WsStreamMetrics {
     counters: CounterVec,
     comm_counters: CounterVec,
     backoff_gauge: GaugeVec,
     stale_gauge: GaugeVec,
+    state_gauge: GaugeVec,
 }
 
 static WS_LIFECYCLE_EVENT: &str = "ws_lifecycle_event";
@@ -206,11 +247,19 @@ impl WsStreamMetrics {
         .const_label("name", name);
         let stale_gauge = GaugeVec::new(opts, &[]).unwrap();
         registry.register(Box::new(stale_gauge.clone())).unwrap();
+        let opts = Opts::new(
+            "connection_state",
+            "Connection state of the websocket : 0 disconnected, 1 connecting, 2 connected",
+        )
+        .const_label("name", name);
+        let state_gauge = GaugeVec::new(opts, &[]).unwrap();
+        registry.register(Box::new(state_gauge.clone())).unwrap();
         WsStreamMetrics {
             counters: WsStreamMetrics::counter_for(registry, WS_LIFECYCLE_EVENT, name),
             comm_counters: WsStreamMetrics::counter_for(registry, WS_COMM_EVENT, name),
             backoff_gauge,
             stale_gauge,
+            state_gauge,
         }
     }
 
@@ -229,6 +278,10 @@ impl WsStreamMetrics {
 
     pub(super) fn conn_backoff(&self, time: f64) { self.backoff_gauge.with_label_values(&[]).set(time); }
 
+    pub(super) fn connection_state(&self, state: WsConnectionState) {
+        self.state_gauge.with_label_values(&[]).set(f64::from(state as u8));
+    }
+
     pub(super) fn stale(&self, is_stale: bool) {
         self.stale_gauge
             .with_label_values(&[])
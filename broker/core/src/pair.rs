@@ -65,6 +65,62 @@ pub struct PairConf {
     pub isolated_margin_allowed: bool,
 }
 
+impl PairConf {
+    /// Whether the trading rules differ from `other`, such as the tick size or the lot size
+    #[allow(clippy::float_cmp)]
+    pub fn rules_differ(&self, other: &PairConf) -> bool {
+        self.min_price != other.min_price
+            || self.max_price != other.max_price
+            || self.step_price != other.step_price
+            || self.min_qty != other.min_qty
+            || self.max_qty != other.max_qty
+            || self.step_qty != other.step_qty
+            || self.min_market_qty != other.min_market_qty
+            || self.max_market_qty != other.max_market_qty
+            || self.step_market_qty != other.step_market_qty
+            || self.min_size != other.min_size
+            || self.base_precision != other.base_precision
+            || self.quote_precision != other.quote_precision
+            || self.spot_allowed != other.spot_allowed
+            || self.cross_margin_allowed != other.cross_margin_allowed
+            || self.isolated_margin_allowed != other.isolated_margin_allowed
+    }
+}
+
+/// Differences between the pairs registered for an exchange and the pairs it lists after a refresh
+#[derive(Clone, Debug, PartialEq, actix::Message)]
+#[rtype(result = "()")]
+pub struct PairConfChanges {
+    pub xchg: Exchange,
+    /// Pairs listed since the previous refresh
+    pub listed: Vec<PairConf>,
+    /// Pairs no longer listed, with their last configuration
+    pub delisted: Vec<PairConf>,
+    /// Pairs whose trading rules changed, with their new configuration
+    pub updated: Vec<PairConf>,
+}
+
+impl PairConfChanges {
+    pub fn new(xchg: Exchange) -> Self {
+        Self {
+            xchg,
+            listed: vec![],
+            delisted: vec![],
+            updated: vec![],
+        }
+    }
+
+    pub fn is_empty(&self) -> bool { self.listed.is_empty() && self.delisted.is_empty() && self.updated.is_empty() }
+
+    /// Whether orders on `pair` may no longer follow the registered trading rules
+    pub fn affects(&self, pair: &Pair) -> bool {
+        self.delisted
+            .iter()
+            .chain(self.updated.iter())
+            .any(|conf| &conf.pair == pair)
+    }
+}
+
 impl Hash for PairConf {
     fn hash<H: Hasher>(&self, state: &mut H) { self.symbol.hash(state); }
 }
@@ -92,12 +148,15 @@ impl Borrow<MarketSymbol> for PairConf {
 #[derive(Clone, Debug)]
 pub struct PairRegistry {
     pairs: Arc<DashMap<Exchange, BiMap<Pair, PairConf>>>,
+    /// Pairs registered manually, which are kept when the exchange no longer lists them
+    manual: Arc<DashMap<Exchange, HashSet<Pair>>>,
 }
 
 impl Default for PairRegistry {
     fn default() -> Self {
         PairRegistry {
             pairs: Arc::new(DashMap::new()),
+            manual: Arc::new(DashMap::new()),
         }
     }
 }
@@ -172,12 +231,48 @@ impl PairRegistry {
             })
     }
 
+    /// Merge the pairs listed by the exchange into the registered pairs, returning how they differ from the pairs
+    /// registered until now
+    ///
+    /// Pairs no longer listed are removed, unless they were registered manually
+    pub fn refresh(&self, xchg: Exchange, pairs: Vec<PairConf>) -> PairConfChanges {
+        let mut changes = PairConfChanges::new(xchg);
+        let manual = self.manual.get(&xchg).map(|manual| manual.clone()).unwrap_or_default();
+        let mut registered = self.pairs.entry(xchg).or_default();
+        for conf in &pairs {
+            match registered.get_by_left(&conf.pair) {
+                None => changes.listed.push(conf.clone()),
+                Some(previous) if previous.rules_differ(conf) => changes.updated.push(conf.clone()),
+                Some(_) => {}
+            }
+        }
+        let listed: HashSet<&Pair> = pairs.iter().map(|conf| &conf.pair).collect();
+        changes.delisted = registered
+            .right_values()
+            .filter(|conf| !listed.contains(&conf.pair) && !manual.contains(&conf.pair))
+            .cloned()
+            .collect();
+        for conf in &changes.delisted {
+            registered.remove_by_left(&conf.pair);
+        }
+        for conf in pairs {
+            registered.insert(conf.pair.clone(), conf);
+        }
+        changes
+    }
+
+    /// Register a pair alongside the registered pairs of the exchange, and keep it across refreshes
+    pub fn register_manual(&self, xchg: Exchange, conf: PairConf) {
+        self.manual.entry(xchg).or_default().insert(conf.pair.clone());
+        self.pairs.entry(xchg).or_default().insert(conf.pair.clone(), conf);
+    }
+
     pub fn register_pair(&self, xchg: &Exchange, pair: Pair, symbol: MarketSymbol) {
-        self.register(*xchg, vec![PairConf {
+        self.register_manual(*xchg, PairConf {
             symbol,
             pair,
             ..PairConf::default()
-        }]);
+        });
     }
 }
 
@@ -211,11 +306,10 @@ pub fn filter_pairs(xchg: &Exchange, expressions: &[String]) -> Result<HashSet<P
     default_pair_registry().filter_pairs(xchg, expressions)
 }
 
-/// Refresh registry pairs using the provided brokerage
-pub async fn refresh_pairs(xchg: &Exchange, api: &'_ dyn Brokerage) -> Result<()> {
+/// Refresh registry pairs using the provided brokerage, returning the pairs that changed
+pub async fn refresh_pairs(xchg: &Exchange, api: &'_ dyn Brokerage) -> Result<PairConfChanges> {
     let pair_confs = api.pairs().await?;
-    default_pair_registry().register(*xchg, pair_confs);
-    Ok(())
+    Ok(default_pair_registry().refresh(*xchg, pair_confs))
 }
 
 /// Manually register a market pair with a market symbol
@@ -267,7 +361,7 @@ pub fn register_pair_default(xch: Exchange, symbol: &str, pair: &str) {
         quote: quote.to_string(),
         ..PairConf::default()
     };
-    default_pair_registry().register(xch, vec![conf]);
+    default_pair_registry().register_manual(xch, conf);
 }
 
 #[cfg(test)]
@@ -301,6 +395,62 @@ mod test {
         );
     }
 
+    #[test]
+    fn refresh_detects_changes() {
+        let registry = PairRegistry::default();
+        let exchange = Exchange::Binance;
+        let conf = |symbol: &str, pair: &str, step_price: f64| PairConf {
+            symbol: symbol.into(),
+            pair: pair.into(),
+            step_price: Some(step_price),
+            ..PairConf::default()
+        };
+        let changes = registry.refresh(exchange, vec![conf("BTCUSDT", "BTC_USDT", 0.01)]);
+        assert_eq!(changes.listed.len(), 1);
+        registry.refresh(exchange, vec![
+            conf("BTCUSDT", "BTC_USDT", 0.01),
+            conf("LUNAUSDT", "LUNA_USDT", 0.001),
+        ]);
+        let changes = registry.refresh(exchange, vec![
+            conf("BTCUSDT", "BTC_USDT", 0.1),
+            conf("ETHUSDT", "ETH_USDT", 0.01),
+        ]);
+        assert_eq!(changes.listed, vec![conf("ETHUSDT", "ETH_USDT", 0.01)]);
+        assert_eq!(changes.delisted, vec![conf("LUNAUSDT", "LUNA_USDT", 0.001)]);
+        assert_eq!(changes.updated, vec![conf("BTCUSDT", "BTC_USDT", 0.1)]);
+        assert!(changes.affects(&"BTC_USDT".into()));
+        assert!(!changes.affects(&"ETH_USDT".into()));
+        assert_eq!(
+            registry.pair_conf(&exchange, &"BTC_USDT".into()).unwrap().step_price,
+            Some(0.1)
+        );
+        assert!(registry
+            .refresh(exchange, vec![
+                conf("BTCUSDT", "BTC_USDT", 0.1),
+                conf("ETHUSDT", "ETH_USDT", 0.01)
+            ])
+            .is_empty());
+    }
+
+    #[test]
+    fn refresh_keeps_manual_pairs() {
+        let registry = PairRegistry::default();
+        let exchange = Exchange::Binance;
+        let conf = |symbol: &str, pair: &str| PairConf {
+            symbol: symbol.into(),
+            pair: pair.into(),
+            ..PairConf::default()
+        };
+        registry.register_pair(&exchange, "CUSTOM_USDT".into(), "CUSTOMUSDT".into());
+        let changes = registry.refresh(exchange, vec![conf("BTCUSDT", "BTC_USDT")]);
+        assert_eq!(changes.listed, vec![conf("BTCUSDT", "BTC_USDT")]);
+        let changes = registry.refresh(exchange, vec![conf("ETHUSDT", "ETH_USDT")]);
+        assert_eq!(changes.delisted, vec![conf("BTCUSDT", "BTC_USDT")]);
+        assert!(registry.pair_conf(&exchange, &"CUSTOM_USDT".into()).is_ok());
+        assert!(registry.pair_conf(&exchange, &"ETH_USDT".into()).is_ok());
+        assert!(registry.pair_conf(&exchange, &"BTC_USDT".into()).is_err());
+    }
+
     #[bench]
     fn pair_conf_bench(b: &mut Bencher) {
         let registry = PairRegistry::default();
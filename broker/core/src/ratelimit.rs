@@ -0,0 +1,139 @@
+//! Request budgets of exchanges, shared by every api and stream of the process so that they all draw from
+//! the same limits as the exchange does.
+
+use std::num::NonZeroU32;
+use std::sync::Arc;
+use std::time::Duration;
+
+use dashmap::DashMap;
+use governor::{DefaultDirectRateLimiter, Quota, RateLimiter};
+use prometheus::{CounterVec, GaugeVec};
+
+use crate::error::*;
+use crate::exchange::Exchange;
+use crate::types::RateLimit;
+
+lazy_static! {
+    static ref DEFAULT_RATE_LIMITER_REGISTRY: RateLimiterRegistry = { RateLimiterRegistry::default() };
+    static ref QUEUED_GAUGE: GaugeVec = register_gauge_vec!(
+        opts!("rate_limit_queued", "Requests waiting for their rate limit budget."),
+        &["xchg", "endpoint"]
+    )
+    .unwrap();
+    static ref DROPPED_COUNTER: CounterVec = register_counter_vec!(
+        opts!("rate_limit_dropped", "Requests heavier than their rate limit budget."),
+        &["xchg", "endpoint"]
+    )
+    .unwrap();
+}
+
+/// Default registry (global static).
+#[must_use]
+pub fn default_rate_limiter() -> &'static RateLimiterRegistry {
+    lazy_static::initialize(&DEFAULT_RATE_LIMITER_REGISTRY);
+    &DEFAULT_RATE_LIMITER_REGISTRY
+}
+
+/// A token bucket refilled with `limit.requests` tokens over `limit.interval_ms`, holding at most `limit.requests`
+fn quota(limit: RateLimit) -> Option<Quota> {
+    let burst = NonZeroU32::new(limit.requests)?;
+    let period = Duration::from_micros(limit.interval_ms * 1000 / u64::from(limit.requests));
+    Quota::with_period(period).map(|q| q.allow_burst(burst))
+}
+
+/// Weighted token buckets for each endpoint of an exchange, endpoints without a bucket are not limited
+#[derive(Clone, Debug, Default)]
+pub struct RateLimiterRegistry {
+    limiters: Arc<DashMap<(Exchange, String), Arc<DefaultDirectRateLimiter>>>,
+}
+
+impl RateLimiterRegistry {
+    #[must_use]
+    pub fn new() -> Self { Self::default() }
+
+    /// Set the budget of an endpoint, replacing the current one
+    pub fn register(&self, xchg: Exchange, endpoint: &str, limit: RateLimit) {
+        match quota(limit) {
+            Some(quota) => {
+                self.limiters
+                    .insert((xchg, endpoint.to_string()), Arc::new(RateLimiter::direct(quota)));
+            }
+            None => warn!(xchg = %xchg, endpoint = %endpoint, limit = ?limit, "invalid rate limit"),
+        }
+    }
+
+    /// Set the budget of an endpoint unless it was already configured
+    pub fn register_default(&self, xchg: Exchange, endpoint: &str, limit: RateLimit) {
+        if !self.limiters.contains_key(&(xchg, endpoint.to_string())) {
+            self.register(xchg, endpoint, limit);
+        }
+    }
+
+    /// Wait until `weight` tokens are available for the endpoint
+    ///
+    /// # Errors
+    ///
+    /// If the weight is greater than the whole budget of the endpoint, the request could never be sent
+    pub async fn acquire(&self, xchg: Exchange, endpoint: &str, weight: u32) -> Result<()> {
+        let Some(limiter) = self
+            .limiters
+            .get(&(xchg, endpoint.to_string()))
+            .map(|l| l.value().clone())
+        else {
+            return Ok(());
+        };
+        let Some(weight) = NonZeroU32::new(weight) else {
+            return Ok(());
+        };
+        let labels = [xchg.as_ref(), endpoint];
+        match limiter.check_n(weight) {
+            Ok(Ok(())) => Ok(()),
+            Ok(Err(_)) => {
+                let queued = QUEUED_GAUGE.with_label_values(&labels);
+                queued.inc();
+                let ready = limiter.until_n_ready(weight).await;
+                queued.dec();
+                ready.map_err(|_| Error::RateLimitExceeded)
+            }
+            Err(_) => {
+                DROPPED_COUNTER.with_label_values(&labels).inc();
+                Err(Error::RateLimitExceeded)
+            }
+        }
+    }
+}
+
+/// # Errors
+///
+/// See [`RateLimiterRegistry::acquire`]
+pub async fn acquire(xchg: Exchange, endpoint: &str, weight: u32) -> Result<()> {
+    default_rate_limiter().acquire(xchg, endpoint, weight).await
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Instant;
+
+    use super::RateLimiterRegistry;
+    use crate::error::Error;
+    use crate::exchange::Exchange;
+    use crate::types::RateLimit;
+
+    #[tokio::test]
+    async fn should_wait_for_the_budget_to_refill() {
+        let registry = RateLimiterRegistry::new();
+        registry.register(Exchange::Binance, "weight", RateLimit::new(10, 100));
+        registry.register_default(Exchange::Binance, "weight", RateLimit::new(1000, 100));
+        let start = Instant::now();
+        registry.acquire(Exchange::Binance, "weight", 10).await.unwrap();
+        assert!(start.elapsed().as_millis() < 50);
+        registry.acquire(Exchange::Binance, "weight", 5).await.unwrap();
+        assert!(start.elapsed().as_millis() >= 40);
+        assert_eq!(
+            registry.acquire(Exchange::Binance, "weight", 11).await,
+            Err(Error::RateLimitExceeded)
+        );
+        // Endpoints without a budget are not limited
+        registry.acquire(Exchange::Kraken, "weight", 1000).await.unwrap();
+    }
+}
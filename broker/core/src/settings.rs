@@ -1,11 +1,45 @@
-use crate::types::MarketChannel;
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::types::{MarketChannel, RateLimit};
 
 fn default_as_false() -> bool { false }
 
+fn default_dead_man_switch_timeout_secs() -> u64 { 60 }
+
+fn default_fee_tier_refresh_secs() -> u64 { 3600 }
+
+fn default_pairs_refresh_secs() -> u64 { 3600 }
+
+/// Exchange side cancellation of every resting order once a timer that the server keeps refreshing expires,
+/// see [`crate::api::Brokerage::cancel_all_after`]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DeadManSwitchSettings {
+    #[serde(default = "default_as_false")]
+    pub enabled: bool,
+    /// Resting orders are canceled this many seconds after the last refresh
+    #[serde(default = "default_dead_man_switch_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+impl DeadManSwitchSettings {
+    pub fn timeout(&self) -> Duration { Duration::from_secs(self.timeout_secs) }
+}
+
+impl Default for DeadManSwitchSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            timeout_secs: default_dead_man_switch_timeout_secs(),
+        }
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[allow(clippy::struct_excessive_bools)]
 pub struct BrokerSettings {
     pub market_channels: Vec<MarketChannel>,
+    /// Fees rate used until the fee tier of the account is fetched, or if the exchange has no fee provider
     pub fees: f64,
     pub use_account: bool,
     pub use_margin_account: bool,
@@ -13,6 +47,21 @@ pub struct BrokerSettings {
     pub isolated_margin_account_pairs: Vec<String>,
     #[serde(default = "default_as_false")]
     pub use_test: bool,
+    /// Overrides the default request budget of endpoints, see [`crate::ratelimit`]
+    #[serde(default)]
+    pub rate_limits: HashMap<String, RateLimit>,
+    /// Named subaccounts traded alongside the main account, see [`crate::brokerages::Brokerages::credentials_for_account`]
+    #[serde(default)]
+    pub accounts: Vec<String>,
+    /// Cancel resting orders exchange side if the server stops refreshing the timer, disabled by default
+    #[serde(default)]
+    pub dead_man_switch: DeadManSwitchSettings,
+    /// How often the fee tier of the account is fetched from the exchange, never if zero
+    #[serde(default = "default_fee_tier_refresh_secs")]
+    pub fee_tier_refresh_secs: u64,
+    /// How often the pairs and their trading rules are fetched from the exchange after boot, never if zero
+    #[serde(default = "default_pairs_refresh_secs")]
+    pub pairs_refresh_secs: u64,
 }
 
 impl BrokerSettings {
@@ -25,6 +74,11 @@ impl BrokerSettings {
             use_test: true,
             use_isolated_margin_account: true,
             isolated_margin_account_pairs: vec![],
+            rate_limits: HashMap::new(),
+            accounts: vec![],
+            dead_man_switch: DeadManSwitchSettings::default(),
+            fee_tier_refresh_secs: default_fee_tier_refresh_secs(),
+            pairs_refresh_secs: default_pairs_refresh_secs(),
         }
     }
 }
@@ -1,7 +1,23 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use actix::io::SinkWrite;
+use awc::ws::{CloseCode, CloseReason, Message};
+use backoff::ExponentialBackoff;
+use chrono::{DateTime, Utc};
+use dashmap::mapref::entry::Entry;
+use dashmap::DashMap;
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::bot::WsFramedSink;
+use crate::broker::MarketEventEnvelopeRef;
 use crate::error::*;
 use crate::exchange::Exchange;
+use crate::metrics::ExchangeMetrics;
 use crate::pair::symbol_to_pair;
-use crate::types::{MarketSymbol, Pair};
+use crate::types::{Candle, LiveAggregatedOrderBook, MarketEvent, MarketEventEnvelope, MarketSymbol, Orderbook, Pair,
+                   SecurityType, Symbol};
 
 pub trait StreamingApi {
     const NAME: &'static str;
@@ -12,3 +28,221 @@ pub trait StreamingApi {
     /// if the pair cannot be converted
     fn get_pair(&self, symbol: &str) -> Result<Pair> { symbol_to_pair(&Self::EXCHANGE, &MarketSymbol::from(symbol)) }
 }
+
+/// A stream that sends the market events it decodes to its subscribers
+pub trait MarketEventStream: StreamingApi {
+    fn sink(&self) -> &UnboundedSender<MarketEventEnvelopeRef>;
+
+    fn metrics(&self) -> &ExchangeMetrics;
+
+    /// Send a market event to the subscribers of the stream
+    fn broadcast(&self, v: MarketEvent) {
+        let (pair, channel) = (&v.pair(), v.chan());
+        self.metrics().event_broadcasted(pair, channel);
+        let msg = Arc::new(MarketEventEnvelope::new(
+            Symbol::new(pair.clone(), SecurityType::Crypto, Self::EXCHANGE),
+            v,
+        ));
+        if let Err(e) = self.sink().send(msg) {
+            self.metrics()
+                .broadcast_failure(e.0.symbol.value.as_ref(), e.0.e.chan());
+        }
+    }
+
+    /// Record the top of a book that changed in the metrics
+    fn log_book(&self, ob: Orderbook) -> Orderbook {
+        if let Some(lowest_ask) = ob.asks.first() {
+            self.metrics()
+                .lowest_ask(lowest_ask.0, lowest_ask.1, &ob.pair, "order_books");
+        }
+        if let Some(highest_bid) = ob.bids.first() {
+            self.metrics()
+                .top_bid(highest_bid.0, highest_bid.1, &ob.pair, "order_books");
+        }
+        ob
+    }
+}
+
+/// A stream that aggregates order book updates into live books
+pub trait LiveBookStream: MarketEventStream {
+    fn books(&self) -> &DashMap<Pair, LiveAggregatedOrderBook>;
+
+    /// Depth of the books by pair, books of other pairs have the default depth
+    fn orderbook_depths(&self) -> &HashMap<Pair, u16>;
+
+    /// Update the live book of `pair` with `f`, the book is created if it didn't exist
+    ///
+    /// returns: the book if it changed
+    fn latest_book<F>(&self, pair: &Pair, f: F) -> Option<Orderbook>
+    where
+        F: Fn(&mut LiveAggregatedOrderBook),
+    {
+        let mut agg = self.books().entry(pair.clone()).or_insert_with(|| {
+            LiveAggregatedOrderBook::default_with_depth(pair.clone(), self.orderbook_depths().get(pair).copied())
+        });
+        f(&mut agg);
+        agg.latest_order_book().map(|b| self.log_book(b))
+    }
+}
+
+/// How a websocket stream reconnects and checks that it is still alive, see [`crate::bot::DefaultWsActor`]
+#[derive(typed_builder::TypedBuilder, Clone, Debug)]
+pub struct WsReconnectOptions {
+    /// Give up on the first connection after this duration, retries forever if not set
+    #[builder(default, setter(strip_option))]
+    pub conn_timeout: Option<Duration>,
+    /// Delay before the first reconnection attempt, doubled on every failure
+    #[builder(default = Duration::from_millis(500))]
+    pub initial_backoff: Duration,
+    /// Upper bound of the delay between two reconnection attempts
+    #[builder(default = Duration::from_secs(60))]
+    pub max_backoff: Duration,
+    /// Interval between two pings sent to the server
+    #[builder(default = Duration::from_secs(25))]
+    pub heartbeat_interval: Duration,
+    /// The socket restarts if no frame, including pongs, was received for this long
+    #[builder(default = Some(Duration::from_secs(90)), setter(strip_option))]
+    pub heartbeat_timeout: Option<Duration>,
+    /// The socket is reported as stale if no frame was received for this long
+    #[builder(default, setter(strip_option))]
+    pub stale_after: Option<Duration>,
+}
+
+impl Default for WsReconnectOptions {
+    fn default() -> Self { Self::builder().build() }
+}
+
+impl WsReconnectOptions {
+    /// The backoff used to space out connection attempts
+    #[must_use]
+    pub fn backoff(&self) -> ExponentialBackoff {
+        ExponentialBackoff {
+            initial_interval: self.initial_backoff,
+            current_interval: self.initial_backoff,
+            max_interval: self.max_backoff,
+            max_elapsed_time: self.conn_timeout,
+            ..ExponentialBackoff::default()
+        }
+    }
+}
+
+/// Closes the socket so that it reconnects and subscribes again, for servers that ask clients to reconnect
+///
+/// # Errors
+///
+/// If the close frame cannot be written to the socket
+pub fn request_reconnect(w: &mut SinkWrite<Message, WsFramedSink>) -> Result<()> {
+    w.write(Message::Close(Some(CloseReason::from(CloseCode::Restart))))
+        .map_err(|_| Error::WsError("failed to request a reconnection".to_string()))
+}
+
+/// Candles missed by a stream, between the last candle received and the current one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CandleGap {
+    pub pair: Pair,
+    /// Start time of the first missing candle
+    pub from: DateTime<Utc>,
+    /// Start time of the candle received after the gap, excluded
+    pub to: DateTime<Utc>,
+    /// Duration of a candle
+    pub interval: chrono::Duration,
+}
+
+impl CandleGap {
+    /// Number of missing candles
+    #[must_use]
+    pub fn missing(&self) -> i64 { (self.to - self.from).num_milliseconds() / self.interval.num_milliseconds() }
+}
+
+/// Compares the start time of streamed candles with the previous candle of the same pair and interval,
+/// so that candles lost while a socket was disconnected can be fetched again
+#[derive(Debug, Default)]
+pub struct CandleGapDetector {
+    last_starts: DashMap<(Pair, i64), DateTime<Utc>>,
+}
+
+impl CandleGapDetector {
+    /// Returns the missing candles if `candle` does not follow the previous candle of its pair
+    pub fn observe(&self, candle: &Candle, interval: chrono::Duration) -> Option<CandleGap> {
+        let mut last_start = match self
+            .last_starts
+            .entry((candle.pair.clone(), interval.num_milliseconds()))
+        {
+            Entry::Vacant(entry) => {
+                entry.insert(candle.start_time);
+                return None;
+            }
+            Entry::Occupied(entry) => entry.into_ref(),
+        };
+        // Updates of the current candle and late candles are not gaps
+        if candle.start_time <= *last_start {
+            return None;
+        }
+        let from = *last_start + interval;
+        *last_start = candle.start_time;
+        (candle.start_time > from).then(|| CandleGap {
+            pair: candle.pair.clone(),
+            from,
+            to: candle.start_time,
+            interval,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use backoff::backoff::Backoff;
+    use chrono::{TimeZone, Utc};
+
+    use super::{CandleGapDetector, WsReconnectOptions};
+    use crate::types::Candle;
+
+    #[test]
+    fn reconnect_backoff_is_bounded() {
+        let options = WsReconnectOptions::builder()
+            .initial_backoff(Duration::from_secs(1))
+            .max_backoff(Duration::from_secs(4))
+            .build();
+        let mut backoff = options.backoff();
+        for _ in 0..10 {
+            let next = backoff.next_backoff().unwrap();
+            // Randomization spreads delays by half of the interval
+            assert!(next <= Duration::from_secs(6));
+        }
+        assert_eq!(options.heartbeat_timeout, Some(Duration::from_secs(90)));
+    }
+
+    fn candle(start_minute: u32) -> Candle {
+        let start_time = Utc.with_ymd_and_hms(2022, 1, 1, 0, start_minute, 0).unwrap();
+        Candle {
+            event_time: start_time,
+            pair: "BTC_USDT".into(),
+            start_time,
+            end_time: start_time + chrono::Duration::minutes(1) - chrono::Duration::milliseconds(1),
+            open: 1.0,
+            high: 1.0,
+            low: 1.0,
+            close: 1.0,
+            volume: 1.0,
+            quote_volume: 1.0,
+            trade_count: 1,
+            is_final: true,
+        }
+    }
+
+    #[test]
+    fn detect_candle_gaps() {
+        let detector = CandleGapDetector::default();
+        let interval = chrono::Duration::minutes(1);
+        assert_eq!(detector.observe(&candle(0), interval), None);
+        assert_eq!(detector.observe(&candle(1), interval), None);
+        assert_eq!(detector.observe(&candle(1), interval), None);
+        let gap = detector.observe(&candle(4), interval).unwrap();
+        assert_eq!(gap.from, candle(2).start_time);
+        assert_eq!(gap.to, candle(4).start_time);
+        assert_eq!(gap.missing(), 2);
+        assert_eq!(detector.observe(&candle(3), interval), None);
+    }
+}
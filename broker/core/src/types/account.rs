@@ -49,4 +49,6 @@ pub struct AccountEventEnveloppe {
     pub xchg: Exchange,
     pub event: AccountEvent,
     pub account_type: AccountType,
+    /// Named account the event was received on, the main account if not set
+    pub account: Option<String>,
 }
@@ -0,0 +1,332 @@
+//! Candles of resolutions that exchanges do not stream are built locally, from candles of a lower resolution that
+//! the exchange streams, or from trades. Alternative bars of candle channels are then built from these candles.
+
+use std::collections::HashSet;
+
+use stats::kline::{BarBuilder, CandleAggregator, Kline};
+use stats::Next;
+
+use crate::exchange::Exchange;
+use crate::types::{Candle, ExchangeCapabilities, MarketChannel, MarketChannelTopic, MarketChannelType, MarketEvent,
+                   MarketEventEnvelope, Pair};
+
+/// Trade candles are buffered until final, the buffer only needs to hold the candle in progress
+const TRADES_KLINE_CAPACITY: usize = 16;
+
+fn to_kline_candle(candle: &Candle) -> stats::kline::Candle {
+    stats::kline::Candle {
+        event_time: candle.event_time,
+        start_time: candle.start_time,
+        end_time: candle.end_time,
+        open: candle.open,
+        high: candle.high,
+        low: candle.low,
+        close: candle.close,
+        volume: candle.volume,
+        quote_volume: candle.quote_volume,
+        trade_count: candle.trade_count,
+        is_final: candle.is_final,
+    }
+}
+
+fn from_kline_candle(pair: Pair, candle: &stats::kline::Candle) -> Candle {
+    Candle {
+        event_time: candle.event_time,
+        pair,
+        start_time: candle.start_time,
+        end_time: candle.end_time,
+        open: candle.open,
+        high: candle.high,
+        low: candle.low,
+        close: candle.close,
+        volume: candle.volume,
+        quote_volume: candle.quote_volume,
+        trade_count: candle.trade_count,
+        is_final: candle.is_final,
+    }
+}
+
+/// The channel to stream from the exchange to serve a candle channel that it does not stream
+///
+/// returns: candles of the highest streamed resolution that divides the channel resolution, otherwise trades, none if
+/// the exchange streams the channel or does not tell which resolutions it streams
+pub fn aggregation_source(channel: &MarketChannel, capabilities: &ExchangeCapabilities) -> Option<MarketChannel> {
+    let resolution = channel.resolution?;
+    if channel.r#type != MarketChannelType::Candles
+        || capabilities.resolutions.is_empty()
+        || capabilities.supports_resolution(&resolution)
+    {
+        return None;
+    }
+    let millis = resolution.as_millis();
+    let base = capabilities
+        .resolutions
+        .iter()
+        .filter(|r| r.as_millis() > 0 && r.as_millis() < millis && millis % r.as_millis() == 0)
+        .max_by_key(|r| r.as_millis());
+    Some(match base {
+        Some(base) => MarketChannel::builder()
+            .symbol(channel.symbol.clone())
+            .r#type(MarketChannelType::Candles)
+            .resolution(Some(*base))
+            .build(),
+        None => MarketChannel::builder()
+            .symbol(channel.symbol.clone())
+            .r#type(MarketChannelType::Trades)
+            .build(),
+    })
+}
+
+#[derive(Debug, Clone)]
+enum Aggregator {
+    /// Candles of the channel resolution are streamed
+    Streamed,
+    Candles(CandleAggregator),
+    Trades(Kline),
+}
+
+/// Builds the candles of a channel from the events of its source channel
+#[derive(Debug, Clone)]
+pub struct CandleAggregation {
+    pub channel: MarketChannel,
+    pub source: MarketChannel,
+    aggregator: Aggregator,
+    /// Builds the alternative bars of the channel from its candles, if any
+    bars: Option<BarBuilder>,
+}
+
+impl CandleAggregation {
+    /// # Panics
+    ///
+    /// if candles of the channel are aggregated and it has no resolution
+    pub fn new(channel: MarketChannel, mut source: MarketChannel) -> Self {
+        let resolution = || channel.resolution.expect("aggregated candles need a resolution");
+        let aggregator = match source.r#type {
+            MarketChannelType::Candles if source.resolution == channel.resolution => Aggregator::Streamed,
+            MarketChannelType::Candles => Aggregator::Candles(CandleAggregator::new(resolution())),
+            _ => Aggregator::Trades(Kline::new(resolution(), TRADES_KLINE_CAPACITY)),
+        };
+        // Bars are built here, exchanges only stream time candles
+        source.bars = None;
+        Self {
+            bars: channel.bars.map(BarBuilder::new),
+            channel,
+            source,
+            aggregator,
+        }
+    }
+
+    /// Aggregate an event of the source channel
+    ///
+    /// returns: the candle events of the channel, the previous candle once final then the current candle
+    pub fn aggregate(&mut self, e: &MarketEventEnvelope) -> Vec<MarketEventEnvelope> {
+        if MarketChannelTopic::from(e) != MarketChannelTopic::from(&self.source) {
+            return vec![];
+        }
+        let mut candles: Vec<stats::kline::Candle> = match (&mut self.aggregator, &e.e) {
+            (Aggregator::Streamed, MarketEvent::TradeCandle(candle)) => vec![to_kline_candle(candle)],
+            (Aggregator::Candles(aggregator), MarketEvent::TradeCandle(candle)) => {
+                aggregator.next(to_kline_candle(candle)).to_vec()
+            }
+            (Aggregator::Trades(kline), MarketEvent::Trade(_)) => {
+                kline.next((e.e.price(), e.e.vol(), e.e.time())).to_vec()
+            }
+            _ => return vec![],
+        };
+        if let Some(bars) = self.bars.as_mut() {
+            candles = candles.into_iter().flat_map(|candle| bars.next(candle)).collect();
+        }
+        let only_final = self.channel.only_final.unwrap_or(false);
+        candles
+            .iter()
+            .filter(|candle| candle.is_final || !only_final)
+            .map(|candle| {
+                let mut msg = e.clone();
+                msg.e = MarketEvent::TradeCandle(from_kline_candle(e.symbol.value.clone(), candle));
+                msg
+            })
+            .collect()
+    }
+}
+
+/// Serves the candle channels of a consumer that exchanges do not stream from the channels that they stream, and the
+/// alternative bars of candle channels
+#[derive(Debug, Clone, Default)]
+pub struct CandleAggregations {
+    aggregations: Vec<CandleAggregation>,
+    /// Channels that are streamed as requested
+    direct: HashSet<MarketChannel>,
+}
+
+impl CandleAggregations {
+    /// # Arguments
+    ///
+    /// * `channels`: the channels requested by the consumer
+    /// * `capabilities`: the capabilities of an exchange, if known
+    pub fn new<F>(channels: &HashSet<MarketChannel>, capabilities: F) -> Self
+    where
+        F: Fn(Exchange) -> Option<ExchangeCapabilities>,
+    {
+        let mut aggregations = vec![];
+        let mut direct = HashSet::new();
+        for channel in channels {
+            let source = capabilities(channel.exchange()).and_then(|c| aggregation_source(channel, &c));
+            match (source, channel.bars) {
+                (Some(source), _) => aggregations.push(CandleAggregation::new(channel.clone(), source)),
+                (None, Some(_)) if channel.r#type == MarketChannelType::Candles => {
+                    aggregations.push(CandleAggregation::new(channel.clone(), channel.clone()))
+                }
+                (None, _) => {
+                    direct.insert(channel.clone());
+                }
+            }
+        }
+        Self { aggregations, direct }
+    }
+
+    pub fn is_empty(&self) -> bool { self.aggregations.is_empty() }
+
+    /// The channels to stream from exchanges
+    pub fn source_channels(&self) -> HashSet<MarketChannel> {
+        self.direct
+            .iter()
+            .cloned()
+            .chain(self.aggregations.iter().map(|a| a.source.clone()))
+            .collect()
+    }
+
+    /// Whether an event is served to the consumer as is, events only streamed as the source of aggregated candles
+    /// are not
+    pub fn passes_through(&self, e: &MarketEventEnvelope) -> bool {
+        let topic = MarketChannelTopic::from(e);
+        self.direct.iter().any(|c| MarketChannelTopic::from(c) == topic)
+            || !self
+                .aggregations
+                .iter()
+                .any(|a| MarketChannelTopic::from(&a.source) == topic)
+    }
+
+    /// The aggregated candle events built from an event
+    pub fn aggregate(&mut self, e: &MarketEventEnvelope) -> Vec<MarketEventEnvelope> {
+        self.aggregations.iter_mut().flat_map(|a| a.aggregate(e)).collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashSet;
+
+    use chrono::{Duration, TimeZone, Utc};
+    use stats::kline::{BarType, Resolution, TimeUnit};
+
+    use crate::exchange::Exchange;
+    use crate::types::{Candle, ExchangeCapabilities, MarketChannel, MarketChannelType, MarketEvent,
+                       MarketEventEnvelope, SecurityType, Symbol};
+
+    use super::{aggregation_source, CandleAggregations};
+
+    fn candles(resolution: Resolution) -> MarketChannel {
+        MarketChannel::builder()
+            .symbol(Symbol::new("BTC_USDT".into(), SecurityType::Crypto, Exchange::Binance))
+            .r#type(MarketChannelType::Candles)
+            .resolution(Some(resolution))
+            .only_final(Some(true))
+            .build()
+    }
+
+    #[test]
+    fn aggregate_unsupported_resolutions() {
+        let capabilities = ExchangeCapabilities {
+            resolutions: vec![
+                Resolution::new(TimeUnit::Minute, 1),
+                Resolution::new(TimeUnit::Minute, 5),
+                Resolution::new(TimeUnit::Hour, 1),
+            ],
+            ..ExchangeCapabilities::default()
+        };
+        let four_hours = candles(Resolution::new(TimeUnit::Hour, 4));
+        let source = aggregation_source(&four_hours, &capabilities).unwrap();
+        assert_eq!(source.resolution, Some(Resolution::new(TimeUnit::Hour, 1)));
+        assert!(aggregation_source(&candles(Resolution::new(TimeUnit::Minute, 5)), &capabilities).is_none());
+        let seven_seconds = candles(Resolution::new(TimeUnit::Second, 7));
+        assert_eq!(
+            aggregation_source(&seven_seconds, &capabilities).unwrap().r#type,
+            MarketChannelType::Trades
+        );
+
+        let channels: HashSet<MarketChannel> = vec![four_hours].into_iter().collect();
+        let mut aggregations = CandleAggregations::new(&channels, |_| Some(capabilities.clone()));
+        assert_eq!(
+            aggregations.source_channels(),
+            vec![source.clone()].into_iter().collect()
+        );
+        let t0 = Utc.with_ymd_and_hms(2022, 1, 1, 0, 0, 0).unwrap();
+        let mut aggregated = vec![];
+        for hour in 0..5 {
+            let start_time = t0 + Duration::hours(hour);
+            let event = MarketEventEnvelope::new(
+                source.symbol.clone(),
+                MarketEvent::TradeCandle(Candle {
+                    event_time: start_time + Duration::hours(1),
+                    pair: "BTC_USDT".into(),
+                    start_time,
+                    end_time: start_time + Duration::hours(1),
+                    open: 100.0 + hour as f64,
+                    high: 110.0 + hour as f64,
+                    low: 90.0,
+                    close: 101.0 + hour as f64,
+                    volume: 1.0,
+                    quote_volume: 100.0,
+                    trade_count: 10,
+                    is_final: true,
+                }),
+            );
+            assert!(!aggregations.passes_through(&event));
+            aggregated.extend(aggregations.aggregate(&event));
+        }
+        assert_eq!(aggregated.len(), 1);
+        let MarketEvent::TradeCandle(candle) = &aggregated[0].e else {
+            panic!("expected a candle");
+        };
+        assert_eq!((candle.start_time, candle.end_time), (t0, t0 + Duration::hours(4)));
+        assert_eq!((candle.open, candle.high, candle.close), (100.0, 113.0, 104.0));
+        assert_eq!((candle.volume, candle.trade_count, candle.is_final), (4.0, 40, true));
+    }
+
+    #[test]
+    fn build_bars_of_streamed_candles() {
+        let channel = MarketChannel {
+            bars: Some(BarType::Volume { volume: 3.0 }),
+            ..candles(Resolution::new(TimeUnit::Minute, 1))
+        };
+        let channels: HashSet<MarketChannel> = vec![channel.clone()].into_iter().collect();
+        let mut aggregations = CandleAggregations::new(&channels, |_| None);
+        let source = MarketChannel { bars: None, ..channel };
+        assert_eq!(aggregations.source_channels(), vec![source].into_iter().collect());
+        let t0 = Utc.with_ymd_and_hms(2022, 1, 1, 0, 0, 0).unwrap();
+        let bars: Vec<MarketEventEnvelope> = (0..4)
+            .flat_map(|minute| {
+                let start_time = t0 + Duration::minutes(minute);
+                aggregations.aggregate(&MarketEventEnvelope::new(
+                    Symbol::new("BTC_USDT".into(), SecurityType::Crypto, Exchange::Binance),
+                    MarketEvent::TradeCandle(Candle {
+                        event_time: start_time,
+                        pair: "BTC_USDT".into(),
+                        start_time,
+                        end_time: start_time + Duration::minutes(1),
+                        open: 100.0,
+                        high: 100.0,
+                        low: 100.0,
+                        close: 100.0,
+                        volume: 1.0,
+                        quote_volume: 100.0,
+                        trade_count: 1,
+                        is_final: true,
+                    }),
+                ))
+            })
+            .collect();
+        assert_eq!(bars.len(), 1);
+        assert!(matches!(&bars[0].e, MarketEvent::TradeCandle(bar) if bar.volume == 3.0 && bar.start_time == t0));
+    }
+}
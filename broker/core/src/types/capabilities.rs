@@ -0,0 +1,158 @@
+use stats::kline::Resolution;
+
+use crate::error::{Error, Result};
+use crate::types::{MarketChannel, MarketChannelType, OrderType};
+
+/// A maximum number of requests over an interval
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RateLimit {
+    pub requests: u32,
+    pub interval_ms: u64,
+}
+
+impl RateLimit {
+    pub const fn new(requests: u32, interval_ms: u64) -> Self { Self { requests, interval_ms } }
+}
+
+/// What an exchange supports, so that configurations can be rejected before trading rather than at order time
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExchangeCapabilities {
+    /// Order types that can be placed
+    pub order_types: Vec<OrderType>,
+    /// Limit orders can be rejected instead of crossing the book
+    pub post_only: bool,
+    /// Margin accounts, cross or isolated
+    pub margin: bool,
+    /// One-cancels-the-other orders
+    pub oco: bool,
+    /// Resting orders can be amended in place
+    pub amend: bool,
+    /// Resting orders can be canceled by the exchange once a timer expires, the range of its timeout in seconds
+    pub dead_man_switch: Option<(u64, u64)>,
+    /// Maximum depth of streamed order books, unbounded if not set
+    pub max_orderbook_depth: Option<u16>,
+    /// Request limits of the api
+    pub rate_limits: Vec<RateLimit>,
+    /// Resolutions of the candles streamed by the exchange
+    pub resolutions: Vec<Resolution>,
+}
+
+impl Default for ExchangeCapabilities {
+    /// Market and limit orders only
+    fn default() -> Self {
+        Self {
+            order_types: vec![OrderType::Limit, OrderType::Market],
+            post_only: false,
+            margin: false,
+            oco: false,
+            amend: false,
+            dead_man_switch: None,
+            max_orderbook_depth: None,
+            rate_limits: vec![],
+            resolutions: vec![],
+        }
+    }
+}
+
+impl ExchangeCapabilities {
+    pub fn supports_order_type(&self, order_type: OrderType) -> bool { self.order_types.contains(&order_type) }
+
+    pub fn supports_resolution(&self, resolution: &Resolution) -> bool { self.resolutions.contains(resolution) }
+
+    /// # Errors
+    ///
+    /// if the order type cannot be placed
+    pub fn check_order_type(&self, order_type: OrderType) -> Result<()> {
+        if self.supports_order_type(order_type) {
+            Ok(())
+        } else {
+            Err(Error::UnsupportedCapability(format!("{:?} orders", order_type)))
+        }
+    }
+
+    /// # Errors
+    ///
+    /// if the exchange has no dead man's switch, or not one with this timeout
+    pub fn check_dead_man_switch(&self, timeout_secs: u64) -> Result<()> {
+        match self.dead_man_switch {
+            Some((min, max)) if (min..=max).contains(&timeout_secs) => Ok(()),
+            Some(_) => Err(Error::UnsupportedCapability(format!(
+                "a dead man's switch timeout of {}s",
+                timeout_secs
+            ))),
+            None => Err(Error::UnsupportedCapability("dead man's switch".to_string())),
+        }
+    }
+
+    /// # Errors
+    ///
+    /// if the exchange does not stream candles of the channel resolution, or order books this deep
+    pub fn check_channel(&self, channel: &MarketChannel) -> Result<()> {
+        if channel.r#type == MarketChannelType::Candles {
+            if let Some(resolution) = channel.resolution.filter(|r| !self.supports_resolution(r)) {
+                return Err(Error::UnsupportedCapability(format!(
+                    "candles of {} {}",
+                    resolution.units,
+                    resolution.time_unit.as_ref()
+                )));
+            }
+        }
+        let depth = channel.orderbook.and_then(|conf| conf.depth);
+        if let (Some(depth), Some(max_depth)) = (depth, self.max_orderbook_depth) {
+            if depth > max_depth {
+                return Err(Error::UnsupportedCapability(format!("order books of depth {}", depth)));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use stats::kline::{Resolution, TimeUnit};
+
+    use crate::error::Error;
+    use crate::exchange::Exchange;
+    use crate::types::{MarketChannel, MarketChannelType, OrderType, OrderbookConf, SecurityType, Symbol};
+
+    use super::ExchangeCapabilities;
+
+    fn channel(r#type: MarketChannelType) -> MarketChannel {
+        MarketChannel::builder()
+            .symbol(Symbol::new("BTC_USDT".into(), SecurityType::Crypto, Exchange::Binance))
+            .r#type(r#type)
+            .build()
+    }
+
+    #[test]
+    fn should_reject_unsupported_capabilities() {
+        let capabilities = ExchangeCapabilities {
+            max_orderbook_depth: Some(20),
+            resolutions: vec![Resolution::new(TimeUnit::Minute, 1)],
+            ..ExchangeCapabilities::default()
+        };
+        assert!(capabilities.check_order_type(OrderType::Limit).is_ok());
+        assert_eq!(
+            capabilities.check_order_type(OrderType::StopLoss),
+            Err(Error::UnsupportedCapability(String::new()))
+        );
+        let mut candles = channel(MarketChannelType::Candles);
+        candles.resolution = Some(Resolution::new(TimeUnit::Minute, 1));
+        assert!(capabilities.check_channel(&candles).is_ok());
+        candles.resolution = Some(Resolution::new(TimeUnit::Minute, 7));
+        assert!(capabilities.check_channel(&candles).is_err());
+        let mut books = channel(MarketChannelType::Orderbooks);
+        books.orderbook = Some(OrderbookConf {
+            depth: Some(50),
+            ..OrderbookConf::default()
+        });
+        assert!(capabilities.check_channel(&books).is_err());
+        assert!(capabilities.check_dead_man_switch(60).is_err());
+        let capabilities = ExchangeCapabilities {
+            dead_man_switch: Some((10, 120)),
+            ..ExchangeCapabilities::default()
+        };
+        assert!(capabilities.check_dead_man_switch(60).is_ok());
+        assert!(capabilities.check_dead_man_switch(600).is_err());
+    }
+}
@@ -18,6 +18,17 @@ pub type MarketSymbol = Atom;
 /// An asset is string representation of a single base or quote asset used in markets, for instance 'BTC', 'USDT' or 'TSLA'
 pub type Asset = Atom;
 
+/// Operational status of an exchange
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq, Hash, Default, AsRefStr)]
+#[serde(rename_all = "snake_case")]
+pub enum SystemStatus {
+    #[default]
+    #[strum(serialize = "normal")]
+    Normal,
+    #[strum(serialize = "maintenance")]
+    Maintenance,
+}
+
 /// Type of tradable security / underlying asset
 #[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq, Hash, EnumString, AsRefStr)]
 #[serde(rename_all = "snake_case")]
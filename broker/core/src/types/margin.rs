@@ -1,3 +1,7 @@
+use crate::error::{Error, Result};
+use crate::exchange::Exchange;
+use crate::types::{Asset, Pair};
+
 #[derive(Copy, Clone, Debug, Serialize, Deserialize, PartialEq, Eq, EnumString, AsRefStr)]
 #[serde(rename_all = "snake_case")]
 pub enum MarginSideEffect {
@@ -70,3 +74,33 @@ impl InterestRate {
         amount * (self.rate / divider as f64) * hours as f64
     }
 }
+
+/// Loan of an asset on a margin account, or the repayment of a loan
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Default)]
+pub struct MarginLoanRequest {
+    pub xch: Exchange,
+    /// The borrowed asset
+    pub asset: Asset,
+    /// Amount of the asset, repayments are applied to the accrued interest first
+    pub amount: f64,
+    /// Pair of the isolated margin account, the cross margin account if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub isolated_pair: Option<Pair>,
+    /// Id of the order the loan funds
+    pub order_id: String,
+    /// Named account to borrow with, the main account of the exchange if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub account: Option<String>,
+}
+
+impl MarginLoanRequest {
+    pub fn validate(&self) -> Result<()> {
+        if self.asset.is_empty() {
+            return Err(Error::InvalidArguments);
+        }
+        if self.amount <= 0.0 {
+            return Err(Error::InvalidQty);
+        }
+        Ok(())
+    }
+}
@@ -8,13 +8,13 @@ use ordered_float::OrderedFloat;
 use uuid::Uuid;
 
 use crate::broker::{MarketEventEnvelopeRef, Subject};
-use stats::kline::Resolution;
+use stats::kline::{BarType, Resolution};
 use util::ser::{decode_duration_opt, encode_duration_str_opt};
 use util::time::now;
 
 use crate::exchange::Exchange;
 use crate::types::order::{Order, OrderEnforcement, OrderStatus, TradeType};
-use crate::types::{Pair, Price, SecurityType, Symbol, Volume};
+use crate::types::{Asset, Pair, Price, SecurityType, Symbol, Volume};
 
 /// A market channel represents a unique stream of data that will be required to run a strategy
 /// Historical and Real-Time data will be provided from this on a best effort basis.
@@ -36,6 +36,9 @@ pub struct MarketChannel {
     pub only_final: Option<bool>,
     #[builder(default)]
     pub orderbook: Option<OrderbookConf>,
+    /// If set, candles are served as alternative bars built from the candles of the resolution
+    #[builder(default)]
+    pub bars: Option<BarType>,
 }
 
 impl MarketChannel {
@@ -51,6 +54,8 @@ impl MarketChannel {
             MarketChannelType::Candles => "candles",
             MarketChannelType::Quotes => "quotes",
             MarketChannelType::QuotesCandles => "book_candles",
+            MarketChannelType::FundingRate => "funding_rates",
+            MarketChannelType::OrderbookL3 => "order_books_l3",
         }
     }
 }
@@ -69,6 +74,10 @@ pub enum MarketChannelType {
     Quotes,
     /// Kline for layer 1 order book [MarketEvent::BookCandle]
     QuotesCandles,
+    /// Funding rates of perpetual contracts see [MarketEvent::FundingRate]
+    FundingRate,
+    /// Full depth order book changes see [MarketEvent::OrderbookL3]
+    OrderbookL3,
 }
 
 impl From<&MarketEvent> for MarketChannelType {
@@ -78,6 +87,10 @@ impl From<&MarketEvent> for MarketChannelType {
             MarketEvent::Orderbook(_) => Self::Orderbooks,
             MarketEvent::TradeCandle(_) => Self::Candles,
             MarketEvent::BookCandle(_) => Self::QuotesCandles,
+            MarketEvent::FundingRate(_) => Self::FundingRate,
+            MarketEvent::OrderbookL3(_) => Self::OrderbookL3,
+            MarketEvent::Quote(_) => Self::Quotes,
+            MarketEvent::OpenInterest(_) => Self::OpenInterest,
         }
     }
 }
@@ -86,6 +99,13 @@ impl From<MarketEvent> for MarketChannelType {
     fn from(e: MarketEvent) -> Self { From::from(&e) }
 }
 
+/// How an exchange computes the CRC32 checksum of the top of its order books
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BookChecksum {
+    /// Top 25 levels, bids and asks interleaved as `bid_px:bid_sz:ask_px:ask_sz:...`
+    Okx,
+}
+
 #[derive(Debug)]
 pub struct LiveAggregatedOrderBook {
     pub depth: u16,
@@ -96,6 +116,11 @@ pub struct LiveAggregatedOrderBook {
     pub last_bids: Vec<Offer>,
     pub ts: i64,
     pub last_order_id: Option<String>,
+    /// The book failed a checksum, it is not published until it is snapshotted again
+    pub desynced: bool,
+    /// Price and size strings of the levels as sent by the exchange, for checksums of the original text
+    pub raw_asks: BTreeMap<OrderedFloat<Price>, (String, String)>,
+    pub raw_bids: BTreeMap<OrderedFloat<Price>, (String, String)>,
 }
 
 impl LiveAggregatedOrderBook {
@@ -109,6 +134,9 @@ impl LiveAggregatedOrderBook {
             last_bids: vec![],
             ts: 0,
             last_order_id: None,
+            desynced: false,
+            raw_asks: BTreeMap::new(),
+            raw_bids: BTreeMap::new(),
         }
     }
 
@@ -140,6 +168,10 @@ impl LiveAggregatedOrderBook {
     }
 
     pub fn latest_order_book(&mut self) -> Option<Orderbook> {
+        if self.desynced {
+            trace!("Order book out of sync, not flushing");
+            return None;
+        }
         let latest_order_book: Orderbook = self.order_book();
         if latest_order_book.asks == self.last_asks && latest_order_book.bids == self.last_bids {
             trace!("Order book top unchanged, not flushing");
@@ -156,6 +188,7 @@ impl LiveAggregatedOrderBook {
         I: Iterator<Item = &'a Offer>,
     {
         self.asks_by_price = BTreeMap::new();
+        self.raw_asks.clear();
         for kp in iter {
             self.asks_by_price.entry(kp.0.into()).or_insert(*kp);
         }
@@ -166,6 +199,7 @@ impl LiveAggregatedOrderBook {
         I: Iterator<Item = &'a Offer>,
     {
         self.bids_by_price = BTreeMap::new();
+        self.raw_bids.clear();
         for kp in iter {
             self.bids_by_price.entry(kp.0.into()).or_insert(*kp);
         }
@@ -176,6 +210,7 @@ impl LiveAggregatedOrderBook {
         I: Iterator<Item = Offer>,
     {
         self.asks_by_price = BTreeMap::new();
+        self.raw_asks.clear();
         for kp in iter {
             self.asks_by_price.entry(kp.0.into()).or_insert(kp);
         }
@@ -186,6 +221,7 @@ impl LiveAggregatedOrderBook {
         I: Iterator<Item = Offer>,
     {
         self.bids_by_price = BTreeMap::new();
+        self.raw_bids.clear();
         for kp in iter {
             self.bids_by_price.entry(kp.0.into()).or_insert(kp);
         }
@@ -215,11 +251,74 @@ impl LiveAggregatedOrderBook {
         Self::upsert(bids, kp);
     }
 
+    /// Keep the `(price, size)` strings of ask levels, after the levels were reset or updated
+    pub fn record_raw_asks<I>(&mut self, iter: I)
+    where
+        I: Iterator<Item = (String, String)>,
+    {
+        Self::upsert_raw(&mut self.raw_asks, iter);
+    }
+
+    /// Keep the `(price, size)` strings of bid levels, after the levels were reset or updated
+    pub fn record_raw_bids<I>(&mut self, iter: I)
+    where
+        I: Iterator<Item = (String, String)>,
+    {
+        Self::upsert_raw(&mut self.raw_bids, iter);
+    }
+
     pub fn set_ts(&mut self, i: i64) { self.ts = i; }
 
+    /// Asks from the lowest price
+    pub fn top_asks(&self) -> impl Iterator<Item = &Offer> { self.asks_by_price.values() }
+
+    /// Bids from the highest price
+    pub fn top_bids(&self) -> impl Iterator<Item = &Offer> { self.bids_by_price.values().rev() }
+
+    /// CRC32 of the top of the book, as computed by the exchange
+    pub fn checksum(&self, format: BookChecksum) -> u32 {
+        let payload = match format {
+            BookChecksum::Okx => {
+                // Levels are hashed as sent, e.g. with trailing zeros, unless their strings were not recorded
+                let level = |raw: &BTreeMap<OrderedFloat<Price>, (String, String)>, (price, volume): &Offer| {
+                    raw.get(&OrderedFloat(*price))
+                        .map_or_else(|| format!("{}:{}", price, volume), |(px, sz)| format!("{}:{}", px, sz))
+                };
+                let (mut bids, mut asks) = (self.top_bids().take(25), self.top_asks().take(25));
+                let mut levels = vec![];
+                loop {
+                    let (bid, ask) = (bids.next(), asks.next());
+                    if bid.is_none() && ask.is_none() {
+                        break;
+                    }
+                    levels.extend(bid.map(|bid| level(&self.raw_bids, bid)));
+                    levels.extend(ask.map(|ask| level(&self.raw_asks, ask)));
+                }
+                levels.join(":")
+            }
+        };
+        crc32fast::hash(payload.as_bytes())
+    }
+
     #[allow(dead_code)]
     pub fn set_last_order_id(&mut self, last_order_id: Option<String>) { self.last_order_id = last_order_id }
 
+    fn upsert_raw<I>(m: &mut BTreeMap<OrderedFloat<Price>, (String, String)>, iter: I)
+    where
+        I: Iterator<Item = (String, String)>,
+    {
+        for (price, size) in iter {
+            let Ok(px) = price.parse::<Price>() else {
+                continue;
+            };
+            if size.parse::<f64>().map_or(true, |sz| sz == 0.0) {
+                m.remove(&px.into());
+            } else {
+                m.insert(px.into(), (price, size));
+            }
+        }
+    }
+
     fn upsert(m: &mut BTreeMap<OrderedFloat<Price>, Offer>, kp: Offer) {
         if kp.1 == 0.0 {
             m.remove(&kp.0.into());
@@ -241,6 +340,107 @@ pub struct Trade {
     pub price: Price,
     /// Buy or Sell
     pub tt: TradeType,
+    /// Side of the taker that initiated the trade, if provided by the exchange
+    #[serde(default)]
+    pub aggressor: Option<TradeType>,
+}
+
+/// Funding rate of a perpetual contract
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct FundingRate {
+    /// UNIX timestamp in ms (when the event occured)
+    pub event_ms: i64,
+    pub pair: Pair,
+    /// Rate exchanged at the next funding, positive when longs pay shorts
+    pub rate: f64,
+    /// UNIX timestamp in ms of the next funding
+    pub next_funding_ms: i64,
+    /// Mark price of the contract, if provided by the exchange
+    #[serde(default)]
+    pub mark_price: Option<Price>,
+}
+
+/// Funding exchanged by a perpetual contract position of the account
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct FundingPayment {
+    /// UNIX timestamp in ms of the funding
+    pub time_ms: i64,
+    pub pair: Pair,
+    /// Asset the funding was exchanged in
+    pub asset: Asset,
+    /// Amount received, negative when paid
+    pub amount: f64,
+}
+
+/// Open interest of a futures contract
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct OpenInterest {
+    /// UNIX timestamp in ms (when the event occured)
+    pub event_ms: i64,
+    pub pair: Pair,
+    /// Number of outstanding contracts
+    pub open_interest: f64,
+}
+
+/// A change of a full depth order book.
+/// For exchanges that stream individual orders `order_id` is set and `qty` is what remains of the order,
+/// otherwise `qty` is the total quantity at the price level. A zero `qty` removes the order or level.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct BookChange {
+    /// Buy for bids, Sell for asks
+    pub side: TradeType,
+    pub price: Price,
+    pub qty: Volume,
+    #[serde(default)]
+    pub order_id: Option<String>,
+}
+
+/// Full depth order book changes, in the sequence of the exchange, so that the queue ahead of an order can be tracked
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct OrderbookL3 {
+    /// UNIX timestamp in ms (when the event occured)
+    pub event_ms: i64,
+    pub pair: Pair,
+    /// Sequence number of the first change
+    pub first_update_id: u64,
+    /// Sequence number of the last change
+    pub last_update_id: u64,
+    /// The changes are the whole book, which replaces the current one
+    pub is_snapshot: bool,
+    pub changes: Vec<BookChange>,
+}
+
+impl OrderbookL3 {
+    /// Whether these changes directly follow a book that is up to date with `last_update_id`
+    pub fn continues(&self, last_update_id: u64) -> bool {
+        self.first_update_id <= last_update_id + 1 && self.last_update_id > last_update_id
+    }
+}
+
+/// Best bid and offer of a book
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct Quote {
+    /// UNIX timestamp in ms (when the event occured)
+    pub event_ms: i64,
+    pub pair: Pair,
+    pub bid: Price,
+    pub bid_qty: Volume,
+    pub ask: Price,
+    pub ask_qty: Volume,
+}
+
+impl Quote {
+    pub fn mid(&self) -> Price { (self.bid + self.ask) / 2.0 }
+
+    /// Average of the bid and ask weighted by their quantities, the mid if both are empty
+    pub fn vwap(&self) -> Price {
+        let qty = self.bid_qty + self.ask_qty;
+        if qty > 0.0 {
+            (self.bid * self.bid_qty + self.ask * self.ask_qty) / qty
+        } else {
+            self.mid()
+        }
+    }
 }
 
 #[allow(clippy::large_enum_variant)]
@@ -252,6 +452,10 @@ pub enum MarketEvent {
     Orderbook(Orderbook),
     TradeCandle(Candle),
     BookCandle(BookCandle),
+    FundingRate(FundingRate),
+    OrderbookL3(OrderbookL3),
+    Quote(Quote),
+    OpenInterest(OpenInterest),
 }
 
 impl MarketEvent {
@@ -261,6 +465,10 @@ impl MarketEvent {
             MarketEvent::Orderbook(_) => "order_book",
             MarketEvent::TradeCandle(_) => "trade_candles",
             MarketEvent::BookCandle(_) => "book_candles",
+            MarketEvent::FundingRate(_) => "funding_rates",
+            MarketEvent::OrderbookL3(_) => "order_book_l3",
+            MarketEvent::Quote(_) => "quotes",
+            MarketEvent::OpenInterest(_) => "open_interests",
         }
     }
 
@@ -271,6 +479,10 @@ impl MarketEvent {
             Self::Orderbook(ref e) => e.pair.clone(),
             Self::TradeCandle(ref e) => e.pair.clone(),
             Self::BookCandle(ref e) => e.pair.clone(),
+            Self::FundingRate(ref e) => e.pair.clone(),
+            Self::OrderbookL3(ref e) => e.pair.clone(),
+            Self::Quote(ref e) => e.pair.clone(),
+            Self::OpenInterest(ref e) => e.pair.clone(),
         }
     }
 
@@ -280,6 +492,10 @@ impl MarketEvent {
             MarketEvent::Orderbook(ob) => Utc.timestamp_millis_opt(ob.timestamp).unwrap(),
             MarketEvent::TradeCandle(c) => c.event_time,
             MarketEvent::BookCandle(c) => c.event_time,
+            MarketEvent::FundingRate(f) => Utc.timestamp_millis_opt(f.event_ms).unwrap(),
+            MarketEvent::OrderbookL3(l3) => Utc.timestamp_millis_opt(l3.event_ms).unwrap(),
+            MarketEvent::Quote(q) => Utc.timestamp_millis_opt(q.event_ms).unwrap(),
+            MarketEvent::OpenInterest(oi) => Utc.timestamp_millis_opt(oi.event_ms).unwrap(),
         }
     }
 
@@ -291,6 +507,9 @@ impl MarketEvent {
             // TODO: vwap should be made available in candles
             MarketEvent::TradeCandle(ct) => (ct.high + ct.low) / 2.0,
             MarketEvent::BookCandle(bc) => bc.mid.close,
+            MarketEvent::FundingRate(f) => f.mark_price.unwrap_or(0.0),
+            MarketEvent::OrderbookL3(_) | MarketEvent::OpenInterest(_) => 0.0,
+            MarketEvent::Quote(q) => q.vwap(),
         }
     }
 
@@ -300,6 +519,9 @@ impl MarketEvent {
             MarketEvent::Orderbook(o) => o.top_bid().map_or(0.0, |b| b.0),
             MarketEvent::TradeCandle(ct) => ct.high,
             MarketEvent::BookCandle(bc) => bc.ask.high,
+            MarketEvent::FundingRate(f) => f.mark_price.unwrap_or(0.0),
+            MarketEvent::OrderbookL3(_) | MarketEvent::OpenInterest(_) => 0.0,
+            MarketEvent::Quote(q) => q.bid,
         }
     }
 
@@ -309,6 +531,9 @@ impl MarketEvent {
             MarketEvent::Orderbook(o) => o.top_ask().map_or(0.0, |b| b.0),
             MarketEvent::TradeCandle(ct) => ct.low,
             MarketEvent::BookCandle(bc) => bc.ask.low,
+            MarketEvent::FundingRate(f) => f.mark_price.unwrap_or(0.0),
+            MarketEvent::OrderbookL3(_) | MarketEvent::OpenInterest(_) => 0.0,
+            MarketEvent::Quote(q) => q.ask,
         }
     }
 
@@ -318,6 +543,9 @@ impl MarketEvent {
             MarketEvent::Orderbook(o) => o.top_bid().map_or(0.0, |b| b.0),
             MarketEvent::TradeCandle(ct) => ct.close,
             MarketEvent::BookCandle(bc) => bc.ask.close,
+            MarketEvent::FundingRate(f) => f.mark_price.unwrap_or(0.0),
+            MarketEvent::OrderbookL3(_) | MarketEvent::OpenInterest(_) => 0.0,
+            MarketEvent::Quote(q) => q.bid,
         }
     }
 
@@ -327,6 +555,9 @@ impl MarketEvent {
             MarketEvent::Orderbook(o) => o.top_ask().map_or(0.0, |b| b.0),
             MarketEvent::TradeCandle(ct) => ct.open,
             MarketEvent::BookCandle(bc) => bc.ask.open,
+            MarketEvent::FundingRate(f) => f.mark_price.unwrap_or(0.0),
+            MarketEvent::OrderbookL3(_) | MarketEvent::OpenInterest(_) => 0.0,
+            MarketEvent::Quote(q) => q.ask,
         }
     }
 
@@ -336,6 +567,8 @@ impl MarketEvent {
             MarketEvent::Orderbook(o) => o.vol(),
             MarketEvent::TradeCandle(ct) => ct.quote_volume,
             MarketEvent::BookCandle(bc) => bc.ask.quote_volume,
+            MarketEvent::FundingRate(_) | MarketEvent::OrderbookL3(_) | MarketEvent::OpenInterest(_) => 0.0,
+            MarketEvent::Quote(q) => q.bid_qty + q.ask_qty,
         }
     }
 
@@ -345,6 +578,17 @@ impl MarketEvent {
             MarketEvent::Orderbook(o) => o.top_ask().or_else(|| o.top_bid()).unwrap_or((0.0, 0.0)).0,
             MarketEvent::TradeCandle(t) => t.close,
             MarketEvent::BookCandle(bc) => bc.ask.close,
+            MarketEvent::FundingRate(f) => f.mark_price.unwrap_or(0.0),
+            MarketEvent::OrderbookL3(_) | MarketEvent::OpenInterest(_) => 0.0,
+            MarketEvent::Quote(q) => q.ask,
+        }
+    }
+
+    /// Side of the taker for trade events, used to compute trade flow imbalance
+    pub fn aggressor_side(&self) -> Option<TradeType> {
+        match self {
+            MarketEvent::Trade(t) => t.aggressor,
+            _ => None,
         }
     }
 }
@@ -386,13 +630,33 @@ impl MarketEventEnvelope {
         Self::new(symbol, MarketEvent::Orderbook(orderbook))
     }
 
-    pub fn trade_event(symbol: Symbol, ts: i64, price: f64, qty: f64, tt: TradeType) -> MarketEventEnvelope {
+    pub fn quote_event(symbol: Symbol, ts: i64, bid: f64, bid_qty: f64, ask: f64, ask_qty: f64) -> MarketEventEnvelope {
+        let quote = Quote {
+            event_ms: ts,
+            pair: symbol.value.clone(),
+            bid,
+            bid_qty,
+            ask,
+            ask_qty,
+        };
+        Self::new(symbol, MarketEvent::Quote(quote))
+    }
+
+    pub fn trade_event(
+        symbol: Symbol,
+        ts: i64,
+        price: f64,
+        qty: f64,
+        tt: TradeType,
+        aggressor: Option<TradeType>,
+    ) -> MarketEventEnvelope {
         let trade = Trade {
             event_ms: ts,
             pair: symbol.value.clone(),
             amount: qty,
             price,
             tt,
+            aggressor,
         };
         Self::new(symbol, MarketEvent::Trade(trade))
     }
@@ -686,3 +950,184 @@ impl From<MarketChannel> for MarketChannelTopic {
 impl From<&MarketChannel> for MarketChannelTopic {
     fn from(mc: &MarketChannel) -> Self { Self(mc.symbol.clone(), mc.r#type) }
 }
+
+#[cfg(test)]
+mod test {
+    use chrono::{DateTime, TimeZone, Utc};
+    use uuid::Uuid;
+
+    use crate::exchange::Exchange;
+    use crate::types::{BookCandle, BookChange, BookChecksum, Candle, FundingRate, LiveAggregatedOrderBook,
+                       MarketEvent, MarketEventEnvelope, OpenInterest, Orderbook, OrderbookL3, Quote, SecurityType,
+                       Symbol, Trade, TradeType};
+
+    const CANDLE_JSON: &str = r#"{"event_time":"2020-09-13T12:26:40Z","pair":"BTC_USDT","start_time":"2020-09-13T12:26:40Z","end_time":"2020-09-13T12:26:40Z","open":100.0,"high":101.0,"low":99.0,"close":100.5,"volume":2.0,"quote_volume":201.0,"trade_count":3,"is_final":true}"#;
+
+    fn ts() -> DateTime<Utc> { Utc.timestamp_opt(1_600_000_000, 0).unwrap() }
+
+    fn candle() -> Candle {
+        Candle {
+            event_time: ts(),
+            pair: "BTC_USDT".into(),
+            start_time: ts(),
+            end_time: ts(),
+            open: 100.0,
+            high: 101.0,
+            low: 99.0,
+            close: 100.5,
+            volume: 2.0,
+            quote_volume: 201.0,
+            trade_count: 3,
+            is_final: true,
+        }
+    }
+
+    fn l3(first_update_id: u64, last_update_id: u64) -> OrderbookL3 {
+        OrderbookL3 {
+            event_ms: 1_600_000_000_000,
+            pair: "BTC_USDT".into(),
+            first_update_id,
+            last_update_id,
+            is_snapshot: false,
+            changes: vec![BookChange {
+                side: TradeType::Buy,
+                price: 100.5,
+                qty: 0.0,
+                order_id: None,
+            }],
+        }
+    }
+
+    fn trade() -> MarketEvent {
+        MarketEvent::Trade(Trade {
+            event_ms: 1_600_000_000_000,
+            pair: "BTC_USDT".into(),
+            amount: 0.5,
+            price: 100.5,
+            tt: TradeType::Buy,
+            aggressor: None,
+        })
+    }
+
+    /// Every variant along with its pinned JSON shape
+    fn golden_events() -> Vec<(MarketEvent, String)> {
+        vec![
+            (
+                trade(),
+                r#"{"type":"Trade","event_ms":1600000000000,"pair":"BTC_USDT","amount":0.5,"price":100.5,"tt":"Buy","aggressor":null}"#.to_string(),
+            ),
+            (
+                MarketEvent::Orderbook(Orderbook {
+                    timestamp: 1_600_000_000_000,
+                    pair: "BTC_USDT".into(),
+                    asks: vec![(100.5, 1.0)],
+                    bids: vec![(100.0, 2.0)],
+                    last_order_id: None,
+                }),
+                r#"{"type":"Orderbook","timestamp":1600000000000,"pair":"BTC_USDT","asks":[[100.5,1.0]],"bids":[[100.0,2.0]],"last_order_id":null}"#.to_string(),
+            ),
+            (
+                MarketEvent::TradeCandle(candle()),
+                format!(r#"{{"type":"TradeCandle",{}"#, &CANDLE_JSON[1..]),
+            ),
+            (
+                MarketEvent::BookCandle(BookCandle {
+                    bid: candle(),
+                    ask: candle(),
+                    mid: candle(),
+                    is_final: true,
+                    pair: "BTC_USDT".into(),
+                    event_time: ts(),
+                }),
+                format!(
+                    r#"{{"type":"BookCandle","bid":{c},"ask":{c},"mid":{c},"is_final":true,"pair":"BTC_USDT","event_time":"2020-09-13T12:26:40Z"}}"#,
+                    c = CANDLE_JSON
+                ),
+            ),
+            (
+                MarketEvent::FundingRate(FundingRate {
+                    event_ms: 1_600_000_000_000,
+                    pair: "BTC_USDT".into(),
+                    rate: 0.0001,
+                    next_funding_ms: 1_600_028_800_000,
+                    mark_price: Some(100.5),
+                }),
+                r#"{"type":"FundingRate","event_ms":1600000000000,"pair":"BTC_USDT","rate":0.0001,"next_funding_ms":1600028800000,"mark_price":100.5}"#.to_string(),
+            ),
+            (
+                MarketEvent::OrderbookL3(l3(1, 2)),
+                r#"{"type":"OrderbookL3","event_ms":1600000000000,"pair":"BTC_USDT","first_update_id":1,"last_update_id":2,"is_snapshot":false,"changes":[{"side":"Buy","price":100.5,"qty":0.0,"order_id":null}]}"#.to_string(),
+            ),
+            (
+                MarketEvent::Quote(Quote {
+                    event_ms: 1_600_000_000_000,
+                    pair: "BTC_USDT".into(),
+                    bid: 100.0,
+                    bid_qty: 2.0,
+                    ask: 100.5,
+                    ask_qty: 1.0,
+                }),
+                r#"{"type":"Quote","event_ms":1600000000000,"pair":"BTC_USDT","bid":100.0,"bid_qty":2.0,"ask":100.5,"ask_qty":1.0}"#.to_string(),
+            ),
+            (
+                MarketEvent::OpenInterest(OpenInterest {
+                    event_ms: 1_600_000_000_000,
+                    pair: "BTC_USDT".into(),
+                    open_interest: 1234.5,
+                }),
+                r#"{"type":"OpenInterest","event_ms":1600000000000,"pair":"BTC_USDT","open_interest":1234.5}"#.to_string(),
+            ),
+        ]
+    }
+
+    #[test]
+    fn market_event_wire_format() {
+        for (event, golden) in golden_events() {
+            assert_eq!(serde_json::to_string(&event).unwrap(), golden);
+            let decoded: MarketEvent = serde_json::from_str(&golden).unwrap();
+            assert_eq!(decoded, event);
+        }
+    }
+
+    #[test]
+    fn market_event_envelope_wire_format() {
+        let envelope = MarketEventEnvelope {
+            symbol: Symbol::new("BTC_USDT".into(), SecurityType::Crypto, Exchange::Binance),
+            trace_id: Uuid::nil(),
+            ts: ts(),
+            e: trade(),
+            sec_type: SecurityType::Crypto,
+        };
+        let golden = r#"{"type":"MarketEventEnvelope","symbol":{"type":"crypto","date":"1970-01-01T00:00:00Z","value":"BTC_USDT","xch":"binance","strike_price":null,"option_type":null},"trace_id":"00000000-0000-0000-0000-000000000000","ts":"2020-09-13T12:26:40Z","e":{"type":"Trade","event_ms":1600000000000,"pair":"BTC_USDT","amount":0.5,"price":100.5,"tt":"Buy","aggressor":null},"sec_type":"crypto"}"#;
+        assert_eq!(serde_json::to_string(&envelope).unwrap(), golden);
+        let decoded: MarketEventEnvelope = serde_json::from_str(golden).unwrap();
+        assert_eq!(decoded, envelope);
+    }
+
+    #[test]
+    fn order_book_checksums() {
+        let mut book = LiveAggregatedOrderBook::default("BTC_USDT".into());
+        book.reset_asks_n(vec![(3366.8, 9.0), (3368.0, 8.0)].into_iter());
+        book.reset_bids_n(vec![(3366.1, 7.0), (3366.0, 6.0)].into_iter());
+        // crc32 of "3366.1:7:3366.8:9:3366:6:3368:8"
+        assert_eq!(book.checksum(BookChecksum::Okx), 2_413_953_002);
+        // The strings sent by the exchange are hashed, trailing zeros included
+        book.record_raw_asks(vec![("3366.8".to_string(), "9.00".to_string())].into_iter());
+        book.record_raw_bids(vec![("3366.10".to_string(), "7".to_string())].into_iter());
+        assert_eq!(
+            book.checksum(BookChecksum::Okx),
+            crc32fast::hash(b"3366.10:7:3366.8:9.00:3366:6:3368:8")
+        );
+    }
+
+    #[test]
+    fn order_book_l3_sequence() {
+        // Overlapping the last update, as the first diff after a snapshot
+        assert!(l3(90, 110).continues(100));
+        assert!(l3(101, 110).continues(100));
+        // Already applied
+        assert!(!l3(90, 100).continues(100));
+        // Gap
+        assert!(!l3(102, 110).continues(100));
+    }
+}
@@ -1,14 +1,19 @@
 //! Types definition used for handling returned data when generic API is used.
 
 mod account;
+mod aggregation;
 mod balance;
+mod capabilities;
 mod common;
 mod margin;
 mod market;
 mod order;
+pub mod schema;
 
 pub use account::*;
+pub use aggregation::*;
 pub use balance::*;
+pub use capabilities::*;
 pub use common::*;
 pub use margin::*;
 pub use market::*;
@@ -1,5 +1,6 @@
 use std::str::FromStr;
 
+use itertools::Itertools;
 use rust_decimal::prelude::{FromPrimitive, ToPrimitive};
 use rust_decimal::{Decimal, RoundingStrategy};
 use uuid::Uuid;
@@ -8,7 +9,7 @@ use crate::error;
 use crate::error::Error;
 use crate::exchange::Exchange;
 use crate::pair::{step_precision, PairConf};
-use crate::types::margin::MarginSideEffect;
+use crate::types::margin::{MarginLoanRequest, MarginSideEffect};
 use crate::types::{Asset, Pair};
 
 #[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
@@ -20,6 +21,35 @@ pub struct OrderFill {
     pub fee_asset: Asset,
 }
 
+/// A trade executed by the account, as reported by the broker, with the actual commission paid
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+pub struct TradeFill {
+    pub id: String,
+    /// Identifier of the order with the remote platform
+    pub order_id: String,
+    pub pair: Pair,
+    pub price: f64,
+    pub qty: f64,
+    pub fee: f64,
+    pub fee_asset: Asset,
+    pub side: TradeType,
+    pub is_maker: bool,
+    /// UNIX timestamp in ms
+    pub time: i64,
+}
+
+impl From<TradeFill> for OrderFill {
+    fn from(t: TradeFill) -> Self {
+        Self {
+            id: Some(t.id),
+            price: t.price,
+            qty: t.qty,
+            fee: t.fee,
+            fee_asset: t.fee_asset,
+        }
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub struct OrderSubmission {
     /// UNIX timestamp in ms (when the response was received)
@@ -75,6 +105,8 @@ pub enum OrderType {
     TakeProfit,
     TakeProfitLimit,
     LimitMaker,
+    /// Market order triggered once the price retraces from its best level by the trailing delta
+    TrailingStop,
 }
 
 impl OrderType {
@@ -100,6 +132,17 @@ impl Default for TradeType {
     fn default() -> Self { Self::Buy }
 }
 
+impl TradeType {
+    /// The side that initiated a trade (the taker), given whether the buyer was the maker
+    pub fn aggressor(is_buyer_maker: bool) -> Self {
+        if is_buyer_maker {
+            TradeType::Sell
+        } else {
+            TradeType::Buy
+        }
+    }
+}
+
 impl From<String> for TradeType {
     fn from(s: String) -> Self {
         match s.to_lowercase().as_str() {
@@ -171,38 +214,333 @@ impl OrderStatus {
 #[rtype(result = "()")]
 pub enum OrderQuery {
     AddOrder(AddOrderRequest),
+    AddOcoOrder(OcoOrderRequest),
+    AddBracketOrder(BracketOrderRequest),
+    AmendOrder(AmendOrderRequest),
+    /// Independent orders submitted together, in a single request where the exchange allows it
+    AddOrders(Vec<AddOrderRequest>),
+    /// Borrow an asset on a margin account
+    Borrow(MarginLoanRequest),
+    /// Repay a margin loan
+    Repay(MarginLoanRequest),
 }
 
 impl OrderQuery {
+    /// The order id, or the list id for queries made of several orders, loans have the id of the order they fund
     pub fn id(&self) -> String {
         match self {
             Self::AddOrder(req) => req.order_id.clone(),
+            Self::AddOcoOrder(req) => req.list_id.clone(),
+            Self::AddBracketOrder(req) => req.list_id.clone(),
+            Self::AmendOrder(req) => req.order_id.clone(),
+            Self::AddOrders(reqs) => reqs
+                .first()
+                .and_then(|req| req.transaction_id.clone())
+                .unwrap_or_default(),
+            Self::Borrow(req) | Self::Repay(req) => req.order_id.clone(),
         }
     }
 
     pub fn xch(&self) -> Exchange {
         match self {
-            Self::AddOrder(req) => req.xch,
+            Self::AmendOrder(req) => req.xch,
+            Self::Borrow(req) | Self::Repay(req) => req.xch,
+            _ => self.legs()[0].xch,
         }
     }
 
+    /// The pair of the first order, loans have the pair of their isolated account or the borrowed asset
     pub fn pair(&self) -> Pair {
         match self {
-            Self::AddOrder(req) => req.pair.clone(),
+            Self::AmendOrder(req) => req.pair.clone(),
+            Self::Borrow(req) | Self::Repay(req) => req.isolated_pair.clone().unwrap_or_else(|| req.asset.clone()),
+            _ => self.legs()[0].pair.clone(),
         }
     }
 
+    pub fn account(&self) -> Option<&str> {
+        match self {
+            Self::AmendOrder(req) => req.account.as_deref(),
+            Self::Borrow(req) | Self::Repay(req) => req.account.as_deref(),
+            _ => self.legs()[0].account.as_deref(),
+        }
+    }
+
+    /// Whether this query borrows or repays an asset instead of placing orders
+    pub fn is_loan(&self) -> bool { matches!(self, Self::Borrow(_) | Self::Repay(_)) }
+
+    /// Every new order of this query, the first one being the one that opens the position
+    pub fn legs(&self) -> Vec<&AddOrderRequest> {
+        match self {
+            Self::AddOrder(req) => vec![req],
+            Self::AddOcoOrder(req) => vec![&req.limit, &req.stop],
+            Self::AddBracketOrder(req) => vec![&req.entry, &req.take_profit, &req.stop_loss],
+            Self::AmendOrder(_) | Self::Borrow(_) | Self::Repay(_) => vec![],
+            Self::AddOrders(reqs) => reqs.iter().collect(),
+        }
+    }
+
+    /// Whether this query links several orders together
+    pub fn is_order_list(&self) -> bool {
+        matches!(
+            self,
+            Self::AddOcoOrder(_) | Self::AddBracketOrder(_) | Self::AddOrders(_)
+        )
+    }
+
+    /// Marks every order of a list as part of the list transaction, batches without a group id are given a new one
+    pub fn link_legs(mut self) -> Self {
+        let list_id = match self.id() {
+            id if id.is_empty() && matches!(self, Self::AddOrders(_)) => Uuid::new_v4().to_string(),
+            id => id,
+        };
+        let legs = match &mut self {
+            Self::AddOrder(_) | Self::AmendOrder(_) | Self::Borrow(_) | Self::Repay(_) => vec![],
+            Self::AddOcoOrder(req) => vec![&mut req.limit, &mut req.stop],
+            Self::AddBracketOrder(req) => vec![&mut req.entry, &mut req.take_profit, &mut req.stop_loss],
+            Self::AddOrders(reqs) => reqs.iter_mut().collect(),
+        };
+        for leg in legs {
+            leg.transaction_id = Some(list_id.clone());
+        }
+        self
+    }
+
     pub fn validate(&self) -> error::Result<()> {
         match self {
             Self::AddOrder(req) => req.validate(),
+            Self::AddOcoOrder(req) => req.validate(),
+            Self::AddBracketOrder(req) => req.validate(),
+            Self::AmendOrder(req) => req.validate(),
+            Self::AddOrders(reqs) => validate_batch(reqs),
+            Self::Borrow(req) | Self::Repay(req) => req.validate(),
+        }
+    }
+
+    /// Validate the orders against the market filters of the exchange, only the orders on the pair of `pair_conf`
+    /// are validated for batches, which can span several pairs
+    pub fn validate_with_conf(&self, pair_conf: &PairConf) -> error::Result<()> {
+        match self {
+            Self::AmendOrder(req) => req.validate(),
+            Self::Borrow(req) | Self::Repay(req) => req.validate(),
+            _ => self
+                .legs()
+                .into_iter()
+                .filter(|req| !matches!(self, Self::AddOrders(_)) || req.pair == pair_conf.pair)
+                .try_for_each(|req| req.validate_with_conf(pair_conf)),
         }
     }
 
     pub fn truncate(&self, pair_conf: &PairConf) -> Self {
         match self {
             Self::AddOrder(req) => Self::AddOrder(req.truncate(pair_conf)),
+            Self::AddOcoOrder(req) => Self::AddOcoOrder(req.truncate(pair_conf)),
+            Self::AddBracketOrder(req) => Self::AddBracketOrder(BracketOrderRequest {
+                list_id: req.list_id.clone(),
+                entry: req.entry.truncate(pair_conf),
+                take_profit: req.take_profit.truncate(pair_conf),
+                stop_loss: req.stop_loss.truncate(pair_conf),
+            }),
+            Self::AmendOrder(req) => Self::AmendOrder(req.truncate(pair_conf)),
+            Self::AddOrders(reqs) => Self::AddOrders(
+                reqs.iter()
+                    .map(|req| {
+                        if req.pair == pair_conf.pair {
+                            req.truncate(pair_conf)
+                        } else {
+                            req.clone()
+                        }
+                    })
+                    .collect(),
+            ),
+            Self::Borrow(_) | Self::Repay(_) => self.clone(),
+        }
+    }
+}
+
+/// Orders of a batch are placed with the same account of a single exchange, and share their group id if any
+fn validate_batch(reqs: &[AddOrderRequest]) -> error::Result<()> {
+    let first = reqs
+        .first()
+        .ok_or_else(|| Error::InvalidOrderList("a batch requires at least one order".to_string()))?;
+    for req in reqs {
+        req.validate()?;
+        if req.xch != first.xch || req.account != first.account {
+            return Err(Error::InvalidOrderList(
+                "orders of a batch must be placed with the same account".to_string(),
+            ));
+        }
+        if req.transaction_id != first.transaction_id {
+            return Err(Error::InvalidOrderList(
+                "orders of a batch must share the same group id".to_string(),
+            ));
         }
     }
+    if !reqs.iter().map(|req| &req.order_id).all_unique() {
+        return Err(Error::InvalidOrderList(
+            "orders of a batch must have distinct ids".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Order amendment, changes the price or the quantity of a resting order in place
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Default)]
+pub struct AmendOrderRequest {
+    pub xch: Exchange,
+    pub pair: Pair,
+    /// Id of the order to amend
+    pub order_id: String,
+    /// New limit price, unchanged if not set
+    pub price: Option<f64>,
+    /// New quantity, unchanged if not set
+    pub quantity: Option<f64>,
+    /// Asset Type, Spot by default
+    pub asset_type: Option<AssetType>,
+    /// Named account the order was placed with, the main account of the exchange if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub account: Option<String>,
+}
+
+impl AmendOrderRequest {
+    pub fn validate(&self) -> error::Result<()> {
+        if self.pair.is_empty() {
+            return Err(Error::EmptyPair);
+        }
+        if self.price.is_none() && self.quantity.is_none() {
+            return Err(Error::InvalidArguments);
+        }
+        if self.quantity.map_or(false, |qty| qty <= 0.0) {
+            return Err(Error::InvalidQty);
+        }
+        if self.price.map_or(false, |price| price <= 0.0) {
+            return Err(Error::InvalidPrice);
+        }
+        Ok(())
+    }
+
+    pub fn truncate(&self, pair_conf: &PairConf) -> Self {
+        let mut new = self.clone();
+        new.quantity = new.quantity.map(|q| round_qty(q, pair_conf, RoundingStrategy::ToZero));
+        new
+    }
+
+    /// The submission of the amended order, exchanges only acknowledge amendments with the remote order id
+    pub fn acknowledge(&self, remote_id: String) -> OrderSubmission {
+        OrderSubmission {
+            timestamp: util::time::get_unix_timestamp_ms(),
+            id: remote_id,
+            pair: self.pair.clone(),
+            client_id: self.order_id.clone(),
+            price: self.price.unwrap_or(0.0),
+            qty: self.quantity.unwrap_or(0.0),
+            status: OrderStatus::New,
+            asset_type: self.asset_type.unwrap_or(AssetType::Spot),
+            ..OrderSubmission::default()
+        }
+    }
+}
+
+/// One-cancels-other order, a limit order and a stop loss order on the same side, the exchange cancels
+/// the remaining order as soon as the other one executes
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Default)]
+pub struct OcoOrderRequest {
+    /// A unique id for the order list, used as the transaction id of both orders
+    pub list_id: String,
+    /// The limit order, usually taking profit
+    pub limit: AddOrderRequest,
+    /// The stop loss order, the stop loss limit price is the price of this order
+    pub stop: AddOrderRequest,
+}
+
+impl OcoOrderRequest {
+    pub fn validate(&self) -> error::Result<()> {
+        self.limit.validate()?;
+        self.stop.validate()?;
+        if self.limit.xch != self.stop.xch || self.limit.pair != self.stop.pair || self.limit.side != self.stop.side {
+            return Err(Error::InvalidOrderList(
+                "both orders should be on the same market and side".to_string(),
+            ));
+        }
+        if self.limit.quantity != self.stop.quantity {
+            return Err(Error::InvalidOrderList(
+                "both orders should have the same quantity".to_string(),
+            ));
+        }
+        if !matches!(self.limit.order_type, OrderType::Limit | OrderType::LimitMaker) {
+            return Err(Error::InvalidOrderList(
+                "the limit order should be a limit order".to_string(),
+            ));
+        }
+        if !matches!(self.stop.order_type, OrderType::StopLoss | OrderType::StopLossLimit) {
+            return Err(Error::InvalidOrderList(
+                "the stop order should be a stop loss order".to_string(),
+            ));
+        }
+        let (limit_price, stop_price) = match (self.limit.price, self.stop.stop_price) {
+            (Some(limit_price), Some(stop_price)) => (limit_price, stop_price),
+            _ => return Err(Error::MissingPrice),
+        };
+        // Selling takes profit above the stop, buying below it
+        let ordered = match self.limit.side {
+            TradeType::Sell => limit_price > stop_price,
+            TradeType::Buy => limit_price < stop_price,
+        };
+        if !ordered {
+            return Err(Error::InvalidOrderList(format!(
+                "limit price {} and stop price {} are on the wrong side of each other",
+                limit_price, stop_price
+            )));
+        }
+        Ok(())
+    }
+
+    pub fn truncate(&self, pair_conf: &PairConf) -> Self {
+        Self {
+            list_id: self.list_id.clone(),
+            limit: self.limit.truncate(pair_conf),
+            stop: self.stop.truncate(pair_conf),
+        }
+    }
+}
+
+/// Bracket order, an entry order followed by a take profit and a stop loss order which are only
+/// placed as a one-cancels-other order once the entry is filled
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Default)]
+pub struct BracketOrderRequest {
+    /// A unique id for the order list, used as the transaction id of all three orders
+    pub list_id: String,
+    pub entry: AddOrderRequest,
+    pub take_profit: AddOrderRequest,
+    pub stop_loss: AddOrderRequest,
+}
+
+impl BracketOrderRequest {
+    /// The exit orders, to place once the entry is filled
+    pub fn exits(&self) -> OcoOrderRequest {
+        OcoOrderRequest {
+            list_id: self.list_id.clone(),
+            limit: self.take_profit.clone(),
+            stop: self.stop_loss.clone(),
+        }
+    }
+
+    pub fn validate(&self) -> error::Result<()> {
+        self.entry.validate()?;
+        let exits = self.exits();
+        exits.validate()?;
+        if exits.limit.xch != self.entry.xch || exits.limit.pair != self.entry.pair {
+            return Err(Error::InvalidOrderList(
+                "exit orders should be on the same market as the entry".to_string(),
+            ));
+        }
+        if exits.limit.side == self.entry.side {
+            return Err(Error::InvalidOrderList(
+                "exit orders should be on the opposite side of the entry".to_string(),
+            ));
+        }
+        Ok(())
+    }
 }
 
 /// Order Request
@@ -233,6 +571,22 @@ pub struct AddOrderRequest {
     pub asset_type: Option<AssetType>,
     /// Side effect, for AssetType::Margin
     pub side_effect_type: Option<MarginSideEffect>,
+    /// Reject limit orders that would cross the book instead of filling them as a taker
+    #[serde(default)]
+    pub post_only: bool,
+    /// Named account to place the order with, the main account of the exchange if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub account: Option<String>,
+    /// Used with trailing stop orders, how far the price can retrace from its best level as a fraction of the price,
+    /// the stop price being the activation price
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trailing_delta: Option<f64>,
+    /// Position the order applies to for futures accounts in hedge mode, the net position if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub position_side: Option<PositionSide>,
+    /// Resting orders older than this many milliseconds are canceled, they rest until filled if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_age_ms: Option<i64>,
 }
 
 impl AddOrderRequest {
@@ -251,39 +605,84 @@ impl AddOrderRequest {
         if self.quantity.filter(|&qty| qty >= 0.0).is_none() {
             return Err(Error::InvalidQty);
         }
+        if self.post_only && !matches!(self.order_type, OrderType::Limit | OrderType::LimitMaker) {
+            return Err(Error::InvalidArguments);
+        }
+        if self.order_type == OrderType::TrailingStop
+            && self
+                .trailing_delta
+                .filter(|&delta| delta > 0.0 && delta < 1.0)
+                .is_none()
+        {
+            return Err(Error::InvalidArguments);
+        }
+        Ok(())
+    }
+
+    /// The order type to send to the exchange, post only limit orders become [`OrderType::LimitMaker`]
+    pub fn effective_order_type(&self) -> OrderType {
+        match self.order_type {
+            OrderType::Limit if self.post_only => OrderType::LimitMaker,
+            order_type => order_type,
+        }
+    }
+
+    /// Validate the request against the market filters of the exchange
+    pub fn validate_with_conf(&self, pair_conf: &PairConf) -> error::Result<()> {
+        if let Some(iceberg_qty) = self.iceberg_qty {
+            if !matches!(
+                self.effective_order_type(),
+                OrderType::Limit | OrderType::LimitMaker | OrderType::StopLossLimit | OrderType::TakeProfitLimit
+            ) {
+                return Err(Error::InvalidIcebergQty(
+                    "only limit orders can be iceberg orders".to_string(),
+                ));
+            }
+            if iceberg_qty <= 0.0 || self.quantity.map_or(true, |qty| iceberg_qty >= qty) {
+                return Err(Error::InvalidIcebergQty(format!(
+                    "{} should be positive and lower than the order quantity",
+                    iceberg_qty
+                )));
+            }
+            if let Some(min_qty) = pair_conf.min_qty.filter(|&min_qty| iceberg_qty < min_qty) {
+                return Err(Error::InvalidIcebergQty(format!(
+                    "{} is lower than the minimum lot size {}",
+                    iceberg_qty, min_qty
+                )));
+            }
+            if let (Some(min_size), Some(price)) = (pair_conf.min_size, self.price) {
+                if iceberg_qty * price < min_size {
+                    return Err(Error::InvalidIcebergQty(format!(
+                        "visible notional {} is lower than the minimum notional {}",
+                        iceberg_qty * price,
+                        min_size
+                    )));
+                }
+            }
+        }
         Ok(())
     }
 
-    #[allow(clippy::cast_possible_wrap, clippy::cast_sign_loss)]
     pub fn truncate(&self, pair_conf: &PairConf) -> Self {
         // Change precision if the symbol information exists
         let mut new = self.clone();
-        new.quantity = new.quantity.map(|q| {
-            let precision = pair_conf
-                .step_qty
-                .and_then(|step_size| step_precision(step_size, '1'))
-                .or_else(|| pair_conf.base_precision.map(|p| p as i32));
-            if let Some(precision) = precision {
-                Decimal::from_f64(q)
-                    .and_then(|d| {
-                        let rounded = d.round_dp_with_strategy(precision as u32, match self.asset_type {
-                            Some(AssetType::Margin | AssetType::IsolatedMargin) => match self.side {
-                                TradeType::Sell => RoundingStrategy::ToZero,
-                                TradeType::Buy => RoundingStrategy::AwayFromZero,
-                            },
-                            // Some(AssetType::Spot)
-                            _ => RoundingStrategy::ToZero,
-                        });
-                        rounded.to_f64()
-                    })
-                    .unwrap_or(q)
-            } else {
-                q
-            }
-        });
+        new.quantity = new.quantity.map(|q| self.truncate_qty(q, pair_conf));
+        new.iceberg_qty = new.iceberg_qty.map(|q| self.truncate_qty(q, pair_conf));
         new
     }
 
+    fn truncate_qty(&self, q: f64, pair_conf: &PairConf) -> f64 {
+        let strategy = match self.asset_type {
+            Some(AssetType::Margin | AssetType::IsolatedMargin) => match self.side {
+                TradeType::Sell => RoundingStrategy::ToZero,
+                TradeType::Buy => RoundingStrategy::AwayFromZero,
+            },
+            // Some(AssetType::Spot)
+            _ => RoundingStrategy::ToZero,
+        };
+        round_qty(q, pair_conf, strategy)
+    }
+
     pub fn with_dry(mut self) -> Self {
         self.dry_run = true;
         self
@@ -384,6 +783,18 @@ pub enum AssetType {
 
 impl AssetType {
     pub fn is_margin(&self) -> bool { matches!(self, Self::IsolatedMargin | Self::MarginFunding | Self::Margin) }
+
+    /// Contracts that exchange periodic funding between longs and shorts
+    pub fn is_funded(&self) -> bool {
+        matches!(
+            self,
+            Self::PerpetualContract
+                | Self::PerpetualSwap
+                | Self::Futures
+                | Self::CoinMarginedFutures
+                | Self::UsdtMarginedFutures
+        )
+    }
 }
 
 impl Default for AssetType {
@@ -410,6 +821,22 @@ pub enum OrderRejectionReason {
     OrderWouldTriggerImmediatly,
 }
 
+/// Round a quantity to the lot size of the market
+#[allow(clippy::cast_possible_wrap, clippy::cast_sign_loss)]
+fn round_qty(q: f64, pair_conf: &PairConf, strategy: RoundingStrategy) -> f64 {
+    let precision = pair_conf
+        .step_qty
+        .and_then(|step_size| step_precision(step_size, '1'))
+        .or_else(|| pair_conf.base_precision.map(|p| p as i32));
+    if let Some(precision) = precision {
+        Decimal::from_f64(q)
+            .and_then(|d| d.round_dp_with_strategy(precision as u32, strategy).to_f64())
+            .unwrap_or(q)
+    } else {
+        q
+    }
+}
+
 /// How an order will be resolved
 #[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Copy, EnumString)]
 pub enum OrderEnforcement {
@@ -423,6 +850,23 @@ pub enum OrderEnforcement {
     GTX,
 }
 
+/// Position of a futures account an order applies to, accounts in hedge mode hold a long and a short position on
+/// the same market while accounts in one-way mode hold a single net position
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord, Hash, Copy, EnumString, AsRefStr)]
+pub enum PositionSide {
+    /// The net position of one-way mode
+    #[strum(serialize = "both")]
+    Both,
+    #[strum(serialize = "long")]
+    Long,
+    #[strum(serialize = "short")]
+    Short,
+}
+
+impl Default for PositionSide {
+    fn default() -> Self { Self::Both }
+}
+
 /// Order
 /// the latest state of an order
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -447,3 +891,233 @@ pub struct Order {
     pub orig_quote_order_qty: f64,
     pub asset_type: AssetType,
 }
+
+#[cfg(test)]
+mod test {
+    use crate::error::Error;
+    use crate::exchange::Exchange;
+    use crate::pair::PairConf;
+    use crate::types::{AddOrderRequest, AmendOrderRequest, AssetType, BracketOrderRequest, OcoOrderRequest, Order,
+                       OrderEnforcement, OrderStatus, OrderType, TradeType};
+
+    fn iceberg_request(iceberg_qty: f64) -> AddOrderRequest {
+        AddOrderRequest {
+            pair: "BTC_USDT".into(),
+            order_type: OrderType::Limit,
+            quantity: Some(1.0),
+            price: Some(100.0),
+            iceberg_qty: Some(iceberg_qty),
+            ..AddOrderRequest::default()
+        }
+    }
+
+    #[test]
+    fn test_iceberg_qty_validation() {
+        let pair_conf = PairConf {
+            min_qty: Some(0.01),
+            step_qty: Some(0.01),
+            min_size: Some(10.0),
+            ..PairConf::default()
+        };
+        assert!(iceberg_request(0.2).validate_with_conf(&pair_conf).is_ok());
+        // Below the lot size
+        assert_eq!(
+            iceberg_request(0.001).validate_with_conf(&pair_conf),
+            Err(Error::InvalidIcebergQty(String::new()))
+        );
+        // Below the minimum notional
+        assert_eq!(
+            iceberg_request(0.05).validate_with_conf(&pair_conf),
+            Err(Error::InvalidIcebergQty(String::new()))
+        );
+        // Not lower than the order quantity
+        assert_eq!(
+            iceberg_request(1.0).validate_with_conf(&pair_conf),
+            Err(Error::InvalidIcebergQty(String::new()))
+        );
+        let market_request = AddOrderRequest {
+            order_type: OrderType::Market,
+            ..iceberg_request(0.2)
+        };
+        assert_eq!(
+            market_request.validate_with_conf(&pair_conf),
+            Err(Error::InvalidIcebergQty(String::new()))
+        );
+        let truncated = iceberg_request(0.2345).truncate(&pair_conf);
+        assert_eq!(truncated.iceberg_qty, Some(0.23));
+    }
+
+    #[test]
+    fn order_wire_format() {
+        let order = Order {
+            xch: Exchange::Binance,
+            symbol: "BTC_USDT".into(),
+            order_id: "1".to_string(),
+            orig_order_id: "client".to_string(),
+            price: 100.5,
+            orig_qty: 1.0,
+            executed_qty: 0.5,
+            cumulative_quote_qty: 50.25,
+            status: OrderStatus::PartiallyFilled,
+            enforcement: OrderEnforcement::GTC,
+            order_type: OrderType::Limit,
+            side: TradeType::Buy,
+            stop_price: 0.0,
+            iceberg_qty: 0.0,
+            orig_time: 1_600_000_000_000,
+            last_event_time: 1_600_000_000_001,
+            is_in_transaction: false,
+            orig_quote_order_qty: 0.0,
+            asset_type: AssetType::Spot,
+        };
+        let golden = r#"{"xch":"binance","symbol":"BTC_USDT","order_id":"1","orig_order_id":"client","price":100.5,"orig_qty":1.0,"executed_qty":0.5,"cumulative_quote_qty":50.25,"status":"PartiallyFilled","enforcement":"GTC","order_type":"Limit","side":"Buy","stop_price":0.0,"iceberg_qty":0.0,"orig_time":1600000000000,"last_event_time":1600000000001,"is_in_transaction":false,"orig_quote_order_qty":0.0,"asset_type":"spot"}"#;
+        assert_eq!(serde_json::to_string(&order).unwrap(), golden);
+        let decoded: Order = serde_json::from_str(golden).unwrap();
+        assert_eq!(serde_json::to_string(&decoded).unwrap(), golden);
+    }
+
+    fn oco_request(side: TradeType, limit_price: f64, stop_price: f64) -> OcoOrderRequest {
+        let leg = AddOrderRequest {
+            pair: "BTC_USDT".into(),
+            side,
+            quantity: Some(1.0),
+            ..AddOrderRequest::default()
+        };
+        OcoOrderRequest {
+            list_id: "list".to_string(),
+            limit: AddOrderRequest {
+                order_type: OrderType::Limit,
+                price: Some(limit_price),
+                ..leg.clone()
+            },
+            stop: AddOrderRequest {
+                order_type: OrderType::StopLossLimit,
+                price: Some(stop_price),
+                stop_price: Some(stop_price),
+                ..leg
+            },
+        }
+    }
+
+    #[test]
+    fn amend_order_validation() {
+        let mut request = AmendOrderRequest {
+            pair: "BTC_USDT".into(),
+            order_id: "1".to_string(),
+            ..AmendOrderRequest::default()
+        };
+        assert_eq!(request.validate(), Err(Error::InvalidArguments));
+        request.price = Some(101.0);
+        assert!(request.validate().is_ok());
+        request.quantity = Some(0.0);
+        assert_eq!(request.validate(), Err(Error::InvalidQty));
+        request.quantity = Some(1.23456);
+        let pair_conf = PairConf {
+            step_qty: Some(0.01),
+            ..PairConf::default()
+        };
+        assert_eq!(request.truncate(&pair_conf).quantity, Some(1.23));
+    }
+
+    #[test]
+    fn batch_validation() {
+        let leg = AddOrderRequest {
+            pair: "BTC_USDT".into(),
+            order_type: OrderType::Market,
+            quantity: Some(1.0),
+            ..AddOrderRequest::default()
+        };
+        let batch = OrderQuery::AddOrders(vec![
+            AddOrderRequest {
+                order_id: "1".to_string(),
+                ..leg.clone()
+            },
+            AddOrderRequest {
+                order_id: "2".to_string(),
+                pair: "ETH_USDT".into(),
+                ..leg.clone()
+            },
+        ]);
+        assert!(batch.validate().is_ok());
+        let batch = batch.link_legs();
+        let group_id = batch.id();
+        assert!(!group_id.is_empty());
+        assert!(batch
+            .legs()
+            .iter()
+            .all(|leg| leg.transaction_id.as_ref() == Some(&group_id)));
+        assert!(matches!(
+            OrderQuery::AddOrders(vec![]).validate(),
+            Err(Error::InvalidOrderList(msg)) if msg.contains("at least one order")
+        ));
+        assert!(matches!(
+            OrderQuery::AddOrders(vec![leg.clone(), leg.clone()]).validate(),
+            Err(Error::InvalidOrderList(msg)) if msg.contains("distinct ids")
+        ));
+        let other_account = AddOrderRequest {
+            order_id: "2".to_string(),
+            account: Some("sub".to_string()),
+            ..leg.clone()
+        };
+        assert!(matches!(
+            OrderQuery::AddOrders(vec![leg.clone(), other_account]).validate(),
+            Err(Error::InvalidOrderList(msg)) if msg.contains("same account")
+        ));
+        let other_group = AddOrderRequest {
+            order_id: "2".to_string(),
+            transaction_id: Some("group".to_string()),
+            ..leg.clone()
+        };
+        assert!(matches!(
+            OrderQuery::AddOrders(vec![leg, other_group]).validate(),
+            Err(Error::InvalidOrderList(msg)) if msg.contains("same group id")
+        ));
+    }
+
+    #[test]
+    fn trailing_stop_validation() {
+        let mut request = AddOrderRequest {
+            pair: "BTC_USDT".into(),
+            order_type: OrderType::TrailingStop,
+            quantity: Some(1.0),
+            ..AddOrderRequest::default()
+        };
+        assert_eq!(request.validate(), Err(Error::InvalidArguments));
+        request.trailing_delta = Some(0.02);
+        assert!(request.validate().is_ok());
+        request.trailing_delta = Some(1.5);
+        assert_eq!(request.validate(), Err(Error::InvalidArguments));
+    }
+
+    #[test]
+    fn oco_and_bracket_validation() {
+        assert!(oco_request(TradeType::Sell, 110.0, 90.0).validate().is_ok());
+        assert!(oco_request(TradeType::Buy, 90.0, 110.0).validate().is_ok());
+        // Taking profit below the stop loss
+        assert_eq!(
+            oco_request(TradeType::Sell, 90.0, 110.0).validate(),
+            Err(Error::InvalidOrderList(String::new()))
+        );
+        let mut mismatched = oco_request(TradeType::Sell, 110.0, 90.0);
+        mismatched.stop.quantity = Some(2.0);
+        assert_eq!(mismatched.validate(), Err(Error::InvalidOrderList(String::new())));
+
+        let exits = oco_request(TradeType::Sell, 110.0, 90.0);
+        let mut bracket = BracketOrderRequest {
+            list_id: "list".to_string(),
+            entry: AddOrderRequest {
+                pair: "BTC_USDT".into(),
+                side: TradeType::Buy,
+                order_type: OrderType::Market,
+                quantity: Some(1.0),
+                ..AddOrderRequest::default()
+            },
+            take_profit: exits.limit,
+            stop_loss: exits.stop,
+        };
+        assert!(bracket.validate().is_ok());
+        assert_eq!(bracket.exits().limit.price, Some(110.0));
+        bracket.entry.side = TradeType::Sell;
+        assert_eq!(bracket.validate(), Err(Error::InvalidOrderList(String::new())));
+    }
+}
@@ -0,0 +1,604 @@
+//! Canonical schemas of the events exchanged between services, in the avro JSON schema format.
+//! Producers and consumers check the schema of their peers against these at startup, so that a change
+//! in the event types can't silently break a service reading them from NATS, Kafka or avro files.
+
+use std::collections::HashMap;
+
+use serde_json::{json, Value};
+use thiserror::Error;
+
+pub const MARKET_EVENT_ENVELOPE: &str = "MarketEventEnvelope";
+pub const ACCOUNT_EVENT_ENVELOPPE: &str = "AccountEventEnveloppe";
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum SchemaError {
+    #[error("unknown schema {0}")]
+    UnknownSchema(String),
+    #[error("unknown named type {0}")]
+    UnknownType(String),
+    #[error("field {0} is missing and has no default")]
+    MissingField(String),
+    #[error("{path} : {writer} cannot be read as {reader}")]
+    TypeMismatch {
+        path: String,
+        writer: String,
+        reader: String,
+    },
+    #[error("{path} : enum symbol {symbol} is unknown to the reader")]
+    MissingSymbol { path: String, symbol: String },
+}
+
+/// Whether this service writes or reads the events
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SchemaRole {
+    Producer,
+    Consumer,
+}
+
+fn symbol_schema() -> Value {
+    json!({
+        "type": "record",
+        "name": "Symbol",
+        "fields": [
+            {"name": "type", "type": {"type": "enum", "name": "SecurityType", "symbols": [
+                "equity", "option", "commodity", "forex", "future", "cfd", "crypto", "future_option", "index",
+                "index_option"
+            ]}},
+            {"name": "date", "type": "string"},
+            {"name": "value", "type": "string"},
+            {"name": "xch", "type": "string"},
+            {"name": "strike_price", "type": ["null", "double"], "default": null},
+            {"name": "option_type", "type": ["null", {"type": "enum", "name": "OptionType", "symbols": ["Call", "Put"]}], "default": null}
+        ]
+    })
+}
+
+fn candle_schema() -> Value {
+    json!({
+        "type": "record",
+        "name": "Candle",
+        "fields": [
+            {"name": "event_time", "type": "string"},
+            {"name": "pair", "type": "string"},
+            {"name": "start_time", "type": "string"},
+            {"name": "end_time", "type": "string"},
+            {"name": "open", "type": "double"},
+            {"name": "high", "type": "double"},
+            {"name": "low", "type": "double"},
+            {"name": "close", "type": "double"},
+            {"name": "volume", "type": "double"},
+            {"name": "quote_volume", "type": "double"},
+            {"name": "trade_count", "type": "long"},
+            {"name": "is_final", "type": "boolean"}
+        ]
+    })
+}
+
+/// Canonical schema of a serialized [`crate::types::MarketEventEnvelope`]
+pub fn market_event_envelope_schema() -> Value {
+    let offers = json!({"type": "array", "items": {"type": "array", "items": "double"}});
+    json!({
+        "type": "record",
+        "name": MARKET_EVENT_ENVELOPE,
+        "fields": [
+            {"name": "type", "type": "string"},
+            {"name": "symbol", "type": symbol_schema()},
+            {"name": "trace_id", "type": "string"},
+            {"name": "ts", "type": "string"},
+            {"name": "e", "type": [
+                {"type": "record", "name": "Trade", "fields": [
+                    {"name": "type", "type": "string"},
+                    {"name": "event_ms", "type": "long"},
+                    {"name": "pair", "type": "string"},
+                    {"name": "amount", "type": "double"},
+                    {"name": "price", "type": "double"},
+                    {"name": "tt", "type": {"type": "enum", "name": "TradeType", "symbols": ["Sell", "Buy"]}},
+                    {"name": "aggressor", "type": ["null", "TradeType"], "default": null}
+                ]},
+                {"type": "record", "name": "Orderbook", "fields": [
+                    {"name": "type", "type": "string"},
+                    {"name": "timestamp", "type": "long"},
+                    {"name": "pair", "type": "string"},
+                    {"name": "asks", "type": offers.clone()},
+                    {"name": "bids", "type": offers},
+                    {"name": "last_order_id", "type": ["null", "string"], "default": null}
+                ]},
+                {"type": "record", "name": "TradeCandle", "fields": [
+                    {"name": "type", "type": "string"},
+                    {"name": "event_time", "type": "string"},
+                    {"name": "pair", "type": "string"},
+                    {"name": "start_time", "type": "string"},
+                    {"name": "end_time", "type": "string"},
+                    {"name": "open", "type": "double"},
+                    {"name": "high", "type": "double"},
+                    {"name": "low", "type": "double"},
+                    {"name": "close", "type": "double"},
+                    {"name": "volume", "type": "double"},
+                    {"name": "quote_volume", "type": "double"},
+                    {"name": "trade_count", "type": "long"},
+                    {"name": "is_final", "type": "boolean"}
+                ]},
+                {"type": "record", "name": "BookCandle", "fields": [
+                    {"name": "type", "type": "string"},
+                    {"name": "bid", "type": candle_schema()},
+                    {"name": "ask", "type": "Candle"},
+                    {"name": "mid", "type": "Candle"},
+                    {"name": "is_final", "type": "boolean"},
+                    {"name": "pair", "type": "string"},
+                    {"name": "event_time", "type": "string"}
+                ]},
+                {"type": "record", "name": "FundingRate", "fields": [
+                    {"name": "type", "type": "string"},
+                    {"name": "event_ms", "type": "long"},
+                    {"name": "pair", "type": "string"},
+                    {"name": "rate", "type": "double"},
+                    {"name": "next_funding_ms", "type": "long"},
+                    {"name": "mark_price", "type": ["null", "double"], "default": null}
+                ]},
+                {"type": "record", "name": "OrderbookL3", "fields": [
+                    {"name": "type", "type": "string"},
+                    {"name": "event_ms", "type": "long"},
+                    {"name": "pair", "type": "string"},
+                    {"name": "first_update_id", "type": "long"},
+                    {"name": "last_update_id", "type": "long"},
+                    {"name": "is_snapshot", "type": "boolean"},
+                    {"name": "changes", "type": {"type": "array", "items": {"type": "record", "name": "BookChange", "fields": [
+                        {"name": "side", "type": "TradeType"},
+                        {"name": "price", "type": "double"},
+                        {"name": "qty", "type": "double"},
+                        {"name": "order_id", "type": ["null", "string"], "default": null}
+                    ]}}}
+                ]},
+                {"type": "record", "name": "Quote", "fields": [
+                    {"name": "type", "type": "string"},
+                    {"name": "event_ms", "type": "long"},
+                    {"name": "pair", "type": "string"},
+                    {"name": "bid", "type": "double"},
+                    {"name": "bid_qty", "type": "double"},
+                    {"name": "ask", "type": "double"},
+                    {"name": "ask_qty", "type": "double"}
+                ]},
+                {"type": "record", "name": "OpenInterest", "fields": [
+                    {"name": "type", "type": "string"},
+                    {"name": "event_ms", "type": "long"},
+                    {"name": "pair", "type": "string"},
+                    {"name": "open_interest", "type": "double"}
+                ]}
+            ]},
+            {"name": "sec_type", "type": "SecurityType"}
+        ]
+    })
+}
+
+/// Canonical schema of an [`crate::types::AccountEventEnveloppe`] sent over the wire
+pub fn account_event_enveloppe_schema() -> Value {
+    json!({
+        "type": "record",
+        "name": ACCOUNT_EVENT_ENVELOPPE,
+        "fields": [
+            {"name": "xchg", "type": "string"},
+            {"name": "account_type", "type": "string"},
+            {"name": "event", "type": [
+                {"type": "record", "name": "OrderUpdate", "fields": [
+                    {"name": "enforcement", "type": "string"},
+                    {"name": "side", "type": {"type": "enum", "name": "TradeType", "symbols": ["Sell", "Buy"]}},
+                    {"name": "orig_order_id", "type": ["null", "string"], "default": null},
+                    {"name": "order_id", "type": "long"},
+                    {"name": "symbol", "type": "string"},
+                    {"name": "timestamp", "type": "long"},
+                    {"name": "new_status", "type": "string"},
+                    {"name": "orig_status", "type": "string"},
+                    {"name": "is_on_the_book", "type": "boolean"},
+                    {"name": "qty", "type": "double"},
+                    {"name": "quote_qty", "type": "double"},
+                    {"name": "price", "type": "double"},
+                    {"name": "stop_price", "type": "double"},
+                    {"name": "iceberg_qty", "type": "double"},
+                    {"name": "commission", "type": "double"},
+                    {"name": "commission_asset", "type": ["null", "string"], "default": null},
+                    {"name": "last_executed_qty", "type": "double"},
+                    {"name": "cummulative_filled_qty", "type": "double"},
+                    {"name": "last_executed_price", "type": "double"},
+                    {"name": "cummulative_quote_asset_transacted_qty", "type": "double"},
+                    {"name": "last_quote_asset_transacted_qty", "type": "double"},
+                    {"name": "quote_order_qty", "type": "double"},
+                    {"name": "rejection_reason", "type": ["null", "string"], "default": null}
+                ]},
+                {"type": "record", "name": "BalanceUpdate", "fields": [
+                    {"name": "event_time", "type": "string"},
+                    {"name": "server_time", "type": "string"},
+                    {"name": "symbol", "type": "string"},
+                    {"name": "delta", "type": "double"},
+                    {"name": "clear_time", "type": "string"}
+                ]},
+                {"type": "record", "name": "AccountPosition", "fields": [
+                    {"name": "balances", "type": {"type": "map", "values": {"type": "record", "name": "Balance", "fields": [
+                        {"name": "free", "type": "double"},
+                        {"name": "locked", "type": "double"}
+                    ]}}},
+                    {"name": "update_time", "type": "string"}
+                ]},
+                "null"
+            ]}
+        ]
+    })
+}
+
+/// The canonical schema registered under `name`
+pub fn canonical_schema(name: &str) -> Option<Value> {
+    match name {
+        MARKET_EVENT_ENVELOPE => Some(market_event_envelope_schema()),
+        ACCOUNT_EVENT_ENVELOPPE => Some(account_event_enveloppe_schema()),
+        _ => None,
+    }
+}
+
+/// Check at startup that events can be exchanged with a peer using the `remote` schema for `name`
+///
+/// A producer checks that the peer can read what it writes, a consumer that it can read what the peer writes.
+pub fn ensure_compatible(name: &str, role: SchemaRole, remote: &Value) -> Result<(), SchemaError> {
+    let local = canonical_schema(name).ok_or_else(|| SchemaError::UnknownSchema(name.to_string()))?;
+    match role {
+        SchemaRole::Producer => check_compatibility(&local, remote),
+        SchemaRole::Consumer => check_compatibility(remote, &local),
+    }
+}
+
+/// Check that data written with the `writer` schema can be read with the `reader` schema,
+/// following avro schema resolution rules
+pub fn check_compatibility(writer: &Value, reader: &Value) -> Result<(), SchemaError> {
+    let mut writer_names = HashMap::new();
+    collect_names(writer, &mut writer_names);
+    let mut reader_names = HashMap::new();
+    collect_names(reader, &mut reader_names);
+    Resolver {
+        writer_names,
+        reader_names,
+    }
+    .check(writer, reader, "")
+}
+
+/// Check that a JSON value, as serialized by serde, matches the schema
+pub fn validate(schema: &Value, value: &Value) -> bool {
+    let mut names = HashMap::new();
+    collect_names(schema, &mut names);
+    validate_with(schema, value, &names)
+}
+
+fn collect_names(schema: &Value, names: &mut HashMap<String, Value>) {
+    match schema {
+        Value::Array(branches) => branches.iter().for_each(|b| collect_names(b, names)),
+        Value::Object(o) => {
+            if let Some(Value::String(name)) = o.get("name") {
+                if matches!(o.get("type"), Some(Value::String(t)) if t == "record" || t == "enum") {
+                    names.insert(name.clone(), schema.clone());
+                }
+            }
+            if let Some(Value::Array(fields)) = o.get("fields") {
+                fields
+                    .iter()
+                    .filter_map(|f| f.get("type"))
+                    .for_each(|t| collect_names(t, names));
+            }
+            for key in ["items", "values"] {
+                if let Some(t) = o.get(key) {
+                    collect_names(t, names);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+fn resolve<'a>(schema: &'a Value, names: &'a HashMap<String, Value>) -> Result<&'a Value, SchemaError> {
+    match schema {
+        Value::String(name) if !is_primitive(name) => {
+            names.get(name).ok_or_else(|| SchemaError::UnknownType(name.clone()))
+        }
+        _ => Ok(schema),
+    }
+}
+
+fn is_primitive(name: &str) -> bool {
+    matches!(
+        name,
+        "null" | "boolean" | "int" | "long" | "float" | "double" | "bytes" | "string"
+    )
+}
+
+fn type_name(schema: &Value) -> String {
+    match schema {
+        Value::String(s) => s.clone(),
+        Value::Array(_) => "union".to_string(),
+        Value::Object(o) => o
+            .get("name")
+            .or_else(|| o.get("type"))
+            .and_then(Value::as_str)
+            .unwrap_or("unknown")
+            .to_string(),
+        _ => "unknown".to_string(),
+    }
+}
+
+fn kind(schema: &Value) -> &str {
+    match schema {
+        Value::String(s) => s.as_str(),
+        Value::Array(_) => "union",
+        Value::Object(o) => o.get("type").and_then(Value::as_str).unwrap_or("unknown"),
+        _ => "unknown",
+    }
+}
+
+fn is_promotable(writer: &str, reader: &str) -> bool {
+    writer == reader
+        || matches!(
+            (writer, reader),
+            ("int", "long" | "float" | "double")
+                | ("long", "float" | "double")
+                | ("float", "double")
+                | ("string", "bytes")
+                | ("bytes", "string")
+        )
+}
+
+struct Resolver {
+    writer_names: HashMap<String, Value>,
+    reader_names: HashMap<String, Value>,
+}
+
+impl Resolver {
+    fn check(&self, writer: &Value, reader: &Value, path: &str) -> Result<(), SchemaError> {
+        let writer = resolve(writer, &self.writer_names)?;
+        let reader = resolve(reader, &self.reader_names)?;
+        let mismatch = || SchemaError::TypeMismatch {
+            path: path.to_string(),
+            writer: type_name(writer),
+            reader: type_name(reader),
+        };
+        match (writer, reader) {
+            (Value::Array(branches), _) => branches.iter().try_for_each(|b| self.check(b, reader, path)),
+            (_, Value::Array(branches)) => {
+                // Named types resolve to the reader branch of the same name, others to the first compatible branch
+                let name = type_name(writer);
+                if let Some(branch) = branches.iter().find(|b| type_name(b) == name) {
+                    self.check(writer, branch, path)
+                } else if branches.iter().any(|b| self.check(writer, b, path).is_ok()) {
+                    Ok(())
+                } else {
+                    Err(mismatch())
+                }
+            }
+            _ => match (kind(writer), kind(reader)) {
+                ("record", "record") => {
+                    let writer_fields = fields(writer);
+                    for field in fields(reader) {
+                        let name = field.get("name").and_then(Value::as_str).unwrap_or_default();
+                        let field_path = format!("{}.{}", path, name);
+                        match writer_fields
+                            .iter()
+                            .find(|f| f.get("name").and_then(Value::as_str) == Some(name))
+                        {
+                            Some(writer_field) => self.check(&writer_field["type"], &field["type"], &field_path)?,
+                            None if field.get("default").is_some() => {}
+                            None => return Err(SchemaError::MissingField(field_path)),
+                        }
+                    }
+                    Ok(())
+                }
+                ("enum", "enum") => {
+                    let reader_symbols = symbols(reader);
+                    match symbols(writer).into_iter().find(|s| !reader_symbols.contains(s)) {
+                        Some(symbol) if reader.get("default").is_none() => Err(SchemaError::MissingSymbol {
+                            path: path.to_string(),
+                            symbol: symbol.to_string(),
+                        }),
+                        _ => Ok(()),
+                    }
+                }
+                ("array", "array") => self.check(&writer["items"], &reader["items"], path),
+                ("map", "map") => self.check(&writer["values"], &reader["values"], path),
+                (w, r) if is_primitive(w) && is_primitive(r) && is_promotable(w, r) => Ok(()),
+                _ => Err(mismatch()),
+            },
+        }
+    }
+}
+
+fn fields(schema: &Value) -> Vec<&Value> {
+    schema
+        .get("fields")
+        .and_then(Value::as_array)
+        .map(|f| f.iter().collect())
+        .unwrap_or_default()
+}
+
+fn symbols(schema: &Value) -> Vec<&str> {
+    schema
+        .get("symbols")
+        .and_then(Value::as_array)
+        .map(|s| s.iter().filter_map(Value::as_str).collect())
+        .unwrap_or_default()
+}
+
+fn validate_with(schema: &Value, value: &Value, names: &HashMap<String, Value>) -> bool {
+    let Ok(schema) = resolve(schema, names) else {
+        return false;
+    };
+    if let Value::Array(branches) = schema {
+        return branches.iter().any(|b| validate_with(b, value, names));
+    }
+    match (kind(schema), value) {
+        ("null", Value::Null) | ("boolean", Value::Bool(_)) | ("string" | "bytes", Value::String(_)) => true,
+        ("int" | "long", Value::Number(n)) => n.is_i64() || n.is_u64(),
+        ("float" | "double", Value::Number(_)) => true,
+        ("enum", Value::String(s)) => symbols(schema).contains(&s.as_str()),
+        ("array", Value::Array(items)) => items.iter().all(|i| validate_with(&schema["items"], i, names)),
+        ("map", Value::Object(entries)) => entries.values().all(|v| validate_with(&schema["values"], v, names)),
+        ("record", Value::Object(entries)) => {
+            let fields = fields(schema);
+            let known = |k: &String| fields.iter().any(|f| f.get("name").and_then(Value::as_str) == Some(k));
+            entries.keys().all(known)
+                && fields.iter().all(|f| {
+                    let name = f.get("name").and_then(Value::as_str).unwrap_or_default();
+                    match entries.get(name) {
+                        Some(v) => validate_with(&f["type"], v, names),
+                        None => f.get("default").is_some(),
+                    }
+                })
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use crate::exchange::Exchange;
+    use crate::types::{BookChange, Candle, FundingRate, MarketEvent, MarketEventEnvelope, OpenInterest, OrderbookL3,
+                       SecurityType, Symbol, Trade, TradeType};
+
+    use super::{check_compatibility, ensure_compatible, market_event_envelope_schema, validate, SchemaError,
+                SchemaRole, ACCOUNT_EVENT_ENVELOPPE, MARKET_EVENT_ENVELOPE};
+
+    #[test]
+    fn market_event_envelope_matches_canonical_schema() {
+        let schema = market_event_envelope_schema();
+        let symbol = Symbol::new("BTC_USDT".into(), SecurityType::Crypto, Exchange::Binance);
+        let trade = MarketEventEnvelope::new(
+            symbol.clone(),
+            MarketEvent::Trade(Trade {
+                event_ms: 0,
+                pair: "BTC_USDT".into(),
+                amount: 1.0,
+                price: 100.0,
+                tt: TradeType::Buy,
+                aggressor: Some(TradeType::Sell),
+            }),
+        );
+        let candle = MarketEventEnvelope::new(
+            symbol.clone(),
+            MarketEvent::TradeCandle(Candle {
+                event_time: util::time::now(),
+                pair: "BTC_USDT".into(),
+                start_time: util::time::now(),
+                end_time: util::time::now(),
+                open: 1.0,
+                high: 1.0,
+                low: 1.0,
+                close: 1.0,
+                volume: 1.0,
+                quote_volume: 1.0,
+                trade_count: 1,
+                is_final: true,
+            }),
+        );
+        let funding_rate = MarketEventEnvelope::new(
+            symbol.clone(),
+            MarketEvent::FundingRate(FundingRate {
+                event_ms: 0,
+                pair: "BTC_USDT".into(),
+                rate: 0.0001,
+                next_funding_ms: 0,
+                mark_price: None,
+            }),
+        );
+        let orderbook_l3 = MarketEventEnvelope::new(
+            symbol.clone(),
+            MarketEvent::OrderbookL3(OrderbookL3 {
+                event_ms: 0,
+                pair: "BTC_USDT".into(),
+                first_update_id: 1,
+                last_update_id: 2,
+                is_snapshot: true,
+                changes: vec![BookChange {
+                    side: TradeType::Sell,
+                    price: 100.0,
+                    qty: 1.0,
+                    order_id: Some("1".to_string()),
+                }],
+            }),
+        );
+        let open_interest = MarketEventEnvelope::new(
+            symbol.clone(),
+            MarketEvent::OpenInterest(OpenInterest {
+                event_ms: 0,
+                pair: "BTC_USDT".into(),
+                open_interest: 1.0,
+            }),
+        );
+        let quote = MarketEventEnvelope::quote_event(symbol.clone(), 0, 0.9, 1.0, 1.0, 1.0);
+        let orderbook = MarketEventEnvelope::order_book_event(symbol, 0, vec![(1.0, 1.0)], vec![(0.9, 1.0)]);
+        for event in [
+            trade,
+            candle,
+            funding_rate,
+            orderbook_l3,
+            open_interest,
+            quote,
+            orderbook,
+        ] {
+            let value = serde_json::to_value(&event).unwrap();
+            assert!(validate(&schema, &value), "{}", value);
+        }
+    }
+
+    #[test]
+    fn known_good_schema_is_compatible() {
+        let schema = market_event_envelope_schema();
+        assert_eq!(check_compatibility(&schema, &schema), Ok(()));
+        assert_eq!(
+            ensure_compatible(MARKET_EVENT_ENVELOPE, SchemaRole::Producer, &schema),
+            Ok(())
+        );
+        let account_schema = super::account_event_enveloppe_schema();
+        assert_eq!(
+            ensure_compatible(ACCOUNT_EVENT_ENVELOPPE, SchemaRole::Consumer, &account_schema),
+            Ok(())
+        );
+
+        // A producer adding an optional field doesn't break older consumers
+        let mut extended = schema.clone();
+        extended["fields"]
+            .as_array_mut()
+            .unwrap()
+            .push(json!({"name": "source", "type": ["null", "string"], "default": null}));
+        assert_eq!(check_compatibility(&extended, &schema), Ok(()));
+        assert_eq!(check_compatibility(&schema, &extended), Ok(()));
+    }
+
+    #[test]
+    fn breaking_schema_change_is_detected() {
+        let schema = market_event_envelope_schema();
+
+        // A producer removing a required field
+        let mut removed = schema.clone();
+        removed["fields"]
+            .as_array_mut()
+            .unwrap()
+            .retain(|f| f["name"] != "trace_id");
+        assert_eq!(
+            ensure_compatible(MARKET_EVENT_ENVELOPE, SchemaRole::Consumer, &removed),
+            Err(SchemaError::MissingField(".trace_id".to_string()))
+        );
+
+        // A producer changing the type of a field
+        let mut retyped = schema.clone();
+        retyped["fields"][2]["type"] = json!("long");
+        assert!(matches!(
+            check_compatibility(&retyped, &schema),
+            Err(SchemaError::TypeMismatch { .. })
+        ));
+
+        // A producer sending an enum symbol consumers don't know about
+        let mut symbols = schema;
+        symbols["fields"][4]["type"][0]["fields"][5]["type"]["symbols"] = json!(["Sell", "Buy", "Short"]);
+        assert_eq!(
+            check_compatibility(&symbols, &market_event_envelope_schema()),
+            Err(SchemaError::MissingSymbol {
+                path: ".e.tt".to_string(),
+                symbol: "Short".to_string()
+            })
+        );
+    }
+}
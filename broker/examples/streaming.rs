@@ -14,6 +14,7 @@ extern crate tokio;
 use actix::{Actor, Context, Handler};
 use broker_core::exchange::Exchange::Binance;
 use broker_core::types::{MarketChannel, MarketChannelType, SecurityType, Symbol};
+use std::collections::HashMap;
 use std::sync::Arc;
 
 use brokers::broker::{ActixMessageBroker, Broker, MarketEventEnvelopeRef, Subject};
@@ -62,6 +63,7 @@ async fn main() {
             resolution: None,
             only_final: None,
             orderbook: None,
+            bars: None,
         }];
         let settings = BrokerSettings {
             fees: 0.01,
@@ -71,6 +73,11 @@ async fn main() {
             isolated_margin_account_pairs: vec![],
             use_test: false,
             market_channels: vec![],
+            rate_limits: HashMap::new(),
+            accounts: vec![],
+            dead_man_switch: Default::default(),
+            fee_tier_refresh_secs: 0,
+            pairs_refresh_secs: 0,
         };
 
         // Initialize the broker and a simple logging actor
@@ -1,4 +1,6 @@
-use std::sync::Arc;
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{Arc, RwLock};
 use std::time::Duration;
 
 use actix::io::SinkWrite;
@@ -18,27 +20,39 @@ use tokio_stream::wrappers::UnboundedReceiverStream;
 use url::Url;
 
 use crate::adapters::{from_binance_account_event, from_binance_error};
+use crate::api::{register_rate_limits, WEIGHT_ENDPOINT};
 use broker_core::error::*;
 use broker_core::json_util::deserialize_json_s;
 use broker_core::prelude::*;
+use broker_core::ratelimit;
+use broker_core::streaming_api::WsReconnectOptions;
+use broker_core::types::PrivateStreamChannel;
+
+/// Listen keys expire after 60 minutes without a keep alive, Binance recommends sending one every 30 minutes
+const KEEP_ALIVE_INTERVAL_MS: i64 = 30 * 60 * 1000;
 
 #[derive(Clone)]
 pub struct BinanceStreamingAccountApi {
     sink: UnboundedSender<AccountEventEnveloppe>,
     user_stream: UserStream,
     margin_stream: Margin,
-    listen_key: Option<String>,
-    //api: Arc<BinanceApi>,
+    /// The websocket endpoint, the listen key is appended to it
+    ws_url: Url,
+    listen_key: Arc<RwLock<String>>,
+    /// Last time the listen key was kept alive, in ms
+    last_keep_alive: Arc<AtomicI64>,
+    channels: HashSet<PrivateStreamChannel>,
     metrics: Arc<AccountMetrics>,
     pub account_type: AccountType,
 }
 
 impl BinanceStreamingAccountApi {
-    /// Create a new binance exchange bot, unavailable channels and currencies are ignored
+    /// Create a new binance account bot, streaming the events of `channels`
     pub async fn new_bot(
         creds: Box<dyn Credentials>,
         use_test: bool,
         account_type: AccountType,
+        channels: HashSet<PrivateStreamChannel>,
     ) -> Result<BotWrapper<DefaultWsActor, UnboundedReceiverStream<AccountEventEnveloppe>>> {
         let metrics = AccountMetrics::for_exchange(Exchange::Binance);
         let api_key = creds.get("api_key");
@@ -46,34 +60,34 @@ impl BinanceStreamingAccountApi {
         let config = if use_test { Config::testnet() } else { Config::default() };
         let stream = Binance::new_with_config(api_key.clone(), api_secret.clone(), &config);
         let margin_stream = Binance::new_with_config(api_key, api_secret, &config);
+        let ws_url = match account_type {
+            AccountType::Spot | AccountType::Margin | AccountType::IsolatedMargin(_) => config.ws_endpoint.as_ref(),
+            AccountType::CoinFutures | AccountType::UsdtFutures => config.futures_ws_endpoint.as_ref(),
+        };
+        register_rate_limits();
         let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
-        let mut api = BinanceStreamingAccountApi {
+        let api = BinanceStreamingAccountApi {
             sink: tx,
             user_stream: stream,
             margin_stream,
-            listen_key: None,
-            //api: Arc::new(BinanceApi::new(Box::new(*creds)).unwrap()),
+            ws_url: Url::parse(ws_url)?,
+            listen_key: Arc::new(RwLock::new(String::new())),
+            last_keep_alive: Arc::new(AtomicI64::new(get_unix_timestamp_ms())),
+            channels,
             metrics: Arc::new(metrics),
-            account_type: account_type.clone(),
+            account_type,
         };
-        let listen_key = api.listen_key().await?;
-        let ws_url = match account_type {
-            AccountType::Spot | AccountType::Margin | AccountType::IsolatedMargin(_) => config.ws_endpoint.as_ref(),
-            AccountType::CoinFutures | AccountType::UsdtFutures => config.futures_ws_endpoint.as_ref(),
-        };
-
-        let mut url = Url::parse(ws_url)?;
-        url.path_segments_mut()
-            .map_err(|_| Error::ParseUrl(url::ParseError::RelativeUrlWithoutBase))?
-            .push(binance::websockets::WS_ENDPOINT)
-            .push(&listen_key);
+        let listen_key = api.new_listen_key().await?;
+        let url = stream_url(&api.ws_url, &listen_key)?;
         info!("Binance connecting with the following key : {}", &listen_key);
-        api.listen_key = Some(listen_key.clone());
+        *api.listen_key.write().unwrap() = listen_key;
         let addr = DefaultWsActor::new(
             "BinanceAccountStream",
             url,
-            Some(Duration::from_secs(30)),
-            Some(Duration::from_secs(60)),
+            WsReconnectOptions::builder()
+                .conn_timeout(Duration::from_secs(30))
+                .stale_after(Duration::from_secs(60))
+                .build(),
             Arc::new(api),
         )
         .await?;
@@ -81,7 +95,8 @@ impl BinanceStreamingAccountApi {
         Ok(BotWrapper::new(addr, UnboundedReceiverStream::new(rx)))
     }
 
-    async fn listen_key(&self) -> Result<String> {
+    async fn new_listen_key(&self) -> Result<String> {
+        ratelimit::acquire(Exchange::Binance, WEIGHT_ENDPOINT, 2).await?;
         let answer = match self.account_type {
             AccountType::Spot => self.user_stream.start().await.map_err(from_binance_error)?,
             AccountType::Margin => self.margin_stream.start().await.map_err(from_binance_error)?,
@@ -95,23 +110,63 @@ impl BinanceStreamingAccountApi {
         Ok(answer.listen_key)
     }
 
+    /// Keep the listen key alive every [`KEEP_ALIVE_INTERVAL_MS`].
+    /// An expired listen key is replaced and an error is returned so that the socket reconnects with the new key.
     async fn keep_alive(&self) -> Result<Success> {
-        let listen_key = &self.listen_key.clone().unwrap();
+        let now = get_unix_timestamp_ms();
+        if now - self.last_keep_alive.load(Ordering::Relaxed) < KEEP_ALIVE_INTERVAL_MS {
+            return Ok(Success {});
+        }
+        ratelimit::acquire(Exchange::Binance, WEIGHT_ENDPOINT, 2).await?;
+        let listen_key = self.listen_key.read().unwrap().clone();
         let keep_alive = match self.account_type {
-            AccountType::Spot => self.user_stream.keep_alive(listen_key).await,
-            AccountType::Margin => self.margin_stream.keep_alive(listen_key).await,
-            AccountType::IsolatedMargin(ref pair) => self.margin_stream.keep_alive_isolated(listen_key, pair).await,
+            AccountType::Spot => self.user_stream.keep_alive(&listen_key).await,
+            AccountType::Margin => self.margin_stream.keep_alive(&listen_key).await,
+            AccountType::IsolatedMargin(ref pair) => self.margin_stream.keep_alive_isolated(&listen_key, pair).await,
             _ => return Err(Error::UnsupportedAccountType),
         };
         match keep_alive {
             Err(e @ binance::errors::Error::InvalidListenKey(_)) => {
-                //self.listen_key = self.listen_key().await.ok();
+                let listen_key = self.new_listen_key().await?;
+                info!(
+                    "Binance listen key expired, reconnecting with the following key : {}",
+                    &listen_key
+                );
+                *self.listen_key.write().unwrap() = listen_key;
+                self.last_keep_alive.store(get_unix_timestamp_ms(), Ordering::Relaxed);
                 Err(Error::ExchangeError(format!("{:?}", e)))
             }
-            Ok(s) => Ok(s),
-            _ => Ok(Success {}),
+            Ok(s) => {
+                self.last_keep_alive.store(now, Ordering::Relaxed);
+                Ok(s)
+            }
+            // Transient failures are retried on the next keep alive
+            Err(e) => {
+                warn!(err = ?e, "binance failed to keep the listen key alive");
+                Ok(Success {})
+            }
         }
     }
+
+    fn accepts(&self, event: &AccountEvent) -> bool {
+        match event {
+            AccountEvent::OrderUpdate(_) => self.channels.contains(&PrivateStreamChannel::Orders),
+            AccountEvent::BalanceUpdate(_) | AccountEvent::AccountPositionUpdate(_) => {
+                self.channels.contains(&PrivateStreamChannel::Balances)
+            }
+            AccountEvent::Noop => false,
+        }
+    }
+}
+
+/// The user data stream url of a listen key
+fn stream_url(ws_url: &Url, listen_key: &str) -> Result<Url> {
+    let mut url = ws_url.clone();
+    url.path_segments_mut()
+        .map_err(|_| Error::ParseUrl(url::ParseError::RelativeUrlWithoutBase))?
+        .push(binance::websockets::WS_ENDPOINT)
+        .push(listen_key);
+    Ok(url)
 }
 
 #[async_trait(?Send)]
@@ -125,7 +180,7 @@ impl WsHandler for BinanceStreamingAccountApi {
             Ok(we) => {
                 debug!("{:?}", &we);
                 let ae: AccountEvent = from_binance_account_event(we);
-                if !matches!(ae, AccountEvent::Noop) {
+                if self.accepts(&ae) {
                     self.broadcast(&ae);
                 }
             }
@@ -143,6 +198,9 @@ impl WsHandler for BinanceStreamingAccountApi {
         self.keep_alive().await?;
         Ok(())
     }
+
+    /// Binance closes user data streams after 24 hours, and the listen key may have been replaced since
+    fn url(&self) -> Option<Url> { stream_url(&self.ws_url, &self.listen_key.read().unwrap()).ok() }
 }
 
 impl BinanceStreamingAccountApi {
@@ -153,6 +211,7 @@ impl BinanceStreamingAccountApi {
                 xchg: Exchange::Binance,
                 event: v.clone(),
                 account_type: self.account_type.clone(),
+                account: None,
             })
             .is_err()
         {
@@ -160,3 +219,21 @@ impl BinanceStreamingAccountApi {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use url::Url;
+
+    #[test]
+    fn should_append_the_listen_key_to_the_stream_url() {
+        let url = super::stream_url(
+            &Url::parse("wss://stream.binance.com:9443").unwrap(),
+            "pqia91ma19a5s61cv6a81va65sdf19v8a65a1a5s61cv6a81va65sdf19v8a65a1",
+        )
+        .unwrap();
+        assert_eq!(
+            url.as_str(),
+            "wss://stream.binance.com:9443/ws/pqia91ma19a5s61cv6a81va65sdf19v8a65a1a5s61cv6a81va65sdf19v8a65a1"
+        );
+    }
+}
@@ -1,19 +1,25 @@
 use binance::account::OrderRequest;
 use binance::bool_to_string;
 use binance::errors::Error as BinanceError;
-use binance::rest_model::{Balance as BinanceBalance, Fill, IsolatedMarginAccountAsset, IsolatedMarginAccountDetails,
+use binance::futures::rest_model::OpenInterest as BinanceOpenInterest;
+use binance::rest_model::{string_or_float, Balance as BinanceBalance, Fill, IsolatedMarginAccountAsset,
+                          IsolatedMarginAccountDetails, KlineSummary,
                           MarginAccountDetails as BinanceMarginAccountDetails, MarginOrder, MarginOrderResult,
-                          MarginOrderState, Order as BinanceOrder, OrderResponse, OrderSide,
-                          OrderStatus as BinanceOrderStatus, OrderType as BinanceOrderType,
-                          SideEffectType as BinanceSideEffectType, TimeInForce, Transaction as BinanceTransaction,
-                          UserAsset};
-use binance::ws_model::{OrderUpdate as BinanceOrderUpdate, WebsocketEvent};
+                          MarginOrderState, Order as BinanceOrder, OrderBook as BinanceOrderBook, OrderResponse,
+                          OrderSide, OrderStatus as BinanceOrderStatus, OrderType as BinanceOrderType,
+                          SideEffectType as BinanceSideEffectType, SymbolPrice, SystemStatus as BinanceSystemStatus,
+                          TimeInForce, Transaction as BinanceTransaction, UserAsset};
+use binance::ws_model::{BookTickerEvent, DepthOrderBookEvent, KlineEvent, OrderUpdate as BinanceOrderUpdate,
+                        TradeEvent, WebsocketEvent};
 use broker_core::error::Error;
+use broker_core::fees::FeeTier;
 use chrono::{TimeZone, Utc};
+use std::collections::{BTreeMap, HashMap};
 
 use broker_core::pair::{symbol_to_pair, PairConf};
 use broker_core::prelude::*;
 use broker_core::types::*;
+use stats::kline::{Resolution, TimeUnit};
 
 #[derive(Serialize, Deserialize, Debug, Message)]
 #[rtype(result = "()")]
@@ -23,26 +29,109 @@ pub struct Subscription {
     id: i32,
 }
 
-pub fn subscription(c: &MarketChannel, currency_pairs: &[String], id: i32, depth: Option<u16>) -> Subscription {
+/// An account trade from `/api/v3/myTrades`, which unlike the client's `TradeHistory` keeps the order id
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct MyTrade {
+    pub symbol: String,
+    pub id: u64,
+    pub order_id: u64,
+    #[serde(with = "string_or_float")]
+    pub price: f64,
+    #[serde(with = "string_or_float")]
+    pub qty: f64,
+    #[serde(with = "string_or_float")]
+    pub commission: f64,
+    pub commission_asset: String,
+    pub time: u64,
+    pub is_buyer: bool,
+    pub is_maker: bool,
+}
+
+/// # Errors
+///
+/// If the channel type or the kline resolution is not streamed by binance
+pub fn subscription(
+    c: &MarketChannel,
+    currency_pairs: &[String],
+    id: i32,
+    depth: Option<u16>,
+) -> Result<Subscription, Error> {
     let channel_str = match c.r#type {
         MarketChannelType::Trades => "trade".to_string(),
-        MarketChannelType::QuotesCandles | MarketChannelType::Quotes => {
-            format!("depth{}@100ms", depth.unwrap_or(10))
-        }
-        MarketChannelType::Orderbooks => "depth@100ms".to_string(),
-        MarketChannelType::Candles => {
-            // TODO : user proper binance channel
-            "ticks".to_string()
+        MarketChannelType::QuotesCandles => format!("depth{}@100ms", depth.unwrap_or(10)),
+        MarketChannelType::Quotes => "bookTicker".to_string(),
+        MarketChannelType::Orderbooks | MarketChannelType::OrderbookL3 => "depth@100ms".to_string(),
+        MarketChannelType::Candles => format!(
+            "kline_{}",
+            kline_interval(c.resolution)
+                .ok_or_else(|| Error::UnsupportedChannel(format!("kline resolution {:?}", c.resolution)))?
+        ),
+        MarketChannelType::OpenInterest | MarketChannelType::FundingRate => {
+            return Err(Error::UnsupportedChannel(format!("{:?}", c.r#type)));
         }
-        _ => unimplemented!("oi does not exist on binance"),
     };
-    Subscription {
+    Ok(Subscription {
         method: String::from("SUBSCRIBE"),
         params: currency_pairs
             .iter()
             .map(|cp| format!("{}@{}", cp.to_lowercase(), channel_str))
             .collect(),
         id,
+    })
+}
+
+/// The kline interval for a resolution, one minute by default
+pub fn kline_interval(resolution: Option<Resolution>) -> Option<String> {
+    let Some(resolution) = resolution else {
+        return Some("1m".to_string());
+    };
+    let unit = match (resolution.time_unit, resolution.units) {
+        (TimeUnit::Second, 1) => "s",
+        (TimeUnit::Minute, 1 | 3 | 5 | 15 | 30) => "m",
+        (TimeUnit::Hour, 1 | 2 | 4 | 6 | 8 | 12) => "h",
+        (TimeUnit::Day, 1 | 3) => "d",
+        (TimeUnit::Week, 1) => "w",
+        (TimeUnit::Month, 1) => "M",
+        _ => return None,
+    };
+    Some(format!("{}{}", resolution.units, unit))
+}
+
+#[allow(clippy::cast_possible_wrap, clippy::cast_sign_loss)]
+pub fn from_binance_kline(e: &KlineEvent, pair: Pair) -> Candle {
+    Candle {
+        event_time: Utc.timestamp_millis_opt(e.event_time as i64).unwrap(),
+        pair,
+        start_time: Utc.timestamp_millis_opt(e.kline.start_time).unwrap(),
+        end_time: Utc.timestamp_millis_opt(e.kline.end_time).unwrap(),
+        open: e.kline.open,
+        high: e.kline.high,
+        low: e.kline.low,
+        close: e.kline.close,
+        volume: e.kline.volume,
+        quote_volume: e.kline.quote_volume,
+        trade_count: e.kline.number_of_trades as u64,
+        is_final: e.kline.is_final_bar,
+    }
+}
+
+/// Klines fetched from the REST api are closed candles, timestamped at their close time
+#[allow(clippy::cast_sign_loss)]
+pub fn from_binance_kline_summary(k: &KlineSummary, pair: Pair) -> Candle {
+    Candle {
+        event_time: Utc.timestamp_millis_opt(k.close_time).unwrap(),
+        pair,
+        start_time: Utc.timestamp_millis_opt(k.open_time).unwrap(),
+        end_time: Utc.timestamp_millis_opt(k.close_time).unwrap(),
+        open: k.open,
+        high: k.high,
+        low: k.low,
+        close: k.close,
+        volume: k.volume,
+        quote_volume: k.quote_asset_volume,
+        trade_count: k.number_of_trades as u64,
+        is_final: true,
     }
 }
 
@@ -195,6 +284,15 @@ pub fn to_binance_time_in_force(t: OrderEnforcement) -> TimeInForce {
     }
 }
 
+/// Limit maker orders do not accept a time in force
+fn to_binance_request_time_in_force(request: &AddOrderRequest) -> Option<TimeInForce> {
+    if request.effective_order_type() == OrderType::LimitMaker {
+        None
+    } else {
+        request.enforcement.map(to_binance_time_in_force)
+    }
+}
+
 pub fn to_binance_order_side(tt: TradeType) -> OrderSide {
     match tt {
         TradeType::Buy => OrderSide::Buy,
@@ -211,6 +309,8 @@ pub fn to_binance_order_type(ot: OrderType) -> BinanceOrderType {
         OrderType::StopLossLimit => BinanceOrderType::StopLossLimit,
         OrderType::TakeProfit => BinanceOrderType::TakeProfit,
         OrderType::TakeProfitLimit => BinanceOrderType::TakeProfitLimit,
+        // Spot trailing stops are stop loss orders with a trailing delta
+        OrderType::TrailingStop => BinanceOrderType::StopLoss,
     }
 }
 
@@ -249,9 +349,9 @@ pub fn to_binance_order_request(request: &AddOrderRequest, pair_conf: &PairConf)
         quantity: request.quantity,
         price: request.price.filter(|_| request.order_type != OrderType::Market),
         side: to_binance_order_side(request.side),
-        order_type: to_binance_order_type(request.order_type),
+        order_type: to_binance_order_type(request.effective_order_type()),
         symbol: pair_conf.symbol.to_string(),
-        time_in_force: request.enforcement.map(to_binance_time_in_force),
+        time_in_force: to_binance_request_time_in_force(request),
         iceberg_qty: request.iceberg_qty,
         recv_window: None,
         new_client_order_id: Some(request.order_id.clone()),
@@ -266,9 +366,9 @@ pub fn to_binance_margin_order(request: &AddOrderRequest, pair_conf: &PairConf,
         quantity: request.quantity,
         price: request.price.filter(|_| request.order_type != OrderType::Market),
         side: to_binance_order_side(request.side),
-        order_type: to_binance_order_type(request.order_type),
+        order_type: to_binance_order_type(request.effective_order_type()),
         symbol: pair_conf.symbol.to_string(),
-        time_in_force: request.enforcement.map(to_binance_time_in_force),
+        time_in_force: to_binance_request_time_in_force(request),
         is_isolated: Some(is_isolated_margin_str(asset_type)),
         iceberg_qty: request.iceberg_qty,
         new_client_order_id: Some(request.order_id.clone()),
@@ -325,6 +425,83 @@ pub fn from_binance_margin_order_result(mor: MarginOrderResult) -> OrderSubmissi
     }
 }
 
+#[allow(clippy::cast_possible_wrap)]
+pub fn from_binance_trade(e: &TradeEvent, pair: Pair) -> Trade {
+    let aggressor = TradeType::aggressor(e.is_buyer_maker);
+    Trade {
+        amount: e.qty.parse::<f64>().unwrap(),
+        event_ms: e.event_time as i64,
+        price: e.price.parse::<f64>().unwrap(),
+        tt: aggressor,
+        aggressor: Some(aggressor),
+        pair,
+    }
+}
+
+/// Binance streams price levels, not orders, changes carry the total quantity of their level
+fn book_changes(bids: impl Iterator<Item = Offer>, asks: impl Iterator<Item = Offer>) -> Vec<BookChange> {
+    let bids = bids.map(|(price, qty)| (TradeType::Buy, price, qty));
+    let asks = asks.map(|(price, qty)| (TradeType::Sell, price, qty));
+    bids.chain(asks)
+        .map(|(side, price, qty)| BookChange {
+            side,
+            price,
+            qty,
+            order_id: None,
+        })
+        .collect()
+}
+
+#[allow(clippy::cast_possible_wrap)]
+pub fn from_binance_depth_diff(e: &DepthOrderBookEvent, pair: Pair) -> OrderbookL3 {
+    OrderbookL3 {
+        event_ms: e.event_time as i64,
+        pair,
+        first_update_id: e.first_update_id,
+        last_update_id: e.final_update_id,
+        is_snapshot: false,
+        changes: book_changes(
+            e.bids.iter().map(|b| (b.price, b.qty)),
+            e.asks.iter().map(|a| (a.price, a.qty)),
+        ),
+    }
+}
+
+/// Book ticker events carry no time, quotes are timestamped on reception
+pub fn from_binance_book_ticker(e: &BookTickerEvent, pair: Pair) -> Quote {
+    Quote {
+        event_ms: get_unix_timestamp_ms(),
+        pair,
+        bid: e.best_bid,
+        bid_qty: e.best_bid_qty,
+        ask: e.best_ask,
+        ask_qty: e.best_ask_qty,
+    }
+}
+
+/// Open interest responses carry no time, it is timestamped on reception
+pub fn from_binance_open_interest(oi: &BinanceOpenInterest, pair: Pair) -> OpenInterest {
+    OpenInterest {
+        event_ms: get_unix_timestamp_ms(),
+        pair,
+        open_interest: oi.open_interest,
+    }
+}
+
+pub fn from_binance_depth_snapshot(book: &BinanceOrderBook, pair: Pair) -> OrderbookL3 {
+    OrderbookL3 {
+        event_ms: get_unix_timestamp_ms(),
+        pair,
+        first_update_id: book.last_update_id,
+        last_update_id: book.last_update_id,
+        is_snapshot: true,
+        changes: book_changes(
+            book.bids.iter().map(|b| (b.price, b.qty)),
+            book.asks.iter().map(|a| (a.price, a.qty)),
+        ),
+    }
+}
+
 pub fn from_binance_fill(t: Fill) -> OrderFill {
     OrderFill {
         id: None,
@@ -335,6 +512,355 @@ pub fn from_binance_fill(t: Fill) -> OrderFill {
     }
 }
 
+#[allow(clippy::cast_possible_wrap)]
+pub fn from_binance_my_trade(t: MyTrade, pair: Pair) -> TradeFill {
+    TradeFill {
+        id: t.id.to_string(),
+        order_id: t.order_id.to_string(),
+        pair,
+        price: t.price,
+        qty: t.qty,
+        fee: t.commission,
+        fee_asset: t.commission_asset.into(),
+        side: if t.is_buyer { TradeType::Buy } else { TradeType::Sell },
+        is_maker: t.is_maker,
+        time: t.time as i64,
+    }
+}
+
+/// A futures order from `/fapi/v1/order` or `/fapi/v1/batchOrders`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct FuturesOrderResult {
+    pub symbol: String,
+    pub order_id: u64,
+    pub client_order_id: String,
+    #[serde(with = "string_or_float")]
+    pub price: f64,
+    #[serde(with = "string_or_float")]
+    pub orig_qty: f64,
+    #[serde(with = "string_or_float")]
+    pub executed_qty: f64,
+    #[serde(with = "string_or_float")]
+    pub cum_quote: f64,
+    pub status: BinanceOrderStatus,
+    pub time_in_force: TimeInForce,
+    pub side: OrderSide,
+    pub update_time: u64,
+}
+
+/// The parameters of a trailing stop order, a `TRAILING_STOP_MARKET` order with a callback rate in percent on futures,
+/// a `STOP_LOSS` order with a trailing delta in basis points on spot
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+pub fn to_binance_trailing_stop_params(
+    request: &AddOrderRequest,
+    pair_conf: &PairConf,
+    futures: bool,
+) -> BTreeMap<String, String> {
+    let delta = request.trailing_delta.unwrap_or_default();
+    let mut params = BTreeMap::new();
+    params.insert("symbol".to_string(), pair_conf.symbol.to_string());
+    params.insert(
+        "side".to_string(),
+        match request.side {
+            TradeType::Buy => "BUY",
+            TradeType::Sell => "SELL",
+        }
+        .to_string(),
+    );
+    if let Some(qty) = request.quantity {
+        params.insert("quantity".to_string(), qty.to_string());
+    }
+    params.insert("newClientOrderId".to_string(), request.order_id.clone());
+    if futures {
+        if let Some(position_side) = request.position_side {
+            params.insert("positionSide".to_string(), to_binance_position_side(position_side));
+        }
+        params.insert("type".to_string(), "TRAILING_STOP_MARKET".to_string());
+        let callback_rate = ((delta * 1000.0).round() / 10.0).clamp(0.1, 5.0);
+        params.insert("callbackRate".to_string(), callback_rate.to_string());
+        if let Some(activation_price) = request.stop_price {
+            params.insert("activationPrice".to_string(), activation_price.to_string());
+        }
+        params.insert("newOrderRespType".to_string(), "RESULT".to_string());
+    } else {
+        params.insert("type".to_string(), "STOP_LOSS".to_string());
+        let trailing_delta = ((delta * 10_000.0).round() as u32).clamp(10, 2000);
+        params.insert("trailingDelta".to_string(), trailing_delta.to_string());
+        if let Some(activation_price) = request.stop_price {
+            params.insert("stopPrice".to_string(), activation_price.to_string());
+        }
+        params.insert("newOrderRespType".to_string(), "FULL".to_string());
+    }
+    params
+}
+
+#[allow(clippy::cast_possible_wrap)]
+pub fn from_binance_futures_order_result(r: FuturesOrderResult, pair: Pair, asset_type: AssetType) -> OrderSubmission {
+    OrderSubmission {
+        timestamp: r.update_time as i64,
+        id: r.order_id.to_string(),
+        pair,
+        client_id: r.client_order_id,
+        price: r.price,
+        qty: r.orig_qty,
+        executed_qty: r.executed_qty,
+        cummulative_quote_qty: r.cum_quote,
+        status: from_binance_order_status(r.status),
+        enforcement: from_binance_time_in_force(r.time_in_force),
+        order_type: OrderType::TrailingStop,
+        side: from_binance_order_side(r.side),
+        asset_type,
+        trades: vec![],
+        borrowed_amount: None,
+        borrow_asset: None,
+    }
+}
+
+/// An entry of a `/fapi/v1/batchOrders` response, every order of a batch is accepted or rejected on its own
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum FuturesBatchOrderResult {
+    Order(FuturesOrderResult),
+    Error { code: i64, msg: String },
+}
+
+/// The parameters of a futures order, as one entry of the `batchOrders` list of `/fapi/v1/batchOrders`
+pub fn to_binance_futures_order_params(request: &AddOrderRequest, pair_conf: &PairConf) -> BTreeMap<String, String> {
+    let order_type = request.effective_order_type();
+    if order_type == OrderType::TrailingStop {
+        return to_binance_trailing_stop_params(request, pair_conf, true);
+    }
+    let mut params = BTreeMap::new();
+    params.insert("symbol".to_string(), pair_conf.symbol.to_string());
+    params.insert(
+        "side".to_string(),
+        match request.side {
+            TradeType::Buy => "BUY",
+            TradeType::Sell => "SELL",
+        }
+        .to_string(),
+    );
+    params.insert(
+        "type".to_string(),
+        match order_type {
+            OrderType::Limit | OrderType::LimitMaker => "LIMIT",
+            OrderType::Market => "MARKET",
+            OrderType::StopLoss => "STOP_MARKET",
+            OrderType::StopLossLimit => "STOP",
+            OrderType::TakeProfit => "TAKE_PROFIT_MARKET",
+            OrderType::TakeProfitLimit => "TAKE_PROFIT",
+            OrderType::TrailingStop => "TRAILING_STOP_MARKET",
+        }
+        .to_string(),
+    );
+    if let Some(qty) = request.quantity {
+        params.insert("quantity".to_string(), qty.to_string());
+    }
+    if order_type.is_maker() {
+        if let Some(price) = request.price {
+            params.insert("price".to_string(), price.to_string());
+        }
+        // Post only orders are good till crossing on futures
+        let enforcement = if order_type == OrderType::LimitMaker {
+            OrderEnforcement::GTX
+        } else {
+            request.enforcement.unwrap_or(OrderEnforcement::GTC)
+        };
+        params.insert(
+            "timeInForce".to_string(),
+            match enforcement {
+                OrderEnforcement::GTC => "GTC",
+                OrderEnforcement::IOC => "IOC",
+                OrderEnforcement::FOK => "FOK",
+                OrderEnforcement::GTX => "GTX",
+            }
+            .to_string(),
+        );
+    }
+    if let Some(stop_price) = request.stop_price {
+        params.insert("stopPrice".to_string(), stop_price.to_string());
+    }
+    if let Some(position_side) = request.position_side {
+        params.insert("positionSide".to_string(), to_binance_position_side(position_side));
+    }
+    params.insert("newClientOrderId".to_string(), request.order_id.clone());
+    params.insert("newOrderRespType".to_string(), "RESULT".to_string());
+    params
+}
+
+/// Futures accounts in hedge mode require the position side of every order
+fn to_binance_position_side(position_side: PositionSide) -> String {
+    match position_side {
+        PositionSide::Both => "BOTH",
+        PositionSide::Long => "LONG",
+        PositionSide::Short => "SHORT",
+    }
+    .to_string()
+}
+
+/// The result of one order of a futures batch, in the order of the requests
+pub fn from_binance_futures_batch_order_result(
+    r: FuturesBatchOrderResult,
+    request: &AddOrderRequest,
+    asset_type: AssetType,
+) -> broker_core::error::Result<OrderSubmission> {
+    match r {
+        FuturesBatchOrderResult::Order(result) => Ok(OrderSubmission {
+            order_type: request.order_type,
+            ..from_binance_futures_order_result(result, request.pair.clone(), asset_type)
+        }),
+        FuturesBatchOrderResult::Error { code, msg } => Err(Error::ExchangeError(format!("{} : {}", code, msg))),
+    }
+}
+
+/// An entry of a `/fapi/v1/income` response
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct FuturesIncome {
+    pub symbol: String,
+    pub income_type: String,
+    #[serde(with = "string_or_float")]
+    pub income: f64,
+    pub asset: String,
+    pub time: i64,
+    pub tran_id: i64,
+}
+
+pub fn from_binance_futures_income(income: FuturesIncome, pair: Pair) -> FundingPayment {
+    FundingPayment {
+        time_ms: income.time,
+        pair,
+        asset: income.asset.into(),
+        amount: income.income,
+    }
+}
+
+/// An order of a `/api/v3/order/oco` or `/sapi/v1/margin/order/oco` response
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct OcoOrderReport {
+    pub symbol: String,
+    pub order_id: u64,
+    pub client_order_id: String,
+    pub transact_time: u64,
+    #[serde(with = "string_or_float")]
+    pub price: f64,
+    #[serde(with = "string_or_float")]
+    pub orig_qty: f64,
+    #[serde(with = "string_or_float")]
+    pub executed_qty: f64,
+    #[serde(with = "string_or_float")]
+    pub cummulative_quote_qty: f64,
+    pub status: BinanceOrderStatus,
+    pub time_in_force: TimeInForce,
+    #[serde(rename = "type")]
+    pub order_type: BinanceOrderType,
+    pub side: OrderSide,
+}
+
+/// The order list created by an OCO order, only the order reports are of interest
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct OcoOrderList {
+    pub order_list_id: i64,
+    pub list_client_order_id: String,
+    pub order_reports: Vec<OcoOrderReport>,
+}
+
+/// The parameters of an OCO order, the margin parameters are set for margin asset types
+pub fn to_binance_oco_order_params(
+    request: &OcoOrderRequest,
+    pair_conf: &PairConf,
+    asset_type: AssetType,
+) -> BTreeMap<String, String> {
+    let (limit, stop) = (&request.limit, &request.stop);
+    let mut params = BTreeMap::new();
+    params.insert("symbol".to_string(), pair_conf.symbol.to_string());
+    params.insert("listClientOrderId".to_string(), request.list_id.clone());
+    params.insert(
+        "side".to_string(),
+        match limit.side {
+            TradeType::Buy => "BUY",
+            TradeType::Sell => "SELL",
+        }
+        .to_string(),
+    );
+    if let Some(qty) = limit.quantity {
+        params.insert("quantity".to_string(), qty.to_string());
+    }
+    params.insert("limitClientOrderId".to_string(), limit.order_id.clone());
+    if let Some(price) = limit.price {
+        params.insert("price".to_string(), price.to_string());
+    }
+    if let Some(iceberg_qty) = limit.iceberg_qty {
+        params.insert("limitIcebergQty".to_string(), iceberg_qty.to_string());
+    }
+    params.insert("stopClientOrderId".to_string(), stop.order_id.clone());
+    if let Some(stop_price) = stop.stop_price {
+        params.insert("stopPrice".to_string(), stop_price.to_string());
+    }
+    if let (OrderType::StopLossLimit, Some(price)) = (stop.order_type, stop.price) {
+        params.insert("stopLimitPrice".to_string(), price.to_string());
+        params.insert(
+            "stopLimitTimeInForce".to_string(),
+            match stop.enforcement.unwrap_or(OrderEnforcement::GTC) {
+                OrderEnforcement::IOC => "IOC",
+                OrderEnforcement::FOK => "FOK",
+                OrderEnforcement::GTC | OrderEnforcement::GTX => "GTC",
+            }
+            .to_string(),
+        );
+    }
+    if let Some(iceberg_qty) = stop.iceberg_qty {
+        params.insert("stopIcebergQty".to_string(), iceberg_qty.to_string());
+    }
+    if asset_type.is_margin() {
+        params.insert("isIsolated".to_string(), is_isolated_margin_str(asset_type));
+        params.insert(
+            "sideEffectType".to_string(),
+            match limit.side_effect_type.unwrap_or(MarginSideEffect::NoSideEffect) {
+                MarginSideEffect::NoSideEffect => "NO_SIDE_EFFECT",
+                MarginSideEffect::MarginBuy => "MARGIN_BUY",
+                MarginSideEffect::AutoRepay => "AUTO_REPAY",
+            }
+            .to_string(),
+        );
+    }
+    params.insert("newOrderRespType".to_string(), "FULL".to_string());
+    params
+}
+
+#[allow(clippy::cast_possible_wrap)]
+pub fn from_binance_oco_order_report(r: OcoOrderReport, pair: Pair, asset_type: AssetType) -> OrderSubmission {
+    OrderSubmission {
+        timestamp: r.transact_time as i64,
+        id: r.order_id.to_string(),
+        pair,
+        client_id: r.client_order_id,
+        price: r.price,
+        qty: r.orig_qty,
+        executed_qty: r.executed_qty,
+        cummulative_quote_qty: r.cummulative_quote_qty,
+        status: from_binance_order_status(r.status),
+        enforcement: from_binance_time_in_force(r.time_in_force),
+        order_type: from_binance_order_type(r.order_type),
+        side: from_binance_order_side(r.side),
+        asset_type,
+        trades: vec![],
+        borrowed_amount: None,
+        borrow_asset: None,
+    }
+}
+
+/// Map the prices of a `/api/v3/ticker/price` response to the pairs of the requested market symbols
+pub fn from_binance_prices(prices: Vec<SymbolPrice>, symbols: &HashMap<String, Pair>) -> HashMap<Pair, f64> {
+    prices
+        .into_iter()
+        .filter_map(|p| symbols.get(&p.symbol).map(|pair| (pair.clone(), p.price)))
+        .collect()
+}
+
 pub fn from_binance_user_asset(ua: UserAsset) -> MarginAsset {
     MarginAsset {
         asset: ua.asset,
@@ -415,9 +941,29 @@ pub fn from_binance_balance(b: BinanceBalance) -> Balance {
     }
 }
 
+/// Commissions of the account are in basis points
+pub fn from_binance_commissions(maker: f32, taker: f32) -> FeeTier {
+    FeeTier {
+        maker: f64::from(maker) / 10000.0,
+        taker: f64::from(taker) / 10000.0,
+    }
+}
+
+pub fn from_binance_system_status(s: BinanceSystemStatus) -> SystemStatus {
+    match s.status {
+        0 => SystemStatus::Normal,
+        _ => SystemStatus::Maintenance,
+    }
+}
+
+/// Binance error code returned while the service is unavailable, typically during maintenance
+const SERVICE_SHUTTING_DOWN: i32 = -1016;
+
 pub fn from_binance_error(e: BinanceError) -> broker_core::error::Error {
     match e {
         BinanceError::InvalidPrice => Error::InvalidPrice,
+        BinanceError::ServiceUnavailable => Error::ExchangeMaintenance,
+        BinanceError::BinanceError { response } if response.code == SERVICE_SHUTTING_DOWN => Error::ExchangeMaintenance,
         _ => Error::ExchangeError(format!("{:?}", e)),
     }
 }
@@ -425,12 +971,25 @@ pub fn from_binance_error(e: BinanceError) -> broker_core::error::Error {
 #[cfg(test)]
 mod test {
     use binance::account::OrderRequest;
-    use binance::rest_model::MarginOrder;
+    use binance::errors::{BinanceContentError, Error as BinanceError};
+    use binance::rest_model::{MarginOrder, OrderType as BinanceOrderType, SymbolPrice,
+                              SystemStatus as BinanceSystemStatus};
+    use binance::ws_model::WebsocketEvent;
+
+    use std::collections::HashMap;
 
-    use crate::adapters::{to_binance_margin_order, to_binance_order_request};
+    use crate::adapters::{from_binance_commissions, from_binance_error, from_binance_futures_income,
+                          from_binance_my_trade, from_binance_prices, from_binance_system_status, from_binance_trade,
+                          kline_interval, subscription, to_binance_futures_order_params, to_binance_margin_order,
+                          to_binance_oco_order_params, to_binance_order_request, to_binance_trailing_stop_params,
+                          FuturesBatchOrderResult, FuturesIncome, MyTrade};
+    use broker_core::error::Error;
     use broker_core::pair::PairConf;
     use broker_core::types::AssetType;
-    use broker_core::types::{AddOrderRequest, OrderType};
+    use broker_core::types::{AddOrderRequest, OcoOrderRequest, OrderEnforcement, OrderType, Pair, PositionSide,
+                             SystemStatus, TradeFill, TradeType};
+    use broker_core::types::{Exchange, MarketChannel, MarketChannelType, SecurityType, Symbol};
+    use stats::kline::{Resolution, TimeUnit};
 
     #[tokio::test]
     async fn test_add_order_request_to_binance_price_erased() {
@@ -461,4 +1020,268 @@ mod test {
             to_binance_margin_order(&order_request, &PairConf::default(), AssetType::IsolatedMargin);
         assert_eq!(binance_margin_request.price, Some(1.0));
     }
+
+    #[test]
+    fn oco_order_params() {
+        let leg = AddOrderRequest {
+            side: TradeType::Sell,
+            quantity: Some(1.0),
+            ..AddOrderRequest::default()
+        };
+        let request = OcoOrderRequest {
+            list_id: "list".to_string(),
+            limit: AddOrderRequest {
+                order_id: "limit".to_string(),
+                order_type: OrderType::Limit,
+                price: Some(110.0),
+                ..leg.clone()
+            },
+            stop: AddOrderRequest {
+                order_id: "stop".to_string(),
+                order_type: OrderType::StopLossLimit,
+                price: Some(89.0),
+                stop_price: Some(90.0),
+                ..leg
+            },
+        };
+        let params = to_binance_oco_order_params(&request, &PairConf::default(), AssetType::Spot);
+        assert_eq!(params["side"], "SELL");
+        assert_eq!(params["price"], "110");
+        assert_eq!(params["stopPrice"], "90");
+        assert_eq!(params["stopLimitPrice"], "89");
+        assert_eq!(params["stopLimitTimeInForce"], "GTC");
+        assert_eq!(params["limitClientOrderId"], "limit");
+        assert!(!params.contains_key("isIsolated"));
+        let params = to_binance_oco_order_params(&request, &PairConf::default(), AssetType::IsolatedMargin);
+        assert_eq!(params["isIsolated"], "TRUE");
+        assert_eq!(params["sideEffectType"], "NO_SIDE_EFFECT");
+    }
+
+    #[test]
+    fn trailing_stop_params() {
+        let request = AddOrderRequest {
+            order_type: OrderType::TrailingStop,
+            side: TradeType::Sell,
+            quantity: Some(1.0),
+            stop_price: Some(105.0),
+            trailing_delta: Some(0.02),
+            ..AddOrderRequest::default()
+        };
+        let params = to_binance_trailing_stop_params(&request, &PairConf::default(), true);
+        assert_eq!(params["type"], "TRAILING_STOP_MARKET");
+        assert_eq!(params["callbackRate"], "2");
+        assert_eq!(params["activationPrice"], "105");
+        let params = to_binance_trailing_stop_params(&request, &PairConf::default(), false);
+        assert_eq!(params["type"], "STOP_LOSS");
+        assert_eq!(params["trailingDelta"], "200");
+        assert_eq!(params["stopPrice"], "105");
+    }
+
+    #[test]
+    fn futures_batch_orders() {
+        let request = AddOrderRequest {
+            order_id: "leg".to_string(),
+            order_type: OrderType::Limit,
+            post_only: true,
+            side: TradeType::Buy,
+            quantity: Some(2.0),
+            price: Some(100.0),
+            ..AddOrderRequest::default()
+        };
+        let params = to_binance_futures_order_params(&request, &PairConf::default());
+        assert_eq!(params["type"], "LIMIT");
+        assert_eq!(params["timeInForce"], "GTX");
+        assert_eq!(params["price"], "100");
+        assert_eq!(params["newClientOrderId"], "leg");
+        assert!(!params.contains_key("positionSide"));
+        let hedged = AddOrderRequest {
+            position_side: Some(PositionSide::Long),
+            ..request
+        };
+        assert_eq!(
+            to_binance_futures_order_params(&hedged, &PairConf::default())["positionSide"],
+            "LONG"
+        );
+        let results: Vec<FuturesBatchOrderResult> = serde_json::from_str(
+            r#"[{"symbol":"BTCUSDT","orderId":1,"clientOrderId":"leg","price":"100","origQty":"2","executedQty":"0","cumQuote":"0","status":"NEW","timeInForce":"GTX","side":"BUY","updateTime":1},{"code":-2019,"msg":"Margin is insufficient."}]"#,
+        )
+        .unwrap();
+        assert!(matches!(results[0], FuturesBatchOrderResult::Order(_)));
+        assert!(matches!(results[1], FuturesBatchOrderResult::Error { code: -2019, .. }));
+    }
+
+    #[test]
+    fn futures_funding_income() {
+        let incomes: Vec<FuturesIncome> = serde_json::from_str(
+            r#"[{"symbol":"BTCUSDT","incomeType":"FUNDING_FEE","income":"-0.37500000","asset":"USDT","info":"","time":1570636800000,"tranId":9689322392,"tradeId":""}]"#,
+        )
+        .unwrap();
+        let payment = from_binance_futures_income(incomes[0].clone(), "BTC_USDT".into());
+        assert_eq!(payment.time_ms, 1_570_636_800_000);
+        assert_eq!(&*payment.asset, "USDT");
+        assert!((payment.amount + 0.375).abs() < f64::EPSILON);
+    }
+
+    #[tokio::test]
+    async fn test_post_only_order_request_to_binance_limit_maker() {
+        let order_request = AddOrderRequest {
+            order_type: OrderType::Limit,
+            enforcement: Some(OrderEnforcement::GTC),
+            price: Some(1.0),
+            post_only: true,
+            ..AddOrderRequest::default()
+        };
+        let binance_request: OrderRequest = to_binance_order_request(&order_request, &PairConf::default());
+        assert!(matches!(binance_request.order_type, BinanceOrderType::LimitMaker));
+        assert!(binance_request.time_in_force.is_none());
+        let binance_margin_request: MarginOrder =
+            to_binance_margin_order(&order_request, &PairConf::default(), AssetType::Margin);
+        assert!(matches!(binance_margin_request.order_type, BinanceOrderType::LimitMaker));
+        assert!(binance_margin_request.time_in_force.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_iceberg_order_request_to_binance() {
+        let order_request = AddOrderRequest {
+            order_type: OrderType::Limit,
+            price: Some(1.0),
+            quantity: Some(10.0),
+            iceberg_qty: Some(2.0),
+            ..AddOrderRequest::default()
+        };
+        let binance_request: OrderRequest = to_binance_order_request(&order_request, &PairConf::default());
+        assert_eq!(binance_request.iceberg_qty, Some(2.0));
+        let binance_margin_request: MarginOrder =
+            to_binance_margin_order(&order_request, &PairConf::default(), AssetType::Margin);
+        assert_eq!(binance_margin_request.iceberg_qty, Some(2.0));
+    }
+
+    #[tokio::test]
+    async fn test_binance_trade_aggressor_side() {
+        let raw = r#"{"e":"trade","E":1672515782136,"s":"BTCUSDT","t":12345,"p":"16500.10","q":"0.5","b":88,"a":50,"T":1672515782134,"m":true,"M":true}"#;
+        let WebsocketEvent::Trade(e) = serde_json::from_str::<WebsocketEvent>(raw).unwrap() else {
+            panic!("expected a trade event");
+        };
+        let trade = from_binance_trade(&e, Pair::from("BTC_USDT"));
+        assert_eq!(trade.aggressor, Some(TradeType::Sell));
+        assert_eq!(trade.price, 16500.10);
+        assert_eq!(trade.amount, 0.5);
+
+        let raw = raw.replace(r#""m":true"#, r#""m":false"#);
+        let WebsocketEvent::Trade(e) = serde_json::from_str::<WebsocketEvent>(&raw).unwrap() else {
+            panic!("expected a trade event");
+        };
+        assert_eq!(from_binance_trade(&e, Pair::from("BTC_USDT")).aggressor, Some(TradeType::Buy));
+    }
+
+    #[tokio::test]
+    async fn test_binance_my_trades_to_trade_fills() {
+        let raw = r#"[{"symbol":"BNBBTC","id":28457,"orderId":100234,"orderListId":-1,"price":"4.00000100","qty":"12.00000000","quoteQty":"48.000012","commission":"10.10000000","commissionAsset":"BNB","time":1499865549590,"isBuyer":true,"isMaker":false,"isBestMatch":true}]"#;
+        let trades: Vec<MyTrade> = serde_json::from_str(raw).unwrap();
+        let fills: Vec<TradeFill> = trades
+            .into_iter()
+            .map(|t| from_binance_my_trade(t, Pair::from("BNB_BTC")))
+            .collect();
+        assert_eq!(fills, vec![TradeFill {
+            id: "28457".to_string(),
+            order_id: "100234".to_string(),
+            pair: Pair::from("BNB_BTC"),
+            price: 4.000001,
+            qty: 12.0,
+            fee: 10.1,
+            fee_asset: "BNB".into(),
+            side: TradeType::Buy,
+            is_maker: false,
+            time: 1_499_865_549_590,
+        }]);
+    }
+
+    #[test]
+    fn test_maintenance_error() {
+        let response: BinanceContentError =
+            serde_json::from_str(r#"{"code":-1016,"msg":"This service is no longer available."}"#).unwrap();
+        assert!(matches!(
+            from_binance_error(BinanceError::BinanceError { response }),
+            Error::ExchangeMaintenance
+        ));
+        assert!(matches!(
+            from_binance_error(BinanceError::ServiceUnavailable),
+            Error::ExchangeMaintenance
+        ));
+    }
+
+    #[test]
+    fn test_binance_system_status() {
+        let status: BinanceSystemStatus = serde_json::from_str(r#"{"status":1,"msg":"system maintenance"}"#).unwrap();
+        assert_eq!(from_binance_system_status(status), SystemStatus::Maintenance);
+        let status: BinanceSystemStatus = serde_json::from_str(r#"{"status":0,"msg":"normal"}"#).unwrap();
+        assert_eq!(from_binance_system_status(status), SystemStatus::Normal);
+    }
+
+    #[test]
+    fn test_binance_prices_to_tickers() {
+        let raw = r#"[{"symbol":"BTCUSDT","price":"16500.10000000"},{"symbol":"ETHUSDT","price":"1200.50000000"},{"symbol":"BNBBTC","price":"0.01500000"}]"#;
+        let prices: Vec<SymbolPrice> = serde_json::from_str(raw).unwrap();
+        let symbols = HashMap::from([
+            ("BTCUSDT".to_string(), Pair::from("BTC_USDT")),
+            ("ETHUSDT".to_string(), Pair::from("ETH_USDT")),
+            ("ADAUSDT".to_string(), Pair::from("ADA_USDT")),
+        ]);
+        let tickers = from_binance_prices(prices, &symbols);
+        assert_eq!(
+            tickers,
+            HashMap::from([(Pair::from("BTC_USDT"), 16500.1), (Pair::from("ETH_USDT"), 1200.5)])
+        );
+    }
+
+    #[test]
+    fn kline_intervals() {
+        assert_eq!(kline_interval(None).as_deref(), Some("1m"));
+        assert_eq!(
+            kline_interval(Some(Resolution::new(TimeUnit::Hour, 4))).as_deref(),
+            Some("4h")
+        );
+        assert_eq!(
+            kline_interval(Some(Resolution::new(TimeUnit::Month, 1))).as_deref(),
+            Some("1M")
+        );
+        assert_eq!(kline_interval(Some(Resolution::new(TimeUnit::Minute, 7))), None);
+    }
+
+    #[test]
+    fn unsupported_channels_are_errors() {
+        let channel = |r#type, resolution| {
+            MarketChannel::builder()
+                .symbol(Symbol::new("BTC_USDT".into(), SecurityType::Crypto, Exchange::Binance))
+                .r#type(r#type)
+                .resolution(resolution)
+                .build()
+        };
+        let pairs = ["BTCUSDT".to_string()];
+        let sub = subscription(
+            &channel(MarketChannelType::Candles, Some(Resolution::new(TimeUnit::Hour, 4))),
+            &pairs,
+            0,
+            None,
+        )
+        .unwrap();
+        assert_eq!(sub.params, vec!["btcusdt@kline_4h".to_string()]);
+        for (r#type, resolution) in [
+            (MarketChannelType::Candles, Some(Resolution::new(TimeUnit::Minute, 7))),
+            (MarketChannelType::OpenInterest, None),
+            (MarketChannelType::FundingRate, None),
+        ] {
+            assert!(matches!(
+                subscription(&channel(r#type, resolution), &pairs, 0, None),
+                Err(Error::UnsupportedChannel(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_commissions_to_fee_tier() {
+        let tier = from_binance_commissions(10.0, 7.5);
+        assert!((tier.maker - 0.001).abs() < f64::EPSILON);
+        assert!((tier.taker - 0.00075).abs() < f64::EPSILON);
+    }
 }
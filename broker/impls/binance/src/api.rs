@@ -1,15 +1,55 @@
 //! Use this module to interact with Binance exchange.
 //! Please see examples for more informations.
 
+use std::collections::BTreeMap;
+
 use binance::account::Account;
 use binance::api::Binance;
 use binance::config::Config;
+use binance::futures::account::FuturesAccount;
+use binance::futures::market::FuturesMarket;
+use binance::futures::rest_model::OpenInterest as BinanceOpenInterest;
 use binance::general::General;
 use binance::margin::Margin;
 use binance::market::Market;
+use binance::rest_model::Transaction as BinanceTransaction;
+use binance::util::build_signed_request;
+use binance::wallet::Wallet;
 
+use crate::adapters::{from_binance_error, from_binance_futures_batch_order_result, from_binance_futures_order_result,
+                      from_binance_transaction, to_binance_futures_order_params, to_binance_trailing_stop_params,
+                      FuturesBatchOrderResult, FuturesIncome, FuturesOrderResult};
 use broker_core::error::*;
+use broker_core::pair::PairConf;
 use broker_core::prelude::*;
+use broker_core::ratelimit::{self, default_rate_limiter};
+use broker_core::types::{OrderSubmission, RateLimit};
+
+static API_V3_ORDER: &str = "/api/v3/order";
+static FAPI_V1_ORDER: &str = "/fapi/v1/order";
+static FAPI_V1_BATCH_ORDERS: &str = "/fapi/v1/batchOrders";
+static FAPI_V1_INCOME: &str = "/fapi/v1/income";
+
+/// Maximum number of orders of a single futures batch
+pub(crate) const FUTURES_BATCH_LIMIT: usize = 5;
+
+/// Request weight budget, shared with the user data streams of the same process
+pub(crate) const WEIGHT_ENDPOINT: &str = "weight";
+pub(crate) const WEIGHT_LIMIT: RateLimit = RateLimit::new(6000, 60_000);
+/// Order placement budget
+pub(crate) const ORDERS_ENDPOINT: &str = "orders";
+pub(crate) const ORDERS_LIMIT: RateLimit = RateLimit::new(100, 10_000);
+/// Request weight budget of the futures api, separate from the spot one
+pub(crate) const FUTURES_WEIGHT_ENDPOINT: &str = "futures_weight";
+pub(crate) const FUTURES_WEIGHT_LIMIT: RateLimit = RateLimit::new(2400, 60_000);
+
+/// Register the exchange budgets, unless they were configured in the broker settings
+pub(crate) fn register_rate_limits() {
+    let limiter = default_rate_limiter();
+    limiter.register_default(Exchange::Binance, WEIGHT_ENDPOINT, WEIGHT_LIMIT);
+    limiter.register_default(Exchange::Binance, ORDERS_ENDPOINT, ORDERS_LIMIT);
+    limiter.register_default(Exchange::Binance, FUTURES_WEIGHT_ENDPOINT, FUTURES_WEIGHT_LIMIT);
+}
 
 #[derive(Debug, Clone)]
 pub struct BinanceApi {
@@ -58,6 +98,7 @@ impl BinanceApi {
         let api_key = creds.get("api_key");
         let api_secret = creds.get("api_secret");
         let vip_level = creds.get("vip_level");
+        register_rate_limits();
 
         Ok(BinanceApi {
             api_key,
@@ -76,12 +117,146 @@ impl BinanceApi {
 
     pub fn general(&self) -> General { Self::private_api(self.api_key.clone(), self.api_secret.clone(), &self.config) }
 
+    pub fn wallet(&self) -> Wallet { Self::private_api(self.api_key.clone(), self.api_secret.clone(), &self.config) }
+
+    pub fn futures_market(&self) -> FuturesMarket {
+        Self::private_api(self.api_key.clone(), self.api_secret.clone(), &self.config)
+    }
+
+    pub fn futures_account(&self) -> FuturesAccount {
+        Self::private_api(self.api_key.clone(), self.api_secret.clone(), &self.config)
+    }
+
+    /// Place a trailing stop order, on the futures api for futures and perpetual contracts
+    pub(crate) async fn add_trailing_stop_order(
+        &self,
+        order: &AddOrderRequest,
+        pair_conf: &PairConf,
+    ) -> Result<OrderSubmission> {
+        match order.asset_type.unwrap_or(AssetType::Spot) {
+            AssetType::Spot => {
+                let account = self.account();
+                let params = to_binance_trailing_stop_params(order, pair_conf, false);
+                let request = build_signed_request(params, account.recv_window).map_err(from_binance_error)?;
+                self.throttle_order(1).await?;
+                let tr: BinanceTransaction = account
+                    .client
+                    .post_signed_d(API_V3_ORDER, &request)
+                    .await
+                    .map_err(from_binance_error)?;
+                Ok(OrderSubmission {
+                    pair: order.pair.clone(),
+                    order_type: OrderType::TrailingStop,
+                    ..from_binance_transaction(tr)
+                })
+            }
+            t @ (AssetType::Futures | AssetType::PerpetualContract) => {
+                let account = self.futures_account();
+                let params = to_binance_trailing_stop_params(order, pair_conf, true);
+                let request = build_signed_request(params, account.recv_window).map_err(from_binance_error)?;
+                if !self.burst {
+                    ratelimit::acquire(Exchange::Binance, FUTURES_WEIGHT_ENDPOINT, 1).await?;
+                }
+                let result: FuturesOrderResult = account
+                    .client
+                    .post_signed_d(FAPI_V1_ORDER, &request)
+                    .await
+                    .map_err(from_binance_error)?;
+                Ok(from_binance_futures_order_result(result, order.pair.clone(), t))
+            }
+            t => Err(Error::UnsupportedCapability(format!(
+                "trailing stop orders on {} accounts",
+                t.as_ref()
+            ))),
+        }
+    }
+
+    /// Place up to [`FUTURES_BATCH_LIMIT`] futures orders in a single request, every order is accepted or
+    /// rejected on its own
+    pub(crate) async fn add_futures_batch_orders(
+        &self,
+        orders: &[AddOrderRequest],
+    ) -> Result<Vec<Result<OrderSubmission>>> {
+        let batch = orders
+            .iter()
+            .map(|order| {
+                broker_core::pair::pair_conf(&Exchange::Binance, &order.pair)
+                    .map(|pair_conf| to_binance_futures_order_params(order, &pair_conf))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let mut params = BTreeMap::new();
+        params.insert("batchOrders".to_string(), serde_json::to_string(&batch)?);
+        let account = self.futures_account();
+        let request = build_signed_request(params, account.recv_window).map_err(from_binance_error)?;
+        if !self.burst {
+            ratelimit::acquire(Exchange::Binance, FUTURES_WEIGHT_ENDPOINT, 5).await?;
+        }
+        let results: Vec<FuturesBatchOrderResult> = account
+            .client
+            .post_signed_d(FAPI_V1_BATCH_ORDERS, &request)
+            .await
+            .map_err(from_binance_error)?;
+        Ok(results
+            .into_iter()
+            .zip(orders)
+            .map(|(result, order)| {
+                from_binance_futures_batch_order_result(result, order, order.asset_type.unwrap_or(AssetType::Futures))
+            })
+            .collect())
+    }
+
+    /// Funding fees of the futures account at or after `start_time`, oldest first
+    pub(crate) async fn futures_funding_income(&self, start_time: i64) -> Result<Vec<FuturesIncome>> {
+        let mut params = BTreeMap::new();
+        params.insert("incomeType".to_string(), "FUNDING_FEE".to_string());
+        params.insert("startTime".to_string(), start_time.to_string());
+        params.insert("limit".to_string(), "1000".to_string());
+        let account = self.futures_account();
+        let request = build_signed_request(params, account.recv_window).map_err(from_binance_error)?;
+        if !self.burst {
+            ratelimit::acquire(Exchange::Binance, FUTURES_WEIGHT_ENDPOINT, 30).await?;
+        }
+        account
+            .client
+            .get_signed_d(FAPI_V1_INCOME, &request)
+            .await
+            .map_err(from_binance_error)
+    }
+
+    /// Current open interest of a futures symbol
+    pub async fn open_interest(&self, symbol: &str) -> Result<BinanceOpenInterest> {
+        if !self.burst {
+            ratelimit::acquire(Exchange::Binance, FUTURES_WEIGHT_ENDPOINT, 1).await?;
+        }
+        self.futures_market()
+            .open_interest(symbol)
+            .await
+            .map_err(from_binance_error)
+    }
+
     /// The number of calls in a given period is limited. In order to avoid a ban we limit
     /// by default the number of api requests.
     /// This function sets or removes the limitation.
     /// Burst false implies no block.
     /// Burst true implies there is a control over the number of calls allowed to the exchange
     pub fn set_burst(&mut self, burst: bool) { self.burst = burst }
+
+    /// Wait until `weight` is available in the request weight budget, unless bursting
+    pub(crate) async fn throttle(&self, weight: u32) -> Result<()> {
+        if self.burst {
+            return Ok(());
+        }
+        ratelimit::acquire(Exchange::Binance, WEIGHT_ENDPOINT, weight).await
+    }
+
+    /// Wait until an order can be placed, orders also count in the request weight budget
+    pub(crate) async fn throttle_order(&self, weight: u32) -> Result<()> {
+        self.throttle(weight).await?;
+        if self.burst {
+            return Ok(());
+        }
+        ratelimit::acquire(Exchange::Binance, ORDERS_ENDPOINT, 1).await
+    }
 }
 
 #[cfg(test)]
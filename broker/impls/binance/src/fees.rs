@@ -3,10 +3,10 @@ use broker_core::fees::{Fee, FeeProvider};
 use broker_core::prelude::AssetType;
 use broker_core::types::OrderType;
 
-#[derive(Debug)]
+#[derive(Debug, Deserialize)]
+#[serde(default)]
 pub(crate) struct BinanceFeeProvider {
     bnb_burn: bool,
-    #[allow(dead_code)]
     vip_level: i8,
 }
 
@@ -2,35 +2,57 @@
 //! This a more convenient and safe way to deal with the exchange since methods return a Result<>
 //! but this generic API does not provide all the functionnality that Binance offers.
 
+use std::collections::{BTreeMap, HashMap};
 use std::time::Duration;
 
 use async_trait::async_trait;
 use backoff::ExponentialBackoffBuilder;
-use chrono::{TimeZone, Utc};
+use chrono::{DateTime, TimeZone, Utc};
 use itertools::Itertools;
+use stats::kline::{Resolution, TimeUnit};
 
-use binance::account::{OrderRequest, OrderStatusRequest};
-use binance::rest_model::{Filters, InterestRateHistoryQuery, MarginOrder, MarginOrderQuery};
+use binance::account::{OrderCancellation, OrderRequest, OrderStatusRequest};
+use binance::rest_model::{Filters, InterestRateHistoryQuery, KlineSummaries, MarginOrder, MarginOrderQuery, Prices};
+use binance::util::build_signed_request;
 use futures::TryFutureExt;
 
 use super::adapters::is_isolated_margin_str;
-use super::api::BinanceApi;
+use super::api::{BinanceApi, FUTURES_BATCH_LIMIT, ORDERS_LIMIT, WEIGHT_LIMIT};
 
-use crate::adapters::{from_binance_balance, from_binance_error, from_binance_isolated_margin_account_details,
+use crate::adapters::{from_binance_balance, from_binance_commissions, from_binance_error, from_binance_futures_income,
+                      from_binance_isolated_margin_account_details, from_binance_kline_summary,
                       from_binance_margin_account_details, from_binance_margin_order_result,
-                      from_binance_margin_order_state, from_binance_order, from_binance_transaction,
-                      to_binance_margin_order, to_binance_order_request};
+                      from_binance_margin_order_state, from_binance_my_trade, from_binance_oco_order_report,
+                      from_binance_order, from_binance_prices, from_binance_system_status, from_binance_transaction,
+                      kline_interval, to_binance_margin_order, to_binance_oco_order_params, to_binance_order_request,
+                      MyTrade, OcoOrderList};
 use broker_core::error::*;
+use broker_core::fees::FeeTier;
 use broker_core::pair::{pair_string, symbol_to_pair, PairConf};
 use broker_core::prelude::*;
 use broker_core::types::*;
 
+static API_V3_MYTRADES: &str = "/api/v3/myTrades";
+static API_V3_ORDER_OCO: &str = "/api/v3/order/oco";
+static SAPI_V1_MARGIN_ORDER_OCO: &str = "/sapi/v1/margin/order/oco";
+/// Maximum number of klines returned by a single request
+const KLINES_LIMIT: u16 = 1000;
+
+/// The symbol of the isolated margin account of a loan, if any
+fn isolated_symbol(loan: &MarginLoanRequest) -> Result<Option<String>> {
+    loan.isolated_pair
+        .as_ref()
+        .map(|pair| pair_string(Exchange::Binance, pair))
+        .transpose()
+}
+
 #[async_trait]
 impl Brokerage for BinanceApi {
     async fn ticker(&self, pair: Pair) -> Result<Ticker> {
         let market = self.market();
 
         let pair_str = pair_string(Exchange::Binance, &pair)?;
+        self.throttle(2).await?;
         let result = market
             .get_24h_price_stats(pair_str.to_string())
             .await
@@ -45,10 +67,24 @@ impl Brokerage for BinanceApi {
         })
     }
 
+    async fn tickers(&self, pairs: Vec<Pair>) -> Result<HashMap<Pair, f64>> {
+        let symbols: HashMap<String, Pair> = pairs
+            .into_iter()
+            .filter_map(|pair| pair_string(Exchange::Binance, &pair).ok().map(|symbol| (symbol, pair)))
+            .collect();
+        if symbols.is_empty() {
+            return Ok(HashMap::new());
+        }
+        self.throttle(4).await?;
+        let Prices::AllPrices(prices) = self.market().get_all_prices().await.map_err(from_binance_error)?;
+        Ok(from_binance_prices(prices, &symbols))
+    }
+
     async fn orderbook(&self, pair: Pair) -> Result<Orderbook> {
         let market = self.market();
         let pair_str = pair_string(Exchange::Binance, &pair)?;
 
+        self.throttle(5).await?;
         let book_ticker = market.get_depth(pair_str).await.map_err(from_binance_error)?;
 
         Ok(Orderbook {
@@ -62,6 +98,7 @@ impl Brokerage for BinanceApi {
 
     /// Return the balances for each currency on the account
     async fn account_balances(&self) -> Result<AccountPosition> {
+        self.throttle(20).await?;
         let result = self.account().get_account().await.map_err(from_binance_error)?;
 
         let mut balances = AccountPosition::new();
@@ -75,7 +112,17 @@ impl Brokerage for BinanceApi {
         Ok(balances)
     }
 
+    async fn fee_tier(&self) -> Result<FeeTier> {
+        self.throttle(20).await?;
+        let result = self.account().get_account().await.map_err(from_binance_error)?;
+        Ok(from_binance_commissions(
+            result.maker_commission,
+            result.taker_commission,
+        ))
+    }
+
     async fn margin_account(&self, asset: Option<String>) -> Result<MarginAccountDetails> {
+        self.throttle(10).await?;
         let details: MarginAccountDetails = match asset {
             Some(pair) => {
                 let pair_str = pair_string(Exchange::Binance, &pair.into())?;
@@ -94,18 +141,58 @@ impl Brokerage for BinanceApi {
         Ok(details)
     }
 
+    async fn borrow(&self, loan: MarginLoanRequest) -> Result<String> {
+        loan.validate()?;
+        let symbol = isolated_symbol(&loan)?;
+        self.throttle(3).await?;
+        self.margin()
+            .loan_with_isolation(
+                loan.asset.to_string(),
+                loan.amount,
+                symbol.as_ref().map(|_| true),
+                symbol,
+            )
+            .await
+            .map(|tx| tx.tran_id.to_string())
+            .map_err(from_binance_error)
+    }
+
+    async fn repay(&self, loan: MarginLoanRequest) -> Result<String> {
+        loan.validate()?;
+        let symbol = isolated_symbol(&loan)?;
+        self.throttle(3).await?;
+        self.margin()
+            .repay_with_isolation(
+                loan.asset.to_string(),
+                loan.amount,
+                symbol.as_ref().map(|_| true),
+                symbol,
+            )
+            .await
+            .map(|tx| tx.tran_id.to_string())
+            .map_err(from_binance_error)
+    }
+
     async fn add_order(&self, order: AddOrderRequest) -> Result<OrderSubmission> {
         let pair_conf = broker_core::pair::pair_conf(&Exchange::Binance, &order.pair)?;
         let is_dry_run = &order.dry_run;
         if order.order_type == OrderType::Limit && order.price.is_none() {
             return Err(Error::MissingPrice);
         }
+        if order.order_type == OrderType::TrailingStop {
+            // There is no test endpoint for futures orders
+            if *is_dry_run {
+                return Ok(order.simulate_submission(0.001));
+            }
+            return self.add_trailing_stop_order(&order, &pair_conf).await;
+        }
         match order.asset_type {
             None | Some(AssetType::Spot) => {
                 let order_request: OrderRequest = to_binance_order_request(&order, &pair_conf);
 
                 let account = self.account();
                 if *is_dry_run {
+                    self.throttle(1).await?;
                     let _tr = account
                         .place_test_order(order_request)
                         .await
@@ -113,6 +200,7 @@ impl Brokerage for BinanceApi {
                     let submission = order.simulate_submission(0.001);
                     Ok(submission)
                 } else {
+                    self.throttle_order(1).await?;
                     let tr = account.place_order(order_request).await.map_err(from_binance_error)?;
                     let symbol = tr.symbol.clone().into();
                     Ok(OrderSubmission {
@@ -124,6 +212,7 @@ impl Brokerage for BinanceApi {
             Some(t @ (AssetType::Margin | AssetType::IsolatedMargin)) => {
                 let margin = self.margin();
                 let new_order: MarginOrder = to_binance_margin_order(&order, &pair_conf, t);
+                self.throttle_order(6).await?;
                 let margin_order_result = margin.new_order(new_order).await.map_err(from_binance_error)?;
                 let symbol = margin_order_result.symbol.clone().into();
                 Ok(OrderSubmission {
@@ -135,7 +224,65 @@ impl Brokerage for BinanceApi {
         }
     }
 
+    async fn add_oco_order(&self, order: OcoOrderRequest) -> Result<Vec<OrderSubmission>> {
+        order.validate()?;
+        let pair_conf = broker_core::pair::pair_conf(&Exchange::Binance, &order.limit.pair)?;
+        let asset_type = order.limit.asset_type.unwrap_or(AssetType::Spot);
+        let params = to_binance_oco_order_params(&order, &pair_conf, asset_type);
+        let order_list: OcoOrderList = match asset_type {
+            AssetType::Spot => {
+                let account = self.account();
+                let request = build_signed_request(params, account.recv_window).map_err(from_binance_error)?;
+                self.throttle_order(2).await?;
+                account
+                    .client
+                    .post_signed_d(API_V3_ORDER_OCO, &request)
+                    .await
+                    .map_err(from_binance_error)?
+            }
+            AssetType::Margin | AssetType::IsolatedMargin => {
+                let margin = self.margin();
+                let request = build_signed_request(params, margin.recv_window).map_err(from_binance_error)?;
+                self.throttle_order(6).await?;
+                margin
+                    .client
+                    .post_signed_d(SAPI_V1_MARGIN_ORDER_OCO, &request)
+                    .await
+                    .map_err(from_binance_error)?
+            }
+            _ => return Err(Error::BrokerFeatureNotImplemented),
+        };
+        Ok(order_list
+            .order_reports
+            .into_iter()
+            .map(|r| from_binance_oco_order_report(r, order.limit.pair.clone(), asset_type))
+            .collect())
+    }
+
+    async fn add_orders(&self, orders: Vec<AddOrderRequest>) -> Result<Vec<Result<OrderSubmission>>> {
+        // Only futures have a batch endpoint, other orders are placed one after the other
+        let is_futures_batch = orders.iter().all(|order| {
+            !order.dry_run
+                && matches!(
+                    order.asset_type,
+                    Some(AssetType::Futures | AssetType::PerpetualContract)
+                )
+        });
+        let mut submissions = Vec::with_capacity(orders.len());
+        if is_futures_batch {
+            for batch in orders.chunks(FUTURES_BATCH_LIMIT) {
+                submissions.extend(self.add_futures_batch_orders(batch).await?);
+            }
+        } else {
+            for order in orders {
+                submissions.push(self.add_order(order).await);
+            }
+        }
+        Ok(submissions)
+    }
+
     async fn get_order(&self, id: String, pair: Pair, asset_type: AssetType) -> Result<Order> {
+        self.throttle(10).await?;
         let res = match asset_type {
             AssetType::Spot => self
                 .account()
@@ -164,6 +311,24 @@ impl Brokerage for BinanceApi {
         }
     }
 
+    async fn cancel_order(&self, id: String, pair: Pair, asset_type: AssetType) -> Result<()> {
+        match asset_type {
+            AssetType::Spot => {
+                self.throttle(1).await?;
+                self.account()
+                    .cancel_order(OrderCancellation {
+                        symbol: pair_string(Exchange::Binance, &pair)?,
+                        orig_client_order_id: Some(id),
+                        ..OrderCancellation::default()
+                    })
+                    .await
+                    .map(|_| ())
+                    .map_err(from_binance_error)
+            }
+            _ => Err(Error::BrokerFeatureNotImplemented),
+        }
+    }
+
     #[allow(clippy::cast_possible_truncation)]
     async fn pairs(&self) -> Result<Vec<PairConf>> {
         let general = self.general();
@@ -174,6 +339,7 @@ impl Brokerage for BinanceApi {
             .with_max_elapsed_time(Some(Duration::from_secs(2)))
             .build();
         let mut symbols: Vec<PairConf> = Vec::new();
+        self.throttle(20).await?;
         let exchange_info = backoff::future::retry(retry_policy, || general.exchange_info().err_into())
             .await
             .map_err(from_binance_error)?;
@@ -231,9 +397,51 @@ impl Brokerage for BinanceApi {
 
     fn uses_account(&self) -> bool { self.api_key.is_some() && self.api_secret.is_some() }
 
+    fn capabilities(&self) -> ExchangeCapabilities {
+        ExchangeCapabilities {
+            order_types: vec![
+                OrderType::Limit,
+                OrderType::Market,
+                OrderType::StopLoss,
+                OrderType::StopLossLimit,
+                OrderType::TakeProfit,
+                OrderType::TakeProfitLimit,
+                OrderType::LimitMaker,
+                OrderType::TrailingStop,
+            ],
+            post_only: true,
+            margin: true,
+            oco: true,
+            amend: false,
+            // Only futures symbols have a countdown, spot orders cannot be canceled with a timer
+            dead_man_switch: None,
+            max_orderbook_depth: Some(5000),
+            rate_limits: vec![WEIGHT_LIMIT, ORDERS_LIMIT],
+            resolutions: vec![
+                Resolution::new(TimeUnit::Second, 1),
+                Resolution::new(TimeUnit::Minute, 1),
+                Resolution::new(TimeUnit::Minute, 3),
+                Resolution::new(TimeUnit::Minute, 5),
+                Resolution::new(TimeUnit::Minute, 15),
+                Resolution::new(TimeUnit::Minute, 30),
+                Resolution::new(TimeUnit::Hour, 1),
+                Resolution::new(TimeUnit::Hour, 2),
+                Resolution::new(TimeUnit::Hour, 4),
+                Resolution::new(TimeUnit::Hour, 6),
+                Resolution::new(TimeUnit::Hour, 8),
+                Resolution::new(TimeUnit::Hour, 12),
+                Resolution::new(TimeUnit::Day, 1),
+                Resolution::new(TimeUnit::Day, 3),
+                Resolution::new(TimeUnit::Week, 1),
+                Resolution::new(TimeUnit::Month, 1),
+            ],
+        }
+    }
+
     #[allow(clippy::cast_possible_truncation)]
     async fn margin_interest_rate(&self, symbol: MarketSymbol) -> Result<InterestRate> {
         let margin = self.margin();
+        self.throttle(1).await?;
         let history = margin
             .interest_rate_history(InterestRateHistoryQuery {
                 asset: symbol.to_string(),
@@ -257,4 +465,72 @@ impl Brokerage for BinanceApi {
             })
             .ok_or(Error::NotFound)
     }
+
+    async fn system_status(&self) -> Result<SystemStatus> {
+        self.throttle(1).await?;
+        self.wallet()
+            .system_status()
+            .await
+            .map(from_binance_system_status)
+            .map_err(from_binance_error)
+    }
+
+    #[allow(clippy::cast_sign_loss)]
+    async fn candles(
+        &self,
+        pair: Pair,
+        resolution: Resolution,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Vec<Candle>> {
+        let interval = kline_interval(Some(resolution))
+            .ok_or_else(|| Error::UnsupportedCapability(format!("binance candles with resolution {:?}", resolution)))?;
+        self.throttle(2).await?;
+        let KlineSummaries::AllKlineSummaries(klines) = self
+            .market()
+            .get_klines(
+                pair_string(Exchange::Binance, &pair)?,
+                interval,
+                KLINES_LIMIT,
+                start.timestamp_millis() as u64,
+                (end.timestamp_millis() - 1) as u64,
+            )
+            .await
+            .map_err(from_binance_error)?;
+        Ok(klines
+            .iter()
+            .map(|k| from_binance_kline_summary(k, pair.clone()))
+            .collect())
+    }
+
+    async fn my_trades(&self, pair: Pair, since: Option<DateTime<Utc>>) -> Result<Vec<TradeFill>> {
+        let account = self.account();
+        let mut parameters: BTreeMap<String, String> = BTreeMap::new();
+        parameters.insert("symbol".to_string(), pair_string(Exchange::Binance, &pair)?);
+        if let Some(since) = since {
+            parameters.insert("startTime".to_string(), since.timestamp_millis().to_string());
+        }
+        let request = build_signed_request(parameters, account.recv_window).map_err(from_binance_error)?;
+        self.throttle(20).await?;
+        let trades: Vec<MyTrade> = account
+            .client
+            .get_signed_d(API_V3_MYTRADES, &request)
+            .await
+            .map_err(from_binance_error)?;
+        Ok(trades
+            .into_iter()
+            .map(|t| from_binance_my_trade(t, pair.clone()))
+            .collect())
+    }
+
+    async fn funding_payments(&self, since: DateTime<Utc>) -> Result<Vec<FundingPayment>> {
+        let incomes = self.futures_funding_income(since.timestamp_millis()).await?;
+        Ok(incomes
+            .into_iter()
+            .filter_map(|income| {
+                let pair = symbol_to_pair(&Exchange::Binance, &income.symbol.clone().into()).ok()?;
+                Some(from_binance_futures_income(income, pair))
+            })
+            .collect())
+    }
 }
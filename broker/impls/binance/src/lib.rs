@@ -57,13 +57,21 @@ impl BrokerConnector for BinanceExchangeConnector {
 
     async fn new_private_stream(
         &self,
-        _ctx: PrivateBotInitContext,
+        ctx: PrivateBotInitContext,
     ) -> broker_core::error::Result<Box<BrokerageAccountDataStreamer>> {
-        todo!()
+        Ok(Box::new(
+            BinanceStreamingAccountApi::new_bot(ctx.creds, ctx.use_test, ctx.account_type, ctx.channels).await?,
+        ))
     }
 
-    fn fees_provider(&self, _conf: Value) -> broker_core::error::Result<Arc<dyn FeeProvider>> {
-        Ok(Arc::new(BinanceFeeProvider::new(false, 0)))
+    fn fees_provider(&self, conf: Value) -> broker_core::error::Result<Arc<dyn FeeProvider>> {
+        // Fees are then refreshed from the tier of the account, see `Brokerage::fee_tier`
+        let provider: BinanceFeeProvider = if conf.is_null() {
+            BinanceFeeProvider::default()
+        } else {
+            serde_json::from_value(conf)?
+        };
+        Ok(Arc::new(provider))
     }
 }
 
@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 use std::time::Duration;
 
@@ -6,13 +6,13 @@ use actix::io::SinkWrite;
 use async_trait::async_trait;
 use awc::ws::Message;
 use binance::config::Config;
+use binance::rest_model::KlineSummaries;
 use binance::ws_model::{CombinedStreamEvent, QueryResult, WebsocketEvent, WebsocketEventUntag};
 use broker_core::bot::{BotWrapper, DefaultWsActor, WsFramedSink, WsHandler};
 use broker_core::broker::MarketEventEnvelopeRef;
 use broker_core::metrics::ExchangeMetrics;
 use bstr::ByteSlice;
 use bytes::Bytes;
-use chrono::{TimeZone, Utc};
 use dashmap::DashMap;
 use futures::stream::{FuturesUnordered, StreamExt};
 use itertools::Itertools;
@@ -25,14 +25,35 @@ use broker_core::error::*;
 use broker_core::json_util::deserialize_json_s;
 use broker_core::pair::{pair_to_symbol, symbol_to_pair};
 use broker_core::prelude::*;
-use broker_core::streaming_api::StreamingApi;
+use broker_core::streaming_api::{CandleGap, CandleGapDetector, LiveBookStream, MarketEventStream, StreamingApi,
+                                 WsReconnectOptions};
 use broker_core::types::*;
 
 use super::adapters::*;
 
+/// Stitching of the diff depth stream of a pair with REST snapshots,
+/// see https://binance-docs.github.io/apidocs/spot/en/#how-to-manage-a-local-order-book-correctly
+#[derive(Debug)]
+enum DepthSync {
+    /// Waiting for a snapshot, diffs are buffered until it arrives
+    Buffering(Vec<OrderbookL3>),
+    /// Id of the last update forwarded
+    Synced(u64),
+}
+
 #[derive(Clone)]
 pub struct BinanceStreamingApi {
     books: Arc<DashMap<Pair, LiveAggregatedOrderBook>>,
+    depth_syncs: Arc<DashMap<Pair, DepthSync>>,
+    /// Pairs streamed as aggregated books
+    diff_book_pairs: HashSet<Pair>,
+    /// Pairs streamed as full depth changes
+    l3_pairs: HashSet<Pair>,
+    /// Futures pairs which open interest is polled, as it is not streamed
+    open_interest_pairs: HashSet<Pair>,
+    candle_gaps: Arc<CandleGapDetector>,
+    /// Candles received while missed candles of the pair are fetched
+    candle_backfills: Arc<DashMap<(Pair, String), Vec<Candle>>>,
     channels: Vec<MarketChannel>,
     sink: UnboundedSender<MarketEventEnvelopeRef>,
     api: Arc<BinanceApi>,
@@ -57,9 +78,22 @@ impl BinanceStreamingApi {
             .filter(|c| c.orderbook.is_some())
             .map(|c| (c.pair().clone(), c.orderbook.unwrap().depth.unwrap()))
             .collect();
+        let pairs_of = |channel_type| -> HashSet<Pair> {
+            channels
+                .iter()
+                .filter(|c| c.r#type == channel_type)
+                .map(|c| c.pair().clone())
+                .collect()
+        };
         let api = Arc::new(Self {
             sink: tx.clone(),
             books: Arc::new(DashMap::new()),
+            depth_syncs: Arc::new(DashMap::new()),
+            diff_book_pairs: pairs_of(MarketChannelType::Orderbooks),
+            l3_pairs: pairs_of(MarketChannelType::OrderbookL3),
+            open_interest_pairs: pairs_of(MarketChannelType::OpenInterest),
+            candle_gaps: Arc::new(CandleGapDetector::default()),
+            candle_backfills: Arc::new(DashMap::new()),
             channels,
             api: Arc::new(exchange_api),
             metrics: Arc::new(metrics),
@@ -69,8 +103,10 @@ impl BinanceStreamingApi {
         let addr = DefaultWsActor::new(
             "BinanceStream",
             url,
-            Some(Duration::from_secs(30)),
-            Some(Duration::from_secs(60)),
+            WsReconnectOptions::builder()
+                .conn_timeout(Duration::from_secs(30))
+                .stale_after(Duration::from_secs(60))
+                .build(),
             api,
         )
         .await?;
@@ -85,12 +121,16 @@ impl BinanceStreamingApi {
             .push(binance::websockets::STREAM_ENDPOINT);
         let stream_str = channels
             .iter()
-            .flat_map(|c| {
-                pair_to_symbol(&Exchange::Binance, c.pair()).and_then(|pair| {
-                    let sub = &subscription(c, &[pair.to_string()], 0, c.orderbook.and_then(|oc| oc.depth));
-                    Ok(sub.params.join("/"))
-                })
+            .filter(|c| c.r#type != MarketChannelType::OpenInterest)
+            .map(|c| {
+                let pair = pair_to_symbol(&Exchange::Binance, c.pair())?;
+                let sub = subscription(c, &[pair.to_string()], 0, c.orderbook.and_then(|oc| oc.depth))?;
+                Ok(sub.params.join("/"))
             })
+            .collect::<Result<Vec<_>>>()?
+            .into_iter()
+            // Aggregated and full depth books of a pair share the diff depth stream
+            .unique()
             .join("/");
         url.set_query(Some(&format!("streams={}", stream_str)));
         debug!("Binance connecting to the following streams : {}", stream_str);
@@ -155,45 +195,161 @@ impl BinanceStreamingApi {
         })
     }
 
-    // TODO: this is generic
-    fn latest_book<F>(&self, pair: &Pair, f: F) -> Option<Orderbook>
-    where
-        F: Fn(&mut LiveAggregatedOrderBook),
-    {
-        // Plumbing, init the book for the pair if it didn't exist
-        let books = self.books.as_ref();
-        let mut agg = books.entry(pair.clone()).or_insert_with(|| {
-            LiveAggregatedOrderBook::default_with_depth(pair.clone(), self.orderbook_depths.get(pair).copied())
-        });
-        f(&mut agg);
-        agg.latest_order_book().map(|b| self.log_book(b))
+    /// Forward a diff if it continues the book, diffs are buffered while a snapshot is fetched
+    fn stitch_depth(&self, diff: OrderbookL3) {
+        let pair = diff.pair.clone();
+        let mut sync = self
+            .depth_syncs
+            .entry(pair.clone())
+            .or_insert_with(|| DepthSync::Buffering(vec![]));
+        if self.apply_diff(sync.value_mut(), diff) {
+            drop(sync);
+            self.snapshot_depth(pair);
+        }
     }
 
-    // TODO: this is generic
-    pub(crate) fn log_book(&self, ob: Orderbook) -> Orderbook {
-        // If the book has changed, flush metrics and broadcast the change
-        if let Some(lowest_ask) = ob.asks.first() {
-            self.metrics
-                .lowest_ask(lowest_ask.0, lowest_ask.1, &ob.pair, "order_books");
+    /// Returns whether a snapshot is needed, either to start the book or because updates were missed
+    fn apply_diff(&self, sync: &mut DepthSync, diff: OrderbookL3) -> bool {
+        match sync {
+            DepthSync::Buffering(buffer) => {
+                buffer.push(diff);
+                buffer.len() == 1
+            }
+            DepthSync::Synced(last) if diff.last_update_id <= *last => false,
+            DepthSync::Synced(last) if diff.continues(*last) => {
+                *last = diff.last_update_id;
+                self.broadcast(MarketEvent::OrderbookL3(diff));
+                false
+            }
+            DepthSync::Synced(last) => {
+                warn!(pair = %diff.pair, last = %last, first = %diff.first_update_id, "binance missed depth updates");
+                *sync = DepthSync::Buffering(vec![diff]);
+                true
+            }
         }
-        if let Some(highest_bid) = ob.bids.first() {
-            self.metrics
-                .top_bid(highest_bid.0, highest_bid.1, &ob.pair, "order_books");
+    }
+
+    /// Fetch a snapshot then replay the buffered diffs on top of it, if it fails the next diff triggers a new one
+    fn snapshot_depth(&self, pair: Pair) {
+        let this = self.clone();
+        actix::spawn(async move {
+            let snapshot = this.depth_snapshot(&pair).await;
+            let Some(mut sync) = this.depth_syncs.get_mut(&pair) else {
+                return;
+            };
+            let DepthSync::Buffering(buffer) = sync.value_mut() else {
+                return;
+            };
+            let buffer = std::mem::take(buffer);
+            let snapshot = match snapshot {
+                Ok(snapshot) => snapshot,
+                Err(e) => {
+                    warn!(err = ?e, pair = %pair, "binance failed to snapshot the order book");
+                    return;
+                }
+            };
+            *sync = DepthSync::Synced(snapshot.last_update_id);
+            this.broadcast(MarketEvent::OrderbookL3(snapshot));
+            let mut resnapshot = false;
+            for diff in buffer {
+                resnapshot |= this.apply_diff(sync.value_mut(), diff);
+            }
+            drop(sync);
+            if resnapshot {
+                this.snapshot_depth(pair);
+            }
+        });
+    }
+
+    async fn depth_snapshot(&self, pair: &Pair) -> Result<OrderbookL3> {
+        let symbol = pair_to_symbol(&Exchange::Binance, pair)?;
+        // Full depth books start from the 1000 best levels of each side
+        self.api.throttle(50).await?;
+        let book = self
+            .api
+            .market()
+            .get_custom_depth(symbol.to_string(), 1000)
+            .await
+            .map_err(from_binance_error)?;
+        Ok(from_binance_depth_snapshot(&book, pair.clone()))
+    }
+
+    /// Hold back candles of pairs being backfilled, and start a backfill if candles were missed
+    #[allow(clippy::cast_sign_loss)]
+    fn sequence_candle(&self, candle: Candle, interval: &str) -> Option<Candle> {
+        let key = (candle.pair.clone(), interval.to_string());
+        if let Some(mut held) = self.candle_backfills.get_mut(&key) {
+            held.push(candle);
+            return None;
         }
+        let duration = candle.end_time - candle.start_time + chrono::Duration::milliseconds(1);
+        let Some(gap) = self.candle_gaps.observe(&candle, duration) else {
+            return Some(candle);
+        };
+        warn!(
+            pair = %gap.pair, from = %gap.from, to = %gap.to, missing = gap.missing(),
+            "binance missed candles, backfilling them"
+        );
+        self.metrics.candle_gap(&gap.pair, "candles", gap.missing() as u64);
+        self.candle_backfills.insert(key, vec![candle]);
+        self.backfill_candles(gap, interval.to_string());
+        None
+    }
+
+    /// Fetch the missed candles then release the candles held in the meantime, which are released even if it fails
+    fn backfill_candles(&self, gap: CandleGap, interval: String) {
+        let this = self.clone();
+        actix::spawn(async move {
+            match this.missed_candles(&gap, &interval).await {
+                Ok(candles) => {
+                    for candle in candles {
+                        this.broadcast(MarketEvent::TradeCandle(candle));
+                    }
+                }
+                Err(e) => warn!(err = ?e, pair = %gap.pair, "binance failed to backfill candles"),
+            }
+            if let Some((_, held)) = this.candle_backfills.remove(&(gap.pair.clone(), interval)) {
+                for candle in held {
+                    this.broadcast(MarketEvent::TradeCandle(candle));
+                }
+            }
+        });
+    }
 
-        ob
+    /// At most 1000 klines are fetched, longer gaps are only partially filled
+    #[allow(clippy::cast_sign_loss)]
+    async fn missed_candles(&self, gap: &CandleGap, interval: &str) -> Result<Vec<Candle>> {
+        let symbol = pair_to_symbol(&Exchange::Binance, &gap.pair)?;
+        self.api.throttle(2).await?;
+        let KlineSummaries::AllKlineSummaries(klines) = self
+            .api
+            .market()
+            .get_klines(
+                symbol.to_string(),
+                interval,
+                1000_u16,
+                gap.from.timestamp_millis() as u64,
+                (gap.to.timestamp_millis() - 1) as u64,
+            )
+            .await
+            .map_err(from_binance_error)?;
+        Ok(klines
+            .iter()
+            .map(|k| from_binance_kline_summary(k, gap.pair.clone()))
+            .collect())
     }
 
-    // TODO: this is generic
-    fn broadcast(&self, v: MarketEvent) {
-        let (pair, channel) = (&v.pair(), v.chan());
-        self.metrics.event_broadcasted(pair, channel);
-        let msg = Arc::new(MarketEventEnvelope::new(
-            Symbol::new(pair.clone(), SecurityType::Crypto, Self::EXCHANGE),
-            v,
-        ));
-        if let Err(e) = self.sink.send(msg) {
-            self.metrics.broadcast_failure(e.0.symbol.value.as_ref(), e.0.e.chan())
+    /// Poll the open interest of futures pairs, failures are retried on the next poll
+    async fn poll_open_interest(&self) {
+        for pair in &self.open_interest_pairs {
+            let open_interest = match pair_to_symbol(&Exchange::Binance, pair) {
+                Ok(symbol) => self.api.open_interest(symbol.as_ref()).await,
+                Err(e) => Err(e),
+            };
+            match open_interest {
+                Ok(oi) => self.broadcast(MarketEvent::OpenInterest(from_binance_open_interest(&oi, pair.clone()))),
+                Err(e) => warn!(err = ?e, pair = %pair, "binance failed to poll the open interest"),
+            }
         }
     }
 
@@ -209,8 +365,18 @@ impl BinanceStreamingApi {
                 })
                 .map(MarketEvent::Orderbook)
             }
+            WebsocketEventUntag::BookTicker(ref bt) => {
+                let pair = self.get_pair(&bt.symbol)?;
+                Some(MarketEvent::Quote(from_binance_book_ticker(bt, pair)))
+            }
             WebsocketEventUntag::WebsocketEvent(WebsocketEvent::DepthOrderBook(ob)) => {
                 let pair = self.get_pair(&ob.symbol)?;
+                if self.l3_pairs.contains(&pair) {
+                    self.stitch_depth(from_binance_depth_diff(&ob, pair.clone()));
+                }
+                if !self.diff_book_pairs.contains(&pair) {
+                    return Ok(None);
+                }
 
                 self.latest_book(&pair, |agg| {
                     // Update the book with latest asks and bids
@@ -224,30 +390,12 @@ impl BinanceStreamingApi {
             }
             WebsocketEventUntag::WebsocketEvent(WebsocketEvent::Trade(e)) => {
                 let pair = self.get_pair(e.symbol.as_str())?;
-                Some(MarketEvent::Trade(Trade {
-                    amount: e.qty.parse::<f64>().unwrap(),
-                    event_ms: e.event_time as i64,
-                    price: e.price.parse::<f64>().unwrap(),
-                    tt: TradeType::Sell,
-                    pair,
-                }))
+                Some(MarketEvent::Trade(from_binance_trade(&e, pair)))
             }
             WebsocketEventUntag::WebsocketEvent(WebsocketEvent::Kline(ke)) => {
                 let pair = self.get_pair(ke.symbol.as_str())?;
-                Some(MarketEvent::TradeCandle(Candle {
-                    event_time: Utc.timestamp_millis_opt(ke.event_time as i64).unwrap(),
-                    pair,
-                    start_time: Utc.timestamp_millis_opt(ke.kline.start_time).unwrap(),
-                    end_time: Utc.timestamp_millis_opt(ke.kline.end_time).unwrap(),
-                    open: ke.kline.open,
-                    high: ke.kline.close,
-                    low: ke.kline.low,
-                    close: ke.kline.close,
-                    volume: ke.kline.volume,
-                    quote_volume: ke.kline.quote_volume,
-                    trade_count: ke.kline.number_of_trades as u64,
-                    is_final: ke.kline.is_final_bar,
-                }))
+                self.sequence_candle(from_binance_kline(&ke, pair), &ke.kline.interval)
+                    .map(MarketEvent::TradeCandle)
             }
             _ => None,
         };
@@ -255,7 +403,7 @@ impl BinanceStreamingApi {
     }
 }
 
-#[async_trait]
+#[async_trait(?Send)]
 impl WsHandler for BinanceStreamingApi {
     #[cfg_attr(feature = "flame", flame)]
     fn handle_in(&self, _w: &mut SinkWrite<Message, WsFramedSink>, msg: Bytes) {
@@ -285,9 +433,27 @@ impl WsHandler for BinanceStreamingApi {
         // Do nothing, connections handled in combined stream
         self.metrics.stream_reconnected();
     }
+
+    /// Open interest is polled along with keep alives
+    async fn handle_keep_alive(&self) -> Result<()> {
+        self.poll_open_interest().await;
+        Ok(())
+    }
 }
 
 impl StreamingApi for BinanceStreamingApi {
     const NAME: &'static str = "binance";
     const EXCHANGE: Exchange = Exchange::Binance;
 }
+
+impl MarketEventStream for BinanceStreamingApi {
+    fn sink(&self) -> &UnboundedSender<MarketEventEnvelopeRef> { &self.sink }
+
+    fn metrics(&self) -> &ExchangeMetrics { &self.metrics }
+}
+
+impl LiveBookStream for BinanceStreamingApi {
+    fn books(&self) -> &DashMap<Pair, LiveAggregatedOrderBook> { &self.books }
+
+    fn orderbook_depths(&self) -> &HashMap<Pair, u16> { &self.orderbook_depths }
+}
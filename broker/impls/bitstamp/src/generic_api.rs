@@ -110,6 +110,7 @@ impl Brokerage for BitstampApi {
                 } else {
                     TradeType::Sell
                 },
+                aggressor: None,
             })
             .collect())
     }
@@ -47,7 +47,9 @@ impl BrokerConnector for BitstampExchangeConnector {
         todo!()
     }
 
-    fn fees_provider(&self, _conf: Value) -> broker_core::error::Result<Arc<dyn FeeProvider>> { todo!() }
+    fn fees_provider(&self, _conf: Value) -> broker_core::error::Result<Arc<dyn FeeProvider>> {
+        Err(broker_core::error::Error::BrokerFeatureNotImplemented)
+    }
 }
 
 exchange!(Exchange::Bitstamp, BitstampExchangeConnector);
@@ -1,8 +1,9 @@
 use serde::{Deserialize, Serialize};
 use serde_aux::prelude::*;
 
+use broker_core::error::Error;
 use broker_core::types::{MarketChannel, MarketChannelType, MarketEvent, MarketSymbol, Orderbook, OrderbookConf,
-                         OrderbookLevel, Pair, Ticker as BrokerTicker, Trade};
+                         OrderbookLevel, Pair, Quote, Ticker as BrokerTicker, Trade};
 
 use super::utils;
 
@@ -84,6 +85,24 @@ impl From<LiveOrderBook> for Orderbook {
     }
 }
 
+impl LiveOrderBook {
+    /// The best bid and offer, if both sides of the book are quoted
+    pub fn quote(&self, pair: Pair) -> Option<Quote> {
+        let parse = |(p, v): &(String, String)| p.parse::<f64>().ok().zip(v.parse::<f64>().ok());
+        let (bid, bid_qty) = self.bids.first().and_then(parse)?;
+        let (ask, ask_qty) = self.asks.first().and_then(parse)?;
+        Some(Quote {
+            // Timestamps are in microseconds
+            event_ms: self.microtimestamp.parse::<i64>().ok()? / 1000,
+            pair,
+            bid,
+            bid_qty,
+            ask,
+            ask_qty,
+        })
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct LiveOrder {
     id: i64,
@@ -102,6 +121,10 @@ pub struct Enveloppe<T> {
     pub channel: String,
 }
 
+impl<T> Enveloppe<T> {
+    pub fn data(&self) -> &T { &self.data }
+}
+
 pub type PlainEvent = Enveloppe<serde_json::Value>;
 
 #[derive(Debug, Serialize, Deserialize, Message)]
@@ -131,6 +154,7 @@ impl TryFrom<Event> for MarketEvent {
                 event_ms: e.data.microtimestamp.parse::<i64>().unwrap(),
                 price: e.data.price,
                 tt: e.data.ty.into(),
+                aggressor: None,
                 pair: (*utils::get_pair_string(&Pair::from("")).unwrap()).into(),
             })),
             Event::LiveFullOrderBook(e) => Ok(MarketEvent::Orderbook(e.data.into())),
@@ -151,10 +175,21 @@ pub struct Subscription {
     data: Data,
 }
 
-pub fn subscription(c: &MarketChannel, currency_pair: &str) -> Subscription {
+impl Subscription {
+    /// Name of the channel, which events of the subscription are sent on
+    pub fn channel(&self) -> &str { &self.data.channel }
+}
+
+/// The subscription to a market channel
+///
+/// # Errors
+///
+/// If Bitstamp does not stream the channel
+pub fn subscription(c: &MarketChannel, currency_pair: &str) -> Result<Subscription, Error> {
     let channel_str = match c.r#type {
         MarketChannelType::Trades => "live_trades",
-        MarketChannelType::Quotes => "live_orders",
+        // Level 1 books are the best bid and offer
+        MarketChannelType::Quotes => "order_book",
         MarketChannelType::Orderbooks => {
             let conf = c.orderbook.unwrap_or_else(OrderbookConf::default);
             match conf.level {
@@ -163,22 +198,20 @@ pub fn subscription(c: &MarketChannel, currency_pair: &str) -> Subscription {
                 OrderbookLevel::Level3 => "detail_order_book",
             }
         }
-        MarketChannelType::Candles => {
-            unimplemented!()
-        }
-        MarketChannelType::OpenInterest => {
-            unimplemented!()
-        }
-        MarketChannelType::QuotesCandles => {
-            unimplemented!()
+        MarketChannelType::Candles
+        | MarketChannelType::OpenInterest
+        | MarketChannelType::QuotesCandles
+        | MarketChannelType::FundingRate
+        | MarketChannelType::OrderbookL3 => {
+            return Err(Error::UnsupportedChannel(format!("{:?}", c.r#type)));
         }
     };
-    Subscription {
+    Ok(Subscription {
         event: String::from("bts:subscribe"),
         data: Data {
             channel: format!("{}_{}", channel_str, currency_pair),
         },
-    }
+    })
 }
 
 #[non_exhaustive]
@@ -200,13 +233,60 @@ pub(crate) struct Transaction {
 
 #[cfg(test)]
 mod model_tests {
+    use broker_core::exchange::Exchange;
+    use broker_core::types::{SecurityType, Symbol};
+
     use super::*;
 
+    #[test]
+    fn subscribe_to_unsupported_channels() {
+        let channel = |r#type| {
+            MarketChannel::builder()
+                .symbol(Symbol::new("BTC_USD".into(), SecurityType::Crypto, Exchange::Bitstamp))
+                .r#type(r#type)
+                .build()
+        };
+        let sub = subscription(&channel(MarketChannelType::Trades), "btcusd").unwrap();
+        assert_eq!(sub.channel(), "live_trades_btcusd");
+        for r#type in [
+            MarketChannelType::Candles,
+            MarketChannelType::OpenInterest,
+            MarketChannelType::QuotesCandles,
+            MarketChannelType::FundingRate,
+            MarketChannelType::OrderbookL3,
+        ] {
+            assert!(matches!(
+                subscription(&channel(r#type), "btcusd"),
+                Err(Error::UnsupportedChannel(_))
+            ));
+        }
+    }
+
     #[tokio::test]
     async fn deserialize_live_trade() {
         let _v: Event = serde_json::from_slice(b"{\"data\": {\"microtimestamp\": \"1577146143220559\", \"amount\": 0.00434678, \"buy_order_id\": 4481152330, \"sell_order_id\": 4481152280, \"amount_str\": \"0.00434678\", \"price_str\": \"7312.91\", \"timestamp\": \"1577146143\", \"price\": 7312.91, \"type\": 0, \"id\": 102177815}, \"event\": \"trade\", \"channel\": \"live_trades_btcusd\"}").unwrap();
     }
 
+    #[test]
+    fn order_book_quote() {
+        let v: Event = serde_json::from_slice(b"{\"data\": {\"timestamp\": \"1577146143\", \"microtimestamp\": \"1577146143220559\", \"bids\": [[\"7312.00\", \"0.5\"], [\"7311.00\", \"1.0\"]], \"asks\": [[\"7313.50\", \"0.25\"]]}, \"event\": \"data\", \"channel\": \"order_book_btcusd\"}").unwrap();
+        let Event::LiveFullOrderBook(e) = v else {
+            panic!("expected an order book");
+        };
+        assert_eq!(e.channel, "order_book_btcusd");
+        assert_eq!(
+            e.data().quote("BTC_USD".into()),
+            Some(Quote {
+                event_ms: 1_577_146_143_220,
+                pair: "BTC_USD".into(),
+                bid: 7312.0,
+                bid_qty: 0.5,
+                ask: 7313.5,
+                ask_qty: 0.25,
+            })
+        );
+    }
+
     #[tokio::test]
     async fn deserialize_sub_succeeded() {
         let _v: Event = serde_json::from_slice(b"{\"data\": {\"microtimestamp\": \"1577146143220559\", \"amount\": 0.00434678, \"buy_order_id\": 4481152330, \"sell_order_id\": 4481152280, \"amount_str\": \"0.00434678\", \"price_str\": \"7312.91\", \"timestamp\": \"1577146143\", \"price\": 7312.91, \"type\": 0, \"id\": 102177815}, \"event\": \"trade\", \"channel\": \"live_trades_btcusd\"}").unwrap();
@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::str::FromStr;
 use std::sync::Arc;
 use std::time::Duration;
@@ -17,7 +18,7 @@ use url::Url;
 use broker_core::error::*;
 use broker_core::json_util::deserialize_json_s;
 use broker_core::prelude::*;
-use broker_core::streaming_api::StreamingApi;
+use broker_core::streaming_api::{request_reconnect, MarketEventStream, StreamingApi, WsReconnectOptions};
 use broker_core::types::*;
 
 use super::models::*;
@@ -26,7 +27,9 @@ use super::models::*;
 #[derivative(Debug)]
 pub struct BitstampStreamingApi {
     sink: UnboundedSender<MarketEventEnvelopeRef>,
-    channels: Vec<MarketChannel>,
+    subscriptions: Vec<(MarketChannel, Subscription)>,
+    /// Pairs of the quotes channels, by name of the book channel they are read from
+    quote_channels: HashMap<String, Pair>,
     #[derivative(Debug = "ignore")]
     metrics: Arc<ExchangeMetrics>,
 }
@@ -39,34 +42,37 @@ impl BitstampStreamingApi {
     ) -> Result<BotWrapper<DefaultWsActor, UnboundedReceiverStream<MarketEventEnvelopeRef>>> {
         let metrics = Arc::new(ExchangeMetrics::for_exchange(Exchange::Binance));
         let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+        let subscriptions = channels
+            .into_iter()
+            .map(|c| {
+                let symbol = broker_core::pair::pair_to_symbol(&Self::EXCHANGE, &c.symbol.value)?;
+                let sub = subscription(&c, symbol.as_ref())?;
+                Ok((c, sub))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let quote_channels = subscriptions
+            .iter()
+            .filter(|(c, _)| c.r#type == MarketChannelType::Quotes)
+            .map(|(c, sub)| (sub.channel().to_string(), c.symbol.value.clone()))
+            .collect();
         let api = BitstampStreamingApi {
             sink: tx,
-            channels,
+            subscriptions,
+            quote_channels,
             metrics,
         };
         let addr = DefaultWsActor::new(
             "BitstampStream",
             Url::from_str("wss://ws.bitstamp.net").unwrap(),
-            Some(Duration::from_secs(5)),
-            Some(Duration::from_secs(60)),
+            WsReconnectOptions::builder()
+                .conn_timeout(Duration::from_secs(5))
+                .stale_after(Duration::from_secs(60))
+                .build(),
             Arc::new(api),
         )
         .await?;
         Ok(BotWrapper::new(addr, UnboundedReceiverStream::new(rx)))
     }
-
-    // TODO : this is generic
-    fn broadcast(&self, v: MarketEvent) {
-        let (pair, channel) = (&v.pair(), v.chan());
-        self.metrics.event_broadcasted(pair, channel);
-        let msg = Arc::new(MarketEventEnvelope::new(
-            Symbol::new(pair.clone(), SecurityType::Crypto, Self::EXCHANGE),
-            v,
-        ));
-        if let Err(e) = self.sink.send(msg) {
-            self.metrics.broadcast_failure(e.0.symbol.value.as_ref(), e.0.e.chan());
-        }
-    }
 }
 
 #[async_trait]
@@ -79,9 +85,16 @@ impl WsHandler for BitstampStreamingApi {
         }
         match v.unwrap() {
             Event::ReconnectRequest(_) => {
-                self.handle_started(w);
+                if let Err(e) = request_reconnect(w) {
+                    tracing::error!(error = ?e, "bitstamp asked for a reconnection but the socket could not be closed");
+                }
             }
             Event::SubSucceeded(_) => (),
+            Event::LiveFullOrderBook(e) if self.quote_channels.contains_key(&e.channel) => {
+                if let Some(quote) = e.data().quote(self.quote_channels[&e.channel].clone()) {
+                    self.broadcast(MarketEvent::Quote(quote));
+                }
+            }
             o => {
                 if let Ok(le) = o.try_into() {
                     self.broadcast(le);
@@ -92,19 +105,12 @@ impl WsHandler for BitstampStreamingApi {
 
     #[cfg_attr(feature = "flame", flame)]
     fn handle_started(&self, w: &mut SinkWrite<Message, WsFramedSink>) {
-        for k in self.channels.iter() {
-            let pair = &k.symbol.value;
-            let result = serde_json::to_string(&subscription(
-                k,
-                broker_core::pair::pair_to_symbol(&Self::EXCHANGE, pair)
-                    .unwrap()
-                    .as_ref(),
-            ))
-            .unwrap();
+        for (k, sub) in self.subscriptions.iter() {
+            let result = serde_json::to_string(sub).unwrap();
             let bytes = result.into();
             match w.write(Message::Binary(bytes)) {
                 Ok(_) => {}
-                Err(_) => self.metrics.subscription_failure(pair, &format!("{:?}", k)),
+                Err(_) => self.metrics.subscription_failure(&k.symbol.value, &format!("{:?}", k)),
             }
         }
     }
@@ -114,3 +120,9 @@ impl StreamingApi for BitstampStreamingApi {
     const NAME: &'static str = "bitstamp";
     const EXCHANGE: Exchange = Exchange::Bitstamp;
 }
+
+impl MarketEventStream for BitstampStreamingApi {
+    fn sink(&self) -> &UnboundedSender<MarketEventEnvelopeRef> { &self.sink }
+
+    fn metrics(&self) -> &ExchangeMetrics { &self.metrics }
+}
@@ -49,7 +49,9 @@ impl BrokerConnector for BittrexExchangeConnector {
         todo!()
     }
 
-    fn fees_provider(&self, _conf: Value) -> broker_core::error::Result<Arc<dyn FeeProvider>> { todo!() }
+    fn fees_provider(&self, _conf: Value) -> broker_core::error::Result<Arc<dyn FeeProvider>> {
+        Err(broker_core::error::Error::BrokerFeatureNotImplemented)
+    }
 }
 
 exchange!(Exchange::Bittrex, BittrexExchangeConnector);
@@ -1,10 +1,14 @@
 use std::collections::{HashMap, HashSet};
+use std::future::Future;
 use std::io::Read;
 use std::sync::{Arc, RwLock};
+use std::time::Duration;
 
-use broker_core::bot::BotWrapper;
+use actix::{Actor, Addr};
+use broker_core::bot::{BotWrapper, ClientSupervisor, StreamActivity};
 use broker_core::broker::MarketEventEnvelopeRef;
 use broker_core::json_util::deserialize_json_s;
+use broker_core::streaming_api::WsReconnectOptions;
 use libflate::deflate::Decoder;
 use serde::de::DeserializeOwned;
 use serde_json::Value;
@@ -24,6 +28,7 @@ pub struct BittrexStreamingApi {
     books: Arc<RwLock<HashMap<Pair, LiveAggregatedOrderBook>>>,
     order_book_pairs: HashSet<Pair>,
     trade_pairs: HashSet<Pair>,
+    activity: Arc<StreamActivity>,
 }
 
 const BITTREX_HUB: &str = "c2";
@@ -36,7 +41,51 @@ impl BittrexStreamingApi {
     pub async fn new_bot(
         _creds: &dyn Credentials,
         channels: Vec<MarketChannel>,
-    ) -> Result<BotWrapper<HubClient, UnboundedReceiverStream<MarketEventEnvelopeRef>>> {
+    ) -> Result<BotWrapper<ClientSupervisor<HubClient>, UnboundedReceiverStream<MarketEventEnvelopeRef>>> {
+        let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+        let options = WsReconnectOptions::builder()
+            .conn_timeout(Duration::from_secs(30))
+            .stale_after(Duration::from_secs(60))
+            .build();
+        let addr = Self::supervise(&channels, tx, options, |api, order_book_pairs| async move {
+            // Restarts are left to the supervisor, so that they follow the same options as other streams
+            let addr = HubClient::new(
+                BITTREX_HUB,
+                "https://socket.bittrex.com/signalr/",
+                20,
+                RestartPolicy::Never,
+                api,
+            )
+            .await
+            .map_err(|e| Error::ExchangeError(format!("{}", e)))?;
+            for pair in order_book_pairs {
+                let currency = super::utils::get_pair_string(&pair).unwrap();
+                addr.do_send(HubQuery::new(
+                    BITTREX_HUB.to_string(),
+                    "QueryExchangeState".to_string(),
+                    vec![currency.to_string()],
+                    "QE2".to_string(),
+                ));
+            }
+            Ok(addr)
+        })
+        .await?;
+        Ok(BotWrapper::new(addr, UnboundedReceiverStream::new(rx)))
+    }
+
+    /// Supervise the hubs created by `new_hub` for a handler of `channels` and the order book pairs to query,
+    /// a new hub is created when the previous one goes stale
+    async fn supervise<A, F, Fut>(
+        channels: &[MarketChannel],
+        sink: UnboundedSender<MarketEventEnvelopeRef>,
+        options: WsReconnectOptions,
+        new_hub: F,
+    ) -> Result<Addr<ClientSupervisor<A>>>
+    where
+        A: Actor,
+        F: Fn(Box<dyn HubClientHandler + Send>, HashSet<Pair>) -> Fut + 'static,
+        Fut: Future<Output = Result<Addr<A>>> + 'static,
+    {
         // Live order book pairs
         let order_book_pairs: HashSet<Pair> = channels
             .iter()
@@ -49,42 +98,17 @@ impl BittrexStreamingApi {
             .filter(|c| c.r#type == MarketChannelType::Trades)
             .map(|c| c.symbol.value.clone())
             .collect();
-
-        let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
-
-        let api: Box<dyn HubClientHandler + Send> = Box::new(BittrexStreamingApi {
-            sink: tx.clone(),
-            books: Arc::new(RwLock::new(HashMap::new())),
-            order_book_pairs: order_book_pairs.clone(),
-            trade_pairs,
-        });
-        // SignalR Client
-        let client = HubClient::new(
-            BITTREX_HUB,
-            "https://socket.bittrex.com/signalr/",
-            20,
-            RestartPolicy::Always,
-            api,
-        )
-        .await;
-        let stream = UnboundedReceiverStream::new(rx);
-        match client {
-            Ok(addr) => {
-                if !order_book_pairs.is_empty() {
-                    for pair in order_book_pairs {
-                        let currency = super::utils::get_pair_string(&pair).unwrap();
-                        addr.do_send(HubQuery::new(
-                            BITTREX_HUB.to_string(),
-                            "QueryExchangeState".to_string(),
-                            vec![currency.to_string()],
-                            "QE2".to_string(),
-                        ));
-                    }
-                }
-                Ok(BotWrapper::new(addr, stream))
-            }
-            Err(e) => Err(Error::ExchangeError(format!("{}", e))),
-        }
+        ClientSupervisor::start("bittrex", options, move |activity| {
+            let api: Box<dyn HubClientHandler + Send> = Box::new(BittrexStreamingApi {
+                sink: sink.clone(),
+                books: Arc::new(RwLock::new(HashMap::new())),
+                order_book_pairs: order_book_pairs.clone(),
+                trade_pairs: trade_pairs.clone(),
+                activity,
+            });
+            new_hub(api, order_book_pairs.clone())
+        })
+        .await
     }
 
     fn deflate<T>(binary: &str) -> Result<T>
@@ -126,6 +150,7 @@ impl BittrexStreamingApi {
 
 impl HubClientHandler for BittrexStreamingApi {
     fn on_connect(&self) -> Vec<Box<dyn PendingQuery>> {
+        self.activity.touch();
         {
             let mut books = self.books.write().unwrap();
             for pair in self.order_book_pairs.iter() {
@@ -156,6 +181,11 @@ impl HubClientHandler for BittrexStreamingApi {
 
     #[allow(clippy::cast_possible_wrap)]
     fn handle(&mut self, method: &str, message: &Value) {
+        self.activity.touch();
+        // Hubs replaced by the supervisor may still deliver messages
+        if !self.activity.is_active() {
+            return;
+        }
         let live_events = match method {
             "uE" => {
                 let delta = Self::deflate_array::<MarketDelta>(message).unwrap();
@@ -185,6 +215,7 @@ impl HubClientHandler for BittrexStreamingApi {
                             amount: fill.Quantity,
                             price: fill.Rate,
                             tt: fill.OrderType.into(),
+                            aggressor: None,
                         };
                         events.push(MarketEvent::Trade(lt));
                     }
@@ -232,3 +263,56 @@ impl HubClientHandler for BittrexStreamingApi {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use actix::{Actor, Context};
+    use signalr_rs::hub::client::HubClientHandler;
+
+    use broker_core::exchange::Exchange;
+    use broker_core::streaming_api::WsReconnectOptions;
+    use broker_core::types::{MarketChannel, MarketChannelType, SecurityType, Symbol};
+
+    use super::BittrexStreamingApi;
+
+    /// A hub that never receives a message
+    struct SilentHub {
+        _api: Box<dyn HubClientHandler + Send>,
+    }
+
+    impl Actor for SilentHub {
+        type Context = Context<Self>;
+    }
+
+    #[actix::test]
+    async fn stale_stream_reconnects() {
+        let channels = vec![MarketChannel::builder()
+            .symbol(Symbol::new("BTC_USDT".into(), SecurityType::Crypto, Exchange::Bittrex))
+            .r#type(MarketChannelType::Orderbooks)
+            .build()];
+        let (tx, _rx) = tokio::sync::mpsc::unbounded_channel();
+        let options = WsReconnectOptions::builder()
+            .heartbeat_interval(Duration::from_millis(10))
+            .heartbeat_timeout(Duration::from_millis(30))
+            .build();
+        let hubs = Arc::new(AtomicUsize::new(0));
+        let queried = Arc::new(Mutex::new(vec![]));
+        let (hub_count, queried_pairs) = (hubs.clone(), queried.clone());
+        let _supervisor = BittrexStreamingApi::supervise(&channels, tx, options, move |api, order_book_pairs| {
+            hub_count.fetch_add(1, Ordering::SeqCst);
+            queried_pairs.lock().unwrap().extend(order_book_pairs);
+            async move { Ok(SilentHub { _api: api }.start()) }
+        })
+        .await
+        .unwrap();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        let hubs = hubs.load(Ordering::SeqCst);
+        assert!(hubs >= 2, "the stale hub was not replaced");
+        // Order books are queried again on every new hub
+        assert_eq!(queried.lock().unwrap().len(), hubs);
+    }
+}
@@ -0,0 +1,424 @@
+use chrono::{TimeZone, Utc};
+use stats::kline::{Resolution, TimeUnit};
+
+use broker_core::error::Error;
+use broker_core::pair::{symbol_to_pair, PairConf};
+use broker_core::prelude::*;
+use broker_core::types::*;
+
+use crate::models::*;
+
+/// Orders are placed on linear contracts, settled in USDT or USDC
+pub const CATEGORY: &str = "linear";
+
+/// Bybit order link ids have at most 36 characters among alphanumerics, dashes and underscores
+pub fn client_order_id(order_id: &str) -> String {
+    order_id
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '-' || *c == '_')
+        .take(36)
+        .collect()
+}
+
+pub fn to_bybit_side(side: TradeType) -> BybitSide {
+    match side {
+        TradeType::Buy => BybitSide::Buy,
+        TradeType::Sell => BybitSide::Sell,
+    }
+}
+
+pub fn from_bybit_side(side: BybitSide) -> TradeType {
+    match side {
+        BybitSide::Buy => TradeType::Buy,
+        BybitSide::Sell => TradeType::Sell,
+    }
+}
+
+/// The order type and time in force of a request
+fn to_bybit_order_type(request: &AddOrderRequest) -> Result<(&'static str, &'static str), Error> {
+    match (request.effective_order_type(), request.enforcement) {
+        (OrderType::Market, _) => Ok(("Market", "IOC")),
+        (OrderType::LimitMaker, _) => Ok(("Limit", "PostOnly")),
+        (OrderType::Limit, Some(OrderEnforcement::FOK)) => Ok(("Limit", "FOK")),
+        (OrderType::Limit, Some(OrderEnforcement::IOC)) => Ok(("Limit", "IOC")),
+        (OrderType::Limit, _) => Ok(("Limit", "GTC")),
+        _ => Err(Error::BrokerFeatureNotImplemented),
+    }
+}
+
+pub fn from_bybit_order_type(order_type: &str, time_in_force: &str) -> (OrderType, OrderEnforcement) {
+    match (order_type, time_in_force) {
+        ("Market", _) => (OrderType::Market, OrderEnforcement::GTC),
+        (_, "PostOnly") => (OrderType::LimitMaker, OrderEnforcement::GTC),
+        (_, "FOK") => (OrderType::Limit, OrderEnforcement::FOK),
+        (_, "IOC") => (OrderType::Limit, OrderEnforcement::IOC),
+        _ => (OrderType::Limit, OrderEnforcement::GTC),
+    }
+}
+
+pub fn from_bybit_order_status(status: &str) -> OrderStatus {
+    match status {
+        "PartiallyFilled" => OrderStatus::PartiallyFilled,
+        "Filled" => OrderStatus::Filled,
+        "Cancelled" | "PartiallyFilledCanceled" => OrderStatus::Canceled,
+        "Rejected" => OrderStatus::Rejected,
+        "Deactivated" => OrderStatus::Expired,
+        _ => OrderStatus::New,
+    }
+}
+
+fn is_working(status: &str) -> bool { matches!(status, "New" | "PartiallyFilled" | "Untriggered") }
+
+/// Linear contract orders are sized in the base coin, quote quantities are not supported
+pub fn to_bybit_order_request(request: &AddOrderRequest, pair_conf: &PairConf) -> Result<BybitOrderRequest, Error> {
+    if !matches!(
+        request.asset_type,
+        None | Some(AssetType::PerpetualSwap | AssetType::PerpetualContract)
+    ) {
+        return Err(Error::BrokerFeatureNotImplemented);
+    }
+    let (order_type, time_in_force) = to_bybit_order_type(request)?;
+    let Some(qty) = request.quantity else {
+        return Err(Error::InvalidQty);
+    };
+    Ok(BybitOrderRequest {
+        category: CATEGORY,
+        symbol: pair_conf.symbol.to_string(),
+        side: to_bybit_side(request.side),
+        order_type: order_type.to_string(),
+        qty: qty.to_string(),
+        price: request.price.filter(|_| order_type != "Market").map(|p| p.to_string()),
+        time_in_force: time_in_force.to_string(),
+        order_link_id: client_order_id(&request.order_id),
+    })
+}
+
+pub fn to_bybit_amend_request(request: &AmendOrderRequest, pair_conf: &PairConf) -> BybitAmendRequest {
+    BybitAmendRequest {
+        category: CATEGORY,
+        symbol: pair_conf.symbol.to_string(),
+        order_link_id: client_order_id(&request.order_id),
+        qty: request.quantity.map(|q| q.to_string()),
+        price: request.price.map(|p| p.to_string()),
+    }
+}
+
+/// Bybit only acknowledges the order, the submission is new until the order is queried
+pub fn from_bybit_order_ack(ack: BybitOrderAck, request: &AddOrderRequest) -> OrderSubmission {
+    OrderSubmission {
+        timestamp: get_unix_timestamp_ms(),
+        id: ack.order_id,
+        pair: request.pair.clone(),
+        client_id: request.order_id.clone(),
+        price: request.price.unwrap_or(0.0),
+        qty: request.quantity.unwrap_or(0.0),
+        status: OrderStatus::New,
+        enforcement: request.enforcement.unwrap_or(OrderEnforcement::GTC),
+        order_type: request.order_type,
+        side: request.side,
+        asset_type: request.asset_type.unwrap_or(AssetType::PerpetualSwap),
+        ..OrderSubmission::default()
+    }
+}
+
+#[allow(clippy::cast_sign_loss)]
+pub fn from_bybit_order(o: BybitOrder, asset_type: AssetType) -> Order {
+    let symbol = o.symbol.into();
+    let (order_type, enforcement) = from_bybit_order_type(&o.order_type, &o.time_in_force);
+    Order {
+        xch: Exchange::Bybit,
+        symbol: symbol_to_pair(&Exchange::Bybit, &symbol).unwrap_or(symbol),
+        order_id: o.order_id,
+        orig_order_id: o.order_link_id,
+        price: o.price,
+        orig_qty: o.qty,
+        executed_qty: o.cum_exec_qty,
+        cumulative_quote_qty: o.cum_exec_value,
+        status: from_bybit_order_status(&o.order_status),
+        enforcement,
+        order_type,
+        side: from_bybit_side(o.side),
+        stop_price: 0.0,
+        iceberg_qty: 0.0,
+        orig_time: o.created_time as u64,
+        last_event_time: o.updated_time as u64,
+        is_in_transaction: is_working(&o.order_status),
+        orig_quote_order_qty: 0.0,
+        asset_type,
+    }
+}
+
+/// Linear contracts pay fees in the settlement coin, negative fees are maker rebates
+pub fn from_bybit_execution(e: BybitExecution, pair_conf: &PairConf) -> TradeFill {
+    TradeFill {
+        id: e.exec_id,
+        order_id: e.order_id,
+        pair: pair_conf.pair.clone(),
+        price: e.exec_price,
+        qty: e.exec_qty,
+        fee: e.exec_fee,
+        fee_asset: pair_conf.quote.clone().into(),
+        side: from_bybit_side(e.side),
+        is_maker: e.is_maker,
+        time: e.exec_time,
+    }
+}
+
+pub fn from_bybit_balances(wallets: Vec<BybitWalletBalance>) -> AccountPosition {
+    let mut balances = AccountPosition::new();
+    for coin in wallets.into_iter().flat_map(|w| w.coin) {
+        balances.insert(coin.coin.into(), Balance {
+            free: coin.wallet_balance - coin.locked,
+            locked: coin.locked,
+        });
+    }
+    balances
+}
+
+/// Order book levels as offers, malformed levels are skipped
+pub fn from_bybit_levels(levels: &[Vec<String>]) -> Vec<Offer> {
+    levels
+        .iter()
+        .filter_map(|level| match (level.first(), level.get(1)) {
+            (Some(px), Some(sz)) => Some((px.parse().ok()?, sz.parse().ok()?)),
+            _ => None,
+        })
+        .collect()
+}
+
+pub fn from_bybit_trade(t: &BybitTrade, pair: Pair) -> Trade {
+    let side = from_bybit_side(t.side);
+    Trade {
+        event_ms: t.time,
+        pair,
+        amount: t.size,
+        price: t.price,
+        tt: side,
+        aggressor: Some(side),
+    }
+}
+
+pub fn from_bybit_public_trade(t: &BybitPublicTrade, pair: Pair) -> Trade {
+    let side = from_bybit_side(t.side);
+    Trade {
+        event_ms: t.time,
+        pair,
+        amount: t.size,
+        price: t.price,
+        tt: side,
+        aggressor: Some(side),
+    }
+}
+
+pub fn from_bybit_kline(k: &BybitKline, pair: Pair) -> Candle {
+    Candle {
+        event_time: Utc.timestamp_millis_opt(k.timestamp).unwrap(),
+        pair,
+        start_time: Utc.timestamp_millis_opt(k.start).unwrap(),
+        end_time: Utc.timestamp_millis_opt(k.end).unwrap(),
+        open: k.open,
+        high: k.high,
+        low: k.low,
+        close: k.close,
+        volume: k.volume,
+        quote_volume: k.turnover,
+        trade_count: 0,
+        is_final: k.confirm,
+    }
+}
+
+/// Merge a ticker update into the last known funding rate of the contract, since deltas only carry the fields
+/// that changed. `None` while the rate of the contract is unknown.
+pub fn merge_funding_rate(
+    last: Option<&FundingRate>,
+    update: &BybitTickerUpdate,
+    pair: Pair,
+    ts: i64,
+) -> Option<FundingRate> {
+    Some(FundingRate {
+        event_ms: ts,
+        pair,
+        rate: update.funding_rate.or_else(|| last.map(|f| f.rate))?,
+        next_funding_ms: update
+            .next_funding_time
+            .or_else(|| last.map(|f| f.next_funding_ms))
+            .unwrap_or_default(),
+        mark_price: update.mark_price.or_else(|| last.and_then(|f| f.mark_price)),
+    })
+}
+
+/// The kline interval for a resolution, one minute by default
+pub fn kline_interval(resolution: Option<Resolution>) -> Option<String> {
+    let Some(resolution) = resolution else {
+        return Some("1".to_string());
+    };
+    let interval = match (resolution.time_unit, resolution.units) {
+        (TimeUnit::Minute, units @ (1 | 3 | 5 | 15 | 30)) => units.to_string(),
+        (TimeUnit::Hour, units @ (1 | 2 | 4 | 6 | 12)) => (units * 60).to_string(),
+        (TimeUnit::Day, 1) => "D".to_string(),
+        (TimeUnit::Week, 1) => "W".to_string(),
+        (TimeUnit::Month, 1) => "M".to_string(),
+        _ => return None,
+    };
+    Some(interval)
+}
+
+/// Order book depths streamed for linear contracts
+fn orderbook_depth(conf: OrderbookConf) -> u16 {
+    match conf {
+        OrderbookConf {
+            level: OrderbookLevel::Level1,
+            ..
+        } => 1,
+        OrderbookConf { depth: Some(depth), .. } if depth <= 50 => 50,
+        OrderbookConf { depth: Some(depth), .. } if depth <= 200 => 200,
+        _ => 500,
+    }
+}
+
+/// The topic of a market channel, `None` if Bybit does not stream it
+pub fn topic(c: &MarketChannel, symbol: &str) -> Option<String> {
+    let topic = match c.r#type {
+        MarketChannelType::Trades => "publicTrade".to_string(),
+        MarketChannelType::Orderbooks | MarketChannelType::Quotes | MarketChannelType::QuotesCandles => {
+            format!("orderbook.{}", orderbook_depth(c.orderbook.unwrap_or_default()))
+        }
+        MarketChannelType::Candles => format!("kline.{}", kline_interval(c.resolution)?),
+        MarketChannelType::FundingRate => "tickers".to_string(),
+        MarketChannelType::OpenInterest | MarketChannelType::OrderbookL3 => return None,
+    };
+    Some(format!("{}.{}", topic, symbol))
+}
+
+pub fn from_bybit_error(code: i64, msg: &str) -> Error {
+    match code {
+        10003 | 10004 | 10005 | 33004 => Error::BadCredentials,
+        10006 | 10018 => Error::RateLimitExceeded,
+        10016 => Error::ServiceUnavailable(msg.to_string()),
+        110001 => Error::NotFound,
+        110004 | 110007 => Error::InsufficientFunds,
+        110094 => Error::InsufficientOrderSize,
+        _ => Error::ExchangeSpecificError(format!("{} : {}", code, msg)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use stats::kline::{Resolution, TimeUnit};
+
+    use broker_core::pair::PairConf;
+    use broker_core::types::*;
+
+    use crate::adapters::{kline_interval, merge_funding_rate, to_bybit_order_request, topic};
+    use crate::models::{BybitTickerUpdate, WsMessage};
+
+    fn pair_conf() -> PairConf {
+        PairConf {
+            base: "BTC".to_string(),
+            quote: "USDT".to_string(),
+            symbol: "BTCUSDT".into(),
+            pair: "BTC_USDT".into(),
+            ..PairConf::default()
+        }
+    }
+
+    fn channel(r#type: MarketChannelType) -> MarketChannel {
+        MarketChannel::builder()
+            .symbol(Symbol::new("BTC_USDT".into(), SecurityType::Crypto, Exchange::Bybit))
+            .r#type(r#type)
+            .build()
+    }
+
+    #[test]
+    fn order_requests() {
+        let market = AddOrderRequest {
+            pair: "BTC_USDT".into(),
+            order_type: OrderType::Market,
+            side: TradeType::Sell,
+            quantity: Some(0.5),
+            price: Some(100.0),
+            ..AddOrderRequest::default()
+        };
+        let request = to_bybit_order_request(&market, &pair_conf()).unwrap();
+        assert_eq!(request.symbol, "BTCUSDT");
+        assert_eq!(request.order_type, "Market");
+        assert_eq!(request.qty, "0.5");
+        assert_eq!(request.price, None);
+
+        let post_only = AddOrderRequest {
+            order_type: OrderType::Limit,
+            post_only: true,
+            asset_type: Some(AssetType::PerpetualSwap),
+            ..market.clone()
+        };
+        let request = to_bybit_order_request(&post_only, &pair_conf()).unwrap();
+        assert_eq!(request.order_type, "Limit");
+        assert_eq!(request.time_in_force, "PostOnly");
+        assert_eq!(request.price.as_deref(), Some("100"));
+
+        let spot = AddOrderRequest {
+            asset_type: Some(AssetType::Spot),
+            ..market
+        };
+        assert!(to_bybit_order_request(&spot, &pair_conf()).is_err());
+    }
+
+    #[test]
+    fn topics() {
+        assert_eq!(
+            topic(&channel(MarketChannelType::FundingRate), "BTCUSDT").as_deref(),
+            Some("tickers.BTCUSDT")
+        );
+        assert_eq!(
+            topic(&channel(MarketChannelType::Trades), "BTCUSDT").as_deref(),
+            Some("publicTrade.BTCUSDT")
+        );
+        let book = MarketChannel {
+            orderbook: Some(OrderbookConf {
+                depth: Some(20),
+                level: OrderbookLevel::Level2,
+            }),
+            ..channel(MarketChannelType::Orderbooks)
+        };
+        assert_eq!(topic(&book, "BTCUSDT").as_deref(), Some("orderbook.50.BTCUSDT"));
+        assert_eq!(topic(&channel(MarketChannelType::OpenInterest), "BTCUSDT"), None);
+        assert_eq!(
+            kline_interval(Some(Resolution::new(TimeUnit::Hour, 4))).as_deref(),
+            Some("240")
+        );
+        assert_eq!(kline_interval(Some(Resolution::new(TimeUnit::Minute, 7))), None);
+    }
+
+    #[test]
+    fn funding_rates_merge_ticker_deltas() {
+        let snapshot = r#"{"topic":"tickers.BTCUSDT","type":"snapshot","data":{"symbol":"BTCUSDT","markPrice":"17216.40","fundingRate":"-0.000212","nextFundingTime":"1673280000000","openInterest":"68.1"},"cs":24987956059,"ts":1673272861686}"#;
+        let WsMessage::Push { topic, data, ts, .. } = serde_json::from_str(snapshot).unwrap() else {
+            panic!("expected a push");
+        };
+        assert_eq!(topic, "tickers.BTCUSDT");
+        let update: BybitTickerUpdate = serde_json::from_value(data).unwrap();
+        let funding = merge_funding_rate(None, &update, "BTC_USDT".into(), ts).unwrap();
+        assert!((funding.rate + 0.000_212).abs() < f64::EPSILON);
+        assert_eq!(funding.next_funding_ms, 1_673_280_000_000);
+        assert_eq!(funding.mark_price, Some(17216.4));
+
+        // Deltas without a rate keep the last known one
+        let delta: BybitTickerUpdate = serde_json::from_str(r#"{"symbol":"BTCUSDT","markPrice":"17217.00"}"#).unwrap();
+        let merged = merge_funding_rate(Some(&funding), &delta, "BTC_USDT".into(), ts + 100).unwrap();
+        assert!((merged.rate - funding.rate).abs() < f64::EPSILON);
+        assert_eq!(merged.next_funding_ms, funding.next_funding_ms);
+        assert_eq!(merged.mark_price, Some(17217.0));
+        assert_eq!(merged.event_ms, ts + 100);
+
+        assert!(merge_funding_rate(None, &delta, "BTC_USDT".into(), ts).is_none());
+    }
+
+    #[test]
+    fn parse_subscription_response() {
+        let msg = r#"{"success":true,"ret_msg":"","conn_id":"2324d924-aa4d-45b0-a858-7b8be29ab52b","req_id":"","op":"subscribe"}"#;
+        assert!(matches!(serde_json::from_str(msg).unwrap(), WsMessage::Op {
+            success: Some(true),
+            ..
+        }));
+    }
+}
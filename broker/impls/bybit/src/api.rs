@@ -0,0 +1,249 @@
+//! Use this module to interact with Bybit exchange.
+//! Please see examples for more informations.
+
+use data_encoding::HEXLOWER;
+use hmac::{Hmac, Mac};
+use reqwest::{Client, Method};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use sha2::Sha256;
+use url::Url;
+
+use broker_core::error::*;
+use broker_core::prelude::*;
+
+use super::adapters::{from_bybit_error, CATEGORY};
+use super::models::*;
+
+static REST_ENDPOINT: &str = "https://api.bybit.com";
+static TEST_REST_ENDPOINT: &str = "https://api-testnet.bybit.com";
+
+/// Requests older than this many ms when received are rejected by Bybit
+const RECV_WINDOW: &str = "5000";
+
+#[derive(Debug, Clone)]
+pub struct BybitApi {
+    pub(super) api_key: Option<String>,
+    pub(super) api_secret: Option<String>,
+    endpoint: &'static str,
+    client: Client,
+}
+
+/// Sign a request, see https://bybit-exchange.github.io/docs/v5/guide#create-a-request
+pub(crate) fn sign(api_secret: &str, prehash: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(api_secret.as_bytes()).expect("HMAC can take key of any size");
+    mac.update(prehash.as_bytes());
+    HEXLOWER.encode(&mac.finalize().into_bytes())
+}
+
+impl<T> BybitResponse<T> {
+    fn into_result(self) -> Result<T> {
+        match self.result {
+            Some(result) if self.ret_code == 0 => Ok(result),
+            Some(_) => Err(from_bybit_error(self.ret_code, &self.ret_msg)),
+            None if self.ret_code == 0 => Err(Error::NotFound),
+            None => Err(from_bybit_error(self.ret_code, &self.ret_msg)),
+        }
+    }
+}
+
+impl BybitApi {
+    /// Create a new BybitApi by providing an API key and API secret
+    pub fn new(creds: &dyn Credentials) -> Result<BybitApi> { Self::new_with_endpoint(creds, REST_ENDPOINT) }
+
+    /// Create a new BybitApi pointing to the testnet
+    pub fn new_test(creds: &dyn Credentials) -> Result<BybitApi> { Self::new_with_endpoint(creds, TEST_REST_ENDPOINT) }
+
+    fn new_with_endpoint(creds: &dyn Credentials, endpoint: &'static str) -> Result<BybitApi> {
+        if creds.exchange() != Exchange::Bybit {
+            return Err(Error::InvalidConfigType {
+                expected: Exchange::Bybit,
+                find: creds.exchange(),
+            });
+        }
+
+        Ok(BybitApi {
+            api_key: creds.get("api_key").filter(|s| !s.is_empty()),
+            api_secret: creds.get("api_secret").filter(|s| !s.is_empty()),
+            endpoint,
+            client: Client::new(),
+        })
+    }
+
+    async fn public_query<T: DeserializeOwned>(&self, path: &str, query: &[(&str, String)]) -> Result<T> {
+        let response: BybitResponse<T> = self
+            .client
+            .get(format!("{}{}", self.endpoint, path))
+            .query(query)
+            .send()
+            .await?
+            .json()
+            .await?;
+        response.into_result()
+    }
+
+    async fn private_query<T: DeserializeOwned>(
+        &self,
+        method: Method,
+        path: &str,
+        query: &[(&str, String)],
+        body: Option<String>,
+    ) -> Result<T> {
+        let (Some(api_key), Some(api_secret)) = (&self.api_key, &self.api_secret) else {
+            return Err(Error::MissingCredentials("bybit api_key or api_secret".to_string()));
+        };
+        let mut url = Url::parse(&format!("{}{}", self.endpoint, path))?;
+        if !query.is_empty() {
+            url.query_pairs_mut().extend_pairs(query);
+        }
+        // GET requests sign the query string, POST requests sign the json body
+        let payload = body.unwrap_or_default();
+        let timestamp = get_unix_timestamp_ms().to_string();
+        let signature = sign(
+            api_secret,
+            &format!(
+                "{}{}{}{}{}",
+                timestamp,
+                api_key,
+                RECV_WINDOW,
+                url.query().unwrap_or_default(),
+                payload
+            ),
+        );
+        let response: BybitResponse<T> = self
+            .client
+            .request(method, url)
+            .header("X-BAPI-API-KEY", api_key)
+            .header("X-BAPI-SIGN", signature)
+            .header("X-BAPI-TIMESTAMP", timestamp)
+            .header("X-BAPI-RECV-WINDOW", RECV_WINDOW)
+            .header("Content-Type", "application/json")
+            .body(payload)
+            .send()
+            .await?
+            .json()
+            .await?;
+        response.into_result()
+    }
+
+    pub async fn market_ticker(&self, symbol: &str) -> Result<BybitTicker> {
+        let tickers: BybitList<BybitTicker> = self
+            .public_query("/v5/market/tickers", &[
+                ("category", CATEGORY.to_string()),
+                ("symbol", symbol.to_string()),
+            ])
+            .await?;
+        tickers.list.into_iter().next().ok_or(Error::NotFound)
+    }
+
+    pub async fn market_tickers(&self) -> Result<Vec<BybitTicker>> {
+        let tickers: BybitList<BybitTicker> = self
+            .public_query("/v5/market/tickers", &[("category", CATEGORY.to_string())])
+            .await?;
+        Ok(tickers.list)
+    }
+
+    pub async fn order_book(&self, symbol: &str, depth: u16) -> Result<BybitOrderBook> {
+        self.public_query("/v5/market/orderbook", &[
+            ("category", CATEGORY.to_string()),
+            ("symbol", symbol.to_string()),
+            ("limit", depth.to_string()),
+        ])
+        .await
+    }
+
+    pub async fn market_trades(&self, symbol: &str) -> Result<Vec<BybitTrade>> {
+        let trades: BybitList<BybitTrade> = self
+            .public_query("/v5/market/recent-trade", &[
+                ("category", CATEGORY.to_string()),
+                ("symbol", symbol.to_string()),
+            ])
+            .await?;
+        Ok(trades.list)
+    }
+
+    pub async fn instruments(&self) -> Result<Vec<BybitInstrument>> {
+        let instruments: BybitList<BybitInstrument> = self
+            .public_query("/v5/market/instruments-info", &[
+                ("category", CATEGORY.to_string()),
+                ("limit", "1000".to_string()),
+            ])
+            .await?;
+        Ok(instruments.list)
+    }
+
+    /// Server time in seconds, used to check that the exchange is up
+    pub async fn server_time(&self) -> Result<Value> { self.public_query("/v5/market/time", &[]).await }
+
+    /// Balances of the unified trading account
+    pub async fn balances(&self) -> Result<Vec<BybitWalletBalance>> {
+        let wallets: BybitList<BybitWalletBalance> = self
+            .private_query(
+                Method::GET,
+                "/v5/account/wallet-balance",
+                &[("accountType", "UNIFIED".to_string())],
+                None,
+            )
+            .await?;
+        Ok(wallets.list)
+    }
+
+    pub async fn place_order(&self, request: &BybitOrderRequest) -> Result<BybitOrderAck> {
+        let body = serde_json::to_string(request)?;
+        self.private_query(Method::POST, "/v5/order/create", &[], Some(body))
+            .await
+    }
+
+    pub async fn amend_resting_order(&self, request: &BybitAmendRequest) -> Result<BybitOrderAck> {
+        let body = serde_json::to_string(request)?;
+        self.private_query(Method::POST, "/v5/order/amend", &[], Some(body))
+            .await
+    }
+
+    pub async fn cancel_resting_order(&self, request: &BybitCancelRequest) -> Result<BybitOrderAck> {
+        let body = serde_json::to_string(request)?;
+        self.private_query(Method::POST, "/v5/order/cancel", &[], Some(body))
+            .await
+    }
+
+    /// Open and recently closed orders
+    pub async fn order_details(&self, symbol: &str, order_link_id: &str) -> Result<BybitOrder> {
+        let orders: BybitList<BybitOrder> = self
+            .private_query(
+                Method::GET,
+                "/v5/order/realtime",
+                &[
+                    ("category", CATEGORY.to_string()),
+                    ("symbol", symbol.to_string()),
+                    ("orderLinkId", order_link_id.to_string()),
+                ],
+                None,
+            )
+            .await?;
+        orders.list.into_iter().next().ok_or(Error::NotFound)
+    }
+
+    /// Executions of the last seven days, since `start_time` in ms if set
+    pub async fn executions(&self, symbol: &str, start_time: Option<i64>) -> Result<Vec<BybitExecution>> {
+        let mut query = vec![("category", CATEGORY.to_string()), ("symbol", symbol.to_string())];
+        if let Some(start_time) = start_time {
+            query.push(("startTime", start_time.to_string()));
+        }
+        let executions: BybitList<BybitExecution> = self
+            .private_query(Method::GET, "/v5/execution/list", &query, None)
+            .await?;
+        Ok(executions.list)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn should_create_a_correct_signature() {
+        let prehash = "1658384314791XXXXXXXXXX5000category=linear&symbol=BTCUSDT";
+        assert_eq!(
+            super::sign("XXXXXXXXXX", prehash),
+            "980d7b8bf7a3aa022f5b85dff69011913510d2e54e4ca15f8dac9dc6585574ef"
+        );
+    }
+}
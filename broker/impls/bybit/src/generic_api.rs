@@ -0,0 +1,181 @@
+//! Use this module to interact with Bybit through a Generic API.
+//! This a more convenient and safe way to deal with the exchange since methods return a Result<>
+//! but this generic API does not provide all the functionnality that Bybit offers.
+
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use stats::kline::{Resolution, TimeUnit};
+
+use broker_core::error::*;
+use broker_core::pair::{pair_conf, pair_string, PairConf};
+use broker_core::prelude::*;
+use broker_core::types::*;
+
+use super::adapters::*;
+use super::api::BybitApi;
+use super::models::BybitCancelRequest;
+
+/// Depth of order books fetched with the REST API
+const ORDERBOOK_DEPTH: u16 = 50;
+
+#[async_trait]
+impl Brokerage for BybitApi {
+    async fn ticker(&self, pair: Pair) -> Result<Ticker> {
+        let symbol = pair_string(Exchange::Bybit, &pair)?;
+        let ticker = self.market_ticker(&symbol).await?;
+        Ok(Ticker {
+            timestamp: get_unix_timestamp_ms(),
+            pair,
+            last_trade_price: ticker.last_price,
+            lowest_ask: ticker.ask_price,
+            highest_bid: ticker.bid_price,
+            volume: Some(ticker.volume_24h),
+        })
+    }
+
+    async fn tickers(&self, pairs: Vec<Pair>) -> Result<HashMap<Pair, f64>> {
+        let mut symbols: HashMap<String, Pair> = pairs
+            .into_iter()
+            .filter_map(|pair| pair_string(Exchange::Bybit, &pair).ok().map(|symbol| (symbol, pair)))
+            .collect();
+        if symbols.is_empty() {
+            return Ok(HashMap::new());
+        }
+        let tickers = self.market_tickers().await?;
+        Ok(tickers
+            .into_iter()
+            .filter_map(|ticker| symbols.remove(&ticker.symbol).map(|pair| (pair, ticker.last_price)))
+            .collect())
+    }
+
+    async fn orderbook(&self, pair: Pair) -> Result<Orderbook> {
+        let symbol = pair_string(Exchange::Bybit, &pair)?;
+        let book = self.order_book(&symbol, ORDERBOOK_DEPTH).await?;
+        Ok(Orderbook {
+            timestamp: book.ts,
+            pair,
+            asks: from_bybit_levels(&book.asks),
+            bids: from_bybit_levels(&book.bids),
+            last_order_id: None,
+        })
+    }
+
+    async fn add_order(&self, order: AddOrderRequest) -> Result<OrderSubmission> {
+        let pair_conf = pair_conf(&Exchange::Bybit, &order.pair)?;
+        if order.order_type == OrderType::Limit && order.price.is_none() {
+            return Err(Error::MissingPrice);
+        }
+        let request = to_bybit_order_request(&order, &pair_conf)?;
+        // Bybit has no order test endpoint, use the testnet to test orders against the exchange
+        if order.dry_run {
+            return Ok(order.simulate_submission(0.00055));
+        }
+        let ack = self.place_order(&request).await?;
+        Ok(from_bybit_order_ack(ack, &order))
+    }
+
+    async fn amend_order(&self, order: AmendOrderRequest) -> Result<OrderSubmission> {
+        let pair_conf = pair_conf(&Exchange::Bybit, &order.pair)?;
+        let ack = self
+            .amend_resting_order(&to_bybit_amend_request(&order, &pair_conf))
+            .await?;
+        Ok(order.acknowledge(ack.order_id))
+    }
+
+    async fn cancel_order(&self, id: String, pair: Pair, _asset_type: AssetType) -> Result<()> {
+        let request = BybitCancelRequest {
+            category: CATEGORY,
+            symbol: pair_string(Exchange::Bybit, &pair)?,
+            order_link_id: client_order_id(&id),
+        };
+        self.cancel_resting_order(&request).await.map(|_| ())
+    }
+
+    /// Return the balances for each coin of the unified trading account
+    async fn account_balances(&self) -> Result<AccountPosition> { self.balances().await.map(from_bybit_balances) }
+
+    async fn get_order(&self, id: String, pair: Pair, asset_type: AssetType) -> Result<Order> {
+        let symbol = pair_string(Exchange::Bybit, &pair)?;
+        let order = self.order_details(&symbol, &client_order_id(&id)).await?;
+        Ok(from_bybit_order(order, asset_type))
+    }
+
+    /// Linear perpetual contracts, dated futures are left out
+    async fn pairs(&self) -> Result<Vec<PairConf>> {
+        let instruments = self.instruments().await?;
+        Ok(instruments
+            .into_iter()
+            .filter(|i| i.contract_type == "LinearPerpetual")
+            .map(|i| PairConf {
+                pair: format!("{}_{}", i.base_coin, i.quote_coin).into(),
+                symbol: i.symbol.into(),
+                base: i.base_coin,
+                quote: i.quote_coin,
+                step_price: Some(i.price_filter.tick_size),
+                min_qty: Some(i.lot_size_filter.min_order_qty),
+                max_qty: Some(i.lot_size_filter.max_order_qty),
+                step_qty: Some(i.lot_size_filter.qty_step),
+                min_market_qty: Some(i.lot_size_filter.min_order_qty),
+                max_market_qty: i.lot_size_filter.max_mkt_order_qty,
+                step_market_qty: Some(i.lot_size_filter.qty_step),
+                cross_margin_allowed: i.status == "Trading",
+                ..PairConf::default()
+            })
+            .collect())
+    }
+
+    fn exchange(&self) -> Exchange { Exchange::Bybit }
+
+    fn uses_account(&self) -> bool { self.api_key.is_some() && self.api_secret.is_some() }
+
+    fn capabilities(&self) -> ExchangeCapabilities {
+        ExchangeCapabilities {
+            order_types: vec![OrderType::Limit, OrderType::Market, OrderType::LimitMaker],
+            post_only: true,
+            amend: true,
+            max_orderbook_depth: Some(500),
+            rate_limits: vec![RateLimit::new(600, 5000)],
+            resolutions: vec![
+                Resolution::new(TimeUnit::Minute, 1),
+                Resolution::new(TimeUnit::Minute, 3),
+                Resolution::new(TimeUnit::Minute, 5),
+                Resolution::new(TimeUnit::Minute, 15),
+                Resolution::new(TimeUnit::Minute, 30),
+                Resolution::new(TimeUnit::Hour, 1),
+                Resolution::new(TimeUnit::Hour, 2),
+                Resolution::new(TimeUnit::Hour, 4),
+                Resolution::new(TimeUnit::Hour, 6),
+                Resolution::new(TimeUnit::Hour, 12),
+                Resolution::new(TimeUnit::Day, 1),
+                Resolution::new(TimeUnit::Week, 1),
+                Resolution::new(TimeUnit::Month, 1),
+            ],
+            ..ExchangeCapabilities::default()
+        }
+    }
+
+    async fn trade_history(&self, pair: Pair) -> Result<Vec<Trade>> {
+        let symbol = pair_string(Exchange::Bybit, &pair)?;
+        let trades = self.market_trades(&symbol).await?;
+        Ok(trades.iter().map(|t| from_bybit_trade(t, pair.clone())).collect())
+    }
+
+    /// Bybit does not publish maintenances, the exchange is considered up if it answers
+    async fn system_status(&self) -> Result<SystemStatus> {
+        self.server_time().await?;
+        Ok(SystemStatus::Normal)
+    }
+
+    async fn my_trades(&self, pair: Pair, since: Option<DateTime<Utc>>) -> Result<Vec<TradeFill>> {
+        let pair_conf = pair_conf(&Exchange::Bybit, &pair)?;
+        let executions = self
+            .executions(&pair_conf.symbol, since.map(|t| t.timestamp_millis()))
+            .await?;
+        Ok(executions
+            .into_iter()
+            .map(|e| from_bybit_execution(e, &pair_conf))
+            .collect())
+    }
+}
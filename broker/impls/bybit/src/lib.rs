@@ -0,0 +1,66 @@
+//! Use this module to interact with Bybit linear perpetual contracts.
+
+#![feature(used_with_arg)]
+
+#[macro_use]
+extern crate broker_core;
+#[macro_use]
+extern crate tracing;
+#[macro_use]
+extern crate async_trait;
+#[macro_use]
+extern crate serde;
+
+use broker_core::bot::DataStreamer;
+use broker_core::broker::MarketEventEnvelopeRef;
+use broker_core::error::Error;
+use broker_core::fees::{FeeProvider, FlatFeeProvider};
+use broker_core::prelude::*;
+use serde_json::Value;
+use std::sync::Arc;
+
+mod adapters;
+mod api;
+mod generic_api;
+mod models;
+mod streaming_api;
+
+pub use self::api::BybitApi;
+pub use self::streaming_api::BybitStreamingApi;
+
+#[async_trait(? Send)]
+impl BrokerConnector for BybitExchangeConnector {
+    async fn new_api(&self, ctx: BrokerageInitContext) -> broker_core::error::Result<Arc<dyn Brokerage>> {
+        let api: Arc<dyn Brokerage> = Arc::new(if ctx.use_test_servers {
+            BybitApi::new_test(ctx.creds.as_ref())?
+        } else {
+            BybitApi::new(ctx.creds.as_ref())?
+        });
+        Ok(api)
+    }
+
+    async fn new_public_stream(
+        &self,
+        ctx: BrokerageBotInitContext,
+    ) -> broker_core::error::Result<Box<MarketDataStreamer>> {
+        let b: Box<dyn DataStreamer<MarketEventEnvelopeRef>> =
+            Box::new(BybitStreamingApi::try_new(ctx.creds.as_ref(), ctx.channels, ctx.settings.use_test).await?);
+        Ok(b)
+    }
+
+    /// Account streams are not supported yet
+    async fn new_private_stream(
+        &self,
+        _ctx: PrivateBotInitContext,
+    ) -> broker_core::error::Result<Box<BrokerageAccountDataStreamer>> {
+        Err(Error::BrokerFeatureNotImplemented)
+    }
+
+    /// Flat fees, configured with `flat_fee` and `symbol`
+    fn fees_provider(&self, conf: Value) -> broker_core::error::Result<Arc<dyn FeeProvider>> {
+        let provider: FlatFeeProvider = serde_json::from_value(conf)?;
+        Ok(Arc::new(provider))
+    }
+}
+
+exchange!(Exchange::Bybit, BybitExchangeConnector);
@@ -0,0 +1,315 @@
+//! Payloads of the Bybit v5 REST and websocket APIs for linear contracts, numbers are sent as strings by Bybit
+
+use serde::{Deserialize, Deserializer};
+use serde_json::Value;
+
+/// Deserialize a number sent as a string, empty strings are zero
+fn string_f64<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+    let s = String::deserialize(deserializer)?;
+    if s.is_empty() {
+        return Ok(0.0);
+    }
+    s.parse().map_err(serde::de::Error::custom)
+}
+
+/// Deserialize a timestamp in ms sent as a string, empty strings are zero
+fn string_i64<'de, D: Deserializer<'de>>(deserializer: D) -> Result<i64, D::Error> {
+    let s = String::deserialize(deserializer)?;
+    if s.is_empty() {
+        return Ok(0);
+    }
+    s.parse().map_err(serde::de::Error::custom)
+}
+
+/// Deserialize an optional number sent as a string, absent and empty strings are `None`
+fn string_f64_opt<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<f64>, D::Error> {
+    match Option::<String>::deserialize(deserializer)? {
+        Some(s) if !s.is_empty() => s.parse().map(Some).map_err(serde::de::Error::custom),
+        _ => Ok(None),
+    }
+}
+
+/// Deserialize an optional timestamp in ms sent as a string, absent and empty strings are `None`
+fn string_i64_opt<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<i64>, D::Error> {
+    match Option::<String>::deserialize(deserializer)? {
+        Some(s) if !s.is_empty() => s.parse().map(Some).map_err(serde::de::Error::custom),
+        _ => Ok(None),
+    }
+}
+
+/// Envelope of every REST response, `ret_code` is 0 on success
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BybitResponse<T> {
+    pub ret_code: i64,
+    #[serde(default)]
+    pub ret_msg: String,
+    pub result: Option<T>,
+}
+
+/// Paginated results
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BybitList<T> {
+    #[serde(default = "Vec::new")]
+    pub list: Vec<T>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BybitTicker {
+    pub symbol: String,
+    #[serde(deserialize_with = "string_f64")]
+    pub last_price: f64,
+    #[serde(rename = "ask1Price", deserialize_with = "string_f64")]
+    pub ask_price: f64,
+    #[serde(rename = "bid1Price", deserialize_with = "string_f64")]
+    pub bid_price: f64,
+    #[serde(rename = "volume24h", deserialize_with = "string_f64")]
+    pub volume_24h: f64,
+    #[serde(deserialize_with = "string_f64")]
+    pub funding_rate: f64,
+    #[serde(deserialize_with = "string_i64")]
+    pub next_funding_time: i64,
+    #[serde(deserialize_with = "string_f64")]
+    pub mark_price: f64,
+}
+
+/// Order book levels are `[price, size]`, a zero size deletes the level in deltas
+#[derive(Debug, Deserialize)]
+pub struct BybitOrderBook {
+    #[serde(rename = "s")]
+    pub symbol: String,
+    #[serde(rename = "a")]
+    pub asks: Vec<Vec<String>>,
+    #[serde(rename = "b")]
+    pub bids: Vec<Vec<String>>,
+    /// Only set by the REST API, pushes carry the timestamp in the envelope
+    #[serde(default)]
+    pub ts: i64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BybitSide {
+    Buy,
+    Sell,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BybitTrade {
+    pub exec_id: String,
+    pub symbol: String,
+    #[serde(deserialize_with = "string_f64")]
+    pub price: f64,
+    #[serde(deserialize_with = "string_f64")]
+    pub size: f64,
+    pub side: BybitSide,
+    #[serde(deserialize_with = "string_i64")]
+    pub time: i64,
+}
+
+/// A trade pushed on the `publicTrade` topic
+#[derive(Debug, Deserialize)]
+pub struct BybitPublicTrade {
+    #[serde(rename = "T")]
+    pub time: i64,
+    #[serde(rename = "S")]
+    pub side: BybitSide,
+    #[serde(rename = "v", deserialize_with = "string_f64")]
+    pub size: f64,
+    #[serde(rename = "p", deserialize_with = "string_f64")]
+    pub price: f64,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BybitPriceFilter {
+    #[serde(deserialize_with = "string_f64")]
+    pub tick_size: f64,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BybitLotSizeFilter {
+    #[serde(deserialize_with = "string_f64")]
+    pub max_order_qty: f64,
+    #[serde(deserialize_with = "string_f64")]
+    pub min_order_qty: f64,
+    #[serde(deserialize_with = "string_f64")]
+    pub qty_step: f64,
+    #[serde(default, deserialize_with = "string_f64_opt")]
+    pub max_mkt_order_qty: Option<f64>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BybitInstrument {
+    pub symbol: String,
+    pub contract_type: String,
+    pub status: String,
+    pub base_coin: String,
+    pub quote_coin: String,
+    pub price_filter: BybitPriceFilter,
+    pub lot_size_filter: BybitLotSizeFilter,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BybitWalletBalance {
+    #[serde(default = "Vec::new")]
+    pub coin: Vec<BybitCoinBalance>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BybitCoinBalance {
+    pub coin: String,
+    #[serde(deserialize_with = "string_f64")]
+    pub wallet_balance: f64,
+    #[serde(deserialize_with = "string_f64")]
+    pub locked: f64,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BybitOrderRequest {
+    pub category: &'static str,
+    pub symbol: String,
+    pub side: BybitSide,
+    pub order_type: String,
+    pub qty: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub price: Option<String>,
+    pub time_in_force: String,
+    pub order_link_id: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BybitAmendRequest {
+    pub category: &'static str,
+    pub symbol: String,
+    pub order_link_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub qty: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub price: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BybitCancelRequest {
+    pub category: &'static str,
+    pub symbol: String,
+    pub order_link_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BybitOrderAck {
+    pub order_id: String,
+    pub order_link_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BybitOrder {
+    pub order_id: String,
+    pub order_link_id: String,
+    pub symbol: String,
+    #[serde(deserialize_with = "string_f64")]
+    pub price: f64,
+    #[serde(deserialize_with = "string_f64")]
+    pub qty: f64,
+    pub side: BybitSide,
+    pub order_status: String,
+    pub order_type: String,
+    pub time_in_force: String,
+    #[serde(deserialize_with = "string_f64")]
+    pub cum_exec_qty: f64,
+    #[serde(deserialize_with = "string_f64")]
+    pub cum_exec_value: f64,
+    #[serde(deserialize_with = "string_i64")]
+    pub created_time: i64,
+    #[serde(deserialize_with = "string_i64")]
+    pub updated_time: i64,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BybitExecution {
+    pub exec_id: String,
+    pub order_id: String,
+    #[serde(deserialize_with = "string_f64")]
+    pub exec_price: f64,
+    #[serde(deserialize_with = "string_f64")]
+    pub exec_qty: f64,
+    #[serde(deserialize_with = "string_f64")]
+    pub exec_fee: f64,
+    pub side: BybitSide,
+    pub is_maker: bool,
+    #[serde(deserialize_with = "string_i64")]
+    pub exec_time: i64,
+}
+
+/// A candle pushed on the `kline` topic
+#[derive(Debug, Deserialize)]
+pub struct BybitKline {
+    pub start: i64,
+    pub end: i64,
+    #[serde(deserialize_with = "string_f64")]
+    pub open: f64,
+    #[serde(deserialize_with = "string_f64")]
+    pub close: f64,
+    #[serde(deserialize_with = "string_f64")]
+    pub high: f64,
+    #[serde(deserialize_with = "string_f64")]
+    pub low: f64,
+    #[serde(deserialize_with = "string_f64")]
+    pub volume: f64,
+    #[serde(deserialize_with = "string_f64")]
+    pub turnover: f64,
+    pub confirm: bool,
+    pub timestamp: i64,
+}
+
+/// Ticker pushed on the `tickers` topic, deltas only carry the fields that changed
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BybitTickerUpdate {
+    pub symbol: String,
+    #[serde(default, deserialize_with = "string_f64_opt")]
+    pub funding_rate: Option<f64>,
+    #[serde(default, deserialize_with = "string_i64_opt")]
+    pub next_funding_time: Option<i64>,
+    #[serde(default, deserialize_with = "string_f64_opt")]
+    pub mark_price: Option<f64>,
+}
+
+/// An operation sent over the websocket
+#[derive(Debug, Serialize)]
+pub struct WsRequest {
+    pub op: &'static str,
+    pub args: Vec<String>,
+}
+
+/// Messages received on the websocket, either operation responses or topic pushes
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum WsMessage {
+    Push {
+        topic: String,
+        #[serde(rename = "type", default)]
+        kind: Option<String>,
+        ts: i64,
+        data: Value,
+    },
+    Op {
+        op: String,
+        #[serde(default)]
+        success: Option<bool>,
+        #[serde(default)]
+        ret_msg: Option<String>,
+    },
+}
@@ -0,0 +1,228 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use actix::io::SinkWrite;
+use async_trait::async_trait;
+use awc::ws::Message;
+use broker_core::bot::{BotWrapper, DefaultWsActor, WsFramedSink, WsHandler};
+use broker_core::broker::MarketEventEnvelopeRef;
+use broker_core::metrics::ExchangeMetrics;
+use bytes::Bytes;
+use dashmap::DashMap;
+use serde_json::Value;
+use tokio::sync::mpsc::{self, UnboundedSender};
+use tokio_stream::wrappers::UnboundedReceiverStream;
+use url::Url;
+
+use broker_core::error::*;
+use broker_core::json_util::deserialize_json_s;
+use broker_core::pair::{pair_to_symbol, symbol_to_pair};
+use broker_core::prelude::*;
+use broker_core::streaming_api::{LiveBookStream, MarketEventStream, StreamingApi, WsReconnectOptions};
+use broker_core::types::*;
+
+use super::adapters::*;
+use super::models::*;
+
+static PUBLIC_WS_ENDPOINT: &str = "wss://stream.bybit.com/v5/public/linear";
+static TEST_PUBLIC_WS_ENDPOINT: &str = "wss://stream-testnet.bybit.com/v5/public/linear";
+
+/// Bybit rejects subscriptions of more than 10 topics at once
+const MAX_TOPICS_PER_REQUEST: usize = 10;
+
+#[derive(Clone)]
+pub struct BybitStreamingApi {
+    books: Arc<DashMap<Pair, LiveAggregatedOrderBook>>,
+    /// Last known funding rate of each contract, ticker deltas only carry the fields that changed
+    funding_rates: Arc<DashMap<Pair, FundingRate>>,
+    topics: Vec<String>,
+    /// Kline topics that only send final candles
+    final_candles: HashMap<String, bool>,
+    sink: UnboundedSender<MarketEventEnvelopeRef>,
+    metrics: Arc<ExchangeMetrics>,
+    orderbook_depths: HashMap<Pair, u16>,
+}
+
+impl BybitStreamingApi {
+    /// Create a new bybit exchange bot, unavailable channels and currencies are ignored
+    pub async fn try_new(
+        _creds: &dyn Credentials,
+        channels: Vec<MarketChannel>,
+        use_test: bool,
+    ) -> Result<BotWrapper<DefaultWsActor, UnboundedReceiverStream<MarketEventEnvelopeRef>>> {
+        let metrics = Arc::new(ExchangeMetrics::for_exchange(Exchange::Bybit));
+        let (tx, rx) = mpsc::unbounded_channel();
+        let mut api = Self {
+            books: Arc::new(DashMap::new()),
+            funding_rates: Arc::new(DashMap::new()),
+            topics: vec![],
+            final_candles: HashMap::new(),
+            sink: tx,
+            metrics: metrics.clone(),
+            orderbook_depths: HashMap::new(),
+        };
+        for channel in &channels {
+            let pair = channel.pair();
+            let Some(topic) = pair_to_symbol(&Exchange::Bybit, pair)
+                .ok()
+                .and_then(|symbol| topic(channel, symbol.as_ref()))
+            else {
+                metrics.subscription_failure(pair, channel.name());
+                warn!(channel = ?channel, "bybit does not stream this channel");
+                continue;
+            };
+            if channel.r#type == MarketChannelType::Candles {
+                api.final_candles
+                    .insert(topic.clone(), channel.only_final.unwrap_or(false));
+            }
+            if let Some(depth) = channel.orderbook.and_then(|c| c.depth) {
+                api.orderbook_depths.insert(pair.clone(), depth);
+            }
+            api.topics.push(topic);
+        }
+
+        let url = if use_test {
+            TEST_PUBLIC_WS_ENDPOINT
+        } else {
+            PUBLIC_WS_ENDPOINT
+        };
+        let addr = DefaultWsActor::new(
+            "BybitStream",
+            Url::parse(url)?,
+            WsReconnectOptions::builder()
+                .conn_timeout(Duration::from_secs(30))
+                .stale_after(Duration::from_secs(60))
+                .build(),
+            Arc::new(api),
+        )
+        .await?;
+
+        Ok(BotWrapper::new(addr, UnboundedReceiverStream::new(rx)))
+    }
+
+    fn get_pair(&self, symbol: &str) -> Result<Pair> {
+        symbol_to_pair(&Self::EXCHANGE, &MarketSymbol::from(symbol)).map_err(|e| {
+            self.metrics.in_unsupported_pair(symbol, "order_books");
+            e
+        })
+    }
+
+    /// The funding rate with the ticker update merged in, only when the rate or the next funding time changed
+    fn funding_rate(&self, pair: Pair, update: &BybitTickerUpdate, ts: i64) -> Option<FundingRate> {
+        let last = self.funding_rates.get(&pair).map(|f| f.clone());
+        let merged = merge_funding_rate(last.as_ref(), update, pair.clone(), ts)?;
+        self.funding_rates.insert(pair, merged.clone());
+        let changed = last.map_or(true, |last| {
+            last.rate != merged.rate || last.next_funding_ms != merged.next_funding_ms
+        });
+        changed.then_some(merged)
+    }
+
+    /// Market events of a topic push, order books send a snapshot followed by deltas
+    fn parse_push(&self, topic: &str, kind: Option<&str>, ts: i64, data: Value) -> Result<Vec<MarketEvent>> {
+        // Topics are `channel.[param.]symbol`
+        let (Some((channel, _)), Some(symbol)) = (topic.split_once('.'), topic.rsplit('.').next()) else {
+            return Ok(vec![]);
+        };
+        let pair = self.get_pair(symbol)?;
+        let events = match channel {
+            "publicTrade" => {
+                let trades: Vec<BybitPublicTrade> = serde_json::from_value(data)?;
+                trades
+                    .iter()
+                    .map(|t| MarketEvent::Trade(from_bybit_public_trade(t, pair.clone())))
+                    .collect()
+            }
+            "orderbook" => {
+                let book: BybitOrderBook = serde_json::from_value(data)?;
+                let (asks, bids) = (from_bybit_levels(&book.asks), from_bybit_levels(&book.bids));
+                let is_snapshot = kind == Some("snapshot");
+                self.latest_book(&pair, |agg| {
+                    if is_snapshot {
+                        agg.reset_asks_n(asks.iter().copied());
+                        agg.reset_bids_n(bids.iter().copied());
+                    } else {
+                        agg.update_asks(asks.iter().copied());
+                        agg.update_bids(bids.iter().copied());
+                    }
+                    agg.set_ts(ts);
+                })
+                .map(MarketEvent::Orderbook)
+                .into_iter()
+                .collect()
+            }
+            "kline" => {
+                let only_final = self.final_candles.get(topic).copied().unwrap_or(false);
+                let klines: Vec<BybitKline> = serde_json::from_value(data)?;
+                klines
+                    .iter()
+                    .filter(|k| k.confirm || !only_final)
+                    .map(|k| MarketEvent::TradeCandle(from_bybit_kline(k, pair.clone())))
+                    .collect()
+            }
+            "tickers" => {
+                let update: BybitTickerUpdate = serde_json::from_value(data)?;
+                self.funding_rate(pair, &update, ts)
+                    .map(MarketEvent::FundingRate)
+                    .into_iter()
+                    .collect()
+            }
+            _ => vec![],
+        };
+        Ok(events)
+    }
+}
+
+#[async_trait]
+impl WsHandler for BybitStreamingApi {
+    #[cfg_attr(feature = "flame", flame)]
+    fn handle_in(&self, _w: &mut SinkWrite<Message, WsFramedSink>, msg: Bytes) {
+        match deserialize_json_s::<WsMessage>(msg.as_ref()) {
+            Ok(WsMessage::Push { topic, kind, ts, data }) => match self.parse_push(&topic, kind.as_deref(), ts, data) {
+                Ok(events) => events.into_iter().for_each(|e| self.broadcast(e)),
+                Err(err) => trace!(err = ?err, topic = %topic, "bybit error parsing push"),
+            },
+            Ok(WsMessage::Op { op, success, ret_msg }) if success == Some(false) => {
+                error!(op = %op, msg = ?ret_msg, "bybit stream error");
+            }
+            Ok(WsMessage::Op { .. }) => {}
+            Err(err) => trace!(err = ?err, msg = ?msg, "bybit error deserializing"),
+        }
+    }
+
+    fn handle_started(&self, w: &mut SinkWrite<Message, WsFramedSink>) {
+        self.metrics.stream_reconnected();
+        for topics in self.topics.chunks(MAX_TOPICS_PER_REQUEST) {
+            let request = WsRequest {
+                op: "subscribe",
+                args: topics.to_vec(),
+            };
+            let written = serde_json::to_string(&request)
+                .ok()
+                .and_then(|s| w.write(Message::Text(s.into())).ok());
+            if written.is_none() {
+                for topic in topics {
+                    self.metrics.subscription_failure(topic, "subscribe");
+                }
+            }
+        }
+    }
+}
+
+impl StreamingApi for BybitStreamingApi {
+    const NAME: &'static str = "bybit";
+    const EXCHANGE: Exchange = Exchange::Bybit;
+}
+
+impl MarketEventStream for BybitStreamingApi {
+    fn sink(&self) -> &UnboundedSender<MarketEventEnvelopeRef> { &self.sink }
+
+    fn metrics(&self) -> &ExchangeMetrics { &self.metrics }
+}
+
+impl LiveBookStream for BybitStreamingApi {
+    fn books(&self) -> &DashMap<Pair, LiveAggregatedOrderBook> { &self.books }
+
+    fn orderbook_depths(&self) -> &HashMap<Pair, u16> { &self.orderbook_depths }
+}
@@ -94,6 +94,8 @@ impl CoinbaseApi {
             "ticker" => format!("{}/products/{}/ticker", self.config.rest, pair),
             "order_book" => format!("{}/products/{}/book", self.config.rest, pair),
             "transactions" => format!("{}/accounts/{}/ledger", self.config.rest, pair),
+            "fees" => format!("{}/fees", self.config.rest),
+            "candles" => format!("{}/products/{}/candles", self.config.rest, pair),
             _ => "not implemented yet".to_string(),
         }
     }
@@ -238,6 +240,45 @@ impl CoinbaseApi {
         self.private_query(&params).await
     }
 
+    /// Returns the fees rates of the current tier of the account.
+    ///
+    /// Sample output:
+    ///
+    /// ```json
+    /// {"maker_fee_rate":"0.0015","taker_fee_rate":"0.0025","usd_volume":"25000.00"}
+    /// ```
+    pub async fn return_fees(&self) -> Result<Map<String, Value>> {
+        let mut params = HashMap::new();
+        params.insert("method", "fees");
+        params.insert("pair", "");
+        self.private_query(&params).await
+    }
+
+    /// Returns the candles of a pair opened between `start` and `end`, newest first and at most 300 of them.
+    /// `granularity` is the duration of candles in seconds, and each candle is `[time, low, high, open, close, volume]`.
+    ///
+    /// Sample output:
+    ///
+    /// ```json
+    /// [[1415398800, 0.32, 4.2, 0.35, 4.2, 12.3], [1415398740, 0.31, 0.36, 0.33, 0.35, 8.1], ... ]
+    /// ```
+    pub async fn return_candles(&self, pair: Pair, granularity: i64, start: &str, end: &str) -> Result<Vec<Vec<f64>>> {
+        let symbol = utils::get_symbol(&pair)?;
+        let url = self.build_url("candles", symbol.as_ref());
+        let granularity = granularity.to_string();
+
+        self.block_or_continue();
+        let req = self
+            .client
+            .request(Method::GET, url)
+            .query(&[("granularity", granularity.as_str()), ("start", start), ("end", end)])
+            .header(USER_AGENT, "tradai_broker")
+            .header(ACCEPT, "application/json")
+            .build()?;
+        let resp = self.client.execute(req).await?;
+        resp.json().err_into().await
+    }
+
     /// Add a buy limit order to the exchange
     /// limit_price: If the order gets executed, a new sell order will be placed,
     /// with "limit_price" as its price.
@@ -0,0 +1,30 @@
+use broker_core::fees::{Fee, FeeProvider, FeeTier};
+use broker_core::types::{AssetType, OrderType};
+use serde::Deserialize;
+
+/// Fees of the starter tier of the maker/taker schedule, until the tier of the account is fetched
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub(crate) struct CoinbaseFeeProvider {
+    maker: f64,
+    taker: f64,
+}
+
+impl Default for CoinbaseFeeProvider {
+    fn default() -> Self {
+        Self {
+            maker: 0.004,
+            taker: 0.006,
+        }
+    }
+}
+
+impl FeeProvider for CoinbaseFeeProvider {
+    fn get_rate(&self, _asset_type: Option<AssetType>, order_type: Option<OrderType>) -> Fee {
+        let tier = FeeTier {
+            maker: self.maker,
+            taker: self.taker,
+        };
+        Fee(tier.rate(order_type), "USD".to_string())
+    }
+}
@@ -3,8 +3,11 @@
 //! but this generic API does not provide all the functionnality that Gdax offers.
 
 use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use stats::kline::Resolution;
 
 use broker_core::error::*;
+use broker_core::fees::FeeTier;
 use broker_core::json_util::from_json_f64;
 use broker_core::pair::PairConf;
 use broker_core::prelude::*;
@@ -13,6 +16,9 @@ use broker_core::types::*;
 use super::api::CoinbaseApi;
 use super::utils;
 
+/// Maximum number of candles returned by a single request
+const CANDLES_LIMIT: i64 = 300;
+
 #[async_trait]
 impl Brokerage for CoinbaseApi {
     async fn ticker(&self, pair: Pair) -> Result<Ticker> {
@@ -134,6 +140,36 @@ impl Brokerage for CoinbaseApi {
         Ok(balances)
     }
 
+    async fn fee_tier(&self) -> Result<FeeTier> {
+        let raw_response = self.return_fees().await?;
+        let result = utils::parse_result(&raw_response)?;
+        utils::parse_fee_tier(&result)
+    }
+
+    async fn candles(
+        &self,
+        pair: Pair,
+        resolution: Resolution,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Vec<Candle>> {
+        let granularity = utils::candle_granularity(resolution).ok_or_else(|| {
+            Error::UnsupportedCapability(format!("coinbase candles with resolution {:?}", resolution))
+        })?;
+        // Requests spanning more candles than the limit are rejected instead of truncated
+        let end = end.min(start + Duration::seconds(granularity * CANDLES_LIMIT));
+        let raw = self
+            .return_candles(pair.clone(), granularity, &start.to_rfc3339(), &end.to_rfc3339())
+            .await?;
+        let mut candles = raw
+            .iter()
+            .map(|c| utils::parse_candle(c, pair.clone(), granularity))
+            .collect::<Result<Vec<Candle>>>()?;
+        candles.retain(|c| c.start_time >= start && c.start_time < end);
+        candles.sort_by_key(|c| c.start_time);
+        Ok(candles)
+    }
+
     async fn get_order(&self, _id: String, _pair: Pair, _asset_type: AssetType) -> Result<Order> { unimplemented!() }
 
     async fn pairs(&self) -> Result<Vec<PairConf>> {
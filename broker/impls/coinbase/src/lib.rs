@@ -9,12 +9,14 @@ extern crate async_trait;
 #[macro_use]
 extern crate anyhow;
 
+use crate::fees::CoinbaseFeeProvider;
 use broker_core::fees::FeeProvider;
 use broker_core::prelude::*;
 use serde_json::Value;
 use std::sync::Arc;
 
 mod api;
+mod fees;
 mod generic_api;
 mod utils;
 
@@ -42,7 +44,14 @@ impl BrokerConnector for CoinbaseExchangeConnector {
         todo!()
     }
 
-    fn fees_provider(&self, _conf: Value) -> broker_core::error::Result<Arc<dyn FeeProvider>> { todo!() }
+    fn fees_provider(&self, conf: Value) -> broker_core::error::Result<Arc<dyn FeeProvider>> {
+        let provider: CoinbaseFeeProvider = if conf.is_null() {
+            CoinbaseFeeProvider::default()
+        } else {
+            serde_json::from_value(conf)?
+        };
+        Ok(Arc::new(provider))
+    }
 }
 
 exchange!(Exchange::Coinbase, CoinbaseExchangeConnector);
@@ -1,11 +1,15 @@
+use chrono::{Duration, TimeZone, Utc};
 use hmac::{Hmac, Mac};
 use serde_json::value::Map;
 use serde_json::Value;
 use sha2::Sha256;
+use stats::kline::Resolution;
 
 use broker_core::error::*;
+use broker_core::fees::FeeTier;
+use broker_core::json_util::from_json_f64;
 use broker_core::prelude::*;
-use broker_core::types::{Asset, MarketSymbol, Pair};
+use broker_core::types::{Asset, Candle, MarketSymbol, Pair};
 
 /// Return the name associated to the pair used by Gdax
 /// If the Pair is not supported, None is returned.
@@ -77,6 +81,50 @@ pub fn parse_result(response: &Map<String, Value>) -> Result<Map<String, Value>>
 /// let currency = get_currency_enum("usd_balance");
 /// assert_eq!(Some(USD), currency);
 /// ```
+pub fn parse_fee_tier(result: &Map<String, Value>) -> Result<FeeTier> {
+    let rate = |key: &str| {
+        result
+            .get(key)
+            .ok_or_else(|| Error::MissingField(key.to_string()))
+            .and_then(|v| from_json_f64(v, key))
+    };
+    Ok(FeeTier {
+        maker: rate("maker_fee_rate")?,
+        taker: rate("taker_fee_rate")?,
+    })
+}
+
+/// The duration in seconds of the candles of a resolution, only a few granularities are served by coinbase
+pub fn candle_granularity(resolution: Resolution) -> Option<i64> {
+    let secs = resolution.as_secs();
+    [60, 300, 900, 3600, 21600, 86400].contains(&secs).then_some(secs)
+}
+
+/// Parse a `[time, low, high, open, close, volume]` candle, coinbase does not report the quote volume nor
+/// the number of trades
+#[allow(clippy::cast_possible_truncation)]
+pub fn parse_candle(raw: &[f64], pair: Pair, granularity: i64) -> Result<Candle> {
+    let [time, low, high, open, close, volume] = raw else {
+        return Err(Error::BadParse);
+    };
+    let start_time = Utc.timestamp_opt(*time as i64, 0).single().ok_or(Error::BadParse)?;
+    let end_time = start_time + Duration::seconds(granularity) - Duration::milliseconds(1);
+    Ok(Candle {
+        event_time: end_time,
+        pair,
+        start_time,
+        end_time,
+        open: *open,
+        high: *high,
+        low: *low,
+        close: *close,
+        volume: *volume,
+        quote_volume: 0.0,
+        trade_count: 0,
+        is_final: true,
+    })
+}
+
 #[allow(clippy::unnecessary_wraps)]
 pub fn get_currency_enum(currency: &str) -> Option<Asset> {
     Some(currency.replace("_balance", "").to_uppercase().into())
@@ -107,4 +155,31 @@ mod test {
             expected_signature
         );
     }
+
+    #[test]
+    fn should_parse_the_fee_tier() {
+        let fees =
+            serde_json::json!({"maker_fee_rate": "0.0015", "taker_fee_rate": "0.0025", "usd_volume": "25000.00"});
+        let tier = super::parse_fee_tier(fees.as_object().unwrap()).unwrap();
+        assert!((tier.maker - 0.0015).abs() < f64::EPSILON);
+        assert!((tier.taker - 0.0025).abs() < f64::EPSILON);
+        assert!(super::parse_fee_tier(&serde_json::Map::new()).is_err());
+    }
+
+    #[test]
+    fn should_parse_a_candle() {
+        use stats::kline::{Resolution, TimeUnit};
+
+        assert_eq!(
+            super::candle_granularity(Resolution::new(TimeUnit::Minute, 15)),
+            Some(900)
+        );
+        assert_eq!(super::candle_granularity(Resolution::new(TimeUnit::Minute, 3)), None);
+        let candle = super::parse_candle(&[1_415_398_800.0, 0.32, 4.2, 0.35, 4.1, 12.3], "BTC_USD".into(), 60).unwrap();
+        assert_eq!(candle.start_time.timestamp(), 1_415_398_800);
+        assert_eq!(candle.end_time.timestamp_millis(), 1_415_398_859_999);
+        assert!((candle.open - 0.35).abs() < f64::EPSILON);
+        assert!((candle.close - 4.1).abs() < f64::EPSILON);
+        assert!(super::parse_candle(&[1_415_398_800.0, 0.32], "BTC_USD".into(), 60).is_err());
+    }
 }
@@ -0,0 +1,176 @@
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use actix::io::SinkWrite;
+use async_trait::async_trait;
+use awc::ws::Message;
+use broker_core::account_metrics::AccountMetrics;
+use broker_core::bot::{BotWrapper, DefaultWsActor, WsFramedSink, WsHandler};
+use bytes::Bytes;
+use dashmap::DashMap;
+use tokio::sync::mpsc::UnboundedSender;
+use tokio_stream::wrappers::UnboundedReceiverStream;
+use url::Url;
+
+use broker_core::error::*;
+use broker_core::json_util::deserialize_json_s;
+use broker_core::prelude::*;
+use broker_core::streaming_api::WsReconnectOptions;
+use broker_core::types::PrivateStreamChannel;
+
+use super::api::KrakenApi;
+use super::model::{WsMessage, WsOrder, WsSubscribe, WsSubscription};
+use super::utils;
+
+static PRIVATE_WS_ENDPOINT: &str = "wss://ws-auth.kraken.com";
+
+/// Tokens must be used within 15 minutes, refresh them before so that the socket can always reconnect
+const TOKEN_REFRESH_INTERVAL_MS: i64 = 10 * 60 * 1000;
+
+/// Streams order updates of the account from the `openOrders` channel.
+/// Order updates carry the cumulative executed volume and cost, so `ownTrades` is not needed to follow fills.
+#[derive(Clone)]
+pub struct KrakenStreamingAccountApi {
+    sink: UnboundedSender<AccountEventEnveloppe>,
+    api: Arc<KrakenApi>,
+    token: Arc<RwLock<String>>,
+    /// Last time the token was fetched, in ms
+    token_fetched_at: Arc<AtomicI64>,
+    /// Last known state of each open order
+    orders: Arc<DashMap<String, WsOrder>>,
+    metrics: Arc<AccountMetrics>,
+    pub account_type: AccountType,
+}
+
+impl KrakenStreamingAccountApi {
+    /// Create a new kraken account bot, only the orders channel is supported
+    pub async fn new_bot(
+        creds: &dyn Credentials,
+        account_type: AccountType,
+        channels: HashSet<PrivateStreamChannel>,
+    ) -> Result<BotWrapper<DefaultWsActor, UnboundedReceiverStream<AccountEventEnveloppe>>> {
+        if account_type != AccountType::Spot {
+            return Err(Error::UnsupportedAccountType);
+        }
+        if !channels.contains(&PrivateStreamChannel::Orders) {
+            return Err(Error::BrokerFeatureNotImplemented);
+        }
+        let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+        let api = KrakenStreamingAccountApi {
+            sink: tx,
+            api: Arc::new(KrakenApi::new(creds)?),
+            token: Arc::new(RwLock::new(String::new())),
+            token_fetched_at: Arc::new(AtomicI64::new(0)),
+            orders: Arc::new(DashMap::new()),
+            metrics: Arc::new(AccountMetrics::for_exchange(Exchange::Kraken)),
+            account_type,
+        };
+        api.refresh_token().await?;
+        let addr = DefaultWsActor::new(
+            "KrakenAccountStream",
+            Url::parse(PRIVATE_WS_ENDPOINT)?,
+            WsReconnectOptions::builder()
+                .conn_timeout(Duration::from_secs(30))
+                .build(),
+            Arc::new(api),
+        )
+        .await?;
+
+        Ok(BotWrapper::new(addr, UnboundedReceiverStream::new(rx)))
+    }
+
+    async fn refresh_token(&self) -> Result<()> {
+        let response = self.api.get_websockets_token().await?;
+        let token = utils::parse_result(&response)?.token.clone();
+        *self.token.write().unwrap() = token;
+        self.token_fetched_at.store(get_unix_timestamp_ms(), Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Merge the update into the known order, closed orders are forgotten
+    fn order_update(&self, txid: &str, update: WsOrder) -> Result<OrderUpdate> {
+        let previous = self.orders.get(txid).map(|o| o.clone());
+        let mut order = previous.clone().unwrap_or_default();
+        order.merge(update);
+        let order_update = utils::from_kraken_order_update(txid, &order, previous.as_ref())?;
+        if matches!(order.status.as_deref(), Some("closed" | "canceled" | "expired")) {
+            self.orders.remove(txid);
+        } else {
+            self.orders.insert(txid.to_string(), order);
+        }
+        Ok(order_update)
+    }
+
+    fn broadcast(&self, v: AccountEvent) {
+        if self
+            .sink
+            .send(AccountEventEnveloppe {
+                xchg: Exchange::Kraken,
+                event: v,
+                account_type: self.account_type.clone(),
+                account: None,
+            })
+            .is_err()
+        {
+            self.metrics.send_error();
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl WsHandler for KrakenStreamingAccountApi {
+    #[cfg_attr(feature = "flame", flame)]
+    fn handle_in(&self, _w: &mut SinkWrite<Message, WsFramedSink>, msg: Bytes) {
+        match deserialize_json_s::<WsMessage>(msg.as_ref()) {
+            Err(err) => {
+                debug!(err = ?err, msg = ?msg, "kraken stream deserialization error");
+            }
+            Ok(WsMessage::Event {
+                event,
+                status,
+                error_message,
+            }) => {
+                if status.as_deref() == Some("error") {
+                    error!(event = %event, msg = ?error_message, "kraken account stream error");
+                }
+            }
+            Ok(WsMessage::Channel(orders, channel, _)) if channel == "openOrders" => {
+                for (txid, update) in orders.into_iter().flatten() {
+                    match self.order_update(&txid, update) {
+                        Ok(order) => self.broadcast(AccountEvent::OrderUpdate(order)),
+                        Err(err) => debug!(err = ?err, txid = %txid, "kraken order update conversion error"),
+                    }
+                }
+            }
+            Ok(WsMessage::Channel(..)) => {}
+        }
+    }
+
+    fn handle_started(&self, w: &mut SinkWrite<Message, WsFramedSink>) {
+        self.metrics.stream_reconnected();
+        let token = self.token.read().unwrap().clone();
+        let subscription = serde_json::to_string(&WsSubscribe {
+            event: "subscribe",
+            subscription: WsSubscription {
+                name: "openOrders",
+                token: &token,
+            },
+        })
+        .unwrap();
+        if w.write(Message::Text(subscription.into())).is_err() {
+            error!("kraken failed to subscribe to account orders");
+        }
+    }
+
+    async fn handle_keep_alive(&self) -> Result<()> {
+        if get_unix_timestamp_ms() - self.token_fetched_at.load(Ordering::Relaxed) > TOKEN_REFRESH_INTERVAL_MS {
+            // The current connection stays authenticated, retry on the next keep alive
+            if let Err(e) = self.refresh_token().await {
+                warn!(err = ?e, "kraken failed to refresh the websocket token");
+            }
+        }
+        Ok(())
+    }
+}
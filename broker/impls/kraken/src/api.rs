@@ -4,8 +4,6 @@
 use data_encoding::BASE64;
 use std::collections::HashMap;
 use std::str;
-use std::thread;
-use std::time::Duration;
 
 use futures::TryFutureExt;
 use hmac::digest::Digest;
@@ -18,18 +16,26 @@ use sha2::{Sha256, Sha512};
 
 use broker_core::error::*;
 use broker_core::prelude::*;
+use broker_core::ratelimit::{self, default_rate_limiter};
+use broker_core::types::RateLimit;
 use broker_core::url_util::{strip_empties, url_encode_hashmap};
 
-use super::model::{OrderResult, Orderbooks, StandardOrder, TickerInfo};
+use super::model::{CancelAllOrdersAfter, OrderResult, Orderbooks, StandardOrder, TickerInfo, TradeVolume,
+                   WebSocketsToken};
 use super::utils::KrakenResponse;
 
 const KEY_HEADER: &str = "API-Key";
 const SIGN_HEADER: &str = "API-Sign";
 
+/// Public endpoints allow about one request per second
+pub(crate) const PUBLIC_ENDPOINT: &str = "public";
+pub(crate) const PUBLIC_LIMIT: RateLimit = RateLimit::new(1, 1000);
+/// Private endpoints have a counter of 15 that decays by one every three seconds
+pub(crate) const PRIVATE_ENDPOINT: &str = "private";
+pub(crate) const PRIVATE_LIMIT: RateLimit = RateLimit::new(15, 45_000);
+
 #[derive(Debug, Clone)]
 pub struct KrakenApi {
-    last_request: i64,
-    // unix timestamp in ms, to avoid ban
     pub(super) api_key: String,
     pub(super) api_secret: String,
     otp: Option<String>,
@@ -48,8 +54,11 @@ impl KrakenApi {
             });
         }
 
+        let limiter = default_rate_limiter();
+        limiter.register_default(Exchange::Kraken, PUBLIC_ENDPOINT, PUBLIC_LIMIT);
+        limiter.register_default(Exchange::Kraken, PRIVATE_ENDPOINT, PRIVATE_LIMIT);
+
         Ok(KrakenApi {
-            last_request: 0,
             api_key: creds.get("api_key").unwrap_or_default(),
             api_secret: creds.get("api_secret").unwrap_or_default(),
             otp: None,
@@ -68,23 +77,19 @@ impl KrakenApi {
     /// Burst true implies there is a control over the number of calls allowed to the exchange
     pub async fn set_burst(&mut self, burst: bool) { self.burst = burst }
 
-    #[allow(clippy::cast_sign_loss)]
-    pub async fn block_or_continue(&self) {
-        if !self.burst {
-            let threshold: u64 = 2000; // 1 request/2sec
-            let offset: u64 = get_unix_timestamp_ms() as u64 - self.last_request as u64;
-            if offset < threshold {
-                let wait_ms = Duration::from_millis(threshold - offset);
-                thread::sleep(wait_ms);
-            }
+    /// Wait for the budget of the endpoint, shared by every kraken api of the process
+    pub async fn block_or_continue(&self, endpoint: &str) -> Result<()> {
+        if self.burst {
+            return Ok(());
         }
+        ratelimit::acquire(Exchange::Kraken, endpoint, 1).await
     }
 
     async fn public_query<T: DeserializeOwned>(&self, method: &str, mut params: HashMap<&str, &str>) -> Result<T> {
         strip_empties(&mut params);
         let url = "https://api.kraken.com/0/public/".to_string() + method + "?" + &url_encode_hashmap(&params);
 
-        self.block_or_continue().await;
+        self.block_or_continue(PUBLIC_ENDPOINT).await?;
         let resp = self.client.get(url).send().await?;
         resp.json().err_into().await
     }
@@ -94,6 +99,8 @@ impl KrakenApi {
 
         let urlpath = "/0/private/".to_string() + method;
 
+        // Wait before taking the nonce, nonces must increase in the order requests are received
+        self.block_or_continue(PRIVATE_ENDPOINT).await?;
         let nonce = get_unix_timestamp_ms().to_string();
         strip_empties(&mut params);
 
@@ -714,13 +721,18 @@ impl KrakenApi {
     /// Note: If an asset pair is on a maker/taker fee schedule, the taker side is given in "fees"
     /// and maker side in "fees_maker". For pairs not on maker/taker, they will only be given in
     /// "fees".
-    pub async fn get_trade_volume(&self, pair: &str, fee_info: &str) -> Result<Map<String, Value>> {
+    pub(super) async fn get_trade_volume(&self, pair: &str, fee_info: &str) -> Result<KrakenResponse<TradeVolume>> {
         let mut params = HashMap::new();
         params.insert("pair", pair);
         params.insert("fee-info", fee_info);
         self.private_query("TradeVolume", params).await
     }
 
+    /// Result: a token to subscribe to private websocket channels, it must be used within 15 minutes
+    pub(super) async fn get_websockets_token(&self) -> Result<KrakenResponse<WebSocketsToken>> {
+        self.private_query("GetWebSocketsToken", HashMap::new()).await
+    }
+
     // TODO: add optional closing order
     /// Input:
     ///
@@ -821,6 +833,7 @@ impl KrakenApi {
         params.insert("starttm", o.starttm);
         params.insert("expiretm", o.expiretm);
         params.insert("userref", o.userref);
+        params.insert("cl_ord_id", o.cl_ord_id);
         params.insert("validate", o.validate);
         self.private_query("AddOrder", params).await
     }
@@ -843,6 +856,23 @@ impl KrakenApi {
         self.private_query("CancelOrder", params).await
     }
 
+    /// Input:
+    ///
+    /// ```json
+    /// timeout = seconds after which all orders are canceled unless called again, 0 disables the timer
+    /// ```
+    /// Result:
+    ///
+    /// ```json
+    /// currentTime = time of the server
+    /// triggerTime = time at which all orders will be canceled, 0 if the timer was disabled
+    /// ```
+    pub(super) async fn cancel_all_orders_after(&self, timeout: &str) -> Result<KrakenResponse<CancelAllOrdersAfter>> {
+        let mut params = HashMap::new();
+        params.insert("timeout", timeout);
+        self.private_query("CancelAllOrdersAfter", params).await
+    }
+
     /// Input:
     ///
     /// ```json
@@ -0,0 +1,29 @@
+use broker_core::fees::{Fee, FeeProvider, FeeTier};
+use broker_core::types::{AssetType, OrderType};
+
+/// Fees of the starter tier of the spot maker/taker schedule, until the tier of the account is fetched
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub(crate) struct KrakenFeeProvider {
+    maker: f64,
+    taker: f64,
+}
+
+impl Default for KrakenFeeProvider {
+    fn default() -> Self {
+        Self {
+            maker: 0.0025,
+            taker: 0.004,
+        }
+    }
+}
+
+impl FeeProvider for KrakenFeeProvider {
+    fn get_rate(&self, _asset_type: Option<AssetType>, order_type: Option<OrderType>) -> Fee {
+        let tier = FeeTier {
+            maker: self.maker,
+            taker: self.taker,
+        };
+        Fee(tier.rate(order_type), "ZUSD".to_string())
+    }
+}
@@ -2,16 +2,23 @@
 //! This a more convenient and safe way to deal with the exchange since methods return a Result<>
 //! but this generic API does not provide all the functionnality that Kraken offers.
 
+use std::time::Duration;
+
 use broker_core::error::*;
+use broker_core::fees::FeeTier;
 use broker_core::json_util::from_json_f64;
 use broker_core::pair::PairConf;
 use broker_core::prelude::*;
 use broker_core::types::*;
 
-use super::api::KrakenApi;
+use super::api::{KrakenApi, PRIVATE_LIMIT, PUBLIC_LIMIT};
 use super::model::StandardOrder;
 use super::utils;
 
+/// Fee tiers depend on the trading volume of the account, so that the tier of this pair applies to every pair on
+/// the same fee schedule
+const FEE_TIER_PAIR: &str = "XXBTZUSD";
+
 #[async_trait]
 impl Brokerage for KrakenApi {
     async fn ticker(&self, pair: Pair) -> Result<Ticker> {
@@ -74,6 +81,8 @@ impl Brokerage for KrakenApi {
             _ => unimplemented!(),
         };
 
+        let oflags = if order.post_only { "post" } else { "" };
+
         let mut price_str = "".to_string();
         if let Some(price) = price {
             price_str = price.to_string();
@@ -86,10 +95,11 @@ impl Brokerage for KrakenApi {
             price2: "",                    // price 2
             volume: &quantity.to_string(), // volume
             leverage: "",                  // leverage
-            oflags: "",                    // oflags (see doc)
+            oflags,                        // oflags (see doc)
             starttm: "",                   // starttm
             expiretm: "",                  // expiretm
             userref: "",                   // userref
+            cl_ord_id: &order.order_id,    // client order id
             validate: "",
         };
 
@@ -103,6 +113,19 @@ impl Brokerage for KrakenApi {
         })
     }
 
+    async fn cancel_all_after(&self, timeout: Duration) -> Result<()> {
+        let raw_response = self.cancel_all_orders_after(&timeout.as_secs().to_string()).await?;
+        let result = utils::parse_result(&raw_response)?;
+        trace!(trigger_time = %result.trigger_time, "armed dead man's switch");
+        Ok(())
+    }
+
+    async fn fee_tier(&self) -> Result<FeeTier> {
+        let raw_response = self.get_trade_volume(FEE_TIER_PAIR, "true").await?;
+        let result = utils::parse_result(&raw_response)?;
+        result.fee_tier(FEE_TIER_PAIR)
+    }
+
     async fn account_balances(&self) -> Result<AccountPosition> {
         let raw_response = self.get_account_balance().await?;
         let result = utils::parse_result(&raw_response)?;
@@ -150,4 +173,13 @@ impl Brokerage for KrakenApi {
     fn exchange(&self) -> Exchange { Exchange::Kraken }
 
     fn uses_account(&self) -> bool { !self.api_key.is_empty() && !self.api_secret.is_empty() }
+
+    fn capabilities(&self) -> ExchangeCapabilities {
+        ExchangeCapabilities {
+            post_only: true,
+            dead_man_switch: Some((1, 86400)),
+            rate_limits: vec![PUBLIC_LIMIT, PRIVATE_LIMIT],
+            ..ExchangeCapabilities::default()
+        }
+    }
 }
@@ -6,21 +6,27 @@
 #[macro_use]
 extern crate broker_core;
 #[macro_use]
+extern crate tracing;
+#[macro_use]
 extern crate async_trait;
 #[macro_use]
 extern crate serde;
 
+use crate::fees::KrakenFeeProvider;
 use broker_core::fees::FeeProvider;
 use serde_json::Value;
 use std::sync::Arc;
 
 use broker_core::prelude::*;
 
+mod account_api;
 mod api;
+mod fees;
 mod generic_api;
 mod model;
 mod utils;
 
+pub use self::account_api::KrakenStreamingAccountApi;
 pub use self::api::KrakenApi;
 pub use utils::{get_currency_enum, get_currency_string};
 
@@ -39,12 +45,21 @@ impl BrokerConnector for KrakenExchangeConnector {
 
     async fn new_private_stream(
         &self,
-        _ctx: PrivateBotInitContext,
+        ctx: PrivateBotInitContext,
     ) -> broker_core::error::Result<Box<BrokerageAccountDataStreamer>> {
-        todo!()
+        Ok(Box::new(
+            KrakenStreamingAccountApi::new_bot(ctx.creds.as_ref(), ctx.account_type, ctx.channels).await?,
+        ))
     }
 
-    fn fees_provider(&self, _conf: Value) -> broker_core::error::Result<Arc<dyn FeeProvider>> { todo!() }
+    fn fees_provider(&self, conf: Value) -> broker_core::error::Result<Arc<dyn FeeProvider>> {
+        let provider: KrakenFeeProvider = if conf.is_null() {
+            KrakenFeeProvider::default()
+        } else {
+            serde_json::from_value(conf)?
+        };
+        Ok(Arc::new(provider))
+    }
 }
 
 exchange!(Exchange::Kraken, KrakenExchangeConnector);
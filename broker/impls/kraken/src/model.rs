@@ -1,4 +1,5 @@
-use broker_core::error::Result;
+use broker_core::error::{Error, Result};
+use broker_core::fees::FeeTier;
 use std::collections::HashMap;
 
 pub(super) struct StandardOrder<'a> {
@@ -12,6 +13,7 @@ pub(super) struct StandardOrder<'a> {
     pub starttm: &'a str,
     pub expiretm: &'a str,
     pub userref: &'a str,
+    pub cl_ord_id: &'a str,
     pub validate: &'a str,
 }
 
@@ -59,3 +61,152 @@ impl TickerInfo {
     pub fn bid(&self) -> Result<f64> { Ok(self.b.0.parse::<f64>()?) }
     pub fn volume(&self) -> Result<f64> { Ok(self.v.0.parse::<f64>()?) }
 }
+
+#[derive(Deserialize)]
+pub(super) struct WebSocketsToken {
+    pub token: String,
+}
+
+/// Fee of an asset pair, in percent
+#[derive(Deserialize)]
+pub(super) struct PairFee {
+    pub fee: String,
+}
+
+#[derive(Deserialize)]
+pub(super) struct TradeVolume {
+    #[serde(default)]
+    pub fees: HashMap<String, PairFee>,
+    /// Only for pairs on a maker/taker schedule
+    #[serde(default)]
+    pub fees_maker: HashMap<String, PairFee>,
+}
+
+impl TradeVolume {
+    /// The fee tier of `pair`, pairs that are not on a maker/taker schedule have the same maker and taker fees
+    pub fn fee_tier(&self, pair: &str) -> Result<FeeTier> {
+        let taker = self
+            .fees
+            .get(pair)
+            .ok_or_else(|| Error::MissingField(pair.to_string()))?
+            .fee
+            .parse::<f64>()?
+            / 100.0;
+        let maker = match self.fees_maker.get(pair) {
+            Some(maker) => maker.fee.parse::<f64>()? / 100.0,
+            None => taker,
+        };
+        Ok(FeeTier { maker, taker })
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(super) struct CancelAllOrdersAfter {
+    pub trigger_time: String,
+}
+
+#[derive(Serialize)]
+pub(super) struct WsSubscription<'a> {
+    pub name: &'a str,
+    pub token: &'a str,
+}
+
+#[derive(Serialize)]
+pub(super) struct WsSubscribe<'a> {
+    pub event: &'a str,
+    pub subscription: WsSubscription<'a>,
+}
+
+/// Events are json objects, channel messages are arrays of `[data, channel name, sequence]`
+#[derive(Deserialize)]
+#[serde(untagged)]
+#[allow(dead_code)]
+pub(super) enum WsMessage {
+    Event {
+        event: String,
+        status: Option<String>,
+        #[serde(rename = "errorMessage")]
+        error_message: Option<String>,
+    },
+    Channel(Vec<HashMap<String, WsOrder>>, String, serde_json::Value),
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub(super) struct WsOrderDescription {
+    pub pair: String,
+    #[serde(rename = "type")]
+    pub side: String,
+    pub price: String,
+}
+
+/// An order of the `openOrders` channel, updates after the first snapshot only carry the fields that changed
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub(super) struct WsOrder {
+    pub status: Option<String>,
+    pub descr: Option<WsOrderDescription>,
+    pub vol: Option<String>,
+    pub vol_exec: Option<String>,
+    pub cost: Option<String>,
+    pub fee: Option<String>,
+    pub opentm: Option<String>,
+    pub lastupdated: Option<String>,
+    pub timeinforce: Option<String>,
+    pub cl_ord_id: Option<String>,
+    pub reason: Option<String>,
+}
+
+fn parse_or_zero(v: &Option<String>) -> Result<f64> { v.as_deref().map_or(Ok(0.0), |s| Ok(s.parse::<f64>()?)) }
+
+impl WsOrder {
+    /// Apply the fields of a partial update
+    pub fn merge(&mut self, update: WsOrder) {
+        self.status = update.status.or(self.status.take());
+        self.descr = update.descr.or(self.descr.take());
+        self.vol = update.vol.or(self.vol.take());
+        self.vol_exec = update.vol_exec.or(self.vol_exec.take());
+        self.cost = update.cost.or(self.cost.take());
+        self.fee = update.fee.or(self.fee.take());
+        self.opentm = update.opentm.or(self.opentm.take());
+        self.lastupdated = update.lastupdated.or(self.lastupdated.take());
+        self.timeinforce = update.timeinforce.or(self.timeinforce.take());
+        self.cl_ord_id = update.cl_ord_id.or(self.cl_ord_id.take());
+        self.reason = update.reason.or(self.reason.take());
+    }
+
+    pub fn vol(&self) -> Result<f64> { parse_or_zero(&self.vol) }
+    pub fn vol_exec(&self) -> Result<f64> { parse_or_zero(&self.vol_exec) }
+    pub fn cost(&self) -> Result<f64> { parse_or_zero(&self.cost) }
+    pub fn fee(&self) -> Result<f64> { parse_or_zero(&self.fee) }
+    pub fn price(&self) -> Result<f64> { self.descr.as_ref().map_or(Ok(0.0), |d| Ok(d.price.parse::<f64>()?)) }
+
+    /// Last update time in ms, timestamps are seconds with a fractional part
+    pub fn timestamp_ms(&self) -> Result<u64> {
+        let secs = parse_or_zero(if self.lastupdated.is_some() {
+            &self.lastupdated
+        } else {
+            &self.opentm
+        })?;
+        Ok((secs * 1000.0) as u64)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::TradeVolume;
+
+    #[test]
+    fn trade_volume_fee_tier() {
+        let volume: TradeVolume = serde_json::from_str(
+            r#"{"currency":"ZUSD","volume":"200709.4223",
+            "fees":{"XXBTZUSD":{"fee":"0.2400","minfee":"0.1000","maxfee":"0.2600"}},
+            "fees_maker":{"XXBTZUSD":{"fee":"0.1400","minfee":"0.0000","maxfee":"0.1600"}}}"#,
+        )
+        .unwrap();
+        let tier = volume.fee_tier("XXBTZUSD").unwrap();
+        assert!((tier.taker - 0.0024).abs() < f64::EPSILON);
+        assert!((tier.maker - 0.0014).abs() < f64::EPSILON);
+        assert!(volume.fee_tier("XETHZUSD").is_err());
+    }
+}
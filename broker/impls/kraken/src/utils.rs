@@ -2,6 +2,8 @@ use broker_core::error::*;
 use broker_core::prelude::*;
 use broker_core::types::*;
 
+use super::model::WsOrder;
+
 /// Return the name associated to the pair used by Kraken
 /// If the Pair is not supported, None is returned.
 pub fn get_pair_string(pair: &Pair) -> Result<MarketSymbol> {
@@ -43,6 +45,66 @@ pub fn parse_result<T>(response: &KrakenResponse<T>) -> Result<&T> {
     }
 }
 
+/// Convert an order of the `openOrders` channel, `previous` is the state of the order before the update
+/// and is used to find the quantity and price of the last execution
+pub(super) fn from_kraken_order_update(txid: &str, order: &WsOrder, previous: Option<&WsOrder>) -> Result<OrderUpdate> {
+    let filled_qty = order.vol_exec()?;
+    let cost = order.cost()?;
+    let (prev_filled_qty, prev_cost) = match previous {
+        Some(previous) => (previous.vol_exec()?, previous.cost()?),
+        None => (0.0, 0.0),
+    };
+    let last_executed_qty = filled_qty - prev_filled_qty;
+    let last_quote_qty = cost - prev_cost;
+    let status = match order.status.as_deref() {
+        Some("open") if filled_qty > 0.0 => OrderStatus::PartiallyFilled,
+        Some("closed") => OrderStatus::Filled,
+        Some("canceled") => OrderStatus::Canceled,
+        Some("expired") => OrderStatus::Expired,
+        _ => OrderStatus::New,
+    };
+    Ok(OrderUpdate {
+        enforcement: match order.timeinforce.as_deref() {
+            Some("IOC") => OrderEnforcement::IOC,
+            _ => OrderEnforcement::GTC,
+        },
+        side: match order.descr.as_ref().map(|d| d.side.as_str()) {
+            Some("sell") => TradeType::Sell,
+            _ => TradeType::Buy,
+        },
+        orig_order_id: order.cl_ord_id.clone(),
+        // Kraken transaction ids are not numeric
+        order_id: 0,
+        symbol: order
+            .descr
+            .as_ref()
+            .map(|d| d.pair.clone())
+            .unwrap_or_else(|| txid.to_string()),
+        timestamp: order.timestamp_ms()?,
+        new_status: status.clone(),
+        orig_status: status,
+        is_on_the_book: matches!(order.status.as_deref(), Some("open")),
+        qty: order.vol()?,
+        quote_qty: 0.0,
+        price: order.price()?,
+        stop_price: 0.0,
+        iceberg_qty: 0.0,
+        commission: order.fee()?,
+        commission_asset: None,
+        last_executed_qty,
+        cummulative_filled_qty: filled_qty,
+        last_executed_price: if last_executed_qty > 0.0 {
+            last_quote_qty / last_executed_qty
+        } else {
+            0.0
+        },
+        cummulative_quote_asset_transacted_qty: cost,
+        last_quote_asset_transacted_qty: last_quote_qty,
+        quote_order_qty: 0.0,
+        rejection_reason: order.reason.clone(),
+    })
+}
+
 /// Return the currency enum associated with the
 /// string used by Kraken. If no currency is found,
 /// return None
@@ -118,3 +180,33 @@ pub fn get_currency_string(currency: &Asset) -> Option<String> {
         _ => None,
     }
 }
+
+#[cfg(test)]
+mod test {
+    use broker_core::types::OrderStatus;
+
+    use super::from_kraken_order_update;
+    use crate::model::WsOrder;
+
+    #[test]
+    fn should_compute_the_last_execution_of_an_order_update() {
+        let snapshot: WsOrder = serde_json::from_str(
+            r#"{"status":"open","descr":{"pair":"XBT/EUR","type":"buy","price":"30000.0"},"vol":"1.0","vol_exec":"0.25","cost":"7500.0","fee":"12.0","opentm":"1688666559.8974","cl_ord_id":"my-order"}"#,
+        )
+        .unwrap();
+        let update: WsOrder = serde_json::from_str(
+            r#"{"status":"closed","vol_exec":"1.0","cost":"30000.0","fee":"48.0","lastupdated":"1688666560.5"}"#,
+        )
+        .unwrap();
+        let mut order = snapshot.clone();
+        order.merge(update);
+        let order_update = from_kraken_order_update("OGTT3Y-C6I3P-XRI6HX", &order, Some(&snapshot)).unwrap();
+        assert_eq!(order_update.orig_order_id.as_deref(), Some("my-order"));
+        assert_eq!(order_update.new_status, OrderStatus::Filled);
+        assert_eq!(order_update.symbol, "XBT/EUR");
+        assert_eq!(order_update.cummulative_filled_qty, 1.0);
+        assert_eq!(order_update.last_executed_qty, 0.75);
+        assert_eq!(order_update.last_executed_price, 30000.0);
+        assert_eq!(order_update.timestamp, 1688666560500);
+    }
+}
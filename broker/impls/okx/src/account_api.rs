@@ -0,0 +1,168 @@
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use actix::io::SinkWrite;
+use actix_http::ws::Message;
+use async_trait::async_trait;
+use broker_core::account_metrics::AccountMetrics;
+use broker_core::bot::{BotWrapper, DefaultWsActor, WsFramedSink, WsHandler};
+use bytes::Bytes;
+use tokio::sync::mpsc::UnboundedSender;
+use tokio_stream::wrappers::UnboundedReceiverStream;
+use url::Url;
+
+use broker_core::error::*;
+use broker_core::json_util::deserialize_json_s;
+use broker_core::prelude::*;
+use broker_core::streaming_api::WsReconnectOptions;
+
+use crate::adapters::from_okx_order_update;
+use crate::api::sign;
+use crate::models::*;
+
+static PRIVATE_WS_ENDPOINT: &str = "wss://ws.okx.com:8443/ws/v5/private";
+static TEST_PRIVATE_WS_ENDPOINT: &str = "wss://wspap.okx.com:8443/ws/v5/private";
+
+/// Streams order updates of the account, the stream logs in before subscribing to the `orders` channel
+#[derive(Clone)]
+pub struct OkxStreamingAccountApi {
+    sink: UnboundedSender<AccountEventEnveloppe>,
+    api_key: String,
+    api_secret: String,
+    passphrase: String,
+    metrics: Arc<AccountMetrics>,
+    pub account_type: AccountType,
+}
+
+impl OkxStreamingAccountApi {
+    /// Create a new okx account bot
+    pub async fn new_bot(
+        creds: Box<dyn Credentials>,
+        use_test: bool,
+        account_type: AccountType,
+    ) -> Result<BotWrapper<DefaultWsActor, UnboundedReceiverStream<AccountEventEnveloppe>>> {
+        if !matches!(
+            account_type,
+            AccountType::Spot | AccountType::Margin | AccountType::IsolatedMargin(_)
+        ) {
+            return Err(Error::UnsupportedAccountType);
+        }
+        let (Some(api_key), Some(api_secret), Some(passphrase)) =
+            (creds.get("api_key"), creds.get("api_secret"), creds.get("passphrase"))
+        else {
+            return Err(Error::MissingCredentials(
+                "okx api_key, api_secret or passphrase".to_string(),
+            ));
+        };
+        let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+        let api = OkxStreamingAccountApi {
+            sink: tx,
+            api_key,
+            api_secret,
+            passphrase,
+            metrics: Arc::new(AccountMetrics::for_exchange(Exchange::Okx)),
+            account_type,
+        };
+        let url = if use_test {
+            TEST_PRIVATE_WS_ENDPOINT
+        } else {
+            PRIVATE_WS_ENDPOINT
+        };
+        let addr = DefaultWsActor::new(
+            "OkxAccountStream",
+            Url::from_str(url)?,
+            WsReconnectOptions::builder()
+                .conn_timeout(Duration::from_secs(30))
+                .build(),
+            Arc::new(api),
+        )
+        .await?;
+
+        Ok(BotWrapper::new(addr, UnboundedReceiverStream::new(rx)))
+    }
+
+    /// See https://www.okx.com/docs-v5/en/#overview-websocket-login
+    fn login(&self) -> WsRequest<WsLogin> {
+        let timestamp = (get_unix_timestamp_ms() / 1000).to_string();
+        let sign = sign(&self.api_secret, &format!("{}GET/users/self/verify", timestamp));
+        WsRequest {
+            op: "login",
+            args: vec![WsLogin {
+                api_key: self.api_key.clone(),
+                passphrase: self.passphrase.clone(),
+                timestamp,
+                sign,
+            }],
+        }
+    }
+
+    fn subscription(&self) -> WsRequest<WsArg> {
+        let inst_type = match self.account_type {
+            AccountType::Spot => "SPOT",
+            _ => "MARGIN",
+        };
+        WsRequest {
+            op: "subscribe",
+            args: vec![WsArg {
+                channel: "orders".to_string(),
+                inst_id: None,
+                inst_type: Some(inst_type.to_string()),
+            }],
+        }
+    }
+
+    fn broadcast(&self, v: AccountEvent) {
+        if self
+            .sink
+            .send(AccountEventEnveloppe {
+                xchg: Exchange::Okx,
+                event: v,
+                account_type: self.account_type.clone(),
+                account: None,
+            })
+            .is_err()
+        {
+            self.metrics.send_error();
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl WsHandler for OkxStreamingAccountApi {
+    #[cfg_attr(feature = "flame", flame)]
+    fn handle_in(&self, w: &mut SinkWrite<Message, WsFramedSink>, msg: Bytes) {
+        match deserialize_json_s::<WsMessage>(msg.as_ref()) {
+            Err(err) => {
+                debug!(err = ?err, msg = ?msg, "okx stream deserialization error");
+            }
+            Ok(WsMessage::Event { event, code, msg }) => match event.as_str() {
+                "login" if code.as_deref() == Some("0") => {
+                    let subscription = serde_json::to_string(&self.subscription()).unwrap();
+                    if w.write(Message::Text(subscription.into())).is_err() {
+                        error!("okx failed to subscribe to account orders");
+                    }
+                }
+                "error" => error!(code = ?code, msg = ?msg, "okx account stream error"),
+                _ => {}
+            },
+            Ok(WsMessage::Push { arg, data, .. }) if arg.channel == "orders" => {
+                for v in data {
+                    match serde_json::from_value::<OkxOrder>(v) {
+                        Ok(order) => self.broadcast(AccountEvent::OrderUpdate(from_okx_order_update(order))),
+                        Err(err) => debug!(err = ?err, "okx order update deserialization error"),
+                    }
+                }
+            }
+            Ok(WsMessage::Push { .. }) => {}
+        }
+    }
+
+    fn handle_started(&self, w: &mut SinkWrite<Message, WsFramedSink>) {
+        self.metrics.stream_reconnected();
+        let login = serde_json::to_string(&self.login()).unwrap();
+        if w.write(Message::Text(login.into())).is_err() {
+            error!("okx failed to login to the account stream");
+        }
+    }
+}
@@ -0,0 +1,443 @@
+use chrono::{Duration, TimeZone, Utc};
+use stats::kline::{Resolution, TimeUnit};
+
+use broker_core::error::Error;
+use broker_core::pair::{symbol_to_pair, PairConf};
+use broker_core::prelude::*;
+use broker_core::types::*;
+
+use crate::models::*;
+
+/// OKX client order ids are alphanumeric with at most 32 characters, dashes are stripped from uuids
+pub fn client_order_id(order_id: &str) -> String {
+    order_id.chars().filter(char::is_ascii_alphanumeric).take(32).collect()
+}
+
+pub fn to_okx_side(side: TradeType) -> OkxSide {
+    match side {
+        TradeType::Buy => OkxSide::Buy,
+        TradeType::Sell => OkxSide::Sell,
+    }
+}
+
+pub fn from_okx_side(side: OkxSide) -> TradeType {
+    match side {
+        OkxSide::Buy => TradeType::Buy,
+        OkxSide::Sell => TradeType::Sell,
+    }
+}
+
+fn to_okx_trade_mode(asset_type: AssetType) -> Result<&'static str, Error> {
+    match asset_type {
+        AssetType::Spot => Ok("cash"),
+        AssetType::Margin => Ok("cross"),
+        AssetType::IsolatedMargin => Ok("isolated"),
+        _ => Err(Error::BrokerFeatureNotImplemented),
+    }
+}
+
+fn to_okx_order_type(request: &AddOrderRequest) -> Result<&'static str, Error> {
+    match (request.effective_order_type(), request.enforcement) {
+        (OrderType::Market, _) => Ok("market"),
+        (OrderType::LimitMaker, _) => Ok("post_only"),
+        (OrderType::Limit, Some(OrderEnforcement::FOK)) => Ok("fok"),
+        (OrderType::Limit, Some(OrderEnforcement::IOC)) => Ok("ioc"),
+        (OrderType::Limit, _) => Ok("limit"),
+        _ => Err(Error::BrokerFeatureNotImplemented),
+    }
+}
+
+pub fn from_okx_order_type(ord_type: &str) -> (OrderType, OrderEnforcement) {
+    match ord_type {
+        "market" => (OrderType::Market, OrderEnforcement::GTC),
+        "post_only" => (OrderType::LimitMaker, OrderEnforcement::GTC),
+        "fok" => (OrderType::Limit, OrderEnforcement::FOK),
+        "ioc" | "optimal_limit_ioc" => (OrderType::Limit, OrderEnforcement::IOC),
+        _ => (OrderType::Limit, OrderEnforcement::GTC),
+    }
+}
+
+pub fn from_okx_order_status(state: &str) -> OrderStatus {
+    match state {
+        "partially_filled" => OrderStatus::PartiallyFilled,
+        "filled" => OrderStatus::Filled,
+        "canceled" | "mmp_canceled" => OrderStatus::Canceled,
+        _ => OrderStatus::New,
+    }
+}
+
+fn is_working(state: &str) -> bool { matches!(state, "live" | "partially_filled") }
+
+/// Spot market orders are sized in base currency, unless only a quote quantity is requested
+pub fn to_okx_order_request(request: &AddOrderRequest, pair_conf: &PairConf) -> Result<OkxOrderRequest, Error> {
+    let td_mode = to_okx_trade_mode(request.asset_type.unwrap_or(AssetType::Spot))?;
+    let ord_type = to_okx_order_type(request)?;
+    let is_spot_market = ord_type == "market" && td_mode == "cash";
+    let (sz, tgt_ccy) = match (request.quantity, request.quote_order_qty) {
+        (Some(qty), _) => (qty, is_spot_market.then(|| "base_ccy".to_string())),
+        (None, Some(quote_qty)) if is_spot_market => (quote_qty, Some("quote_ccy".to_string())),
+        _ => return Err(Error::InvalidQty),
+    };
+    Ok(OkxOrderRequest {
+        inst_id: pair_conf.symbol.to_string(),
+        td_mode: td_mode.to_string(),
+        cl_ord_id: client_order_id(&request.order_id),
+        side: to_okx_side(request.side),
+        ord_type: ord_type.to_string(),
+        sz: sz.to_string(),
+        px: request.price.filter(|_| ord_type != "market").map(|p| p.to_string()),
+        tgt_ccy,
+    })
+}
+
+pub fn to_okx_amend_request(request: &AmendOrderRequest, pair_conf: &PairConf) -> OkxAmendRequest {
+    OkxAmendRequest {
+        inst_id: pair_conf.symbol.to_string(),
+        cl_ord_id: client_order_id(&request.order_id),
+        new_sz: request.quantity.map(|q| q.to_string()),
+        new_px: request.price.map(|p| p.to_string()),
+    }
+}
+
+/// OKX only acknowledges the order, the submission is new until the order is queried
+pub fn from_okx_order_ack(ack: OkxOrderAck, request: &AddOrderRequest) -> OrderSubmission {
+    OrderSubmission {
+        timestamp: get_unix_timestamp_ms(),
+        id: ack.ord_id,
+        pair: request.pair.clone(),
+        client_id: request.order_id.clone(),
+        price: request.price.unwrap_or(0.0),
+        qty: request.quantity.unwrap_or(0.0),
+        status: OrderStatus::New,
+        enforcement: request.enforcement.unwrap_or(OrderEnforcement::GTC),
+        order_type: request.order_type,
+        side: request.side,
+        asset_type: request.asset_type.unwrap_or(AssetType::Spot),
+        ..OrderSubmission::default()
+    }
+}
+
+#[allow(clippy::cast_sign_loss)]
+pub fn from_okx_order(o: OkxOrder, asset_type: AssetType) -> Order {
+    let symbol = o.inst_id.into();
+    let (order_type, enforcement) = from_okx_order_type(&o.ord_type);
+    Order {
+        xch: Exchange::Okx,
+        symbol: symbol_to_pair(&Exchange::Okx, &symbol).unwrap_or(symbol),
+        order_id: o.ord_id,
+        orig_order_id: o.cl_ord_id,
+        price: o.px,
+        orig_qty: o.sz,
+        executed_qty: o.acc_fill_sz,
+        cumulative_quote_qty: o.acc_fill_sz * o.avg_px,
+        status: from_okx_order_status(&o.state),
+        enforcement,
+        order_type,
+        side: from_okx_side(o.side),
+        stop_price: 0.0,
+        iceberg_qty: 0.0,
+        orig_time: o.c_time as u64,
+        last_event_time: o.u_time as u64,
+        is_in_transaction: is_working(&o.state),
+        orig_quote_order_qty: if o.tgt_ccy == "quote_ccy" { o.sz } else { 0.0 },
+        asset_type,
+    }
+}
+
+#[allow(clippy::cast_sign_loss)]
+pub fn from_okx_order_update(o: OkxOrder) -> OrderUpdate {
+    let (_, enforcement) = from_okx_order_type(&o.ord_type);
+    let status = from_okx_order_status(&o.state);
+    OrderUpdate {
+        enforcement,
+        side: from_okx_side(o.side),
+        orig_order_id: Some(o.cl_ord_id),
+        order_id: o.ord_id.parse().unwrap_or_default(),
+        symbol: o.inst_id,
+        timestamp: o.u_time as u64,
+        new_status: status.clone(),
+        orig_status: status,
+        is_on_the_book: is_working(&o.state),
+        qty: o.sz,
+        quote_qty: 0.0,
+        price: o.px,
+        stop_price: 0.0,
+        iceberg_qty: 0.0,
+        commission: -o.fee,
+        commission_asset: Some(o.fee_ccy).filter(|ccy| !ccy.is_empty()),
+        last_executed_qty: o.fill_sz,
+        cummulative_filled_qty: o.acc_fill_sz,
+        last_executed_price: o.fill_px,
+        cummulative_quote_asset_transacted_qty: o.acc_fill_sz * o.avg_px,
+        last_quote_asset_transacted_qty: o.fill_sz * o.fill_px,
+        quote_order_qty: if o.tgt_ccy == "quote_ccy" { o.sz } else { 0.0 },
+        rejection_reason: None,
+    }
+}
+
+pub fn from_okx_fill(f: OkxFill, pair: Pair) -> TradeFill {
+    TradeFill {
+        id: f.trade_id,
+        order_id: f.ord_id,
+        pair,
+        price: f.fill_px,
+        qty: f.fill_sz,
+        fee: -f.fee,
+        fee_asset: f.fee_ccy.into(),
+        side: from_okx_side(f.side),
+        is_maker: f.exec_type == "M",
+        time: f.ts,
+    }
+}
+
+pub fn from_okx_balances(b: OkxAccountBalance) -> AccountPosition {
+    let mut balances = AccountPosition::new();
+    balances.update_time = Utc.timestamp_millis_opt(b.u_time).unwrap();
+    for detail in b.details {
+        balances.insert(detail.ccy.into(), Balance {
+            free: detail.avail_bal,
+            locked: detail.frozen_bal,
+        });
+    }
+    balances
+}
+
+/// Order book levels as offers, malformed levels are skipped
+pub fn from_okx_levels(levels: &[Vec<String>]) -> Vec<Offer> {
+    levels
+        .iter()
+        .filter_map(|level| match (level.first(), level.get(1)) {
+            (Some(px), Some(sz)) => Some((px.parse().ok()?, sz.parse().ok()?)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// The `(price, size)` strings of levels, which okx checksums are computed on
+pub fn okx_raw_levels(levels: &[Vec<String>]) -> impl Iterator<Item = (String, String)> + '_ {
+    levels
+        .iter()
+        .filter_map(|level| Some((level.first()?.clone(), level.get(1)?.clone())))
+}
+
+pub fn from_okx_trade(t: &OkxTrade, pair: Pair) -> Trade {
+    let side = from_okx_side(t.side);
+    Trade {
+        event_ms: t.ts,
+        pair,
+        amount: t.sz,
+        price: t.px,
+        tt: side,
+        aggressor: Some(side),
+    }
+}
+
+pub fn from_okx_candle(c: &OkxCandle, pair: Pair, resolution: Resolution) -> Candle {
+    let start_time = Utc.timestamp_millis_opt(c.0).unwrap();
+    Candle {
+        event_time: Utc.timestamp_millis_opt(get_unix_timestamp_ms()).unwrap(),
+        pair,
+        start_time,
+        end_time: resolution.add(start_time) - Duration::milliseconds(1),
+        open: c.1,
+        high: c.2,
+        low: c.3,
+        close: c.4,
+        volume: c.5,
+        quote_volume: c.7,
+        trade_count: 0,
+        is_final: c.8 == "1",
+    }
+}
+
+/// The candle channel for a resolution, one minute by default.
+/// Candles of six hours or more are aligned on UTC rather than Hong Kong time.
+pub fn candle_channel(resolution: Option<Resolution>) -> Option<String> {
+    let Some(resolution) = resolution else {
+        return Some("candle1m".to_string());
+    };
+    let bar = match (resolution.time_unit, resolution.units) {
+        (TimeUnit::Second, 1) => "s",
+        (TimeUnit::Minute, 1 | 3 | 5 | 15 | 30) => "m",
+        (TimeUnit::Hour, 1 | 2 | 4) => "H",
+        (TimeUnit::Hour, 6 | 12) => "Hutc",
+        (TimeUnit::Day, 1 | 2 | 3 | 5) => "Dutc",
+        (TimeUnit::Week, 1) => "Wutc",
+        (TimeUnit::Month, 1 | 3) => "Mutc",
+        _ => return None,
+    };
+    Some(format!("candle{}{}", resolution.units, bar))
+}
+
+/// Candles are streamed on the business endpoint, other channels on the public endpoint
+pub fn is_business_channel(channel: &str) -> bool { channel.starts_with("candle") }
+
+/// The subscription argument of a market channel, `None` if OKX does not stream it
+pub fn subscription(c: &MarketChannel, inst_id: &str) -> Option<WsArg> {
+    let channel = match c.r#type {
+        MarketChannelType::Trades => "trades".to_string(),
+        MarketChannelType::Orderbooks | MarketChannelType::Quotes | MarketChannelType::QuotesCandles => {
+            match c.orderbook.unwrap_or_default() {
+                OrderbookConf {
+                    level: OrderbookLevel::Level1,
+                    ..
+                } => "bbo-tbt".to_string(),
+                OrderbookConf { depth: Some(depth), .. } if depth <= 5 => "books5".to_string(),
+                _ => "books".to_string(),
+            }
+        }
+        MarketChannelType::Candles => candle_channel(c.resolution)?,
+        MarketChannelType::OpenInterest | MarketChannelType::FundingRate | MarketChannelType::OrderbookL3 => {
+            return None
+        }
+    };
+    Some(WsArg {
+        channel,
+        inst_id: Some(inst_id.to_string()),
+        inst_type: None,
+    })
+}
+
+pub fn from_okx_error(code: &str, msg: &str) -> Error {
+    match code {
+        "50105" | "50111" | "50112" | "50113" | "50114" => Error::BadCredentials,
+        "50011" | "50061" => Error::RateLimitExceeded,
+        "50001" | "50013" => Error::ServiceUnavailable(msg.to_string()),
+        "51001" => Error::PairUnsupported,
+        "51008" => Error::InsufficientFunds,
+        "51020" => Error::InsufficientOrderSize,
+        "51603" => Error::NotFound,
+        _ => Error::ExchangeSpecificError(format!("{} : {}", code, msg)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use stats::kline::{Resolution, TimeUnit};
+
+    use broker_core::pair::PairConf;
+    use broker_core::types::*;
+
+    use crate::adapters::{candle_channel, client_order_id, from_okx_levels, subscription, to_okx_amend_request,
+                          to_okx_order_request};
+    use crate::models::WsMessage;
+
+    fn pair_conf() -> PairConf {
+        PairConf {
+            base: "BTC".to_string(),
+            quote: "USDT".to_string(),
+            symbol: "BTC-USDT".into(),
+            pair: "BTC_USDT".into(),
+            ..PairConf::default()
+        }
+    }
+
+    #[test]
+    fn amend_requests_only_carry_changes() {
+        let request = AmendOrderRequest {
+            pair: "BTC_USDT".into(),
+            order_id: "a-b".to_string(),
+            price: Some(101.5),
+            ..AmendOrderRequest::default()
+        };
+        let json = serde_json::to_string(&to_okx_amend_request(&request, &pair_conf())).unwrap();
+        assert_eq!(json, r#"{"instId":"BTC-USDT","clOrdId":"ab","newPx":"101.5"}"#);
+    }
+
+    #[test]
+    fn client_order_ids_are_alphanumeric() {
+        let id = client_order_id("5f0b3c2e-9d1a-4b7c-8e6f-0a1b2c3d4e5f");
+        assert_eq!(id, "5f0b3c2e9d1a4b7c8e6f0a1b2c3d4e5f");
+    }
+
+    #[test]
+    fn order_requests() {
+        let market = AddOrderRequest {
+            pair: "BTC_USDT".into(),
+            order_type: OrderType::Market,
+            side: TradeType::Buy,
+            quantity: Some(0.5),
+            price: Some(100.0),
+            ..AddOrderRequest::default()
+        };
+        let request = to_okx_order_request(&market, &pair_conf()).unwrap();
+        assert_eq!(request.inst_id, "BTC-USDT");
+        assert_eq!(request.td_mode, "cash");
+        assert_eq!(request.ord_type, "market");
+        assert_eq!(request.sz, "0.5");
+        assert_eq!(request.px, None);
+        assert_eq!(request.tgt_ccy.as_deref(), Some("base_ccy"));
+
+        let post_only = AddOrderRequest {
+            order_type: OrderType::Limit,
+            post_only: true,
+            asset_type: Some(AssetType::Margin),
+            ..market.clone()
+        };
+        let request = to_okx_order_request(&post_only, &pair_conf()).unwrap();
+        assert_eq!(request.td_mode, "cross");
+        assert_eq!(request.ord_type, "post_only");
+        assert_eq!(request.px.as_deref(), Some("100"));
+        assert_eq!(request.tgt_ccy, None);
+
+        let quote_qty = AddOrderRequest {
+            quantity: None,
+            quote_order_qty: Some(50.0),
+            ..market
+        };
+        let request = to_okx_order_request(&quote_qty, &pair_conf()).unwrap();
+        assert_eq!(request.sz, "50");
+        assert_eq!(request.tgt_ccy.as_deref(), Some("quote_ccy"));
+    }
+
+    #[test]
+    fn candle_channels() {
+        assert_eq!(candle_channel(None).as_deref(), Some("candle1m"));
+        assert_eq!(
+            candle_channel(Some(Resolution::new(TimeUnit::Minute, 15))).as_deref(),
+            Some("candle15m")
+        );
+        assert_eq!(
+            candle_channel(Some(Resolution::new(TimeUnit::Hour, 12))).as_deref(),
+            Some("candle12Hutc")
+        );
+        assert_eq!(candle_channel(Some(Resolution::new(TimeUnit::Minute, 7))), None);
+    }
+
+    #[test]
+    fn book_subscriptions() {
+        let channel = MarketChannel::builder()
+            .symbol(Symbol::new("BTC_USDT".into(), SecurityType::Crypto, Exchange::Okx))
+            .r#type(MarketChannelType::Orderbooks)
+            .orderbook(Some(OrderbookConf {
+                depth: Some(5),
+                level: OrderbookLevel::Level2,
+            }))
+            .build();
+        assert_eq!(subscription(&channel, "BTC-USDT").unwrap().channel, "books5");
+        let deep = MarketChannel {
+            orderbook: Some(OrderbookConf {
+                depth: Some(50),
+                level: OrderbookLevel::Level2,
+            }),
+            ..channel
+        };
+        assert_eq!(subscription(&deep, "BTC-USDT").unwrap().channel, "books");
+    }
+
+    #[test]
+    fn parse_book_push() {
+        let msg = r#"{"arg":{"channel":"books5","instId":"BTC-USDT"},"data":[{"asks":[["8446","95","0","3"]],"bids":[["8445.9","12","0","1"]],"instId":"BTC-USDT","ts":"1597026383085"}]}"#;
+        let WsMessage::Push { arg, data, .. } = serde_json::from_str(msg).unwrap() else {
+            panic!("expected a push");
+        };
+        assert_eq!(arg.channel, "books5");
+        let book: crate::models::OkxOrderBook = serde_json::from_value(data[0].clone()).unwrap();
+        assert_eq!(from_okx_levels(&book.asks), vec![(8446.0, 95.0)]);
+        assert_eq!(from_okx_levels(&book.bids), vec![(8445.9, 12.0)]);
+        assert_eq!(book.ts, 1_597_026_383_085);
+
+        let event = r#"{"event":"subscribe","arg":{"channel":"books5","instId":"BTC-USDT"},"connId":"a4d3ae55"}"#;
+        assert!(matches!(serde_json::from_str(event).unwrap(), WsMessage::Event { .. }));
+    }
+}
@@ -0,0 +1,264 @@
+//! Use this module to interact with OKX exchange.
+//! Please see examples for more informations.
+
+use chrono::{SecondsFormat, Utc};
+use data_encoding::BASE64;
+use hmac::{Hmac, Mac};
+use reqwest::{Client, Method, RequestBuilder};
+use serde::de::DeserializeOwned;
+use sha2::Sha256;
+use url::Url;
+
+use broker_core::error::*;
+use broker_core::prelude::*;
+
+use super::adapters::from_okx_error;
+use super::models::*;
+
+static REST_ENDPOINT: &str = "https://www.okx.com";
+
+/// Routes requests to the demo trading environment
+static SIMULATED_TRADING_HEADER: &str = "x-simulated-trading";
+
+#[derive(Debug, Clone)]
+pub struct OkxApi {
+    pub(super) api_key: Option<String>,
+    pub(super) api_secret: Option<String>,
+    pub(super) passphrase: Option<String>,
+    /// Whether requests are sent to the demo trading environment
+    pub(super) use_test: bool,
+    client: Client,
+}
+
+/// Sign a request, see https://www.okx.com/docs-v5/en/#overview-rest-authentication-signature
+pub(crate) fn sign(api_secret: &str, prehash: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(api_secret.as_bytes()).expect("HMAC can take key of any size");
+    mac.update(prehash.as_bytes());
+    BASE64.encode(&mac.finalize().into_bytes())
+}
+
+impl<T> OkxResponse<T> {
+    fn into_result(self) -> Result<Vec<T>> {
+        if self.code == "0" {
+            Ok(self.data)
+        } else {
+            Err(from_okx_error(&self.code, &self.msg))
+        }
+    }
+}
+
+impl OkxApi {
+    /// Create a new OkxApi by providing an API key, API secret and passphrase
+    pub fn new(creds: &dyn Credentials) -> Result<OkxApi> { Self::new_with_env(creds, false) }
+
+    /// Create a new OkxApi pointing to the demo trading environment
+    pub fn new_test(creds: &dyn Credentials) -> Result<OkxApi> { Self::new_with_env(creds, true) }
+
+    fn new_with_env(creds: &dyn Credentials, use_test: bool) -> Result<OkxApi> {
+        if creds.exchange() != Exchange::Okx {
+            return Err(Error::InvalidConfigType {
+                expected: Exchange::Okx,
+                find: creds.exchange(),
+            });
+        }
+
+        Ok(OkxApi {
+            api_key: creds.get("api_key").filter(|s| !s.is_empty()),
+            api_secret: creds.get("api_secret").filter(|s| !s.is_empty()),
+            passphrase: creds.get("passphrase").filter(|s| !s.is_empty()),
+            use_test,
+            client: Client::new(),
+        })
+    }
+
+    fn with_env(&self, request: RequestBuilder) -> RequestBuilder {
+        if self.use_test {
+            request.header(SIMULATED_TRADING_HEADER, "1")
+        } else {
+            request
+        }
+    }
+
+    async fn public_query<T: DeserializeOwned>(&self, path: &str, query: &[(&str, String)]) -> Result<Vec<T>> {
+        let request = self.client.get(format!("{}{}", REST_ENDPOINT, path)).query(query);
+        let response: OkxResponse<T> = self.with_env(request).send().await?.json().await?;
+        response.into_result()
+    }
+
+    async fn private_response<T: DeserializeOwned>(
+        &self,
+        method: Method,
+        path: &str,
+        query: &[(&str, String)],
+        body: Option<String>,
+    ) -> Result<OkxResponse<T>> {
+        let (Some(api_key), Some(api_secret), Some(passphrase)) = (&self.api_key, &self.api_secret, &self.passphrase)
+        else {
+            return Err(Error::MissingCredentials(
+                "okx api_key, api_secret or passphrase".to_string(),
+            ));
+        };
+        let mut url = Url::parse(&format!("{}{}", REST_ENDPOINT, path))?;
+        if !query.is_empty() {
+            url.query_pairs_mut().extend_pairs(query);
+        }
+        // The signed path includes the query string
+        let request_path = match url.query() {
+            Some(q) => format!("{}?{}", path, q),
+            None => path.to_string(),
+        };
+        let timestamp = Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true);
+        let body = body.unwrap_or_default();
+        let signature = sign(
+            api_secret,
+            &format!("{}{}{}{}", timestamp, method.as_str(), request_path, body),
+        );
+        let request = self
+            .client
+            .request(method, url)
+            .header("OK-ACCESS-KEY", api_key)
+            .header("OK-ACCESS-SIGN", signature)
+            .header("OK-ACCESS-TIMESTAMP", timestamp)
+            .header("OK-ACCESS-PASSPHRASE", passphrase)
+            .header("Content-Type", "application/json")
+            .body(body);
+        Ok(self.with_env(request).send().await?.json().await?)
+    }
+
+    async fn private_query<T: DeserializeOwned>(
+        &self,
+        method: Method,
+        path: &str,
+        query: &[(&str, String)],
+        body: Option<String>,
+    ) -> Result<Vec<T>> {
+        self.private_response(method, path, query, body).await?.into_result()
+    }
+
+    pub async fn market_ticker(&self, inst_id: &str) -> Result<OkxTicker> {
+        let tickers = self
+            .public_query("/api/v5/market/ticker", &[("instId", inst_id.to_string())])
+            .await?;
+        tickers.into_iter().next().ok_or(Error::NotFound)
+    }
+
+    pub async fn market_tickers(&self) -> Result<Vec<OkxTicker>> {
+        self.public_query("/api/v5/market/tickers", &[("instType", "SPOT".to_string())])
+            .await
+    }
+
+    pub async fn order_book(&self, inst_id: &str, depth: u16) -> Result<OkxOrderBook> {
+        let books = self
+            .public_query("/api/v5/market/books", &[
+                ("instId", inst_id.to_string()),
+                ("sz", depth.to_string()),
+            ])
+            .await?;
+        books.into_iter().next().ok_or(Error::NotFound)
+    }
+
+    pub async fn market_trades(&self, inst_id: &str) -> Result<Vec<OkxTrade>> {
+        self.public_query("/api/v5/market/trades", &[("instId", inst_id.to_string())])
+            .await
+    }
+
+    pub async fn instruments(&self) -> Result<Vec<OkxInstrument>> {
+        self.public_query("/api/v5/public/instruments", &[("instType", "SPOT".to_string())])
+            .await
+    }
+
+    pub async fn maintenances(&self) -> Result<Vec<OkxMaintenance>> {
+        self.public_query("/api/v5/system/status", &[]).await
+    }
+
+    pub async fn balances(&self) -> Result<OkxAccountBalance> {
+        let balances = self
+            .private_query(Method::GET, "/api/v5/account/balance", &[], None)
+            .await?;
+        balances.into_iter().next().ok_or(Error::NotFound)
+    }
+
+    /// Place an order, OKX reports rejections in the acknowledgement of the order
+    pub async fn place_order(&self, request: &OkxOrderRequest) -> Result<OkxOrderAck> {
+        let body = serde_json::to_string(request)?;
+        let response: OkxResponse<OkxOrderAck> = self
+            .private_response(Method::POST, "/api/v5/trade/order", &[], Some(body))
+            .await?;
+        match response.data.into_iter().next() {
+            Some(ack) if ack.s_code == "0" => Ok(ack),
+            Some(ack) => Err(from_okx_error(&ack.s_code, &ack.s_msg)),
+            None => Err(from_okx_error(&response.code, &response.msg)),
+        }
+    }
+
+    /// Amend the price or size of a resting order, rejections are reported like for placed orders
+    pub async fn amend_resting_order(&self, request: &OkxAmendRequest) -> Result<OkxOrderAck> {
+        let body = serde_json::to_string(request)?;
+        let response: OkxResponse<OkxOrderAck> = self
+            .private_response(Method::POST, "/api/v5/trade/amend-order", &[], Some(body))
+            .await?;
+        match response.data.into_iter().next() {
+            Some(ack) if ack.s_code == "0" => Ok(ack),
+            Some(ack) => Err(from_okx_error(&ack.s_code, &ack.s_msg)),
+            None => Err(from_okx_error(&response.code, &response.msg)),
+        }
+    }
+
+    /// Cancel a resting order, rejections are reported like for placed orders
+    pub async fn cancel_resting_order(&self, request: &OkxCancelRequest) -> Result<OkxOrderAck> {
+        let body = serde_json::to_string(request)?;
+        let response: OkxResponse<OkxOrderAck> = self
+            .private_response(Method::POST, "/api/v5/trade/cancel-order", &[], Some(body))
+            .await?;
+        match response.data.into_iter().next() {
+            Some(ack) if ack.s_code == "0" => Ok(ack),
+            Some(ack) => Err(from_okx_error(&ack.s_code, &ack.s_msg)),
+            None => Err(from_okx_error(&response.code, &response.msg)),
+        }
+    }
+
+    /// Cancel every order once `timeout_secs` have elapsed unless called again, 0 disables the timer
+    pub async fn cancel_all_orders_after(&self, timeout_secs: u64) -> Result<OkxCancelAllAfter> {
+        let body = serde_json::to_string(&OkxCancelAllAfterRequest {
+            time_out: timeout_secs.to_string(),
+        })?;
+        let timers = self
+            .private_query(Method::POST, "/api/v5/trade/cancel-all-after", &[], Some(body))
+            .await?;
+        timers.into_iter().next().ok_or(Error::NotFound)
+    }
+
+    pub async fn order_details(&self, inst_id: &str, cl_ord_id: &str) -> Result<OkxOrder> {
+        let orders = self
+            .private_query(
+                Method::GET,
+                "/api/v5/trade/order",
+                &[("instId", inst_id.to_string()), ("clOrdId", cl_ord_id.to_string())],
+                None,
+            )
+            .await?;
+        orders.into_iter().next().ok_or(Error::NotFound)
+    }
+
+    /// Account fills of the last three days, since `begin` in ms if set
+    pub async fn fills(&self, inst_id: &str, begin: Option<i64>) -> Result<Vec<OkxFill>> {
+        let mut query = vec![("instType", "SPOT".to_string()), ("instId", inst_id.to_string())];
+        if let Some(begin) = begin {
+            query.push(("begin", begin.to_string()));
+        }
+        self.private_query(Method::GET, "/api/v5/trade/fills", &query, None)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn should_create_a_correct_signature() {
+        let prehash = "2020-12-08T09:08:57.715ZGET/api/v5/account/balance?ccy=BTC";
+        assert_eq!(
+            super::sign("22582BD0CFF14C41EDBF1AB98506286D", prehash),
+            "HiZhvSfMtWJA3uUIVXV3a/bSXNPCWvYFXoGCVS8V4zY="
+        );
+    }
+}
@@ -0,0 +1,191 @@
+//! Use this module to interact with OKX through a Generic API.
+//! This a more convenient and safe way to deal with the exchange since methods return a Result<>
+//! but this generic API does not provide all the functionnality that OKX offers.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use stats::kline::{Resolution, TimeUnit};
+
+use broker_core::error::*;
+use broker_core::pair::{pair_string, PairConf};
+use broker_core::prelude::*;
+use broker_core::types::*;
+
+use super::adapters::*;
+use super::api::OkxApi;
+use super::models::OkxCancelRequest;
+
+/// Depth of order books fetched with the REST API
+const ORDERBOOK_DEPTH: u16 = 20;
+
+#[async_trait]
+impl Brokerage for OkxApi {
+    async fn ticker(&self, pair: Pair) -> Result<Ticker> {
+        let inst_id = pair_string(Exchange::Okx, &pair)?;
+        let ticker = self.market_ticker(&inst_id).await?;
+        Ok(Ticker {
+            timestamp: ticker.ts,
+            pair,
+            last_trade_price: ticker.last,
+            lowest_ask: ticker.ask_px,
+            highest_bid: ticker.bid_px,
+            volume: Some(ticker.vol_24h),
+        })
+    }
+
+    async fn tickers(&self, pairs: Vec<Pair>) -> Result<HashMap<Pair, f64>> {
+        let mut symbols: HashMap<String, Pair> = pairs
+            .into_iter()
+            .filter_map(|pair| pair_string(Exchange::Okx, &pair).ok().map(|symbol| (symbol, pair)))
+            .collect();
+        if symbols.is_empty() {
+            return Ok(HashMap::new());
+        }
+        let tickers = self.market_tickers().await?;
+        Ok(tickers
+            .into_iter()
+            .filter_map(|ticker| symbols.remove(&ticker.inst_id).map(|pair| (pair, ticker.last)))
+            .collect())
+    }
+
+    async fn orderbook(&self, pair: Pair) -> Result<Orderbook> {
+        let inst_id = pair_string(Exchange::Okx, &pair)?;
+        let book = self.order_book(&inst_id, ORDERBOOK_DEPTH).await?;
+        Ok(Orderbook {
+            timestamp: book.ts,
+            pair,
+            asks: from_okx_levels(&book.asks),
+            bids: from_okx_levels(&book.bids),
+            last_order_id: None,
+        })
+    }
+
+    async fn add_order(&self, order: AddOrderRequest) -> Result<OrderSubmission> {
+        let pair_conf = broker_core::pair::pair_conf(&Exchange::Okx, &order.pair)?;
+        if order.order_type == OrderType::Limit && order.price.is_none() {
+            return Err(Error::MissingPrice);
+        }
+        let request = to_okx_order_request(&order, &pair_conf)?;
+        // OKX has no order test endpoint, use the demo trading environment to test orders against the exchange
+        if order.dry_run {
+            return Ok(order.simulate_submission(0.001));
+        }
+        let ack = self.place_order(&request).await?;
+        Ok(from_okx_order_ack(ack, &order))
+    }
+
+    async fn amend_order(&self, order: AmendOrderRequest) -> Result<OrderSubmission> {
+        let pair_conf = broker_core::pair::pair_conf(&Exchange::Okx, &order.pair)?;
+        let ack = self
+            .amend_resting_order(&to_okx_amend_request(&order, &pair_conf))
+            .await?;
+        Ok(order.acknowledge(ack.ord_id))
+    }
+
+    async fn cancel_order(&self, id: String, pair: Pair, _asset_type: AssetType) -> Result<()> {
+        let request = OkxCancelRequest {
+            inst_id: pair_string(Exchange::Okx, &pair)?,
+            cl_ord_id: client_order_id(&id),
+        };
+        self.cancel_resting_order(&request).await.map(|_| ())
+    }
+
+    async fn cancel_all_after(&self, timeout: Duration) -> Result<()> {
+        let timer = self.cancel_all_orders_after(timeout.as_secs()).await?;
+        trace!(trigger_time = timer.trigger_time, "armed dead man's switch");
+        Ok(())
+    }
+
+    /// Return the balances for each currency on the trading account
+    async fn account_balances(&self) -> Result<AccountPosition> { self.balances().await.map(from_okx_balances) }
+
+    async fn get_order(&self, id: String, pair: Pair, asset_type: AssetType) -> Result<Order> {
+        let inst_id = pair_string(Exchange::Okx, &pair)?;
+        let order = self.order_details(&inst_id, &client_order_id(&id)).await?;
+        Ok(from_okx_order(order, asset_type))
+    }
+
+    async fn pairs(&self) -> Result<Vec<PairConf>> {
+        let instruments = self.instruments().await?;
+        Ok(instruments
+            .into_iter()
+            .map(|i| PairConf {
+                pair: format!("{}_{}", i.base_ccy, i.quote_ccy).into(),
+                symbol: i.inst_id.into(),
+                base: i.base_ccy,
+                quote: i.quote_ccy,
+                step_price: Some(i.tick_sz),
+                min_qty: Some(i.min_sz),
+                max_qty: Some(i.max_lmt_sz),
+                step_qty: Some(i.lot_sz),
+                min_market_qty: Some(i.min_sz),
+                max_market_qty: Some(i.max_mkt_sz),
+                step_market_qty: Some(i.lot_sz),
+                spot_allowed: i.state == "live",
+                ..PairConf::default()
+            })
+            .collect())
+    }
+
+    fn exchange(&self) -> Exchange { Exchange::Okx }
+
+    fn uses_account(&self) -> bool { self.api_key.is_some() && self.api_secret.is_some() && self.passphrase.is_some() }
+
+    fn capabilities(&self) -> ExchangeCapabilities {
+        ExchangeCapabilities {
+            order_types: vec![OrderType::Limit, OrderType::Market, OrderType::LimitMaker],
+            post_only: true,
+            margin: true,
+            amend: true,
+            dead_man_switch: Some((10, 120)),
+            max_orderbook_depth: Some(400),
+            // Order placement per instrument
+            rate_limits: vec![RateLimit::new(60, 2000)],
+            resolutions: vec![
+                Resolution::new(TimeUnit::Second, 1),
+                Resolution::new(TimeUnit::Minute, 1),
+                Resolution::new(TimeUnit::Minute, 3),
+                Resolution::new(TimeUnit::Minute, 5),
+                Resolution::new(TimeUnit::Minute, 15),
+                Resolution::new(TimeUnit::Minute, 30),
+                Resolution::new(TimeUnit::Hour, 1),
+                Resolution::new(TimeUnit::Hour, 2),
+                Resolution::new(TimeUnit::Hour, 4),
+                Resolution::new(TimeUnit::Hour, 6),
+                Resolution::new(TimeUnit::Hour, 12),
+                Resolution::new(TimeUnit::Day, 1),
+                Resolution::new(TimeUnit::Day, 2),
+                Resolution::new(TimeUnit::Day, 3),
+                Resolution::new(TimeUnit::Day, 5),
+                Resolution::new(TimeUnit::Week, 1),
+                Resolution::new(TimeUnit::Month, 1),
+                Resolution::new(TimeUnit::Month, 3),
+            ],
+            ..ExchangeCapabilities::default()
+        }
+    }
+
+    async fn trade_history(&self, pair: Pair) -> Result<Vec<Trade>> {
+        let inst_id = pair_string(Exchange::Okx, &pair)?;
+        let trades = self.market_trades(&inst_id).await?;
+        Ok(trades.iter().map(|t| from_okx_trade(t, pair.clone())).collect())
+    }
+
+    async fn system_status(&self) -> Result<SystemStatus> {
+        let maintenances = self.maintenances().await?;
+        if maintenances.iter().any(|m| m.state == "ongoing") {
+            Ok(SystemStatus::Maintenance)
+        } else {
+            Ok(SystemStatus::Normal)
+        }
+    }
+
+    async fn my_trades(&self, pair: Pair, since: Option<DateTime<Utc>>) -> Result<Vec<TradeFill>> {
+        let inst_id = pair_string(Exchange::Okx, &pair)?;
+        let fills = self.fills(&inst_id, since.map(|t| t.timestamp_millis())).await?;
+        Ok(fills.into_iter().map(|f| from_okx_fill(f, pair.clone())).collect())
+    }
+}
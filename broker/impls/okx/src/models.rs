@@ -0,0 +1,307 @@
+//! Payloads of the OKX v5 REST and websocket APIs, numbers are sent as strings by OKX
+
+use serde::{Deserialize, Deserializer};
+use serde_json::Value;
+
+/// Deserialize a number sent as a string, empty strings are zero
+fn string_f64<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+    let s = String::deserialize(deserializer)?;
+    if s.is_empty() {
+        return Ok(0.0);
+    }
+    s.parse().map_err(serde::de::Error::custom)
+}
+
+/// Deserialize a timestamp in ms sent as a string, empty strings are zero
+fn string_i64<'de, D: Deserializer<'de>>(deserializer: D) -> Result<i64, D::Error> {
+    let s = String::deserialize(deserializer)?;
+    if s.is_empty() {
+        return Ok(0);
+    }
+    s.parse().map_err(serde::de::Error::custom)
+}
+
+/// Envelope of every REST response, `code` is "0" on success
+#[derive(Debug, Deserialize)]
+pub struct OkxResponse<T> {
+    pub code: String,
+    #[serde(default)]
+    pub msg: String,
+    #[serde(default = "Vec::new")]
+    pub data: Vec<T>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OkxTicker {
+    pub inst_id: String,
+    #[serde(deserialize_with = "string_f64")]
+    pub last: f64,
+    #[serde(deserialize_with = "string_f64")]
+    pub ask_px: f64,
+    #[serde(deserialize_with = "string_f64")]
+    pub bid_px: f64,
+    #[serde(rename = "vol24h", deserialize_with = "string_f64")]
+    pub vol_24h: f64,
+    #[serde(deserialize_with = "string_i64")]
+    pub ts: i64,
+}
+
+/// Order book levels are `[price, size, deprecated, order count]`
+#[derive(Debug, Deserialize)]
+pub struct OkxOrderBook {
+    pub asks: Vec<Vec<String>>,
+    pub bids: Vec<Vec<String>>,
+    #[serde(deserialize_with = "string_i64")]
+    pub ts: i64,
+    /// Signed CRC32 of the top 25 levels after the update, only sent by the `books` channels
+    #[serde(default)]
+    pub checksum: Option<i32>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OkxTrade {
+    pub inst_id: String,
+    pub trade_id: String,
+    #[serde(deserialize_with = "string_f64")]
+    pub px: f64,
+    #[serde(deserialize_with = "string_f64")]
+    pub sz: f64,
+    pub side: OkxSide,
+    #[serde(deserialize_with = "string_i64")]
+    pub ts: i64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OkxSide {
+    Buy,
+    Sell,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OkxInstrument {
+    pub inst_id: String,
+    pub base_ccy: String,
+    pub quote_ccy: String,
+    #[serde(deserialize_with = "string_f64")]
+    pub tick_sz: f64,
+    #[serde(deserialize_with = "string_f64")]
+    pub lot_sz: f64,
+    #[serde(deserialize_with = "string_f64")]
+    pub min_sz: f64,
+    #[serde(deserialize_with = "string_f64")]
+    pub max_lmt_sz: f64,
+    #[serde(deserialize_with = "string_f64")]
+    pub max_mkt_sz: f64,
+    /// `live`, `suspend`, `preopen` or `test`
+    pub state: String,
+}
+
+/// A scheduled system maintenance
+#[derive(Debug, Deserialize)]
+pub struct OkxMaintenance {
+    /// `scheduled`, `ongoing`, `pre_open`, `completed` or `canceled`
+    pub state: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OkxAccountBalance {
+    #[serde(deserialize_with = "string_i64")]
+    pub u_time: i64,
+    pub details: Vec<OkxAssetBalance>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OkxAssetBalance {
+    pub ccy: String,
+    #[serde(deserialize_with = "string_f64")]
+    pub avail_bal: f64,
+    #[serde(deserialize_with = "string_f64")]
+    pub frozen_bal: f64,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OkxOrderRequest {
+    pub inst_id: String,
+    /// `cash` for spot, `cross` or `isolated` for margin
+    pub td_mode: String,
+    pub cl_ord_id: String,
+    pub side: OkxSide,
+    /// `market`, `limit`, `post_only`, `fok` or `ioc`
+    pub ord_type: String,
+    pub sz: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub px: Option<String>,
+    /// Currency of `sz` for spot market orders, `base_ccy` or `quote_ccy`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tgt_ccy: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OkxAmendRequest {
+    pub inst_id: String,
+    pub cl_ord_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new_sz: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new_px: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OkxCancelRequest {
+    pub inst_id: String,
+    pub cl_ord_id: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OkxCancelAllAfterRequest {
+    /// Seconds, "0" disables the timer
+    pub time_out: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OkxCancelAllAfter {
+    /// Time at which every order is canceled in seconds, 0 if the timer is disabled
+    #[serde(deserialize_with = "string_i64")]
+    pub trigger_time: i64,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OkxOrderAck {
+    #[serde(default)]
+    pub ord_id: String,
+    #[serde(default)]
+    pub cl_ord_id: String,
+    pub s_code: String,
+    #[serde(default)]
+    pub s_msg: String,
+}
+
+/// Order details, from the REST API or the `orders` channel
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OkxOrder {
+    pub inst_id: String,
+    pub ord_id: String,
+    #[serde(default)]
+    pub cl_ord_id: String,
+    #[serde(deserialize_with = "string_f64")]
+    pub px: f64,
+    #[serde(deserialize_with = "string_f64")]
+    pub sz: f64,
+    pub ord_type: String,
+    pub side: OkxSide,
+    /// `live`, `partially_filled`, `filled`, `canceled` or `mmp_canceled`
+    pub state: String,
+    #[serde(deserialize_with = "string_f64")]
+    pub acc_fill_sz: f64,
+    #[serde(deserialize_with = "string_f64")]
+    pub avg_px: f64,
+    /// Last filled quantity, only set in the `orders` channel
+    #[serde(default, deserialize_with = "string_f64")]
+    pub fill_sz: f64,
+    /// Last filled price, only set in the `orders` channel
+    #[serde(default, deserialize_with = "string_f64")]
+    pub fill_px: f64,
+    /// Accumulated fee, negative when charged
+    #[serde(deserialize_with = "string_f64")]
+    pub fee: f64,
+    #[serde(default)]
+    pub fee_ccy: String,
+    #[serde(default)]
+    pub tgt_ccy: String,
+    #[serde(deserialize_with = "string_i64")]
+    pub c_time: i64,
+    #[serde(deserialize_with = "string_i64")]
+    pub u_time: i64,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OkxFill {
+    pub trade_id: String,
+    pub ord_id: String,
+    #[serde(deserialize_with = "string_f64")]
+    pub fill_px: f64,
+    #[serde(deserialize_with = "string_f64")]
+    pub fill_sz: f64,
+    pub side: OkxSide,
+    /// `T` for taker, `M` for maker
+    pub exec_type: String,
+    /// Fee, negative when charged
+    #[serde(deserialize_with = "string_f64")]
+    pub fee: f64,
+    pub fee_ccy: String,
+    #[serde(deserialize_with = "string_i64")]
+    pub ts: i64,
+}
+
+/// Candles are `[ts, open, high, low, close, vol, volCcy, volCcyQuote, confirm]`
+#[derive(Debug, Deserialize)]
+pub struct OkxCandle(
+    #[serde(deserialize_with = "string_i64")] pub i64,
+    #[serde(deserialize_with = "string_f64")] pub f64,
+    #[serde(deserialize_with = "string_f64")] pub f64,
+    #[serde(deserialize_with = "string_f64")] pub f64,
+    #[serde(deserialize_with = "string_f64")] pub f64,
+    #[serde(deserialize_with = "string_f64")] pub f64,
+    #[serde(deserialize_with = "string_f64")] pub f64,
+    #[serde(deserialize_with = "string_f64")] pub f64,
+    pub String,
+);
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WsArg {
+    pub channel: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inst_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inst_type: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WsRequest<T> {
+    pub op: &'static str,
+    pub args: Vec<T>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WsLogin {
+    pub api_key: String,
+    pub passphrase: String,
+    pub timestamp: String,
+    pub sign: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum WsMessage {
+    /// Acknowledgement of an operation or error, `event` is `subscribe`, `login` or `error`
+    Event {
+        event: String,
+        #[serde(default)]
+        code: Option<String>,
+        #[serde(default)]
+        msg: Option<String>,
+    },
+    /// Data pushed on a subscribed channel, order book channels set `action` to `snapshot` or `update`
+    Push {
+        arg: WsArg,
+        #[serde(default)]
+        action: Option<String>,
+        data: Vec<Value>,
+    },
+}
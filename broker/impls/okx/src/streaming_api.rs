@@ -0,0 +1,331 @@
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+use actix::io::SinkWrite;
+use actix::Addr;
+use async_trait::async_trait;
+use awc::ws::Message;
+use broker_core::bot::{DataStreamer, DefaultWsActor, WsFramedSink, WsHandler};
+use broker_core::broker::MarketEventEnvelopeRef;
+use broker_core::metrics::ExchangeMetrics;
+use bytes::Bytes;
+use dashmap::{DashMap, DashSet};
+use futures::StreamExt;
+use serde_json::Value;
+use stats::kline::{Resolution, TimeUnit};
+use tokio::sync::mpsc::{self, UnboundedSender};
+use tokio_stream::wrappers::UnboundedReceiverStream;
+use url::Url;
+
+use broker_core::error::*;
+use broker_core::json_util::deserialize_json_s;
+use broker_core::pair::{pair_to_symbol, symbol_to_pair};
+use broker_core::prelude::*;
+use broker_core::streaming_api::{LiveBookStream, MarketEventStream, StreamingApi, WsReconnectOptions};
+use broker_core::types::*;
+
+use super::adapters::*;
+use super::api::OkxApi;
+use super::models::*;
+
+static PUBLIC_WS_ENDPOINT: &str = "wss://ws.okx.com:8443/ws/v5/public";
+static BUSINESS_WS_ENDPOINT: &str = "wss://ws.okx.com:8443/ws/v5/business";
+static TEST_PUBLIC_WS_ENDPOINT: &str = "wss://wspap.okx.com:8443/ws/v5/public";
+static TEST_BUSINESS_WS_ENDPOINT: &str = "wss://wspap.okx.com:8443/ws/v5/business";
+
+/// Depth of the REST snapshots of books that failed their checksum, the most OKX serves
+const RESYNC_DEPTH: u16 = 400;
+
+/// A subscribed candle channel
+#[derive(Clone, Copy, Debug)]
+struct CandleConf {
+    resolution: Resolution,
+    only_final: bool,
+}
+
+#[derive(Clone)]
+pub struct OkxStreamingApi {
+    books: Arc<DashMap<Pair, LiveAggregatedOrderBook>>,
+    /// Books being snapshotted again after a checksum mismatch
+    resyncs: Arc<DashSet<Pair>>,
+    api: Arc<OkxApi>,
+    subscriptions: Vec<WsArg>,
+    /// Candle channels by channel name and instrument
+    candles: HashMap<(String, String), CandleConf>,
+    sink: UnboundedSender<MarketEventEnvelopeRef>,
+    metrics: Arc<ExchangeMetrics>,
+    orderbook_depths: HashMap<Pair, u16>,
+}
+
+/// Market data streamed by OKX, candles are served by a different endpoint than trades and order books
+/// so up to two websockets feed the stream
+pub struct OkxMarketDataStreamer {
+    addrs: Vec<Addr<DefaultWsActor>>,
+    stream: Pin<Box<UnboundedReceiverStream<MarketEventEnvelopeRef>>>,
+}
+
+#[async_trait]
+impl DataStreamer<MarketEventEnvelopeRef> for OkxMarketDataStreamer {
+    fn is_connected(&self) -> bool { true }
+
+    fn ping(&self) { assert!(self.addrs.iter().all(Addr::connected)) }
+
+    async fn add_sink(&mut self, f: Box<dyn Fn(MarketEventEnvelopeRef) -> std::result::Result<(), Infallible> + Send>) {
+        let stream = &mut self.stream;
+        stream.map(f).forward(futures::sink::drain()).await.unwrap();
+    }
+}
+
+impl OkxStreamingApi {
+    /// Create a new okx exchange bot, unavailable channels and currencies are ignored
+    pub async fn try_new(
+        creds: &dyn Credentials,
+        channels: Vec<MarketChannel>,
+        use_test: bool,
+    ) -> Result<OkxMarketDataStreamer> {
+        let metrics = Arc::new(ExchangeMetrics::for_exchange(Exchange::Okx));
+        let api = Arc::new(if use_test {
+            OkxApi::new_test(creds)?
+        } else {
+            OkxApi::new(creds)?
+        });
+        let (tx, rx) = mpsc::unbounded_channel();
+        let (mut public, mut business) = (
+            Self::new(tx.clone(), metrics.clone(), api.clone()),
+            Self::new(tx, metrics.clone(), api),
+        );
+        for channel in &channels {
+            let pair = channel.pair();
+            let Some(arg) = pair_to_symbol(&Exchange::Okx, pair)
+                .ok()
+                .and_then(|inst_id| subscription(channel, inst_id.as_ref()))
+            else {
+                metrics.subscription_failure(pair, channel.name());
+                warn!(channel = ?channel, "okx does not stream this channel");
+                continue;
+            };
+            if is_business_channel(&arg.channel) {
+                business.candles.insert(
+                    (arg.channel.clone(), arg.inst_id.clone().unwrap_or_default()),
+                    CandleConf {
+                        resolution: channel.resolution.unwrap_or(Resolution::new(TimeUnit::Minute, 1)),
+                        only_final: channel.only_final.unwrap_or(false),
+                    },
+                );
+                business.subscriptions.push(arg);
+            } else {
+                if let Some(depth) = channel.orderbook.and_then(|c| c.depth) {
+                    public.orderbook_depths.insert(pair.clone(), depth);
+                }
+                public.subscriptions.push(arg);
+            }
+        }
+
+        let (public_url, business_url) = if use_test {
+            (TEST_PUBLIC_WS_ENDPOINT, TEST_BUSINESS_WS_ENDPOINT)
+        } else {
+            (PUBLIC_WS_ENDPOINT, BUSINESS_WS_ENDPOINT)
+        };
+        let mut addrs = vec![];
+        for (name, api, url) in [
+            ("OkxStream", public, public_url),
+            ("OkxBusinessStream", business, business_url),
+        ] {
+            if api.subscriptions.is_empty() {
+                continue;
+            }
+            let addr = DefaultWsActor::new(
+                name,
+                Url::parse(url)?,
+                WsReconnectOptions::builder()
+                    .conn_timeout(Duration::from_secs(30))
+                    .stale_after(Duration::from_secs(60))
+                    .build(),
+                Arc::new(api),
+            )
+            .await?;
+            addrs.push(addr);
+        }
+
+        Ok(OkxMarketDataStreamer {
+            addrs,
+            stream: Box::pin(UnboundedReceiverStream::new(rx)),
+        })
+    }
+
+    fn new(sink: UnboundedSender<MarketEventEnvelopeRef>, metrics: Arc<ExchangeMetrics>, api: Arc<OkxApi>) -> Self {
+        Self {
+            books: Arc::new(DashMap::new()),
+            resyncs: Arc::new(DashSet::new()),
+            api,
+            subscriptions: vec![],
+            candles: HashMap::new(),
+            sink,
+            metrics,
+            orderbook_depths: HashMap::new(),
+        }
+    }
+
+    fn get_pair(&self, symbol: &str) -> Result<Pair> {
+        symbol_to_pair(&Self::EXCHANGE, &MarketSymbol::from(symbol)).map_err(|e| {
+            self.metrics.in_unsupported_pair(symbol, "order_books");
+            e
+        })
+    }
+
+    /// Replace a desynced book with a REST snapshot, unless one is already being fetched.
+    /// Updates received in the meantime are lost, if the book drifts again the next checksum triggers another resync.
+    fn resync(&self, pair: &Pair, inst_id: &str) {
+        if !self.resyncs.insert(pair.clone()) {
+            return;
+        }
+        let (api, books, resyncs, pair, inst_id) = (
+            self.api.clone(),
+            self.books.clone(),
+            self.resyncs.clone(),
+            pair.clone(),
+            inst_id.to_string(),
+        );
+        actix::spawn(async move {
+            match api.order_book(&inst_id, RESYNC_DEPTH).await {
+                Ok(book) => {
+                    if let Some(mut agg) = books.get_mut(&pair) {
+                        agg.reset_asks_n(from_okx_levels(&book.asks).into_iter());
+                        agg.reset_bids_n(from_okx_levels(&book.bids).into_iter());
+                        agg.record_raw_asks(okx_raw_levels(&book.asks));
+                        agg.record_raw_bids(okx_raw_levels(&book.bids));
+                        agg.set_ts(book.ts);
+                        agg.desynced = false;
+                    }
+                }
+                // The next update retries
+                Err(e) => warn!(err = ?e, pair = %pair, "okx failed to snapshot the order book"),
+            }
+            resyncs.remove(&pair);
+        });
+    }
+
+    /// Market events of data pushed on a channel, the `books` channel sends a snapshot followed by updates
+    fn parse_push(&self, arg: &WsArg, action: Option<&str>, data: Vec<Value>) -> Result<Vec<MarketEvent>> {
+        let Some(inst_id) = arg.inst_id.as_deref() else {
+            return Ok(vec![]);
+        };
+        let pair = self.get_pair(inst_id)?;
+        let mut events = vec![];
+        match arg.channel.as_str() {
+            "trades" => {
+                for v in data {
+                    let trade: OkxTrade = serde_json::from_value(v)?;
+                    events.push(MarketEvent::Trade(from_okx_trade(&trade, pair.clone())));
+                }
+            }
+            channel @ ("books" | "books5" | "bbo-tbt") => {
+                let is_snapshot = channel != "books" || action == Some("snapshot");
+                for v in data {
+                    let book: OkxOrderBook = serde_json::from_value(v)?;
+                    let (asks, bids) = (from_okx_levels(&book.asks), from_okx_levels(&book.bids));
+                    let mismatch = Cell::new(false);
+                    let latest = self.latest_book(&pair, |agg| {
+                        if is_snapshot {
+                            agg.reset_asks_n(asks.iter().copied());
+                            agg.reset_bids_n(bids.iter().copied());
+                            agg.desynced = false;
+                        } else if agg.desynced {
+                            // Updates are dropped until the book is snapshotted again
+                            return;
+                        } else {
+                            agg.update_asks(asks.iter().copied());
+                            agg.update_bids(bids.iter().copied());
+                        }
+                        agg.record_raw_asks(okx_raw_levels(&book.asks));
+                        agg.record_raw_bids(okx_raw_levels(&book.bids));
+                        agg.set_ts(book.ts);
+                        if book
+                            .checksum
+                            .map_or(false, |checksum| agg.checksum(BookChecksum::Okx) as i32 != checksum)
+                        {
+                            agg.desynced = true;
+                            mismatch.set(true);
+                        }
+                    });
+                    if mismatch.get() {
+                        self.metrics.checksum_mismatch(&pair, "order_books");
+                    }
+                    if self.books.get(&pair).map_or(false, |agg| agg.desynced) {
+                        self.resync(&pair, inst_id);
+                    }
+                    events.extend(latest.map(MarketEvent::Orderbook));
+                }
+            }
+            channel => {
+                let Some(conf) = self.candles.get(&(channel.to_string(), inst_id.to_string())) else {
+                    return Ok(vec![]);
+                };
+                for v in data {
+                    let candle: OkxCandle = serde_json::from_value(v)?;
+                    let candle = from_okx_candle(&candle, pair.clone(), conf.resolution);
+                    if candle.is_final || !conf.only_final {
+                        events.push(MarketEvent::TradeCandle(candle));
+                    }
+                }
+            }
+        }
+        Ok(events)
+    }
+}
+
+#[async_trait]
+impl WsHandler for OkxStreamingApi {
+    #[cfg_attr(feature = "flame", flame)]
+    fn handle_in(&self, _w: &mut SinkWrite<Message, WsFramedSink>, msg: Bytes) {
+        match deserialize_json_s::<WsMessage>(msg.as_ref()) {
+            Ok(WsMessage::Push { arg, action, data }) => match self.parse_push(&arg, action.as_deref(), data) {
+                Ok(events) => events.into_iter().for_each(|e| self.broadcast(e)),
+                Err(err) => trace!(err = ?err, channel = ?arg, "okx error parsing push"),
+            },
+            Ok(WsMessage::Event { event, code, msg }) if event == "error" => {
+                error!(code = ?code, msg = ?msg, "okx stream error");
+            }
+            Ok(WsMessage::Event { .. }) => {}
+            Err(err) => trace!(err = ?err, msg = ?msg, "okx error deserializing"),
+        }
+    }
+
+    fn handle_started(&self, w: &mut SinkWrite<Message, WsFramedSink>) {
+        self.metrics.stream_reconnected();
+        let request = WsRequest {
+            op: "subscribe",
+            args: self.subscriptions.clone(),
+        };
+        let written = serde_json::to_string(&request)
+            .ok()
+            .and_then(|s| w.write(Message::Text(s.into())).ok());
+        if written.is_none() {
+            for arg in &self.subscriptions {
+                self.metrics
+                    .subscription_failure(arg.inst_id.as_deref().unwrap_or_default(), &arg.channel);
+            }
+        }
+    }
+}
+
+impl StreamingApi for OkxStreamingApi {
+    const NAME: &'static str = "okx";
+    const EXCHANGE: Exchange = Exchange::Okx;
+}
+
+impl MarketEventStream for OkxStreamingApi {
+    fn sink(&self) -> &UnboundedSender<MarketEventEnvelopeRef> { &self.sink }
+
+    fn metrics(&self) -> &ExchangeMetrics { &self.metrics }
+}
+
+impl LiveBookStream for OkxStreamingApi {
+    fn books(&self) -> &DashMap<Pair, LiveAggregatedOrderBook> { &self.books }
+
+    fn orderbook_depths(&self) -> &HashMap<Pair, u16> { &self.orderbook_depths }
+}
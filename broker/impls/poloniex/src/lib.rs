@@ -43,7 +43,9 @@ impl BrokerConnector for PoloniexExchangeConnector {
         todo!()
     }
 
-    fn fees_provider(&self, _conf: Value) -> broker_core::error::Result<Arc<dyn FeeProvider>> { todo!() }
+    fn fees_provider(&self, _conf: Value) -> broker_core::error::Result<Arc<dyn FeeProvider>> {
+        Err(broker_core::error::Error::BrokerFeatureNotImplemented)
+    }
 }
 
 exchange!(Exchange::Poloniex, PoloniexExchangeConnector);
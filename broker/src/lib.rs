@@ -4,10 +4,14 @@ pub use broker_binance;
 pub use broker_bitstamp;
 #[cfg(any(feature = "bittrex", feature = "all_exchanges"))]
 pub use broker_bittrex;
+#[cfg(any(feature = "bybit", feature = "all_exchanges"))]
+pub use broker_bybit;
 #[cfg(any(feature = "coinbase", feature = "all_exchanges"))]
 pub use broker_coinbase;
 #[cfg(any(feature = "kraken", feature = "all_exchanges"))]
 pub use broker_kraken;
+#[cfg(any(feature = "okx", feature = "all_exchanges"))]
+pub use broker_okx;
 #[cfg(any(feature = "poloniex", feature = "all_exchanges"))]
 pub use broker_poloniex;
 
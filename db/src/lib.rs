@@ -41,6 +41,7 @@ pub use storage::rocksdb::{RocksDbOptions, RocksDbStorage};
 pub use storage::ser::json::JsonStorageExt as StorageExt;
 pub use storage::ser::json::JsonStorageExt;
 pub use storage::ser::rkyv::RkyvStorageExt;
+pub use storage::snapshot::Snapshot;
 pub use storage::{get_or_create, repo::DefaultRepository, DbEngineOptions, DbOptions, Storage};
 
 mod error;
@@ -2,7 +2,7 @@ use std::collections::BTreeMap;
 use std::sync::RwLock;
 
 use crate::error::{Error, Result};
-use crate::storage::{BatchOperation, Bytes};
+use crate::storage::{BatchOperation, Bytes, BytesIter};
 use crate::Storage;
 
 type InMemoryTable = BTreeMap<Vec<u8>, Vec<u8>>;
@@ -72,6 +72,17 @@ impl Storage for MemoryKVStore {
         Ok(vec)
     }
 
+    fn _scan_prefix<'a>(&'a self, table: &str, prefix: &[u8]) -> Result<BytesIter<'a>> {
+        // Records are copied so that the table lock isn't held while iterating
+        let records: Vec<Result<(Bytes, Bytes)>> = self.with_table(table, |t| {
+            t.range(prefix.to_vec()..)
+                .take_while(|(k, _v)| k.starts_with(prefix))
+                .map(|(k, v)| Ok((k.clone().into_boxed_slice(), v.clone().into_boxed_slice())))
+                .collect()
+        });
+        Ok(Box::new(records.into_iter()))
+    }
+
     fn _delete(&self, table: &str, key: &[u8]) -> Result<()> {
         self.with_table(table, |t| t.remove(key));
         Ok(())
@@ -91,4 +102,9 @@ impl Storage for MemoryKVStore {
         }
         Ok(())
     }
+
+    fn tables(&self) -> Result<Vec<String>> {
+        let r = self.inner.read().unwrap();
+        Ok(r.keys().map(|k| String::from_utf8_lossy(k).to_string()).collect())
+    }
 }
@@ -14,9 +14,12 @@ pub(crate) mod repo;
 pub mod rkv;
 pub mod rocksdb;
 pub mod ser;
+pub mod snapshot;
 
 pub type Bytes = Box<[u8]>;
 
+pub type BytesIter<'a> = Box<dyn Iterator<Item = Result<(Bytes, Bytes)>> + 'a>;
+
 pub type BatchOperation<'a> = (&'a str, &'a [u8], Option<Vec<u8>>);
 
 pub trait Storage: Send + Sync + Debug + ToAny {
@@ -33,11 +36,17 @@ pub trait Storage: Send + Sync + Debug + ToAny {
     /// TODO: this should return impl Iterator
     fn _get_all(&self, table: &str) -> Result<Vec<(Bytes, Bytes)>>;
 
+    /// Lazily iterate over the records whose key starts with `prefix`, in key order
+    fn _scan_prefix<'a>(&'a self, table: &str, prefix: &[u8]) -> Result<BytesIter<'a>>;
+
     fn _delete(&self, table: &str, key: &[u8]) -> Result<()>;
 
     fn _delete_range(&self, table: &str, from: &[u8], to: &[u8]) -> Result<()>;
 
     fn ensure_table(&self, name: &str) -> Result<()>;
+
+    /// The names of all the tables of the storage
+    fn tables(&self) -> Result<Vec<String>>;
 }
 
 pub type BatchOperationSer<'a, K> = (&'a str, K, Option<Box<dyn erased_serde::Serialize>>);
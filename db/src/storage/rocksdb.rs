@@ -9,7 +9,7 @@ use rocksdb::{BoundColumnFamily, ColumnFamilyDescriptor, DBCompressionType, Dire
 use ext::ResultExt;
 
 use crate::error::*;
-use crate::storage::{BatchOperation, Storage};
+use crate::storage::{BatchOperation, BytesIter, Storage};
 
 type Bytes = Box<[u8]>;
 
@@ -209,6 +209,18 @@ impl Storage for RocksDbStorage {
         self.inner.iterator_cf(&cf, mode).map(|r| r.err_into()).collect()
     }
 
+    fn _scan_prefix<'a>(&'a self, table: &str, prefix: &[u8]) -> Result<BytesIter<'a>> {
+        let mode = IteratorMode::From(prefix, Direction::Forward);
+        let cf = self.cf(table)?;
+        let prefix = prefix.to_vec();
+        Ok(Box::new(
+            self.inner
+                .iterator_cf(&cf, mode)
+                .take_while(move |r| r.as_ref().map_or(true, |(k, _v)| k.starts_with(&prefix)))
+                .map(|r| r.err_into()),
+        ))
+    }
+
     fn _delete(&self, table: &str, key: &[u8]) -> Result<()> {
         let cf = self.cf(table)?;
         self.inner.delete_cf(&cf, key).err_into()
@@ -228,6 +240,8 @@ impl Storage for RocksDbStorage {
             Ok(())
         }
     }
+
+    fn tables(&self) -> Result<Vec<String>> { DB::list_cf(&Options::default(), self.inner.path()).err_into() }
 }
 
 #[cfg(test)]
@@ -364,6 +378,27 @@ mod test {
         assert_eq!(vec1, items);
     }
 
+    #[test]
+    fn scan_prefix_cf() {
+        let table = "rows";
+        let db = db(vec![table.to_string()]);
+        for prefix in ["a", "b", "c"] {
+            for i in 0..10 {
+                let v = Foobar {
+                    foo: prefix.to_string(),
+                    number: i,
+                };
+                let r = db.put(table, format!("{}|{}", prefix, i), v);
+                assert!(r.is_ok(), "{:?}", r);
+            }
+        }
+        let scanned: Vec<Foobar> = db.scan_prefix(table, "b|").unwrap().map(|r| r.unwrap().1).collect();
+        assert_eq!(scanned.len(), 10);
+        assert!(scanned.iter().all(|v| v.foo == "b"));
+        assert_eq!(db.scan_prefix::<_, Foobar>(table, "").unwrap().count(), 30);
+        assert_eq!(db.scan_prefix::<_, Foobar>(table, "d").unwrap().count(), 0);
+    }
+
     #[test]
     fn get_ranged_cf() {
         let table = "rows";
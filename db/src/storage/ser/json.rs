@@ -1,5 +1,5 @@
 use crate::error::*;
-use crate::storage::{BatchOperation, BatchOperationSer};
+use crate::storage::{BatchOperation, BatchOperationSer, Bytes};
 use crate::Storage;
 use ext::ResultExt;
 use serde::de::DeserializeOwned;
@@ -35,6 +35,16 @@ pub trait JsonStorageExt {
     where
         V: DeserializeOwned;
 
+    /// Lazily iterate over the records whose key starts with `prefix`, in key order
+    fn scan_prefix<'a, K, V>(
+        &'a self,
+        table: &str,
+        prefix: K,
+    ) -> Result<Box<dyn Iterator<Item = Result<(Bytes, V)>> + 'a>>
+    where
+        K: AsRef<[u8]>,
+        V: DeserializeOwned + 'a;
+
     fn delete<K>(&self, table: &str, key: K) -> Result<()>
     where
         K: AsRef<[u8]>;
@@ -113,6 +123,21 @@ impl<T: Storage + ?Sized> JsonStorageExt for T {
             .collect()
     }
 
+    fn scan_prefix<'a, K, V>(
+        &'a self,
+        table: &str,
+        prefix: K,
+    ) -> Result<Box<dyn Iterator<Item = Result<(Bytes, V)>> + 'a>>
+    where
+        K: AsRef<[u8]>,
+        V: DeserializeOwned + 'a,
+    {
+        let items = self._scan_prefix(table, prefix.as_ref())?;
+        Ok(Box::new(items.map(|r| {
+            r.and_then(|(k, v)| serde_json::from_slice::<V>(&v).map(|v| (k, v)).err_into())
+        })))
+    }
+
     fn delete<K>(&self, table: &str, key: K) -> Result<()>
     where
         K: AsRef<[u8]>,
@@ -0,0 +1,80 @@
+use std::collections::BTreeMap;
+
+use crate::error::Result;
+use crate::storage::BatchOperation;
+use crate::Storage;
+
+/// A copy of every record of a storage, by table
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub tables: BTreeMap<String, Vec<(Vec<u8>, Vec<u8>)>>,
+}
+
+impl Snapshot {
+    /// Copy all the records of `db`
+    pub fn take(db: &dyn Storage) -> Result<Self> {
+        let mut tables = BTreeMap::new();
+        for table in db.tables()? {
+            let records = db
+                ._get_all(&table)?
+                .into_iter()
+                .map(|(k, v)| (k.into_vec(), v.into_vec()))
+                .collect();
+            tables.insert(table, records);
+        }
+        Ok(Self { tables })
+    }
+
+    /// Roll `db` back to this snapshot, records written since are deleted in the same batch
+    pub fn restore(&self, db: &dyn Storage) -> Result<()> {
+        for table in self.tables.keys() {
+            db.ensure_table(table)?;
+        }
+        let current: Vec<(String, Vec<Vec<u8>>)> = db
+            .tables()?
+            .into_iter()
+            .map(|table| {
+                let keys = db._get_all(&table)?.into_iter().map(|(k, _)| k.into_vec()).collect();
+                Ok((table, keys))
+            })
+            .collect::<Result<_>>()?;
+        let mut batch: Vec<BatchOperation> = vec![];
+        for (table, keys) in &current {
+            for key in keys {
+                batch.push((table.as_str(), key.as_slice(), None));
+            }
+        }
+        for (table, records) in &self.tables {
+            for (k, v) in records {
+                batch.push((table.as_str(), k.as_slice(), Some(v.clone())));
+            }
+        }
+        db._batch(&batch)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::storage::snapshot::Snapshot;
+    use crate::{MemoryKVStore, Storage};
+
+    #[test]
+    fn restore_rolls_back_tables() {
+        let db = MemoryKVStore::new();
+        db.ensure_table("positions").unwrap();
+        db._put("positions", b"a", b"1").unwrap();
+        let snapshot = Snapshot::take(&db).unwrap();
+        db._put("positions", b"a", b"2").unwrap();
+        db._put("positions", b"b", b"3").unwrap();
+        db.ensure_table("models").unwrap();
+        db._put("models", b"m", b"4").unwrap();
+        snapshot.restore(&db).unwrap();
+        assert_eq!(db._get("positions", b"a").unwrap(), b"1".to_vec());
+        assert!(db._get("positions", b"b").is_err());
+        assert!(db._get_all("models").unwrap().is_empty());
+        assert_eq!(
+            Snapshot::take(&db).unwrap().tables.get("positions"),
+            snapshot.tables.get("positions")
+        );
+    }
+}
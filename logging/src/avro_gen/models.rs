@@ -128,3 +128,55 @@ impl Default for LiveOrder {
         }
     }
 }
+
+lazy_static! {
+    pub static ref QUOTE_SCHEMA : avro_rs::schema::Schema = avro_rs::schema::Schema::parse_str("{\"type\":\"record\",\"name\":\"Quote\",\"fields\":[{\"name\":\"event_ms\",\"type\":\"long\"},{\"name\":\"pair\",\"type\":\"string\"},{\"name\":\"bid\",\"type\":\"double\"},{\"name\":\"bid_qty\",\"type\":\"double\"},{\"name\":\"ask\",\"type\":\"double\"},{\"name\":\"ask_qty\",\"type\":\"double\"}]}").unwrap();
+}
+
+#[derive(Debug, PartialEq, Clone, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct Quote {
+    pub event_ms: i64,
+    pub pair: String,
+    pub bid: f64,
+    pub bid_qty: f64,
+    pub ask: f64,
+    pub ask_qty: f64,
+}
+
+#[allow(clippy::derivable_impls)]
+impl Default for Quote {
+    fn default() -> Quote {
+        Quote {
+            event_ms: 0,
+            pair: String::default(),
+            bid: 0.0,
+            bid_qty: 0.0,
+            ask: 0.0,
+            ask_qty: 0.0,
+        }
+    }
+}
+
+lazy_static! {
+    pub static ref OPENINTEREST_SCHEMA : avro_rs::schema::Schema = avro_rs::schema::Schema::parse_str("{\"type\":\"record\",\"name\":\"OpenInterest\",\"fields\":[{\"name\":\"event_ms\",\"type\":\"long\"},{\"name\":\"pair\",\"type\":\"string\"},{\"name\":\"open_interest\",\"type\":\"double\"}]}").unwrap();
+}
+
+#[derive(Debug, PartialEq, Clone, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct OpenInterest {
+    pub event_ms: i64,
+    pub pair: String,
+    pub open_interest: f64,
+}
+
+#[allow(clippy::derivable_impls)]
+impl Default for OpenInterest {
+    fn default() -> OpenInterest {
+        OpenInterest {
+            event_ms: 0,
+            pair: String::default(),
+            open_interest: 0.0,
+        }
+    }
+}
@@ -19,7 +19,7 @@ use uuid::Uuid;
 
 use crate::file::metrics::FileLoggerMetrics;
 use crate::file::rotate::{RotatingFile, SizeAndExpirationPolicy};
-use crate::file::{Partition, Partitioner};
+use crate::file::{FileFormat, Partition, Partitioner};
 
 type RotatingWriter = Writer<'static, RotatingFile<SizeAndExpirationPolicy>>;
 
@@ -31,6 +31,8 @@ pub struct FileActorOptions<T> {
     pub max_file_time: Duration,
     /// Record partitioner
     pub partitioner: Rc<dyn Partitioner<T>>,
+    /// Format of the files, which decides the actor that writes them
+    pub format: FileFormat,
 }
 
 #[derive(Debug, Display, Error)]
@@ -231,6 +233,7 @@ mod test {
             max_file_time: Duration::milliseconds(100),
             base_dir: String::from(base_dir),
             partitioner: Rc::new(MarketEventPartitioner::new(Duration::seconds(200))),
+            format: FileFormat::Avro,
         })
     }
 
@@ -1,11 +1,24 @@
 use std::path::PathBuf;
 
 use chrono::{DateTime, Utc};
+use serde::Deserialize;
 
 pub mod file_actor;
 mod metrics;
+pub mod parquet_actor;
 mod rotate;
 
+/// The format of the files records are written to
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FileFormat {
+    /// Avro files, written by [`file_actor::AvroFileActor`]
+    #[default]
+    Avro,
+    /// Parquet files, written by [`parquet_actor::ParquetFileActor`]
+    Parquet,
+}
+
 #[derive(Hash, PartialEq, Eq)]
 pub struct Partition {
     path: PathBuf,
@@ -0,0 +1,371 @@
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::Arc;
+
+use actix::{Actor, Handler, Message, Running, SyncContext};
+use arrow::datatypes::SchemaRef;
+use arrow::record_batch::RecordBatch;
+use chrono::{DateTime, Duration, Utc};
+use parquet::arrow::ArrowWriter;
+use parquet::file::properties::WriterProperties;
+use uuid::Uuid;
+
+use crate::file::file_actor::{Error, FileActorOptions};
+use crate::file::metrics::FileLoggerMetrics;
+use crate::file::{Partition, Partitioner};
+
+const PARQUET_EXTENSION: &str = "parquet";
+/// Files are only readable once closed, so they are hidden from readers until then
+const IN_PROGRESS_EXTENSION: &str = "parquet.inprogress";
+/// Records buffered in memory before being written as a row group
+const ROW_GROUP_SIZE: usize = 10_000;
+/// Longest time records are buffered in memory before being written, whatever their count
+const FLUSH_INTERVAL_SECS: i64 = 5;
+
+pub trait ToRecordBatch: Sized {
+    /// The schema of the record batches of a record, if it can be written
+    fn arrow_schema(&self) -> Option<SchemaRef>;
+
+    /// Convert records of a single partition, which all share the same schema, into a record batch
+    ///
+    /// # Errors
+    ///
+    /// The records cannot be written
+    fn to_record_batch(records: &[Arc<Self>]) -> Result<RecordBatch, Error>;
+}
+
+/// The file currently written for a partition, and the records not yet written to it
+struct PartitionWriter<T> {
+    dir: PathBuf,
+    part: u32,
+    records: Vec<Arc<T>>,
+    writer: Option<ArrowWriter<File>>,
+    /// When the first record of the current file was received
+    opened_at: Option<DateTime<Utc>>,
+    /// When the oldest buffered record was received
+    buffered_at: Option<DateTime<Utc>>,
+}
+
+impl<T: ToRecordBatch> PartitionWriter<T> {
+    fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            part: 0,
+            records: vec![],
+            writer: None,
+            opened_at: None,
+            buffered_at: None,
+        }
+    }
+
+    fn file_path(&self, session_uuid: &Uuid, extension: &str) -> PathBuf {
+        self.dir
+            .join(format!("{}-{:04}.{}", session_uuid, self.part, extension))
+    }
+
+    fn push(&mut self, record: Arc<T>) {
+        let now = Utc::now();
+        self.opened_at.get_or_insert(now);
+        self.buffered_at.get_or_insert(now);
+        self.records.push(record);
+    }
+
+    fn should_flush(&self, flush_interval: Duration) -> bool {
+        self.records.len() >= ROW_GROUP_SIZE
+            || self
+                .buffered_at
+                .map_or(false, |buffered_at| Utc::now() - buffered_at >= flush_interval)
+    }
+
+    /// Write buffered records to the file as a row group
+    fn write_records(&mut self, session_uuid: &Uuid) -> Result<(), Error> {
+        if self.records.is_empty() {
+            return Ok(());
+        }
+        let batch = T::to_record_batch(&self.records)?;
+        self.records.clear();
+        self.buffered_at = None;
+        if self.writer.is_none() {
+            let file = File::create(self.file_path(session_uuid, IN_PROGRESS_EXTENSION))?;
+            let props = WriterProperties::builder()
+                .set_max_row_group_size(ROW_GROUP_SIZE)
+                .build();
+            let writer = ArrowWriter::try_new(file, batch.schema(), Some(props)).map_err(|e| {
+                trace!("Error creating parquet writer {:?}", e);
+                Error::NoWriter
+            })?;
+            self.writer = Some(writer);
+        }
+        let writer = self.writer.as_mut().ok_or(Error::NoWriter)?;
+        writer.write(&batch).map_err(|e| {
+            trace!("Error writing parquet batch {:?}", e);
+            Error::Writer
+        })?;
+        // The arrow writer buffers rows until a row group is full, flush them to the file right away
+        writer.flush().map_err(|e| {
+            trace!("Error flushing parquet row group {:?}", e);
+            Error::Writer
+        })
+    }
+
+    fn should_rotate(&self, session_uuid: &Uuid, max_file_size: u128, max_file_time: Duration) -> bool {
+        let expired = self
+            .opened_at
+            .map_or(false, |opened_at| Utc::now() - opened_at > max_file_time);
+        let too_large = self.writer.is_some()
+            && fs::metadata(self.file_path(session_uuid, IN_PROGRESS_EXTENSION))
+                .map_or(false, |m| u128::from(m.len()) > max_file_size);
+        expired || too_large
+    }
+
+    /// Write buffered records and close the current file, making it readable
+    fn close(&mut self, session_uuid: &Uuid) -> Result<(), Error> {
+        self.write_records(session_uuid)?;
+        self.opened_at = None;
+        if let Some(writer) = self.writer.take() {
+            writer.close().map_err(|e| {
+                trace!("Error closing parquet writer {:?}", e);
+                Error::Writer
+            })?;
+            fs::rename(
+                self.file_path(session_uuid, IN_PROGRESS_EXTENSION),
+                self.file_path(session_uuid, PARQUET_EXTENSION),
+            )?;
+            self.part += 1;
+        }
+        Ok(())
+    }
+}
+
+/// Writes records to parquet files, partitioned in the same way as avro files so that they can be read by the
+/// backtest datasets without conversion
+pub struct ParquetFileActor<T> {
+    base_path: PathBuf,
+    partitioner: Rc<dyn Partitioner<T>>,
+    writers: HashMap<Partition, PartitionWriter<T>>,
+    max_file_size: u128,
+    max_file_time: Duration,
+    flush_interval: Duration,
+    session_uuid: Uuid,
+    metrics: &'static FileLoggerMetrics,
+}
+
+impl<T: ToRecordBatch> ParquetFileActor<T>
+where
+    T: 'static,
+{
+    pub fn new(options: &FileActorOptions<T>) -> Self {
+        Self {
+            base_path: Path::new(options.base_dir.as_str()).to_path_buf(),
+            partitioner: options.partitioner.clone(),
+            writers: HashMap::new(),
+            max_file_size: options.max_file_size,
+            max_file_time: options.max_file_time,
+            flush_interval: Duration::seconds(FLUSH_INTERVAL_SECS),
+            session_uuid: Uuid::new_v4(),
+            metrics: super::metrics::metrics(),
+        }
+    }
+
+    /// Override the longest time records are buffered in memory before being written
+    #[must_use]
+    pub fn with_flush_interval(mut self, flush_interval: Duration) -> Self {
+        self.flush_interval = flush_interval;
+        self
+    }
+
+    /// Buffer a record in the file of its partition, writing buffered records once too many or too old,
+    /// and rotating the file once too old or too large
+    ///
+    /// # Errors
+    ///
+    /// The record cannot be partitioned, has no schema, or writing fails
+    pub fn append(&mut self, record: Arc<T>) -> Result<(), Error> {
+        if record.arrow_schema().is_none() {
+            return Err(Error::NoSchema);
+        }
+        let partition = self.partitioner.partition(&record).ok_or(Error::NoPartition)?;
+        let session_uuid = self.session_uuid;
+        let writer = match self.writers.entry(partition) {
+            Entry::Occupied(o) => o.into_mut(),
+            Entry::Vacant(v) => {
+                // Create base directory for partition if necessary
+                let dir = self.base_path.join(&v.key().path);
+                fs::create_dir_all(&dir)?;
+                v.insert(PartitionWriter::new(dir))
+            }
+        };
+        writer.push(record);
+        if writer.should_flush(self.flush_interval) {
+            writer.write_records(&session_uuid)?;
+        }
+        if writer.should_rotate(&session_uuid, self.max_file_size, self.max_file_time) {
+            writer.close(&session_uuid)?;
+        }
+        Ok(())
+    }
+
+    /// Write the records buffered for too long in partitions that no longer receive any
+    pub(crate) fn flush_stale_records(&mut self) {
+        let session_uuid = self.session_uuid;
+        for writer in self.writers.values_mut() {
+            if writer.should_flush(self.flush_interval) {
+                if let Err(e) = writer.write_records(&session_uuid) {
+                    self.metrics.flush_failure();
+                    trace!("Failed to write parquet records {:?}", e);
+                }
+            }
+        }
+    }
+
+    /// Close the files of expired partitions, and forget them
+    pub(crate) fn remove_expired_entries(&mut self) {
+        let session_uuid = self.session_uuid;
+        let metrics = self.metrics;
+        self.writers.retain(|partition, writer| {
+            if !partition.is_expired() {
+                return true;
+            }
+            if let Err(e) = writer.close(&session_uuid) {
+                metrics.flush_failure();
+                trace!("Failed to close parquet file {:?}", e);
+            }
+            false
+        });
+    }
+
+    /// Close the files of every partition
+    pub fn close_all(&mut self) {
+        let session_uuid = self.session_uuid;
+        for writer in self.writers.values_mut() {
+            if let Err(e) = writer.close(&session_uuid) {
+                self.metrics.flush_failure();
+                trace!("Failed to close parquet file {:?}", e);
+            }
+        }
+    }
+}
+
+impl<T: ToRecordBatch> Actor for ParquetFileActor<T>
+where
+    T: 'static,
+{
+    type Context = SyncContext<Self>;
+
+    fn started(&mut self, _ctx: &mut Self::Context) {
+        info!("parquet file logger started");
+    }
+
+    fn stopping(&mut self, _ctx: &mut Self::Context) -> Running {
+        info!("parquet file logger stopping, closing files...");
+        Running::Stop
+    }
+
+    fn stopped(&mut self, _ctx: &mut Self::Context) {
+        info!("parquet file logger stopped, closing files...");
+        self.close_all();
+    }
+}
+
+impl<T> Handler<Arc<T>> for ParquetFileActor<T>
+where
+    T: ToRecordBatch + Message<Result = anyhow::Result<()>> + 'static,
+{
+    type Result = anyhow::Result<()>;
+
+    fn handle(&mut self, msg: Arc<T>, _ctx: &mut Self::Context) -> Self::Result {
+        if let Err(e) = self.append(msg) {
+            self.metrics.write_append_failure();
+            trace!("Failed to append record {:?}", e);
+            return Err(anyhow!(e));
+        }
+        self.flush_stale_records();
+        self.remove_expired_entries();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs::File;
+    use std::rc::Rc;
+    use std::sync::Arc;
+
+    use chrono::Duration;
+    use fs_extra::dir::get_dir_content;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    use brokers::exchange::Exchange;
+    use brokers::types::{MarketEventEnvelope, SecurityType, Symbol, TradeType};
+
+    use crate::file::file_actor::FileActorOptions;
+    use crate::file::FileFormat;
+    use crate::market_event::MarketEventPartitioner;
+
+    use super::ParquetFileActor;
+
+    #[test]
+    fn write_parquet_files() {
+        util::test::init_test_env();
+        let dir = tempdir::TempDir::new("s").unwrap();
+        let mut actor: ParquetFileActor<MarketEventEnvelope> = ParquetFileActor::new(&FileActorOptions {
+            max_file_size: 100_000_000,
+            max_file_time: Duration::hours(1),
+            base_dir: dir.path().to_str().unwrap().to_string(),
+            partitioner: Rc::new(MarketEventPartitioner::new(Duration::seconds(200))),
+            format: FileFormat::Parquet,
+        });
+        let symbol = Symbol::new("BTC_USDT".into(), SecurityType::Crypto, Exchange::Binance);
+        let now = chrono::Utc::now().timestamp_millis();
+        for i in 0..100 {
+            let trade = MarketEventEnvelope::trade_event(symbol.clone(), now + i, 0.1, 0.2, TradeType::Buy, None);
+            actor.append(Arc::new(trade)).unwrap();
+        }
+        // Nothing is readable until files are closed
+        let content = get_dir_content(dir.path()).unwrap();
+        assert!(content.files.iter().all(|f| !f.ends_with(".parquet")));
+
+        actor.close_all();
+        let content = get_dir_content(dir.path()).unwrap();
+        assert_eq!(content.files.len(), 1);
+        let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(&content.files[0]).unwrap())
+            .unwrap()
+            .build()
+            .unwrap();
+        let rows: usize = reader.map(|batch| batch.unwrap().num_rows()).sum();
+        assert_eq!(rows, 100);
+    }
+
+    #[test]
+    fn write_records_once_buffered_for_too_long() {
+        util::test::init_test_env();
+        let dir = tempdir::TempDir::new("s").unwrap();
+        let mut actor: ParquetFileActor<MarketEventEnvelope> = ParquetFileActor::new(&FileActorOptions {
+            max_file_size: 100_000_000,
+            max_file_time: Duration::hours(1),
+            base_dir: dir.path().to_str().unwrap().to_string(),
+            partitioner: Rc::new(MarketEventPartitioner::new(Duration::seconds(200))),
+            format: FileFormat::Parquet,
+        })
+        .with_flush_interval(Duration::zero());
+        let symbol = Symbol::new("BTC_USDT".into(), SecurityType::Crypto, Exchange::Binance);
+        let trade = MarketEventEnvelope::trade_event(
+            symbol,
+            chrono::Utc::now().timestamp_millis(),
+            0.1,
+            0.2,
+            TradeType::Buy,
+            None,
+        );
+        actor.append(Arc::new(trade)).unwrap();
+        // The row group is written to the in progress file without waiting for more records
+        let content = get_dir_content(dir.path()).unwrap();
+        assert_eq!(content.files.len(), 1);
+        assert!(content.files[0].ends_with(".parquet.inprogress"));
+        assert!(std::fs::metadata(&content.files[0]).unwrap().len() > 4);
+        assert!(actor.writers.values().all(|writer| writer.records.is_empty()));
+    }
+}
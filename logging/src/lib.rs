@@ -3,7 +3,7 @@ Defines common data logging output
 
 # Overview
 
-Currently logs events to files in AVRO binary format
+Currently logs events to files in AVRO binary format, or in Parquet format to be read by DataFusion
 
  */
 
@@ -26,7 +26,8 @@ extern crate tracing;
 
 pub mod prelude {
     pub use crate::file::file_actor::{AvroFileActor, FileActorOptions};
-    pub use crate::file::{Partition, Partitioner};
+    pub use crate::file::parquet_actor::ParquetFileActor;
+    pub use crate::file::{FileFormat, Partition, Partitioner};
     pub use crate::market_event::MarketEventPartitioner;
 }
 
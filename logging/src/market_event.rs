@@ -3,14 +3,19 @@ use std::path::PathBuf;
 use std::sync::Arc;
 
 use actix::Handler;
+use arrow::array::{ArrayRef, Float64Array, Float64Builder, Int32Array, Int64Array, ListBuilder, StringArray};
+use arrow::datatypes::{DataType, Field, Schema as ArrowSchema, SchemaRef};
+use arrow::record_batch::RecordBatch;
 use avro_rs::Schema;
 use chrono::{Duration, TimeZone, Timelike, Utc};
 
 use brokers::types::{MarketEvent, MarketEventEnvelope};
 
 use crate::avro_gen::{self,
-                      models::{Candle as AvroCandle, LiveTrade as AvroTrade, Orderbook as AvroOrderbook}};
+                      models::{Candle as AvroCandle, LiveTrade as AvroTrade, OpenInterest as AvroOpenInterest,
+                               Orderbook as AvroOrderbook, Quote as AvroQuote}};
 use crate::file::file_actor::{AvroFileActor, Error, ToAvroSchema};
+use crate::file::parquet_actor::ToRecordBatch;
 use crate::file::{Partition, Partitioner};
 
 #[derive(Clone)]
@@ -27,20 +32,26 @@ impl Partitioner<MarketEventEnvelope> for MarketEventPartitioner {
     /// each partition has a key and value formatted like hdfs does
     /// /k1=v1/k2=v2/...
     /// Dates are formatted using strftime/Ymd
+    /// The layout is the one of backtest datasets : chan={channel}/xch={exchange}/pr={pair}/dt={date}, recordings of
+    /// the former {Exchange}/{channel}/pr={pair}/dt={date} layout are migrated when backtests read them
     fn partition(&self, data: &MarketEventEnvelope) -> Option<Partition> {
-        let exchange = format!("{:?}", data.symbol.xch);
+        let exchange = data.symbol.xch.to_string();
         match &data.e {
             MarketEvent::Orderbook(ob) => Some((ob.timestamp, "order_books", ob.pair.clone())),
             MarketEvent::Trade(t) => Some((t.event_ms, "trades", t.pair.clone())),
             MarketEvent::TradeCandle(ct) => Some((ct.event_time.timestamp_millis(), "candles", ct.pair.clone())),
             MarketEvent::BookCandle(bc) => Some((bc.event_time.timestamp_millis(), "bcandles", bc.pair.clone())),
+            MarketEvent::FundingRate(fr) => Some((fr.event_ms, "funding_rates", fr.pair.clone())),
+            MarketEvent::OrderbookL3(l3) => Some((l3.event_ms, "order_books_l3", l3.pair.clone())),
+            MarketEvent::Quote(q) => Some((q.event_ms, "quotes", q.pair.clone())),
+            MarketEvent::OpenInterest(oi) => Some((oi.event_ms, "open_interests", oi.pair.clone())),
         }
         .map(|(ts, channel, pair)| {
             let ts = Utc.timestamp_millis_opt(ts).unwrap();
             let dt_par = ts.format("%Y%m%d");
             let path = PathBuf::new()
-                .join(&exchange)
-                .join(channel)
+                .join(format!("chan={}", channel))
+                .join(format!("xch={}", exchange))
                 .join(format!("pr={}", pair))
                 .join(format!("dt={}", dt_par));
             let midnight = ts
@@ -60,11 +71,198 @@ impl ToAvroSchema for MarketEventEnvelope {
             MarketEvent::Trade(_) => Some(&*avro_gen::models::LIVETRADE_SCHEMA),
             MarketEvent::Orderbook(_) => Some(&*avro_gen::models::ORDERBOOK_SCHEMA),
             MarketEvent::TradeCandle(_) => Some(&*avro_gen::models::CANDLE_SCHEMA),
-            MarketEvent::BookCandle(_) => None,
+            MarketEvent::Quote(_) => Some(&*avro_gen::models::QUOTE_SCHEMA),
+            MarketEvent::OpenInterest(_) => Some(&*avro_gen::models::OPENINTEREST_SCHEMA),
+            MarketEvent::BookCandle(_) | MarketEvent::FundingRate(_) | MarketEvent::OrderbookL3(_) => None,
         }
     }
 }
 
+fn levels_field(name: &str) -> Field {
+    let level = Field::new("item", DataType::Float64, true);
+    let levels = Field::new("item", DataType::List(Box::new(level)), true);
+    Field::new(name, DataType::List(Box::new(levels)), false)
+}
+
+lazy_static! {
+    static ref TRADE_ARROW_SCHEMA: SchemaRef = Arc::new(ArrowSchema::new(vec![
+        Field::new("event_ms", DataType::Int64, false),
+        Field::new("pair", DataType::Utf8, false),
+        Field::new("amount", DataType::Float64, false),
+        Field::new("price", DataType::Float64, false),
+        Field::new("tt", DataType::Int32, false),
+    ]));
+    static ref ORDERBOOK_ARROW_SCHEMA: SchemaRef = Arc::new(ArrowSchema::new(vec![
+        Field::new("event_ms", DataType::Int64, false),
+        Field::new("pair", DataType::Utf8, false),
+        levels_field("asks"),
+        levels_field("bids"),
+    ]));
+    static ref CANDLE_ARROW_SCHEMA: SchemaRef = Arc::new(ArrowSchema::new(vec![
+        Field::new("event_ms", DataType::Int64, false),
+        Field::new("pair", DataType::Utf8, false),
+        Field::new("start_ms", DataType::Int64, false),
+        Field::new("end_ms", DataType::Int64, false),
+        Field::new("open", DataType::Float64, false),
+        Field::new("high", DataType::Float64, false),
+        Field::new("low", DataType::Float64, false),
+        Field::new("close", DataType::Float64, false),
+        Field::new("volume", DataType::Float64, false),
+        Field::new("quote_volume", DataType::Float64, false),
+        Field::new("trade_count", DataType::Int64, false),
+    ]));
+    static ref QUOTE_ARROW_SCHEMA: SchemaRef = Arc::new(ArrowSchema::new(vec![
+        Field::new("event_ms", DataType::Int64, false),
+        Field::new("pair", DataType::Utf8, false),
+        Field::new("bid", DataType::Float64, false),
+        Field::new("bid_qty", DataType::Float64, false),
+        Field::new("ask", DataType::Float64, false),
+        Field::new("ask_qty", DataType::Float64, false),
+    ]));
+    static ref OPEN_INTEREST_ARROW_SCHEMA: SchemaRef = Arc::new(ArrowSchema::new(vec![
+        Field::new("event_ms", DataType::Int64, false),
+        Field::new("pair", DataType::Utf8, false),
+        Field::new("open_interest", DataType::Float64, false),
+    ]));
+}
+
+fn i64_col<R>(rows: &[&R], f: impl Fn(&R) -> i64) -> ArrayRef {
+    Arc::new(Int64Array::from_iter_values(rows.iter().map(|r| f(*r))))
+}
+
+fn f64_col<R>(rows: &[&R], f: impl Fn(&R) -> f64) -> ArrayRef {
+    Arc::new(Float64Array::from_iter_values(rows.iter().map(|r| f(*r))))
+}
+
+fn str_col<R>(rows: &[&R], f: impl Fn(&R) -> String) -> ArrayRef {
+    Arc::new(StringArray::from_iter_values(rows.iter().map(|r| f(*r))))
+}
+
+/// Book levels as lists of `[price, qty]`, like in avro files
+fn levels_col<R>(rows: &[&R], f: impl Fn(&R) -> &Vec<(f64, f64)>) -> ArrayRef {
+    let mut builder = ListBuilder::new(ListBuilder::new(Float64Builder::new()));
+    for row in rows {
+        for (price, qty) in f(*row) {
+            builder.values().values().append_value(*price);
+            builder.values().values().append_value(*qty);
+            builder.values().append(true);
+        }
+        builder.append(true);
+    }
+    Arc::new(builder.finish())
+}
+
+impl ToRecordBatch for MarketEventEnvelope {
+    fn arrow_schema(&self) -> Option<SchemaRef> {
+        match &self.e {
+            MarketEvent::Trade(_) => Some(TRADE_ARROW_SCHEMA.clone()),
+            MarketEvent::Orderbook(_) => Some(ORDERBOOK_ARROW_SCHEMA.clone()),
+            MarketEvent::TradeCandle(_) => Some(CANDLE_ARROW_SCHEMA.clone()),
+            MarketEvent::Quote(_) => Some(QUOTE_ARROW_SCHEMA.clone()),
+            MarketEvent::OpenInterest(_) => Some(OPEN_INTEREST_ARROW_SCHEMA.clone()),
+            MarketEvent::BookCandle(_) | MarketEvent::FundingRate(_) | MarketEvent::OrderbookL3(_) => None,
+        }
+    }
+
+    #[allow(clippy::cast_possible_wrap)]
+    fn to_record_batch(records: &[Arc<Self>]) -> Result<RecordBatch, Error> {
+        let schema = records.first().and_then(|r| r.arrow_schema()).ok_or(Error::NoSchema)?;
+        let columns = match &records[0].e {
+            MarketEvent::Trade(_) => {
+                let rows: Vec<_> = records
+                    .iter()
+                    .filter_map(|r| match &r.e {
+                        MarketEvent::Trade(t) => Some(t),
+                        _ => None,
+                    })
+                    .collect();
+                vec![
+                    i64_col(&rows, |t| t.event_ms),
+                    str_col(&rows, |t| t.pair.to_string()),
+                    f64_col(&rows, |t| t.amount),
+                    f64_col(&rows, |t| t.price),
+                    Arc::new(Int32Array::from_iter_values(rows.iter().map(|t| i32::from(t.tt)))) as ArrayRef,
+                ]
+            }
+            MarketEvent::Orderbook(_) => {
+                let rows: Vec<_> = records
+                    .iter()
+                    .filter_map(|r| match &r.e {
+                        MarketEvent::Orderbook(ob) => Some(ob),
+                        _ => None,
+                    })
+                    .collect();
+                vec![
+                    i64_col(&rows, |ob| ob.timestamp),
+                    str_col(&rows, |ob| ob.pair.to_string()),
+                    levels_col(&rows, |ob| &ob.asks),
+                    levels_col(&rows, |ob| &ob.bids),
+                ]
+            }
+            MarketEvent::TradeCandle(_) => {
+                let rows: Vec<_> = records
+                    .iter()
+                    .filter_map(|r| match &r.e {
+                        MarketEvent::TradeCandle(ct) => Some(ct),
+                        _ => None,
+                    })
+                    .collect();
+                vec![
+                    i64_col(&rows, |ct| ct.event_time.timestamp_millis()),
+                    str_col(&rows, |ct| ct.pair.to_string()),
+                    i64_col(&rows, |ct| ct.start_time.timestamp_millis()),
+                    i64_col(&rows, |ct| ct.end_time.timestamp_millis()),
+                    f64_col(&rows, |ct| ct.open),
+                    f64_col(&rows, |ct| ct.high),
+                    f64_col(&rows, |ct| ct.low),
+                    f64_col(&rows, |ct| ct.close),
+                    f64_col(&rows, |ct| ct.volume),
+                    f64_col(&rows, |ct| ct.quote_volume),
+                    i64_col(&rows, |ct| ct.trade_count as i64),
+                ]
+            }
+            MarketEvent::Quote(_) => {
+                let rows: Vec<_> = records
+                    .iter()
+                    .filter_map(|r| match &r.e {
+                        MarketEvent::Quote(q) => Some(q),
+                        _ => None,
+                    })
+                    .collect();
+                vec![
+                    i64_col(&rows, |q| q.event_ms),
+                    str_col(&rows, |q| q.pair.to_string()),
+                    f64_col(&rows, |q| q.bid),
+                    f64_col(&rows, |q| q.bid_qty),
+                    f64_col(&rows, |q| q.ask),
+                    f64_col(&rows, |q| q.ask_qty),
+                ]
+            }
+            MarketEvent::OpenInterest(_) => {
+                let rows: Vec<_> = records
+                    .iter()
+                    .filter_map(|r| match &r.e {
+                        MarketEvent::OpenInterest(oi) => Some(oi),
+                        _ => None,
+                    })
+                    .collect();
+                vec![
+                    i64_col(&rows, |oi| oi.event_ms),
+                    str_col(&rows, |oi| oi.pair.to_string()),
+                    f64_col(&rows, |oi| oi.open_interest),
+                ]
+            }
+            MarketEvent::BookCandle(_) | MarketEvent::FundingRate(_) | MarketEvent::OrderbookL3(_) => {
+                return Err(Error::NoSchema)
+            }
+        };
+        RecordBatch::try_new(schema, columns).map_err(|e| {
+            trace!("Error building record batch {:?}", e);
+            Error::Writer
+        })
+    }
+}
+
 impl Handler<Arc<MarketEventEnvelope>> for AvroFileActor<MarketEventEnvelope> {
     type Result = anyhow::Result<()>;
 
@@ -120,7 +318,28 @@ impl Handler<Arc<MarketEventEnvelope>> for AvroFileActor<MarketEventEnvelope> {
                 };
                 self.append_log(&mut writer, candle)
             }
-            MarketEvent::BookCandle(_) => Ok(0),
+            MarketEvent::Quote(q) => {
+                self.metrics.event_lag(now.timestamp_millis() - q.event_ms);
+                let quote = AvroQuote {
+                    pair: q.pair.to_string(),
+                    event_ms: q.event_ms,
+                    bid: q.bid,
+                    bid_qty: q.bid_qty,
+                    ask: q.ask,
+                    ask_qty: q.ask_qty,
+                };
+                self.append_log(&mut writer, quote)
+            }
+            MarketEvent::OpenInterest(oi) => {
+                self.metrics.event_lag(now.timestamp_millis() - oi.event_ms);
+                let open_interest = AvroOpenInterest {
+                    pair: oi.pair.to_string(),
+                    event_ms: oi.event_ms,
+                    open_interest: oi.open_interest,
+                };
+                self.append_log(&mut writer, open_interest)
+            }
+            MarketEvent::BookCandle(_) | MarketEvent::FundingRate(_) | MarketEvent::OrderbookL3(_) => Ok(0),
         };
         if let Err(e) = appended.and_then(|_| writer.flush().map_err(|_e| Error::Writer)) {
             self.metrics.flush_failure();
@@ -3,7 +3,7 @@ use std::collections::HashMap;
 use std::sync::{Arc, RwLock};
 use std::time::Duration;
 
-use actix::{Actor, ActorFutureExt, Addr, AsyncContext, Context, ContextFutureSpawner, Handler, WrapFuture};
+use actix::{Actor, ActorFutureExt, Addr, AsyncContext, Context, ContextFutureSpawner, Handler, Recipient, WrapFuture};
 use chrono::{DateTime, Utc};
 use futures::FutureExt;
 use prometheus::GaugeVec;
@@ -12,10 +12,12 @@ use brokers::bot::Ping;
 use brokers::manager::BrokerageManagerRef;
 use brokers::prelude::*;
 use brokers::types::{AccountPosition, Balance, BalanceUpdate, Balances};
+use trading::order_manager::types::OrderDetail;
 
 #[derive(Clone)]
 pub struct BalanceMetrics {
     asset_gauge: GaugeVec,
+    drift_gauge: GaugeVec,
 }
 
 impl BalanceMetrics {
@@ -30,9 +32,19 @@ impl BalanceMetrics {
             &["xchg", "asset"]
         )
         .unwrap();
+        let drift_metrics: GaugeVec = register_gauge_vec!(
+            opts!(
+                "balance_drift",
+                "Difference between the reported and the expected balance for this asset and account.",
+                const_labels
+            ),
+            &["xchg", "account", "asset"]
+        )
+        .unwrap();
 
         Self {
             asset_gauge: asset_amount_metrics,
+            drift_gauge: drift_metrics,
         }
     }
 
@@ -41,12 +53,86 @@ impl BalanceMetrics {
             .with_label_values(&[xchg.as_ref(), asset.as_ref()])
             .set(amount);
     }
+
+    pub fn balance_drift(&self, xchg: Exchange, account: &AccountType, asset: &Asset, drift: f64) {
+        self.drift_gauge
+            .with_label_values(&[xchg.as_ref(), account.as_ref(), asset.as_ref()])
+            .set(drift);
+    }
 }
 
 impl Default for BalanceMetrics {
     fn default() -> Self { Self::new() }
 }
 
+/// Converts amounts paid in a commission asset (e.g. BNB) to another asset using the latest known rates
+#[derive(Clone, Debug, Default)]
+pub struct FeeConverter {
+    /// Latest price of a base asset, expressed in a quote asset
+    rates: HashMap<(Asset, Asset), f64>,
+}
+
+impl FeeConverter {
+    /// Record the latest price of `base` expressed in `quote`
+    pub fn update_rate(&mut self, base: Asset, quote: Asset, price: f64) {
+        if price > 0.0 {
+            self.rates.insert((base, quote), price);
+        }
+    }
+
+    /// Record the latest price of a market whose pair is formatted as BASE_QUOTE
+    pub fn update_pair_rate(&mut self, pair: &Pair, price: f64) {
+        if let Some((base, quote)) = pair.as_ref().split_once('_') {
+            self.update_rate(base.into(), quote.into(), price);
+        }
+    }
+
+    /// The latest rate to convert `from` into `to`, using the opposite market if that is the only one known
+    pub fn rate(&self, from: &Asset, to: &Asset) -> Option<f64> {
+        if from == to {
+            return Some(1.0);
+        }
+        self.rates
+            .get(&(from.clone(), to.clone()))
+            .copied()
+            .or_else(|| self.rates.get(&(to.clone(), from.clone())).map(|rate| 1.0 / rate))
+    }
+
+    pub fn convert(&self, amount: f64, from: &Asset, to: &Asset) -> Option<f64> {
+        self.rate(from, to).map(|rate| amount * rate)
+    }
+
+    /// Convert the fees of fills paid in an asset that is neither the base nor the quote of the order
+    /// into the quote asset, so that the realized value of the order is net of real fees.
+    /// Fees for which no rate is known are left untouched.
+    ///
+    /// returns: whether any fee was converted
+    pub fn convert_order_fees(&self, order: &mut OrderDetail) -> bool {
+        let quote: Asset = order.quote_asset.as_str().into();
+        let mut converted = false;
+        for fill in &mut order.fills {
+            let Some(fee_asset) = fill.fee_asset.as_ref() else {
+                continue;
+            };
+            if fee_asset == &order.base_asset || fee_asset == &order.quote_asset {
+                continue;
+            }
+            let fee_asset: Asset = fee_asset.as_str().into();
+            match self.convert(fill.fee, &fee_asset, &quote) {
+                Some(fee) => {
+                    fill.fee = fee;
+                    fill.fee_asset = Some(order.quote_asset.clone());
+                    converted = true;
+                }
+                None => {
+                    warn!(order_id = %order.id, fee_asset = %fee_asset, "no rate to convert fees to the quote asset")
+                }
+            }
+        }
+        converted
+    }
+}
+
 #[derive(Default)]
 struct BalanceReport {
     balances: Balances,
@@ -88,6 +174,28 @@ impl BalanceReport {
         }
     }
 
+    /// Compare reported balances (free and locked) to expected ones, an asset diverges when the
+    /// difference exceeds `tolerance` relative to the expected amount
+    fn reconcile(
+        &self,
+        xchg: Exchange,
+        account: &AccountType,
+        expected: &HashMap<Asset, f64>,
+        tolerance: f64,
+    ) -> Vec<BalanceDrift> {
+        expected
+            .iter()
+            .map(|(asset, expected)| BalanceDrift {
+                xchg,
+                account: account.clone(),
+                asset: asset.clone(),
+                reported: self.balances.get(asset).map_or(0.0, |b| b.free + b.locked),
+                expected: *expected,
+            })
+            .filter(|drift| drift.drift().abs() > tolerance * drift.expected.abs())
+            .collect()
+    }
+
     fn reset(&mut self, pos: AccountPosition) {
         match self.server_time {
             Some(server_time) => {
@@ -102,18 +210,49 @@ impl BalanceReport {
     }
 }
 
+/// Relative difference tolerated between reported and expected balances
+const DEFAULT_DRIFT_TOLERANCE: f64 = 0.01;
+
 #[derive(Clone, Debug, Deserialize)]
 pub struct BalanceReporterOptions {
     #[serde(deserialize_with = "util::ser::string_duration")]
     pub refresh_rate: Duration,
+    /// Relative difference tolerated between reported and expected balances before alerting
+    #[serde(default)]
+    pub drift_tolerance: Option<f64>,
+}
+
+/// A divergence between the balance reported by an exchange and the one expected from positions
+#[derive(Clone, Debug, PartialEq)]
+pub struct BalanceDrift {
+    pub xchg: Exchange,
+    pub account: AccountType,
+    pub asset: Asset,
+    pub reported: f64,
+    pub expected: f64,
+}
+
+impl BalanceDrift {
+    pub fn drift(&self) -> f64 { self.reported - self.expected }
+}
+
+/// Balances a portfolio expects an account of an exchange to hold
+#[derive(actix::Message, Clone, Debug)]
+#[rtype(result = "HashMap<Asset, f64>")]
+pub struct ExpectedBalances {
+    pub xchg: Exchange,
+    pub account: AccountType,
 }
 
 #[derive(Clone)]
 pub struct BalanceReporter {
     apis: BrokerageManagerRef,
-    balances: Arc<RwLock<HashMap<Exchange, BalanceReport>>>,
+    balances: Arc<RwLock<HashMap<(Exchange, AccountType), BalanceReport>>>,
     refresh_rate: Duration,
+    drift_tolerance: f64,
     metrics: BalanceMetrics,
+    /// Portfolios whose expected balances are summed and reconciled with the reported ones
+    portfolios: Vec<Recipient<ExpectedBalances>>,
 }
 
 impl BalanceReporter {
@@ -122,26 +261,103 @@ impl BalanceReporter {
             apis,
             balances: Arc::new(RwLock::new(HashMap::default())),
             refresh_rate: options.refresh_rate,
+            drift_tolerance: options.drift_tolerance.unwrap_or(DEFAULT_DRIFT_TOLERANCE),
             metrics: BalanceMetrics::default(),
+            portfolios: vec![],
         }
     }
 
-    pub async fn actor(options: &BalanceReporterOptions, apis: BrokerageManagerRef) -> Addr<Self> {
-        let balance_reporter = Self::new(apis, options);
+    pub async fn actor(
+        options: &BalanceReporterOptions,
+        apis: BrokerageManagerRef,
+        portfolios: Vec<Recipient<ExpectedBalances>>,
+    ) -> Addr<Self> {
+        let balance_reporter = Self::new(apis, options).with_portfolios(portfolios);
         Self::start(balance_reporter)
     }
 
-    fn with_reporter<F>(&self, xchg: Exchange, f: F)
+    /// Reconcile the reported balances with the balances expected by these portfolios
+    #[must_use]
+    pub fn with_portfolios(mut self, portfolios: Vec<Recipient<ExpectedBalances>>) -> Self {
+        self.portfolios = portfolios;
+        self
+    }
+
+    fn with_reporter<F>(&self, xchg: Exchange, account: &AccountType, f: F)
     where
         F: Fn(&mut BalanceReport),
     {
         let mut writer = self.balances.write().unwrap();
-        if writer.get(&xchg).is_none() {
-            writer.insert(xchg, BalanceReport::default());
+        f(writer.entry((xchg, account.clone())).or_default());
+    }
+
+    /// Compare the balances reported by the exchange to the expected ones, alerting for each asset
+    /// that diverges beyond the tolerance
+    pub fn reconcile(
+        &self,
+        xchg: Exchange,
+        account: &AccountType,
+        expected: &HashMap<Asset, f64>,
+    ) -> Vec<BalanceDrift> {
+        let drifts = {
+            let reader = self.balances.read().unwrap();
+            reader
+                .get(&(xchg, account.clone()))
+                .map(|report| report.reconcile(xchg, account, expected, self.drift_tolerance))
+                .unwrap_or_default()
+        };
+        for drift in &drifts {
+            self.metrics.balance_drift(xchg, account, &drift.asset, drift.drift());
+            warn!(
+                xchg = %xchg,
+                account = %account.as_ref(),
+                asset = %drift.asset,
+                reported = drift.reported,
+                expected = drift.expected,
+                "reported balance diverges from expected balance"
+            );
+        }
+        drifts
+    }
+
+    /// Sum the balances the portfolios expect on every reported account, and reconcile them with the reported ones
+    fn reconcile_portfolios(&self, ctx: &mut Context<Self>) {
+        if self.portfolios.is_empty() {
+            return;
         }
-        if let Some(balance_report) = writer.get_mut(&xchg) {
-            f(balance_report);
+        let accounts: Vec<(Exchange, AccountType)> = self.balances.read().unwrap().keys().cloned().collect();
+        let portfolios = self.portfolios.clone();
+        async move {
+            let mut expected = vec![];
+            for (xchg, account) in accounts {
+                let mut balances: HashMap<Asset, f64> = HashMap::new();
+                for portfolio in &portfolios {
+                    match portfolio
+                        .send(ExpectedBalances {
+                            xchg,
+                            account: account.clone(),
+                        })
+                        .await
+                    {
+                        Ok(held) => {
+                            for (asset, amount) in held {
+                                *balances.entry(asset).or_default() += amount;
+                            }
+                        }
+                        Err(e) => warn!(xchg = %xchg, err = %e, "failed to query the expected balances of a portfolio"),
+                    }
+                }
+                expected.push((xchg, account, balances));
+            }
+            expected
         }
+        .into_actor(self)
+        .map(|expected, this, _| {
+            for (xchg, account, balances) in expected {
+                this.reconcile(xchg, &account, &balances);
+            }
+        })
+        .spawn(ctx);
     }
 }
 
@@ -150,15 +366,16 @@ impl Actor for BalanceReporter {
 
     fn started(&mut self, ctx: &mut Self::Context) {
         ctx.notify(RefreshBalances);
-        ctx.run_interval(self.refresh_rate, move |act, _ctx| {
+        ctx.run_interval(self.refresh_rate, move |act, ctx| {
             for api_ref in act.apis.exchange_apis() {
                 let xchg = *api_ref.key();
-                act.with_reporter(xchg, |balance_report| {
+                act.with_reporter(xchg, &AccountType::Spot, |balance_report| {
                     for (asset, amount) in balance_report.balances.clone() {
                         act.metrics.free_amount(xchg, &asset, amount.free);
                     }
                 });
             }
+            act.reconcile_portfolios(ctx);
         });
     }
 }
@@ -167,17 +384,18 @@ impl Handler<AccountEventEnveloppe> for BalanceReporter {
     type Result = anyhow::Result<()>;
 
     fn handle(&mut self, msg: AccountEventEnveloppe, _ctx: &mut Self::Context) -> Self::Result {
-        if msg.account_type != AccountType::Spot {
+        // Isolated margin accounts are not reconciled
+        if !matches!(msg.account_type, AccountType::Spot | AccountType::Margin) {
             return Ok(());
         }
         match msg.event {
             AccountEvent::BalanceUpdate(update) => {
-                self.with_reporter(msg.xchg, |balance_report| {
+                self.with_reporter(msg.xchg, &msg.account_type, |balance_report| {
                     balance_report.push(update.clone());
                 });
             }
             AccountEvent::AccountPositionUpdate(position) => {
-                self.with_reporter(msg.xchg, |balance_report| {
+                self.with_reporter(msg.xchg, &msg.account_type, |balance_report| {
                     balance_report.reset(position.clone());
                 });
             }
@@ -211,7 +429,7 @@ impl Handler<RefreshBalances> for BalanceReporter {
                 for (xchg, balance_result) in balances_results {
                     match balance_result {
                         Ok(balance) => {
-                            this.with_reporter(xchg, |balance_report| {
+                            this.with_reporter(xchg, &AccountType::Spot, |balance_report| {
                                 balance_report.init(&balance);
                             });
                         }
@@ -230,8 +448,132 @@ impl Handler<RefreshBalances> for BalanceReporter {
     }
 }
 
+/// Reconcile the balances reported by an exchange with the expected balances per asset
+#[derive(actix::Message)]
+#[rtype(result = "Vec<BalanceDrift>")]
+pub struct ReconcileBalances {
+    pub xchg: Exchange,
+    pub account: AccountType,
+    pub expected: HashMap<Asset, f64>,
+}
+
+impl Handler<ReconcileBalances> for BalanceReporter {
+    type Result = Vec<BalanceDrift>;
+
+    fn handle(&mut self, msg: ReconcileBalances, _ctx: &mut Self::Context) -> Self::Result {
+        self.reconcile(msg.xchg, &msg.account, &msg.expected)
+    }
+}
+
 impl Handler<Ping> for BalanceReporter {
     type Result = ();
 
     fn handle(&mut self, _msg: Ping, _ctx: &mut Context<Self>) {}
 }
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use chrono::Utc;
+
+    use brokers::manager::BrokerageManager;
+    use brokers::prelude::{Asset, Exchange};
+    use brokers::types::{AccountPosition, AccountType, AddOrderRequest, Balance, Pair, TradeType};
+    use trading::order_manager::types::OrderDetail;
+
+    use crate::balance::{BalanceDrift, BalanceReporter, BalanceReporterOptions, FeeConverter};
+
+    #[test]
+    fn convert_commission_asset_fees_to_quote() {
+        let request = AddOrderRequest {
+            pair: Pair::from("BTC_USDT"),
+            side: TradeType::Sell,
+            quantity: Some(0.1),
+            price: Some(20000.0),
+            ..AddOrderRequest::default()
+        };
+        let mut order = OrderDetail::from_query(request.clone());
+        let mut submission = request.simulate_submission(0.001);
+        submission.trades[0].fee = 0.01;
+        submission.trades[0].fee_asset = "BNB".into();
+        order.from_submission(submission);
+
+        let mut converter = FeeConverter::default();
+        assert!(!converter.convert_order_fees(&mut order));
+        assert_eq!(order.fills[0].fee_asset.as_deref(), Some("BNB"));
+
+        converter.update_pair_rate(&Pair::from("BNB_USDT"), 300.0);
+        assert!(converter.convert_order_fees(&mut order));
+        assert_eq!(order.fills[0].fee_asset.as_deref(), Some("USDT"));
+        assert!(approx_eq!(f64, order.quote_fees(), 3.0, ulps = 2));
+        assert!(approx_eq!(f64, order.realized_quote_value(), 1997.0, ulps = 2));
+    }
+
+    #[test]
+    fn inverse_rate() {
+        let mut converter = FeeConverter::default();
+        converter.update_pair_rate(&Pair::from("USDT_BNB"), 0.004);
+        assert!(approx_eq!(
+            f64,
+            converter.convert(0.01, &"BNB".into(), &"USDT".into()).unwrap(),
+            2.5,
+            ulps = 2
+        ));
+    }
+
+    #[test]
+    fn balance_drift_alert() {
+        let reporter = BalanceReporter::new(Arc::new(BrokerageManager::new()), &BalanceReporterOptions {
+            refresh_rate: Duration::from_secs(60),
+            drift_tolerance: Some(0.01),
+        });
+        let btc: Asset = "BTC".into();
+        let usdt: Asset = "USDT".into();
+        let expected: HashMap<Asset, f64> = HashMap::from([(btc.clone(), 1.0), (usdt.clone(), 1000.0)]);
+        // Nothing was reported yet for this exchange
+        assert!(reporter
+            .reconcile(Exchange::Binance, &AccountType::Spot, &expected)
+            .is_empty());
+
+        reporter.with_reporter(Exchange::Binance, &AccountType::Spot, |report| {
+            report.init(&AccountPosition {
+                balances: HashMap::from([
+                    (btc.clone(), Balance { free: 0.6, locked: 0.4 }),
+                    (usdt.clone(), Balance {
+                        free: 995.0,
+                        locked: 0.0,
+                    }),
+                ]),
+                update_time: Utc::now(),
+            });
+        });
+        assert!(reporter
+            .reconcile(Exchange::Binance, &AccountType::Spot, &expected)
+            .is_empty());
+
+        reporter.with_reporter(Exchange::Binance, &AccountType::Spot, |report| {
+            report.balances.insert(btc.clone(), Balance { free: 0.5, locked: 0.0 });
+        });
+        let drifts = reporter.reconcile(Exchange::Binance, &AccountType::Spot, &expected);
+        assert_eq!(drifts, vec![BalanceDrift {
+            xchg: Exchange::Binance,
+            account: AccountType::Spot,
+            asset: btc.clone(),
+            reported: 0.5,
+            expected: 1.0,
+        }]);
+        let drift_metric = reporter
+            .metrics
+            .drift_gauge
+            .with_label_values(&[Exchange::Binance.as_ref(), AccountType::Spot.as_ref(), btc.as_ref()])
+            .get();
+        assert!(approx_eq!(f64, drift_metric, -0.5, ulps = 2));
+        // Margin balances are reconciled separately
+        assert!(reporter
+            .reconcile(Exchange::Binance, &AccountType::Margin, &expected)
+            .is_empty());
+    }
+}
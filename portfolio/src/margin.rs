@@ -100,6 +100,69 @@ impl Default for MarginAccountMetrics {
     fn default() -> Self { Self::new() }
 }
 
+/// Margin levels at which an account is considered at risk, the margin level being the ratio of total assets to
+/// total liabilities
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq)]
+pub struct MarginThresholds {
+    /// Below this level, a warning is emitted
+    pub warn_level: f64,
+    /// Below this level, positions are reduced before the exchange liquidates them
+    pub deleverage_level: f64,
+    /// Maintenance level of the exchange, below which a margin call is issued
+    pub maintenance_level: f64,
+}
+
+impl Default for MarginThresholds {
+    fn default() -> Self {
+        Self {
+            warn_level: 1.5,
+            deleverage_level: 1.25,
+            maintenance_level: 1.1,
+        }
+    }
+}
+
+impl MarginThresholds {
+    pub fn health(&self, margin_level: f64) -> MarginHealth {
+        if margin_level <= self.maintenance_level {
+            MarginHealth::MarginCall
+        } else if margin_level <= self.deleverage_level {
+            MarginHealth::Deleverage
+        } else if margin_level <= self.warn_level {
+            MarginHealth::Warning
+        } else {
+            MarginHealth::Healthy
+        }
+    }
+}
+
+/// Health of a margin account, from the safest to the closest to liquidation
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum MarginHealth {
+    Healthy,
+    Warning,
+    Deleverage,
+    MarginCall,
+}
+
+/// Margin level of an account, infinite without liabilities
+pub fn margin_level(total: f64, liability: f64) -> f64 {
+    if liability <= 0.0 {
+        f64::INFINITY
+    } else {
+        total / liability
+    }
+}
+
+/// Margin level of an account, as reported by the exchange or computed from its summary otherwise
+pub fn account_margin_level(details: &MarginAccountDetails) -> f64 {
+    if details.summary.margin_level > 0.0 {
+        details.summary.margin_level
+    } else {
+        margin_level(details.summary.total, details.summary.total_liability)
+    }
+}
+
 #[derive(Default)]
 struct MarginAccountReport {
     balances: HashMap<String, MarginAsset>,
@@ -273,3 +336,18 @@ impl Handler<Ping> for MarginAccountReporter {
 
     fn handle(&mut self, _msg: Ping, _ctx: &mut Context<Self>) {}
 }
+
+#[cfg(test)]
+mod test {
+    use crate::margin::{margin_level, MarginHealth, MarginThresholds};
+
+    #[test]
+    fn margin_health_from_level() {
+        let thresholds = MarginThresholds::default();
+        assert_eq!(thresholds.health(margin_level(100.0, 0.0)), MarginHealth::Healthy);
+        assert_eq!(thresholds.health(margin_level(140.0, 100.0)), MarginHealth::Warning);
+        assert_eq!(thresholds.health(margin_level(120.0, 100.0)), MarginHealth::Deleverage);
+        assert_eq!(thresholds.health(margin_level(105.0, 100.0)), MarginHealth::MarginCall);
+        assert!(MarginHealth::Deleverage > MarginHealth::Warning);
+    }
+}
@@ -1,25 +1,31 @@
 use std::collections::btree_map::Entry;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt::Debug;
 use std::str::FromStr;
 use std::sync::Arc;
 
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, TimeZone, Utc};
 use itertools::Itertools;
 use tracing::Level;
 use uuid::Uuid;
 
-use brokers::prelude::{Exchange, TradeType};
-use brokers::types::{AddOrderRequest, MarketEventEnvelope, Pair};
+use brokers::fees::FeeProvider;
+use brokers::manager::BrokerageManager;
+use brokers::prelude::{Exchange, OrderType, TradeType};
+use brokers::types::{AccountType, AddOrderRequest, Asset, AssetType, FundingPayment, FundingRate, MarginLoanRequest,
+                     MarginSideEffect, MarketEvent, MarketEventEnvelope, OrderQuery, Pair, PositionSide, TradeFill};
 use db::{Storage, StorageExt};
 use ext::ResultExt;
+use trading::capital::SharedCapital;
+use trading::funding::FundingLedger;
 use trading::interest::InterestRateProvider;
 use trading::order_manager::types::OrderDetail;
 use trading::position::{Position, PositionKind};
 use trading::signal::TradeSignal;
 
+use crate::balance::FeeConverter;
 use crate::error::*;
-use crate::risk::RiskEvaluator;
+use crate::risk::{DrawdownThrottle, RiskEvaluator, VolatilityTargetSizer};
 
 /// Determines how to handle multiple positions
 pub enum MarketLockRule {
@@ -29,17 +35,44 @@ pub enum MarketLockRule {
     Decoupled,
 }
 
-pub type PositionKey = (Exchange, Pair);
+/// Determines how many positions can be held on a single market
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PositionMode {
+    /// A single net position per market
+    Netting,
+    /// A long and a short position per market, for futures accounts in hedge mode
+    Hedging,
+}
+
+impl Default for PositionMode {
+    fn default() -> Self { Self::Netting }
+}
+
+pub type MarketKey = (Exchange, Pair);
+
+/// Positions are keyed by market and side, the side being [`PositionSide::Both`] when netting
+pub type PositionKey = (Exchange, Pair, PositionSide);
 
 fn pos_key_from_order(order: &OrderDetail) -> Result<PositionKey> {
     Ok((
         Exchange::from_str(&order.exchange)
             .map_err(|_| brokers::error::Error::InvalidExchange(order.exchange.to_string()))?,
         order.symbol.clone().into(),
+        order.position_side.unwrap_or_default(),
     ))
 }
 
-fn pos_key_from_position(pos: &Position) -> PositionKey { (pos.exchange, pos.symbol.clone()) }
+fn pos_key_from_position(pos: &Position) -> PositionKey {
+    (
+        pos.exchange,
+        pos.symbol.clone(),
+        pos.open_order
+            .as_ref()
+            .and_then(|o| o.position_side)
+            .unwrap_or_default(),
+    )
+}
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub struct PositionLock {
@@ -47,6 +80,48 @@ pub struct PositionLock {
     pub order_id: String,
 }
 
+/// Base inventory of a market accumulated by fills outside of positions, such as the fills of the quotes of
+/// market making strategies
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct Inventory {
+    pub xch: Exchange,
+    pub pair: Pair,
+    /// Signed base quantity, negative when short
+    pub qty: f64,
+    /// Average price of the held quantity
+    pub avg_price: f64,
+    /// Latest price of the market
+    pub mark_price: f64,
+}
+
+impl Inventory {
+    /// Notional value at the latest price, negative when short
+    pub fn signed_notional(&self) -> f64 { self.qty * self.mark_price }
+
+    /// Profit or loss of the held quantity at the latest price
+    pub fn unreal_profit_loss(&self) -> f64 { self.qty * (self.mark_price - self.avg_price) }
+
+    /// Add a signed fill at `price`, returns the profit or loss realized by the part that reduced the inventory
+    fn fill(&mut self, qty: f64, price: f64) -> f64 {
+        self.mark_price = price;
+        if self.qty == 0.0 || self.qty.signum() == qty.signum() {
+            self.avg_price = (self.avg_price * self.qty + price * qty) / (self.qty + qty);
+            self.qty += qty;
+            return 0.0;
+        }
+        let reduced = qty.abs().min(self.qty.abs()) * self.qty.signum();
+        let realized = reduced * (price - self.avg_price);
+        self.qty += qty;
+        if self.qty.abs() < f64::EPSILON {
+            self.qty = 0.0;
+        } else if self.qty.signum() == qty.signum() {
+            // The fill flipped the inventory, the remainder was bought or sold at the fill price
+            self.avg_price = price;
+        }
+        realized
+    }
+}
+
 /// A [`Portfolio`] has real time access to accounts, and keeps track of `PnL`,
 /// value, and positions.
 /// [`TradeSignal`]s typically go through the [`Portfolio`] to determine whether or not they
@@ -68,14 +143,72 @@ pub struct Portfolio {
     repo: Arc<dyn PortfolioRepo>,
     risk: Arc<dyn RiskEvaluator>,
     interest_rates: Arc<dyn InterestRateProvider>,
+    /// Fees rate anticipated on exchanges without a fee provider
     fees_rate: f64,
+    /// Fees rates of the tier of the account on each exchange
+    fee_providers: BTreeMap<Exchange, Arc<dyn FeeProvider>>,
     risk_threshold: f64,
+    /// Latest top of the book (bid, ask) seen for each market
+    quotes: BTreeMap<MarketKey, (f64, f64)>,
+    /// Converts fees paid in a commission asset to the quote asset
+    fee_converter: FeeConverter,
+    /// Sizes opened positions to a target volatility instead of allocating the whole value
+    sizer: Option<VolatilityTargetSizer>,
+    /// Reduces the size of opened positions as realized equity draws down
+    throttle: Option<DrawdownThrottle>,
+    /// Equity peak of the drawdown throttle loaded from the repository, restored once a throttle is set
+    drawdown_peak: Option<f64>,
+    position_mode: PositionMode,
+    /// Cumulative funding received by perpetual contract positions, negative when paid
+    funding: f64,
+    /// Cumulative fees paid by executed orders, in the quote asset
+    fees: f64,
+    /// Time in ms of the latest funding payment applied, by exchange
+    funding_watermarks: BTreeMap<Exchange, i64>,
+    /// Settle funding from the funding rate events instead of polling the exchanges, for backtests
+    simulate_funding: bool,
+    /// Mark positions to the prices of the replayed market events instead of the exchange tickers, for backtests
+    simulate_marks: bool,
+    /// Latest price of the market events received, by market
+    last_prices: BTreeMap<MarketKey, f64>,
+    /// Latest announced funding rate and time of the next funding, by market
+    funding_schedule: BTreeMap<MarketKey, (f64, i64)>,
+    /// Splits the funding payments of the account between the portfolios trading it
+    funding_ledger: Option<Arc<FundingLedger>>,
+    /// Margin loans of the positions opened with borrowed assets
+    loans: BTreeMap<PositionKey, MarginLoanRequest>,
+    /// Loans of closed positions waiting to be repaid, with the order the interest accrues since
+    repayments: Vec<(MarginLoanRequest, Option<OrderDetail>)>,
+    /// Capital pool the positions are sized from, instead of the value of the portfolio
+    shared_capital: Option<Arc<SharedCapital>>,
+    /// Inventories accumulated by fills outside of positions, by market
+    inventories: BTreeMap<MarketKey, Inventory>,
+}
+
+/// The account orders of an asset type are placed with, isolated margin accounts are per market
+fn account_of(asset_type: AssetType) -> Option<AccountType> {
+    match asset_type {
+        AssetType::Spot => Some(AccountType::Spot),
+        AssetType::Margin => Some(AccountType::Margin),
+        _ => None,
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct PortfolioVars {
     value: f64,
     pnl: f64,
+    #[serde(default)]
+    funding: f64,
+    #[serde(default)]
+    fees: f64,
+    #[serde(default)]
+    funding_watermarks: BTreeMap<Exchange, i64>,
+    #[serde(default)]
+    inventories: Vec<Inventory>,
+    /// Equity peak of the drawdown throttle
+    #[serde(default)]
+    drawdown_peak: Option<f64>,
 }
 
 impl Portfolio {
@@ -101,6 +234,25 @@ impl Portfolio {
             locks: BTreeMap::default(),
             interest_rates,
             fees_rate,
+            fee_providers: BTreeMap::default(),
+            quotes: BTreeMap::default(),
+            fee_converter: FeeConverter::default(),
+            sizer: None,
+            throttle: None,
+            drawdown_peak: None,
+            position_mode: PositionMode::default(),
+            funding: 0.0,
+            fees: 0.0,
+            funding_watermarks: BTreeMap::default(),
+            simulate_funding: false,
+            simulate_marks: false,
+            last_prices: BTreeMap::default(),
+            funding_schedule: BTreeMap::default(),
+            funding_ledger: None,
+            loans: BTreeMap::default(),
+            repayments: vec![],
+            shared_capital: None,
+            inventories: BTreeMap::default(),
         };
         {
             let arc = p.repo.clone();
@@ -109,10 +261,144 @@ impl Portfolio {
         Ok(p)
     }
 
+    /// Reload the positions, locks and margin loans of the portfolio from its repository, after it was rolled back
+    ///
+    /// # Errors
+    ///
+    /// If the repository fails to load
+    pub fn reload(&mut self) -> Result<()> {
+        self.open_positions.clear();
+        self.locks.clear();
+        self.loans.clear();
+        self.repayments.clear();
+        self.inventories.clear();
+        let repo = self.repo.clone();
+        repo.load(self)
+    }
+
+    /// Size opened positions with `sizer`
+    pub fn with_sizer(mut self, sizer: VolatilityTargetSizer) -> Self {
+        self.sizer = Some(sizer);
+        self
+    }
+
+    /// Throttle the size of opened positions with `throttle`
+    pub fn with_drawdown_throttle(mut self, mut throttle: DrawdownThrottle) -> Self {
+        if let Some(peak) = self.drawdown_peak {
+            throttle.restore(peak, self.pnl);
+        }
+        self.throttle = Some(throttle);
+        self
+    }
+
+    /// Anticipate the fees of orders on `xch` with the rates of `provider` rather than the static fees rate
+    pub fn with_fee_provider(mut self, xch: Exchange, provider: Arc<dyn FeeProvider>) -> Self {
+        self.fee_providers.insert(xch, provider);
+        self
+    }
+
+    /// Hold positions according to `position_mode`
+    pub fn with_position_mode(mut self, position_mode: PositionMode) -> Self {
+        self.position_mode = position_mode;
+        self
+    }
+
+    pub fn position_mode(&self) -> PositionMode { self.position_mode }
+
+    /// Settle funding when a funding rate event passes the announced funding time, instead of
+    /// polling the exchanges for funding payments
+    pub fn with_simulated_funding(mut self) -> Self {
+        self.simulate_funding = true;
+        self
+    }
+
+    /// Mark positions to the latest prices of the replayed market events when marking to market, instead of
+    /// fetching the exchange tickers
+    pub fn with_simulated_marks(mut self) -> Self {
+        self.simulate_marks = true;
+        self
+    }
+
+    /// Apply the share of the funding payments of the account owed to the positions of this portfolio, as
+    /// recorded by a ledger shared with the other portfolios trading the account
+    pub fn with_funding_ledger(mut self, ledger: Arc<FundingLedger>) -> Self {
+        self.funding_ledger = Some(ledger);
+        self
+    }
+
+    /// Size opened positions with the budget allocated to the portfolio by a capital pool shared with other
+    /// portfolios
+    pub fn with_shared_capital(mut self, pool: Arc<SharedCapital>) -> Self {
+        pool.register(&self.key, self.equity());
+        self.shared_capital = Some(pool);
+        self
+    }
+
+    /// The value of the portfolio with open positions at their cost
+    fn equity(&self) -> f64 {
+        self.value
+            - self
+                .open_positions
+                .values()
+                .filter_map(|p| p.open_order.as_ref().map(|o| open_value_change(p.kind, o)))
+                .sum::<f64>()
+    }
+
+    /// Report the capital held by open positions and the realized equity to the shared capital pool
+    fn report_shared_capital(&self) {
+        if let Some(pool) = &self.shared_capital {
+            let committed: f64 = self
+                .open_positions
+                .values()
+                .filter_map(|p| p.open_order.as_ref().map(OrderDetail::quote_value))
+                .chain(self.inventories.values().map(|i| (i.qty * i.avg_price).abs()))
+                .sum();
+            pool.update(&self.key, committed, self.equity());
+        }
+    }
+
+    /// The capital available to open a position, the budget of the shared capital pool if any
+    pub fn allocatable_value(&self) -> f64 {
+        self.shared_capital
+            .as_ref()
+            .map_or(self.value, |pool| pool.budget(&self.key).min(self.value))
+    }
+
+    /// The key of the position a signal of `kind` applies to on `market`
+    fn position_key(&self, (xch, pair): MarketKey, kind: PositionKind) -> PositionKey {
+        let side = match (self.position_mode, kind) {
+            (PositionMode::Netting, _) => PositionSide::Both,
+            (PositionMode::Hedging, PositionKind::Long) => PositionSide::Long,
+            (PositionMode::Hedging, PositionKind::Short) => PositionSide::Short,
+        };
+        (xch, pair, side)
+    }
+
+    /// Keys of the positions held on a market, at most one when netting
+    fn market_position_keys(&self, xch: Exchange, pair: &Pair) -> Vec<PositionKey> {
+        self.open_positions
+            .keys()
+            .filter(|(pos_xch, pos_pair, _)| *pos_xch == xch && pos_pair == pair)
+            .cloned()
+            .collect()
+    }
+
+    /// Multiplier applied to the size of opened positions
+    pub fn size_multiplier(&self) -> f64 { self.throttle.as_ref().map_or(1.0, DrawdownThrottle::multiplier) }
+
     pub fn vars(&self) -> PortfolioVars {
         PortfolioVars {
             value: self.value,
             pnl: self.pnl,
+            funding: self.funding,
+            fees: self.fees,
+            funding_watermarks: self.funding_watermarks.clone(),
+            inventories: self.inventories.values().cloned().collect(),
+            drawdown_peak: self
+                .throttle
+                .as_ref()
+                .map(DrawdownThrottle::peak)
+                .or(self.drawdown_peak),
         }
     }
 
@@ -125,20 +411,17 @@ impl Portfolio {
     #[allow(clippy::missing_panics_doc)]
     pub async fn maybe_convert(&mut self, signal: &TradeSignal) -> Result<Option<AddOrderRequest>> {
         // Determine whether position can be opened or closed
-        let pos_key = signal.xch_and_pair();
-        if self.is_locked(&pos_key) {
+        let market_key = signal.xch_and_pair();
+        let pos_key = self.position_key(market_key.clone(), signal.pos_kind);
+        if self.is_position_locked(&pos_key) {
             return Err(Error::PositionLocked);
         }
-        // TODO: replace with allocator
-        if self.pnl <= 0.0 {
-            return Ok(None);
-        }
         let mut request: AddOrderRequest = if let Some(p) = self.open_positions.get(&pos_key) {
             if signal.op_kind.is_close() {
                 if p.is_opened() {
                     let interests = self.interest_fees_since_open(p.open_order.as_ref()).await?;
                     AddOrderRequest {
-                        quantity: p.close_qty(self.fees_rate, interests),
+                        quantity: p.close_qty(self.position_fees_rate(p), interests),
                         ..signal.into()
                     }
                 } else {
@@ -148,21 +431,58 @@ impl Portfolio {
                 return Err(bad_signal(p, signal));
             }
         } else if signal.op_kind.is_open() {
-            signal.into()
+            // TODO: replace with allocator, positions can always be closed
+            if self.pnl <= 0.0 {
+                return Ok(None);
+            }
+            let multiplier = self.size_multiplier();
+            if multiplier <= 0.0 {
+                return Ok(None);
+            }
+            let mut request: AddOrderRequest = signal.into();
+            request.quantity = request.quantity.map(|qty| qty * multiplier);
+            request
         } else {
             return Err(Error::BadCloseSignal(signal.pos_kind));
         };
-        // Default quantity allocation is portfolio value / price
+        if let (Some(policy), Some((bid, ask))) = (signal.spread_policy, self.quotes.get(&market_key)) {
+            policy.apply(&mut request, *bid, *ask);
+        }
+        // Default quantity allocation is portfolio value / price, scaled to the target volatility if any
         if request.quantity.is_none() {
-            request.quantity = Some(self.value / signal.price);
+            let value = self.allocatable_value();
+            let qty = match &self.sizer {
+                Some(sizer) => sizer.quantity(&market_key, value, signal.price),
+                None => value / signal.price,
+            };
+            request.quantity = Some(qty * self.size_multiplier());
         }
         if request.quantity.unwrap() <= 0.0 {
             return Err(Error::ZeroOrNegativeOrderQty);
         }
+        if self.position_mode == PositionMode::Hedging {
+            request.position_side = Some(pos_key.2);
+        }
         // TODO: Check that cash can be provisionned for pair, this should be compatible with margin trading multiplers
         if self.risk.evaluate(self, &request) > self.risk_threshold {
             return Ok(None);
         }
+        // Loans are sized by the portfolio rather than the exchange, so that they can be repaid in full on close
+        if !request.dry_run && request.asset_type.map_or(false, |t| t.is_margin()) {
+            match request.side_effect_type {
+                Some(MarginSideEffect::MarginBuy) if signal.op_kind.is_open() => {
+                    if let Some(loan) = self.size_loan(&request, signal.price) {
+                        self.repo.set_loan(&pos_key, &loan)?;
+                        self.loans.insert(pos_key.clone(), loan);
+                    }
+                    request.side_effect_type = Some(MarginSideEffect::NoSideEffect);
+                }
+                Some(MarginSideEffect::AutoRepay) if self.loans.contains_key(&pos_key) => {
+                    request.side_effect_type = Some(MarginSideEffect::NoSideEffect);
+                }
+                _ => {}
+            }
+        }
         let lock = PositionLock {
             at: Utc::now(),
             order_id: request.order_id.clone(),
@@ -172,41 +492,74 @@ impl Portfolio {
     }
 
     /// Update the position from an order, closing or opening with the wrong side and kind will
-    /// result in error. The lock will be released if the order is filled
+    /// result in error. Partial fills are accounted as they are executed, the position is opened with
+    /// the first fill. The lock will be released once the order is resolved
     ///
     /// # Errors
     ///
     /// If a lock did not exist or is incompatible for a position corresponding to the order
     pub fn update_position(&mut self, order: &OrderDetail) -> Result<Option<Position>> {
-        let pos_key: PositionKey = pos_key_from_order(order)?;
+        let mut order = order.clone();
+        self.fee_converter.convert_order_fees(&mut order);
+        let pos_key: PositionKey = pos_key_from_order(&order)?;
+        // Orders opening a position with a loan did not borrow by themselves
+        if let Some(loan) = self.loans.get(&pos_key).filter(|loan| loan.order_id == order.id) {
+            if order.borrowed_amount.is_none() {
+                order.borrowed_amount = Some(loan.amount);
+                order.borrowed_asset = Some(loan.asset.to_string());
+            }
+        }
+        let order = &order;
         // TODO: Using SQL could get rid of this, if performance allows
         if let Some(PositionLock { order_id, .. }) = self.locks.get(&pos_key) {
             if order_id != &order.id {
                 return Err(Error::NoLockForOrder);
             }
         }
+        // Quantity and fees of this order already accounted for by a previous update
+        let (accounted_qty, accounted_fees) = self
+            .open_positions
+            .get(&pos_key)
+            .and_then(|pos| {
+                [pos.open_order.as_ref(), pos.close_order.as_ref()]
+                    .into_iter()
+                    .flatten()
+                    .find(|o| o.id == order.id)
+                    .map(|o| (o.total_executed_qty, o.quote_fees()))
+            })
+            .unwrap_or((0.0, 0.0));
+        let progressed = order.total_executed_qty > accounted_qty;
         if let Some(pos) = self.open_positions.get_mut(&pos_key) {
-            // Close
-            if matches!(
+            if pos.open_order.as_ref().map_or(false, |o| o.id == order.id) {
+                // Further fills of the opening order
+                let value_strat_before = self.value;
+                let accounted = pos.open_order.as_ref().map_or(0.0, |o| open_value_change(pos.kind, o));
+                self.value += open_value_change(pos.kind, order) - accounted;
+                pos.fill_open(order);
+                if progressed {
+                    Self::log_position(order, value_strat_before, self.value, pos.kind, pos.quantity);
+                }
+            } else if matches!(
                 (pos.kind, order.side),
                 (PositionKind::Short, TradeType::Buy) | (PositionKind::Long, TradeType::Sell)
             ) && pos.is_opened()
             {
+                // Close
                 let value_strat_before = self.value;
+                let accounted = pos
+                    .close_order
+                    .as_ref()
+                    .filter(|o| o.id == order.id)
+                    .map_or(0.0, |o| close_value_change(pos.kind, o));
                 pos.close(self.value, order);
-                if order.is_filled() {
-                    match pos.kind {
-                        PositionKind::Short => self.value -= order.quote_value(),
-                        PositionKind::Long => self.value += order.realized_quote_value(),
-                    }
-                }
+                self.value += close_value_change(pos.kind, order) - accounted;
                 Self::log_position(order, value_strat_before, self.value, pos.kind, pos.quantity);
             } else {
                 return Err(Error::BadSideForPosition("close", pos.kind, order.side));
             }
         } else {
-            // Open
-            if order.is_filled() {
+            // Open, as soon as part of the order is executed
+            if order.total_executed_qty > 0.0 {
                 let pos = Position::open(order);
                 let qty = pos.quantity;
                 let kind = pos.kind;
@@ -216,10 +569,7 @@ impl Portfolio {
                     (PositionKind::Short, TradeType::Sell) | (PositionKind::Long, TradeType::Buy)
                 ) {
                     let value_strat_before = self.value;
-                    match kind {
-                        PositionKind::Short => self.value += order.realized_quote_value(),
-                        PositionKind::Long => self.value -= order.quote_value(),
-                    }
+                    self.value += open_value_change(kind, order);
                     Self::log_position(order, value_strat_before, self.value, kind, qty);
                 } else {
                     return Err(Error::BadSideForPosition("open", kind, order.side));
@@ -227,30 +577,177 @@ impl Portfolio {
             }
         }
 
+        if progressed {
+            self.fees += (order.quote_fees() - accounted_fees).max(0.0);
+        }
         let mut resp = Ok(None);
         if let Entry::Occupied(pos_entry) = self.open_positions.entry(pos_key.clone()) {
             let pos = pos_entry.get();
-            if order.is_filled() {
+            if order.is_filled() || progressed {
                 resp = Ok(Some(pos.clone()));
                 if pos.is_closed() {
                     self.repo.close_position(pos)?;
+                    if let Some(loan) = self.loans.remove(&pos_key) {
+                        self.repayments.push((loan, pos.open_order.clone()));
+                        self.repo.set_repayments(&self.key, &self.repayments)?;
+                        self.repo.release_loan(&pos_key)?;
+                    }
                     pos_entry.remove();
                     // TODO: this isn't the right way to manage multiple positions, as the pnl should be the sum of all gains and losses
                     if self.open_positions.is_empty() {
                         self.pnl = self.value;
+                        if let Some(throttle) = self.throttle.as_mut() {
+                            throttle.update(self.pnl);
+                        }
                     }
-                } else if pos.is_opened() {
+                } else {
                     self.repo.open_position(pos)?;
                 }
                 self.repo.update_vars(self)?;
             }
-            if order.is_resolved() {
-                self.remove_lock(&pos_key)?;
+        }
+        // Rejected open orders have no position, but must still release the lock and repay what was borrowed
+        if order.is_resolved() && self.is_position_locked(&pos_key) {
+            self.remove_lock(&pos_key)?;
+        }
+        if order.is_resolved()
+            && !self.open_positions.contains_key(&pos_key)
+            && self.loans.get(&pos_key).map_or(false, |loan| loan.order_id == order.id)
+        {
+            if let Some(loan) = self.loans.remove(&pos_key) {
+                self.repayments.push((loan, None));
+                self.repo.set_repayments(&self.key, &self.repayments)?;
+                self.repo.release_loan(&pos_key)?;
             }
         }
+        if progressed {
+            self.report_shared_capital();
+        }
         resp
     }
 
+    /// The loan required by a margin order, shorts borrow the quantity sold and longs the part of the notional
+    /// that the portfolio value does not cover
+    fn size_loan(&self, request: &AddOrderRequest, price: f64) -> Option<MarginLoanRequest> {
+        let (base, quote) = request.pair.split_once('_')?;
+        let qty = request.quantity?;
+        let (asset, amount) = match request.side {
+            TradeType::Sell => (base, qty),
+            TradeType::Buy => (quote, qty * request.price.unwrap_or(price) - self.value),
+        };
+        (amount > 0.0).then(|| MarginLoanRequest {
+            xch: request.xch,
+            asset: asset.into(),
+            amount,
+            isolated_pair: (request.asset_type == Some(AssetType::IsolatedMargin)).then(|| request.pair.clone()),
+            order_id: request.order_id.clone(),
+            account: request.account.clone(),
+        })
+    }
+
+    /// The loan to pass before placing the order `order_id`, if it opens a position with borrowed assets
+    pub fn borrow_query(&self, order_id: &str) -> Option<OrderQuery> {
+        self.loans
+            .values()
+            .find(|loan| loan.order_id == order_id)
+            .cloned()
+            .map(OrderQuery::Borrow)
+    }
+
+    /// Take the repayments of the loans of closed positions, each one including the interest accrued since the
+    /// position was opened
+    ///
+    /// # Errors
+    ///
+    /// If the interest rates are unavailable, the repayments are then kept until the next call
+    pub async fn take_repayments(&mut self) -> Result<Vec<OrderQuery>> {
+        if self.repayments.is_empty() {
+            return Ok(vec![]);
+        }
+        let mut queries = Vec::with_capacity(self.repayments.len());
+        for (loan, open_order) in &self.repayments {
+            let interests = self.interest_fees_since_open(open_order.as_ref()).await?;
+            queries.push(OrderQuery::Repay(MarginLoanRequest {
+                amount: loan.amount + interests,
+                ..loan.clone()
+            }));
+        }
+        self.repayments.clear();
+        self.repo.set_repayments(&self.key, &self.repayments)?;
+        Ok(queries)
+    }
+
+    /// Queue a repayment taken by [`Portfolio::take_repayments`] that could not be passed, its amount already
+    /// includes the interest accrued
+    ///
+    /// # Errors
+    ///
+    /// If the repayments cannot be persisted
+    pub fn requeue_repayment(&mut self, repayment: MarginLoanRequest) -> Result<()> {
+        self.repayments.push((repayment, None));
+        self.repo.set_repayments(&self.key, &self.repayments)
+    }
+
+    /// Record a fill outside of positions in the inventory of its market, the realized profit or loss and fees
+    /// are accounted in the value of the portfolio
+    ///
+    /// # Errors
+    ///
+    /// If the portfolio variables cannot be persisted
+    pub fn record_inventory_fill(
+        &mut self,
+        (xch, pair): MarketKey,
+        side: TradeType,
+        qty: f64,
+        price: f64,
+        fees: f64,
+    ) -> Result<()> {
+        if qty <= 0.0 {
+            return Ok(());
+        }
+        let signed_qty = if side == TradeType::Buy { qty } else { -qty };
+        let realized = self
+            .inventories
+            .entry((xch, pair.clone()))
+            .or_insert_with(|| Inventory {
+                xch,
+                pair,
+                qty: 0.0,
+                avg_price: price,
+                mark_price: price,
+            })
+            .fill(signed_qty, price);
+        self.value += realized - fees;
+        self.fees += fees;
+        self.repo.update_vars(self)?;
+        self.report_shared_capital();
+        Ok(())
+    }
+
+    /// Signed base inventory of a market accumulated by fills outside of positions
+    pub fn inventory(&self, xch: Exchange, pair: Pair) -> f64 {
+        self.inventories.get(&(xch, pair)).map_or(0.0, |i| i.qty)
+    }
+
+    /// Inventories of every market, including flat ones
+    pub fn inventories(&self) -> impl Iterator<Item = &Inventory> { self.inventories.values() }
+
+    /// Same as [`Portfolio::update_position`], but fees are taken from the trades reported by the
+    /// broker for this order when they are available, rather than from estimated fills
+    ///
+    /// # Errors
+    ///
+    /// If a lock did not exist or is incompatible for a position corresponding to the order
+    pub fn update_position_with_trades(
+        &mut self,
+        order: &OrderDetail,
+        trades: &[TradeFill],
+    ) -> Result<Option<Position>> {
+        let mut order = order.clone();
+        order.with_trade_fills(trades);
+        self.update_position(&order)
+    }
+
     fn log_position(
         order: &OrderDetail,
         value_strat_before: f64,
@@ -281,17 +778,211 @@ impl Portfolio {
     ///
     /// Interest rates could not be fetched
     pub async fn update_from_market(&mut self, event: &MarketEventEnvelope) -> Result<()> {
+        if let MarketEvent::FundingRate(rate) = &event.e {
+            if self.simulate_funding {
+                self.settle_funding(event.symbol.xch, rate)?;
+            }
+        }
+        if matches!(
+            &event.e,
+            MarketEvent::FundingRate(FundingRate { mark_price: None, .. })
+                | MarketEvent::OrderbookL3(_)
+                | MarketEvent::OpenInterest(_)
+        ) {
+            return Ok(());
+        }
         // This ugly bit of code is because of the mutable borrow, it should be refactored away
         let pair = event.symbol.value.clone();
         let xch = event.symbol.xch;
-        let interests = if let Some(p) = self.open_positions.get(&(xch, pair.clone())) {
-            let option = p.open_order.as_ref();
-            self.interest_fees_since_open(option).await
-        } else {
+        let quote = match &event.e {
+            MarketEvent::Orderbook(ob) => ob.top_bid().zip(ob.top_ask()).map(|(bid, ask)| (bid.0, ask.0)),
+            MarketEvent::BookCandle(bc) => Some((bc.bid.close, bc.ask.close)),
+            MarketEvent::Quote(q) => Some((q.bid, q.ask)),
+            _ => None,
+        };
+        if let Some(quote) = quote {
+            self.quotes.insert((xch, pair.clone()), quote);
+        }
+        let price = event.e.vwap();
+        if price > 0.0 {
+            self.last_prices.insert((xch, pair.clone()), price);
+        }
+        if let Some(inventory) = self.inventories.get_mut(&(xch, pair.clone())) {
+            inventory.mark_price = price;
+        }
+        self.fee_converter.update_pair_rate(&pair, price);
+        if let Some(sizer) = self.sizer.as_mut() {
+            sizer.update(&(xch, pair.clone()), price, event.ts);
+        }
+        for key in self.market_position_keys(xch, &pair) {
+            let (interests, fees_rate) = match self.open_positions.get(&key) {
+                Some(p) => (
+                    self.interest_fees_since_open(p.open_order.as_ref()).await?,
+                    self.position_fees_rate(p),
+                ),
+                None => continue,
+            };
+            if let Some(p) = self.open_positions.get_mut(&key) {
+                p.update(event, fees_rate, interests);
+            }
+        }
+        Ok(())
+    }
+
+    /// Mark open positions to the ticker prices of their exchange, so that positions whose pair has
+    /// no market subscription do not go stale
+    ///
+    /// # Errors
+    ///
+    /// Interest rates could not be fetched
+    pub async fn mark_to_market(&mut self, brokerages: &BrokerageManager, at: DateTime<Utc>) -> Result<()> {
+        for (xch, pairs) in self.held_pairs() {
+            if self.simulate_marks {
+                let prices: HashMap<Pair, f64> = pairs
+                    .into_iter()
+                    .filter_map(|pair| {
+                        let price = self.last_prices.get(&(xch, pair.clone())).copied();
+                        price.map(|price| (pair, price))
+                    })
+                    .collect();
+                self.mark_positions(xch, &prices, at).await?;
+                continue;
+            }
+            let Some(api) = brokerages.get_api(xch) else {
+                continue;
+            };
+            match api.tickers(pairs).await {
+                Ok(prices) => self.mark_positions(xch, &prices, at).await?,
+                Err(e) => debug!(xch = %xch, err = %e, "failed to fetch tickers"),
+            }
+        }
+        Ok(())
+    }
+
+    /// Apply the funding settled since the previous funding rate event of the market, then record the
+    /// rate announced for the next funding
+    fn settle_funding(&mut self, xch: Exchange, rate: &FundingRate) -> Result<()> {
+        let market_key = (xch, rate.pair.clone());
+        if let Some((settled_rate, funding_ms)) = self.funding_schedule.get(&market_key).copied() {
+            if rate.event_ms >= funding_ms && funding_ms != rate.next_funding_ms {
+                for key in self.market_position_keys(xch, &rate.pair) {
+                    let Some(pos) = self.open_positions.get(&key) else {
+                        continue;
+                    };
+                    let notional = pos.quantity * rate.mark_price.unwrap_or(pos.current_symbol_price);
+                    let amount = match pos.kind {
+                        PositionKind::Long => -settled_rate * notional,
+                        PositionKind::Short => settled_rate * notional,
+                    };
+                    self.apply_funding(xch, &FundingPayment {
+                        time_ms: funding_ms,
+                        pair: rate.pair.clone(),
+                        asset: Asset::default(),
+                        amount,
+                    })?;
+                }
+            }
+        }
+        self.funding_schedule
+            .insert(market_key, (rate.rate, rate.next_funding_ms));
+        Ok(())
+    }
+
+    /// Credit or debit the portfolio value with a funding payment, if a position is held on its market
+    ///
+    /// # Errors
+    ///
+    /// The portfolio variables could not be saved
+    pub fn apply_funding(&mut self, xch: Exchange, payment: &FundingPayment) -> Result<()> {
+        if !self.has_open_position(xch, payment.pair.clone()) {
             return Ok(());
-        }?;
-        if let Some(p) = self.open_positions.get_mut(&(xch, pair.clone())) {
-            p.update(event, self.fees_rate, interests);
+        }
+        self.value += payment.amount;
+        self.funding += payment.amount;
+        let watermark = self.funding_watermarks.entry(xch).or_default();
+        *watermark = (*watermark).max(payment.time_ms);
+        debug!(xch = %xch, pair = %payment.pair, amount = %format!("{:.6}", payment.amount), funding = %format!("{:.6}", self.funding), "funding");
+        self.repo.update_vars(self)
+    }
+
+    /// Fetch and apply the funding payments of the exchanges on which funded contracts are held, since the
+    /// latest payment applied or the opening of the oldest position, nothing is polled if funding is simulated
+    ///
+    /// Payments are reported for the whole account, only the share of the contracts of this portfolio is applied
+    /// if it has a funding ledger.
+    ///
+    /// # Errors
+    ///
+    /// The portfolio variables could not be saved
+    pub async fn poll_funding(&mut self, brokerages: &BrokerageManager) -> Result<()> {
+        if self.simulate_funding {
+            return Ok(());
+        }
+        let mut funded: BTreeMap<Exchange, DateTime<Utc>> = BTreeMap::new();
+        let mut held: BTreeMap<MarketKey, f64> = BTreeMap::new();
+        for pos in self.open_positions.values() {
+            if pos.open_order.as_ref().map_or(false, |o| o.asset_type.is_funded()) {
+                let since = funded.entry(pos.exchange).or_insert(pos.meta.open_at);
+                *since = (*since).min(pos.meta.open_at);
+                *held.entry((pos.exchange, pos.symbol.clone())).or_default() += pos.quantity.abs();
+            }
+        }
+        if let Some(ledger) = self.funding_ledger.as_ref() {
+            ledger.update(&self.key, &held);
+        }
+        for (xch, opened_at) in funded {
+            let Some(api) = brokerages.get_api(xch) else {
+                continue;
+            };
+            let watermark = self.funding_watermarks.get(&xch).copied();
+            let since = watermark
+                .and_then(|ms| Utc.timestamp_millis_opt(ms + 1).single())
+                .unwrap_or(opened_at);
+            match api.funding_payments(since).await {
+                Ok(payments) => {
+                    for payment in payments {
+                        if watermark.map_or(true, |ms| payment.time_ms > ms) {
+                            let share = self
+                                .funding_ledger
+                                .as_ref()
+                                .map_or(1.0, |ledger| ledger.share(&self.key, xch, &payment.pair));
+                            self.apply_funding(xch, &FundingPayment {
+                                amount: payment.amount * share,
+                                ..payment
+                            })?;
+                        }
+                    }
+                }
+                Err(e) => debug!(xch = %xch, err = %e, "failed to fetch funding payments"),
+            }
+        }
+        Ok(())
+    }
+
+    /// Mark the open positions of `xch` to `prices`
+    ///
+    /// # Errors
+    ///
+    /// Interest rates could not be fetched
+    pub async fn mark_positions(
+        &mut self,
+        xch: Exchange,
+        prices: &HashMap<Pair, f64>,
+        at: DateTime<Utc>,
+    ) -> Result<()> {
+        for (pair, price) in prices {
+            for key in self.market_position_keys(xch, pair) {
+                let (interests, fees_rate) = match self.open_positions.get(&key) {
+                    Some(p) => (
+                        self.interest_fees_since_open(p.open_order.as_ref()).await?,
+                        self.position_fees_rate(p),
+                    ),
+                    None => continue,
+                };
+                if let Some(p) = self.open_positions.get_mut(&key) {
+                    p.mark(*price, at, fees_rate, interests);
+                }
+            }
         }
         Ok(())
     }
@@ -308,9 +999,66 @@ impl Portfolio {
         }
     }
 
-    /// True if there is an open position
+    /// Record the latest rate of `base` in `quote`, used to convert fees paid in a commission asset
+    /// for markets the portfolio does not receive events for
+    pub fn update_fee_rate(&mut self, base: Asset, quote: Asset, price: f64) {
+        self.fee_converter.update_rate(base, quote, price);
+    }
+
+    /// Balances expected on an account of an exchange, to reconcile with the balances reported by the exchange :
+    /// base assets of open positions and inventories, negative for shorts sold with borrowed assets, and the cash of
+    /// the portfolio in its quote asset, held in the account of its positions. Isolated margin accounts are not
+    /// reconciled.
+    pub fn expected_balances(&self, xch: Exchange, account: &AccountType) -> HashMap<Asset, f64> {
+        let mut balances: HashMap<Asset, f64> = HashMap::new();
+        let mut cash: Option<(Asset, AccountType)> = None;
+        for position in self.open_positions.values() {
+            if position.exchange != xch || !position.is_opened() {
+                continue;
+            }
+            let Some(order) = position.open_order.as_ref() else {
+                continue;
+            };
+            let Some(position_account) = account_of(order.asset_type) else {
+                continue;
+            };
+            if cash.is_none() {
+                cash = Some((order.quote_asset.as_str().into(), position_account.clone()));
+            }
+            if &position_account != account {
+                continue;
+            }
+            let qty = match position.kind {
+                PositionKind::Long => position.quantity,
+                PositionKind::Short => -position.quantity,
+            };
+            *balances.entry(order.base_asset.as_str().into()).or_default() += qty;
+        }
+        // Inventories are traded on the spot account, and their cost is not deducted from the value
+        let mut inventory_cost = 0.0;
+        for inventory in self.inventories.values().filter(|inventory| inventory.xch == xch) {
+            let Some((base, quote)) = inventory.pair.as_ref().split_once('_') else {
+                continue;
+            };
+            if cash.is_none() {
+                cash = Some((quote.into(), AccountType::Spot));
+            }
+            inventory_cost += inventory.qty * inventory.avg_price;
+            if *account == AccountType::Spot {
+                *balances.entry(base.into()).or_default() += inventory.qty;
+            }
+        }
+        if let Some((quote, cash_account)) = cash {
+            if &cash_account == account {
+                *balances.entry(quote).or_default() += self.value - inventory_cost;
+            }
+        }
+        balances
+    }
+
+    /// True if there is an open position, on either side when hedging
     pub fn has_open_position(&self, xch: Exchange, pair: Pair) -> bool {
-        self.open_positions.get(&(xch, pair)).is_some()
+        !self.market_position_keys(xch, &pair).is_empty()
     }
 
     /// True if there are any open positions
@@ -328,12 +1076,16 @@ impl Portfolio {
     /// # Errors
     ///
     /// The position could not be unlocked or closed (if it was only opened)
-    pub fn unlock_position(&mut self, xch: Exchange, pair: Pair) -> Result<()> {
-        let position_key = (xch, pair);
+    pub fn unlock_position(&mut self, xch: Exchange, pair: Pair, side: PositionSide) -> Result<()> {
+        let position_key = (xch, pair, side);
         match self.locks.get(&position_key) {
             None => Ok(()),
             Some(_) => {
                 self.remove_lock(&position_key)?;
+                // The opening order was never placed, so neither was its loan
+                if !self.open_positions.contains_key(&position_key) && self.loans.remove(&position_key).is_some() {
+                    self.repo.release_loan(&position_key)?;
+                }
                 if let Some(pos) = self.open_positions.get(&position_key) {
                     if pos.is_failed_open() {
                         self.repo.close_position(pos)?;
@@ -350,10 +1102,10 @@ impl Portfolio {
     /// # Panics
     ///
     /// if the position can be closed, unimplemented
-    pub fn force_close(&mut self, xch: Exchange, pair: Pair) -> Result<()> {
-        let position_key = (xch, pair);
+    pub fn force_close(&mut self, xch: Exchange, pair: Pair, side: PositionSide) -> Result<()> {
+        let position_key = (xch, pair, side);
         match self.open_positions.get(&position_key) {
-            Some(pos) if !self.is_locked(&position_key) && pos.is_opened() && !pos.is_closed() => {
+            Some(pos) if !self.is_position_locked(&position_key) && pos.is_opened() && !pos.is_closed() => {
                 unimplemented!();
                 // if let Some(pos) = self.open_positions.get(&position_key) {
                 //     if pos.is_failed_open() {
@@ -367,8 +1119,15 @@ impl Portfolio {
         }
     }
 
+    /// If a lock exists for any position of a market
+    pub fn is_locked(&self, (xch, pair): &MarketKey) -> bool {
+        self.locks
+            .keys()
+            .any(|(lock_xch, lock_pair, _)| lock_xch == xch && lock_pair == pair)
+    }
+
     /// If a lock exists for a [`PositionKey`]
-    pub fn is_locked(&self, key: &PositionKey) -> bool { self.locks.get(key).is_some() }
+    pub fn is_position_locked(&self, key: &PositionKey) -> bool { self.locks.contains_key(key) }
 
     /// Current position locks
     pub fn locks(&self) -> &BTreeMap<PositionKey, PositionLock> { &self.locks }
@@ -388,6 +1147,34 @@ impl Portfolio {
 
     pub fn pnl(&self) -> f64 { self.pnl }
 
+    /// Latest price of the market events received for a market, if any
+    pub fn last_price(&self, key: &MarketKey) -> Option<f64> { self.last_prices.get(key).copied() }
+
+    /// Cumulative funding received, negative when paid
+    pub fn funding(&self) -> f64 { self.funding }
+
+    /// Cumulative fees paid by executed orders, in the quote asset
+    pub fn fees(&self) -> f64 { self.fees }
+
+    /// Fees rate anticipated for orders on exchanges without a fee provider
+    pub fn fees_rate(&self) -> f64 { self.fees_rate }
+
+    /// Fees rate anticipated for an order on `xch`, from the tier of the account if the exchange has a fee provider
+    pub fn fees_rate_for(&self, xch: Exchange, asset_type: Option<AssetType>, order_type: Option<OrderType>) -> f64 {
+        self.fee_providers
+            .get(&xch)
+            .map_or(self.fees_rate, |provider| provider.get_rate(asset_type, order_type).0)
+    }
+
+    /// Positions are closed with market orders
+    fn position_fees_rate(&self, position: &Position) -> f64 {
+        self.fees_rate_for(
+            position.exchange,
+            position.open_order.as_ref().map(|o| o.asset_type),
+            Some(OrderType::Market),
+        )
+    }
+
     pub fn set_value(&mut self, value: f64) -> Result<()> {
         self.value = value;
         self.repo.update_vars(self)
@@ -398,12 +1185,43 @@ impl Portfolio {
         self.repo.update_vars(self)
     }
 
+    /// The open position of a market, either of the two positions when hedging
     pub fn open_position(&self, xch: Exchange, pair: Pair) -> Option<&Position> {
-        self.open_positions.get(&(xch, pair))
+        self.market_position_keys(xch, &pair)
+            .first()
+            .and_then(|key| self.open_positions.get(key))
+    }
+
+    /// Annualized volatility forecast of a market, if positions are volatility targeted
+    pub fn volatility(&self, xch: Exchange, pair: Pair) -> Option<f64> {
+        self.sizer.as_ref().and_then(|sizer| sizer.volatility(&(xch, pair)))
+    }
+
+    /// Share of the pending order of a market executed so far, either the opening or the closing order
+    /// of its position, so that strategies can chase or cancel the remainder
+    pub fn fill_ratio(&self, xch: Exchange, pair: Pair) -> Option<f64> {
+        self.open_position(xch, pair).map(Position::fill_ratio)
+    }
+
+    /// The open position of a market on `side`, which is [`PositionSide::Both`] when netting
+    pub fn open_position_on_side(&self, xch: Exchange, pair: Pair, side: PositionSide) -> Option<&Position> {
+        self.open_positions.get(&(xch, pair, side))
     }
 
     pub fn open_positions(&self) -> &BTreeMap<PositionKey, Position> { &self.open_positions }
 
+    /// Pairs of the open positions, by exchange
+    pub fn held_pairs(&self) -> BTreeMap<Exchange, Vec<Pair>> {
+        let mut pairs: BTreeMap<Exchange, Vec<Pair>> = BTreeMap::new();
+        for (xch, pair, _) in self.open_positions.keys() {
+            let xch_pairs = pairs.entry(*xch).or_default();
+            if !xch_pairs.contains(pair) {
+                xch_pairs.push(pair.clone());
+            }
+        }
+        pairs
+    }
+
     pub fn current_return(&self) -> f64 {
         if self.open_positions.is_empty() {
             0.0
@@ -436,6 +1254,22 @@ impl Portfolio {
     }
 }
 
+/// Change of the portfolio value from the executed part of an opening order
+fn open_value_change(kind: PositionKind, order: &OrderDetail) -> f64 {
+    match kind {
+        PositionKind::Short => order.realized_quote_value(),
+        PositionKind::Long => -order.quote_value(),
+    }
+}
+
+/// Change of the portfolio value from the executed part of a closing order
+fn close_value_change(kind: PositionKind, order: &OrderDetail) -> f64 {
+    match kind {
+        PositionKind::Short => -order.quote_value(),
+        PositionKind::Long => order.realized_quote_value(),
+    }
+}
+
 fn bad_signal(pos: &Position, signal: &TradeSignal) -> Error {
     Error::BadSignal(
         pos.open_order.as_ref().map_or(false, OrderDetail::is_filled),
@@ -465,6 +1299,16 @@ pub trait PortfolioRepo: Debug + Send + Sync {
     fn set_lock(&self, key: &PositionKey, lock: &PositionLock) -> Result<()>;
     /// Release a position lock
     fn release_lock(&self, key: &PositionKey) -> Result<()>;
+    /// Set the margin loan of a position
+    fn set_loan(&self, key: &PositionKey, loan: &MarginLoanRequest) -> Result<()>;
+    /// Release the margin loan of a position, once it is queued for repayment
+    fn release_loan(&self, key: &PositionKey) -> Result<()>;
+    /// Save the loans of a portfolio waiting to be repaid
+    fn set_repayments(
+        &self,
+        portfolio_key: &str,
+        repayments: &[(MarginLoanRequest, Option<OrderDetail>)],
+    ) -> Result<()>;
     /// Update portfolio variables
     fn update_vars(&self, _: &Portfolio) -> Result<()>;
     /// Load a portfolio from storage
@@ -474,6 +1318,8 @@ pub trait PortfolioRepo: Debug + Send + Sync {
 static POSITIONS_TABLE: &str = "positions";
 static OPEN_POSITIONS_INDEX: &str = "open_pos_idx";
 static POSITION_LOCKS_TABLE: &str = "locks";
+static POSITION_LOANS_TABLE: &str = "loans";
+static REPAYMENTS_TABLE: &str = "repayments";
 static PORTFOLIO_VARS: &str = "vars";
 
 /// K/V Store based implementation of the portfolio repository
@@ -489,6 +1335,8 @@ impl PortfolioRepoImpl {
     pub fn new(db: Arc<dyn Storage>) -> Self {
         for table in &[
             POSITION_LOCKS_TABLE,
+            POSITION_LOANS_TABLE,
+            REPAYMENTS_TABLE,
             POSITIONS_TABLE,
             PORTFOLIO_VARS,
             OPEN_POSITIONS_INDEX,
@@ -498,11 +1346,21 @@ impl PortfolioRepoImpl {
         Self { db }
     }
 
-    fn key_string(key: &PositionKey) -> String { format!("{}_{}", key.0, key.1) }
+    /// Net positions keep the key of single position markets, hedged positions are suffixed with their side
+    fn key_string(key: &PositionKey) -> String {
+        match key.2 {
+            PositionSide::Both => format!("{}_{}", key.0, key.1),
+            side => format!("{}_{}:{}", key.0, key.1, side.as_ref()),
+        }
+    }
 
     fn parse_key_string(key: &str) -> PositionKey {
-        let (xch, pair) = key.split_once('_').unwrap();
-        (Exchange::from_str(xch).unwrap(), Pair::from(pair))
+        let (market, side) = match key.rsplit_once(':') {
+            Some((market, side)) => (market, PositionSide::from_str(side).unwrap()),
+            None => (key, PositionSide::Both),
+        };
+        let (xch, pair) = market.split_once('_').unwrap();
+        (Exchange::from_str(xch).unwrap(), Pair::from(pair), side)
     }
 }
 
@@ -565,6 +1423,24 @@ impl PortfolioRepo for PortfolioRepoImpl {
         self.db.delete(POSITION_LOCKS_TABLE, Self::key_string(key)).err_into()
     }
 
+    fn set_loan(&self, key: &PositionKey, loan: &MarginLoanRequest) -> Result<()> {
+        self.db
+            .put(POSITION_LOANS_TABLE, Self::key_string(key), loan)
+            .err_into()
+    }
+
+    fn release_loan(&self, key: &PositionKey) -> Result<()> {
+        self.db.delete(POSITION_LOANS_TABLE, Self::key_string(key)).err_into()
+    }
+
+    fn set_repayments(
+        &self,
+        portfolio_key: &str,
+        repayments: &[(MarginLoanRequest, Option<OrderDetail>)],
+    ) -> Result<()> {
+        self.db.put(REPAYMENTS_TABLE, portfolio_key, repayments).err_into()
+    }
+
     fn update_vars(&self, p: &Portfolio) -> Result<()> {
         let vars = p.vars();
         self.db.put(PORTFOLIO_VARS, &p.key, vars).err_into()
@@ -578,6 +1454,18 @@ impl PortfolioRepo for PortfolioRepoImpl {
         if let Some(vars) = maybe_vars {
             p.pnl = vars.pnl;
             p.value = vars.value;
+            p.funding = vars.funding;
+            p.fees = vars.fees;
+            p.funding_watermarks = vars.funding_watermarks;
+            p.inventories = vars
+                .inventories
+                .into_iter()
+                .map(|inventory| ((inventory.xch, inventory.pair.clone()), inventory))
+                .collect();
+            p.drawdown_peak = vars.drawdown_peak;
+            if let (Some(throttle), Some(peak)) = (p.throttle.as_mut(), vars.drawdown_peak) {
+                throttle.restore(peak, p.pnl);
+            }
         }
         for (pos_id, _) in self.db.get_all::<bool>(OPEN_POSITIONS_INDEX)? {
             let pos_id = Uuid::from_slice(&*pos_id)?;
@@ -592,6 +1480,16 @@ impl PortfolioRepo for PortfolioRepoImpl {
                 .into_iter()
                 .map(|(k, v)| (Self::parse_key_string(std::str::from_utf8(&*k).unwrap()), v)),
         );
+        p.loans.extend(
+            self.db
+                .get_all::<MarginLoanRequest>(POSITION_LOANS_TABLE)?
+                .into_iter()
+                .map(|(k, v)| (Self::parse_key_string(std::str::from_utf8(&*k).unwrap()), v)),
+        );
+        p.repayments = match self.db.get(REPAYMENTS_TABLE, &p.key) {
+            Err(db::Error::NotFound(_)) => Ok(vec![]),
+            r => r,
+        }?;
         Ok(())
     }
 }
@@ -605,7 +1503,7 @@ mod repository_test {
     use chrono::Utc;
     use test_log::test;
 
-    use brokers::types::AddOrderRequest;
+    use brokers::types::{AddOrderRequest, MarginLoanRequest};
     use trading::interest::FlatInterestRateProvider;
     use trading::order_manager::types::OrderDetail;
     use trading::position::Position;
@@ -725,19 +1623,72 @@ mod repository_test {
         expected.insert(pos_key, lock);
         assert_eq!(portfolio.locks, expected);
     }
+
+    #[test(tokio::test)]
+    async fn load_loans_and_repayments() {
+        let repo = make_test_repo();
+        let risk = DefaultMarketRiskEvaluator::default();
+        let mut portfolio = Portfolio::try_new(
+            100.0,
+            0.001,
+            "key".to_string(),
+            Arc::new(repo),
+            Arc::new(risk),
+            Arc::new(FlatInterestRateProvider::new(0.002)),
+        )
+        .unwrap();
+        let arc = portfolio.repo.clone();
+        let pos_key = pos_key_from_position(&Position::default());
+        let loan = MarginLoanRequest {
+            asset: "BTC".into(),
+            amount: 1.0,
+            ..MarginLoanRequest::default()
+        };
+        assert_matches!(arc.set_loan(&pos_key, &loan), Ok(_));
+        let repayment = MarginLoanRequest {
+            amount: 2.0,
+            ..loan.clone()
+        };
+        assert_matches!(portfolio.requeue_repayment(repayment.clone()), Ok(_));
+        portfolio.repayments.clear();
+        assert_matches!(arc.load(&mut portfolio), Ok(_));
+        let mut expected = BTreeMap::new();
+        expected.insert(pos_key.clone(), loan);
+        assert_eq!(portfolio.loans, expected);
+        assert_eq!(portfolio.repayments, vec![(repayment, None)]);
+        assert_matches!(arc.release_loan(&pos_key), Ok(_));
+        portfolio.loans.clear();
+        assert_matches!(arc.load(&mut portfolio), Ok(_));
+        assert!(portfolio.loans.is_empty());
+    }
 }
 
 #[cfg(test)]
 mod portfolio_test {
+    use std::collections::{BTreeMap, HashMap};
     use std::sync::Arc;
 
     use test_log::test;
 
+    use brokers::api::MockBrokerage;
+    use brokers::exchange::Exchange;
+    use brokers::fees::{FeeTier, FlatFeeProvider, TieredFeeProvider};
+    use brokers::manager::{BrokerageManager, BrokerageRegistry};
+    use brokers::types::{AccountType, Asset, AssetType, FundingPayment, FundingRate, MarginSideEffect, MarketEvent,
+                         MarketEventEnvelope, OrderEnforcement, OrderQuery, OrderType, PositionSide, SecurityType,
+                         Symbol, TradeFill, TradeType};
+    use chrono::{Duration, Utc};
+    use db::Storage;
+    use trading::capital::{AllocationPolicy, SharedCapital, SharedCapitalSettings};
+    use trading::funding::FundingLedger;
     use trading::interest::FlatInterestRateProvider;
+    use trading::order_manager::types::{OrderDetail, OrderStatus, Rejection};
+    use trading::position::{OperationKind, PositionKind};
     use trading::signal::TradeSignal;
+    use trading::types::{SpreadOrderPolicy, TradeKind};
 
-    use crate::portfolio::{Portfolio, PortfolioRepoImpl};
-    use crate::risk::DefaultMarketRiskEvaluator;
+    use crate::portfolio::{Portfolio, PortfolioRepo, PortfolioRepoImpl, PositionMode};
+    use crate::risk::{DefaultMarketRiskEvaluator, DrawdownThrottle, DrawdownThrottleOptions};
     use crate::test_util::test_db;
 
     fn make_test_portfolio() -> Portfolio {
@@ -755,6 +1706,31 @@ mod portfolio_test {
         .unwrap()
     }
 
+    #[test(tokio::test)]
+    async fn drawdown_peak_is_restored() {
+        let db = test_db();
+        let portfolio = |db: Arc<dyn Storage>| {
+            Portfolio::try_new(
+                100.0,
+                0.001,
+                "portfolio_key".to_string(),
+                Arc::new(PortfolioRepoImpl::new(db)),
+                Arc::new(DefaultMarketRiskEvaluator::default()),
+                Arc::new(FlatInterestRateProvider::new(0.002)),
+            )
+            .unwrap()
+        };
+        let options = DrawdownThrottleOptions::default();
+        // The equity peaked at 120 before the restart, the portfolio is now at 100
+        let before = portfolio(db.clone()).with_drawdown_throttle(DrawdownThrottle::new(options, 120.0));
+        before.repo.update_vars(&before).unwrap();
+
+        let after = portfolio(db);
+        let after = after.with_drawdown_throttle(DrawdownThrottle::new(options, after.pnl()));
+        assert_eq!(after.size_multiplier(), 0.5);
+        assert_eq!(after.vars().drawdown_peak, Some(120.0));
+    }
+
     #[test(tokio::test)]
     async fn convert_open_signal() {
         let _portfolio = make_test_portfolio();
@@ -762,4 +1738,499 @@ mod portfolio_test {
             ..TradeSignal::default()
         };
     }
+
+    #[test(tokio::test)]
+    async fn convert_signal_with_spread_policy() {
+        let signal = TradeSignal {
+            price: 100.0,
+            qty: Some(0.1),
+            spread_policy: Some(SpreadOrderPolicy::new(0.001)),
+            ..TradeSignal::default()
+        };
+        let symbol = Symbol::new(signal.pair.clone(), SecurityType::Crypto, signal.exchange);
+
+        let mut portfolio = make_test_portfolio();
+        let tight_book =
+            MarketEventEnvelope::order_book_event(symbol.clone(), 0, vec![(100.01, 1.0)], vec![(100.0, 1.0)]);
+        portfolio.update_from_market(&tight_book).await.unwrap();
+        let request = portfolio.maybe_convert(&signal).await.unwrap().unwrap();
+        assert_eq!(request.order_type, OrderType::Market);
+
+        let mut portfolio = make_test_portfolio();
+        let wide_book = MarketEventEnvelope::order_book_event(symbol, 0, vec![(101.0, 1.0)], vec![(100.0, 1.0)]);
+        portfolio.update_from_market(&wide_book).await.unwrap();
+        let request = portfolio.maybe_convert(&signal).await.unwrap().unwrap();
+        assert_eq!(request.order_type, OrderType::Limit);
+        // The limit order does not keep the signal price, it rests at the mid price
+        assert_eq!(request.price, Some(100.5));
+        assert_eq!(request.enforcement, Some(OrderEnforcement::GTC));
+    }
+
+    #[test(tokio::test)]
+    async fn post_only_rejection_unlocks_position() {
+        let mut portfolio = make_test_portfolio();
+        let signal = TradeSignal {
+            price: 100.0,
+            qty: Some(0.1),
+            order_type: OrderType::Limit,
+            post_only: true,
+            ..TradeSignal::default()
+        };
+        let request = portfolio.maybe_convert(&signal).await.unwrap().unwrap();
+        assert!(request.post_only);
+        assert_eq!(request.effective_order_type(), OrderType::LimitMaker);
+        assert!(portfolio.is_locked(&signal.xch_and_pair()));
+
+        let mut order = OrderDetail::from_query(request);
+        assert_eq!(order.order_type, OrderType::LimitMaker);
+        order.from_rejected(Rejection::BadRequest(
+            "Order would immediately match and take.".to_string(),
+        ));
+        let position = portfolio.update_position(&order).unwrap();
+        assert!(position.is_none());
+        assert!(!portfolio.is_locked(&signal.xch_and_pair()));
+        assert!(portfolio.open_positions().is_empty());
+    }
+
+    #[test(tokio::test)]
+    async fn update_position_uses_reported_commissions() {
+        let mut portfolio = make_test_portfolio();
+        let signal = TradeSignal {
+            price: 100.0,
+            qty: Some(0.1),
+            ..TradeSignal::default()
+        };
+        let request = portfolio.maybe_convert(&signal).await.unwrap().unwrap();
+        let mut order = OrderDetail::from_query(request.clone());
+        let mut submission = request.simulate_submission(0.001);
+        submission.id = "100234".to_string();
+        order.from_submission(submission);
+        assert!(order.is_filled());
+        let trades = vec![TradeFill {
+            id: "28457".to_string(),
+            order_id: "100234".to_string(),
+            pair: signal.pair.clone(),
+            price: order.weighted_price,
+            qty: order.total_executed_qty,
+            fee: 0.0015,
+            fee_asset: order.base_asset.as_str().into(),
+            side: TradeType::Buy,
+            is_maker: false,
+            time: 1_499_865_549_590,
+        }];
+        let position = portfolio.update_position_with_trades(&order, &trades).unwrap().unwrap();
+        let open_order = position.open_order.unwrap();
+        assert_eq!(open_order.fills.len(), 1);
+        assert_eq!(open_order.fills[0].fee, 0.0015);
+        assert_ne!(order.fills[0].fee, open_order.fills[0].fee);
+    }
+
+    #[test(tokio::test)]
+    async fn hedging_holds_both_sides() {
+        let long = TradeSignal {
+            price: 100.0,
+            qty: Some(0.1),
+            ..TradeSignal::default()
+        };
+        let short = TradeSignal {
+            pos_kind: PositionKind::Short,
+            trade_kind: TradeKind::Sell,
+            ..long.clone()
+        };
+        let mut portfolio = make_test_portfolio();
+        let request = portfolio.maybe_convert(&long).await.unwrap().unwrap();
+        assert_eq!(request.position_side, None);
+        let mut order = OrderDetail::from_query(request.clone());
+        order.from_submission(request.simulate_submission(0.001));
+        portfolio.update_position(&order).unwrap();
+        // A net position can only be closed
+        assert!(portfolio.maybe_convert(&short).await.is_err());
+
+        let mut portfolio = make_test_portfolio().with_position_mode(PositionMode::Hedging);
+        for signal in [&long, &short] {
+            let request = portfolio.maybe_convert(signal).await.unwrap().unwrap();
+            let mut order = OrderDetail::from_query(request.clone());
+            order.from_submission(request.simulate_submission(0.001));
+            portfolio.update_position(&order).unwrap();
+        }
+        let long_pos = portfolio.open_position_on_side(long.exchange, long.pair.clone(), PositionSide::Long);
+        assert_eq!(long_pos.map(|p| p.kind), Some(PositionKind::Long));
+        let short_pos = portfolio.open_position_on_side(short.exchange, short.pair.clone(), PositionSide::Short);
+        assert_eq!(short_pos.map(|p| p.kind), Some(PositionKind::Short));
+        assert_eq!(portfolio.held_pairs()[&long.exchange], vec![long.pair.clone()]);
+        assert!(!portfolio.is_locked(&long.xch_and_pair()));
+    }
+
+    #[test(tokio::test)]
+    async fn margin_short_borrows_and_repays_with_interest() {
+        let open = TradeSignal {
+            price: 100.0,
+            qty: Some(0.1),
+            pos_kind: PositionKind::Short,
+            trade_kind: TradeKind::Sell,
+            asset_type: Some(AssetType::Margin),
+            side_effect: Some(MarginSideEffect::MarginBuy),
+            ..TradeSignal::default()
+        };
+        let mut portfolio = make_test_portfolio();
+        let request = portfolio.maybe_convert(&open).await.unwrap().unwrap();
+        assert_eq!(request.side_effect_type, Some(MarginSideEffect::NoSideEffect));
+        let Some(OrderQuery::Borrow(loan)) = portfolio.borrow_query(&request.order_id) else {
+            panic!("the short should borrow the quantity sold");
+        };
+        assert_eq!(loan.asset, Asset::from("BTC"));
+        assert!((loan.amount - 0.1).abs() < f64::EPSILON);
+        let mut order = OrderDetail::from_query(request.clone());
+        order.from_submission(request.simulate_submission(0.001));
+        portfolio.update_position(&order).unwrap();
+        assert!(portfolio.take_repayments().await.unwrap().is_empty());
+
+        let close = TradeSignal {
+            op_kind: OperationKind::Close,
+            trade_kind: TradeKind::Buy,
+            side_effect: Some(MarginSideEffect::AutoRepay),
+            ..open
+        };
+        let request = portfolio.maybe_convert(&close).await.unwrap().unwrap();
+        assert_eq!(request.side_effect_type, Some(MarginSideEffect::NoSideEffect));
+        assert!(portfolio.borrow_query(&request.order_id).is_none());
+        let mut order = OrderDetail::from_query(request.clone());
+        order.from_submission(request.simulate_submission(0.001));
+        portfolio.update_position(&order).unwrap();
+        assert!(portfolio.open_positions().is_empty());
+        let repayments = portfolio.take_repayments().await.unwrap();
+        let [OrderQuery::Repay(repayment)] = repayments.as_slice() else {
+            panic!("the loan should be repaid once the short is closed");
+        };
+        assert_eq!(repayment.asset, loan.asset);
+        assert!(repayment.amount > loan.amount);
+        assert!(portfolio.take_repayments().await.unwrap().is_empty());
+    }
+
+    #[test(tokio::test)]
+    async fn partial_fills_open_incrementally() {
+        let mut portfolio = make_test_portfolio();
+        let signal = TradeSignal {
+            price: 100.0,
+            qty: Some(0.1),
+            ..TradeSignal::default()
+        };
+        let request = portfolio.maybe_convert(&signal).await.unwrap().unwrap();
+        let mut partial = OrderDetail::from_query(request.clone());
+        partial.status = OrderStatus::PartiallyFilled;
+        partial.total_executed_qty = 0.05;
+        partial.weighted_price = 100.0;
+        let position = portfolio.update_position(&partial).unwrap().unwrap();
+        assert!(!position.is_opened());
+        assert!((portfolio.value() - 95.0).abs() < 1e-9);
+        assert_eq!(portfolio.fill_ratio(signal.exchange, signal.pair.clone()), Some(0.5));
+        assert!(portfolio.is_locked(&signal.xch_and_pair()));
+        // The same fill is only accounted once
+        assert!(portfolio.update_position(&partial).unwrap().is_none());
+        assert!((portfolio.value() - 95.0).abs() < 1e-9);
+
+        let mut order = OrderDetail::from_query(request.clone());
+        order.from_submission(request.simulate_submission(0.001));
+        let position = portfolio.update_position(&order).unwrap().unwrap();
+        assert!(position.is_opened());
+        assert!((portfolio.value() - 90.0).abs() < 1e-9);
+        assert_eq!(portfolio.fill_ratio(signal.exchange, signal.pair.clone()), Some(1.0));
+        assert!(!portfolio.is_locked(&signal.xch_and_pair()));
+        // Fees are accounted once for the whole order, in the quote asset
+        assert!((portfolio.fees() - 0.01).abs() < 1e-9);
+        portfolio.update_position(&order).unwrap();
+        assert!((portfolio.fees() - 0.01).abs() < 1e-9);
+    }
+
+    #[test(tokio::test)]
+    async fn partial_close_keeps_the_rest_open() {
+        let mut portfolio = make_test_portfolio();
+        let open = TradeSignal {
+            price: 100.0,
+            qty: Some(0.1),
+            ..TradeSignal::default()
+        };
+        let request = portfolio.maybe_convert(&open).await.unwrap().unwrap();
+        let mut order = OrderDetail::from_query(request);
+        order.status = OrderStatus::Filled;
+        order.total_executed_qty = 0.1;
+        order.weighted_price = 100.0;
+        portfolio.update_position(&order).unwrap();
+
+        let close = TradeSignal {
+            op_kind: OperationKind::Close,
+            trade_kind: TradeKind::Sell,
+            price: 110.0,
+            ..open
+        };
+        let request = portfolio.maybe_convert(&close).await.unwrap().unwrap();
+        assert!((request.quantity.unwrap() - 0.1).abs() < 1e-9);
+        let mut canceled = OrderDetail::from_query(request);
+        canceled.status = OrderStatus::Canceled;
+        canceled.total_executed_qty = 0.04;
+        canceled.weighted_price = 110.0;
+        let position = portfolio.update_position(&canceled).unwrap().unwrap();
+        assert!(!position.is_closed());
+        assert!((position.quantity() - 0.06).abs() < 1e-9);
+        assert!((position.result_profit_loss - 0.4).abs() < 1e-9);
+        assert!(!portfolio.is_locked(&close.xch_and_pair()));
+        // The same cancellation is only accounted once
+        let position = portfolio.update_position(&canceled).unwrap().unwrap();
+        assert!((position.quantity() - 0.06).abs() < 1e-9);
+
+        // The next close only sells what is left, against the rest of the entry value
+        let close = TradeSignal { price: 120.0, ..close };
+        let request = portfolio.maybe_convert(&close).await.unwrap().unwrap();
+        assert!((request.quantity.unwrap() - 0.06).abs() < 1e-9);
+        let mut filled = OrderDetail::from_query(request);
+        filled.status = OrderStatus::Filled;
+        filled.total_executed_qty = 0.06;
+        filled.weighted_price = 120.0;
+        let position = portfolio.update_position(&filled).unwrap().unwrap();
+        assert!(position.is_closed());
+        assert!((position.result_profit_loss - 1.6).abs() < 1e-9);
+        assert!(!portfolio.has_any_open_position());
+        assert!((portfolio.value() - 101.6).abs() < 1e-9);
+    }
+
+    #[test(tokio::test)]
+    async fn size_from_shared_capital() {
+        let pool = Arc::new(SharedCapital::new(&SharedCapitalSettings {
+            capital: 100.0,
+            allocation: AllocationPolicy::default(),
+        }));
+        pool.register("other_key", 100.0);
+        let mut portfolio = make_test_portfolio().with_shared_capital(pool.clone());
+        let signal = TradeSignal {
+            price: 100.0,
+            qty: None,
+            ..TradeSignal::default()
+        };
+        let request = portfolio.maybe_convert(&signal).await.unwrap().unwrap();
+        assert_eq!(request.quantity, Some(0.5));
+        let mut order = OrderDetail::from_query(request.clone());
+        order.from_submission(request.simulate_submission(0.0));
+        portfolio.update_position(&order).unwrap();
+        // Half of the pool is held by the open position
+        assert!((pool.budget("other_key") - 25.0).abs() < 1e-9);
+    }
+
+    #[test(tokio::test)]
+    async fn simulated_funding_settles_at_funding_time() {
+        let mut portfolio = make_test_portfolio().with_simulated_funding();
+        let signal = TradeSignal {
+            price: 100.0,
+            qty: Some(0.1),
+            ..TradeSignal::default()
+        };
+        let request = portfolio.maybe_convert(&signal).await.unwrap().unwrap();
+        let mut order = OrderDetail::from_query(request.clone());
+        order.from_submission(request.simulate_submission(0.001));
+        portfolio.update_position(&order).unwrap();
+        let value = portfolio.value();
+        let symbol = Symbol::new(signal.pair.clone(), SecurityType::Crypto, signal.exchange);
+        for (event_ms, rate, next_funding_ms) in [(0, 0.001, 1000), (500, 0.001, 1000), (1000, 0.002, 2000)] {
+            let event = MarketEventEnvelope::new(
+                symbol.clone(),
+                MarketEvent::FundingRate(FundingRate {
+                    event_ms,
+                    pair: signal.pair.clone(),
+                    rate,
+                    next_funding_ms,
+                    mark_price: Some(100.0),
+                }),
+            );
+            portfolio.update_from_market(&event).await.unwrap();
+        }
+        // Longs pay the rate announced before the funding time, once
+        assert!((portfolio.funding() + 0.01).abs() < 1e-9);
+        assert!((portfolio.value() - (value - 0.01)).abs() < 1e-9);
+    }
+
+    #[test(tokio::test)]
+    async fn poll_funding_applies_new_payments() {
+        let mut portfolio = make_test_portfolio();
+        let signal = TradeSignal {
+            price: 100.0,
+            qty: Some(0.1),
+            ..TradeSignal::default()
+        };
+        let mut request = portfolio.maybe_convert(&signal).await.unwrap().unwrap();
+        request.asset_type = Some(AssetType::PerpetualContract);
+        let mut order = OrderDetail::from_query(request.clone());
+        order.from_submission(request.simulate_submission(0.001));
+        portfolio.update_position(&order).unwrap();
+        let value = portfolio.value();
+        let brokerages = BrokerageManager::new_with_reg(BrokerageRegistry::new());
+        brokerages.exchange_apis().insert(
+            signal.exchange,
+            Arc::new(MockBrokerage::with_funding_payments(vec![FundingPayment {
+                time_ms: (Utc::now() + Duration::hours(8)).timestamp_millis(),
+                pair: signal.pair.clone(),
+                asset: "USDT".into(),
+                amount: 0.05,
+            }])),
+        );
+        portfolio.poll_funding(&brokerages).await.unwrap();
+        // Payments already applied are skipped
+        portfolio.poll_funding(&brokerages).await.unwrap();
+        assert!((portfolio.funding() - 0.05).abs() < 1e-9);
+        assert!((portfolio.value() - (value + 0.05)).abs() < 1e-9);
+    }
+
+    #[test(tokio::test)]
+    async fn polled_funding_is_split_with_other_portfolios() {
+        let ledger = Arc::new(FundingLedger::default());
+        let mut portfolio = make_test_portfolio().with_funding_ledger(ledger.clone());
+        let signal = TradeSignal {
+            price: 100.0,
+            qty: Some(0.1),
+            ..TradeSignal::default()
+        };
+        let mut request = portfolio.maybe_convert(&signal).await.unwrap().unwrap();
+        request.asset_type = Some(AssetType::PerpetualContract);
+        let mut order = OrderDetail::from_query(request.clone());
+        order.from_submission(request.simulate_submission(0.001));
+        portfolio.update_position(&order).unwrap();
+        let qty = portfolio
+            .open_position(signal.exchange, signal.pair.clone())
+            .unwrap()
+            .quantity;
+        // Another portfolio holds contracts of the same market on the account
+        ledger.update(
+            "other",
+            &BTreeMap::from([((signal.exchange, signal.pair.clone()), 0.3)]),
+        );
+        let brokerages = BrokerageManager::new_with_reg(BrokerageRegistry::new());
+        brokerages.exchange_apis().insert(
+            signal.exchange,
+            Arc::new(MockBrokerage::with_funding_payments(vec![FundingPayment {
+                time_ms: (Utc::now() + Duration::hours(8)).timestamp_millis(),
+                pair: signal.pair.clone(),
+                asset: "USDT".into(),
+                amount: 0.05,
+            }])),
+        );
+        portfolio.poll_funding(&brokerages).await.unwrap();
+        assert!((portfolio.funding() - 0.05 * qty / (qty + 0.3)).abs() < 1e-9);
+    }
+
+    #[test(tokio::test)]
+    async fn mark_to_market_without_feed() {
+        let mut portfolio = make_test_portfolio();
+        let signal = TradeSignal {
+            price: 100.0,
+            qty: Some(0.1),
+            ..TradeSignal::default()
+        };
+        let request = portfolio.maybe_convert(&signal).await.unwrap().unwrap();
+        let mut order = OrderDetail::from_query(request.clone());
+        order.from_submission(request.simulate_submission(0.001));
+        portfolio.update_position(&order).unwrap();
+        let position = portfolio.open_position(signal.exchange, signal.pair.clone()).unwrap();
+        let stale_pnl = position.unreal_profit_loss;
+
+        // No market event is ever received for the pair, only the ticker sweep marks the position
+        let brokerages = BrokerageManager::new_with_reg(BrokerageRegistry::new());
+        brokerages.exchange_apis().insert(
+            signal.exchange,
+            Arc::new(MockBrokerage::with_prices(HashMap::from([(
+                signal.pair.clone(),
+                110.0,
+            )]))),
+        );
+        let at = Utc::now() + Duration::minutes(5);
+        portfolio.mark_to_market(&brokerages, at).await.unwrap();
+        let position = portfolio.open_position(signal.exchange, signal.pair.clone()).unwrap();
+        assert_eq!(position.current_symbol_price, 110.0);
+        assert_eq!(position.meta.last_update, at);
+        assert!(position.unreal_profit_loss > stale_pnl);
+    }
+
+    #[test(tokio::test)]
+    async fn mark_to_market_with_replayed_prices() {
+        let mut portfolio = make_test_portfolio().with_simulated_marks();
+        let signal = TradeSignal {
+            price: 100.0,
+            qty: Some(0.1),
+            ..TradeSignal::default()
+        };
+        let request = portfolio.maybe_convert(&signal).await.unwrap().unwrap();
+        let mut order = OrderDetail::from_query(request.clone());
+        order.from_submission(request.simulate_submission(0.001));
+        portfolio.update_position(&order).unwrap();
+        let symbol = Symbol::new(signal.pair.clone(), SecurityType::Crypto, signal.exchange);
+        let replayed = MarketEventEnvelope::trade_event(symbol, 0, 105.0, 1.0, TradeType::Buy, None);
+        portfolio.update_from_market(&replayed).await.unwrap();
+
+        // Live tickers are ignored while replaying
+        let brokerages = BrokerageManager::new_with_reg(BrokerageRegistry::new());
+        brokerages.exchange_apis().insert(
+            signal.exchange,
+            Arc::new(MockBrokerage::with_prices(HashMap::from([(
+                signal.pair.clone(),
+                110.0,
+            )]))),
+        );
+        let at = Utc::now() + Duration::minutes(5);
+        portfolio.mark_to_market(&brokerages, at).await.unwrap();
+        let position = portfolio.open_position(signal.exchange, signal.pair.clone()).unwrap();
+        assert_eq!(position.current_symbol_price, 105.0);
+        assert_eq!(position.meta.last_update, at);
+    }
+
+    #[test(tokio::test)]
+    async fn expected_balances_include_cash_and_shorts() {
+        let short = TradeSignal {
+            price: 100.0,
+            qty: Some(0.1),
+            pos_kind: PositionKind::Short,
+            trade_kind: TradeKind::Sell,
+            asset_type: Some(AssetType::Margin),
+            side_effect: Some(MarginSideEffect::MarginBuy),
+            ..TradeSignal::default()
+        };
+        let mut portfolio = make_test_portfolio();
+        let request = portfolio.maybe_convert(&short).await.unwrap().unwrap();
+        let mut order = OrderDetail::from_query(request.clone());
+        order.from_submission(request.simulate_submission(0.001));
+        portfolio.update_position(&order).unwrap();
+        let quantity = portfolio
+            .open_position(short.exchange, short.pair.clone())
+            .unwrap()
+            .quantity;
+
+        let margin = portfolio.expected_balances(short.exchange, &AccountType::Margin);
+        assert_eq!(margin.get(&Asset::from("BTC")), Some(&-quantity));
+        assert_eq!(margin.get(&Asset::from("USDT")), Some(&portfolio.value()));
+        assert!(portfolio
+            .expected_balances(short.exchange, &AccountType::Spot)
+            .is_empty());
+    }
+
+    #[test]
+    fn fees_rate_from_fee_provider() {
+        let provider = Arc::new(TieredFeeProvider::new(Arc::new(FlatFeeProvider::new(0.002, "USDT"))));
+        let portfolio = make_test_portfolio().with_fee_provider(Exchange::Binance, provider.clone());
+        assert_eq!(
+            portfolio.fees_rate_for(Exchange::Kraken, None, Some(OrderType::Market)),
+            0.001
+        );
+        assert_eq!(
+            portfolio.fees_rate_for(Exchange::Binance, None, Some(OrderType::Market)),
+            0.002
+        );
+        provider.set_tier(FeeTier {
+            maker: 0.0002,
+            taker: 0.0004,
+        });
+        assert_eq!(
+            portfolio.fees_rate_for(Exchange::Binance, None, Some(OrderType::Limit)),
+            0.0002
+        );
+        assert_eq!(
+            portfolio.fees_rate_for(Exchange::Binance, None, Some(OrderType::Market)),
+            0.0004
+        );
+    }
 }
@@ -1,7 +1,18 @@
-use brokers::types::AddOrderRequest;
-use std::fmt::Debug;
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::{Debug, Display, Formatter};
+use std::sync::{Arc, Mutex};
 
-use crate::portfolio::Portfolio;
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+
+use brokers::prelude::TradeType;
+use brokers::types::{AddOrderRequest, Pair};
+use db::{Storage, StorageExt};
+use stats::indicators::volatility::VolatilityEstimator;
+use stats::Next;
+use trading::position::Position;
+use trading::sizing::VolatilityTargeted;
+
+use crate::portfolio::{Inventory, MarketKey, Portfolio};
 
 /// Trait to assess risk level associated to an order
 #[async_trait]
@@ -18,3 +29,678 @@ pub struct DefaultMarketRiskEvaluator {}
 impl RiskEvaluator for DefaultMarketRiskEvaluator {
     fn evaluate(&self, _portfolio: &Portfolio, _order: &AddOrderRequest) -> f64 { 0.0 }
 }
+
+/// Limits on the exposure and losses of a portfolio, unset limits are not enforced
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct RiskLimits {
+    /// Maximum notional value held on a single pair, in quote currency
+    pub max_position_notional: Option<f64>,
+    /// Maximum sum of the notional values of open positions
+    pub max_gross_exposure: Option<f64>,
+    /// Maximum difference between the notional values of long and short positions
+    pub max_net_exposure: Option<f64>,
+    /// Maximum loss of equity within a UTC day, trading stops once it is reached
+    pub max_daily_loss: Option<f64>,
+}
+
+impl RiskLimits {
+    /// These limits, replaced by the ones set in `overrides`
+    pub fn with_overrides(self, overrides: &RiskLimits) -> Self {
+        Self {
+            max_position_notional: overrides.max_position_notional.or(self.max_position_notional),
+            max_gross_exposure: overrides.max_gross_exposure.or(self.max_gross_exposure),
+            max_net_exposure: overrides.max_net_exposure.or(self.max_net_exposure),
+            max_daily_loss: overrides.max_daily_loss.or(self.max_daily_loss),
+        }
+    }
+}
+
+/// A risk limit that was or would be exceeded
+#[derive(Debug, Clone, PartialEq)]
+pub enum RiskBreach {
+    PositionNotional { pair: Pair, notional: f64 },
+    GrossExposure(f64),
+    NetExposure(f64),
+    DailyLoss(f64),
+}
+
+impl Display for RiskBreach {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RiskBreach::PositionNotional { pair, notional } => {
+                write!(f, "position notional of {} at {}", pair, notional)
+            }
+            RiskBreach::GrossExposure(exposure) => write!(f, "gross exposure at {}", exposure),
+            RiskBreach::NetExposure(exposure) => write!(f, "net exposure at {}", exposure),
+            RiskBreach::DailyLoss(loss) => write!(f, "daily loss at {}", loss),
+        }
+    }
+}
+
+static RISK_TABLE: &str = "risk";
+
+/// Enforces [`RiskLimits`] on the orders of a portfolio, orders that would exceed an exposure limit are rated
+/// as maximum risk, orders that reduce a position are always allowed
+#[derive(Debug)]
+pub struct RiskEngine {
+    limits: RiskLimits,
+    /// The current UTC day and the equity of the portfolio when it was first checked that day
+    day_open: Mutex<Option<(NaiveDate, f64)>>,
+    /// Keeps the equity of the day open across restarts, with the key of the portfolio
+    storage: Option<(Arc<dyn Storage>, String)>,
+}
+
+impl RiskEngine {
+    pub fn new(limits: RiskLimits) -> Self {
+        Self {
+            limits,
+            day_open: Mutex::new(None),
+            storage: None,
+        }
+    }
+
+    /// Store the equity of the day open of the portfolio `key` in `db`, resuming from the stored one if any
+    ///
+    /// # Panics
+    ///
+    /// if the risk table cannot be ensured
+    pub fn with_storage(mut self, db: Arc<dyn Storage>, key: &str) -> Self {
+        db.ensure_table(RISK_TABLE).unwrap();
+        match db.get::<_, (NaiveDate, f64)>(RISK_TABLE, key) {
+            Ok(day_open) => *self.day_open.get_mut().unwrap() = Some(day_open),
+            Err(db::Error::NotFound(_)) => {}
+            Err(e) => warn!(err = %e, key = %key, "failed to load the day open equity"),
+        }
+        self.storage = Some((db, key.to_string()));
+        self
+    }
+
+    pub fn limits(&self) -> &RiskLimits { &self.limits }
+
+    /// The exposure limit that `order` would exceed if it were filled
+    pub fn check_order(&self, portfolio: &Portfolio, order: &AddOrderRequest) -> Option<RiskBreach> {
+        let positions = portfolio.open_positions().values();
+        let reduces = |p: &&Position| {
+            p.exchange == order.xch
+                && p.symbol == order.pair
+                && matches!(
+                    (p.is_long(), order.side),
+                    (true, TradeType::Sell) | (false, TradeType::Buy)
+                )
+        };
+        let inventories = portfolio.inventories().filter(|i| i.qty != 0.0);
+        let reduces_inventory = |i: &&Inventory| {
+            i.xch == order.xch
+                && i.pair == order.pair
+                && matches!(
+                    (i.qty > 0.0, order.side),
+                    (true, TradeType::Sell) | (false, TradeType::Buy)
+                )
+        };
+        if positions.clone().any(|p| reduces(&p)) || inventories.clone().any(|i| reduces_inventory(&i)) {
+            return None;
+        }
+        // Market orders without a price are valued at the last price of their market
+        let price = order
+            .price
+            .or_else(|| portfolio.last_price(&(order.xch, order.pair.clone())))
+            .unwrap_or(0.0);
+        let notional = order.quantity.unwrap_or(0.0) * price;
+        if let Some(max) = self.limits.max_position_notional {
+            let pair_notional = notional
+                + positions
+                    .clone()
+                    .filter(|p| p.exchange == order.xch && p.symbol == order.pair)
+                    .map(|p| signed_notional(p).abs())
+                    .sum::<f64>()
+                + inventories
+                    .clone()
+                    .filter(|i| i.xch == order.xch && i.pair == order.pair)
+                    .map(|i| i.signed_notional().abs())
+                    .sum::<f64>();
+            if pair_notional > max {
+                return Some(RiskBreach::PositionNotional {
+                    pair: order.pair.clone(),
+                    notional: pair_notional,
+                });
+            }
+        }
+        let order_notional = if order.side == TradeType::Buy {
+            notional
+        } else {
+            -notional
+        };
+        let (gross, net) = positions
+            .map(signed_notional)
+            .chain(inventories.map(Inventory::signed_notional))
+            .fold((notional, order_notional), |(gross, net), position_notional| {
+                (gross + position_notional.abs(), net + position_notional)
+            });
+        if self.limits.max_gross_exposure.map_or(false, |max| gross > max) {
+            return Some(RiskBreach::GrossExposure(gross));
+        }
+        if self.limits.max_net_exposure.map_or(false, |max| net.abs() > max) {
+            return Some(RiskBreach::NetExposure(net));
+        }
+        None
+    }
+
+    /// The daily loss of the portfolio at `at` if it exceeds the limit, losses are measured from the equity
+    /// of the first check of each UTC day
+    #[allow(clippy::missing_panics_doc)]
+    pub fn check_daily_loss(&self, portfolio: &Portfolio, at: DateTime<Utc>) -> Option<RiskBreach> {
+        let max = self.limits.max_daily_loss?;
+        let equity = portfolio.value()
+            + portfolio
+                .open_positions()
+                .values()
+                .map(|p| p.unreal_profit_loss)
+                .sum::<f64>()
+            + portfolio.inventories().map(Inventory::unreal_profit_loss).sum::<f64>();
+        let mut day_open = self.day_open.lock().unwrap();
+        let day = at.date_naive();
+        let open_equity = match *day_open {
+            Some((open_day, open_equity)) if open_day == day => open_equity,
+            _ => {
+                *day_open = Some((day, equity));
+                if let Some((db, key)) = self.storage.as_ref() {
+                    if let Err(e) = db.put(RISK_TABLE, key, (day, equity)) {
+                        warn!(err = %e, key = %key, "failed to store the day open equity");
+                    }
+                }
+                equity
+            }
+        };
+        let loss = open_equity - equity;
+        (loss > max).then_some(RiskBreach::DailyLoss(loss))
+    }
+}
+
+#[async_trait]
+impl RiskEvaluator for RiskEngine {
+    fn evaluate(&self, portfolio: &Portfolio, order: &AddOrderRequest) -> f64 {
+        match self.check_order(portfolio, order) {
+            Some(breach) => {
+                warn!(breach = %breach, order_id = %order.order_id, "order rejected by risk limits");
+                1.0
+            }
+            None => 0.0,
+        }
+    }
+}
+
+/// Notional value of a position at its current price, negative for short positions
+fn signed_notional(position: &Position) -> f64 {
+    let notional = position.quantity() * position.current_symbol_price;
+    if position.is_short() {
+        -notional
+    } else {
+        notional
+    }
+}
+
+/// Annualized volatility of markets, from a rolling estimate of the volatility of returns sampled at a fixed interval,
+/// or from the forecast of a volatility estimator
+#[derive(Debug, Clone)]
+pub struct MarketVolatility {
+    /// Number of returns in the rolling estimate
+    window: usize,
+    /// Interval between two sampled prices
+    sample_freq: Duration,
+    /// Forecasts the volatility of the next sampled return instead of the rolling estimate, if set
+    estimator: Option<VolatilityEstimator>,
+    samples: BTreeMap<MarketKey, PriceSamples>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct PriceSamples {
+    last: Option<(DateTime<Utc>, f64)>,
+    returns: VecDeque<f64>,
+    estimator: Option<VolatilityEstimator>,
+}
+
+impl Default for MarketVolatility {
+    fn default() -> Self { Self::new(Self::DEFAULT_WINDOW, Duration::minutes(Self::DEFAULT_SAMPLE_MINUTES)) }
+}
+
+impl MarketVolatility {
+    /// Default number of sampled returns in the volatility estimate
+    pub const DEFAULT_WINDOW: usize = 288;
+    /// Default interval between sampled prices, with the default window the estimate covers a day
+    pub const DEFAULT_SAMPLE_MINUTES: i64 = 5;
+
+    pub fn new(window: usize, sample_freq: Duration) -> Self {
+        Self {
+            window,
+            sample_freq,
+            estimator: None,
+            samples: BTreeMap::default(),
+        }
+    }
+
+    /// Forecast volatilities with `estimator`, updated with the sampled returns of each market
+    pub fn with_estimator(mut self, estimator: VolatilityEstimator) -> Self {
+        self.estimator = Some(estimator);
+        self
+    }
+
+    /// Record the price of a market at `at`, a return is sampled once `sample_freq` has elapsed since the last one
+    pub fn update(&mut self, key: &MarketKey, price: f64, at: DateTime<Utc>) {
+        if price <= 0.0 || !price.is_finite() {
+            return;
+        }
+        let estimator = self.estimator;
+        let samples = self.samples.entry(key.clone()).or_insert_with(|| PriceSamples {
+            estimator,
+            ..PriceSamples::default()
+        });
+        match samples.last {
+            Some((last_at, _)) if at - last_at < self.sample_freq => {}
+            Some((_, last_price)) => {
+                let ret = (price / last_price).ln();
+                if let Some(estimator) = samples.estimator.as_mut() {
+                    estimator.next(ret);
+                }
+                samples.returns.push_back(ret);
+                if samples.returns.len() > self.window {
+                    samples.returns.pop_front();
+                }
+                samples.last = Some((at, price));
+            }
+            None => samples.last = Some((at, price)),
+        }
+    }
+
+    /// Annualized volatility of the sampled returns of a market, if at least two returns were sampled
+    #[allow(clippy::cast_precision_loss)]
+    pub fn volatility(&self, key: &MarketKey) -> Option<f64> {
+        let samples = self.samples.get(key)?;
+        let returns = &samples.returns;
+        if returns.len() < 2 {
+            return None;
+        }
+        let periods_per_year = Duration::days(365).num_seconds() as f64 / self.sample_freq.num_seconds() as f64;
+        if let Some(estimator) = samples.estimator.as_ref() {
+            return Some(estimator.forecast(1) * periods_per_year.sqrt());
+        }
+        let n = returns.len() as f64;
+        let mean = returns.iter().sum::<f64>() / n;
+        let variance = returns.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / (n - 1.0);
+        Some((variance * periods_per_year).sqrt())
+    }
+}
+
+/// Sizes positions so that each targets an annualized volatility, see [`MarketVolatility`].
+/// Positions are scaled down in turbulent regimes and up to `max_leverage` in calm ones.
+#[derive(Debug, Clone)]
+pub struct VolatilityTargetSizer {
+    /// Targets an annualized volatility with each position, allocating at most the whole portfolio value
+    targeted: VolatilityTargeted,
+    volatility: MarketVolatility,
+}
+
+impl VolatilityTargetSizer {
+    pub fn new(target_vol: f64) -> Self { Self::with_volatility(target_vol, MarketVolatility::default()) }
+
+    pub fn with_window(target_vol: f64, window: usize, sample_freq: Duration) -> Self {
+        Self::with_volatility(target_vol, MarketVolatility::new(window, sample_freq))
+    }
+
+    pub fn with_volatility(target_vol: f64, volatility: MarketVolatility) -> Self {
+        Self {
+            targeted: VolatilityTargeted::new(target_vol, 1.0),
+            volatility,
+        }
+    }
+
+    /// Forecast volatilities with `estimator`, updated with the sampled returns of each market
+    pub fn with_estimator(mut self, estimator: VolatilityEstimator) -> Self {
+        self.volatility = self.volatility.with_estimator(estimator);
+        self
+    }
+
+    /// Record the price of a market at `at`, see [`MarketVolatility::update`]
+    pub fn update(&mut self, key: &MarketKey, price: f64, at: DateTime<Utc>) { self.volatility.update(key, price, at) }
+
+    /// Annualized volatility of a market, see [`MarketVolatility::volatility`]
+    pub fn volatility(&self, key: &MarketKey) -> Option<f64> { self.volatility.volatility(key) }
+
+    /// Fraction of the portfolio value to allocate to a position on a market, the whole value until the
+    /// volatility can be estimated
+    pub fn allocation(&self, key: &MarketKey) -> f64 { self.targeted.allocation(self.volatility(key), 1) }
+
+    /// Quantity to order for a position on a market at `price`
+    pub fn quantity(&self, key: &MarketKey, value: f64, price: f64) -> f64 { value * self.allocation(key) / price }
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq)]
+pub struct DrawdownThrottleOptions {
+    /// Drawdown from the equity peak at which position sizes are reduced
+    pub reduce_at: f64,
+    /// Multiplier of position sizes once reduced
+    pub reduced_size: f64,
+    /// Drawdown from the equity peak at which no position is opened
+    pub stop_at: f64,
+}
+
+impl Default for DrawdownThrottleOptions {
+    fn default() -> Self {
+        Self {
+            reduce_at: 0.1,
+            reduced_size: 0.5,
+            stop_at: 0.2,
+        }
+    }
+}
+
+/// Reduces position sizes as the drawdown of realized equity deepens, and restores them as it recovers
+#[derive(Debug, Clone)]
+pub struct DrawdownThrottle {
+    options: DrawdownThrottleOptions,
+    peak: f64,
+    drawdown: f64,
+}
+
+impl DrawdownThrottle {
+    pub fn new(options: DrawdownThrottleOptions, equity: f64) -> Self {
+        Self {
+            options,
+            peak: equity,
+            drawdown: 0.0,
+        }
+    }
+
+    /// Record the latest realized equity, returns the new sizing multiplier
+    pub fn update(&mut self, equity: f64) -> f64 {
+        self.peak = self.peak.max(equity);
+        self.drawdown = if self.peak > 0.0 { 1.0 - equity / self.peak } else { 0.0 };
+        self.multiplier()
+    }
+
+    /// Current drawdown from the equity peak, as a fraction of the peak
+    pub fn drawdown(&self) -> f64 { self.drawdown }
+
+    /// Highest realized equity recorded
+    pub fn peak(&self) -> f64 { self.peak }
+
+    /// Resume from the equity `peak` recorded before a restart, returns the sizing multiplier at `equity`
+    pub fn restore(&mut self, peak: f64, equity: f64) -> f64 {
+        self.peak = peak;
+        self.update(equity)
+    }
+
+    /// Multiplier of position sizes for the current drawdown, between 0 (stopped) and 1
+    pub fn multiplier(&self) -> f64 {
+        if self.drawdown >= self.options.stop_at {
+            0.0
+        } else if self.drawdown >= self.options.reduce_at {
+            self.options.reduced_size
+        } else {
+            1.0
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use chrono::{Duration, TimeZone, Utc};
+
+    use brokers::prelude::{Exchange, TradeType};
+    use brokers::types::{AddOrderRequest, MarketEventEnvelope, OrderType, SecurityType, Symbol};
+    use trading::interest::FlatInterestRateProvider;
+
+    use crate::portfolio::{MarketKey, Portfolio, PortfolioRepoImpl};
+    use crate::risk::{DefaultMarketRiskEvaluator, DrawdownThrottle, DrawdownThrottleOptions, RiskBreach, RiskEngine,
+                      RiskEvaluator, RiskLimits, VolatilityTargetSizer};
+    use crate::test_util::test_db;
+    use stats::indicators::volatility::{EwmaVolatility, VolatilityEstimator};
+
+    /// Feed prices alternating between 100 and 100 * (1 + amplitude) every hour, from `hour`
+    fn feed(sizer: &mut VolatilityTargetSizer, key: &MarketKey, hour: i64, amplitude: f64, samples: i64) -> i64 {
+        let start = Utc.timestamp_opt(0, 0).unwrap();
+        for i in hour..hour + samples {
+            let price = if i % 2 == 0 { 100.0 } else { 100.0 * (1.0 + amplitude) };
+            let at = start + Duration::hours(i);
+            sizer.update(key, price, at);
+            // Prices within a sample interval are not sampled
+            sizer.update(key, 1000.0, at + Duration::minutes(30));
+        }
+        hour + samples
+    }
+
+    #[test]
+    fn position_size_shrinks_when_volatility_rises() {
+        let key: MarketKey = (Exchange::Binance, "BTC_USDT".into());
+        let mut sizer = VolatilityTargetSizer::with_window(0.2, 10, Duration::hours(1));
+        assert!(approx_eq!(f64, sizer.quantity(&key, 1000.0, 100.0), 10.0));
+
+        let hour = feed(&mut sizer, &key, 0, 0.001, 11);
+        let calm_vol = sizer.volatility(&key).unwrap();
+        let calm_qty = sizer.quantity(&key, 1000.0, 100.0);
+        assert!(calm_qty > 0.0 && calm_qty <= 10.0);
+
+        feed(&mut sizer, &key, hour, 0.01, 11);
+        let turbulent_vol = sizer.volatility(&key).unwrap();
+        let turbulent_qty = sizer.quantity(&key, 1000.0, 100.0);
+        assert!(turbulent_vol > calm_vol);
+        assert!(turbulent_qty < calm_qty);
+        assert!(approx_eq!(
+            f64,
+            turbulent_qty,
+            1000.0 * 0.2 / turbulent_vol / 100.0,
+            epsilon = 1e-9
+        ));
+    }
+
+    #[test]
+    fn size_with_volatility_forecast() {
+        let key: MarketKey = (Exchange::Binance, "BTC_USDT".into());
+        let estimator = EwmaVolatility::new(0.9).unwrap();
+        let mut sizer = VolatilityTargetSizer::with_window(0.2, 10, Duration::hours(1))
+            .with_estimator(VolatilityEstimator::Ewma(estimator));
+        feed(&mut sizer, &key, 0, 0.01, 3);
+        // Returns of +ln(1.01) then -ln(1.01)
+        let variance = 1.01_f64.ln().powi(2);
+        let periods_per_year = 365.0 * 24.0;
+        assert!(approx_eq!(
+            f64,
+            sizer.volatility(&key).unwrap(),
+            (variance * periods_per_year).sqrt(),
+            epsilon = 1e-9
+        ));
+    }
+
+    #[test]
+    fn throttle_tracks_drawdown() {
+        let mut throttle = DrawdownThrottle::new(DrawdownThrottleOptions::default(), 100.0);
+        // (realized equity, expected multiplier)
+        let curve = [
+            (100.0, 1.0),
+            (110.0, 1.0),
+            (100.0, 1.0),
+            (98.0, 0.5),
+            (90.0, 0.5),
+            (87.0, 0.0),
+            (80.0, 0.0),
+            (92.0, 0.5),
+            (100.0, 1.0),
+            (120.0, 1.0),
+            (107.0, 0.5),
+        ];
+        for (equity, multiplier) in curve {
+            assert!(
+                approx_eq!(f64, throttle.update(equity), multiplier),
+                "equity {} drawdown {}",
+                equity,
+                throttle.drawdown()
+            );
+        }
+    }
+
+    #[test]
+    fn risk_engine_enforces_limits() {
+        let engine = RiskEngine::new(RiskLimits {
+            max_position_notional: Some(500.0),
+            max_daily_loss: Some(10.0),
+            ..RiskLimits::default()
+        });
+        let mut portfolio = Portfolio::try_new(
+            1000.0,
+            0.001,
+            "risk".to_string(),
+            Arc::new(PortfolioRepoImpl::new(test_db())),
+            Arc::new(DefaultMarketRiskEvaluator::default()),
+            Arc::new(FlatInterestRateProvider::new(0.0)),
+        )
+        .unwrap();
+        let order = |qty| AddOrderRequest {
+            xch: Exchange::Binance,
+            pair: "BTC_USDT".into(),
+            side: TradeType::Buy,
+            quantity: Some(qty),
+            price: Some(100.0),
+            ..AddOrderRequest::default()
+        };
+        assert_eq!(engine.check_order(&portfolio, &order(4.0)), None);
+        assert!(matches!(
+            engine.check_order(&portfolio, &order(6.0)),
+            Some(RiskBreach::PositionNotional { .. })
+        ));
+        assert!(approx_eq!(f64, engine.evaluate(&portfolio, &order(6.0)), 1.0));
+
+        let day = Utc.with_ymd_and_hms(2022, 1, 1, 0, 0, 0).unwrap();
+        assert_eq!(engine.check_daily_loss(&portfolio, day), None);
+        portfolio.set_value(985.0).unwrap();
+        assert_eq!(
+            engine.check_daily_loss(&portfolio, day + Duration::hours(1)),
+            Some(RiskBreach::DailyLoss(15.0))
+        );
+        // Losses are measured from the equity of the next day onwards
+        assert_eq!(engine.check_daily_loss(&portfolio, day + Duration::days(1)), None);
+    }
+
+    #[test]
+    fn risk_engine_keeps_day_open_across_restarts() {
+        let limits = RiskLimits {
+            max_daily_loss: Some(10.0),
+            ..RiskLimits::default()
+        };
+        let db = test_db();
+        let mut portfolio = Portfolio::try_new(
+            1000.0,
+            0.001,
+            "risk".to_string(),
+            Arc::new(PortfolioRepoImpl::new(test_db())),
+            Arc::new(DefaultMarketRiskEvaluator::default()),
+            Arc::new(FlatInterestRateProvider::new(0.0)),
+        )
+        .unwrap();
+        let day = Utc.with_ymd_and_hms(2022, 1, 1, 0, 0, 0).unwrap();
+        let engine = RiskEngine::new(limits).with_storage(db.clone(), "risk");
+        assert_eq!(engine.check_daily_loss(&portfolio, day), None);
+
+        portfolio.set_value(985.0).unwrap();
+        let restarted = RiskEngine::new(limits).with_storage(db, "risk");
+        assert_eq!(
+            restarted.check_daily_loss(&portfolio, day + Duration::hours(1)),
+            Some(RiskBreach::DailyLoss(15.0))
+        );
+    }
+
+    #[tokio::test]
+    async fn risk_engine_values_market_orders_at_the_last_price() {
+        let engine = RiskEngine::new(RiskLimits {
+            max_position_notional: Some(500.0),
+            ..RiskLimits::default()
+        });
+        let mut portfolio = Portfolio::try_new(
+            1000.0,
+            0.001,
+            "risk".to_string(),
+            Arc::new(PortfolioRepoImpl::new(test_db())),
+            Arc::new(DefaultMarketRiskEvaluator::default()),
+            Arc::new(FlatInterestRateProvider::new(0.0)),
+        )
+        .unwrap();
+        let symbol = Symbol::new("BTC_USDT".into(), SecurityType::Crypto, Exchange::Binance);
+        let trade = MarketEventEnvelope::trade_event(symbol, 0, 100.0, 1.0, TradeType::Buy, None);
+        portfolio.update_from_market(&trade).await.unwrap();
+        let market_order = AddOrderRequest {
+            xch: Exchange::Binance,
+            pair: "BTC_USDT".into(),
+            side: TradeType::Buy,
+            order_type: OrderType::Market,
+            quantity: Some(6.0),
+            price: None,
+            ..AddOrderRequest::default()
+        };
+        assert!(matches!(
+            engine.check_order(&portfolio, &market_order),
+            Some(RiskBreach::PositionNotional { notional, .. }) if approx_eq!(f64, notional, 600.0)
+        ));
+    }
+
+    #[test]
+    fn risk_engine_counts_inventories() {
+        let engine = RiskEngine::new(RiskLimits {
+            max_position_notional: Some(500.0),
+            ..RiskLimits::default()
+        });
+        let mut portfolio = Portfolio::try_new(
+            1000.0,
+            0.001,
+            "risk".to_string(),
+            Arc::new(PortfolioRepoImpl::new(test_db())),
+            Arc::new(DefaultMarketRiskEvaluator::default()),
+            Arc::new(FlatInterestRateProvider::new(0.0)),
+        )
+        .unwrap();
+        let market = (Exchange::Binance, "BTC_USDT".into());
+        portfolio
+            .record_inventory_fill(market.clone(), TradeType::Buy, 3.0, 100.0, 0.3)
+            .unwrap();
+        let order = |side, qty| AddOrderRequest {
+            xch: Exchange::Binance,
+            pair: "BTC_USDT".into(),
+            side,
+            quantity: Some(qty),
+            price: Some(100.0),
+            ..AddOrderRequest::default()
+        };
+        assert!(matches!(
+            engine.check_order(&portfolio, &order(TradeType::Buy, 3.0)),
+            Some(RiskBreach::PositionNotional { notional, .. }) if approx_eq!(f64, notional, 600.0)
+        ));
+        // Orders reducing the inventory are allowed
+        assert_eq!(engine.check_order(&portfolio, &order(TradeType::Sell, 3.0)), None);
+        // Fills reducing the inventory realize its profit or loss
+        portfolio
+            .record_inventory_fill(market, TradeType::Sell, 2.0, 110.0, 0.2)
+            .unwrap();
+        assert!(approx_eq!(
+            f64,
+            portfolio.inventory(Exchange::Binance, "BTC_USDT".into()),
+            1.0
+        ));
+        assert!(approx_eq!(f64, portfolio.value(), 1000.0 + 20.0 - 0.5));
+    }
+
+    #[test]
+    fn risk_limit_overrides() {
+        let base = RiskLimits {
+            max_gross_exposure: Some(1000.0),
+            max_daily_loss: Some(50.0),
+            ..RiskLimits::default()
+        };
+        let limits = base.with_overrides(&RiskLimits {
+            max_daily_loss: Some(20.0),
+            ..RiskLimits::default()
+        });
+        assert_eq!(limits.max_gross_exposure, Some(1000.0));
+        assert_eq!(limits.max_daily_loss, Some(20.0));
+        assert_eq!(limits.max_net_exposure, None);
+    }
+}
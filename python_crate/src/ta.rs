@@ -7,12 +7,17 @@ use pyo3::types::PyDict;
 use pyo3::PyResult;
 use serde::{Deserialize, Serialize};
 
+use chrono::{DateTime, Duration, Utc};
+
 use crate::candle::PyCandle;
 use stats::indicators::ppo::PercentPriceOscillator;
+use stats::indicators::rolling::{RollingCorrelation, RollingZScore};
+use stats::indicators::volatility::{EwmaVolatility, Garch11};
+use stats::indicators::vwap::{AnchoredVwap, SessionTwap, SessionVwap};
 use stats::yata_prelude::dd::{IndicatorConfigDyn, IndicatorInstanceDyn};
 #[allow(unused_imports)]
 use stats::*;
-use stats::{Close, Next};
+use stats::{Close, Next, Reset};
 
 #[allow(clippy::upper_case_acronyms)]
 #[derive(Copy, Clone, Serialize, Deserialize)]
@@ -618,6 +623,165 @@ yata_method!(
     WSMA
 );
 
+#[pyclass]
+pub(crate) struct PySessionVwap {
+    inner: SessionVwap,
+}
+
+#[pymethods]
+impl PySessionVwap {
+    fn next(&mut self, price: f64, volume: f64, at: DateTime<Utc>) -> f64 { self.inner.next((price, volume, at)) }
+
+    fn reset(&mut self) { self.inner.reset() }
+}
+
+#[doc = "Volume Weighted Average Price of sessions starting every `session_secs` seconds since the epoch, see rust api stats::indicators::vwap::SessionVwap"]
+#[pyfunction]
+#[pyo3(signature = (session_secs = 86400), text_signature = "(session_secs: int = 86400) -> SessionVwap")]
+pub(crate) fn session_vwap(session_secs: i64) -> PyResult<PySessionVwap> {
+    if session_secs <= 0 {
+        return Err(PyErr::new::<PyTypeError, _>("sessions last at least a second"));
+    }
+    Ok(PySessionVwap {
+        inner: SessionVwap::new(Duration::seconds(session_secs)),
+    })
+}
+
+#[pyclass]
+pub(crate) struct PyAnchoredVwap {
+    inner: AnchoredVwap,
+}
+
+#[pymethods]
+impl PyAnchoredVwap {
+    fn next(&mut self, price: f64, volume: f64, at: DateTime<Utc>) -> f64 { self.inner.next((price, volume, at)) }
+
+    fn anchor_at(&mut self, anchor: DateTime<Utc>) { self.inner.anchor_at(anchor) }
+
+    fn reset(&mut self) { self.inner.reset() }
+}
+
+#[doc = "Volume Weighted Average Price since an anchor time, see rust api stats::indicators::vwap::AnchoredVwap"]
+#[pyfunction]
+#[pyo3(text_signature = "(anchor: datetime) -> AnchoredVwap")]
+pub(crate) fn anchored_vwap(anchor: DateTime<Utc>) -> PyAnchoredVwap {
+    PyAnchoredVwap {
+        inner: AnchoredVwap::new(anchor),
+    }
+}
+
+#[pyclass]
+pub(crate) struct PySessionTwap {
+    inner: SessionTwap,
+}
+
+#[pymethods]
+impl PySessionTwap {
+    fn next(&mut self, price: f64, at: DateTime<Utc>) -> f64 { self.inner.next((price, at)) }
+
+    fn reset(&mut self) { self.inner.reset() }
+}
+
+#[doc = "Time Weighted Average Price of sessions starting every `session_secs` seconds since the epoch, see rust api stats::indicators::vwap::SessionTwap"]
+#[pyfunction]
+#[pyo3(signature = (session_secs = 86400), text_signature = "(session_secs: int = 86400) -> SessionTwap")]
+pub(crate) fn session_twap(session_secs: i64) -> PyResult<PySessionTwap> {
+    if session_secs <= 0 {
+        return Err(PyErr::new::<PyTypeError, _>("sessions last at least a second"));
+    }
+    Ok(PySessionTwap {
+        inner: SessionTwap::new(Duration::seconds(session_secs)),
+    })
+}
+
+#[pyclass]
+pub(crate) struct PyRollingZScore {
+    inner: RollingZScore,
+}
+
+#[pymethods]
+impl PyRollingZScore {
+    fn next(&mut self, value: f64) -> f64 { self.inner.next(value) }
+
+    fn reset(&mut self) { self.inner.reset() }
+}
+
+#[doc = "Z-score of the last value over a rolling window, see rust api stats::indicators::rolling::RollingZScore"]
+#[pyfunction]
+#[pyo3(text_signature = "(period: int) -> RollingZScore")]
+pub(crate) fn zscore(period: usize) -> PyResult<PyRollingZScore> {
+    Ok(PyRollingZScore {
+        inner: RollingZScore::new(period).map_err(|e| PyErr::new::<PyTypeError, _>(format!("{}", e)))?,
+    })
+}
+
+#[pyclass]
+pub(crate) struct PyRollingCorrelation {
+    inner: RollingCorrelation,
+}
+
+#[pymethods]
+impl PyRollingCorrelation {
+    fn next(&mut self, a: f64, b: f64) -> f64 { self.inner.next((a, b)) }
+
+    fn reset(&mut self) { self.inner.reset() }
+}
+
+#[doc = "Pearson correlation of two series over a rolling window, see rust api stats::indicators::rolling::RollingCorrelation"]
+#[pyfunction]
+#[pyo3(text_signature = "(period: int) -> RollingCorrelation")]
+pub(crate) fn correlation(period: usize) -> PyResult<PyRollingCorrelation> {
+    Ok(PyRollingCorrelation {
+        inner: RollingCorrelation::new(period).map_err(|e| PyErr::new::<PyTypeError, _>(format!("{}", e)))?,
+    })
+}
+
+#[pyclass]
+pub(crate) struct PyEwmaVolatility {
+    inner: EwmaVolatility,
+}
+
+#[pymethods]
+impl PyEwmaVolatility {
+    fn next(&mut self, ret: f64) -> f64 { self.inner.next(ret) }
+
+    fn forecast(&self, horizon: usize) -> f64 { self.inner.forecast(horizon) }
+
+    fn reset(&mut self) { self.inner.reset() }
+}
+
+#[doc = "EWMA volatility of returns, see rust api stats::indicators::volatility::EwmaVolatility"]
+#[pyfunction]
+#[pyo3(text_signature = "(decay: float) -> EwmaVolatility")]
+pub(crate) fn ewma_volatility(decay: f64) -> PyResult<PyEwmaVolatility> {
+    Ok(PyEwmaVolatility {
+        inner: EwmaVolatility::new(decay).map_err(|e| PyErr::new::<PyTypeError, _>(format!("{}", e)))?,
+    })
+}
+
+#[pyclass]
+pub(crate) struct PyGarchVolatility {
+    inner: Garch11,
+}
+
+#[pymethods]
+impl PyGarchVolatility {
+    fn next(&mut self, ret: f64) -> f64 { self.inner.next(ret) }
+
+    fn forecast(&self, horizon: usize) -> f64 { self.inner.forecast(horizon) }
+
+    fn reset(&mut self) { self.inner.reset() }
+}
+
+#[doc = "GARCH(1,1) volatility of returns, see rust api stats::indicators::volatility::Garch11"]
+#[pyfunction]
+#[pyo3(text_signature = "(omega: float, alpha: float, beta: float) -> GarchVolatility")]
+pub(crate) fn garch_volatility(omega: f64, alpha: f64, beta: f64) -> PyResult<PyGarchVolatility> {
+    Ok(PyGarchVolatility {
+        inner: Garch11::new(omega, alpha, beta).map_err(|e| PyErr::new::<PyTypeError, _>(format!("{}", e)))?,
+    })
+}
+
 #[pymodule]
 pub(crate) fn ta(_py: Python, m: &PyModule) -> PyResult<()> {
     m.add_function(wrap_pyfunction!(adidx, m)?)?;
@@ -644,5 +808,13 @@ pub(crate) fn ta(_py: Python, m: &PyModule) -> PyResult<()> {
 
     m.add_function(wrap_pyfunction!(trima, m)?)?;
 
+    m.add_function(wrap_pyfunction!(session_vwap, m)?)?;
+    m.add_function(wrap_pyfunction!(anchored_vwap, m)?)?;
+    m.add_function(wrap_pyfunction!(session_twap, m)?)?;
+    m.add_function(wrap_pyfunction!(zscore, m)?)?;
+    m.add_function(wrap_pyfunction!(correlation, m)?)?;
+    m.add_function(wrap_pyfunction!(ewma_volatility, m)?)?;
+    m.add_function(wrap_pyfunction!(garch_volatility, m)?)?;
+
     Ok(())
 }
@@ -74,6 +74,10 @@ impl PyTradeSignal {
                 enforcement: enforcement.map_into(),
                 asset_type: Some(asset_type.into()),
                 side_effect: side_effect.map_into(),
+                spread_policy: None,
+                post_only: false,
+                account: None,
+                max_order_age: None,
             },
         })
     }
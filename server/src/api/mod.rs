@@ -1,25 +1,29 @@
 use std::collections::HashMap;
 #[cfg(feature = "flame")]
 use std::fs::File;
+use std::path::Path;
 use std::str::FromStr;
 use std::sync::Arc;
 
 use actix::Addr;
 use actix_web::body::BoxBody;
+use actix_web::http::header;
 use actix_web::{web::{self},
-                Error, HttpResponse, ResponseError};
+                Error, HttpRequest, HttpResponse, ResponseError};
 use derive_more::Display;
 use serde::{Deserialize, Serialize};
 
 use brokers::pair::pair_confs;
 use brokers::prelude::*;
-use strategy::{StrategyKey, Trader};
+use strategy::plugin::{list_plugins, load_plugin_library, reload_plugin, unload_plugin, PluginSource};
+use strategy::{StrategyKey, StrategyLifecycleCmd, Trader};
 use trading::order_manager::OrderManager;
 
 use crate::api::ApiError::ExchangeNotFound;
 use crate::graphql_schemas::root::Schema;
 use crate::graphql_schemas::Context;
-use crate::settings::Version;
+use crate::kill_switch::{KillSwitch, KillSwitchStatus, TriggerKillSwitch};
+use crate::settings::{PluginApiSettings, Version};
 
 mod graphql;
 mod playground_source;
@@ -42,6 +46,14 @@ pub enum ApiError {
     Broker(brokers::error::Error),
     #[display(fmt = "std Io Error {}", _0)]
     IoError(std::io::Error),
+    #[display(fmt = "kill switch unavailable")]
+    KillSwitchUnavailable,
+    #[display(fmt = "strategy plugin error {}", _0)]
+    Plugin(strategy::error::Error),
+    #[display(fmt = "unauthorized")]
+    Unauthorized,
+    #[display(fmt = "plugin library outside of the library directory {}", _0)]
+    ForbiddenLibrary(String),
 }
 
 impl ResponseError for ApiError {
@@ -50,6 +62,11 @@ impl ResponseError for ApiError {
             ExchangeNotFound(_e) => HttpResponse::NotFound().finish(),
             ApiError::Broker(e) => HttpResponse::InternalServerError().body(e.to_string()),
             ApiError::IoError(e) => HttpResponse::InternalServerError().body(e.to_string()),
+            ApiError::KillSwitchUnavailable => HttpResponse::ServiceUnavailable().finish(),
+            ApiError::Plugin(strategy::error::Error::StrategyPluginNotFound) => HttpResponse::NotFound().finish(),
+            ApiError::Plugin(e) => HttpResponse::BadRequest().body(e.to_string()),
+            ApiError::Unauthorized => HttpResponse::Unauthorized().finish(),
+            ApiError::ForbiddenLibrary(_) => HttpResponse::Forbidden().body(self.to_string()),
             //_ => HttpResponse::InternalServerError().finish(),
         }
     }
@@ -66,6 +83,7 @@ async fn playground_handler() -> Result<HttpResponse, Error> { self::graphql::pl
 type BrokerageData = web::Data<Arc<BrokerageRegistry>>;
 type StratsData = web::Data<Arc<HashMap<StrategyKey, Trader>>>;
 type OrderManagerData = web::Data<Arc<HashMap<Exchange, Addr<OrderManager>>>>;
+type KillSwitchData = web::Data<Addr<KillSwitch>>;
 
 async fn graphql(
     req: actix_web::HttpRequest,
@@ -74,11 +92,13 @@ async fn graphql(
     strats: StratsData,
     exchanges: BrokerageData,
     order_managers: OrderManagerData,
+    kill_switch: Option<KillSwitchData>,
 ) -> Result<HttpResponse, Error> {
     let ctx = Context {
         strats: strats.get_ref().clone(),
         exchanges: exchanges.get_ref().clone(),
         order_managers: order_managers.get_ref().clone(),
+        kill_switch: kill_switch.map(|ks| ks.get_ref().clone()),
     };
     self::graphql::graphql_handler(&schema, &ctx, req, payload).await
 }
@@ -106,6 +126,145 @@ async fn exchange_conf(q: web::Query<HashMap<String, String>>) -> Result<HttpRes
     Ok(HttpResponse::Ok().json(confs))
 }
 
+async fn trigger_kill_switch(
+    q: web::Query<HashMap<String, String>>,
+    kill_switch: Option<KillSwitchData>,
+) -> Result<HttpResponse, Error> {
+    let kill_switch = kill_switch.ok_or(ApiError::KillSwitchUnavailable)?;
+    let flatten = q.get("flatten").map_or(false, |flatten| flatten == "true");
+    let progress = kill_switch
+        .send(TriggerKillSwitch { flatten })
+        .await
+        .map_err(|_| ApiError::KillSwitchUnavailable)?;
+    Ok(HttpResponse::Ok().json(progress))
+}
+
+async fn kill_switch_status(kill_switch: Option<KillSwitchData>) -> Result<HttpResponse, Error> {
+    let kill_switch = kill_switch.ok_or(ApiError::KillSwitchUnavailable)?;
+    let progress = kill_switch
+        .send(KillSwitchStatus)
+        .await
+        .map_err(|_| ApiError::KillSwitchUnavailable)?;
+    Ok(HttpResponse::Ok().json(progress))
+}
+
+#[derive(Debug, Serialize)]
+struct PluginInfo {
+    name: String,
+    source: PluginSource,
+}
+
+#[derive(Debug, Serialize)]
+struct PluginsReloaded {
+    plugins: Vec<String>,
+    /// Strategies restarted to run the reloaded plugins
+    restarted: usize,
+}
+
+async fn plugins() -> Result<HttpResponse, Error> {
+    let plugins: Vec<PluginInfo> = list_plugins()
+        .into_iter()
+        .map(|(name, source)| PluginInfo { name, source })
+        .collect();
+    Ok(HttpResponse::Ok().json(plugins))
+}
+
+/// Restart the strategies of plugins, restarted strategies run the plugins currently loaded
+async fn restart_strategies(strats: &StratsData, plugins: &[String]) -> usize {
+    let mut restarted = 0;
+    for trader in strats.values().filter(|t| plugins.contains(&t.key.0)) {
+        match trader.send(StrategyLifecycleCmd::Restart).await {
+            Ok(Ok(_)) => restarted += 1,
+            Ok(Err(e)) | Err(e) => error!(key = %trader.key.to_string(), err = %e, "failed to restart strategy"),
+        }
+    }
+    restarted
+}
+
+#[derive(Debug, Deserialize)]
+struct PluginPath {
+    path: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct PluginName {
+    name: String,
+}
+
+/// Check the bearer token of a request managing plugins
+fn authorize(req: &HttpRequest, settings: &PluginApiSettings) -> Result<(), ApiError> {
+    let token = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "))
+        .ok_or(ApiError::Unauthorized)?;
+    // Compare every byte so that the time taken does not leak the length of the matching prefix
+    let expected = settings.token.as_bytes();
+    let matches = token.len() == expected.len()
+        && token
+            .as_bytes()
+            .iter()
+            .zip(expected)
+            .fold(0_u8, |acc, (a, b)| acc | (a ^ b))
+            == 0;
+    if matches {
+        Ok(())
+    } else {
+        Err(ApiError::Unauthorized)
+    }
+}
+
+/// Resolve a plugin library path, which must be within the library directory
+fn library_path(path: &str, settings: &PluginApiSettings) -> Result<String, ApiError> {
+    let dir = settings.library_dir.canonicalize()?;
+    let path = dir.join(Path::new(path)).canonicalize()?;
+    if path.starts_with(&dir) {
+        Ok(path.to_string_lossy().to_string())
+    } else {
+        Err(ApiError::ForbiddenLibrary(dir.to_string_lossy().to_string()))
+    }
+}
+
+async fn load_plugin(
+    req: HttpRequest,
+    q: web::Query<PluginPath>,
+    settings: web::Data<PluginApiSettings>,
+    strats: StratsData,
+) -> Result<HttpResponse, Error> {
+    authorize(&req, &settings)?;
+    let path = library_path(&q.path, &settings)?;
+    let plugins = load_plugin_library(&path).map_err(ApiError::Plugin)?;
+    let restarted = restart_strategies(&strats, &plugins).await;
+    Ok(HttpResponse::Ok().json(PluginsReloaded { plugins, restarted }))
+}
+
+async fn reload(
+    req: HttpRequest,
+    q: web::Query<PluginName>,
+    settings: web::Data<PluginApiSettings>,
+    strats: StratsData,
+) -> Result<HttpResponse, Error> {
+    authorize(&req, &settings)?;
+    let mut plugins = reload_plugin(&q.name).map_err(ApiError::Plugin)?;
+    // Builtin plugins are not reloaded, their strategies are restarted to re-read their scripts
+    if !plugins.contains(&q.name) {
+        plugins.push(q.name.clone());
+    }
+    let restarted = restart_strategies(&strats, &plugins).await;
+    Ok(HttpResponse::Ok().json(PluginsReloaded { plugins, restarted }))
+}
+
+async fn unload(
+    req: HttpRequest,
+    q: web::Query<PluginName>,
+    settings: web::Data<PluginApiSettings>,
+) -> Result<HttpResponse, Error> {
+    authorize(&req, &settings)?;
+    unload_plugin(&q.name).map_err(ApiError::Plugin)?;
+    Ok(HttpResponse::Ok().finish())
+}
+
 async fn version(version: web::Data<Option<Version>>) -> Result<HttpResponse, Error> {
     Ok(HttpResponse::Ok().json(version))
 }
@@ -118,12 +277,26 @@ pub fn config_app(cfg: &mut web::ServiceConfig) {
     );
     cfg.service(web::resource("/exchange_conf").route(web::get().to(exchange_conf)));
     cfg.service(web::resource("/version").route(web::get().to(version)));
+    cfg.service(
+        web::resource("/kill_switch")
+            .route(web::post().to(trigger_kill_switch))
+            .route(web::get().to(kill_switch_status)),
+    );
+    cfg.service(web::resource("/plugins").route(web::get().to(plugins)));
     cfg.service(web::resource("/playground").route(web::get().to(playground_handler)));
     cfg.service(web::resource("/graphiql").route(web::get().to(graphiql_handler)));
     #[cfg(feature = "flame")]
     cfg.service(web::scope("/profiling").service(web::resource("dump").route(web::post().to(dump_profiler))));
 }
 
+/// Endpoints managing plugins, only registered if enabled in the api settings
+pub fn config_plugins(cfg: &mut web::ServiceConfig, settings: &PluginApiSettings) {
+    cfg.app_data(web::Data::new(settings.clone()));
+    cfg.service(web::resource("/plugins/load").route(web::post().to(load_plugin)));
+    cfg.service(web::resource("/plugins/reload").route(web::post().to(reload)));
+    cfg.service(web::resource("/plugins/unload").route(web::post().to(unload)));
+}
+
 #[cfg(test)]
 mod tests {
     use std::collections::HashMap;
@@ -137,11 +310,11 @@ mod tests {
 
     use brokers::manager::BrokerageRegistry;
     use brokers::prelude::*;
-    use util::test::test_config_path;
+    use util::test::{test_config_path, test_dir};
 
-    use crate::api::config_app;
+    use crate::api::{config_app, config_plugins};
     use crate::graphql_schemas::root::create_schema;
-    use crate::settings::Version;
+    use crate::settings::{PluginApiSettings, Version};
     use crate::{OrderManagerRegistry, StrategyRegistry};
 
     static DEFAULT_SYMBOL: &str = "BTC_USDT";
@@ -155,6 +328,11 @@ mod tests {
             use_isolated_margin_account: true,
             isolated_margin_account_pairs: vec![],
             use_test: true,
+            rate_limits: HashMap::new(),
+            accounts: vec![],
+            dead_man_switch: Default::default(),
+            fee_tier_refresh_secs: 0,
+            pairs_refresh_secs: 0,
         })]);
         let manager = Arc::new(Brokerages::new_manager());
         manager
@@ -225,4 +403,42 @@ mod tests {
             serde_json::to_string_pretty(&v).unwrap()
         );
     }
+
+    #[actix::test]
+    async fn test_plugin_endpoints_require_token_and_library_dir() {
+        let dir = test_dir();
+        let settings = PluginApiSettings {
+            token: "secret".to_string(),
+            library_dir: dir.path().to_path_buf(),
+        };
+        let strats: Arc<StrategyRegistry> = Arc::new(Default::default());
+        let app = App::new()
+            .app_data(Data::new(strats))
+            .configure(|cfg| config_plugins(cfg, &settings));
+        let app = test::init_service(app).await;
+
+        let req = test::TestRequest::post().uri("/plugins/unload?name=test").to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::UNAUTHORIZED);
+
+        let req = test::TestRequest::post()
+            .uri("/plugins/unload?name=test")
+            .insert_header(("Authorization", "Bearer wrong"))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::UNAUTHORIZED);
+
+        let req = test::TestRequest::post()
+            .uri("/plugins/load")
+            .insert_header(("Authorization", "Bearer secret"))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::BAD_REQUEST);
+
+        let outside = test_dir();
+        let library = outside.path().join("libplugin.so");
+        std::fs::write(&library, b"").unwrap();
+        let req = test::TestRequest::post()
+            .uri(&format!("/plugins/load?path={}", library.display()))
+            .insert_header(("Authorization", "Bearer secret"))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::FORBIDDEN);
+    }
 }
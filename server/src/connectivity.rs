@@ -1,20 +1,47 @@
-///! Checks internet connectivity
+///! Checks internet connectivity, and the system status of exchanges
 use std::time::Duration;
 
 use actix_web::rt::time;
-use prometheus::{register_int_counter, IntCounter};
+use prometheus::{register_gauge_vec, register_int_counter, GaugeVec, IntCounter};
+
+use brokers::manager::BrokerageManagerRef;
+use brokers::types::SystemStatus;
+use util::time::now;
 
 lazy_static! {
     pub static ref CONNECTIVITY_COUNTER: IntCounter =
         register_int_counter!("connection_check", "Internet connection check").unwrap();
+    pub static ref MAINTENANCE_GAUGE: GaugeVec = register_gauge_vec!(
+        "exchange_maintenance",
+        "Whether the exchange reports being under maintenance.",
+        &["xchg"]
+    )
+    .unwrap();
 }
 
-pub async fn run_connectivity_checker(interval_secs: u64) {
+pub async fn run_connectivity_checker(interval_secs: u64, manager: BrokerageManagerRef) {
     let mut interval = time::interval(Duration::from_secs(interval_secs));
+    // Maintenance windows outlive a missed poll, but expire if polling stops
+    let window = chrono::Duration::seconds(i64::try_from(interval_secs * 2).unwrap_or(i64::MAX));
     loop {
         interval.tick().await;
         if online::tokio::check(None).await.is_ok() {
             (*CONNECTIVITY_COUNTER).inc();
         }
+        for api_ref in manager.exchange_apis() {
+            let xchg = *api_ref.key();
+            match api_ref.value().system_status().await {
+                Ok(status) => {
+                    (*MAINTENANCE_GAUGE)
+                        .with_label_values(&[xchg.as_ref()])
+                        .set(if status == SystemStatus::Maintenance { 1.0 } else { 0.0 });
+                    match status {
+                        SystemStatus::Maintenance => manager.maintenance().start(xchg, now() + window),
+                        SystemStatus::Normal => manager.maintenance().end(xchg),
+                    }
+                }
+                Err(e) => debug!(xchg = %xchg, err = %e, "failed to fetch exchange system status"),
+            }
+        }
     }
 }
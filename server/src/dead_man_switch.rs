@@ -0,0 +1,155 @@
+//! Keeps the dead man's switch of exchanges armed, so that resting orders are canceled by the exchanges themselves
+//! once the server stops refreshing the timers, e.g. when it crashes.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use actix::{Actor, ActorFutureExt, Addr, AsyncContext, Context, ContextFutureSpawner, Handler, WrapFuture};
+
+use brokers::bot::Ping;
+use brokers::manager::BrokerageManagerRef;
+use brokers::prelude::*;
+
+use crate::system::bots::accounts;
+
+/// An account whose timer is kept armed
+#[derive(Clone, Debug, PartialEq)]
+struct ArmedAccount {
+    xchg: Exchange,
+    account: Option<String>,
+    timeout: Duration,
+}
+
+pub struct DeadManSwitch {
+    xchg_mgr: BrokerageManagerRef,
+    accounts: Vec<ArmedAccount>,
+}
+
+impl DeadManSwitch {
+    /// Arms every account of the exchanges that enable the dead man's switch
+    pub fn new(apis: BrokerageManagerRef, exchanges: &HashMap<Exchange, BrokerSettings>) -> Self {
+        let accounts = exchanges
+            .iter()
+            .filter(|(_, conf)| conf.dead_man_switch.enabled)
+            .flat_map(|(xchg, conf)| {
+                accounts(conf).map(move |account| ArmedAccount {
+                    xchg: *xchg,
+                    account: account.map(ToString::to_string),
+                    timeout: conf.dead_man_switch.timeout(),
+                })
+            })
+            .collect();
+        Self {
+            xchg_mgr: apis,
+            accounts,
+        }
+    }
+
+    pub fn actor(apis: BrokerageManagerRef, exchanges: &HashMap<Exchange, BrokerSettings>) -> Addr<Self> {
+        Self::start(Self::new(apis, exchanges))
+    }
+
+    /// Whether no exchange enables the dead man's switch
+    pub fn is_empty(&self) -> bool { self.accounts.is_empty() }
+
+    /// Disarm the accounts of exchanges that cannot cancel orders with a timer, or not with this timeout
+    fn retain_supported(&mut self) {
+        let xchg_mgr = self.xchg_mgr.clone();
+        self.accounts.retain(|armed| {
+            let Some(api) = xchg_mgr.get_account_api(armed.xchg, armed.account.as_deref()) else {
+                error!(xchg = %armed.xchg, account = ?armed.account, "no api to arm the dead man's switch with");
+                return false;
+            };
+            match api.capabilities().check_dead_man_switch(armed.timeout.as_secs()) {
+                Ok(()) => true,
+                Err(e) => {
+                    error!(xchg = %armed.xchg, err = %e, "dead man's switch is not supported");
+                    false
+                }
+            }
+        });
+    }
+}
+
+/// Timers are refreshed three times per timeout, so that a single failed refresh does not cancel orders
+fn refresh_interval(timeout: Duration) -> Duration { timeout / 3 }
+
+impl Actor for DeadManSwitch {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        self.retain_supported();
+        for armed in self.accounts.clone() {
+            info!(xchg = %armed.xchg, account = ?armed.account, timeout = ?armed.timeout, "arming dead man's switch");
+            ctx.notify(Heartbeat(armed.clone()));
+            ctx.run_interval(refresh_interval(armed.timeout), move |_act, ctx| {
+                ctx.notify(Heartbeat(armed.clone()));
+            });
+        }
+    }
+}
+
+/// Refresh the timer of an account before it expires
+#[derive(actix::Message)]
+#[rtype(result = "()")]
+struct Heartbeat(ArmedAccount);
+
+impl Handler<Heartbeat> for DeadManSwitch {
+    type Result = ();
+
+    fn handle(&mut self, msg: Heartbeat, ctx: &mut Self::Context) -> Self::Result {
+        let armed = msg.0;
+        let Some(api) = self.xchg_mgr.get_account_api(armed.xchg, armed.account.as_deref()) else {
+            return;
+        };
+        let timeout = armed.timeout;
+        async move { api.cancel_all_after(timeout).await }
+            .into_actor(self)
+            .map(move |result, _act, _ctx| {
+                if let Err(e) = result {
+                    error!(xchg = %armed.xchg, account = ?armed.account, err = %e, "failed to refresh the dead man's switch");
+                }
+            })
+            .spawn(ctx);
+    }
+}
+
+impl Handler<Ping> for DeadManSwitch {
+    type Result = ();
+
+    fn handle(&mut self, _msg: Ping, _ctx: &mut Context<Self>) {}
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use brokers::manager::BrokerageManager;
+    use brokers::prelude::*;
+
+    use super::{refresh_interval, DeadManSwitch};
+
+    #[test]
+    fn arms_supported_accounts() {
+        let mut enabled = BrokerSettings::default_test(0.1);
+        enabled.dead_man_switch.enabled = true;
+        enabled.accounts = vec!["hedge".to_string()];
+        let exchanges = HashMap::from([
+            (Exchange::Binance, enabled.clone()),
+            (Exchange::Kraken, enabled),
+            (Exchange::Okx, BrokerSettings::default_test(0.1)),
+        ]);
+        let manager = BrokerageManager::new();
+        manager.build_mock_exchange_apis(&[Exchange::Binance, Exchange::Kraken]);
+        let mut switch = DeadManSwitch::new(Arc::new(manager), &exchanges);
+        // The main and named accounts of exchanges that enable it
+        assert_eq!(switch.accounts.len(), 4);
+        switch.retain_supported();
+        // Named accounts have no api in the mock manager
+        assert_eq!(switch.accounts.len(), 2);
+        assert!(switch.accounts.iter().all(|armed| armed.account.is_none()));
+        assert_eq!(refresh_interval(Duration::from_secs(60)), Duration::from_secs(20));
+    }
+}
@@ -0,0 +1,122 @@
+//! Keeps the fee tiers of the accounts up to date, so that the fees anticipated for orders and positions follow
+//! the trading volume of the accounts.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use actix::{Actor, ActorFutureExt, Addr, AsyncContext, Context, ContextFutureSpawner, Handler, WrapFuture};
+
+use brokers::bot::Ping;
+use brokers::error::Error;
+use brokers::manager::BrokerageManagerRef;
+use brokers::prelude::*;
+
+pub struct FeeTierRefresher {
+    xchg_mgr: BrokerageManagerRef,
+    /// How often the fee tier of each exchange is fetched
+    exchanges: Vec<(Exchange, Duration)>,
+}
+
+impl FeeTierRefresher {
+    pub fn new(apis: BrokerageManagerRef, exchanges: &HashMap<Exchange, BrokerSettings>) -> Self {
+        let exchanges = exchanges
+            .iter()
+            .filter(|(_, conf)| conf.fee_tier_refresh_secs > 0)
+            .map(|(xchg, conf)| (*xchg, Duration::from_secs(conf.fee_tier_refresh_secs)))
+            .collect();
+        Self {
+            xchg_mgr: apis,
+            exchanges,
+        }
+    }
+
+    pub fn actor(apis: BrokerageManagerRef, exchanges: &HashMap<Exchange, BrokerSettings>) -> Addr<Self> {
+        Self::start(Self::new(apis, exchanges))
+    }
+
+    /// Whether no exchange refreshes its fee tier
+    pub fn is_empty(&self) -> bool { self.exchanges.is_empty() }
+}
+
+impl Actor for FeeTierRefresher {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        for (xchg, every) in self.exchanges.clone() {
+            ctx.notify(RefreshFeeTier(xchg));
+            ctx.run_interval(every, move |act, ctx| {
+                if act.exchanges.iter().any(|(x, _)| *x == xchg) {
+                    ctx.notify(RefreshFeeTier(xchg));
+                }
+            });
+        }
+    }
+}
+
+/// Fetch the current fee tiers of the accounts of an exchange
+#[derive(actix::Message)]
+#[rtype(result = "()")]
+struct RefreshFeeTier(Exchange);
+
+impl Handler<RefreshFeeTier> for FeeTierRefresher {
+    type Result = ();
+
+    fn handle(&mut self, msg: RefreshFeeTier, ctx: &mut Self::Context) -> Self::Result {
+        let xchg = msg.0;
+        let xchg_mgr = self.xchg_mgr.clone();
+        async move { xchg_mgr.refresh_fee_tiers(xchg).await }
+            .into_actor(self)
+            .map(move |results, act, _ctx| {
+                for (account, result) in results {
+                    let account = account.unwrap_or_default();
+                    match result {
+                        Ok(tier) => {
+                            info!(xchg = %xchg, account = %account, maker = tier.maker, taker = tier.taker, "refreshed fee tier");
+                        }
+                        Err(Error::BrokerFeatureNotImplemented) => {
+                            // Configured fees are used for exchanges that do not report the fee tier of the account
+                            info!(xchg = %xchg, "no fee tier for exchange, using configured fees");
+                            act.exchanges.retain(|(x, _)| *x != xchg);
+                            return;
+                        }
+                        Err(e) => error!(xchg = %xchg, account = %account, err = %e, "failed to refresh fee tier"),
+                    }
+                }
+            })
+            .spawn(ctx);
+    }
+}
+
+impl Handler<Ping> for FeeTierRefresher {
+    type Result = ();
+
+    fn handle(&mut self, _msg: Ping, _ctx: &mut Context<Self>) {}
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use brokers::manager::BrokerageManager;
+    use brokers::prelude::*;
+
+    use super::FeeTierRefresher;
+
+    #[test]
+    fn refreshes_enabled_exchanges() {
+        let mut disabled = BrokerSettings::default_test(0.1);
+        disabled.fee_tier_refresh_secs = 0;
+        let exchanges = HashMap::from([
+            (Exchange::Binance, BrokerSettings::default_test(0.1)),
+            (Exchange::Kraken, disabled),
+        ]);
+        let refresher = FeeTierRefresher::new(Arc::new(BrokerageManager::new()), &exchanges);
+        assert!(!refresher.is_empty());
+        assert_eq!(refresher.exchanges, vec![(
+            Exchange::Binance,
+            Duration::from_secs(3600)
+        )]);
+    }
+}
@@ -13,6 +13,7 @@ use strategy::query::{DataQuery, DataResult};
 use strategy::StrategyKey;
 use trading::order_manager::OrderManager;
 
+use crate::kill_switch::KillSwitch;
 use crate::{OrderManagerRegistry, StrategyRegistry};
 
 use super::types::TypeAndKeyInput;
@@ -21,6 +22,7 @@ pub(crate) struct Context {
     pub strats: Arc<StrategyRegistry>,
     pub exchanges: Arc<BrokerageRegistry>,
     pub order_managers: Arc<OrderManagerRegistry>,
+    pub kill_switch: Option<actix::Addr<KillSwitch>>,
 }
 
 impl juniper::Context for Context {}
@@ -92,6 +94,26 @@ impl Context {
         }
     }
 
+    pub async fn with_kill_switch<M: 'static>(&self, m: M) -> FieldResult<M::Result>
+    where
+        M: actix::Message + Send,
+        M::Result: Send + Debug,
+        KillSwitch: actix::Handler<M>,
+    {
+        let kill_switch = self.kill_switch.as_ref().ok_or_else(|| {
+            FieldError::new(
+                "Kill switch unavailable",
+                graphql_value!({ "unavailable": "kill switch unavailable" }),
+            )
+        })?;
+        kill_switch.send(m).await.map_err(|_| {
+            FieldError::new(
+                "Kill switch mailbox was full",
+                graphql_value!({ "unavailable": "kill switch mailbox full" }),
+            )
+        })
+    }
+
     pub async fn with_strat_mut<M: 'static>(&self, tk: TypeAndKeyInput, m: M) -> FieldResult<M::Result>
     where
         M: actix::Message + Send,
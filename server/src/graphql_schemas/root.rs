@@ -12,6 +12,7 @@ use trading::order_manager::types::PassOrder;
 use trading::position::Position;
 
 use crate::graphql_schemas::unhandled_data_result;
+use crate::kill_switch::{KillSwitchProgress, KillSwitchStatus, TriggerKillSwitch};
 
 use super::context::Context;
 use super::types::*;
@@ -118,6 +119,25 @@ impl QueryRoot {
             .await
     }
 
+    #[graphql(description = "Get the orders of a strat pending confirmation")]
+    async fn pending_orders(context: &Context, tk: TypeAndKeyInput) -> FieldResult<Vec<String>> {
+        context
+            .with_strat(tk, DataQuery::PendingOrders, |dr| match dr {
+                DataResult::PendingOrders(orders) => orders
+                    .into_iter()
+                    .map(|o| serde_json::to_string(&o))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(FieldError::from),
+                _ => unhandled_data_result(),
+            })
+            .await
+    }
+
+    #[graphql(description = "Progress of the kill switch")]
+    async fn kill_switch_status(context: &Context) -> FieldResult<KillSwitchProgress> {
+        context.with_kill_switch(KillSwitchStatus).await
+    }
+
     #[graphql(description = "Get the latest model values")]
     async fn models(context: &Context, tk: TypeAndKeyInput) -> FieldResult<Vec<Model>> {
         context
@@ -136,6 +156,20 @@ impl QueryRoot {
             })
             .await
     }
+
+    #[graphql(description = "Get the recorded features with their labels, as csv")]
+    async fn feature_dataset(context: &Context, tk: TypeAndKeyInput) -> FieldResult<String> {
+        context
+            .with_strat(tk, DataQuery::Features, |dr| match dr {
+                DataResult::Features(dataset) => {
+                    let mut csv = vec![];
+                    dataset.write_csv(&mut csv)?;
+                    Ok(String::from_utf8_lossy(&csv).to_string())
+                }
+                _ => unhandled_data_result(),
+            })
+            .await
+    }
 }
 
 pub(crate) struct MutationRoot;
@@ -157,6 +191,26 @@ impl MutationRoot {
             .await
     }
 
+    #[graphql(description = "Confirm the orders pending confirmation, subsequent orders are not held")]
+    async fn confirm_pending_orders(context: &Context, tk: TypeAndKeyInput) -> FieldResult<bool> {
+        context
+            .with_strat(tk, DataQuery::ConfirmPendingOrders, |dr| match dr {
+                DataResult::Success(confirmed) => Ok(confirmed),
+                _ => unhandled_data_result(),
+            })
+            .await
+    }
+
+    #[graphql(description = "Reject the orders pending confirmation, releasing their positions")]
+    async fn reject_pending_orders(context: &Context, tk: TypeAndKeyInput) -> FieldResult<bool> {
+        context
+            .with_strat(tk, DataQuery::RejectPendingOrders, |dr| match dr {
+                DataResult::Success(rejected) => Ok(rejected),
+                _ => unhandled_data_result(),
+            })
+            .await
+    }
+
     #[graphql(description = "Reset the specified model")]
     async fn reset_model(context: &Context, tk: TypeAndKeyInput, mr: ModelReset) -> FieldResult<StrategyStatus> {
         context.with_strat_mut(tk, mr).await.and_then(|r| {
@@ -185,6 +239,11 @@ impl MutationRoot {
         })
     }
 
+    #[graphql(description = "Stop all trading and cancel all orders, market closing open positions if flatten is set")]
+    async fn kill_switch(context: &Context, flatten: bool) -> FieldResult<KillSwitchProgress> {
+        context.with_kill_switch(TriggerKillSwitch { flatten }).await
+    }
+
     #[graphql(description = "Add an order (test mode only)")]
     async fn add_order(context: &Context, input: AddOrderInput) -> FieldResult<OrderResult> {
         let exchg: Exchange = Exchange::from_str(&input.exchg)?;
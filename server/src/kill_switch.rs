@@ -0,0 +1,177 @@
+//! Emergency stop of the whole trading system : all strategies stop trading, all resting orders are canceled
+//! and open positions are optionally market closed.
+
+use std::sync::Arc;
+
+use actix::{Actor, Addr, Context, Handler, ResponseFuture};
+use serde::Serialize;
+use tokio::sync::watch;
+
+use strategy::StrategyLifecycleCmd;
+use trading::order_manager::types::CancelAllOrders;
+use trading::order_manager::OrderManager;
+
+use crate::StrategyRegistry;
+
+/// Progress of the kill switch as it drains the system
+#[derive(Clone, Debug, Default, Serialize, juniper::GraphQLObject)]
+pub struct KillSwitchProgress {
+    /// Whether the kill switch was triggered
+    pub triggered: bool,
+    /// Whether open positions are market closed
+    pub flatten: bool,
+    /// Number of strategies to stop
+    pub strategies_total: i32,
+    /// Number of strategies that stopped trading
+    pub strategies_stopped: i32,
+    /// Number of resting orders canceled
+    pub orders_canceled: i32,
+    /// Number of strategies whose open positions were market closed
+    pub strategies_flattened: i32,
+    /// Whether every step was run
+    pub done: bool,
+    /// Errors of the steps that failed
+    pub errors: Vec<String>,
+}
+
+pub struct KillSwitch {
+    strategies: Arc<StrategyRegistry>,
+    order_managers: Vec<Addr<OrderManager>>,
+    progress: Arc<watch::Sender<KillSwitchProgress>>,
+}
+
+impl KillSwitch {
+    pub fn new(strategies: Arc<StrategyRegistry>, order_managers: Vec<Addr<OrderManager>>) -> Self {
+        Self {
+            strategies,
+            order_managers,
+            progress: Arc::new(watch::channel(KillSwitchProgress::default()).0),
+        }
+    }
+
+    /// Updates of the progress of the kill switch
+    pub fn subscribe(&self) -> watch::Receiver<KillSwitchProgress> { self.progress.subscribe() }
+
+    pub fn actor(strategies: Arc<StrategyRegistry>, order_managers: Vec<Addr<OrderManager>>) -> Addr<Self> {
+        Self::start(Self::new(strategies, order_managers))
+    }
+}
+
+impl Actor for KillSwitch {
+    type Context = Context<Self>;
+}
+
+/// Stop all trading, and market close open positions if `flatten` is set
+#[derive(actix::Message)]
+#[rtype(result = "KillSwitchProgress")]
+pub struct TriggerKillSwitch {
+    pub flatten: bool,
+}
+
+/// The progress of the kill switch
+#[derive(actix::Message)]
+#[rtype(result = "KillSwitchProgress")]
+pub struct KillSwitchStatus;
+
+/// Stop strategies, cancel resting orders then flatten positions, positions still locked by an order when
+/// flattening are left open
+async fn drain(
+    strategies: Arc<StrategyRegistry>,
+    order_managers: Vec<Addr<OrderManager>>,
+    progress: Arc<watch::Sender<KillSwitchProgress>>,
+    flatten: bool,
+) {
+    let error = |e: String| progress.send_modify(|p| p.errors.push(e));
+    for (key, trader) in strategies.iter() {
+        match trader.send(StrategyLifecycleCmd::StopTrading).await.and_then(|r| r) {
+            Ok(_) => progress.send_modify(|p| p.strategies_stopped += 1),
+            Err(e) => error(format!("{}: {}", key.to_string(), e)),
+        }
+    }
+    for om in &order_managers {
+        match om.send(CancelAllOrders).await.map_err(|e| e.to_string()) {
+            Ok(Ok(canceled)) => {
+                progress.send_modify(|p| p.orders_canceled += i32::try_from(canceled).unwrap_or(i32::MAX));
+            }
+            Ok(Err(e)) => error(e.to_string()),
+            Err(e) => error(e),
+        }
+    }
+    if flatten {
+        for (key, trader) in strategies.iter() {
+            match trader.send(StrategyLifecycleCmd::Flatten).await.and_then(|r| r) {
+                Ok(_) => progress.send_modify(|p| p.strategies_flattened += 1),
+                Err(e) => error(format!("{}: {}", key.to_string(), e)),
+            }
+        }
+    }
+    progress.send_modify(|p| p.done = true);
+    warn!(progress = ?*progress.borrow(), "kill switch drained");
+}
+
+impl Handler<TriggerKillSwitch> for KillSwitch {
+    type Result = ResponseFuture<KillSwitchProgress>;
+
+    fn handle(&mut self, msg: TriggerKillSwitch, _ctx: &mut Self::Context) -> Self::Result {
+        let strategies = self.strategies.clone();
+        let order_managers = self.order_managers.clone();
+        let progress = self.progress.clone();
+        Box::pin(async move {
+            // Draining is already in progress
+            let draining = progress.send_if_modified(|progress| {
+                if progress.triggered && !progress.done {
+                    return false;
+                }
+                *progress = KillSwitchProgress {
+                    triggered: true,
+                    flatten: msg.flatten,
+                    strategies_total: i32::try_from(strategies.len()).unwrap_or(i32::MAX),
+                    ..KillSwitchProgress::default()
+                };
+                true
+            });
+            if draining {
+                warn!(flatten = msg.flatten, "kill switch triggered");
+                actix::spawn(drain(strategies, order_managers, progress.clone(), msg.flatten));
+            }
+            let progress = progress.borrow().clone();
+            progress
+        })
+    }
+}
+
+impl Handler<KillSwitchStatus> for KillSwitch {
+    type Result = ResponseFuture<KillSwitchProgress>;
+
+    fn handle(&mut self, _msg: KillSwitchStatus, _ctx: &mut Self::Context) -> Self::Result {
+        let progress = self.progress.clone();
+        Box::pin(async move { progress.borrow().clone() })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use actix::Actor;
+
+    use super::{KillSwitch, KillSwitchStatus, TriggerKillSwitch};
+
+    #[actix::test]
+    async fn kill_switch_drains() {
+        let kill_switch = KillSwitch::new(Arc::new(Default::default()), vec![]);
+        let mut updates = kill_switch.subscribe();
+        let kill_switch = kill_switch.start();
+        let progress = kill_switch.send(KillSwitchStatus).await.unwrap();
+        assert!(!progress.triggered);
+        let progress = kill_switch.send(TriggerKillSwitch { flatten: true }).await.unwrap();
+        assert!(progress.triggered);
+        assert!(progress.flatten);
+        while !updates.borrow_and_update().done {
+            updates.changed().await.unwrap();
+        }
+        let progress = kill_switch.send(KillSwitchStatus).await.unwrap();
+        assert!(progress.done);
+        assert!(progress.errors.is_empty());
+    }
+}
@@ -43,10 +43,16 @@ extern crate tracing;
 
 pub mod api;
 mod connectivity;
+pub mod dead_man_switch;
+pub mod fee_tiers;
 pub mod graphql_schemas;
+pub mod kill_switch;
+pub mod margin_monitor;
 pub mod nats;
 mod notify;
+pub mod pair_registry;
 pub mod runner;
+pub mod screener;
 pub mod server;
 pub mod settings;
 pub mod system;
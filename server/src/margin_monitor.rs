@@ -0,0 +1,230 @@
+//! Watches the margin level of margin accounts, warns as it falls towards the maintenance level of the exchange
+//! and deleverages strategies before the exchange liquidates their positions.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+
+use actix::{Actor, ActorFutureExt, Addr, AsyncContext, Context, ContextFutureSpawner, Handler, Recipient, WrapFuture};
+use serde::Deserialize;
+
+use brokers::bot::Ping;
+use brokers::manager::BrokerageManagerRef;
+use brokers::prelude::*;
+use portfolio::balance::ExpectedBalances;
+use portfolio::margin::{account_margin_level, MarginHealth, MarginThresholds};
+use strategy::{StrategyLifecycleCmd, Trader};
+
+use crate::notify::Notification;
+use crate::StrategyRegistry;
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct MarginMonitorOptions {
+    #[serde(deserialize_with = "util::ser::string_duration")]
+    pub refresh_rate: Duration,
+    #[serde(default)]
+    pub thresholds: MarginThresholds,
+    /// Flatten the strategies trading on an exchange once its margin level falls to the deleverage level
+    #[serde(default)]
+    pub auto_deleverage: bool,
+}
+
+pub struct MarginMonitor {
+    xchg_mgr: BrokerageManagerRef,
+    options: MarginMonitorOptions,
+    strategies: Arc<StrategyRegistry>,
+    notifier: Option<Recipient<Notification>>,
+    health: HashMap<Exchange, MarginHealth>,
+    /// Exchanges whose strategies are being deleveraged
+    deleveraging: HashSet<Exchange>,
+}
+
+impl MarginMonitor {
+    pub fn new(
+        apis: BrokerageManagerRef,
+        options: &MarginMonitorOptions,
+        strategies: Arc<StrategyRegistry>,
+        notifier: Option<Recipient<Notification>>,
+    ) -> Self {
+        Self {
+            xchg_mgr: apis,
+            options: options.clone(),
+            strategies,
+            notifier,
+            health: HashMap::new(),
+            deleveraging: HashSet::new(),
+        }
+    }
+
+    pub fn actor(
+        apis: BrokerageManagerRef,
+        options: &MarginMonitorOptions,
+        strategies: Arc<StrategyRegistry>,
+        notifier: Option<Recipient<Notification>>,
+    ) -> Addr<Self> {
+        Self::start(Self::new(apis, options, strategies, notifier))
+    }
+
+    /// Record the margin level of an exchange account
+    ///
+    /// returns: the new health of the account if it changed
+    fn update(&mut self, xchg: Exchange, margin_level: f64) -> Option<MarginHealth> {
+        let health = self.options.thresholds.health(margin_level);
+        let previous = self.health.insert(xchg, health).unwrap_or(MarginHealth::Healthy);
+        (health != previous).then_some(health)
+    }
+
+    fn notify(&self, msg: String) {
+        if let Some(notifier) = self.notifier.as_ref() {
+            notifier.do_send(Notification::new(msg));
+        }
+    }
+
+    /// Flatten the positions of the strategies holding margin positions on `xchg`, one pass at a time
+    fn deleverage(&mut self, xchg: Exchange, ctx: &mut Context<Self>) {
+        if !self.deleveraging.insert(xchg) {
+            return;
+        }
+        let traders: Vec<(String, Trader)> = self
+            .strategies
+            .iter()
+            .filter(|(_, trader)| trader.channels.iter().any(|channel| channel.exchange() == xchg))
+            .map(|(key, trader)| (key.to_string(), trader.clone()))
+            .collect();
+        async move {
+            for (key, trader) in traders {
+                // Spot strategies do not weigh on the margin level
+                match trader
+                    .send(ExpectedBalances {
+                        xchg,
+                        account: AccountType::Margin,
+                    })
+                    .await
+                {
+                    Ok(balances) if balances.is_empty() => continue,
+                    Ok(_) => {}
+                    Err(e) => {
+                        error!(strategy = %key, err = %e, "failed to query the margin balances of strategy");
+                        continue;
+                    }
+                }
+                warn!(xchg = %xchg, strategy = %key, "deleveraging strategy");
+                if let Err(e) = trader.send(StrategyLifecycleCmd::Flatten).await.and_then(|r| r) {
+                    error!(strategy = %key, err = %e, "failed to deleverage strategy");
+                }
+            }
+        }
+        .into_actor(self)
+        .map(move |_, act, _| {
+            act.deleveraging.remove(&xchg);
+        })
+        .spawn(ctx);
+    }
+
+    fn on_margin_level(&mut self, xchg: Exchange, margin_level: f64, ctx: &mut Context<Self>) {
+        match self.update(xchg, margin_level) {
+            Some(MarginHealth::Healthy) => {
+                info!(xchg = %xchg, margin_level = margin_level, "margin level recovered");
+                self.notify(format!("{} margin level recovered at {:.3}", xchg, margin_level));
+            }
+            Some(health) => {
+                warn!(xchg = %xchg, margin_level = margin_level, health = ?health, "margin level is low");
+                self.notify(format!(
+                    "{} margin level at {:.3} ({:?}), maintenance level is {}",
+                    xchg, margin_level, health, self.options.thresholds.maintenance_level
+                ));
+            }
+            None => {}
+        }
+        // Strategies are deleveraged again on each check until the margin level recovers
+        if self.options.auto_deleverage && self.options.thresholds.health(margin_level) >= MarginHealth::Deleverage {
+            self.deleverage(xchg, ctx);
+        }
+    }
+}
+
+impl Actor for MarginMonitor {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        ctx.run_interval(self.options.refresh_rate, |act, ctx| {
+            for api_ref in act.xchg_mgr.exchange_apis() {
+                if api_ref.value().capabilities().margin {
+                    ctx.notify(CheckMargin(*api_ref.key()));
+                }
+            }
+        });
+    }
+}
+
+/// Fetch the margin account of an exchange and check its margin level
+#[derive(actix::Message)]
+#[rtype(result = "()")]
+struct CheckMargin(Exchange);
+
+impl Handler<CheckMargin> for MarginMonitor {
+    type Result = ();
+
+    fn handle(&mut self, msg: CheckMargin, ctx: &mut Self::Context) -> Self::Result {
+        let Some(api) = self.xchg_mgr.get_api(msg.0) else {
+            return;
+        };
+        async move { api.margin_account(None).await }
+            .into_actor(self)
+            .map(move |result, act, ctx| match result {
+                Ok(details) => act.on_margin_level(msg.0, account_margin_level(&details), ctx),
+                Err(e) => error!(xchg = %msg.0, err = %e, "failed to fetch margin account"),
+            })
+            .spawn(ctx);
+    }
+}
+
+impl Handler<AccountEventEnveloppe> for MarginMonitor {
+    type Result = anyhow::Result<()>;
+
+    fn handle(&mut self, msg: AccountEventEnveloppe, ctx: &mut Self::Context) -> Self::Result {
+        // Balances of margin accounts are in many assets, the margin level is recomputed by the exchange
+        if msg.account_type == AccountType::Margin && matches!(msg.event, AccountEvent::BalanceUpdate(_)) {
+            ctx.notify(CheckMargin(msg.xchg));
+        }
+        Ok(())
+    }
+}
+
+impl Handler<Ping> for MarginMonitor {
+    type Result = ();
+
+    fn handle(&mut self, _msg: Ping, _ctx: &mut Context<Self>) {}
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use brokers::manager::BrokerageManager;
+    use brokers::prelude::*;
+    use portfolio::margin::{MarginHealth, MarginThresholds};
+
+    use super::{MarginMonitor, MarginMonitorOptions};
+
+    #[test]
+    fn margin_health_transitions() {
+        let options = MarginMonitorOptions {
+            refresh_rate: Duration::from_secs(60),
+            thresholds: MarginThresholds::default(),
+            auto_deleverage: false,
+        };
+        let mut monitor = MarginMonitor::new(
+            Arc::new(BrokerageManager::new()),
+            &options,
+            Arc::new(Default::default()),
+            None,
+        );
+        assert_eq!(monitor.update(Exchange::Binance, 3.0), None);
+        assert_eq!(monitor.update(Exchange::Binance, 1.4), Some(MarginHealth::Warning));
+        assert_eq!(monitor.update(Exchange::Binance, 1.45), None);
+        assert_eq!(monitor.update(Exchange::Binance, 1.2), Some(MarginHealth::Deleverage));
+        assert_eq!(monitor.update(Exchange::Binance, 2.0), Some(MarginHealth::Healthy));
+    }
+}
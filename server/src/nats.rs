@@ -1,13 +1,17 @@
 use std::sync::Arc;
+use std::time::Duration;
 
 use actix::{Actor, Context, Handler, Message, Recipient};
 use nats::Connection;
 use serde::de::DeserializeOwned;
 
+use brokers::types::schema::{self, SchemaRole};
 use brokers::types::{MarketChannel, MarketChannelType, MarketEvent, MarketEventEnvelope};
 
 type Result<T> = anyhow::Result<T>;
 
+const SCHEMA_REQUEST_TIMEOUT: Duration = Duration::from_secs(2);
+
 fn nats_conn(nats_host: &str, username: &str, password: &str) -> Result<Connection> {
     let nats_connection = nats::Options::with_user_pass(username, password)
         .with_name("bitcoins_feeder")
@@ -15,7 +19,39 @@ fn nats_conn(nats_host: &str, username: &str, password: &str) -> Result<Connecti
     Ok(nats_connection)
 }
 
+fn schema_subject(name: &str, role: SchemaRole) -> String {
+    let role = match role {
+        SchemaRole::Producer => "producer",
+        SchemaRole::Consumer => "consumer",
+    };
+    format!("schemas.{}.{}", name, role)
+}
+
+/// Check the schema of the peers already connected, then serve ours to the peers connecting later
+///
+/// Fails if a peer uses an incompatible schema, proceeds if no peer answers.
+fn ensure_schema(conn: &Connection, name: &str, role: SchemaRole) -> Result<()> {
+    let peer_role = match role {
+        SchemaRole::Producer => SchemaRole::Consumer,
+        SchemaRole::Consumer => SchemaRole::Producer,
+    };
+    match conn.request_timeout(&schema_subject(name, peer_role), "", SCHEMA_REQUEST_TIMEOUT) {
+        Ok(reply) => {
+            let remote: serde_json::Value = serde_json::from_slice(reply.data.as_slice())?;
+            schema::ensure_compatible(name, role, &remote)?;
+        }
+        Err(e) => info!(schema = %name, err = %e, "no peer schema to check"),
+    }
+    let local = serde_json::to_vec(&schema::canonical_schema(name).ok_or_else(|| anyhow!("unknown schema {}", name))?)?;
+    conn.subscribe(&schema_subject(name, role))?
+        .with_handler(move |msg| msg.respond(&local));
+    Ok(())
+}
+
 pub trait Subject {
+    /// Name of the canonical schema of these events
+    const SCHEMA: &'static str;
+
     fn subject(&self) -> String;
 
     fn glob() -> String;
@@ -24,12 +60,18 @@ pub trait Subject {
 }
 
 impl Subject for MarketEventEnvelope {
+    const SCHEMA: &'static str = schema::MARKET_EVENT_ENVELOPE;
+
     fn subject(&self) -> String {
         format!("live_event.{}.{}", self.symbol.xch, match &self.e {
             MarketEvent::Trade(lt) => format!("{}.trades", lt.pair),
             MarketEvent::Orderbook(ob) => format!("{}.obs", ob.pair),
             MarketEvent::TradeCandle(ct) => format!("{}.cts", ct.pair),
             MarketEvent::BookCandle(bc) => format!("{}.bcs", bc.pair),
+            MarketEvent::FundingRate(fr) => format!("{}.frs", fr.pair),
+            MarketEvent::OrderbookL3(l3) => format!("{}.l3s", l3.pair),
+            MarketEvent::Quote(q) => format!("{}.quotes", q.pair),
+            MarketEvent::OpenInterest(oi) => format!("{}.oi", oi.pair),
         })
     }
 
@@ -45,6 +87,8 @@ impl Subject for MarketEventEnvelope {
             MarketChannelType::OpenInterest => format!("live_event.{}.{}.oi", xch, pair),
             MarketChannelType::Quotes => format!("live_event.{}.{}.quotes", xch, pair),
             MarketChannelType::QuotesCandles => format!("live_event.{}.{}.bcandles", xch, pair),
+            MarketChannelType::FundingRate => format!("live_event.{}.{}.frs", xch, pair),
+            MarketChannelType::OrderbookL3 => format!("live_event.{}.{}.l3s", xch, pair),
         }
     }
 }
@@ -56,6 +100,11 @@ pub struct NatsProducer {
 impl NatsProducer {
     pub fn new(nats_host: &str, username: &str, password: &str) -> Result<Self> {
         let nats_connection = nats_conn(nats_host, username, password)?;
+        ensure_schema(
+            &nats_connection,
+            <MarketEventEnvelope as Subject>::SCHEMA,
+            SchemaRole::Producer,
+        )?;
         Ok(NatsProducer {
             nats_conn: nats_connection,
         })
@@ -84,6 +133,10 @@ impl NatsConsumer {
     /// # Panics
     ///
     /// if it cannot acquire a connection to NATS
+    ///
+    /// # Errors
+    ///
+    /// if the producers connected to NATS use a schema incompatible with `T`
     pub fn new<T: 'static>(
         nats_host: &str,
         username: &str,
@@ -92,10 +145,11 @@ impl NatsConsumer {
         recipients: Vec<Recipient<Arc<T>>>,
     ) -> Result<Self>
     where
-        T: DeserializeOwned + Message + Send + Sync,
+        T: DeserializeOwned + Message + Subject + Send + Sync,
         <T as Message>::Result: Send,
     {
         let connection = nats_conn(nats_host, username, password)?;
+        ensure_schema(&connection, T::SCHEMA, SchemaRole::Consumer)?;
         let recipients = Arc::new(recipients);
         for topic in topics {
             let arc = recipients.clone();
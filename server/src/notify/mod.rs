@@ -14,6 +14,10 @@ pub struct Notification {
     msg: String,
 }
 
+impl Notification {
+    pub fn new(msg: String) -> Self { Self { msg } }
+}
+
 #[derive(Debug, serde::Deserialize)]
 pub struct DiscordNotifierOptions {
     webhook: String,
@@ -32,8 +36,7 @@ pub struct DiscordMessage {
 }
 
 impl DiscordNotifier {
-    #[allow(dead_code)]
-    fn new(options: &DiscordNotifierOptions) -> Self {
+    pub fn new(options: &DiscordNotifierOptions) -> Self {
         let connector = awc::Connector::new().timeout(Duration::from_secs(1)).limit(200);
         let client = Client::builder().connector(connector).finish();
         Self {
@@ -0,0 +1,131 @@
+//! Refreshes the pair registry of exchanges during long running sessions, so that orders are truncated and
+//! validated with the current trading rules of each pair, and notifies order managers of what changed.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use actix::{Actor, ActorFutureExt, Addr, AsyncContext, Context, ContextFutureSpawner, Handler, Recipient, WrapFuture};
+
+use brokers::bot::Ping;
+use brokers::manager::BrokerageManagerRef;
+use brokers::pair::{refresh_pairs, PairConfChanges};
+use brokers::prelude::*;
+
+pub struct PairRegistryRefresher {
+    xchg_mgr: BrokerageManagerRef,
+    /// How often the pairs of each exchange are fetched
+    exchanges: Vec<(Exchange, Duration)>,
+    recipients: Vec<Recipient<PairConfChanges>>,
+}
+
+impl PairRegistryRefresher {
+    pub fn new(
+        apis: BrokerageManagerRef,
+        exchanges: &HashMap<Exchange, BrokerSettings>,
+        recipients: Vec<Recipient<PairConfChanges>>,
+    ) -> Self {
+        let exchanges = exchanges
+            .iter()
+            .filter(|(_, conf)| conf.pairs_refresh_secs > 0)
+            .map(|(xchg, conf)| (*xchg, Duration::from_secs(conf.pairs_refresh_secs)))
+            .collect();
+        Self {
+            xchg_mgr: apis,
+            exchanges,
+            recipients,
+        }
+    }
+
+    pub fn actor(
+        apis: BrokerageManagerRef,
+        exchanges: &HashMap<Exchange, BrokerSettings>,
+        recipients: Vec<Recipient<PairConfChanges>>,
+    ) -> Addr<Self> {
+        Self::start(Self::new(apis, exchanges, recipients))
+    }
+
+    /// Whether no exchange refreshes its pairs
+    pub fn is_empty(&self) -> bool { self.exchanges.is_empty() }
+}
+
+impl Actor for PairRegistryRefresher {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        // Pairs are loaded at boot, so the first refresh only happens after a full interval
+        for (xchg, every) in self.exchanges.clone() {
+            ctx.run_interval(every, move |_act, ctx| {
+                ctx.notify(RefreshPairs(xchg));
+            });
+        }
+    }
+}
+
+/// Fetch the pairs of an exchange
+#[derive(actix::Message)]
+#[rtype(result = "()")]
+struct RefreshPairs(Exchange);
+
+impl Handler<RefreshPairs> for PairRegistryRefresher {
+    type Result = ();
+
+    fn handle(&mut self, msg: RefreshPairs, ctx: &mut Self::Context) -> Self::Result {
+        let xchg = msg.0;
+        let Some(api) = self.xchg_mgr.get_api(xchg) else {
+            return;
+        };
+        async move { refresh_pairs(&xchg, api.as_ref()).await }
+            .into_actor(self)
+            .map(move |result, act, _ctx| match result {
+                Ok(changes) if changes.is_empty() => {}
+                Ok(changes) => {
+                    info!(
+                        xchg = %xchg,
+                        listed = changes.listed.len(),
+                        delisted = changes.delisted.len(),
+                        updated = changes.updated.len(),
+                        "refreshed pairs"
+                    );
+                    for recipient in &act.recipients {
+                        recipient.do_send(changes.clone());
+                    }
+                }
+                Err(e) => error!(xchg = %xchg, err = %e, "failed to refresh pairs"),
+            })
+            .spawn(ctx);
+    }
+}
+
+impl Handler<Ping> for PairRegistryRefresher {
+    type Result = ();
+
+    fn handle(&mut self, _msg: Ping, _ctx: &mut Context<Self>) {}
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use brokers::manager::BrokerageManager;
+    use brokers::prelude::*;
+
+    use super::PairRegistryRefresher;
+
+    #[test]
+    fn refreshes_enabled_exchanges() {
+        let mut disabled = BrokerSettings::default_test(0.1);
+        disabled.pairs_refresh_secs = 0;
+        let exchanges = HashMap::from([
+            (Exchange::Binance, BrokerSettings::default_test(0.1)),
+            (Exchange::Kraken, disabled),
+        ]);
+        let refresher = PairRegistryRefresher::new(Arc::new(BrokerageManager::new()), &exchanges, vec![]);
+        assert!(!refresher.is_empty());
+        assert_eq!(refresher.exchanges, vec![(
+            Exchange::Binance,
+            Duration::from_secs(3600)
+        )]);
+    }
+}
@@ -0,0 +1,394 @@
+//! Periodically scans exchanges with the configured screeners, so that the universes of pairs they persist follow
+//! the current liquidity of markets, see [`trading::screener`].
+//!
+//! After each scan, the replicas of strategies copied for the universe of the screener are reconciled with it :
+//! replicas of pairs that entered the universe are started with their own market streams, replicas of pairs that
+//! left it are flattened and stop trading until their pair enters the universe again.
+
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use actix::{Actor, ActorFutureExt, AsyncContext, Context, ContextFutureSpawner, Handler, WrapFuture};
+use futures::future::join_all;
+use multimap::MultiMap;
+
+use brokers::bot::{DataStreamer, Ping};
+use brokers::manager::BrokerageManagerRef;
+use brokers::prelude::*;
+use brokers::types::MarketChannel;
+use db::DbOptions;
+use strategy::actor::StrategyActorOptions;
+use strategy::plugin::plugin_registry;
+use strategy::prelude::StrategyDriverSettings;
+use strategy::settings::StrategyCopySettings;
+use strategy::{strategy_key, StrategyKey, StrategyLifecycleCmd, Trader};
+use trading::engine::TradingEngine;
+use trading::screener::{load_universe, screener_db, Screener, ScreenerOptions};
+
+use crate::system::bots;
+
+/// Replicas of strategies copied for the universes of screeners
+pub struct ScreenedReplicas {
+    copies: Vec<StrategyCopySettings>,
+    db_opts: DbOptions<String>,
+    actor_options: StrategyActorOptions,
+    engine: Arc<TradingEngine>,
+    brokers_conf: Arc<HashMap<Exchange, BrokerSettings>>,
+    keys_path: PathBuf,
+    /// Replicas by screener, with whether they trade
+    traders: HashMap<String, HashMap<StrategyKey, (Trader, bool)>>,
+}
+
+/// Replicas to start, stop and resume after a scan
+#[derive(Default)]
+struct Reconciliation {
+    started: Vec<Trader>,
+    stopped: Vec<Trader>,
+    resumed: Vec<Trader>,
+}
+
+impl ScreenedReplicas {
+    /// Replicas of the copies of strategies, `traders` are the running traders that replicate the current universes
+    pub fn new(
+        copies: &[StrategyCopySettings],
+        db_opts: &DbOptions<String>,
+        actor_options: &StrategyActorOptions,
+        engine: Arc<TradingEngine>,
+        brokers_conf: Arc<HashMap<Exchange, BrokerSettings>>,
+        keys_path: PathBuf,
+        traders: &[Trader],
+    ) -> Self {
+        let copies: Vec<StrategyCopySettings> = copies.iter().filter(|c| c.screener().is_some()).cloned().collect();
+        let mut replicas = Self {
+            copies,
+            db_opts: db_opts.clone(),
+            actor_options: actor_options.clone(),
+            engine,
+            brokers_conf,
+            keys_path,
+            traders: HashMap::new(),
+        };
+        let names: HashSet<String> = replicas
+            .copies
+            .iter()
+            .filter_map(|c| c.screener().map(ToString::to_string))
+            .collect();
+        for name in names {
+            let keys: HashSet<StrategyKey> = replicas.replicas_of(&name).into_iter().map(|(key, _)| key).collect();
+            let running = traders
+                .iter()
+                .filter(|t| keys.contains(&t.key))
+                .map(|t| (t.key.clone(), (t.clone(), true)))
+                .collect();
+            replicas.traders.insert(name, running);
+        }
+        replicas
+    }
+
+    /// Whether no strategy is replicated for a screener
+    pub fn is_empty(&self) -> bool { self.copies.is_empty() }
+
+    /// The replicas of the latest universe of the screener, by key
+    fn replicas_of(&self, screener: &str) -> Vec<(StrategyKey, StrategyDriverSettings)> {
+        self.copies
+            .iter()
+            .filter(|c| c.screener() == Some(screener))
+            .flat_map(|c| match c.all_in(&self.db_opts) {
+                Ok(replicas) => replicas,
+                Err(e) => {
+                    error!(screener = %screener, err = %e, "failed to replicate strategies");
+                    vec![]
+                }
+            })
+            .filter_map(|settings| match strategy_key(plugin_registry(), &settings) {
+                Ok(key) => Some((key, settings)),
+                Err(e) => {
+                    error!(screener = %screener, err = %e, "failed to key a replica");
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// Start the replicas of pairs that entered the universe of the screener, and stop those of pairs that left it
+    fn reconcile(&mut self, screener: &str) -> Reconciliation {
+        let desired = self.replicas_of(screener);
+        let desired_keys: HashSet<StrategyKey> = desired.iter().map(|(key, _)| key.clone()).collect();
+        let running = self.traders.entry(screener.to_string()).or_default();
+        let (start, stop, resume) = diff(running, &desired_keys);
+        let mut reconciliation = Reconciliation::default();
+        for (key, settings) in desired.into_iter().filter(|(key, _)| start.contains(key)) {
+            match Trader::try_new(
+                plugin_registry(),
+                &self.db_opts,
+                &self.actor_options,
+                &settings,
+                self.engine.clone(),
+                None,
+            ) {
+                Ok(trader) => {
+                    running.insert(key, (trader.clone(), true));
+                    reconciliation.started.push(trader);
+                }
+                Err(e) => error!(key = ?key, err = %e, "failed to start replica"),
+            }
+        }
+        for key in stop {
+            if let Some((trader, trading)) = running.get_mut(&key) {
+                *trading = false;
+                reconciliation.stopped.push(trader.clone());
+            }
+        }
+        for key in resume {
+            if let Some((trader, trading)) = running.get_mut(&key) {
+                *trading = true;
+                reconciliation.resumed.push(trader.clone());
+            }
+        }
+        reconciliation
+    }
+
+    /// Stream the markets of started replicas
+    async fn stream(brokers_conf: Arc<HashMap<Exchange, BrokerSettings>>, keys_path: PathBuf, traders: Vec<Trader>) {
+        for trader in traders {
+            let mut channels: MultiMap<Exchange, MarketChannel> = MultiMap::new();
+            for channel in &trader.channels {
+                channels.insert(channel.exchange(), channel.clone());
+            }
+            let conf: HashMap<Exchange, BrokerSettings> = brokers_conf
+                .iter()
+                .filter(|(xch, _)| channels.contains_key(xch))
+                .map(|(xch, conf)| (*xch, conf.clone()))
+                .collect();
+            let mut streams = match bots::market_data_bots(Arc::new(conf), keys_path.clone(), &channels).await {
+                Ok(streams) => streams,
+                Err(e) => {
+                    error!(key = ?trader.key, err = %e, "failed to stream the markets of replica");
+                    continue;
+                }
+            };
+            let recipient = trader.market_event_recipient();
+            actix::spawn(async move {
+                join_all(streams.iter_mut().map(|(_, stream)| {
+                    let recipient = recipient.clone();
+                    stream.add_sink(Box::new(move |msg| {
+                        recipient.do_send(msg);
+                        Ok(())
+                    }))
+                }))
+                .await;
+            });
+        }
+    }
+}
+
+/// Replicas to start, stop and resume so that the trading replicas are the desired ones
+fn diff<T>(
+    running: &HashMap<StrategyKey, (T, bool)>,
+    desired: &HashSet<StrategyKey>,
+) -> (Vec<StrategyKey>, Vec<StrategyKey>, Vec<StrategyKey>) {
+    let start = desired.iter().filter(|k| !running.contains_key(*k)).cloned().collect();
+    let stop = running
+        .iter()
+        .filter(|(k, (_, trading))| *trading && !desired.contains(*k))
+        .map(|(k, _)| k.clone())
+        .collect();
+    let resume = running
+        .iter()
+        .filter(|(k, (_, trading))| !*trading && desired.contains(*k))
+        .map(|(k, _)| k.clone())
+        .collect();
+    (start, stop, resume)
+}
+
+pub struct PairScreener {
+    xchg_mgr: BrokerageManagerRef,
+    screeners: Vec<Arc<Screener>>,
+    replicas: Option<ScreenedReplicas>,
+}
+
+impl PairScreener {
+    pub fn try_new(
+        apis: BrokerageManagerRef,
+        db_opts: &DbOptions<String>,
+        screeners: &[ScreenerOptions],
+    ) -> trading::error::Result<Self> {
+        let db = screener_db(db_opts);
+        let screeners = screeners
+            .iter()
+            .map(|options| Screener::new(options.clone(), db.clone()).map(Arc::new))
+            .collect::<trading::error::Result<_>>()?;
+        Ok(Self {
+            xchg_mgr: apis,
+            screeners,
+            replicas: None,
+        })
+    }
+
+    /// Reconcile `replicas` with the universes of screeners after each scan
+    #[must_use]
+    pub fn with_replicas(mut self, replicas: ScreenedReplicas) -> Self {
+        self.replicas = Some(replicas).filter(|r| !r.is_empty());
+        self
+    }
+
+    /// Whether no screener is configured
+    pub fn is_empty(&self) -> bool { self.screeners.is_empty() }
+
+    /// Scan with the screeners that have no universe yet, so that replicas can be created for their universe
+    pub async fn scan_unscreened(&self, db_opts: &DbOptions<String>) {
+        let db = screener_db(db_opts);
+        for screener in &self.screeners {
+            let options = screener.options();
+            match load_universe(db.as_ref(), &options.name) {
+                Ok(Some(_)) => continue,
+                Ok(None) => {}
+                Err(e) => {
+                    error!(screener = %options.name, err = %e, "failed to load universe");
+                    continue;
+                }
+            }
+            let Some(api) = self.xchg_mgr.get_api(options.exchange) else {
+                continue;
+            };
+            match screener.scan(api.as_ref()).await {
+                Ok(universe) => info!(screener = %universe.name, pairs = universe.pairs.len(), "screened pairs"),
+                Err(e) => error!(xchg = %options.exchange, err = %e, "failed to screen pairs"),
+            }
+        }
+    }
+
+    /// Start, stop and resume the replicas of the universe of a screener
+    fn reconcile(&mut self, screener: &str, ctx: &mut Context<Self>) {
+        let Some(replicas) = self.replicas.as_mut() else {
+            return;
+        };
+        let Reconciliation {
+            started,
+            stopped,
+            resumed,
+        } = replicas.reconcile(screener);
+        if started.is_empty() && stopped.is_empty() && resumed.is_empty() {
+            return;
+        }
+        info!(
+            screener = %screener,
+            started = started.len(),
+            stopped = stopped.len(),
+            resumed = resumed.len(),
+            "reconciled replicas"
+        );
+        let lifecycle = stopped
+            .into_iter()
+            .map(|t| (t, StrategyLifecycleCmd::Flatten))
+            .chain(resumed.into_iter().map(|t| (t, StrategyLifecycleCmd::ResumeTrading)));
+        for (trader, cmd) in lifecycle {
+            async move {
+                if let Err(e) = trader.send(cmd).await.and_then(|r| r) {
+                    error!(key = ?trader.key, err = %e, "failed to reconcile replica");
+                }
+            }
+            .into_actor(self)
+            .spawn(ctx);
+        }
+        ScreenedReplicas::stream(replicas.brokers_conf.clone(), replicas.keys_path.clone(), started)
+            .into_actor(self)
+            .spawn(ctx);
+    }
+}
+
+impl Actor for PairScreener {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        // Universes are screened at boot, so that strategies created on the next start follow them
+        for (i, screener) in self.screeners.iter().enumerate() {
+            ctx.notify(Scan(i));
+            let every = Duration::from_secs(screener.options().refresh_secs.max(1));
+            ctx.run_interval(every, move |_act, ctx| {
+                ctx.notify(Scan(i));
+            });
+        }
+    }
+}
+
+/// Scan an exchange with a screener
+#[derive(actix::Message)]
+#[rtype(result = "()")]
+struct Scan(usize);
+
+impl Handler<Scan> for PairScreener {
+    type Result = ();
+
+    fn handle(&mut self, msg: Scan, ctx: &mut Self::Context) -> Self::Result {
+        let screener = self.screeners[msg.0].clone();
+        let xchg = screener.options().exchange;
+        let Some(api) = self.xchg_mgr.get_api(xchg) else {
+            return;
+        };
+        async move { screener.scan(api.as_ref()).await }
+            .into_actor(self)
+            .map(move |result, act, ctx| match result {
+                Ok(universe) => {
+                    info!(
+                        xchg = %xchg,
+                        screener = %universe.name,
+                        pairs = universe.pairs.len(),
+                        "screened pairs"
+                    );
+                    act.reconcile(&universe.name, ctx);
+                }
+                Err(e) => error!(xchg = %xchg, err = %e, "failed to screen pairs"),
+            })
+            .spawn(ctx);
+    }
+}
+
+impl Handler<Ping> for PairScreener {
+    type Result = ();
+
+    fn handle(&mut self, _msg: Ping, _ctx: &mut Context<Self>) {}
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::{HashMap, HashSet};
+    use std::sync::Arc;
+
+    use brokers::manager::BrokerageManager;
+    use db::{DbEngineOptions, DbOptions};
+    use strategy::StrategyKey;
+
+    use super::{diff, PairScreener};
+
+    #[test]
+    fn screens_configured_exchanges() {
+        let db_opts = DbOptions {
+            path: "/tmp/screener_test".to_string(),
+            engine: DbEngineOptions::InMemory,
+        };
+        let options = serde_json::from_value(serde_json::json!({"name": "liquid", "exchange": "binance"})).unwrap();
+        let screener = PairScreener::try_new(Arc::new(BrokerageManager::new()), &db_opts, &[options]).unwrap();
+        assert!(!screener.is_empty());
+        assert!(PairScreener::try_new(Arc::new(BrokerageManager::new()), &db_opts, &[])
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn replicas_follow_the_universe() {
+        let key = |pair: &str| StrategyKey("replica".to_string(), pair.to_string());
+        let running: HashMap<StrategyKey, ((), bool)> = HashMap::from([
+            (key("BTC_USDT"), ((), true)),
+            (key("ETH_USDT"), ((), true)),
+            (key("SOL_USDT"), ((), false)),
+        ]);
+        let desired: HashSet<StrategyKey> = HashSet::from([key("BTC_USDT"), key("SOL_USDT"), key("ADA_USDT")]);
+        let (start, stop, resume) = diff(&running, &desired);
+        assert_eq!(start, vec![key("ADA_USDT")]);
+        assert_eq!(stop, vec![key("ETH_USDT")]);
+        assert_eq!(resume, vec![key("SOL_USDT")]);
+    }
+}
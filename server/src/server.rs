@@ -1,5 +1,6 @@
 use std::sync::Arc;
 
+use actix::Addr;
 use actix_cors::Cors;
 use actix_web::middleware::{Compat, Logger};
 use actix_web::web::Data;
@@ -7,6 +8,7 @@ use actix_web::{http, HttpServer};
 use brokers::manager::BrokerageManagerRef;
 
 use crate::graphql_schemas::root::create_schema;
+use crate::kill_switch::KillSwitch;
 use crate::settings::{ApiSettings, CorsMode, Version};
 use crate::StrategyRegistry;
 
@@ -15,11 +17,13 @@ pub async fn httpserver(
     version: Option<Version>,
     apis: BrokerageManagerRef,
     strategies: Arc<StrategyRegistry>,
+    kill_switch: Addr<KillSwitch>,
 ) -> std::io::Result<()> {
     // Make and start the api
     let port = settings.port.0;
     let cors_mode = settings.cors.clone();
     let allowed_origins = settings.allowed_origins.as_ref().unwrap_or(&vec![]).clone();
+    let plugin_settings = settings.plugins.clone();
     let app = move || {
         let schema = create_schema();
 
@@ -56,8 +60,14 @@ pub async fn httpserver(
             .app_data(Data::new(schema))
             .app_data(Data::new(apis.clone()))
             .app_data(Data::new(strategies.clone()))
+            .app_data(Data::new(kill_switch.clone()))
             .app_data(Data::new(version.clone()))
             .configure(crate::api::config_app)
+            .configure(|cfg| {
+                if let Some(plugin_settings) = plugin_settings.as_ref() {
+                    crate::api::config_plugins(cfg, plugin_settings);
+                }
+            })
     };
     debug!("Starting api server on {} ...", port);
     HttpServer::new(app).bind(format!("0.0.0.0:{}", port))?.run().await
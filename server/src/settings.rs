@@ -1,4 +1,6 @@
 use std::collections::{HashMap, HashSet};
+use std::num::NonZeroUsize;
+use std::path::PathBuf;
 
 use chrono::Duration;
 use config::{Config, ConfigError, Environment, File};
@@ -6,13 +8,17 @@ use serde::{Deserialize, Serialize};
 
 use brokers::prelude::*;
 use db::DbOptions;
+use logging::prelude::FileFormat;
 use metrics::prom::PrometheusOptions;
 use portfolio::balance::BalanceReporterOptions;
 use portfolio::margin::MarginAccountReporterOptions;
 use strategy::actor::StrategyActorOptions;
 use strategy::prelude::*;
+use trading::order_manager::OrderManagerConfig;
+use trading::screener::ScreenerOptions;
 use util::ser::{decode_duration, decode_file_size};
 
+use crate::margin_monitor::MarginMonitorOptions;
 use crate::notify::DiscordNotifierOptions;
 
 #[derive(Debug, Deserialize, Clone)]
@@ -41,6 +47,18 @@ pub struct ApiSettings {
     pub cors: CorsMode,
     #[serde(default)]
     pub allowed_origins: Option<Vec<String>>,
+    /// Enables the endpoints loading strategy plugins, they are disabled if unset
+    #[serde(default)]
+    pub plugins: Option<PluginApiSettings>,
+}
+
+/// Plugin libraries run in the server process, so loading them requires a token and is restricted to a directory
+#[derive(Debug, Deserialize, Clone)]
+pub struct PluginApiSettings {
+    /// Bearer token of the requests managing plugins
+    pub token: String,
+    /// Directory plugin libraries are loaded from
+    pub library_dir: PathBuf,
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -84,7 +102,57 @@ pub struct AvroFileLoggerSettings {
     pub basedir: String,
     #[serde(deserialize_with = "util::ser::string_duration_chrono")]
     pub partitions_grace_period: Duration,
+    /// Overrides the worker threads configured in [`WorkerPoolSettings`]
     pub parallelism: Option<usize>,
+    /// Parquet files can be read by backtests as they are, avro by default
+    #[serde(default)]
+    pub format: FileFormat,
+}
+
+/// Name of the avro file logger in [`WorkerPoolSettings::actors`]
+pub const AVRO_FILE_LOGGER_POOL: &str = "avro_file_logger";
+
+/// Worker threads of actors started with a `SyncArbiter`
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct WorkerPoolSettings {
+    /// Worker threads for actors that are not configured in `actors`
+    pub default: Option<usize>,
+    /// Worker threads by actor name
+    #[serde(default)]
+    pub actors: HashMap<String, usize>,
+}
+
+impl WorkerPoolSettings {
+    pub const DEFAULT_PARALLELISM: usize = 2;
+
+    /// Number of worker threads to start for `actor`
+    pub fn parallelism(&self, actor: &str) -> usize {
+        self.actors
+            .get(actor)
+            .copied()
+            .or(self.default)
+            .unwrap_or(Self::DEFAULT_PARALLELISM)
+    }
+
+    /// # Errors
+    ///
+    /// if a pool has no threads, or more threads than available cores
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let cores = std::thread::available_parallelism().map_or(1, NonZeroUsize::get);
+        let pools = self
+            .default
+            .iter()
+            .map(|workers| ("default", *workers))
+            .chain(self.actors.iter().map(|(actor, workers)| (actor.as_str(), *workers)));
+        for (actor, workers) in pools {
+            if workers == 0 || workers > cores {
+                return Err(ConfigError::Message(format!(
+                    "worker pool '{actor}' has {workers} threads, expected between 1 and {cores} (available cores)"
+                )));
+            }
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -129,17 +197,25 @@ pub struct Settings {
     pub strategies: Vec<StrategyDriverSettings>,
     #[serde(default)]
     pub strategies_copy: Vec<StrategyCopySettings>,
+    /// Screeners of the pairs of exchanges, whose universes can be referenced by strategy copies
+    #[serde(default)]
+    pub screeners: Vec<ScreenerOptions>,
     pub storage: DbOptions<String>,
     pub prometheus: PrometheusOptions,
     #[serde(default)]
     pub telemetry: OpenTelemetrySettings,
     pub balance_reporter: Option<BalanceReporterOptions>,
     pub margin_account_reporter: Option<MarginAccountReporterOptions>,
+    pub margin_monitor: Option<MarginMonitorOptions>,
     pub version: Option<Version>,
     pub discord_notifier: Option<DiscordNotifierOptions>,
     pub connectivity_check_interval: Option<u64>,
     #[serde(default)]
+    pub order_manager: OrderManagerConfig,
+    #[serde(default)]
     pub strat_actor: StrategyActorOptions,
+    #[serde(default)]
+    pub worker_pool: WorkerPoolSettings,
 }
 
 impl Settings {
@@ -155,7 +231,9 @@ impl Settings {
             .build()?;
 
         // You can deserialize (and thus freeze) the entire configuration as
-        s.try_deserialize().map_err(Into::into)
+        let settings: Self = s.try_deserialize()?;
+        settings.worker_pool.validate()?;
+        Ok(settings)
     }
 
     pub fn sanitize(&self) {
@@ -178,6 +256,44 @@ impl Settings {
 
 #[cfg(test)]
 mod test {
+    use std::collections::HashMap;
+
+    use super::WorkerPoolSettings;
+
     #[test]
     fn test_deserialize() {}
+
+    #[test]
+    fn worker_pool_parallelism() {
+        let pool = WorkerPoolSettings {
+            default: Some(1),
+            actors: HashMap::from([("avro_file_logger".to_string(), 3)]),
+        };
+        assert_eq!(pool.parallelism("avro_file_logger"), 3);
+        assert_eq!(pool.parallelism("other"), 1);
+        assert_eq!(
+            WorkerPoolSettings::default().parallelism("other"),
+            WorkerPoolSettings::DEFAULT_PARALLELISM
+        );
+    }
+
+    #[test]
+    fn worker_pool_validation() {
+        let cores = std::thread::available_parallelism().unwrap().get();
+        let valid = WorkerPoolSettings {
+            default: Some(cores),
+            actors: HashMap::from([("avro_file_logger".to_string(), 1)]),
+        };
+        assert!(valid.validate().is_ok());
+        let empty = WorkerPoolSettings {
+            default: Some(0),
+            actors: HashMap::new(),
+        };
+        assert!(empty.validate().is_err());
+        let oversized = WorkerPoolSettings {
+            default: None,
+            actors: HashMap::from([("avro_file_logger".to_string(), cores + 1)]),
+        };
+        assert!(oversized.validate().is_err());
+    }
 }
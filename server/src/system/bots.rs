@@ -12,6 +12,14 @@ use brokers::pair::pair_to_symbol;
 use brokers::prelude::*;
 use brokers::types::{MarketChannel, PrivateStreamChannel};
 
+/// An account stream along with the named account it streams, `None` for the main account
+pub type AccountBot = (Option<String>, Box<BrokerageAccountDataStreamer>);
+
+/// The main account followed by the named accounts of the exchange
+pub fn accounts(conf: &BrokerSettings) -> impl Iterator<Item = Option<&str>> {
+    std::iter::once(None).chain(conf.accounts.iter().map(|account| Some(account.as_str())))
+}
+
 pub async fn market_data_bots<'a>(
     brokers_settings: Arc<HashMap<Exchange, BrokerSettings>>,
     keys_path: PathBuf,
@@ -33,7 +41,7 @@ pub async fn market_data_bots<'a>(
 pub async fn spot_account_bots(
     exchanges_settings: Arc<HashMap<Exchange, BrokerSettings>>,
     keys_path: PathBuf,
-) -> anyhow::Result<Vec<Box<BrokerageAccountDataStreamer>>> {
+) -> anyhow::Result<Vec<AccountBot>> {
     make_account_bots(exchanges_settings, keys_path, AccountType::Spot, |(_, conf)| {
         conf.use_account
     })
@@ -43,7 +51,7 @@ pub async fn spot_account_bots(
 pub async fn margin_account_bots(
     exchanges_settings: Arc<HashMap<Exchange, BrokerSettings>>,
     keys_path: PathBuf,
-) -> anyhow::Result<Vec<Box<BrokerageAccountDataStreamer>>> {
+) -> anyhow::Result<Vec<AccountBot>> {
     make_account_bots(exchanges_settings, keys_path, AccountType::Margin, |(_, conf)| {
         conf.use_margin_account
     })
@@ -53,7 +61,7 @@ pub async fn margin_account_bots(
 pub async fn isolated_margin_account_bots(
     exchanges_settings: Arc<HashMap<Exchange, BrokerSettings>>,
     keys_path: PathBuf,
-) -> anyhow::Result<Vec<Box<BrokerageAccountDataStreamer>>> {
+) -> anyhow::Result<Vec<AccountBot>> {
     let mut bots = vec![];
     for (xch, conf) in exchanges_settings
         .iter()
@@ -61,16 +69,18 @@ pub async fn isolated_margin_account_bots(
     {
         for pair in &conf.isolated_margin_account_pairs {
             let symbol = pair_to_symbol(xch, &Pair::from(pair.as_str()))?;
-            let creds = Brokerages::credentials_for(*xch, keys_path.clone())?;
-            let bot = Brokerages::new_account_stream(
-                *xch,
-                creds.clone(),
-                conf.use_test,
-                AccountType::IsolatedMargin(symbol.to_string()),
-                PrivateStreamChannel::all(),
-            )
-            .await?;
-            bots.push(bot);
+            for account in accounts(conf) {
+                let creds = Brokerages::credentials_for_account(*xch, account, keys_path.clone())?;
+                let bot = Brokerages::new_account_stream(
+                    *xch,
+                    creds,
+                    conf.use_test,
+                    AccountType::IsolatedMargin(symbol.to_string()),
+                    PrivateStreamChannel::all(),
+                )
+                .await?;
+                bots.push((account.map(ToString::to_string), bot));
+            }
         }
     }
     Ok(bots)
@@ -81,20 +91,22 @@ pub async fn make_account_bots(
     keys_path: PathBuf,
     account_type: AccountType,
     pred: fn(&(&Exchange, &BrokerSettings)) -> bool,
-) -> anyhow::Result<Vec<Box<BrokerageAccountDataStreamer>>> {
+) -> anyhow::Result<Vec<AccountBot>> {
     let mut bots = vec![];
 
     for (xch, conf) in exchanges_settings.iter().filter(pred) {
-        let creds = Brokerages::credentials_for(*xch, keys_path.clone())?;
-        let bot = Brokerages::new_account_stream(
-            *xch,
-            creds.clone(),
-            conf.use_test,
-            account_type.clone(),
-            PrivateStreamChannel::all(),
-        )
-        .await?;
-        bots.push(bot);
+        for account in accounts(conf) {
+            let creds = Brokerages::credentials_for_account(*xch, account, keys_path.clone())?;
+            let bot = Brokerages::new_account_stream(
+                *xch,
+                creds,
+                conf.use_test,
+                account_type.clone(),
+                PrivateStreamChannel::all(),
+            )
+            .await?;
+            bots.push((account.map(ToString::to_string), bot));
+        }
     }
     Ok(bots)
 }
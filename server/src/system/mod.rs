@@ -8,7 +8,7 @@ use std::rc::Rc;
 use std::sync::Arc;
 use std::time::Duration;
 
-use actix::{Actor, Addr, Recipient, SyncArbiter};
+use actix::{Actor, Addr, Recipient, SyncArbiter, SyncContext};
 use futures::future::select_all;
 use futures::TryFutureExt;
 use multimap::MultiMap;
@@ -20,9 +20,17 @@ use brokers::broker::{ActixMessageBroker, Broker, MarketEventEnvelopeRef};
 // use tokio::select;
 // use tokio::signal::unix::{signal, SignalKind};
 use crate::connectivity::run_connectivity_checker;
+use crate::dead_man_switch::DeadManSwitch;
+use crate::fee_tiers::FeeTierRefresher;
+use crate::kill_switch::KillSwitch;
+use crate::margin_monitor::MarginMonitor;
 use crate::nats::{NatsConsumer, NatsProducer, Subject};
+use crate::notify::DiscordNotifier;
+use crate::pair_registry::PairRegistryRefresher;
+use crate::screener::{PairScreener, ScreenedReplicas};
 use crate::server;
-use crate::settings::{AvroFileLoggerSettings, OutputSettings, Settings, StreamSettings};
+use crate::settings::{AvroFileLoggerSettings, OutputSettings, Settings, StreamSettings, WorkerPoolSettings,
+                      AVRO_FILE_LOGGER_POOL};
 use brokers::prelude::*;
 use brokers::types::{MarketChannel, MarketChannelTopic};
 use logging::prelude::*;
@@ -30,7 +38,6 @@ use metrics::prom::PrometheusPushActor;
 use portfolio::balance::BalanceReporter;
 use portfolio::margin::MarginAccountReporter;
 use strategy::plugin::plugin_registry;
-use strategy::prelude::StrategyCopySettings;
 use strategy::{self, StrategyKey, Trader};
 use trading::engine::{new_trading_engine, TradingEngine};
 use trading::interest::MarginInterestRateProvider;
@@ -74,14 +81,23 @@ pub async fn start(settings: Arc<RwLock<Settings>>) -> anyhow::Result<()> {
     let mut broadcast_recipients: Vec<Recipient<Arc<MarketEventEnvelope>>> = Vec::new();
     let mut strat_recipients: Vec<Recipient<Arc<MarketEventEnvelope>>> = Vec::new();
     let mut traders = vec![];
+    let mut order_managers = vec![];
 
     // strategies, cf strategies crate
     let settings_arc = Arc::clone(&settings);
 
+    // universes of pairs screened on exchanges, replicas of screened universes are created once they are screened
+    let mut screener = PairScreener::try_new(manager.clone(), &settings_v.storage, &settings_v.screeners)?;
+    screener
+        .scan_unscreened(&settings_v.storage)
+        .instrument(tracing::info_span!("screening pairs"))
+        .await;
+    let mut screened_replicas = None;
+
     for output in settings_v.outputs.clone() {
         match output {
             OutputSettings::AvroFileLogger(logger_settings) => {
-                broadcast_recipients.push(file_actor(logger_settings).recipient());
+                broadcast_recipients.push(file_actor(logger_settings, &settings_v.worker_pool));
             }
             OutputSettings::Nats(nats_settings) => {
                 let producer = NatsProducer::new(&nats_settings.host, &nats_settings.username, &nats_settings.password)
@@ -89,23 +105,38 @@ pub async fn start(settings: Arc<RwLock<Settings>>) -> anyhow::Result<()> {
                 broadcast_recipients.push(NatsProducer::start(producer).recipient());
             }
             OutputSettings::Strategies => {
-                let om = OrderManager::actor(&settings_v.storage, manager.clone()).await;
+                let om = OrderManager::actor_with_options(
+                    &settings_v.storage,
+                    manager.clone(),
+                    settings_v.order_manager.clone(),
+                )
+                .await;
                 termination_handles.push(Box::pin(bots::poll_pingables(vec![om.clone().recipient()])));
-                for entry in manager.exchange_apis().iter() {
-                    account_broker.register(
-                        AccountChannel::new(*entry.key(), AccountType::Spot),
-                        om.clone().recipient(),
-                    );
-                    account_broker.register(
-                        AccountChannel::new(*entry.key(), AccountType::Margin),
-                        om.clone().recipient(),
-                    );
+                order_managers.push(om.clone());
+                for (xch, conf) in exchanges.iter() {
+                    for account in bots::accounts(conf) {
+                        for account_type in [AccountType::Spot, AccountType::Margin] {
+                            account_broker.register(
+                                AccountChannel::for_account(*xch, account.map(ToString::to_string), account_type),
+                                om.clone().recipient(),
+                            );
+                        }
+                    }
                 }
                 let mirp = MarginInterestRateProvider::actor(manager.clone());
-                let engine = new_trading_engine(manager.clone(), om, mirp);
-                let strategies = make_traders(settings_arc.clone(), Arc::new(engine))
+                let engine = Arc::new(new_trading_engine(manager.clone(), om, mirp));
+                let strategies = make_traders(settings_arc.clone(), engine.clone())
                     .instrument(tracing::info_span!("starting strategies"))
                     .await;
+                screened_replicas = Some(ScreenedReplicas::new(
+                    &settings_v.strategies_copy,
+                    &settings_v.storage,
+                    &settings_v.strat_actor,
+                    engine,
+                    market_brokers_conf.clone(),
+                    keys_path.clone(),
+                    &strategies,
+                ));
                 for trader in strategies {
                     for channel in &trader.channels {
                         market_channels.insert(channel.exchange(), channel.clone());
@@ -121,16 +152,28 @@ pub async fn start(settings: Arc<RwLock<Settings>>) -> anyhow::Result<()> {
     // balance reporter
     if let Some(balance_reporter_opts) = &settings_v.balance_reporter {
         info!("starting balance reporter");
-        let reporter_addr = BalanceReporter::actor(balance_reporter_opts, manager.clone()).await;
+        let portfolios = traders.iter().map(Trader::expected_balances_recipient).collect();
+        let reporter_addr = BalanceReporter::actor(balance_reporter_opts, manager.clone(), portfolios).await;
         for api_ref in manager.exchange_apis() {
-            account_broker.register(
-                AccountChannel::new(*api_ref.key(), AccountType::Spot),
-                reporter_addr.clone().recipient(),
-            );
+            for account_type in [AccountType::Spot, AccountType::Margin] {
+                account_broker.register(
+                    AccountChannel::new(*api_ref.key(), account_type),
+                    reporter_addr.clone().recipient(),
+                );
+            }
         }
         termination_handles.push(Box::pin(bots::poll_pingables(vec![reporter_addr.recipient()])));
     }
 
+    let traders_by_key: Arc<HashMap<StrategyKey, Trader>> =
+        Arc::new(traders.clone().iter().map(|s| (s.key.clone(), s.clone())).collect());
+
+    // notifications
+    let notifier = settings_v.discord_notifier.as_ref().map(|options| {
+        info!("starting discord notifier");
+        DiscordNotifier::start(DiscordNotifier::new(options)).recipient()
+    });
+
     // margin balance reporter
     if let Some(margin_account_reporter_opts) = &settings_v.margin_account_reporter {
         info!("starting margin account reporter");
@@ -144,6 +187,62 @@ pub async fn start(settings: Arc<RwLock<Settings>>) -> anyhow::Result<()> {
         termination_handles.push(Box::pin(bots::poll_pingables(vec![reporter_addr.recipient()])));
     }
 
+    // margin level monitor
+    if let Some(margin_monitor_opts) = &settings_v.margin_monitor {
+        info!("starting margin monitor");
+        let monitor_addr = MarginMonitor::actor(
+            manager.clone(),
+            margin_monitor_opts,
+            traders_by_key.clone(),
+            notifier.clone(),
+        );
+        for api_ref in manager.exchange_apis().iter() {
+            account_broker.register(
+                AccountChannel::new(*api_ref.key(), AccountType::Margin),
+                monitor_addr.clone().recipient(),
+            );
+        }
+        termination_handles.push(Box::pin(bots::poll_pingables(vec![monitor_addr.recipient()])));
+    }
+
+    // exchange side cancellation of resting orders once the server stops
+    let dead_man_switch = DeadManSwitch::new(manager.clone(), exchanges);
+    if !dead_man_switch.is_empty() {
+        info!("starting dead man's switch");
+        let switch_addr = DeadManSwitch::start(dead_man_switch);
+        termination_handles.push(Box::pin(bots::poll_pingables(vec![switch_addr.recipient()])));
+    }
+
+    // fee tiers of the accounts
+    let fee_tiers = FeeTierRefresher::new(manager.clone(), exchanges);
+    if !fee_tiers.is_empty() {
+        info!("starting fee tier refresher");
+        let refresher_addr = FeeTierRefresher::start(fee_tiers);
+        termination_handles.push(Box::pin(bots::poll_pingables(vec![refresher_addr.recipient()])));
+    }
+
+    // pairs listed by exchanges and their trading rules
+    let pair_registry = PairRegistryRefresher::new(
+        manager.clone(),
+        exchanges,
+        order_managers.iter().map(|om| om.clone().recipient()).collect(),
+    );
+    if !pair_registry.is_empty() {
+        info!("starting pair registry refresher");
+        let refresher_addr = PairRegistryRefresher::start(pair_registry);
+        termination_handles.push(Box::pin(bots::poll_pingables(vec![refresher_addr.recipient()])));
+    }
+
+    // universes of pairs screened on exchanges, replicas follow each scan
+    if let Some(replicas) = screened_replicas {
+        screener = screener.with_replicas(replicas);
+    }
+    if !screener.is_empty() {
+        info!("starting pair screener");
+        let screener_addr = PairScreener::start(screener);
+        termination_handles.push(Box::pin(bots::poll_pingables(vec![screener_addr.recipient()])));
+    }
+
     // metrics actor
     let _prom_push = PrometheusPushActor::start(PrometheusPushActor::new(&settings_v.prometheus));
 
@@ -185,9 +284,11 @@ pub async fn start(settings: Arc<RwLock<Settings>>) -> anyhow::Result<()> {
                 if !bots.is_empty() {
                     let account_broker_ref = account_broker_ref.clone();
                     let fut = async move {
-                        select_all(bots.iter_mut().map(|bot| {
+                        select_all(bots.iter_mut().map(|(account, bot)| {
                             let account_broker_ref = account_broker_ref.clone();
-                            bot.add_sink(Box::new(move |msg| {
+                            let account = account.clone();
+                            bot.add_sink(Box::new(move |mut msg| {
+                                msg.account = account.clone();
                                 account_broker_ref.broadcast(msg);
                                 Ok(())
                             }))
@@ -237,14 +338,14 @@ pub async fn start(settings: Arc<RwLock<Settings>>) -> anyhow::Result<()> {
         }
     }
 
-    let traders_by_key: Arc<HashMap<StrategyKey, Trader>> =
-        Arc::new(traders.clone().iter().map(|s| (s.key.clone(), s.clone())).collect());
+    let kill_switch = KillSwitch::actor(traders_by_key.clone(), order_managers);
     // API Server
     let server = server::httpserver(
         &settings_v.api,
         settings_v.version.clone(),
         manager.clone(),
         traders_by_key,
+        kill_switch,
     );
     termination_handles.push(Box::pin(server));
 
@@ -255,7 +356,7 @@ pub async fn start(settings: Arc<RwLock<Settings>>) -> anyhow::Result<()> {
     // Somehow necessary because settings_v doesn't live long enough
     termination_handles.push(Box::pin(tokio::signal::ctrl_c()));
     if let Some(interval) = settings_v.connectivity_check_interval {
-        connectivity_checker(interval);
+        connectivity_checker(interval, manager.clone());
     }
 
     let x = select_all(termination_handles).await.0.map_err(|e| anyhow!(e));
@@ -284,20 +385,50 @@ pub async fn start(settings: Arc<RwLock<Settings>>) -> anyhow::Result<()> {
     // }
 }
 
-fn connectivity_checker(interval: u64) { actix::spawn(run_connectivity_checker(interval)); }
+fn connectivity_checker(interval: u64, manager: BrokerageManagerRef) {
+    actix::spawn(run_connectivity_checker(interval, manager));
+}
+
+/// Start `workers` threads running the actor built by `factory`
+fn start_sync_actor<A, F>(name: &str, workers: usize, factory: F) -> Addr<A>
+where
+    A: Actor<Context = SyncContext<A>>,
+    F: Fn() -> A + Send + Sync + 'static,
+{
+    info!(actor = name, workers, "starting sync actor");
+    SyncArbiter::start(workers, factory)
+}
 
-fn file_actor(settings: AvroFileLoggerSettings) -> Addr<AvroFileActor<MarketEventEnvelope>> {
-    info!("starting avro file logger");
-    SyncArbiter::start(settings.parallelism.unwrap_or(2), move || {
+fn file_actor(settings: AvroFileLoggerSettings, pool: &WorkerPoolSettings) -> Recipient<Arc<MarketEventEnvelope>> {
+    let workers = settings
+        .parallelism
+        .unwrap_or_else(|| pool.parallelism(AVRO_FILE_LOGGER_POOL));
+    let options = move || -> FileActorOptions<MarketEventEnvelope> {
         let dir = Path::new(settings.basedir.as_str());
         fs::create_dir_all(&dir).unwrap();
-        AvroFileActor::new(&FileActorOptions {
+        FileActorOptions {
             base_dir: dir.to_str().unwrap().to_string(),
             max_file_size: settings.file_rotation.max_file_size,
             max_file_time: settings.file_rotation.max_file_time,
             partitioner: Rc::new(MarketEventPartitioner::new(settings.partitions_grace_period)),
-        })
-    })
+            format: settings.format,
+        }
+    };
+    let format = options().format;
+    info!(?format, "starting file logger");
+    match format {
+        FileFormat::Avro => {
+            start_sync_actor(AVRO_FILE_LOGGER_POOL, workers, move || AvroFileActor::new(&options())).recipient()
+        }
+        FileFormat::Parquet => {
+            start_sync_actor(
+                AVRO_FILE_LOGGER_POOL,
+                workers,
+                move || ParquetFileActor::new(&options()),
+            )
+            .recipient()
+        }
+    }
 }
 
 #[tracing::instrument(skip(settings, engine), level = "info")]
@@ -308,7 +439,7 @@ async fn make_traders(settings: Arc<RwLock<Settings>>, engine: Arc<TradingEngine
         settings_v
             .strategies_copy
             .iter()
-            .flat_map(StrategyCopySettings::all)
+            .flat_map(|copy| copy.all_in(&settings_v.storage))
             .flatten(),
     );
     let storage = Arc::new(settings_v.storage.clone());
@@ -339,3 +470,52 @@ pub async fn poll_actor<T: Actor>(addr: Addr<T>) -> std::io::Result<()> {
         addr.connected();
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::collections::{HashMap, HashSet};
+    use std::thread::ThreadId;
+    use std::time::Duration;
+
+    use actix::{Actor, Handler, SyncContext};
+
+    use crate::settings::WorkerPoolSettings;
+
+    use super::start_sync_actor;
+
+    struct ThreadReporter;
+
+    impl Actor for ThreadReporter {
+        type Context = SyncContext<Self>;
+    }
+
+    #[derive(actix::Message)]
+    #[rtype(result = "ThreadId")]
+    struct WhichThread;
+
+    impl Handler<WhichThread> for ThreadReporter {
+        type Result = ThreadId;
+
+        fn handle(&mut self, _msg: WhichThread, _ctx: &mut Self::Context) -> Self::Result {
+            // Keep the worker busy so that pending messages are spread over the whole pool
+            std::thread::sleep(Duration::from_millis(20));
+            std::thread::current().id()
+        }
+    }
+
+    #[actix::test]
+    async fn sync_actor_starts_configured_workers() {
+        let pool = WorkerPoolSettings {
+            default: None,
+            actors: HashMap::from([("reporter".to_string(), 3)]),
+        };
+        let workers = pool.parallelism("reporter");
+        let addr = start_sync_actor("reporter", workers, || ThreadReporter);
+        let threads: HashSet<ThreadId> = futures::future::join_all((0..workers * 10).map(|_| addr.send(WhichThread)))
+            .await
+            .into_iter()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(threads.len(), 3);
+    }
+}
@@ -0,0 +1,240 @@
+//! Stationarity and cointegration tests for mean reversion strategies
+
+use crate::math::mean_reversion::half_life_of_gamma;
+
+/// Result of an ordinary least squares regression `y = alpha + beta * x`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ols {
+    pub alpha: f64,
+    pub beta: f64,
+    /// Standard error of `beta`
+    pub beta_std_err: f64,
+}
+
+/// Fit `y = alpha + beta * x` with ordinary least squares, `None` if there are less than three points or `x` is
+/// constant
+#[allow(clippy::cast_precision_loss)]
+pub fn ols(x: &[f64], y: &[f64]) -> Option<Ols> {
+    let len = x.len().min(y.len());
+    if len < 3 {
+        return None;
+    }
+    let (x, y) = (&x[..len], &y[..len]);
+    let n = len as f64;
+    let mean_x = x.iter().sum::<f64>() / n;
+    let mean_y = y.iter().sum::<f64>() / n;
+    let (mut sxx, mut sxy) = (0.0, 0.0);
+    for (xi, yi) in x.iter().zip(y) {
+        sxx += (xi - mean_x).powi(2);
+        sxy += (xi - mean_x) * (yi - mean_y);
+    }
+    if sxx == 0.0 {
+        return None;
+    }
+    let beta = sxy / sxx;
+    let alpha = mean_y - beta * mean_x;
+    let ssr: f64 = x.iter().zip(y).map(|(xi, yi)| (yi - alpha - beta * xi).powi(2)).sum();
+    let beta_std_err = (ssr / (n - 2.0) / sxx).sqrt();
+    Some(Ols {
+        alpha,
+        beta,
+        beta_std_err,
+    })
+}
+
+/// Dickey-Fuller test of `Δy(t) = c + gamma * y(t-1)`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Adf {
+    /// Coefficient of the lagged level, negative for a mean reverting series
+    pub gamma: f64,
+    /// t-statistic of `gamma`, the more negative the more likely the series is stationary
+    pub statistic: f64,
+}
+
+impl Adf {
+    /// Approximate 5% critical value of the test with a constant, for large samples
+    pub const CRITICAL_5PCT: f64 = -2.86;
+
+    /// Whether the unit root hypothesis is rejected at the 5% level
+    pub fn is_stationary(&self) -> bool { self.statistic < Self::CRITICAL_5PCT }
+}
+
+/// Dickey-Fuller unit root test with a constant and no lagged differences, `None` if the series is too short
+pub fn adf(series: &[f64]) -> Option<Adf> {
+    let lagged = &series[..series.len().saturating_sub(1)];
+    let diffs: Vec<f64> = series.windows(2).map(|w| w[1] - w[0]).collect();
+    let fit = ols(lagged, &diffs)?;
+    if fit.beta_std_err == 0.0 {
+        return None;
+    }
+    Some(Adf {
+        gamma: fit.beta,
+        statistic: fit.beta / fit.beta_std_err,
+    })
+}
+
+/// Fit `y = X * coefs` with ordinary least squares for any number of regressors
+///
+/// returns: the coefficients and their standard errors, `None` if there are not more rows than regressors or the
+/// regressors are collinear
+#[allow(clippy::cast_precision_loss, clippy::needless_range_loop)]
+fn multi_ols(rows: &[Vec<f64>], y: &[f64]) -> Option<(Vec<f64>, Vec<f64>)> {
+    let k = rows.first()?.len();
+    let n = rows.len().min(y.len());
+    if n <= k {
+        return None;
+    }
+    // Invert X'X with Gauss-Jordan elimination, augmented with the identity
+    let mut m = vec![vec![0.0; 2 * k]; k];
+    let mut xty = vec![0.0; k];
+    for (row, yi) in rows.iter().zip(y) {
+        for i in 0..k {
+            for j in 0..k {
+                m[i][j] += row[i] * row[j];
+            }
+            xty[i] += row[i] * yi;
+        }
+    }
+    for i in 0..k {
+        m[i][k + i] = 1.0;
+    }
+    for col in 0..k {
+        let pivot = (col..k).max_by(|a, b| m[*a][col].abs().total_cmp(&m[*b][col].abs()))?;
+        if m[pivot][col].abs() < 1e-12 {
+            return None;
+        }
+        m.swap(col, pivot);
+        let p = m[col][col];
+        m[col].iter_mut().for_each(|v| *v /= p);
+        for r in 0..k {
+            if r != col {
+                let factor = m[r][col];
+                for c in 0..2 * k {
+                    m[r][c] -= factor * m[col][c];
+                }
+            }
+        }
+    }
+    let coefs: Vec<f64> = (0..k).map(|i| (0..k).map(|j| m[i][k + j] * xty[j]).sum()).collect();
+    let ssr: f64 = rows
+        .iter()
+        .zip(y)
+        .map(|(row, yi)| (yi - row.iter().zip(&coefs).map(|(x, c)| x * c).sum::<f64>()).powi(2))
+        .sum();
+    let sigma2 = ssr / (n - k) as f64;
+    let std_errs = (0..k).map(|i| (sigma2 * m[i][k + i]).sqrt()).collect();
+    Some((coefs, std_errs))
+}
+
+/// Augmented Dickey-Fuller unit root test of `Δy(t) = c + gamma * y(t-1) + sum(phi(i) * Δy(t-i))` with `lags`
+/// lagged differences to absorb the autocorrelation of the differences, `None` if the series is too short
+pub fn adf_with_lags(series: &[f64], lags: usize) -> Option<Adf> {
+    if lags == 0 {
+        return adf(series);
+    }
+    let diffs: Vec<f64> = series.windows(2).map(|w| w[1] - w[0]).collect();
+    // Δy(t) is diffs[t - 1], regressed on y(t-1) and Δy(t-1)..Δy(t-lags)
+    let (rows, y): (Vec<Vec<f64>>, Vec<f64>) = (lags + 1..series.len())
+        .map(|t| {
+            let mut row = Vec::with_capacity(lags + 2);
+            row.push(1.0);
+            row.push(series[t - 1]);
+            row.extend((1..=lags).map(|i| diffs[t - 1 - i]));
+            (row, diffs[t - 1])
+        })
+        .unzip();
+    let (coefs, std_errs) = multi_ols(&rows, &y)?;
+    if std_errs[1] == 0.0 || !std_errs[1].is_finite() {
+        return None;
+    }
+    Some(Adf {
+        gamma: coefs[1],
+        statistic: coefs[1] / std_errs[1],
+    })
+}
+
+/// Engle-Granger cointegration of `y` and `x` : `y = alpha + beta * x + spread` where the spread is stationary
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Cointegration {
+    pub alpha: f64,
+    pub beta: f64,
+    /// Unit root test of the spread
+    pub adf: Adf,
+    /// Periods for a deviation of the spread to revert halfway to its mean, infinite if it does not revert
+    pub half_life: f64,
+}
+
+impl Cointegration {
+    /// Approximate 5% critical value of the unit root test of the residuals of a regression of two series with a
+    /// constant, lower than the one of [`Adf`] since the spread is fitted to be as stationary as possible
+    pub const CRITICAL_5PCT: f64 = -3.34;
+
+    /// Whether the no cointegration hypothesis is rejected at the 5% level
+    pub fn is_cointegrated(&self) -> bool { self.adf.statistic < Self::CRITICAL_5PCT }
+}
+
+/// Test the cointegration of `y` and `x` with the Engle-Granger two-step method
+pub fn engle_granger(y: &[f64], x: &[f64]) -> Option<Cointegration> { engle_granger_with_lags(y, x, 0) }
+
+/// Test the cointegration of `y` and `x` with the Engle-Granger two-step method, the spread is tested with `lags`
+/// lagged differences
+pub fn engle_granger_with_lags(y: &[f64], x: &[f64], lags: usize) -> Option<Cointegration> {
+    let fit = ols(x, y)?;
+    let spread: Vec<f64> = x.iter().zip(y).map(|(xi, yi)| yi - fit.alpha - fit.beta * xi).collect();
+    let adf = adf_with_lags(&spread, lags)?;
+    Some(Cointegration {
+        alpha: fit.alpha,
+        beta: fit.beta,
+        adf,
+        half_life: half_life_of_gamma(adf.gamma),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use crate::cointegration::{adf, adf_with_lags, engle_granger, engle_granger_with_lags, ols};
+    use crate::test_util::{noise, random_walk};
+
+    #[test]
+    fn test_ols() {
+        let x = [1.0, 2.0, 3.0, 4.0];
+        let y = [3.0, 5.0, 7.0, 9.0];
+        let fit = ols(&x, &y).unwrap();
+        assert!(approx_eq!(f64, fit.alpha, 1.0, epsilon = 1e-12));
+        assert!(approx_eq!(f64, fit.beta, 2.0, epsilon = 1e-12));
+        assert!(ols(&[1.0, 1.0, 1.0], &y).is_none());
+    }
+
+    #[test]
+    fn test_adf() {
+        let stationary = noise(7, 1000);
+        assert!(adf(&stationary).unwrap().is_stationary());
+        let walk = random_walk(7, 1000);
+        assert!(!adf(&walk).unwrap().is_stationary());
+    }
+
+    #[test]
+    fn test_augmented_adf() {
+        let stationary = noise(11, 1000);
+        let augmented = adf_with_lags(&stationary, 3).unwrap();
+        assert!(augmented.is_stationary());
+        assert!(approx_eq!(f64, augmented.gamma, -1.0, epsilon = 0.3));
+        assert!(!adf_with_lags(&random_walk(11, 1000), 3).unwrap().is_stationary());
+        assert_eq!(adf_with_lags(&stationary, 0), adf(&stationary));
+        assert!(adf_with_lags(&stationary[..4], 3).is_none());
+    }
+
+    #[test]
+    fn test_engle_granger() {
+        let x = random_walk(1, 1000);
+        let y: Vec<f64> = x.iter().zip(noise(2, 1000)).map(|(xi, e)| 2.0 * xi + 5.0 + e).collect();
+        let cointegration = engle_granger(&y, &x).unwrap();
+        assert!(approx_eq!(f64, cointegration.beta, 2.0, epsilon = 0.05));
+        assert!(cointegration.adf.is_stationary());
+        assert!(cointegration.half_life < 2.0);
+        assert!(cointegration.is_cointegrated());
+        let z = random_walk(3, 1000);
+        assert!(!engle_granger(&z, &x).unwrap().adf.is_stationary());
+        assert!(!engle_granger_with_lags(&z, &x, 2).unwrap().is_cointegrated());
+    }
+}
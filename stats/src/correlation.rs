@@ -0,0 +1,189 @@
+//! Pairwise correlation of returns between price series
+
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Utc};
+
+/// A symmetric matrix of pairwise correlations, rows and columns are in the order of `keys`
+#[derive(Debug, Clone, PartialEq)]
+pub struct CorrelationMatrix<K> {
+    pub keys: Vec<K>,
+    values: Vec<f64>,
+}
+
+impl<K: PartialEq> CorrelationMatrix<K> {
+    fn new(keys: Vec<K>) -> Self {
+        let len = keys.len();
+        Self {
+            keys,
+            values: vec![f64::NAN; len * len],
+        }
+    }
+
+    /// Number of series in the matrix
+    pub fn len(&self) -> usize { self.keys.len() }
+
+    pub fn is_empty(&self) -> bool { self.keys.is_empty() }
+
+    /// Correlation between the series at row `i` and column `j`
+    ///
+    /// # Panics
+    ///
+    /// if `i` or `j` is out of bounds
+    pub fn get(&self, i: usize, j: usize) -> f64 {
+        assert!(i < self.len() && j < self.len(), "index out of bounds");
+        self.values[i * self.len() + j]
+    }
+
+    /// Correlation between the series of `a` and `b`, if both are in the matrix
+    pub fn correlation(&self, a: &K, b: &K) -> Option<f64> {
+        let i = self.keys.iter().position(|k| k == a)?;
+        let j = self.keys.iter().position(|k| k == b)?;
+        Some(self.get(i, j))
+    }
+
+    /// Rows of the matrix, in the order of `keys`
+    pub fn rows(&self) -> impl Iterator<Item = &[f64]> { self.values.chunks(self.len().max(1)) }
+
+    fn set(&mut self, i: usize, j: usize, value: f64) {
+        let len = self.len();
+        self.values[i * len + j] = value;
+        self.values[j * len + i] = value;
+    }
+}
+
+/// Compute the matrix of pairwise Pearson correlations between the returns of price series.
+///
+/// Series are aligned on their most recent price, so that series of different lengths cover the same
+/// period, and missing prices are expected to be `NaN`, see [`align_on_timestamps`] for timestamped series.
+/// Each pair of series is correlated over the periods where both have a return, pairs with less than two
+/// common returns or a constant series have a `NaN` correlation.
+pub fn correlation_matrix<K: Clone + PartialEq>(series: &[(K, &[f64])]) -> CorrelationMatrix<K> {
+    let len = series.iter().map(|(_, prices)| prices.len()).max().unwrap_or(0);
+    let returns: Vec<Vec<f64>> = series.iter().map(|(_, prices)| aligned_returns(prices, len)).collect();
+    let mut matrix = CorrelationMatrix::new(series.iter().map(|(k, _)| k.clone()).collect());
+    for i in 0..returns.len() {
+        matrix.set(i, i, 1.0);
+        for j in (i + 1)..returns.len() {
+            matrix.set(i, j, pearson(&returns[i], &returns[j]));
+        }
+    }
+    matrix
+}
+
+/// Align timestamped price series on the union of their timestamps, prices missing from a series are `NaN`
+pub fn align_on_timestamps<K: Clone>(series: &[(K, &[(DateTime<Utc>, f64)])]) -> Vec<(K, Vec<f64>)> {
+    let mut timestamps: BTreeMap<DateTime<Utc>, usize> = series
+        .iter()
+        .flat_map(|(_, prices)| prices.iter().map(|(ts, _)| (*ts, 0)))
+        .collect();
+    for (index, position) in timestamps.values_mut().enumerate() {
+        *position = index;
+    }
+    series
+        .iter()
+        .map(|(k, prices)| {
+            let mut aligned = vec![f64::NAN; timestamps.len()];
+            for (ts, price) in prices.iter() {
+                aligned[timestamps[ts]] = *price;
+            }
+            (k.clone(), aligned)
+        })
+        .collect()
+}
+
+/// Simple returns of `prices` padded at the start to `len` prices, missing returns are `NaN`
+fn aligned_returns(prices: &[f64], len: usize) -> Vec<f64> {
+    let mut padded = vec![f64::NAN; len - prices.len()];
+    padded.extend_from_slice(prices);
+    padded.windows(2).map(|w| w[1] / w[0] - 1.0).collect()
+}
+
+/// Pearson correlation over the indices where both series have a finite value
+pub(crate) fn pearson(a: &[f64], b: &[f64]) -> f64 {
+    let pairs: Vec<(f64, f64)> = a
+        .iter()
+        .zip(b)
+        .filter(|(x, y)| x.is_finite() && y.is_finite())
+        .map(|(x, y)| (*x, *y))
+        .collect();
+    if pairs.len() < 2 {
+        return f64::NAN;
+    }
+    #[allow(clippy::cast_precision_loss)]
+    let n = pairs.len() as f64;
+    let mean_a = pairs.iter().map(|(x, _)| x).sum::<f64>() / n;
+    let mean_b = pairs.iter().map(|(_, y)| y).sum::<f64>() / n;
+    let (mut cov, mut var_a, mut var_b) = (0.0, 0.0, 0.0);
+    for (x, y) in &pairs {
+        cov += (x - mean_a) * (y - mean_b);
+        var_a += (x - mean_a).powi(2);
+        var_b += (y - mean_b).powi(2);
+    }
+    if var_a == 0.0 || var_b == 0.0 {
+        return f64::NAN;
+    }
+    cov / (var_a.sqrt() * var_b.sqrt())
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{TimeZone, Utc};
+
+    use crate::correlation::{align_on_timestamps, correlation_matrix};
+
+    #[test]
+    fn test_correlation_matrix() {
+        let btc = [100.0, 102.0, 101.0, 105.0, 104.0, 108.0];
+        // Same returns as btc, at a different price level
+        let eth = [10.0, 10.2, 10.1, 10.5, 10.4, 10.8];
+        let usdc = [1.0, 0.99, 1.01, 0.98, 1.02, 0.97];
+        let matrix = correlation_matrix(&[("BTC_USDT", &btc[..]), ("ETH_USDT", &eth[..]), ("USDC_USDT", &usdc[..])]);
+        assert_eq!(matrix.len(), 3);
+        for i in 0..3 {
+            assert!(approx_eq!(f64, matrix.get(i, i), 1.0));
+            for j in 0..3 {
+                assert!(approx_eq!(f64, matrix.get(i, j), matrix.get(j, i)));
+                assert!(matrix.get(i, j) >= -1.0 - 1e-12 && matrix.get(i, j) <= 1.0 + 1e-12);
+            }
+        }
+        assert!(approx_eq!(
+            f64,
+            matrix.correlation(&"BTC_USDT", &"ETH_USDT").unwrap(),
+            1.0,
+            epsilon = 1e-9
+        ));
+        assert!(matrix.correlation(&"BTC_USDT", &"USDC_USDT").unwrap() < 0.0);
+        assert!(matrix.correlation(&"BTC_USDT", &"XRP_USDT").is_none());
+    }
+
+    #[test]
+    fn test_misaligned_series() {
+        let ts = |s: i64| Utc.timestamp_opt(s, 0).unwrap();
+        let btc = [
+            (ts(0), 100.0),
+            (ts(1), 102.0),
+            (ts(2), 101.0),
+            (ts(3), 105.0),
+            (ts(4), 104.0),
+        ];
+        // Missing the price at ts(2)
+        let eth = [(ts(0), 10.0), (ts(1), 10.2), (ts(3), 10.5), (ts(4), 10.4)];
+        let aligned = align_on_timestamps(&[("BTC_USDT", &btc[..]), ("ETH_USDT", &eth[..])]);
+        assert_eq!(aligned[0].1.len(), 5);
+        assert!(aligned[1].1[2].is_nan());
+        let series: Vec<(&str, &[f64])> = aligned.iter().map(|(k, v)| (*k, v.as_slice())).collect();
+        let matrix = correlation_matrix(&series);
+        let correlation = matrix.get(0, 1);
+        assert!(correlation.is_finite());
+        assert!(approx_eq!(f64, correlation, matrix.get(1, 0)));
+
+        // Shorter series are aligned on their most recent price
+        let short = [10.1, 10.5, 10.4];
+        let matrix = correlation_matrix(&[
+            ("BTC_USDT", &[100.0, 102.0, 101.0, 105.0, 104.0][..]),
+            ("ETH_USDT", &short[..]),
+        ]);
+        assert!(approx_eq!(f64, matrix.get(0, 1), 1.0, epsilon = 1e-9));
+    }
+}
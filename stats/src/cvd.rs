@@ -0,0 +1,117 @@
+//! Cumulative volume delta, the running difference between buyer and seller initiated volume
+
+use std::collections::VecDeque;
+
+use crate::error::Error;
+use crate::{Next, Reset};
+
+/// Cumulative volume delta over a session, or over the last `window` trades if set.
+///
+/// Inputs are `(volume, is_buy)` tuples where `is_buy` is true if the trade was initiated by a buyer.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CumulativeVolumeDelta {
+    window: Option<usize>,
+    deltas: VecDeque<f64>,
+    pub buy_volume: f64,
+    pub sell_volume: f64,
+}
+
+impl CumulativeVolumeDelta {
+    /// A CVD accumulating every trade until reset, typically for a trading session
+    pub fn session() -> Self { Self::default() }
+
+    /// A CVD over the last `window` trades
+    pub fn windowed(window: usize) -> anyhow::Result<Self> {
+        if window == 0 {
+            Err(Error::InvalidParameter {
+                name: "window".to_string(),
+                expected: "!= 0".to_string(),
+                found: format!("{}", window),
+            }
+            .into())
+        } else {
+            Ok(Self {
+                window: Some(window),
+                deltas: VecDeque::with_capacity(window),
+                ..Self::default()
+            })
+        }
+    }
+
+    /// Buy volume minus sell volume
+    pub fn value(&self) -> f64 { self.buy_volume - self.sell_volume }
+
+    fn add(&mut self, delta: f64) {
+        if delta > 0.0 {
+            self.buy_volume += delta;
+        } else {
+            self.sell_volume -= delta;
+        }
+    }
+
+    fn remove(&mut self, delta: f64) {
+        if delta > 0.0 {
+            self.buy_volume -= delta;
+        } else {
+            self.sell_volume += delta;
+        }
+    }
+}
+
+impl Next<(f64, bool)> for CumulativeVolumeDelta {
+    type Output = f64;
+
+    fn next(&mut self, (volume, is_buy): (f64, bool)) -> Self::Output {
+        let delta = if is_buy { volume } else { -volume };
+        self.add(delta);
+        if let Some(window) = self.window {
+            self.deltas.push_back(delta);
+            if self.deltas.len() > window {
+                if let Some(expired) = self.deltas.pop_front() {
+                    self.remove(expired);
+                }
+            }
+        }
+        self.value()
+    }
+}
+
+impl Reset for CumulativeVolumeDelta {
+    fn reset(&mut self) {
+        self.deltas.clear();
+        self.buy_volume = 0.0;
+        self.sell_volume = 0.0;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::cvd::CumulativeVolumeDelta;
+    use crate::{Next, Reset};
+
+    #[test]
+    fn test_session_cvd() {
+        let mut cvd = CumulativeVolumeDelta::session();
+        assert!(approx_eq!(f64, cvd.next((2.0, true)), 2.0));
+        assert!(approx_eq!(f64, cvd.next((0.5, false)), 1.5));
+        assert!(approx_eq!(f64, cvd.next((3.0, false)), -1.5));
+        assert!(approx_eq!(f64, cvd.next((1.0, true)), -0.5));
+        assert!(approx_eq!(f64, cvd.buy_volume, 3.0));
+        assert!(approx_eq!(f64, cvd.sell_volume, 3.5));
+        cvd.reset();
+        assert!(approx_eq!(f64, cvd.value(), 0.0));
+    }
+
+    #[test]
+    fn test_windowed_cvd() {
+        assert!(CumulativeVolumeDelta::windowed(0).is_err());
+        let mut cvd = CumulativeVolumeDelta::windowed(2).unwrap();
+        assert!(approx_eq!(f64, cvd.next((2.0, true)), 2.0));
+        assert!(approx_eq!(f64, cvd.next((0.5, false)), 1.5));
+        // The first buy of 2.0 leaves the window
+        assert!(approx_eq!(f64, cvd.next((3.0, false)), -3.5));
+        assert!(approx_eq!(f64, cvd.next((1.0, true)), -2.0));
+        assert!(approx_eq!(f64, cvd.buy_volume, 1.0));
+        assert!(approx_eq!(f64, cvd.sell_volume, 3.0));
+    }
+}
@@ -0,0 +1,222 @@
+use yata::core::{Error, IndicatorConfig, IndicatorInstance, IndicatorResult, PeriodType, ValueType, Window, OHLCV};
+
+/// Ichimoku Cloud (Ichimoku Kinko Hyo).
+/// Outputs, for each candle : Tenkan-sen, Kijun-sen, Senkou Span A, Senkou Span B and Chikou Span.
+///
+/// Senkou spans are projected `displacement` periods forward, the values output for a candle are the ones
+/// computed `displacement` candles earlier, which form the cloud at this candle.
+/// The Chikou span is the close of the candle, to be plotted `displacement` periods back.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Ichimoku {
+    /// Period of the conversion line (Tenkan-sen)
+    pub tenkan: PeriodType,
+    /// Period of the base line (Kijun-sen)
+    pub kijun: PeriodType,
+    /// Period of the second leading span (Senkou Span B)
+    pub senkou_b: PeriodType,
+    /// Number of periods the leading spans are shifted forward, and the lagging span backward
+    pub displacement: PeriodType,
+}
+
+impl Default for Ichimoku {
+    fn default() -> Self {
+        Self {
+            tenkan: 9,
+            kijun: 26,
+            senkou_b: 52,
+            displacement: 26,
+        }
+    }
+}
+
+impl IndicatorConfig for Ichimoku {
+    type Instance = IchimokuInstance;
+    const NAME: &'static str = "Ichimoku";
+
+    fn validate(&self) -> bool {
+        self.tenkan > 0 && self.tenkan < self.kijun && self.kijun < self.senkou_b && self.displacement > 0
+    }
+
+    fn set(&mut self, name: &str, value: String) -> Result<(), yata::core::Error> {
+        match name {
+            "tenkan" => match value.parse() {
+                Err(_) => return Err(Error::ParameterParse(name.to_string(), value.to_string())),
+                Ok(value) => self.tenkan = value,
+            },
+            "kijun" => match value.parse() {
+                Err(_) => return Err(Error::ParameterParse(name.to_string(), value.to_string())),
+                Ok(value) => self.kijun = value,
+            },
+            "senkou_b" => match value.parse() {
+                Err(_) => return Err(Error::ParameterParse(name.to_string(), value.to_string())),
+                Ok(value) => self.senkou_b = value,
+            },
+            "displacement" => match value.parse() {
+                Err(_) => return Err(Error::ParameterParse(name.to_string(), value.to_string())),
+                Ok(value) => self.displacement = value,
+            },
+
+            _ => {
+                return Err(Error::ParameterParse(name.to_string(), value));
+            }
+        };
+        Ok(())
+    }
+
+    fn size(&self) -> (u8, u8) { (5, 0) }
+
+    fn init<T: OHLCV>(self, initial_value: &T) -> Result<Self::Instance, Error> {
+        if !self.validate() {
+            return Err(Error::WrongConfig);
+        }
+
+        let cfg = self;
+        let mid = (initial_value.high() + initial_value.low()) / 2.0;
+
+        Ok(Self::Instance {
+            highs: Window::new(cfg.senkou_b, initial_value.high()),
+            lows: Window::new(cfg.senkou_b, initial_value.low()),
+            senkou_a: Window::new(cfg.displacement, mid),
+            senkou_b: Window::new(cfg.displacement, mid),
+            cfg,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IchimokuInstance {
+    /// Highs of the longest period, the shorter periods use the most recent values
+    highs: Window<ValueType>,
+    lows: Window<ValueType>,
+    /// Leading spans waiting to be displaced
+    senkou_a: Window<ValueType>,
+    senkou_b: Window<ValueType>,
+    cfg: Ichimoku,
+}
+
+impl IchimokuInstance {
+    /// Midpoint of the highest high and the lowest low of the last `period` candles
+    fn midpoint(&self, period: PeriodType) -> ValueType {
+        // Windows iterate from the oldest to the newest value
+        let skip = (self.cfg.senkou_b - period) as usize;
+        let highest = self
+            .highs
+            .iter()
+            .skip(skip)
+            .fold(ValueType::NEG_INFINITY, |acc, v| acc.max(*v));
+        let lowest = self
+            .lows
+            .iter()
+            .skip(skip)
+            .fold(ValueType::INFINITY, |acc, v| acc.min(*v));
+        (highest + lowest) / 2.0
+    }
+}
+
+impl IndicatorInstance for IchimokuInstance {
+    type Config = Ichimoku;
+
+    fn config(&self) -> &Self::Config { &self.cfg }
+
+    fn next<T: OHLCV>(&mut self, candle: &T) -> IndicatorResult {
+        self.highs.push(candle.high());
+        self.lows.push(candle.low());
+        let tenkan = self.midpoint(self.cfg.tenkan);
+        let kijun = self.midpoint(self.cfg.kijun);
+        let leading_a = (tenkan + kijun) / 2.0;
+        let leading_b = self.midpoint(self.cfg.senkou_b);
+        let senkou_a = self.senkou_a.push(leading_a);
+        let senkou_b = self.senkou_b.push(leading_b);
+
+        IndicatorResult::new(&[tenkan, kijun, senkou_a, senkou_b, candle.close()], &[])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use yata::core::{IndicatorConfig, IndicatorInstance};
+
+    use crate::indicators::ichimoku::Ichimoku;
+    use crate::kline::Candle;
+
+    fn candle(high: f64, low: f64, close: f64) -> Candle {
+        Candle {
+            open: close,
+            high,
+            low,
+            close,
+            ..Candle::default()
+        }
+    }
+
+    fn candles() -> Vec<Candle> {
+        vec![
+            candle(10.5, 9.5, 10.2),
+            candle(10.8, 10.0, 10.6),
+            candle(11.2, 10.4, 11.0),
+            candle(11.1, 10.3, 10.4),
+            candle(10.6, 9.8, 10.0),
+            candle(10.9, 9.9, 10.8),
+            candle(11.5, 10.7, 11.4),
+            candle(11.6, 11.0, 11.1),
+        ]
+    }
+
+    fn cfg() -> Ichimoku {
+        Ichimoku {
+            tenkan: 2,
+            kijun: 3,
+            senkou_b: 4,
+            displacement: 2,
+        }
+    }
+
+    #[test]
+    fn test_lines() {
+        let candles = candles();
+        // (tenkan, kijun, senkou a, senkou b, chikou)
+        let expected = [
+            (10.0, 10.0, 10.0, 10.0, 10.2),
+            (10.15, 10.15, 10.0, 10.0, 10.6),
+            (10.6, 10.35, 10.0, 10.0, 11.0),
+            (10.75, 10.6, 10.15, 10.15, 10.4),
+            (10.45, 10.5, 10.475, 10.35, 10.0),
+            (10.35, 10.45, 10.675, 10.35, 10.8),
+            (10.7, 10.65, 10.475, 10.5, 11.4),
+            (11.15, 10.75, 10.4, 10.5, 11.1),
+        ];
+        let mut ichimoku = cfg().init(&candles[0]).unwrap();
+        for (candle, (tenkan, kijun, senkou_a, senkou_b, chikou)) in candles.iter().zip(expected) {
+            let result = ichimoku.next(candle);
+            assert!(approx_eq!(f64, result.value(0), tenkan, epsilon = 1e-9));
+            assert!(approx_eq!(f64, result.value(1), kijun, epsilon = 1e-9));
+            assert!(approx_eq!(f64, result.value(2), senkou_a, epsilon = 1e-9));
+            assert!(approx_eq!(f64, result.value(3), senkou_b, epsilon = 1e-9));
+            assert!(approx_eq!(f64, result.value(4), chikou, epsilon = 1e-9));
+        }
+    }
+
+    #[test]
+    fn test_forward_displacement() {
+        let candles = candles();
+        let cfg = cfg();
+        let displacement = cfg.displacement as usize;
+        let mut ichimoku = cfg.init(&candles[0]).unwrap();
+        let results: Vec<_> = candles.iter().map(|c| ichimoku.next(c)).collect();
+        for (t, result) in results.iter().enumerate().skip(displacement) {
+            let origin = &results[t - displacement];
+            let leading_a = (origin.value(0) + origin.value(1)) / 2.0;
+            assert!(approx_eq!(f64, result.value(2), leading_a, epsilon = 1e-9));
+        }
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(Ichimoku::default().validate());
+        let cfg = Ichimoku {
+            tenkan: 30,
+            ..Ichimoku::default()
+        };
+        assert!(!cfg.validate());
+    }
+}
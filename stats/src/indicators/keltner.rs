@@ -0,0 +1,173 @@
+use yata::core::{Error, IndicatorConfig, IndicatorInstance, IndicatorResult, Method, MovingAverageConstructor, Source,
+                 ValueType, OHLCV};
+use yata::helpers::MA;
+
+/// Keltner Channel, a moving average midline enclosed by bands at a multiple of the average true range.
+/// Outputs the upper band, the midline and the lower band.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeltnerChannel<M: MovingAverageConstructor = MA> {
+    /// Moving average of the midline
+    pub ma: M,
+    /// Moving average of the true range
+    pub atr_ma: M,
+    /// Width of the bands, in average true ranges
+    pub multiplier: ValueType,
+    pub source: Source,
+}
+
+impl Default for KeltnerChannel {
+    fn default() -> Self {
+        Self {
+            ma: MA::EMA(20),
+            atr_ma: MA::RMA(10),
+            multiplier: 2.0,
+            source: Source::Close,
+        }
+    }
+}
+
+impl<M: MovingAverageConstructor> IndicatorConfig for KeltnerChannel<M> {
+    type Instance = KeltnerChannelInstance<M>;
+    const NAME: &'static str = "KeltnerChannel";
+
+    fn validate(&self) -> bool { self.ma.ma_period() > 0 && self.atr_ma.ma_period() > 0 && self.multiplier > 0.0 }
+
+    fn set(&mut self, name: &str, value: String) -> Result<(), yata::core::Error> {
+        match name {
+            "ma" => match value.parse() {
+                Err(_) => return Err(Error::ParameterParse(name.to_string(), value.to_string())),
+                Ok(value) => self.ma = value,
+            },
+            "atr_ma" => match value.parse() {
+                Err(_) => return Err(Error::ParameterParse(name.to_string(), value.to_string())),
+                Ok(value) => self.atr_ma = value,
+            },
+            "multiplier" => match value.parse() {
+                Err(_) => return Err(Error::ParameterParse(name.to_string(), value.to_string())),
+                Ok(value) => self.multiplier = value,
+            },
+            "source" => match value.parse() {
+                Err(_) => return Err(Error::ParameterParse(name.to_string(), value.to_string())),
+                Ok(value) => self.source = value,
+            },
+
+            _ => {
+                return Err(Error::ParameterParse(name.to_string(), value));
+            }
+        };
+        Ok(())
+    }
+
+    fn size(&self) -> (u8, u8) { (3, 0) }
+
+    fn init<T: OHLCV>(self, initial_value: &T) -> Result<Self::Instance, Error> {
+        if !self.validate() {
+            return Err(Error::WrongConfig);
+        }
+
+        let cfg = self;
+        let src = initial_value.source(cfg.source);
+        let range = initial_value.high() - initial_value.low();
+
+        Ok(Self::Instance {
+            ma: cfg.ma.init(src)?,
+            atr_ma: cfg.atr_ma.init(range)?,
+            prev_close: initial_value.close(),
+            cfg,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeltnerChannelInstance<M: MovingAverageConstructor = MA> {
+    ma: M::Instance,
+    atr_ma: M::Instance,
+    prev_close: ValueType,
+    cfg: KeltnerChannel<M>,
+}
+
+impl<M: MovingAverageConstructor> IndicatorInstance for KeltnerChannelInstance<M> {
+    type Config = KeltnerChannel<M>;
+
+    fn config(&self) -> &Self::Config { &self.cfg }
+
+    fn next<T: OHLCV>(&mut self, candle: &T) -> IndicatorResult {
+        let src = candle.source(self.cfg.source);
+        let true_range = (candle.high() - candle.low())
+            .max((candle.high() - self.prev_close).abs())
+            .max((candle.low() - self.prev_close).abs());
+        self.prev_close = candle.close();
+        let middle: f64 = self.ma.next(&src);
+        let atr: f64 = self.atr_ma.next(&true_range);
+        let width = self.cfg.multiplier * atr;
+
+        IndicatorResult::new(&[middle + width, middle, middle - width], &[])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use yata::core::{IndicatorConfig, IndicatorInstance, Source};
+    use yata::helpers::MA;
+
+    use crate::indicators::keltner::KeltnerChannel;
+    use crate::kline::Candle;
+
+    fn candle(open: f64, high: f64, low: f64, close: f64) -> Candle {
+        Candle {
+            open,
+            high,
+            low,
+            close,
+            ..Candle::default()
+        }
+    }
+
+    #[test]
+    fn test_bands() {
+        let candles = [
+            candle(10.0, 10.5, 9.5, 10.2),
+            candle(10.2, 10.8, 10.0, 10.6),
+            candle(10.6, 11.2, 10.4, 11.0),
+            candle(11.0, 11.1, 10.3, 10.4),
+            candle(10.4, 10.6, 9.8, 10.0),
+            candle(10.0, 10.9, 9.9, 10.8),
+            candle(10.8, 11.5, 10.7, 11.4),
+            candle(11.4, 11.6, 11.0, 11.1),
+        ];
+        // (upper, middle, lower) for EMA(3), RMA(3) of the true range and 2 ATRs
+        let expected = [
+            (12.200_000_000_000, 10.200_000_000_000, 8.200_000_000_000),
+            (12.266_666_666_667, 10.400_000_000_000, 8.533_333_333_333),
+            (12.477_777_777_778, 10.700_000_000_000, 8.922_222_222_222),
+            (12.268_518_518_519, 10.550_000_000_000, 8.831_481_481_481),
+            (11.954_012_345_679, 10.275_000_000_000, 8.595_987_654_321),
+            (12.323_508_230_453, 10.537_500_000_000, 8.751_491_769_547),
+            (12.692_755_486_968, 10.968_750_000_000, 9.244_744_513_032),
+            (12.583_711_991_312, 11.034_375_000_000, 9.485_038_008_688),
+        ];
+        let cfg = KeltnerChannel {
+            ma: MA::EMA(3),
+            atr_ma: MA::RMA(3),
+            multiplier: 2.0,
+            source: Source::Close,
+        };
+        let mut keltner = cfg.init(&candles[0]).unwrap();
+        for (candle, (upper, middle, lower)) in candles.iter().zip(expected) {
+            let result = keltner.next(candle);
+            assert!(approx_eq!(f64, result.value(0), upper, epsilon = 1e-9));
+            assert!(approx_eq!(f64, result.value(1), middle, epsilon = 1e-9));
+            assert!(approx_eq!(f64, result.value(2), lower, epsilon = 1e-9));
+        }
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(KeltnerChannel::default().validate());
+        let cfg = KeltnerChannel {
+            multiplier: 0.0,
+            ..KeltnerChannel::default()
+        };
+        assert!(!cfg.validate());
+    }
+}
@@ -0,0 +1,299 @@
+//! Indicators derived from order books, inputs are `(price, quantity)` levels with the best price first.
+
+use std::collections::VecDeque;
+use std::fmt;
+
+use crate::{Next, Reset};
+
+/// A `(price, quantity)` level of a book
+pub type BookLevel = (f64, f64);
+
+const BPS: f64 = 10_000.0;
+
+/// Mid price of the best bid and ask
+pub fn mid_price(bids: &[BookLevel], asks: &[BookLevel]) -> Option<f64> {
+    match (bids.first(), asks.first()) {
+        (Some(bid), Some(ask)) => Some((bid.0 + ask.0) / 2.0),
+        _ => None,
+    }
+}
+
+/// Mid price weighted by the quantities of the best levels, it leans towards the side with less quantity
+pub fn microprice(bids: &[BookLevel], asks: &[BookLevel]) -> Option<f64> {
+    match (bids.first(), asks.first()) {
+        (Some(bid), Some(ask)) if bid.1 + ask.1 > 0.0 => Some((bid.0 * ask.1 + ask.0 * bid.1) / (bid.1 + ask.1)),
+        (Some(bid), Some(ask)) => Some((bid.0 + ask.0) / 2.0),
+        _ => None,
+    }
+}
+
+/// Quantity of the levels within `bps` basis points of `mid`, each level weighted by its proximity to `mid`
+/// from 1 at `mid` to 0 at the bound
+pub fn weighted_depth(levels: &[BookLevel], mid: f64, bps: f64) -> f64 {
+    if mid <= 0.0 || bps <= 0.0 {
+        return 0.0;
+    }
+    levels
+        .iter()
+        .map(|(price, qty)| ((price - mid).abs() / mid * BPS, qty))
+        .take_while(|(distance, _)| *distance <= bps)
+        .map(|(distance, qty)| qty * (1.0 - distance / bps))
+        .sum()
+}
+
+/// Order flow imbalance of the best levels summed over the last `period` updates, positive when buying pressure
+/// dominates.
+///
+/// Inputs are `(best bid, best ask)` levels.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrderFlowImbalance {
+    period: usize,
+    last: Option<(BookLevel, BookLevel)>,
+    window: VecDeque<f64>,
+    pub current: f64,
+}
+
+impl OrderFlowImbalance {
+    pub fn new(period: usize) -> anyhow::Result<Self> {
+        match period {
+            0 => Err(anyhow!("wrong method parameter")),
+            period => Ok(Self {
+                period,
+                last: None,
+                window: VecDeque::with_capacity(period),
+                current: 0.0,
+            }),
+        }
+    }
+}
+
+impl Next<(BookLevel, BookLevel)> for OrderFlowImbalance {
+    type Output = f64;
+
+    fn next(&mut self, (bid, ask): (BookLevel, BookLevel)) -> Self::Output {
+        if let Some((last_bid, last_ask)) = self.last {
+            let mut flow = 0.0;
+            if bid.0 >= last_bid.0 {
+                flow += bid.1;
+            }
+            if bid.0 <= last_bid.0 {
+                flow -= last_bid.1;
+            }
+            if ask.0 <= last_ask.0 {
+                flow -= ask.1;
+            }
+            if ask.0 >= last_ask.0 {
+                flow += last_ask.1;
+            }
+            if self.window.len() == self.period {
+                self.window.pop_front();
+            }
+            self.window.push_back(flow);
+            self.current = self.window.iter().sum();
+        }
+        self.last = Some((bid, ask));
+        self.current
+    }
+}
+
+impl Reset for OrderFlowImbalance {
+    fn reset(&mut self) {
+        self.last = None;
+        self.window.clear();
+        self.current = 0.0;
+    }
+}
+
+impl fmt::Display for OrderFlowImbalance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result { write!(f, "OFI({})", self.period) }
+}
+
+/// Percentiles of the last `period` spreads
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpreadPercentiles {
+    period: usize,
+    window: VecDeque<f64>,
+}
+
+impl SpreadPercentiles {
+    pub fn new(period: usize) -> anyhow::Result<Self> {
+        match period {
+            0 => Err(anyhow!("wrong method parameter")),
+            period => Ok(Self {
+                period,
+                window: VecDeque::with_capacity(period),
+            }),
+        }
+    }
+
+    /// The spread under which `q` of the recorded spreads are, by nearest rank, none until a spread is recorded
+    pub fn percentile(&self, q: f64) -> Option<f64> {
+        if self.window.is_empty() {
+            return None;
+        }
+        let mut sorted: Vec<f64> = self.window.iter().copied().collect();
+        sorted.sort_by(f64::total_cmp);
+        #[allow(
+            clippy::cast_precision_loss,
+            clippy::cast_possible_truncation,
+            clippy::cast_sign_loss
+        )]
+        let rank = (q.clamp(0.0, 1.0) * sorted.len() as f64).ceil() as usize;
+        Some(sorted[rank.saturating_sub(1)])
+    }
+}
+
+impl Next<f64> for SpreadPercentiles {
+    type Output = ();
+
+    fn next(&mut self, spread: f64) -> Self::Output {
+        if self.window.len() == self.period {
+            self.window.pop_front();
+        }
+        self.window.push_back(spread);
+    }
+}
+
+impl Reset for SpreadPercentiles {
+    fn reset(&mut self) { self.window.clear(); }
+}
+
+impl fmt::Display for SpreadPercentiles {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result { write!(f, "SPREAD({})", self.period) }
+}
+
+/// Microstructure indicators of a book
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct MicrostructureValues {
+    pub mid: f64,
+    pub microprice: f64,
+    /// Spread between the best ask and bid in basis points of the mid price
+    pub spread_bps: f64,
+    pub order_flow_imbalance: f64,
+    /// Weighted depth of the bids within the depth bound
+    pub bid_depth: f64,
+    /// Weighted depth of the asks within the depth bound
+    pub ask_depth: f64,
+}
+
+impl MicrostructureValues {
+    /// Imbalance of the weighted depths, from -1 when only asks are quoted to 1 when only bids are
+    pub fn depth_imbalance(&self) -> f64 {
+        let depth = self.bid_depth + self.ask_depth;
+        if depth > 0.0 {
+            (self.bid_depth - self.ask_depth) / depth
+        } else {
+            0.0
+        }
+    }
+}
+
+/// All microstructure indicators of a book, updated with each `(bids, asks)` book.
+///
+/// Books with an empty side are ignored.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Microstructure {
+    depth_bps: f64,
+    ofi: OrderFlowImbalance,
+    spreads: SpreadPercentiles,
+    pub current: Option<MicrostructureValues>,
+}
+
+impl Microstructure {
+    /// * `depth_bps`: bound of the weighted depth, in basis points from the mid price
+    /// * `period`: number of books of the order flow imbalance and spread percentiles
+    pub fn new(depth_bps: f64, period: usize) -> anyhow::Result<Self> {
+        if depth_bps <= 0.0 {
+            return Err(anyhow!("wrong method parameter"));
+        }
+        Ok(Self {
+            depth_bps,
+            ofi: OrderFlowImbalance::new(period)?,
+            spreads: SpreadPercentiles::new(period)?,
+            current: None,
+        })
+    }
+
+    /// See [`SpreadPercentiles::percentile`]
+    pub fn spread_percentile(&self, q: f64) -> Option<f64> { self.spreads.percentile(q) }
+}
+
+impl Next<(&[BookLevel], &[BookLevel])> for Microstructure {
+    type Output = Option<MicrostructureValues>;
+
+    fn next(&mut self, (bids, asks): (&[BookLevel], &[BookLevel])) -> Self::Output {
+        let (Some(bid), Some(ask), Some(mid)) = (bids.first(), asks.first(), mid_price(bids, asks)) else {
+            return self.current;
+        };
+        let spread_bps = if mid > 0.0 { (ask.0 - bid.0) / mid * BPS } else { 0.0 };
+        self.spreads.next(spread_bps);
+        self.current = Some(MicrostructureValues {
+            mid,
+            microprice: microprice(bids, asks).unwrap_or(mid),
+            spread_bps,
+            order_flow_imbalance: self.ofi.next((*bid, *ask)),
+            bid_depth: weighted_depth(bids, mid, self.depth_bps),
+            ask_depth: weighted_depth(asks, mid, self.depth_bps),
+        });
+        self.current
+    }
+}
+
+impl Reset for Microstructure {
+    fn reset(&mut self) {
+        self.ofi.reset();
+        self.spreads.reset();
+        self.current = None;
+    }
+}
+
+impl fmt::Display for Microstructure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "MICROSTRUCTURE({}, {})", self.depth_bps, self.ofi.period)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::indicators::microstructure::{microprice, weighted_depth, Microstructure, OrderFlowImbalance};
+    use crate::{Next, Reset};
+
+    #[test]
+    fn test_book_values() {
+        let bids = [(99.0, 3.0), (98.0, 1.0), (90.0, 10.0)];
+        let asks = [(101.0, 1.0), (102.0, 2.0)];
+        assert!(approx_eq!(f64, microprice(&bids, &asks).unwrap(), 100.5));
+        // 99 is 100 bps from the mid, 98 is 200 bps, 90 is out of bounds
+        assert!(approx_eq!(f64, weighted_depth(&bids, 100.0, 400.0), 3.0 * 0.75 + 0.5));
+        assert!(microprice(&bids, &[]).is_none());
+    }
+
+    #[test]
+    fn test_order_flow_imbalance() {
+        let mut ofi = OrderFlowImbalance::new(2).unwrap();
+        assert!(approx_eq!(f64, ofi.next(((99.0, 1.0), (101.0, 1.0))), 0.0));
+        // The bid grows
+        assert!(approx_eq!(f64, ofi.next(((99.0, 3.0), (101.0, 1.0))), 2.0));
+        // The bid improves
+        assert!(approx_eq!(f64, ofi.next(((100.0, 1.0), (101.0, 1.0))), 3.0));
+        // The ask improves, the first update leaves the window
+        assert!(approx_eq!(f64, ofi.next(((100.0, 1.0), (100.5, 4.0))), -3.0));
+        ofi.reset();
+        assert!(approx_eq!(f64, ofi.next(((99.0, 1.0), (101.0, 1.0))), 0.0));
+    }
+
+    #[test]
+    fn test_microstructure() {
+        let mut micro = Microstructure::new(50.0, 10).unwrap();
+        assert!(micro.next((&[][..], &[(101.0, 1.0)][..])).is_none());
+        for spread in [2.0, 4.0, 1.0, 3.0] {
+            let (bids, asks) = ([(100.0 - spread / 2.0, 1.0)], [(100.0 + spread / 2.0, 1.0)]);
+            micro.next((&bids[..], &asks[..]));
+        }
+        let values = micro.current.unwrap();
+        assert!(approx_eq!(f64, values.spread_bps, 300.0));
+        assert!(approx_eq!(f64, micro.spread_percentile(0.5).unwrap(), 200.0));
+        assert!(approx_eq!(f64, micro.spread_percentile(1.0).unwrap(), 400.0));
+        assert!(approx_eq!(f64, values.depth_imbalance(), 0.0));
+    }
+}
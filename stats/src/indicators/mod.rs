@@ -4,10 +4,17 @@ use yata::indicators::{StochasticOscillator, MACD, RSI};
 
 pub mod cross;
 pub mod ema;
+pub mod ichimoku;
+pub mod keltner;
+pub mod microstructure;
 pub mod momentum;
+pub mod obv;
 pub mod ppo;
 pub mod ppo_yata;
+pub mod rolling;
 pub mod thresholds;
+pub mod volatility;
+pub mod vwap;
 
 pub fn stoch(period: u32, smooth_k: u32, signal: u32, zone_low: f64) -> StochasticOscillator {
     StochasticOscillator {
@@ -0,0 +1,88 @@
+use std::fmt;
+
+use crate::{Close, Next, Reset, Volume};
+
+/// On-Balance Volume, the running sum of volumes signed by the direction of the close.
+///
+/// Inputs are `(close, volume)` tuples, or anything that has a close and a volume.
+/// The volume of the first input is not counted since there is no previous close to compare to.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct OnBalanceVolume {
+    prev_close: Option<f64>,
+    pub current: f64,
+}
+
+impl OnBalanceVolume {
+    pub fn new() -> Self { Self::default() }
+}
+
+impl Next<(f64, f64)> for OnBalanceVolume {
+    type Output = f64;
+
+    fn next(&mut self, (close, volume): (f64, f64)) -> Self::Output {
+        if let Some(prev_close) = self.prev_close {
+            if close > prev_close {
+                self.current += volume;
+            } else if close < prev_close {
+                self.current -= volume;
+            }
+        }
+        self.prev_close = Some(close);
+        self.current
+    }
+}
+
+impl<'a, T: Close + Volume> Next<&'a T> for OnBalanceVolume {
+    type Output = f64;
+
+    fn next(&mut self, input: &'a T) -> Self::Output { self.next((input.close(), input.volume())) }
+}
+
+impl Reset for OnBalanceVolume {
+    fn reset(&mut self) {
+        self.prev_close = None;
+        self.current = 0.0;
+    }
+}
+
+impl fmt::Display for OnBalanceVolume {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result { write!(f, "OBV") }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::indicators::obv::OnBalanceVolume;
+    use crate::{Next, Reset};
+
+    #[test]
+    fn test_next() {
+        let mut obv = OnBalanceVolume::new();
+        // (close, volume, expected obv)
+        let series = [
+            (10.0, 25.0, 0.0),
+            (10.5, 30.0, 30.0),
+            (10.2, 10.0, 20.0),
+            (10.2, 50.0, 20.0),
+            (10.8, 40.0, 60.0),
+            (10.1, 70.0, -10.0),
+        ];
+        for (close, volume, expected) in series {
+            assert!(approx_eq!(f64, obv.next((close, volume)), expected));
+        }
+    }
+
+    #[test]
+    fn test_reset() {
+        let mut obv = OnBalanceVolume::new();
+        obv.next((10.0, 25.0));
+        obv.next((10.5, 30.0));
+        obv.reset();
+        assert!(approx_eq!(f64, obv.next((9.0, 10.0)), 0.0));
+        assert!(approx_eq!(f64, obv.next((8.0, 10.0)), -10.0));
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(format!("{}", OnBalanceVolume::new()), "OBV");
+    }
+}
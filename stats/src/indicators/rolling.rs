@@ -0,0 +1,144 @@
+use std::collections::VecDeque;
+use std::fmt;
+
+use crate::correlation::pearson;
+use crate::{Next, Reset};
+
+/// Z-score of the last value relative to the mean and standard deviation of the last `period` values.
+///
+/// The z-score is NaN until the window holds two distinct values.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RollingZScore {
+    period: usize,
+    window: VecDeque<f64>,
+    pub current: f64,
+}
+
+impl RollingZScore {
+    pub fn new(period: usize) -> anyhow::Result<Self> {
+        match period {
+            0 | 1 => Err(anyhow!("the period of a z-score is at least 2")),
+            period => Ok(Self {
+                period,
+                window: VecDeque::with_capacity(period),
+                current: f64::NAN,
+            }),
+        }
+    }
+}
+
+impl Next<f64> for RollingZScore {
+    type Output = f64;
+
+    fn next(&mut self, value: f64) -> Self::Output {
+        if self.window.len() == self.period {
+            self.window.pop_front();
+        }
+        self.window.push_back(value);
+        #[allow(clippy::cast_precision_loss)]
+        let n = self.window.len() as f64;
+        let mean = self.window.iter().sum::<f64>() / n;
+        let std_dev = (self.window.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / n).sqrt();
+        self.current = if std_dev > 0.0 {
+            (value - mean) / std_dev
+        } else {
+            f64::NAN
+        };
+        self.current
+    }
+}
+
+impl Reset for RollingZScore {
+    fn reset(&mut self) {
+        self.window.clear();
+        self.current = f64::NAN;
+    }
+}
+
+impl fmt::Display for RollingZScore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result { write!(f, "ZSCORE({})", self.period) }
+}
+
+/// Pearson correlation of two series over their last `period` values.
+///
+/// The correlation is NaN until both series vary over the window.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RollingCorrelation {
+    period: usize,
+    a: VecDeque<f64>,
+    b: VecDeque<f64>,
+    pub current: f64,
+}
+
+impl RollingCorrelation {
+    pub fn new(period: usize) -> anyhow::Result<Self> {
+        match period {
+            0 | 1 => Err(anyhow!("the period of a correlation is at least 2")),
+            period => Ok(Self {
+                period,
+                a: VecDeque::with_capacity(period),
+                b: VecDeque::with_capacity(period),
+                current: f64::NAN,
+            }),
+        }
+    }
+}
+
+impl Next<(f64, f64)> for RollingCorrelation {
+    type Output = f64;
+
+    fn next(&mut self, (a, b): (f64, f64)) -> Self::Output {
+        if self.a.len() == self.period {
+            self.a.pop_front();
+            self.b.pop_front();
+        }
+        self.a.push_back(a);
+        self.b.push_back(b);
+        self.current = pearson(self.a.make_contiguous(), self.b.make_contiguous());
+        self.current
+    }
+}
+
+impl Reset for RollingCorrelation {
+    fn reset(&mut self) {
+        self.a.clear();
+        self.b.clear();
+        self.current = f64::NAN;
+    }
+}
+
+impl fmt::Display for RollingCorrelation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result { write!(f, "CORR({})", self.period) }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::indicators::rolling::{RollingCorrelation, RollingZScore};
+    use crate::{Next, Reset};
+
+    #[test]
+    fn test_zscore() {
+        assert!(RollingZScore::new(1).is_err());
+        let mut zscore = RollingZScore::new(3).unwrap();
+        assert!(zscore.next(1.0).is_nan());
+        assert!(approx_eq!(f64, zscore.next(3.0), 1.0));
+        // Window of 3, 2, 4 after the first value leaves it
+        zscore.next(2.0);
+        assert!(approx_eq!(f64, zscore.next(4.0), 1.224_744_871_391_589));
+        zscore.reset();
+        assert!(zscore.next(4.0).is_nan());
+        assert_eq!(format!("{}", zscore), "ZSCORE(3)");
+    }
+
+    #[test]
+    fn test_correlation() {
+        let mut corr = RollingCorrelation::new(3).unwrap();
+        assert!(corr.next((1.0, 2.0)).is_nan());
+        assert!(approx_eq!(f64, corr.next((2.0, 4.0)), 1.0, epsilon = 1e-9));
+        assert!(approx_eq!(f64, corr.next((3.0, 6.0)), 1.0, epsilon = 1e-9));
+        // Window of (2, 4), (3, 6), (4, 0)
+        assert!(approx_eq!(f64, corr.next((4.0, 0.0)), -0.654_653_670_7, epsilon = 1e-9));
+        corr.reset();
+        assert!(corr.next((1.0, 1.0)).is_nan());
+    }
+}
@@ -0,0 +1,234 @@
+use std::fmt;
+
+use crate::{Next, Reset};
+
+/// Default decay of the EWMA variance, from RiskMetrics for daily returns
+const DEFAULT_EWMA_LAMBDA: f64 = 0.94;
+
+fn default_ewma_lambda() -> f64 { DEFAULT_EWMA_LAMBDA }
+
+/// Exponentially weighted moving average of squared returns, updated with each return.
+///
+/// The output is the forecast volatility of the next return, NaN until the first return.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct EwmaVolatility {
+    lambda: f64,
+    variance: f64,
+}
+
+impl EwmaVolatility {
+    /// * `lambda`: weight of the previous variance, in (0, 1)
+    pub fn new(lambda: f64) -> anyhow::Result<Self> {
+        if lambda <= 0.0 || lambda >= 1.0 {
+            return Err(anyhow!("the decay of an ewma volatility is in (0, 1)"));
+        }
+        Ok(Self {
+            lambda,
+            variance: f64::NAN,
+        })
+    }
+
+    /// Variance of the next return
+    pub fn variance(&self) -> f64 { self.variance }
+
+    /// Volatility of the sum of the next `horizon` returns
+    #[allow(clippy::cast_precision_loss)]
+    pub fn forecast(&self, horizon: usize) -> f64 { (self.variance * horizon as f64).sqrt() }
+}
+
+impl Next<f64> for EwmaVolatility {
+    type Output = f64;
+
+    fn next(&mut self, ret: f64) -> Self::Output {
+        self.variance = if self.variance.is_nan() {
+            ret.powi(2)
+        } else {
+            self.lambda * self.variance + (1.0 - self.lambda) * ret.powi(2)
+        };
+        self.variance.sqrt()
+    }
+}
+
+impl Reset for EwmaVolatility {
+    fn reset(&mut self) { self.variance = f64::NAN; }
+}
+
+impl fmt::Display for EwmaVolatility {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result { write!(f, "EWMAVOL({})", self.lambda) }
+}
+
+/// GARCH(1,1) conditional variance, `variance = omega + alpha * ret^2 + beta * variance`, updated with each return.
+///
+/// The variance starts at the long run variance, the output is the forecast volatility of the next return.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Garch11 {
+    omega: f64,
+    alpha: f64,
+    beta: f64,
+    variance: f64,
+}
+
+impl Garch11 {
+    /// Parameters must be positive with `alpha + beta < 1` for the variance to revert to its long run value
+    pub fn new(omega: f64, alpha: f64, beta: f64) -> anyhow::Result<Self> {
+        if omega <= 0.0 || alpha < 0.0 || beta < 0.0 || alpha + beta >= 1.0 {
+            return Err(anyhow!(
+                "garch parameters must satisfy omega > 0, alpha >= 0, beta >= 0 and alpha + beta < 1"
+            ));
+        }
+        let mut garch = Self {
+            omega,
+            alpha,
+            beta,
+            variance: 0.0,
+        };
+        garch.variance = garch.long_run_variance();
+        Ok(garch)
+    }
+
+    /// Variance the forecasts revert to
+    pub fn long_run_variance(&self) -> f64 { self.omega / (1.0 - self.alpha - self.beta) }
+
+    /// Variance of the next return
+    pub fn variance(&self) -> f64 { self.variance }
+
+    /// Volatility of the sum of the next `horizon` returns, the variance of each return reverts to the long run
+    /// variance at the rate `alpha + beta`
+    pub fn forecast(&self, horizon: usize) -> f64 {
+        let long_run = self.long_run_variance();
+        let persistence = self.alpha + self.beta;
+        let mut decay = 1.0;
+        let mut variance = 0.0;
+        for _ in 0..horizon {
+            variance += long_run + decay * (self.variance - long_run);
+            decay *= persistence;
+        }
+        variance.sqrt()
+    }
+}
+
+impl Next<f64> for Garch11 {
+    type Output = f64;
+
+    fn next(&mut self, ret: f64) -> Self::Output {
+        self.variance = self.omega + self.alpha * ret.powi(2) + self.beta * self.variance;
+        self.variance.sqrt()
+    }
+}
+
+impl Reset for Garch11 {
+    fn reset(&mut self) { self.variance = self.long_run_variance(); }
+}
+
+impl fmt::Display for Garch11 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "GARCH({}, {}, {})", self.omega, self.alpha, self.beta)
+    }
+}
+
+/// Configuration of a volatility estimator
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum VolatilityModel {
+    Ewma {
+        #[serde(default = "default_ewma_lambda")]
+        lambda: f64,
+    },
+    Garch {
+        omega: f64,
+        alpha: f64,
+        beta: f64,
+    },
+}
+
+impl VolatilityModel {
+    pub fn estimator(&self) -> anyhow::Result<VolatilityEstimator> {
+        Ok(match *self {
+            VolatilityModel::Ewma { lambda } => VolatilityEstimator::Ewma(EwmaVolatility::new(lambda)?),
+            VolatilityModel::Garch { omega, alpha, beta } => {
+                VolatilityEstimator::Garch(Garch11::new(omega, alpha, beta)?)
+            }
+        })
+    }
+}
+
+/// A volatility estimator updated with returns
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum VolatilityEstimator {
+    Ewma(EwmaVolatility),
+    Garch(Garch11),
+}
+
+impl VolatilityEstimator {
+    /// Volatility of the sum of the next `horizon` returns
+    pub fn forecast(&self, horizon: usize) -> f64 {
+        match self {
+            VolatilityEstimator::Ewma(e) => e.forecast(horizon),
+            VolatilityEstimator::Garch(g) => g.forecast(horizon),
+        }
+    }
+}
+
+impl Next<f64> for VolatilityEstimator {
+    type Output = f64;
+
+    fn next(&mut self, ret: f64) -> Self::Output {
+        match self {
+            VolatilityEstimator::Ewma(e) => e.next(ret),
+            VolatilityEstimator::Garch(g) => g.next(ret),
+        }
+    }
+}
+
+impl Reset for VolatilityEstimator {
+    fn reset(&mut self) {
+        match self {
+            VolatilityEstimator::Ewma(e) => e.reset(),
+            VolatilityEstimator::Garch(g) => g.reset(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Next, Reset};
+
+    use super::{EwmaVolatility, Garch11, VolatilityModel};
+
+    #[test]
+    fn ewma_volatility() {
+        assert!(EwmaVolatility::new(1.0).is_err());
+        let mut ewma = EwmaVolatility::new(0.9).unwrap();
+        assert!(ewma.forecast(1).is_nan());
+        assert!(approx_eq!(f64, ewma.next(0.01), 0.01, epsilon = 1e-12));
+        assert!(approx_eq!(f64, ewma.next(0.02), 1.3e-4_f64.sqrt(), epsilon = 1e-12));
+        assert!(approx_eq!(f64, ewma.forecast(4), 5.2e-4_f64.sqrt(), epsilon = 1e-12));
+        ewma.reset();
+        assert!(ewma.variance().is_nan());
+    }
+
+    #[test]
+    fn garch_volatility_reverts_to_long_run() {
+        assert!(Garch11::new(1e-6, 0.5, 0.5).is_err());
+        let mut garch = Garch11::new(1e-6, 0.1, 0.85).unwrap();
+        assert!(approx_eq!(f64, garch.long_run_variance(), 2e-5, epsilon = 1e-15));
+        assert!(approx_eq!(f64, garch.next(0.02), 5.8e-5_f64.sqrt(), epsilon = 1e-12));
+        assert!(approx_eq!(f64, garch.forecast(2), 1.141e-4_f64.sqrt(), epsilon = 1e-12));
+        // Far forecasts grow like the long run variance
+        let far = garch.forecast(10_000).powi(2) / 10_000.0;
+        assert!((far - garch.long_run_variance()).abs() < 1e-8);
+        let model = VolatilityModel::Garch {
+            omega: 1e-6,
+            alpha: 0.1,
+            beta: 0.85,
+        };
+        let mut estimator = model.estimator().unwrap();
+        estimator.next(0.02);
+        assert!(approx_eq!(
+            f64,
+            estimator.forecast(2),
+            garch.forecast(2),
+            epsilon = 1e-12
+        ));
+    }
+}
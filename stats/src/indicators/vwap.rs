@@ -0,0 +1,255 @@
+use std::fmt;
+
+use chrono::{DateTime, Duration, DurationRound, Utc};
+
+use crate::{Next, Reset};
+
+/// Sessions start at fixed intervals from the epoch, daily at midnight UTC by default
+const DAILY_SESSION_SECS: i64 = 24 * 60 * 60;
+
+fn session_start(session_secs: i64, at: DateTime<Utc>) -> DateTime<Utc> {
+    at.duration_trunc(Duration::seconds(session_secs)).unwrap_or(at)
+}
+
+/// Volume weighted average price of the current session.
+///
+/// Inputs are `(price, volume, time)` tuples, inputs of a previous session are ignored.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionVwap {
+    session_secs: i64,
+    session_start: Option<DateTime<Utc>>,
+    price_volume: f64,
+    volume: f64,
+    pub current: f64,
+}
+
+impl SessionVwap {
+    /// # Panics
+    ///
+    /// if the session is shorter than a second
+    pub fn new(session: Duration) -> Self {
+        assert!(session.num_seconds() > 0, "sessions last at least a second");
+        Self {
+            session_secs: session.num_seconds(),
+            session_start: None,
+            price_volume: 0.0,
+            volume: 0.0,
+            current: f64::NAN,
+        }
+    }
+
+    pub fn daily() -> Self { Self::new(Duration::seconds(DAILY_SESSION_SECS)) }
+}
+
+impl Next<(f64, f64, DateTime<Utc>)> for SessionVwap {
+    type Output = f64;
+
+    fn next(&mut self, (price, volume, at): (f64, f64, DateTime<Utc>)) -> Self::Output {
+        let start = session_start(self.session_secs, at);
+        match self.session_start {
+            Some(current) if start < current => return self.current,
+            Some(current) if start == current => {}
+            _ => {
+                self.reset();
+                self.session_start = Some(start);
+            }
+        }
+        self.price_volume += price * volume;
+        self.volume += volume;
+        self.current = if self.volume > 0.0 {
+            self.price_volume / self.volume
+        } else {
+            price
+        };
+        self.current
+    }
+}
+
+impl Reset for SessionVwap {
+    fn reset(&mut self) {
+        self.session_start = None;
+        self.price_volume = 0.0;
+        self.volume = 0.0;
+        self.current = f64::NAN;
+    }
+}
+
+impl fmt::Display for SessionVwap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result { write!(f, "VWAP({}s)", self.session_secs) }
+}
+
+/// Volume weighted average price since an anchor time, such as a listing or an earnings release.
+///
+/// Inputs are `(price, volume, time)` tuples, inputs before the anchor are ignored.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnchoredVwap {
+    pub anchor: DateTime<Utc>,
+    price_volume: f64,
+    volume: f64,
+    pub current: f64,
+}
+
+impl AnchoredVwap {
+    pub fn new(anchor: DateTime<Utc>) -> Self {
+        Self {
+            anchor,
+            price_volume: 0.0,
+            volume: 0.0,
+            current: f64::NAN,
+        }
+    }
+
+    /// Restart the average from a new anchor
+    pub fn anchor_at(&mut self, anchor: DateTime<Utc>) {
+        self.reset();
+        self.anchor = anchor;
+    }
+}
+
+impl Next<(f64, f64, DateTime<Utc>)> for AnchoredVwap {
+    type Output = f64;
+
+    fn next(&mut self, (price, volume, at): (f64, f64, DateTime<Utc>)) -> Self::Output {
+        if at < self.anchor {
+            return self.current;
+        }
+        self.price_volume += price * volume;
+        self.volume += volume;
+        self.current = if self.volume > 0.0 {
+            self.price_volume / self.volume
+        } else {
+            price
+        };
+        self.current
+    }
+}
+
+impl Reset for AnchoredVwap {
+    fn reset(&mut self) {
+        self.price_volume = 0.0;
+        self.volume = 0.0;
+        self.current = f64::NAN;
+    }
+}
+
+impl fmt::Display for AnchoredVwap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result { write!(f, "AVWAP({})", self.anchor) }
+}
+
+/// Time weighted average price of the current session, each price is weighted by the time until the next one.
+///
+/// Inputs are `(price, time)` tuples, inputs of a previous session or older than the last one are ignored.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionTwap {
+    session_secs: i64,
+    session_start: Option<DateTime<Utc>>,
+    last: Option<(f64, DateTime<Utc>)>,
+    price_time: f64,
+    time: f64,
+    pub current: f64,
+}
+
+impl SessionTwap {
+    /// # Panics
+    ///
+    /// if the session is shorter than a second
+    pub fn new(session: Duration) -> Self {
+        assert!(session.num_seconds() > 0, "sessions last at least a second");
+        Self {
+            session_secs: session.num_seconds(),
+            session_start: None,
+            last: None,
+            price_time: 0.0,
+            time: 0.0,
+            current: f64::NAN,
+        }
+    }
+
+    pub fn daily() -> Self { Self::new(Duration::seconds(DAILY_SESSION_SECS)) }
+}
+
+impl Next<(f64, DateTime<Utc>)> for SessionTwap {
+    type Output = f64;
+
+    fn next(&mut self, (price, at): (f64, DateTime<Utc>)) -> Self::Output {
+        let start = session_start(self.session_secs, at);
+        match (self.session_start, self.last) {
+            (Some(current), Some((_, last_at))) if start < current || at < last_at => return self.current,
+            (Some(current), Some((last_price, last_at))) if start == current => {
+                #[allow(clippy::cast_precision_loss)]
+                let elapsed = (at - last_at).num_milliseconds() as f64;
+                self.price_time += last_price * elapsed;
+                self.time += elapsed;
+            }
+            _ => {
+                self.reset();
+                self.session_start = Some(start);
+            }
+        }
+        self.last = Some((price, at));
+        self.current = if self.time > 0.0 {
+            self.price_time / self.time
+        } else {
+            price
+        };
+        self.current
+    }
+}
+
+impl Reset for SessionTwap {
+    fn reset(&mut self) {
+        self.session_start = None;
+        self.last = None;
+        self.price_time = 0.0;
+        self.time = 0.0;
+        self.current = f64::NAN;
+    }
+}
+
+impl fmt::Display for SessionTwap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result { write!(f, "TWAP({}s)", self.session_secs) }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{Duration, TimeZone, Utc};
+
+    use crate::indicators::vwap::{AnchoredVwap, SessionTwap, SessionVwap};
+    use crate::{Next, Reset};
+
+    #[test]
+    fn test_session_vwap() {
+        let t0 = Utc.with_ymd_and_hms(2022, 1, 1, 22, 0, 0).unwrap();
+        let mut vwap = SessionVwap::daily();
+        assert!(approx_eq!(f64, vwap.next((10.0, 1.0, t0)), 10.0));
+        assert!(approx_eq!(f64, vwap.next((13.0, 2.0, t0 + Duration::hours(1))), 12.0));
+        // A new session starts at midnight
+        assert!(approx_eq!(f64, vwap.next((20.0, 1.0, t0 + Duration::hours(2))), 20.0));
+        // Late inputs of the previous session are ignored
+        assert!(approx_eq!(f64, vwap.next((1.0, 1.0, t0)), 20.0));
+        vwap.reset();
+        assert!(vwap.current.is_nan());
+    }
+
+    #[test]
+    fn test_anchored_vwap() {
+        let t0 = Utc.with_ymd_and_hms(2022, 1, 1, 0, 0, 0).unwrap();
+        let mut vwap = AnchoredVwap::new(t0 + Duration::hours(1));
+        assert!(vwap.next((10.0, 1.0, t0)).is_nan());
+        assert!(approx_eq!(f64, vwap.next((10.0, 1.0, t0 + Duration::hours(1))), 10.0));
+        assert!(approx_eq!(f64, vwap.next((20.0, 3.0, t0 + Duration::hours(30))), 17.5));
+        vwap.anchor_at(t0 + Duration::hours(40));
+        assert!(approx_eq!(f64, vwap.next((5.0, 1.0, t0 + Duration::hours(41))), 5.0));
+    }
+
+    #[test]
+    fn test_session_twap() {
+        let t0 = Utc.with_ymd_and_hms(2022, 1, 1, 0, 0, 0).unwrap();
+        let mut twap = SessionTwap::daily();
+        assert!(approx_eq!(f64, twap.next((10.0, t0)), 10.0));
+        // 10 held for 3 minutes then 20 for 1 minute
+        assert!(approx_eq!(f64, twap.next((20.0, t0 + Duration::minutes(3))), 10.0));
+        assert!(approx_eq!(f64, twap.next((30.0, t0 + Duration::minutes(4))), 12.5));
+        assert!(approx_eq!(f64, twap.next((30.0, t0 + Duration::days(1))), 30.0));
+    }
+}
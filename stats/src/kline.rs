@@ -3,6 +3,7 @@
 use chrono::{DateTime, Datelike, Duration, DurationRound, TimeZone, Utc};
 use ringbuffer::{AllocRingBuffer, RingBuffer, RingBufferExt, RingBufferWrite};
 use smallvec::SmallVec;
+use std::hash::{Hash, Hasher};
 use std::ops::{Add, Mul};
 use ta::Next;
 use yata::core::ValueType;
@@ -299,6 +300,217 @@ impl Next<(f64, f64, DateTime<Utc>)> for Kline {
     }
 }
 
+/// Builds candles of a resolution from candles of a lower resolution, such as 4 hour candles from 1 minute candles
+///
+/// Base candles can be updated until they are final, the last update of a base candle replaces the previous ones.
+#[derive(Debug, Clone)]
+pub struct CandleAggregator {
+    resolution: Resolution,
+    /// Sum of the base candles of the current candle that are superseded
+    closed: Option<Candle>,
+    /// The last update of the base candle in progress
+    pending: Option<Candle>,
+}
+
+impl CandleAggregator {
+    pub fn new(resolution: Resolution) -> Self {
+        Self {
+            resolution,
+            closed: None,
+            pending: None,
+        }
+    }
+
+    pub fn resolution(&self) -> Resolution { self.resolution }
+
+    /// The current candle, with the times of the aggregated resolution
+    fn current(&self) -> Option<Candle> {
+        let candle = match (self.closed, self.pending) {
+            (Some(closed), Some(pending)) => closed + pending,
+            (closed, pending) => closed.or(pending)?,
+        };
+        let start_time = self.resolution.truncate(candle.start_time);
+        Some(Candle {
+            start_time,
+            end_time: self.resolution.add(start_time),
+            is_final: false,
+            ..candle
+        })
+    }
+}
+
+impl Next<Candle> for CandleAggregator {
+    /// The previous candle if the base candle started a new one, then the current candle
+    type Output = SmallVec<[Candle; 2]>;
+
+    fn next(&mut self, input: Candle) -> Self::Output {
+        let mut output = SmallVec::new();
+        let start_time = self.resolution.truncate(input.start_time);
+        if let Some(current) = self.current() {
+            if current.start_time < start_time {
+                output.push(Candle {
+                    is_final: true,
+                    ..current
+                });
+                self.closed = None;
+                self.pending = None;
+            } else if current.start_time > start_time {
+                // Late base candles of a finished candle are dropped
+                return output;
+            }
+        }
+        match self.pending.take() {
+            Some(pending) if pending.start_time < input.start_time => {
+                self.closed = Some(self.closed.map_or(pending, |closed| closed + pending));
+            }
+            _ => {}
+        }
+        self.pending = Some(input);
+        if let Some(current) = self.current() {
+            if input.is_final && input.end_time >= current.end_time {
+                output.push(Candle {
+                    is_final: true,
+                    ..current
+                });
+                self.closed = None;
+                self.pending = None;
+            } else {
+                output.push(current);
+            }
+        }
+        output
+    }
+}
+
+/// Bars built from candles instead of time candles, see [`BarBuilder`]
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BarType {
+    /// Candles smoothed with the previous candle
+    HeikinAshi,
+    /// Bricks of a fixed price move, regardless of time
+    Renko { brick_size: f64 },
+    /// Bars of a fixed traded volume in base asset
+    Volume { volume: f64 },
+    /// Bars of a fixed traded volume in quote asset
+    Dollar { value: f64 },
+}
+
+impl Eq for BarType {}
+
+impl Hash for BarType {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match self {
+            BarType::HeikinAshi => {}
+            BarType::Renko { brick_size: size }
+            | BarType::Volume { volume: size }
+            | BarType::Dollar { value: size } => {
+                size.to_bits().hash(state);
+            }
+        }
+    }
+}
+
+/// Builds bars of a [`BarType`] from candle ticks
+///
+/// Heikin-Ashi candles follow every tick, other bars are only built from final candles and are always final.
+#[derive(Debug, Clone)]
+pub struct BarBuilder {
+    bar_type: BarType,
+    /// The last final Heikin-Ashi candle, or the bar in progress
+    current: Option<Candle>,
+    /// Close of the last Renko brick
+    anchor: Option<f64>,
+}
+
+impl BarBuilder {
+    pub fn new(bar_type: BarType) -> Self {
+        Self {
+            bar_type,
+            current: None,
+            anchor: None,
+        }
+    }
+
+    pub fn bar_type(&self) -> BarType { self.bar_type }
+
+    fn heikin_ashi(&mut self, candle: Candle) -> Candle {
+        let close = (candle.open + candle.high + candle.low + candle.close) / 4.0;
+        let open = self.current.map_or((candle.open + candle.close) / 2.0, |previous| {
+            (previous.open + previous.close) / 2.0
+        });
+        let bar = Candle {
+            open,
+            high: candle.high.max(open).max(close),
+            low: candle.low.min(open).min(close),
+            close,
+            ..candle
+        };
+        if candle.is_final {
+            self.current = Some(bar);
+        }
+        bar
+    }
+
+    fn renko(&mut self, candle: Candle, brick_size: f64) -> SmallVec<[Candle; 2]> {
+        let mut bricks = SmallVec::new();
+        let Some(mut anchor) = self.anchor else {
+            self.anchor = Some(candle.close);
+            return bricks;
+        };
+        // Volume traded since the last brick is attributed to the next brick
+        let mut pending = self.current.map_or(candle, |current| current + candle);
+        while brick_size > 0.0 && (candle.close - anchor).abs() >= brick_size {
+            let close = anchor + brick_size.copysign(candle.close - anchor);
+            bricks.push(Candle {
+                open: anchor,
+                high: anchor.max(close),
+                low: anchor.min(close),
+                close,
+                is_final: true,
+                ..pending
+            });
+            pending = Candle {
+                volume: 0.0,
+                quote_volume: 0.0,
+                trade_count: 0,
+                start_time: candle.end_time,
+                ..candle
+            };
+            anchor = close;
+        }
+        self.anchor = Some(anchor);
+        self.current = if bricks.is_empty() { Some(pending) } else { None };
+        bricks
+    }
+
+    fn threshold(&mut self, candle: Candle, reached: impl Fn(&Candle) -> bool) -> SmallVec<[Candle; 2]> {
+        let bar = self.current.map_or(candle, |current| current + candle);
+        if reached(&bar) {
+            self.current = None;
+            SmallVec::from_slice(&[Candle { is_final: true, ..bar }])
+        } else {
+            self.current = Some(bar);
+            SmallVec::new()
+        }
+    }
+}
+
+impl Next<Candle> for BarBuilder {
+    type Output = SmallVec<[Candle; 2]>;
+
+    fn next(&mut self, input: Candle) -> Self::Output {
+        match self.bar_type {
+            BarType::HeikinAshi => SmallVec::from_slice(&[self.heikin_ashi(input)]),
+            _ if !input.is_final => SmallVec::new(),
+            BarType::Renko { brick_size } => self.renko(input, brick_size),
+            BarType::Volume { volume } => self.threshold(input, |bar| bar.volume >= volume),
+            BarType::Dollar { value } => self.threshold(input, |bar| bar.quote_volume >= value),
+        }
+    }
+}
+
 pub struct KlineIterator<'a>(&'a dyn Iterator<Item = Candle>);
 
 impl<'a> IntoIterator for &'a Kline {
@@ -310,8 +522,9 @@ impl<'a> IntoIterator for &'a Kline {
 
 #[cfg(test)]
 mod test {
+    use crate::kline::TimeUnit::Minute;
     use crate::kline::TimeUnit::Second;
-    use crate::kline::{Candle, Kline, Resolution};
+    use crate::kline::{BarBuilder, BarType, Candle, CandleAggregator, Kline, Resolution};
     use chrono::{Duration, Utc};
     use pretty_assertions::assert_eq;
     use std::ops::Add;
@@ -366,4 +579,94 @@ mod test {
         assert_eq!(kline_candles, expected);
         //assert_eq!(kline.interval(), SampleInterval { time_unit: }));
     }
+
+    #[test]
+    fn test_aggregate_higher_resolution() {
+        let base = Resolution::new(Minute, 1);
+        let mut aggregator = CandleAggregator::new(Resolution::new(Minute, 5));
+        let t0 = Resolution::new(Minute, 5).truncate(Utc::now());
+        let candle = |minute: i64, price: f64, is_final: bool| {
+            let start_time = t0.add(Duration::minutes(minute));
+            Candle {
+                event_time: start_time,
+                is_final,
+                ..Candle::new(price, 1.0, start_time, start_time, base.add(start_time))
+            }
+        };
+        // Updates of a base candle replace each other
+        aggregator.next(candle(0, 10.0, false));
+        let first = aggregator.next(candle(0, 12.0, true));
+        assert_eq!(first.len(), 1);
+        assert_eq!((first[0].open, first[0].volume, first[0].is_final), (12.0, 1.0, false));
+        for minute in 1..4 {
+            aggregator.next(candle(minute, 10.0 + minute as f64, true));
+        }
+        let last = aggregator.next(candle(4, 8.0, true));
+        assert_eq!(last.len(), 1);
+        let aggregated = last[0];
+        assert!(aggregated.is_final);
+        assert_eq!(
+            (aggregated.start_time, aggregated.end_time),
+            (t0, t0.add(Duration::minutes(5)))
+        );
+        assert_eq!(
+            (aggregated.open, aggregated.high, aggregated.low, aggregated.close),
+            (12.0, 13.0, 8.0, 8.0)
+        );
+        assert_eq!((aggregated.volume, aggregated.trade_count), (5.0, 5));
+        // The next candle starts from the next base candle
+        let next = aggregator.next(candle(5, 9.0, false));
+        assert_eq!(
+            (next.len(), next[0].open, next[0].start_time),
+            (1, 9.0, t0.add(Duration::minutes(5)))
+        );
+    }
+
+    #[test]
+    fn test_alternative_bars() {
+        let base = Resolution::new(Minute, 1);
+        let t0 = base.truncate(Utc::now());
+        let candle = |minute: i64, open: f64, close: f64| {
+            let start_time = t0.add(Duration::minutes(minute));
+            Candle {
+                open,
+                high: open.max(close) + 1.0,
+                low: open.min(close) - 1.0,
+                close,
+                is_final: true,
+                ..Candle::new(close, 2.0, start_time, start_time, base.add(start_time))
+            }
+        };
+        let mut heikin_ashi = BarBuilder::new(BarType::HeikinAshi);
+        let first = heikin_ashi.next(candle(0, 10.0, 12.0))[0];
+        assert_eq!((first.open, first.close, first.high), (11.0, 11.0, 13.0));
+        let second = heikin_ashi.next(candle(1, 12.0, 14.0))[0];
+        assert_eq!((second.open, second.close), (11.0, 13.0));
+
+        let mut renko = BarBuilder::new(BarType::Renko { brick_size: 2.0 });
+        assert!(renko.next(candle(0, 10.0, 10.0)).is_empty());
+        assert!(renko.next(candle(1, 10.0, 11.0)).is_empty());
+        let bricks = renko.next(candle(2, 11.0, 14.5));
+        let closes: Vec<f64> = bricks.iter().map(|b| b.close).collect();
+        assert_eq!(closes, vec![12.0, 14.0]);
+        assert_eq!((bricks[0].volume, bricks[1].volume), (4.0, 0.0));
+        let bricks = renko.next(candle(3, 14.5, 11.0));
+        assert_eq!((bricks.len(), bricks[0].open, bricks[0].close), (1, 14.0, 12.0));
+
+        let mut volume_bars = BarBuilder::new(BarType::Volume { volume: 5.0 });
+        assert!(volume_bars.next(candle(0, 10.0, 11.0)).is_empty());
+        assert!(volume_bars.next(candle(1, 11.0, 12.0)).is_empty());
+        let bars = volume_bars.next(candle(2, 12.0, 9.0));
+        assert_eq!(bars.len(), 1);
+        assert_eq!(
+            (bars[0].open, bars[0].close, bars[0].volume, bars[0].start_time),
+            (10.0, 9.0, 6.0, t0)
+        );
+        assert!(volume_bars
+            .next(Candle {
+                is_final: false,
+                ..candle(3, 9.0, 9.0)
+            })
+            .is_empty());
+    }
 }
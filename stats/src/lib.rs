@@ -6,6 +6,9 @@ A collection of statistical tools related to trading
 Indicators : re-exports of the `ta` library plus some more technical indicators
 Math : optimal algorithms for common math functions
 Summary : statistical tools to summarize data series
+Cvd : cumulative volume delta from trade aggressor sides
+Correlation : pairwise correlation of returns between price series
+Cointegration : stationarity and cointegration tests for mean reversion
 
  */
 
@@ -43,9 +46,16 @@ pub use yata::indicators as yata_indicators;
 pub use yata::methods as yata_methods;
 pub use yata::prelude as yata_prelude;
 
+pub use correlation::correlation_matrix;
+
+pub mod cointegration;
+pub mod correlation;
+pub mod cvd;
 pub mod dispersion;
 pub mod error;
 pub mod indicators;
 pub mod iter;
 pub mod kline;
 pub mod math;
+#[cfg(any(test, feature = "test_util"))]
+pub mod test_util;
@@ -0,0 +1,59 @@
+use crate::cointegration::ols;
+
+/// Half-life of mean reversion of a spread, in periods, modeled as a discrete Ornstein-Uhlenbeck process
+/// `Δs(t) = c + gamma * s(t-1) + e(t)`.
+///
+/// Returns infinity if the spread does not revert to its mean, and `NaN` if it has less than four values or is
+/// constant.
+pub fn half_life(spread: &[f64]) -> f64 {
+    let lagged = &spread[..spread.len().saturating_sub(1)];
+    let diffs: Vec<f64> = spread.windows(2).map(|w| w[1] - w[0]).collect();
+    ols(lagged, &diffs).map_or(f64::NAN, |fit| half_life_of_gamma(fit.beta))
+}
+
+/// Half-life of a process reverting at the rate `gamma` per period
+pub(crate) fn half_life_of_gamma(gamma: f64) -> f64 {
+    if gamma >= 0.0 {
+        f64::INFINITY
+    } else if gamma <= -1.0 {
+        0.0
+    } else {
+        -std::f64::consts::LN_2 / gamma.ln_1p()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::math::half_life;
+    use crate::test_util::noise;
+
+    #[test]
+    fn test_ornstein_uhlenbeck_half_life() {
+        let theta = 0.1;
+        let mean = 10.0;
+        let expected = -std::f64::consts::LN_2 / (1.0_f64 - theta).ln();
+        let mut level = mean;
+        let spread: Vec<f64> = noise(11, 20000)
+            .into_iter()
+            .map(|e| {
+                level += theta * (mean - level) + e;
+                level
+            })
+            .collect();
+        let estimate = half_life(&spread);
+        assert!(
+            (estimate - expected).abs() < expected * 0.1,
+            "estimated {} expected {}",
+            estimate,
+            expected
+        );
+    }
+
+    #[test]
+    fn test_degenerate_spreads() {
+        let trending: Vec<f64> = (0..100).map(|i| f64::from(i) * f64::from(i)).collect();
+        assert!(half_life(&trending).is_infinite());
+        assert!(half_life(&[1.0, 2.0]).is_nan());
+        assert!(half_life(&[1.0; 10]).is_nan());
+    }
+}
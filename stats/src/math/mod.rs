@@ -1 +1,4 @@
+pub use mean_reversion::half_life;
+
+pub mod mean_reversion;
 pub mod welford;
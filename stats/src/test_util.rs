@@ -0,0 +1,27 @@
+//! Deterministic series for tests, so that statistical assertions do not depend on a random seed
+
+/// Deterministic pseudo random noise, uniform in [-0.5, 0.5)
+pub fn noise(seed: u64, len: usize) -> Vec<f64> {
+    let mut state = seed;
+    (0..len)
+        .map(|_| {
+            state = state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            #[allow(clippy::cast_precision_loss)]
+            let uniform = (state >> 11) as f64 / (1u64 << 53) as f64;
+            uniform - 0.5
+        })
+        .collect()
+}
+
+/// Deterministic random walk starting from 100.0, with [`noise`] steps
+pub fn random_walk(seed: u64, len: usize) -> Vec<f64> {
+    noise(seed, len)
+        .into_iter()
+        .scan(100.0, |level, step| {
+            *level += step;
+            Some(*level)
+        })
+        .collect()
+}
@@ -209,4 +209,6 @@ impl Strategy for BollingerPlusStrategy {
         // .build()
         vec![].into_iter().collect()
     }
+
+    fn order_conf(&self) -> Option<&OrderConf> { Some(&self.order_conf) }
 }
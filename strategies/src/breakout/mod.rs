@@ -304,4 +304,6 @@ impl Strategy for BreakoutStrategy {
     fn model(&self) -> SerializedModel { todo!() }
 
     fn channels(&self) -> HashSet<MarketChannel> { todo!() }
+
+    fn order_conf(&self) -> Option<&OrderConf> { Some(&self.order_conf) }
 }
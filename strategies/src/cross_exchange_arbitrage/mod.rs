@@ -0,0 +1,434 @@
+//! A cross exchange arbitrage strategy : the best quotes of a pair are watched on two exchanges, and when the bid of
+//! one exceeds the ask of the other by more than the fees and the cost of transferring inventory back, the pair is
+//! bought on the cheaper exchange and sold on the other in a single order batch, which leaves offsetting positions.
+//!
+//! The base and quote inventories held on each exchange bound the size of every trade. They are updated with the
+//! executions of the legs reported by the driver and persisted, and their balance is checked periodically, a transfer
+//! that would even them out being suggested as a [`StratEvent::RebalanceSuggested`] event.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use chrono::{DateTime, Duration, Utc};
+use serde_json::Value;
+use uuid::Uuid;
+
+use brokers::prelude::*;
+use brokers::types::{MarketChannel, MarketChannelType, SecurityType, Symbol};
+use db::Storage;
+use strategy::driver::{DefaultStrategyContext, Strategy, TradeSignals};
+use strategy::error::*;
+use strategy::models::io::SerializedModel;
+use strategy::models::persist::{ModelValue, PersistentValue};
+use strategy::plugin::{provide_options, StrategyPlugin, StrategyPluginContext};
+use strategy::settings::{StrategyOptions, StrategySettingsReplicator};
+use strategy::types::StratEvent;
+use strategy::{StratEventLoggerRef, StrategyKey};
+use trading::quoting::QuoteFill;
+use trading::signal::OrderBatch;
+use trading::types::OrderConf;
+use util::time::TimedData;
+
+pub fn provide_strat(_name: &str, ctx: StrategyPluginContext, conf: serde_json::Value) -> Result<Box<dyn Strategy>> {
+    let options: Options = serde_json::from_value(conf)?;
+    Ok(Box::new(CrossExchangeArbitrageStrategy::try_new(
+        &options, ctx.db, ctx.logger,
+    )?))
+}
+
+inventory::submit! {
+    StrategyPlugin::new("cross_exchange_arbitrage", provide_options::<Options>, provide_strat)
+}
+
+fn default_rebalance_threshold() -> f64 { 0.25 }
+
+/// Base and quote amounts held on an exchange
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct VenueInventory {
+    pub base: f64,
+    pub quote: f64,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Options {
+    pub pair: Pair,
+    /// The two exchanges the pair is traded on
+    pub exchanges: Vec<Exchange>,
+    /// Inventory held on each exchange when the strategy first starts, then restored from the persisted model
+    pub inventory: HashMap<Exchange, VenueInventory>,
+    /// Maximum base quantity of each trade
+    pub qty: f64,
+    /// Taker fee rate of each leg
+    pub fee_rate: f64,
+    /// Cost of transferring inventory back between exchanges, as a fraction of the traded notional
+    #[serde(default)]
+    pub transfer_cost: f64,
+    /// Minimum spread after fees and transfer costs, as a fraction of the buy price
+    #[serde(default)]
+    pub threshold: f64,
+    /// Share of an asset that may drift away from an even split between exchanges before a transfer is suggested
+    #[serde(default = "default_rebalance_threshold")]
+    pub rebalance_threshold: f64,
+    /// Interval between two checks of the balance of inventories
+    #[serde(
+        deserialize_with = "util::ser::string_duration_chrono",
+        serialize_with = "util::ser::encode_duration_str"
+    )]
+    pub rebalance_every: Duration,
+    #[serde(default)]
+    pub order_conf: OrderConf,
+}
+
+impl StrategySettingsReplicator for Options {
+    fn replicate_for_pairs(&self, pairs: HashSet<Pair>) -> Vec<Value> {
+        pairs
+            .into_iter()
+            .map(|pair| {
+                let mut new = self.clone();
+                new.pair = pair;
+                serde_json::to_value(new).unwrap()
+            })
+            .collect()
+    }
+}
+
+impl StrategyOptions for Options {
+    fn key(&self) -> StrategyKey { StrategyKey("cross_exchange_arbitrage".to_string(), self.pair.to_string()) }
+}
+
+/// Buying on `buy` at `ask` and selling on `sell` at `bid` returns `edge` after costs
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Opportunity {
+    buy: Exchange,
+    sell: Exchange,
+    ask: f64,
+    bid: f64,
+    qty: f64,
+    edge: f64,
+}
+
+pub struct CrossExchangeArbitrageStrategy {
+    pair: Pair,
+    base_asset: String,
+    quote_asset: String,
+    exchanges: [Exchange; 2],
+    inventory: HashMap<Exchange, VenueInventory>,
+    /// Inventories of both exchanges, in the order of `exchanges`
+    inventory_model: PersistentValue<[VenueInventory; 2]>,
+    qty: f64,
+    fee_rate: f64,
+    transfer_cost: f64,
+    threshold: f64,
+    rebalance_threshold: f64,
+    rebalance_every: Duration,
+    order_conf: OrderConf,
+    /// Best bid and ask on each exchange
+    quotes: HashMap<Exchange, (f64, f64)>,
+    last_edge: Option<f64>,
+    last_rebalance_check: Option<DateTime<Utc>>,
+    logger: Option<StratEventLoggerRef>,
+}
+
+impl CrossExchangeArbitrageStrategy {
+    pub fn try_new(options: &Options, db: Arc<dyn Storage>, logger: Option<StratEventLoggerRef>) -> Result<Self> {
+        let exchanges: [Exchange; 2] = match options.exchanges.as_slice() {
+            [a, b] if a != b => [*a, *b],
+            _ => {
+                return Err(Error::BadConfiguration(
+                    "cross exchange arbitrage needs two distinct exchanges".to_string(),
+                ))
+            }
+        };
+        let (base_asset, quote_asset) = options
+            .pair
+            .split_once('_')
+            .ok_or_else(|| Error::BadConfiguration(format!("pair {} is not BASE_QUOTE", options.pair)))?;
+        if options.qty <= 0.0 || options.rebalance_every <= Duration::zero() {
+            return Err(Error::BadConfiguration(
+                "cross exchange arbitrage needs a positive quantity and rebalancing interval".to_string(),
+            ));
+        }
+        let initial = exchanges.map(|xch| options.inventory.get(&xch).copied().unwrap_or_default());
+        let inventory = exchanges.into_iter().zip(initial).collect();
+        let inventory_model = PersistentValue::new(
+            db,
+            &format!(
+                "cross_exchange_arbitrage_{}_{}_{}_inventory",
+                exchanges[0], exchanges[1], options.pair
+            ),
+            Some(ModelValue::new(initial)),
+        );
+        Ok(Self {
+            pair: options.pair.clone(),
+            base_asset: base_asset.to_string(),
+            quote_asset: quote_asset.to_string(),
+            exchanges,
+            inventory,
+            inventory_model,
+            qty: options.qty,
+            fee_rate: options.fee_rate,
+            transfer_cost: options.transfer_cost,
+            threshold: options.threshold,
+            rebalance_threshold: options.rebalance_threshold,
+            rebalance_every: options.rebalance_every,
+            order_conf: options.order_conf.clone(),
+            quotes: HashMap::default(),
+            last_edge: None,
+            last_rebalance_check: None,
+            logger,
+        })
+    }
+
+    /// The best of both directions, with a quantity bounded by the base inventory of the exchange it is sold on and
+    /// the quote inventory of the exchange it is bought on
+    fn opportunity(&mut self) -> Option<Opportunity> {
+        let [a, b] = self.exchanges;
+        let (&(bid_a, ask_a), &(bid_b, ask_b)) = (self.quotes.get(&a)?, self.quotes.get(&b)?);
+        let best = [(a, ask_a, b, bid_b), (b, ask_b, a, bid_a)]
+            .into_iter()
+            .filter(|(_, ask, _, _)| *ask > 0.0)
+            .map(|(buy, ask, sell, bid)| Opportunity {
+                buy,
+                sell,
+                ask,
+                bid,
+                qty: 0.0,
+                edge: (bid - ask) / ask - 2.0 * self.fee_rate - self.transfer_cost,
+            })
+            .max_by(|x, y| x.edge.total_cmp(&y.edge))?;
+        self.last_edge = Some(best.edge);
+        if best.edge <= self.threshold {
+            return None;
+        }
+        let qty = self
+            .qty
+            .min(self.inventory[&best.sell].base)
+            .min(self.inventory[&best.buy].quote / (best.ask * (1.0 + self.fee_rate)));
+        (qty > 0.0).then_some(Opportunity { qty, ..best })
+    }
+
+    /// Account an execution on `xch` in its inventory, fees are paid in the quote asset
+    fn fill(&mut self, xch: Exchange, fill: &QuoteFill) -> Result<()> {
+        let Some(inventory) = self.inventory.get_mut(&xch) else {
+            return Ok(());
+        };
+        let notional = fill.qty * fill.price;
+        match fill.side {
+            TradeType::Buy => {
+                inventory.base += fill.qty;
+                inventory.quote -= notional + fill.fees;
+            }
+            TradeType::Sell => {
+                inventory.base -= fill.qty;
+                inventory.quote += notional - fill.fees;
+            }
+        }
+        self.inventory_model
+            .set_last_model(self.exchanges.map(|xch| self.inventory[&xch]));
+        self.inventory_model.persist()
+    }
+
+    /// Transfers of the assets whose split between exchanges drifted beyond the rebalancing threshold
+    fn rebalancing(&self) -> Vec<StratEvent> {
+        let [a, b] = self.exchanges;
+        let (inv_a, inv_b) = (self.inventory[&a], self.inventory[&b]);
+        [
+            (&self.base_asset, inv_a.base, inv_b.base),
+            (&self.quote_asset, inv_a.quote, inv_b.quote),
+        ]
+        .into_iter()
+        .filter_map(|(asset, held_a, held_b)| {
+            let even = (held_a + held_b) / 2.0;
+            let excess = held_a - even;
+            if even <= 0.0 || excess.abs() <= self.rebalance_threshold * even {
+                return None;
+            }
+            let (from, to) = if excess > 0.0 { (a, b) } else { (b, a) };
+            Some(StratEvent::RebalanceSuggested {
+                asset: asset.clone(),
+                from,
+                to,
+                amount: excess.abs(),
+            })
+        })
+        .collect()
+    }
+}
+
+#[async_trait]
+impl Strategy for CrossExchangeArbitrageStrategy {
+    fn key(&self) -> String {
+        format!(
+            "cross_exchange_arbitrage_{}_{}_{}",
+            self.exchanges[0], self.exchanges[1], self.pair
+        )
+    }
+
+    fn init(&mut self) -> Result<()> {
+        self.inventory_model.load()?;
+        if let Some(persisted) = self.inventory_model.value() {
+            self.inventory = self.exchanges.into_iter().zip(persisted).collect();
+        }
+        Ok(())
+    }
+
+    async fn eval(&mut self, le: &MarketEventEnvelope, _ctx: &DefaultStrategyContext) -> Result<Option<TradeSignals>> {
+        let at = le.e.time();
+        if self
+            .last_rebalance_check
+            .map_or(false, |last| at - last < self.rebalance_every)
+        {
+            return Ok(None);
+        }
+        self.last_rebalance_check = Some(at);
+        if let Some(logger) = &self.logger {
+            for event in self.rebalancing() {
+                logger.log(TimedData::new(at, event)).await;
+            }
+        }
+        Ok(None)
+    }
+
+    fn order_batch(&mut self, le: &MarketEventEnvelope, _ctx: &DefaultStrategyContext) -> Option<OrderBatch> {
+        let MarketEvent::Quote(quote) = &le.e else {
+            return None;
+        };
+        if le.symbol.value != self.pair || !self.exchanges.contains(&le.symbol.xch) {
+            return None;
+        }
+        self.quotes.insert(le.symbol.xch, (quote.bid, quote.ask));
+        let opportunity = self.opportunity()?;
+        let order = |xch: Exchange, side: TradeType, price: f64| AddOrderRequest {
+            xch,
+            pair: self.pair.clone(),
+            side,
+            order_type: OrderType::Limit,
+            enforcement: Some(OrderEnforcement::IOC),
+            quantity: Some(opportunity.qty),
+            price: Some(price),
+            order_id: Uuid::new_v4().to_string(),
+            dry_run: self.order_conf.dry_mode,
+            asset_type: Some(self.order_conf.asset_type),
+            account: self.order_conf.account.clone(),
+            ..AddOrderRequest::default()
+        };
+        Some(OrderBatch::new(le, vec![
+            order(opportunity.buy, TradeType::Buy, opportunity.ask),
+            order(opportunity.sell, TradeType::Sell, opportunity.bid),
+        ]))
+    }
+
+    fn on_batch_fill(&mut self, order: &AddOrderRequest, fill: &QuoteFill) {
+        if order.pair != self.pair {
+            return;
+        }
+        if let Err(e) = self.fill(order.xch, fill) {
+            error!(err = %e, xch = %order.xch, "failed to persist arbitrage inventories");
+        }
+    }
+
+    fn model(&self) -> SerializedModel {
+        vec![
+            (
+                "edge".to_string(),
+                self.last_edge.and_then(|e| serde_json::to_value(e).ok()),
+            ),
+            ("inventory".to_string(), serde_json::to_value(&self.inventory).ok()),
+        ]
+    }
+
+    fn channels(&self) -> HashSet<MarketChannel> {
+        self.exchanges
+            .iter()
+            .map(|xch| {
+                MarketChannel::builder()
+                    .symbol(Symbol::new(self.pair.clone(), SecurityType::Crypto, *xch))
+                    .r#type(MarketChannelType::Quotes)
+                    .build()
+            })
+            .collect()
+    }
+
+    fn order_conf(&self) -> Option<&OrderConf> { Some(&self.order_conf) }
+}
+
+#[cfg(test)]
+mod test {
+    use brokers::exchange::Exchange;
+    use brokers::types::TradeType;
+    use strategy::driver::Strategy;
+    use strategy::types::StratEvent;
+    use strategy_test_util::test_db;
+    use trading::quoting::QuoteFill;
+
+    use super::{CrossExchangeArbitrageStrategy, Options};
+
+    fn fill(side: TradeType, qty: f64, price: f64) -> QuoteFill {
+        QuoteFill {
+            order_id: "leg".to_string(),
+            side,
+            qty,
+            price,
+            fees: qty * price * 0.001,
+        }
+    }
+
+    #[test]
+    fn trade_spreads_within_inventory_and_suggest_rebalancing() {
+        let options: Options = serde_json::from_value(serde_json::json!({
+            "pair": "BTC_USDT",
+            "exchanges": ["binance", "kraken"],
+            "inventory": {
+                "binance": { "base": 1.0, "quote": 100000.0 },
+                "kraken": { "base": 0.8, "quote": 100000.0 }
+            },
+            "qty": 1.0,
+            "fee_rate": 0.001,
+            "transfer_cost": 0.0005,
+            "threshold": 0.0,
+            "rebalance_every": "1h"
+        }))
+        .unwrap();
+        let db = test_db();
+        let mut arb = CrossExchangeArbitrageStrategy::try_new(&options, db.clone(), None).unwrap();
+        arb.init().unwrap();
+        arb.quotes.insert(Exchange::Binance, (19990.0, 20000.0));
+        arb.quotes.insert(Exchange::Kraken, (20010.0, 20020.0));
+        // A 5bps spread does not cover the fees
+        assert!(arb.opportunity().is_none());
+        assert!(arb.last_edge.unwrap() < 0.0);
+
+        // Kraken is bid 1% over the binance ask, the size is bounded by the base held on kraken
+        arb.quotes.insert(Exchange::Kraken, (20200.0, 20210.0));
+        let opportunity = arb.opportunity().unwrap();
+        assert_eq!(
+            (opportunity.buy, opportunity.sell),
+            (Exchange::Binance, Exchange::Kraken)
+        );
+        assert_eq!(opportunity.qty, 0.8);
+        assert!((opportunity.edge - (0.01 - 0.0025)).abs() < 1e-9);
+        assert!(arb.rebalancing().is_empty());
+
+        // Inventories change with the executions of the legs
+        arb.fill(Exchange::Binance, &fill(TradeType::Buy, 0.8, 20000.0))
+            .unwrap();
+        arb.fill(Exchange::Kraken, &fill(TradeType::Sell, 0.8, 20200.0))
+            .unwrap();
+        assert_eq!(arb.inventory[&Exchange::Kraken].base, 0.0);
+        assert!((arb.inventory[&Exchange::Binance].quote - (100000.0 - 16000.0 * 1.001)).abs() < 1e-6);
+        assert!(arb.opportunity().is_none());
+        let events = arb.rebalancing();
+        assert!(matches!(
+            &events[0],
+            StratEvent::RebalanceSuggested { asset, from: Exchange::Binance, to: Exchange::Kraken, amount }
+                if asset == "BTC" && (amount - 0.9).abs() < 1e-9
+        ));
+        assert_eq!(events.len(), 1);
+
+        // Inventories are restored from the persisted model on start
+        let mut restarted = CrossExchangeArbitrageStrategy::try_new(&options, db, None).unwrap();
+        assert_eq!(restarted.inventory[&Exchange::Kraken].base, 0.8);
+        restarted.init().unwrap();
+        assert_eq!(restarted.inventory, arb.inventory);
+    }
+}
@@ -0,0 +1,347 @@
+//! A dollar cost averaging reference strategy : a fixed quote amount of a pair is bought on a schedule, more when an
+//! indicator signals a dip, and part of the accumulated position is sold as its gain over the average cost crosses
+//! take profit bands.
+//!
+//! Positions can only be opened or closed in full, so changing the size of a held position closes it and opens it
+//! again at its new size once the close is resolved, the average cost of the accumulation is kept by the strategy.
+
+use std::collections::HashSet;
+
+use chrono::{DateTime, Duration, Utc};
+use serde_json::Value;
+
+use brokers::prelude::*;
+use brokers::types::{MarketChannel, MarketChannelType, SecurityType, Symbol};
+use stats::kline::Resolution;
+use stats::ta_indicators::{RelativeStrengthIndex, SimpleMovingAverage};
+use stats::Next;
+use strategy::driver::{DefaultStrategyContext, Strategy, TradeSignals};
+use strategy::error::*;
+use strategy::models::io::SerializedModel;
+use strategy::plugin::{provide_options, StrategyPlugin, StrategyPluginContext};
+use strategy::settings::{StrategyOptions, StrategySettingsReplicator};
+use strategy::StrategyKey;
+use trading::position::{OperationKind, PositionKind};
+use trading::signal::new_trade_signal;
+use trading::types::OrderConf;
+
+pub fn provide_strat(_name: &str, _ctx: StrategyPluginContext, conf: serde_json::Value) -> Result<Box<dyn Strategy>> {
+    let options: Options = serde_json::from_value(conf)?;
+    Ok(Box::new(DcaStrategy::try_new(&options)?))
+}
+
+inventory::submit! {
+    StrategyPlugin::new("dca", provide_options::<Options>, provide_strat)
+}
+
+/// An indicator condition under which the market is considered in a dip
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum DipCondition {
+    /// The relative strength index of closes is below `threshold`
+    RsiBelow { period: usize, threshold: f64 },
+    /// The close is below the simple moving average of closes by more than `discount`, relative to the average
+    BelowSma { period: usize, discount: f64 },
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DipOptions {
+    pub condition: DipCondition,
+    /// Multiplier of the amount bought in a dip
+    pub multiplier: f64,
+}
+
+/// Sell a fraction of the held quantity once the gain over the average cost reaches `gain`
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq)]
+pub struct TakeProfitBand {
+    pub gain: f64,
+    pub fraction: f64,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Options {
+    pub exchange: Exchange,
+    pub pair: Pair,
+    pub resolution: Resolution,
+    /// Quote amount of each scheduled buy
+    pub amount: f64,
+    /// Interval between two scheduled buys
+    #[serde(
+        deserialize_with = "util::ser::string_duration_chrono",
+        serialize_with = "util::ser::encode_duration_str"
+    )]
+    pub every: Duration,
+    #[serde(default)]
+    pub dip: Option<DipOptions>,
+    /// Bands are crossed in increasing order of gain, each at most once between two buys
+    #[serde(default)]
+    pub take_profit: Vec<TakeProfitBand>,
+    #[serde(default)]
+    pub order_conf: OrderConf,
+}
+
+impl StrategySettingsReplicator for Options {
+    fn replicate_for_pairs(&self, pairs: HashSet<Pair>) -> Vec<Value> {
+        pairs
+            .into_iter()
+            .map(|pair| {
+                let mut new = self.clone();
+                new.pair = pair;
+                serde_json::to_value(new).unwrap()
+            })
+            .collect()
+    }
+}
+
+impl StrategyOptions for Options {
+    fn key(&self) -> StrategyKey { StrategyKey("dca".to_string(), self.pair.to_string()) }
+}
+
+enum DipIndicator {
+    Rsi(RelativeStrengthIndex, f64),
+    Sma(SimpleMovingAverage, f64),
+}
+
+impl DipIndicator {
+    fn try_new(condition: &DipCondition) -> Result<Self> {
+        let bad_period = |e| Error::BadConfiguration(format!("dip indicator: {:?}", e));
+        Ok(match *condition {
+            DipCondition::RsiBelow { period, threshold } => {
+                DipIndicator::Rsi(RelativeStrengthIndex::new(period).map_err(bad_period)?, threshold)
+            }
+            DipCondition::BelowSma { period, discount } => {
+                DipIndicator::Sma(SimpleMovingAverage::new(period).map_err(bad_period)?, discount)
+            }
+        })
+    }
+
+    /// Whether the market is in a dip after this close
+    fn next(&mut self, close: f64) -> bool {
+        match self {
+            DipIndicator::Rsi(rsi, threshold) => rsi.next(close) < *threshold,
+            DipIndicator::Sma(sma, discount) => close < sma.next(close) * (1.0 - *discount),
+        }
+    }
+}
+
+pub struct DcaStrategy {
+    exchange: Exchange,
+    pair: Pair,
+    resolution: Resolution,
+    amount: f64,
+    every: Duration,
+    dip: Option<(DipIndicator, f64)>,
+    take_profit: Vec<TakeProfitBand>,
+    order_conf: OrderConf,
+    last_buy: Option<DateTime<Utc>>,
+    /// Average price of the accumulated quantity
+    avg_cost: Option<f64>,
+    /// Number of take profit bands crossed since the last buy
+    bands_crossed: usize,
+    /// Size the position is opened at once its close is resolved
+    pending_size: Option<f64>,
+    in_dip: bool,
+}
+
+impl DcaStrategy {
+    pub fn try_new(options: &Options) -> Result<Self> {
+        if options.amount <= 0.0 || options.every <= Duration::zero() {
+            return Err(Error::BadConfiguration(
+                "dca needs a positive amount and interval".to_string(),
+            ));
+        }
+        if options
+            .take_profit
+            .iter()
+            .any(|band| band.gain <= 0.0 || !(0.0..=1.0).contains(&band.fraction))
+        {
+            return Err(Error::BadConfiguration(
+                "take profit bands need a positive gain and a fraction in [0, 1]".to_string(),
+            ));
+        }
+        let dip = options
+            .dip
+            .as_ref()
+            .map(|dip| DipIndicator::try_new(&dip.condition).map(|indicator| (indicator, dip.multiplier)))
+            .transpose()?;
+        let mut take_profit = options.take_profit.clone();
+        take_profit.sort_by(|a, b| a.gain.total_cmp(&b.gain));
+        Ok(Self {
+            exchange: options.exchange,
+            pair: options.pair.clone(),
+            resolution: options.resolution,
+            amount: options.amount,
+            every: options.every,
+            dip,
+            take_profit,
+            order_conf: options.order_conf.clone(),
+            last_buy: None,
+            avg_cost: None,
+            bands_crossed: 0,
+            pending_size: None,
+            in_dip: false,
+        })
+    }
+
+    /// Trades to hold `size` instead of `held`, a held position is closed first
+    fn resize(&mut self, held: f64, size: f64) -> Vec<(OperationKind, Option<f64>)> {
+        if held > 0.0 {
+            self.pending_size = Some(size);
+            vec![(OperationKind::Close, None)]
+        } else if size > 0.0 {
+            vec![(OperationKind::Open, Some(size))]
+        } else {
+            vec![]
+        }
+    }
+
+    /// The trades after a close at `price` with `held` quantity, resolving a pending resize before anything else
+    fn decide(&mut self, held: f64, price: f64, at: DateTime<Utc>) -> Vec<(OperationKind, Option<f64>)> {
+        if let Some(size) = self.pending_size {
+            if held > 0.0 {
+                return vec![];
+            }
+            self.pending_size = None;
+            return self.resize(0.0, size);
+        }
+        if held <= 0.0 {
+            self.avg_cost = None;
+        }
+        if let (Some(avg_cost), Some(band)) = (self.avg_cost, self.take_profit.get(self.bands_crossed).copied()) {
+            if held > 0.0 && price / avg_cost - 1.0 >= band.gain {
+                self.bands_crossed += 1;
+                return self.resize(held, held * (1.0 - band.fraction));
+            }
+        }
+        if self.last_buy.map_or(false, |last| at - last < self.every) {
+            return vec![];
+        }
+        self.last_buy = Some(at);
+        let multiplier = match &self.dip {
+            Some((_, multiplier)) if self.in_dip => *multiplier,
+            _ => 1.0,
+        };
+        let qty = self.amount * multiplier / price;
+        let held_cost = self.avg_cost.map_or(0.0, |avg| avg * held);
+        self.avg_cost = Some((held_cost + qty * price) / (held + qty));
+        self.bands_crossed = 0;
+        self.resize(held, held + qty)
+    }
+}
+
+#[async_trait]
+impl Strategy for DcaStrategy {
+    fn key(&self) -> String { format!("dca_{}_{}", self.exchange, self.pair) }
+
+    fn init(&mut self) -> Result<()> { Ok(()) }
+
+    async fn eval(&mut self, le: &MarketEventEnvelope, ctx: &DefaultStrategyContext) -> Result<Option<TradeSignals>> {
+        let MarketEvent::TradeCandle(candle) = &le.e else {
+            return Ok(None);
+        };
+        if let Some((indicator, _)) = self.dip.as_mut() {
+            self.in_dip = indicator.next(candle.close);
+        }
+        if !ctx.portfolio.locks().is_empty() || candle.close <= 0.0 {
+            return Ok(None);
+        }
+        let held = ctx
+            .portfolio
+            .open_position(self.exchange, self.pair.clone())
+            .filter(|pos| pos.is_long() && pos.is_opened())
+            .map_or(0.0, |pos| pos.quantity());
+        let trades = self.decide(held, candle.close, le.e.time());
+        if trades.is_empty() {
+            return Ok(None);
+        }
+        let signals: TradeSignals = trades
+            .into_iter()
+            .map(|(op, qty)| {
+                new_trade_signal(
+                    self.pair.clone(),
+                    self.exchange,
+                    &self.order_conf,
+                    le.e.time(),
+                    le.trace_id,
+                    op,
+                    PositionKind::Long,
+                    candle.close,
+                    qty,
+                )
+            })
+            .collect();
+        Ok(Some(signals))
+    }
+
+    fn model(&self) -> SerializedModel {
+        vec![
+            (
+                "avg_cost".to_string(),
+                self.avg_cost.and_then(|c| serde_json::to_value(c).ok()),
+            ),
+            ("in_dip".to_string(), serde_json::to_value(self.in_dip).ok()),
+            (
+                "last_buy".to_string(),
+                self.last_buy.and_then(|at| serde_json::to_value(at).ok()),
+            ),
+        ]
+    }
+
+    fn channels(&self) -> HashSet<MarketChannel> {
+        vec![MarketChannel::builder()
+            .symbol(Symbol::new(self.pair.clone(), SecurityType::Crypto, self.exchange))
+            .r#type(MarketChannelType::Candles)
+            .resolution(Some(self.resolution))
+            .build()]
+        .into_iter()
+        .collect()
+    }
+
+    fn order_conf(&self) -> Option<&OrderConf> { Some(&self.order_conf) }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{Duration, TimeZone, Utc};
+
+    use trading::position::OperationKind;
+
+    use super::{DcaStrategy, Options};
+
+    #[test]
+    fn accumulate_and_take_profit() {
+        let options: Options = serde_json::from_value(serde_json::json!({
+            "exchange": "binance",
+            "pair": "BTC_USDT",
+            "resolution": {"time_unit": "hour", "units": 1},
+            "amount": 100.0,
+            "every": "1d",
+            "dip": {"condition": {"type": "below_sma", "period": 2, "discount": 0.1}, "multiplier": 2.0},
+            "take_profit": [{"gain": 0.5, "fraction": 0.5}]
+        }))
+        .unwrap();
+        let mut dca = DcaStrategy::try_new(&options).unwrap();
+        let day = |i: i64| Utc.with_ymd_and_hms(2022, 1, 1, 0, 0, 0).unwrap() + Duration::days(i);
+
+        assert_eq!(dca.decide(0.0, 100.0, day(0)), vec![(OperationKind::Open, Some(1.0))]);
+        // Not due yet
+        assert!(dca.decide(1.0, 100.0, day(0) + Duration::hours(1)).is_empty());
+        // Bought twice as much in a dip, the held position is resized
+        dca.in_dip = true;
+        assert_eq!(dca.decide(1.0, 50.0, day(1)), vec![(OperationKind::Close, None)]);
+        assert!(dca.decide(1.0, 50.0, day(1)).is_empty());
+        assert_eq!(dca.decide(0.0, 50.0, day(1)), vec![(OperationKind::Open, Some(5.0))]);
+        assert!((dca.avg_cost.unwrap() - 60.0).abs() < 1e-9);
+        // Half is sold once the gain over the average cost reaches 50%, the band is crossed once
+        dca.in_dip = false;
+        assert_eq!(dca.decide(5.0, 90.0, day(1) + Duration::hours(1)), vec![(
+            OperationKind::Close,
+            None
+        )]);
+        assert_eq!(dca.decide(0.0, 90.0, day(1) + Duration::hours(2)), vec![(
+            OperationKind::Open,
+            Some(2.5)
+        )]);
+        assert!(dca.decide(2.5, 95.0, day(1) + Duration::hours(3)).is_empty());
+    }
+}
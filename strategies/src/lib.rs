@@ -3,8 +3,14 @@ A collection of pre-developped strategies
 
 # Overview
 
+Cross Exchange Arbitrage : buys a pair on one exchange and sells it on another when the spread beats the costs
+DCA : buys a fixed amount on a schedule, more in dips, and takes profit in bands over the average cost
+Market Making : quotes both sides around the microprice, skewed by the inventory of fills
 Mean Reverting : a MACD variant to enter a position if the oscillator goes over a threshold
 Naive Spread : a linear regression that enters a position depending on the direction of spread between two markets
+Onnx Inference : an example of a strategy scoring candles with an ONNX model, with the `onnx` feature
+Rebalancing : holds a set of assets at target weights, rebalanced on drift or on a schedule
+Triangular Arbitrage : converts around a cycle of three pairs when the implied cross rate beats the fees
 
 # Nota Bene
 
@@ -35,10 +41,17 @@ extern crate tracing;
 
 pub mod bbplusb;
 pub mod breakout;
+pub mod cross_exchange_arbitrage;
+pub mod dca;
 pub mod kline_logger;
+pub mod market_making;
 pub mod mean_reverting;
 pub mod naive_pair_trading;
+#[cfg(feature = "onnx")]
+pub mod onnx_inference;
+pub mod rebalancing;
 pub mod rsistoch_strategy;
+pub mod triangular_arbitrage;
 
 pub fn init() {
     trace!("strat crate initialized");
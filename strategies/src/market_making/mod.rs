@@ -0,0 +1,213 @@
+//! A basic market making strategy : both sides of a book are quoted around its microprice, with a reservation price
+//! and quantities skewed by the inventory accumulated by fills, so that quotes lean towards getting back to a flat
+//! inventory.
+//!
+//! Quotes are rested by the driver as post only limit orders, amended in place as the microprice moves, within the
+//! quoting limits of the driver options which are checked on every tick.
+
+use std::collections::HashSet;
+
+use serde_json::Value;
+
+use brokers::prelude::*;
+use brokers::types::{MarketChannel, MarketChannelType, SecurityType, Symbol};
+use stats::indicators::microstructure::microprice;
+use strategy::driver::{DefaultStrategyContext, Strategy, TradeSignals};
+use strategy::error::*;
+use strategy::models::io::SerializedModel;
+use strategy::plugin::{provide_options, StrategyPlugin, StrategyPluginContext};
+use strategy::settings::{StrategyOptions, StrategySettingsReplicator};
+use strategy::StrategyKey;
+use trading::quoting::{QuoteLevel, TwoSidedQuote};
+
+const BPS: f64 = 10_000.0;
+
+pub fn provide_strat(_name: &str, _ctx: StrategyPluginContext, conf: serde_json::Value) -> Result<Box<dyn Strategy>> {
+    let options: Options = serde_json::from_value(conf)?;
+    Ok(Box::new(MarketMakingStrategy::try_new(&options)?))
+}
+
+inventory::submit! {
+    StrategyPlugin::new("market_making", provide_options::<Options>, provide_strat)
+}
+
+fn default_book() -> MarketChannelType { MarketChannelType::Quotes }
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Options {
+    pub exchange: Exchange,
+    pub pair: Pair,
+    /// Channel of the books the microprice is measured from, either best quotes or order books
+    #[serde(default = "default_book")]
+    pub book: MarketChannelType,
+    /// Distance of each quote to the reservation price, in basis points
+    pub half_spread_bps: f64,
+    /// Base quantity of each quote when the inventory is flat
+    pub qty: f64,
+    /// Shift of the reservation price at the maximum inventory, in basis points
+    #[serde(default)]
+    pub skew_bps: f64,
+    /// Inventory at which the skew is full, and the side that adds to the inventory is no longer quoted
+    pub max_inventory: f64,
+}
+
+impl StrategySettingsReplicator for Options {
+    fn replicate_for_pairs(&self, pairs: HashSet<Pair>) -> Vec<Value> {
+        pairs
+            .into_iter()
+            .map(|pair| {
+                let mut new = self.clone();
+                new.pair = pair;
+                serde_json::to_value(new).unwrap()
+            })
+            .collect()
+    }
+}
+
+impl StrategyOptions for Options {
+    fn key(&self) -> StrategyKey { StrategyKey("market_making".to_string(), self.pair.to_string()) }
+}
+
+pub struct MarketMakingStrategy {
+    exchange: Exchange,
+    pair: Pair,
+    book: MarketChannelType,
+    half_spread_bps: f64,
+    qty: f64,
+    skew_bps: f64,
+    max_inventory: f64,
+    last_microprice: Option<f64>,
+    last_reservation: Option<f64>,
+}
+
+impl MarketMakingStrategy {
+    pub fn try_new(options: &Options) -> Result<Self> {
+        if !matches!(options.book, MarketChannelType::Quotes | MarketChannelType::Orderbooks) {
+            return Err(Error::BadConfiguration(
+                "market making quotes from best quotes or order books".to_string(),
+            ));
+        }
+        if options.half_spread_bps <= 0.0 || options.qty <= 0.0 || options.max_inventory <= 0.0 {
+            return Err(Error::BadConfiguration(
+                "market making needs a positive spread, quantity and maximum inventory".to_string(),
+            ));
+        }
+        Ok(Self {
+            exchange: options.exchange,
+            pair: options.pair.clone(),
+            book: options.book,
+            half_spread_bps: options.half_spread_bps,
+            qty: options.qty,
+            skew_bps: options.skew_bps,
+            max_inventory: options.max_inventory,
+            last_microprice: None,
+            last_reservation: None,
+        })
+    }
+
+    /// Bid and ask around `microprice`, skewed by `inventory`
+    fn quotes(&mut self, microprice: f64, inventory: f64) -> (Option<QuoteLevel>, Option<QuoteLevel>) {
+        let ratio = (inventory / self.max_inventory).clamp(-1.0, 1.0);
+        let reservation = microprice * (1.0 - ratio * self.skew_bps / BPS);
+        let half_spread = reservation * self.half_spread_bps / BPS;
+        self.last_microprice = Some(microprice);
+        self.last_reservation = Some(reservation);
+        let level = |price: f64, qty: f64| Some(QuoteLevel { price, qty }).filter(|l| l.qty > 0.0);
+        (
+            level(reservation - half_spread, self.qty * (1.0 - ratio.max(0.0))),
+            level(reservation + half_spread, self.qty * (1.0 + ratio.min(0.0))),
+        )
+    }
+}
+
+#[async_trait]
+impl Strategy for MarketMakingStrategy {
+    fn key(&self) -> String { format!("market_making_{}_{}", self.exchange, self.pair) }
+
+    fn init(&mut self) -> Result<()> { Ok(()) }
+
+    async fn eval(&mut self, _e: &MarketEventEnvelope, _ctx: &DefaultStrategyContext) -> Result<Option<TradeSignals>> {
+        Ok(None)
+    }
+
+    fn quote(&mut self, le: &MarketEventEnvelope, ctx: &DefaultStrategyContext) -> Option<TwoSidedQuote> {
+        if le.symbol.xch != self.exchange || le.symbol.value != self.pair {
+            return None;
+        }
+        let microprice = match &le.e {
+            MarketEvent::Quote(quote) => microprice(&[(quote.bid, quote.bid_qty)], &[(quote.ask, quote.ask_qty)]),
+            MarketEvent::Orderbook(_) => ctx
+                .microstructure(self.exchange, self.pair.clone())
+                .and_then(|m| m.current)
+                .map(|values| values.microprice),
+            _ => None,
+        }?;
+        let inventory = ctx.inventory(self.exchange, self.pair.clone());
+        let (bid, ask) = self.quotes(microprice, inventory);
+        Some(TwoSidedQuote {
+            xch: self.exchange,
+            pair: self.pair.clone(),
+            bid,
+            ask,
+        })
+    }
+
+    fn model(&self) -> SerializedModel {
+        vec![
+            (
+                "microprice".to_string(),
+                self.last_microprice.and_then(|p| serde_json::to_value(p).ok()),
+            ),
+            (
+                "reservation".to_string(),
+                self.last_reservation.and_then(|p| serde_json::to_value(p).ok()),
+            ),
+        ]
+    }
+
+    fn channels(&self) -> HashSet<MarketChannel> {
+        vec![MarketChannel::builder()
+            .symbol(Symbol::new(self.pair.clone(), SecurityType::Crypto, self.exchange))
+            .r#type(self.book)
+            .build()]
+        .into_iter()
+        .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use trading::quoting::QuoteLevel;
+
+    use super::{MarketMakingStrategy, Options};
+
+    #[test]
+    fn skew_quotes_with_inventory() {
+        let options: Options = serde_json::from_value(serde_json::json!({
+            "exchange": "binance",
+            "pair": "BTC_USDT",
+            "half_spread_bps": 10.0,
+            "qty": 2.0,
+            "skew_bps": 20.0,
+            "max_inventory": 4.0
+        }))
+        .unwrap();
+        let mut mm = MarketMakingStrategy::try_new(&options).unwrap();
+        let (bid, ask) = mm.quotes(100.0, 0.0);
+        let (bid, ask): (QuoteLevel, QuoteLevel) = (bid.unwrap(), ask.unwrap());
+        assert!((bid.price - 99.9).abs() < 1e-9 && (ask.price - 100.1).abs() < 1e-9);
+        assert_eq!((bid.qty, ask.qty), (2.0, 2.0));
+
+        // Long inventory lowers the quotes and the bid quantity
+        let (bid, ask) = mm.quotes(100.0, 2.0);
+        assert!((mm.last_reservation.unwrap() - 99.9).abs() < 1e-9);
+        assert!((bid.unwrap().price - 99.8001).abs() < 1e-9);
+        assert_eq!(bid.unwrap().qty, 1.0);
+        assert_eq!(ask.unwrap().qty, 2.0);
+
+        // The side adding to a full inventory is not quoted
+        let (bid, ask) = mm.quotes(100.0, -4.0);
+        assert_eq!(bid.unwrap().qty, 2.0);
+        assert_eq!(ask, None);
+    }
+}
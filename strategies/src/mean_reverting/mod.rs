@@ -14,7 +14,7 @@ use portfolio::portfolio::Portfolio;
 use strategy::driver::{DefaultStrategyContext, Strategy, TradeSignals};
 use strategy::error::*;
 use strategy::models::io::{IterativeModel, SerializedModel};
-use strategy::models::Sampler;
+use strategy::models::{ModelMigration, Sampler};
 use strategy::plugin::{provide_options, StrategyPlugin, StrategyPluginContext};
 use strategy::prelude::*;
 use strategy::StratEventLoggerRef;
@@ -256,6 +256,8 @@ impl Strategy for MeanRevertingStrategy {
 
     fn model(&self) -> SerializedModel { self.model.values() }
 
+    fn migrations(&self) -> Vec<ModelMigration<'_>> { self.model.migrations() }
+
     fn channels(&self) -> HashSet<MarketChannel> {
         vec![MarketChannel::builder()
             .symbol(Symbol::new(self.pair.clone(), SecurityType::Crypto, self.exchange))
@@ -265,4 +267,6 @@ impl Strategy for MeanRevertingStrategy {
         .into_iter()
         .collect()
     }
+
+    fn order_conf(&self) -> Option<&OrderConf> { Some(&self.order_conf) }
 }
@@ -13,7 +13,7 @@ use stats::indicators::thresholds::Thresholds;
 use strategy::error::{Error, Result};
 use strategy::models::indicator_reducer::PersistentIndicatorReducer;
 use strategy::models::io::{IterativeModel, LoadableModel};
-use strategy::models::{IndicatorModel, Sampler, TimedValue, WindowedModel};
+use strategy::models::{IndicatorModel, ModelMigration, Sampler, TimedValue, WindowedModel};
 use strategy::prelude::*;
 use util::time::utc_zero;
 
@@ -128,6 +128,8 @@ impl MeanRevertingModel {
         Ok(())
     }
 
+    pub(crate) fn migrations(&self) -> Vec<ModelMigration<'_>> { vec![ModelMigration::of(self.ppo.key(), &self.ppo)] }
+
     pub(crate) fn values(&self) -> Vec<(String, Option<serde_json::Value>)> {
         vec![
             (
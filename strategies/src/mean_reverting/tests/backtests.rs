@@ -122,6 +122,7 @@ async fn margin_backtest() {
                 execution_instruction: None,
                 asset_type: AssetType::Margin,
                 dry_mode: true,
+                ..OrderConf::default()
             },
             ..Options::new_test_default(PAIR, exchange)
         };
@@ -5,6 +5,7 @@ use chrono::{DateTime, Duration, Utc};
 use itertools::Itertools;
 
 use db::Storage;
+use stats::cointegration::{engle_granger_with_lags, Cointegration};
 use stats::iter::{CovarianceExt, MeanExt, VarianceExt};
 use stats::Next;
 use strategy::error::*;
@@ -12,6 +13,9 @@ use strategy::models::{Model, PersistentReducer, Sampler, Window, WindowedModel}
 use trading::book::BookPosition;
 use util::time::{now, utc_zero};
 
+use super::kalman_model::{KalmanOptions, KalmanSpreadModel};
+use super::options::Options;
+
 #[derive(Debug, Copy, Clone, Serialize, Deserialize)]
 pub struct DualBookPosition {
     pub time: DateTime<Utc>,
@@ -63,12 +67,26 @@ pub fn predict(alpha: f64, beta: f64, value: f64) -> f64 { alpha + beta * value
 
 const LM_AGE_CUTOFF_RATIO: f64 = 0.0013;
 
+/// Periodic check that the pairs of the model are still cointegrated
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CointegrationCheckOptions {
+    /// Number of samples between two checks
+    pub every: usize,
+    /// Lagged differences of the unit root test of the spread
+    #[serde(default)]
+    pub lags: usize,
+}
+
 #[derive(Debug)]
 pub struct LinearSpreadModel {
     sampler: Sampler,
     linear_model: PersistentReducer<DualBookPosition, LinearModelValue>,
     last_sample_time_at_eval: DateTime<Utc>,
     beta_eval_freq: i32,
+    cointegration_check: Option<CointegrationCheckOptions>,
+    samples_since_check: usize,
+    /// Result of the last cointegration check, pairs are assumed cointegrated until checked
+    cointegrated: bool,
 }
 
 impl LinearSpreadModel {
@@ -84,9 +102,43 @@ impl LinearSpreadModel {
             linear_model: PersistentReducer::new(id, db, window_size, None, linear_model, None),
             last_sample_time_at_eval: utc_zero(),
             beta_eval_freq: eval_freq,
+            cointegration_check: None,
+            samples_since_check: 0,
+            cointegrated: true,
+        }
+    }
+
+    pub(super) fn with_cointegration_check(mut self, check: Option<CointegrationCheckOptions>) -> Self {
+        self.cointegration_check = check;
+        self
+    }
+
+    /// Test the cointegration of the pairs over the sample window with the Engle-Granger method, the right price
+    /// being modeled from the left price
+    ///
+    /// returns: none if the window is not filled or the test is degenerate, the last result is then kept
+    pub fn check_cointegration(&mut self) -> Option<Cointegration> {
+        self.samples_since_check = 0;
+        if !self.linear_model.is_filled() {
+            return None;
+        }
+        let (left, right): (Vec<f64>, Vec<f64>) = self.linear_model.window().map(|r| (r.left.mid, r.right.mid)).unzip();
+        let lags = self.cointegration_check.as_ref().map_or(0, |c| c.lags);
+        let cointegration = engle_granger_with_lags(&right, &left, lags)?;
+        if cointegration.is_cointegrated() != self.cointegrated {
+            info!(
+                cointegrated = cointegration.is_cointegrated(),
+                adf_statistic = cointegration.adf.statistic,
+                "cointegration of the spread changed"
+            );
         }
+        self.cointegrated = cointegration.is_cointegrated();
+        Some(cointegration)
     }
 
+    /// Whether the spread was stationary at the last cointegration check
+    pub(super) fn is_cointegrated(&self) -> bool { self.cointegrated }
+
     pub(super) fn should_eval(&self, event_time: DateTime<Utc>) -> bool {
         let model_time = self.last_sample_time_at_eval;
         let sample_freq = self.sampler.freq();
@@ -168,25 +220,138 @@ impl LinearSpreadModel {
     pub(super) fn reset(&mut self) -> Result<()> { self.linear_model.wipe() }
 
     pub(super) fn push(&mut self, input: DualBookPosition) { self.linear_model.push(input); }
+}
+
+/// Spread models of the right price from the left price
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SpreadModelOptions {
+    /// Least squares over a window of samples, re-evaluated every `beta_eval_freq` samples
+    #[default]
+    Linear,
+    /// Hedge ratio filtered at each sample
+    Kalman(KalmanOptions),
+}
+
+/// The spread model selected by the options of the strategy
+#[derive(Debug)]
+pub enum SpreadModel {
+    Linear(LinearSpreadModel),
+    Kalman(KalmanSpreadModel),
+}
+
+impl SpreadModel {
+    #[allow(clippy::cast_sign_loss)]
+    pub(super) fn new(db: Arc<dyn Storage>, id: &str, options: &Options) -> Self {
+        match options.spread_model {
+            SpreadModelOptions::Linear => Self::Linear(
+                LinearSpreadModel::new(
+                    db,
+                    id,
+                    options.window_size as usize,
+                    options.beta_sample_freq,
+                    options.beta_eval_freq,
+                )
+                .with_cointegration_check(options.cointegration_check.clone()),
+            ),
+            SpreadModelOptions::Kalman(kalman) => {
+                Self::Kalman(KalmanSpreadModel::new(db, id, options.beta_sample_freq, &kalman))
+            }
+        }
+    }
+
+    pub(super) fn value(&self) -> Option<LinearModelValue> {
+        match self {
+            Self::Linear(m) => m.value(),
+            Self::Kalman(m) => m.value(),
+        }
+    }
+
+    pub(super) fn beta(&self) -> Option<f64> {
+        match self {
+            Self::Linear(m) => m.beta(),
+            Self::Kalman(m) => m.value().map(|lm| lm.beta),
+        }
+    }
+
+    pub(super) fn predict(&self, current_price: f64) -> Option<f64> {
+        match self {
+            Self::Linear(m) => m.predict(current_price),
+            Self::Kalman(m) => m.value().map(|lm| predict(lm.alpha, lm.beta, current_price)),
+        }
+    }
+
+    pub(super) fn has_model(&self) -> bool {
+        match self {
+            Self::Linear(m) => m.has_model(),
+            Self::Kalman(m) => m.has_model(),
+        }
+    }
+
+    /// Whether new positions can be opened with the model, a linear model must be recent and its pairs
+    /// cointegrated while a filtered model is always up to date
+    pub(super) fn can_open(&self) -> bool {
+        match self {
+            Self::Linear(m) => m.is_obsolete() && m.is_cointegrated(),
+            Self::Kalman(_) => true,
+        }
+    }
+
+    pub(super) fn should_eval(&self, event_time: DateTime<Utc>) -> bool {
+        match self {
+            Self::Linear(m) => m.should_eval(event_time),
+            Self::Kalman(_) => false,
+        }
+    }
+
+    pub(super) fn update(&mut self) -> Result<()> {
+        match self {
+            Self::Linear(m) => m.update(),
+            Self::Kalman(_) => Ok(()),
+        }
+    }
+
+    pub fn try_load(&mut self) -> Result<()> {
+        match self {
+            Self::Linear(m) => m.try_load(),
+            Self::Kalman(m) => m.try_load(),
+        }
+    }
+
+    #[allow(dead_code)]
+    pub(super) fn reset(&mut self) -> Result<()> {
+        match self {
+            Self::Linear(m) => m.reset(),
+            Self::Kalman(m) => m.wipe(),
+        }
+    }
 
     pub(crate) fn serialized(&self) -> Vec<(String, Option<serde_json::Value>)> {
+        let value = self.value();
         vec![
             (
                 "beta".to_string(),
-                self.linear_model
-                    .value()
-                    .and_then(|v| serde_json::to_value(v.beta).ok()),
+                value.and_then(|v| serde_json::to_value(v.beta).ok()),
             ),
             (
                 "alpha".to_string(),
-                self.linear_model
-                    .value()
-                    .and_then(|v| serde_json::to_value(v.alpha).ok()),
+                value.and_then(|v| serde_json::to_value(v.alpha).ok()),
             ),
         ]
     }
 }
 
+impl Next<DualBookPosition> for SpreadModel {
+    type Output = Result<Option<LinearModelValue>>;
+
+    fn next(&mut self, input: DualBookPosition) -> Self::Output {
+        match self {
+            Self::Linear(m) => m.next(input),
+            Self::Kalman(m) => m.next(input),
+        }
+    }
+}
+
 impl Next<DualBookPosition> for LinearSpreadModel {
     type Output = Result<Option<LinearModelValue>>;
 
@@ -198,6 +363,55 @@ impl Next<DualBookPosition> for LinearSpreadModel {
         if self.linear_model.value().is_none() && self.linear_model.is_filled() {
             self.update()?;
         }
+        if let Some(every) = self.cointegration_check.as_ref().map(|c| c.every) {
+            self.samples_since_check += 1;
+            if self.samples_since_check >= every {
+                self.check_cointegration();
+            }
+        }
         Ok(self.linear_model.value())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use chrono::{Duration, TimeZone, Utc};
+    use uuid::Uuid;
+
+    use stats::test_util::random_walk;
+    use stats::Next;
+    use strategy_test_util::test_db;
+    use trading::book::BookPosition;
+
+    use super::{CointegrationCheckOptions, DualBookPosition, LinearSpreadModel};
+
+    #[test]
+    fn stop_when_no_longer_cointegrated() {
+        let mut model = LinearSpreadModel::new(test_db(), "left_right", 100, Duration::minutes(1), 10)
+            .with_cointegration_check(Some(CointegrationCheckOptions { every: 50, lags: 0 }));
+        let left = random_walk(1, 300);
+        // Cointegrated with the left price for 150 samples, then an independent random walk
+        let noise: Vec<f64> = random_walk(2, 151).windows(2).map(|w| w[1] - w[0]).collect();
+        let right: Vec<f64> = (0..150)
+            .map(|i| 2.0 * left[i] + 5.0 + noise[i])
+            .chain(random_walk(3, 150))
+            .collect();
+        let t0 = Utc.with_ymd_and_hms(2022, 1, 1, 0, 0, 0).unwrap();
+        let position = |time, price| BookPosition::new(Uuid::new_v4(), time, &[(price, 1.0)], &[(price, 1.0)]);
+        for (i, (l, r)) in left.iter().zip(&right).enumerate() {
+            let time = t0 + Duration::minutes(i as i64);
+            model
+                .next(DualBookPosition {
+                    time,
+                    left: position(time, *l),
+                    right: position(time, *r),
+                })
+                .unwrap();
+            if i == 149 {
+                assert!(model.is_cointegrated());
+                assert!(model.check_cointegration().unwrap().is_cointegrated());
+            }
+        }
+        assert!(!model.is_cointegrated());
+    }
+}
@@ -0,0 +1,178 @@
+//! Dynamic hedge ratio of a pair estimated with a Kalman filter : the state `(beta, alpha)` of
+//! `right = alpha + beta * left` is modeled as a random walk, and updated with each sample so that the model adapts
+//! continuously instead of being re-evaluated over a window.
+
+use std::sync::Arc;
+
+use chrono::Duration;
+
+use db::Storage;
+use stats::Next;
+use strategy::error::*;
+use strategy::models::{IndicatorModel, Model, Sampler};
+use util::time::utc_zero;
+
+use super::covar_model::{DualBookPosition, LinearModelValue};
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct KalmanOptions {
+    /// Variance of the random walk of the state relative to the state estimate, the higher the faster the model
+    /// adapts to changes of the hedge ratio
+    #[serde(default = "KalmanOptions::default_delta")]
+    pub delta: f64,
+    /// Variance of the observation noise, which is the spread
+    #[serde(default = "KalmanOptions::default_observation_variance")]
+    pub observation_variance: f64,
+    /// Number of samples filtered before the model is used
+    #[serde(default = "KalmanOptions::default_warmup")]
+    pub warmup: u64,
+}
+
+impl KalmanOptions {
+    fn default_delta() -> f64 { 1e-4 }
+
+    fn default_observation_variance() -> f64 { 1e-3 }
+
+    fn default_warmup() -> u64 { 100 }
+}
+
+impl Default for KalmanOptions {
+    fn default() -> Self {
+        Self {
+            delta: Self::default_delta(),
+            observation_variance: Self::default_observation_variance(),
+            warmup: Self::default_warmup(),
+        }
+    }
+}
+
+/// Kalman filter of the hedge ratio of a pair, inputs are `(left, right)` prices
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct KalmanHedgeRatio {
+    delta: f64,
+    observation_variance: f64,
+    /// Estimate of `(beta, alpha)`
+    pub state: [f64; 2],
+    /// Covariance of the state estimate
+    pub covariance: [[f64; 2]; 2],
+    /// Number of filtered samples
+    pub updates: u64,
+}
+
+impl KalmanHedgeRatio {
+    pub fn new(options: &KalmanOptions) -> Self {
+        Self {
+            delta: options.delta,
+            observation_variance: options.observation_variance,
+            state: [0.0, 0.0],
+            covariance: [[0.0; 2]; 2],
+            updates: 0,
+        }
+    }
+
+    pub fn beta(&self) -> f64 { self.state[0] }
+
+    pub fn alpha(&self) -> f64 { self.state[1] }
+}
+
+impl Next<(f64, f64)> for KalmanHedgeRatio {
+    /// The prediction error of the right price before the update
+    type Output = f64;
+
+    fn next(&mut self, (left, right): (f64, f64)) -> Self::Output {
+        let x = [left, 1.0];
+        // Predict : the state is unchanged and its covariance grows with the state noise
+        let state_noise = self.delta / (1.0 - self.delta);
+        let mut r = self.covariance;
+        r[0][0] += state_noise;
+        r[1][1] += state_noise;
+        // Update with the observation of the right price
+        let error = right - (x[0] * self.state[0] + x[1] * self.state[1]);
+        let rx = [r[0][0] * x[0] + r[0][1] * x[1], r[1][0] * x[0] + r[1][1] * x[1]];
+        let error_variance = x[0] * rx[0] + x[1] * rx[1] + self.observation_variance;
+        let gain = [rx[0] / error_variance, rx[1] / error_variance];
+        self.state = [self.state[0] + gain[0] * error, self.state[1] + gain[1] * error];
+        // R is symmetric so x'R is the transpose of Rx
+        for (i, row) in self.covariance.iter_mut().enumerate() {
+            for (j, cell) in row.iter_mut().enumerate() {
+                *cell = r[i][j] - gain[i] * rx[j];
+            }
+        }
+        self.updates += 1;
+        error
+    }
+}
+
+/// Spread model of a pair with a hedge ratio filtered at each sample, the filter state is persisted after each
+/// update
+#[derive(Debug)]
+pub struct KalmanSpreadModel {
+    sampler: Sampler,
+    filter: IndicatorModel<KalmanHedgeRatio, (f64, f64)>,
+    warmup: u64,
+}
+
+impl KalmanSpreadModel {
+    pub(super) fn new(db: Arc<dyn Storage>, id: &str, sample_freq: Duration, options: &KalmanOptions) -> Self {
+        Self {
+            sampler: Sampler::new(sample_freq, utc_zero()),
+            filter: IndicatorModel::new(&format!("{}_kalman", id), db, KalmanHedgeRatio::new(options)),
+            warmup: options.warmup,
+        }
+    }
+
+    /// The current hedge ratio, once the filter is warmed up
+    pub(super) fn value(&self) -> Option<LinearModelValue> {
+        self.filter
+            .value()
+            .filter(|k| k.updates >= self.warmup)
+            .map(|k| LinearModelValue {
+                beta: k.beta(),
+                alpha: k.alpha(),
+            })
+    }
+
+    pub(super) fn has_model(&self) -> bool { self.filter.is_loaded() && self.value().is_some() }
+
+    pub fn try_load(&mut self) -> Result<()> { self.filter.try_load() }
+
+    pub(super) fn wipe(&mut self) -> Result<()> { self.filter.wipe() }
+}
+
+impl Next<DualBookPosition> for KalmanSpreadModel {
+    type Output = Result<Option<LinearModelValue>>;
+
+    fn next(&mut self, input: DualBookPosition) -> Self::Output {
+        if !self.sampler.sample(input.time) {
+            return Ok(None);
+        }
+        self.filter.update((input.left.mid, input.right.mid))?;
+        Ok(self.value())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use stats::Next;
+
+    use super::{KalmanHedgeRatio, KalmanOptions};
+
+    #[test]
+    fn track_hedge_ratio() {
+        let mut filter = KalmanHedgeRatio::new(&KalmanOptions::default());
+        let prices: Vec<f64> = (0..2000).map(|i| 100.0 + 10.0 * (f64::from(i) / 50.0).sin()).collect();
+        let predicted = |filter: &KalmanHedgeRatio, left: f64| filter.alpha() + filter.beta() * left;
+        for left in &prices[..1000] {
+            filter.next((*left, 2.0 * left + 5.0));
+        }
+        assert!((filter.beta() - 2.0).abs() < 0.1);
+        assert!((predicted(&filter, prices[999]) - (2.0 * prices[999] + 5.0)).abs() < 1e-3);
+        // The hedge ratio changes, the filter follows it
+        for left in &prices[1000..] {
+            filter.next((*left, 1.5 * left + 5.0));
+        }
+        assert!((filter.beta() - 1.5).abs() < 0.1);
+        assert!((predicted(&filter, prices[1999]) - (1.5 * prices[1999] + 5.0)).abs() < 1e-3);
+        assert_eq!(filter.updates, 2000);
+    }
+}
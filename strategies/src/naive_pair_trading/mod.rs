@@ -26,12 +26,14 @@ use trading::stop::FixedStopper;
 use trading::types::OrderConf;
 use util::time::{now, TimedData};
 
-use self::covar_model::{DualBookPosition, LinearModelValue, LinearSpreadModel};
+use self::covar_model::{DualBookPosition, LinearModelValue, SpreadModel};
 use self::metrics::NaiveStrategyMetrics;
 
 pub mod covar_model;
+pub mod kalman_model;
 pub mod metrics;
 pub mod options;
+pub mod screening;
 
 #[cfg(test)]
 mod tests;
@@ -49,7 +51,7 @@ pub struct NaiveTradingStrategy {
     res_threshold_long: f64,
     res_threshold_short: f64,
     max_pos_duration: Duration,
-    model: LinearSpreadModel,
+    model: SpreadModel,
     right_pair: Pair,
     left_pair: Pair,
     metrics: Arc<NaiveStrategyMetrics>,
@@ -63,7 +65,6 @@ pub struct NaiveTradingStrategy {
 }
 
 impl NaiveTradingStrategy {
-    #[allow(clippy::cast_sign_loss)]
     pub fn new(
         db: Arc<dyn Storage>,
         strat_key: String,
@@ -72,13 +73,7 @@ impl NaiveTradingStrategy {
         logger: Option<StratEventLoggerRef>,
     ) -> Self {
         let metrics = NaiveStrategyMetrics::for_strat(prometheus::default_registry(), &n.left, &n.right);
-        let model = LinearSpreadModel::new(
-            db,
-            &format!("{}_{}", &n.left, &n.right),
-            n.window_size as usize,
-            n.beta_sample_freq,
-            n.beta_eval_freq,
-        );
+        let model = SpreadModel::new(db, &format!("{}_{}", &n.left, &n.right), n);
 
         Self {
             key: strat_key,
@@ -278,7 +273,8 @@ impl NaiveTradingStrategy {
 
     fn can_eval(&self, portfolio: &Portfolio) -> bool {
         let has_position = portfolio.has_any_open_position();
-        self.model.has_model() && (has_position || self.model.is_obsolete())
+        // Open positions are still closed once the spread is no longer stationary
+        self.model.has_model() && (has_position || self.model.can_open())
     }
 
     #[allow(dead_code)]
@@ -351,4 +347,6 @@ impl Strategy for NaiveTradingStrategy {
         .into_iter()
         .collect()
     }
+
+    fn order_conf(&self) -> Option<&OrderConf> { Some(&self.order_conf) }
 }
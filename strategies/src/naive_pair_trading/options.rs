@@ -8,6 +8,8 @@ use strategy::settings::{StrategyOptions, StrategySettingsReplicator};
 use strategy::StrategyKey;
 use trading::types::OrderConf;
 
+use super::covar_model::{CointegrationCheckOptions, SpreadModelOptions};
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Options {
     pub left: Pair,
@@ -31,6 +33,12 @@ pub struct Options {
         serialize_with = "util::ser::encode_duration_str_opt"
     )]
     pub max_pos_duration: Option<Duration>,
+    /// Stop opening positions when the pairs are no longer cointegrated
+    #[serde(default)]
+    pub cointegration_check: Option<CointegrationCheckOptions>,
+    /// Model of the spread, a linear model by default
+    #[serde(default)]
+    pub spread_model: SpreadModelOptions,
 }
 
 impl Options {
@@ -49,6 +57,8 @@ impl Options {
             initial_cap: 100.0,
             order_conf: OrderConf::default(),
             max_pos_duration: None,
+            cointegration_check: None,
+            spread_model: SpreadModelOptions::Linear,
         }
     }
 
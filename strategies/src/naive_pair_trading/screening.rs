@@ -0,0 +1,165 @@
+//! Screening of a universe of pairs for candidates to the naive spread strategy
+
+use std::cmp::Ordering;
+
+use brokers::prelude::*;
+use stats::cointegration::{engle_granger, Adf, Cointegration};
+use stats::correlation_matrix;
+
+use super::options::Options;
+
+/// A candidate pair for the naive spread strategy, the right price is modeled as `alpha + beta * left`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PairCandidate {
+    pub left: Pair,
+    pub right: Pair,
+    pub beta: f64,
+    /// Dickey-Fuller statistic of the spread, the more negative the stronger the cointegration
+    pub adf_statistic: f64,
+    /// Half-life of mean reversion of the spread, in sample periods
+    pub half_life: f64,
+    /// Correlation of the returns of both pairs
+    pub correlation: f64,
+}
+
+impl PairCandidate {
+    fn new(left: &Pair, right: &Pair, cointegration: &Cointegration, correlation: f64) -> Self {
+        Self {
+            left: left.clone(),
+            right: right.clone(),
+            beta: cointegration.beta,
+            adf_statistic: cointegration.adf.statistic,
+            half_life: cointegration.half_life,
+            correlation,
+        }
+    }
+
+    /// Strategy options for this candidate, positions are held at most for a few half-lives of the spread
+    /// sampled at `beta_sample_freq`
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn to_options(&self, template: &Options) -> Options {
+        let mut options = template.clone();
+        options.left = self.left.clone();
+        options.right = self.right.clone();
+        if self.half_life.is_finite() {
+            let periods = (self.half_life * MAX_POS_HALF_LIVES).ceil() as i32;
+            options.max_pos_duration = Some(template.beta_sample_freq * periods.max(1));
+        }
+        options
+    }
+}
+
+/// Number of half-lives after which a position is unlikely to revert
+const MAX_POS_HALF_LIVES: f64 = 4.0;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ScreeningOptions {
+    /// Pairs with a lower correlation of returns are not tested, all pairs are tested by default
+    pub min_correlation: f64,
+    /// Only keep pairs with a stationary spread at the 5% level
+    pub stationary_only: bool,
+}
+
+impl Default for ScreeningOptions {
+    fn default() -> Self {
+        Self {
+            min_correlation: -1.0,
+            stationary_only: false,
+        }
+    }
+}
+
+/// Rank the pairs of a universe of price series sampled at the same times, from the strongest cointegration to
+/// the weakest, ties are broken by the shortest half-life.
+///
+/// Both orientations of each pair are tested and the most cointegrated one is kept.
+pub fn screen_pairs(universe: &[(Pair, &[f64])], options: &ScreeningOptions) -> Vec<PairCandidate> {
+    let correlations = correlation_matrix(universe);
+    let mut candidates = vec![];
+    for i in 0..universe.len() {
+        for j in (i + 1)..universe.len() {
+            let correlation = correlations.get(i, j);
+            if correlation.is_nan() || correlation < options.min_correlation {
+                continue;
+            }
+            let (left, left_prices) = &universe[i];
+            let (right, right_prices) = &universe[j];
+            let candidate = match (
+                engle_granger(right_prices, left_prices),
+                engle_granger(left_prices, right_prices),
+            ) {
+                (Some(direct), Some(reverse)) if reverse.adf.statistic < direct.adf.statistic => {
+                    PairCandidate::new(right, left, &reverse, correlation)
+                }
+                (Some(direct), _) => PairCandidate::new(left, right, &direct, correlation),
+                (None, Some(reverse)) => PairCandidate::new(right, left, &reverse, correlation),
+                (None, None) => continue,
+            };
+            if options.stationary_only && candidate.adf_statistic >= Adf::CRITICAL_5PCT {
+                continue;
+            }
+            candidates.push(candidate);
+        }
+    }
+    candidates.sort_by(|a, b| {
+        a.adf_statistic
+            .total_cmp(&b.adf_statistic)
+            .then_with(|| a.half_life.partial_cmp(&b.half_life).unwrap_or(Ordering::Equal))
+    });
+    candidates
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::Duration;
+
+    use brokers::prelude::*;
+    use stats::test_util::{noise, random_walk};
+
+    use crate::naive_pair_trading::options::Options;
+    use crate::naive_pair_trading::screening::{screen_pairs, ScreeningOptions};
+
+    #[test]
+    fn cointegrated_pair_ranks_first() {
+        let btc = random_walk(1, 1000);
+        // Mean reverting spread around twice btc
+        let mut spread = 0.0;
+        let eth: Vec<f64> = btc
+            .iter()
+            .zip(noise(2, 1000))
+            .map(|(price, e)| {
+                spread = 0.8 * spread + e;
+                2.0 * price + spread
+            })
+            .collect();
+        let xrp = random_walk(3, 1000);
+        let ada = random_walk(4, 1000);
+        let universe: Vec<(Pair, &[f64])> = vec![
+            ("XRP_USDT".into(), xrp.as_slice()),
+            ("BTC_USDT".into(), btc.as_slice()),
+            ("ADA_USDT".into(), ada.as_slice()),
+            ("ETH_USDT".into(), eth.as_slice()),
+        ];
+        let candidates = screen_pairs(&universe, &ScreeningOptions::default());
+        assert_eq!(candidates.len(), 6);
+        let best = &candidates[0];
+        let pairs = [best.left.clone(), best.right.clone()];
+        assert!(pairs.contains(&"BTC_USDT".into()) && pairs.contains(&"ETH_USDT".into()));
+        assert!(best.half_life > 1.0 && best.half_life < 10.0);
+        assert!(candidates[1].adf_statistic > best.adf_statistic);
+
+        let stationary = screen_pairs(&universe, &ScreeningOptions {
+            stationary_only: true,
+            ..ScreeningOptions::default()
+        });
+        assert_eq!(stationary.len(), 1);
+
+        let template = Options::new_test_default(Exchange::Binance, "".into(), "".into());
+        let options = best.to_options(&template);
+        assert_eq!(options.left, best.left);
+        assert_eq!(options.right, best.right);
+        let max_pos_duration = options.max_pos_duration.unwrap();
+        assert!(max_pos_duration > Duration::minutes(4) && max_pos_duration < Duration::minutes(41));
+        assert!(serde_json::to_value(options).is_ok());
+    }
+}
@@ -142,6 +142,7 @@ async fn margin_backtest() {
                 execution_instruction: None,
                 asset_type: AssetType::Margin,
                 dry_mode: true,
+                ..OrderConf::default()
             },
             ..Options::new_test_default(exchange, LEFT_PAIR.into(), RIGHT_PAIR.into())
         };
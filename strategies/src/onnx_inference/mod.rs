@@ -0,0 +1,163 @@
+//! An example of a strategy driven by a model trained outside of the platform : an ONNX model scores the features
+//! of recent candles, and a long position is held while the score is above a threshold.
+//!
+//! The model takes a `[1, lookback * FEATURES_PER_CANDLE]` input laid out as described in
+//! [`CandleFeatures`], and its first output is the score.
+
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+use serde_json::Value;
+
+use brokers::prelude::*;
+use brokers::types::{MarketChannel, MarketChannelType, SecurityType, Symbol};
+use stats::kline::Resolution;
+use stats::Next;
+use strategy::driver::{DefaultStrategyContext, Strategy, TradeSignals};
+use strategy::error::*;
+use strategy::models::features::CandleFeatures;
+use strategy::models::io::SerializedModel;
+use strategy::models::onnx::OnnxModel;
+use strategy::plugin::{provide_options, StrategyPlugin, StrategyPluginContext};
+use strategy::settings::{StrategyOptions, StrategySettingsReplicator};
+use strategy::StrategyKey;
+use trading::position::{OperationKind, PositionKind};
+use trading::signal::new_trade_signal;
+use trading::types::OrderConf;
+
+pub fn provide_strat(_name: &str, _ctx: StrategyPluginContext, conf: serde_json::Value) -> Result<Box<dyn Strategy>> {
+    let options: Options = serde_json::from_value(conf)?;
+    Ok(Box::new(OnnxInferenceStrategy::try_new(&options)?))
+}
+
+inventory::submit! {
+    StrategyPlugin::new("onnx_inference", provide_options::<Options>, provide_strat)
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Options {
+    pub exchange: Exchange,
+    pub pair: Pair,
+    pub resolution: Resolution,
+    /// Path of the ONNX model file
+    pub model_path: PathBuf,
+    /// Number of candles of the features
+    pub lookback: usize,
+    /// Open a long position when the score is above this threshold
+    pub threshold_open: f64,
+    /// Close the long position when the score is below this threshold
+    pub threshold_close: f64,
+    #[serde(default)]
+    pub order_conf: OrderConf,
+}
+
+impl StrategySettingsReplicator for Options {
+    fn replicate_for_pairs(&self, pairs: HashSet<Pair>) -> Vec<Value> {
+        pairs
+            .into_iter()
+            .map(|pair| {
+                let mut new = self.clone();
+                new.pair = pair;
+                serde_json::to_value(new).unwrap()
+            })
+            .collect()
+    }
+}
+
+impl StrategyOptions for Options {
+    fn key(&self) -> StrategyKey { StrategyKey("onnx_inference".to_string(), self.pair.to_string()) }
+}
+
+pub struct OnnxInferenceStrategy {
+    exchange: Exchange,
+    pair: Pair,
+    resolution: Resolution,
+    model: OnnxModel,
+    features: CandleFeatures,
+    threshold_open: f64,
+    threshold_close: f64,
+    order_conf: OrderConf,
+    last_score: Option<f32>,
+}
+
+impl OnnxInferenceStrategy {
+    pub fn try_new(options: &Options) -> Result<Self> {
+        if options.lookback == 0 {
+            return Err(Error::BadConfiguration("lookback should be > 0".to_string()));
+        }
+        let features = CandleFeatures::new(options.lookback);
+        Ok(Self {
+            exchange: options.exchange,
+            pair: options.pair.clone(),
+            resolution: options.resolution,
+            model: OnnxModel::load(&options.model_path, features.len())?,
+            features,
+            threshold_open: options.threshold_open,
+            threshold_close: options.threshold_close,
+            order_conf: options.order_conf.clone(),
+            last_score: None,
+        })
+    }
+}
+
+#[async_trait]
+impl Strategy for OnnxInferenceStrategy {
+    fn key(&self) -> String { format!("onnx_inference_{}_{}", self.exchange, self.pair) }
+
+    fn init(&mut self) -> Result<()> { Ok(()) }
+
+    async fn eval(&mut self, le: &MarketEventEnvelope, ctx: &DefaultStrategyContext) -> Result<Option<TradeSignals>> {
+        let MarketEvent::TradeCandle(candle) = &le.e else {
+            return Ok(None);
+        };
+        let Some(features) = self.features.next(candle) else {
+            return Ok(None);
+        };
+        let Some(score) = self.model.predict(&features)?.first().copied() else {
+            return Ok(None);
+        };
+        self.last_score = Some(score);
+        let score = f64::from(score);
+        let is_long = ctx
+            .portfolio
+            .open_position(self.exchange, self.pair.clone())
+            .map_or(false, |p| p.is_long());
+        let op = match (is_long, score) {
+            (false, score) if score > self.threshold_open => OperationKind::Open,
+            (true, score) if score < self.threshold_close => OperationKind::Close,
+            _ => return Ok(None),
+        };
+        let mut signals = TradeSignals::new();
+        signals.push(new_trade_signal(
+            self.pair.clone(),
+            self.exchange,
+            &self.order_conf,
+            le.e.time(),
+            le.trace_id,
+            op,
+            PositionKind::Long,
+            candle.close,
+            None,
+        ));
+        Ok(Some(signals))
+    }
+
+    fn model(&self) -> SerializedModel {
+        vec![(
+            "score".to_string(),
+            self.last_score.and_then(|s| serde_json::to_value(s).ok()),
+        )]
+    }
+
+    fn channels(&self) -> HashSet<MarketChannel> {
+        vec![MarketChannel::builder()
+            .symbol(Symbol::new(self.pair.clone(), SecurityType::Crypto, self.exchange))
+            .r#type(MarketChannelType::Candles)
+            .resolution(Some(self.resolution))
+            .build()]
+        .into_iter()
+        .collect()
+    }
+
+    fn order_conf(&self) -> Option<&OrderConf> { Some(&self.order_conf) }
+}
@@ -0,0 +1,384 @@
+//! A baseline portfolio rebalancing strategy : long positions are held in a set of assets at target weights of the
+//! portfolio value, and rebalanced when the weights drift past a threshold or on a schedule.
+//!
+//! Positions can only be opened or closed in full, so a rebalance closes the drifted legs that are held and opens
+//! the ones that are not, in a single batch of signals. Closed legs are reopened at their target weight once the
+//! portfolio is unlocked. Legs within `min_drift` of their target are left untouched.
+
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+use chrono::{DateTime, Duration, Utc};
+use serde_json::Value;
+
+use brokers::prelude::*;
+use brokers::types::{MarketChannel, MarketChannelType, SecurityType, Symbol};
+use portfolio::portfolio::Portfolio;
+use stats::kline::Resolution;
+use strategy::driver::{DefaultStrategyContext, Strategy, TradeSignals};
+use strategy::error::*;
+use strategy::models::io::SerializedModel;
+use strategy::plugin::{provide_options, StrategyPlugin, StrategyPluginContext};
+use strategy::settings::{StrategyOptions, StrategySettingsReplicator};
+use strategy::StrategyKey;
+use trading::position::{OperationKind, PositionKind};
+use trading::signal::new_trade_signal;
+use trading::types::OrderConf;
+
+pub fn provide_strat(name: &str, _ctx: StrategyPluginContext, conf: serde_json::Value) -> Result<Box<dyn Strategy>> {
+    let options: Options = serde_json::from_value(conf)?;
+    Ok(Box::new(RebalancingStrategy::try_new(name.to_string(), &options)?))
+}
+
+inventory::submit! {
+    StrategyPlugin::new("rebalancing", provide_options::<Options>, provide_strat)
+}
+
+fn default_min_drift() -> f64 { 0.01 }
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Options {
+    pub name: String,
+    pub exchange: Exchange,
+    /// Target weight of each pair in the portfolio value, the remainder is held in the quote asset
+    pub weights: BTreeMap<Pair, f64>,
+    /// Rebalance when the weight of a pair drifts from its target by more than this
+    #[serde(default)]
+    pub drift_threshold: Option<f64>,
+    /// Rebalance at this interval, whatever the drift
+    #[serde(
+        deserialize_with = "util::ser::string_duration_chrono_opt",
+        serialize_with = "util::ser::encode_duration_str_opt"
+    )]
+    #[serde(default)]
+    pub rebalance_every: Option<Duration>,
+    /// Legs that drifted less than this from their target are not traded by a rebalance
+    #[serde(default = "default_min_drift")]
+    pub min_drift: f64,
+    /// Resolution of the candles pricing the pairs
+    pub resolution: Resolution,
+    #[serde(default)]
+    pub order_conf: OrderConf,
+}
+
+impl StrategySettingsReplicator for Options {
+    /// The pairs of a rebalanced portfolio are fixed by its weights, so it is never replicated per pair
+    fn replicate_for_pairs(&self, _pairs: HashSet<Pair>) -> Vec<Value> { vec![serde_json::to_value(self).unwrap()] }
+}
+
+impl StrategyOptions for Options {
+    fn key(&self) -> StrategyKey { StrategyKey("rebalancing".to_string(), self.name.clone()) }
+}
+
+/// A trade of a rebalance, opened legs are sized to their target weight and closed legs are closed in full
+#[derive(Clone, Debug, PartialEq)]
+pub struct RebalanceLeg {
+    pub pair: Pair,
+    pub op: OperationKind,
+    pub qty: Option<f64>,
+}
+
+/// Value of the held legs and cash of the portfolio
+#[derive(Clone, Debug, Default)]
+pub struct Allocation {
+    pub holdings: BTreeMap<Pair, f64>,
+    pub cash: f64,
+}
+
+impl Allocation {
+    pub fn total(&self) -> f64 { self.cash + self.holdings.values().sum::<f64>() }
+
+    /// Weight of a pair in the total value, zero if not held
+    pub fn weight(&self, pair: &Pair) -> f64 {
+        let total = self.total();
+        if total <= 0.0 {
+            return 0.0;
+        }
+        self.holdings.get(pair).copied().unwrap_or(0.0) / total
+    }
+}
+
+pub struct RebalancingStrategy {
+    key: String,
+    exchange: Exchange,
+    weights: BTreeMap<Pair, f64>,
+    drift_threshold: Option<f64>,
+    rebalance_every: Option<Duration>,
+    min_drift: f64,
+    resolution: Resolution,
+    order_conf: OrderConf,
+    /// Latest close of each pair
+    prices: BTreeMap<Pair, f64>,
+    last_rebalance: Option<DateTime<Utc>>,
+    /// Legs closed by the last rebalance, reopened at their target weight once closed
+    reopen: BTreeSet<Pair>,
+    max_drift: Option<f64>,
+}
+
+impl RebalancingStrategy {
+    pub fn try_new(key: String, options: &Options) -> Result<Self> {
+        if options.weights.is_empty() || options.weights.values().any(|w| !(0.0..=1.0).contains(w)) {
+            return Err(Error::BadConfiguration(
+                "rebalancing weights should be in [0, 1] for at least one pair".to_string(),
+            ));
+        }
+        if options.weights.values().sum::<f64>() > 1.0 + f64::EPSILON {
+            return Err(Error::BadConfiguration(
+                "rebalancing weights should sum to at most 1".to_string(),
+            ));
+        }
+        if options.drift_threshold.is_none() && options.rebalance_every.is_none() {
+            return Err(Error::BadConfiguration(
+                "either a drift threshold or a rebalancing interval is required".to_string(),
+            ));
+        }
+        Ok(Self {
+            key,
+            exchange: options.exchange,
+            weights: options.weights.clone(),
+            drift_threshold: options.drift_threshold,
+            rebalance_every: options.rebalance_every,
+            min_drift: options.min_drift.max(0.0),
+            resolution: options.resolution,
+            order_conf: options.order_conf.clone(),
+            prices: BTreeMap::new(),
+            last_rebalance: None,
+            reopen: BTreeSet::new(),
+            max_drift: None,
+        })
+    }
+
+    /// The held long positions in the rebalanced pairs
+    fn allocation(&self, portfolio: &Portfolio) -> Allocation {
+        Allocation {
+            holdings: self
+                .weights
+                .keys()
+                .filter_map(|pair| {
+                    let pos = portfolio.open_position(self.exchange, pair.clone())?;
+                    (pos.is_long() && pos.is_opened()).then(|| (pair.clone(), pos.current_value_gross()))
+                })
+                .collect(),
+            cash: portfolio.value(),
+        }
+    }
+
+    fn open_leg(&self, pair: &Pair, total: f64) -> Option<RebalanceLeg> {
+        let target = self.weights.get(pair).copied().unwrap_or(0.0);
+        let price = self.prices.get(pair).copied()?;
+        (target > 0.0 && price > 0.0).then(|| RebalanceLeg {
+            pair: pair.clone(),
+            op: OperationKind::Open,
+            qty: Some(target * total / price),
+        })
+    }
+
+    /// The legs to trade at `at`, none unless a rebalance is due or closed legs are waiting to be reopened
+    pub fn rebalance(&mut self, allocation: &Allocation, at: DateTime<Utc>) -> Vec<RebalanceLeg> {
+        let total = allocation.total();
+        if !self.reopen.is_empty() {
+            if self.reopen.iter().any(|pair| allocation.holdings.contains_key(pair)) {
+                return vec![];
+            }
+            let reopen = std::mem::take(&mut self.reopen);
+            return reopen.iter().filter_map(|pair| self.open_leg(pair, total)).collect();
+        }
+        let drifts: Vec<(Pair, f64)> = self
+            .weights
+            .iter()
+            .map(|(pair, target)| (pair.clone(), (allocation.weight(pair) - target).abs()))
+            .collect();
+        let max_drift = drifts.iter().map(|(_, drift)| *drift).fold(0.0, f64::max);
+        self.max_drift = Some(max_drift);
+        let drifted = self.drift_threshold.map_or(false, |threshold| max_drift > threshold);
+        let scheduled = self.rebalance_every.map_or(false, |every| {
+            self.last_rebalance.map_or(true, |last| at - last >= every)
+        });
+        if !drifted && !scheduled {
+            return vec![];
+        }
+        self.last_rebalance = Some(at);
+        let mut legs = vec![];
+        for (pair, drift) in drifts {
+            if drift <= self.min_drift {
+                continue;
+            }
+            if allocation.holdings.contains_key(&pair) {
+                self.reopen.insert(pair.clone());
+                legs.push(RebalanceLeg {
+                    pair,
+                    op: OperationKind::Close,
+                    qty: None,
+                });
+            } else if let Some(leg) = self.open_leg(&pair, total) {
+                legs.push(leg);
+            }
+        }
+        legs
+    }
+}
+
+#[async_trait]
+impl Strategy for RebalancingStrategy {
+    fn key(&self) -> String { self.key.clone() }
+
+    fn init(&mut self) -> Result<()> { Ok(()) }
+
+    async fn eval(&mut self, le: &MarketEventEnvelope, ctx: &DefaultStrategyContext) -> Result<Option<TradeSignals>> {
+        let MarketEvent::TradeCandle(candle) = &le.e else {
+            return Ok(None);
+        };
+        if le.symbol.xch != self.exchange || !self.weights.contains_key(&le.symbol.value) {
+            return Ok(None);
+        }
+        self.prices.insert(le.symbol.value.clone(), candle.close);
+        // Every pair is priced before the first rebalance, and orders of the previous one are resolved
+        if self.prices.len() < self.weights.len() || !ctx.portfolio.locks().is_empty() {
+            return Ok(None);
+        }
+        let at = le.e.time();
+        let allocation = self.allocation(ctx.portfolio);
+        let legs = self.rebalance(&allocation, at);
+        if legs.is_empty() {
+            return Ok(None);
+        }
+        let signals: TradeSignals = legs
+            .into_iter()
+            .map(|leg| {
+                let price = self.prices[&leg.pair];
+                new_trade_signal(
+                    leg.pair,
+                    self.exchange,
+                    &self.order_conf,
+                    at,
+                    le.trace_id,
+                    leg.op,
+                    PositionKind::Long,
+                    price,
+                    leg.qty,
+                )
+            })
+            .collect();
+        Ok(Some(signals))
+    }
+
+    fn model(&self) -> SerializedModel {
+        vec![
+            (
+                "max_drift".to_string(),
+                self.max_drift.and_then(|d| serde_json::to_value(d).ok()),
+            ),
+            (
+                "last_rebalance".to_string(),
+                self.last_rebalance.and_then(|at| serde_json::to_value(at).ok()),
+            ),
+        ]
+    }
+
+    fn channels(&self) -> HashSet<MarketChannel> {
+        self.weights
+            .keys()
+            .map(|pair| {
+                MarketChannel::builder()
+                    .symbol(Symbol::new(pair.clone(), SecurityType::Crypto, self.exchange))
+                    .r#type(MarketChannelType::Candles)
+                    .resolution(Some(self.resolution))
+                    .build()
+            })
+            .collect()
+    }
+
+    fn order_conf(&self) -> Option<&OrderConf> { Some(&self.order_conf) }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use chrono::{Duration, TimeZone, Utc};
+
+    use trading::position::OperationKind;
+
+    use super::{Allocation, Options, RebalanceLeg, RebalancingStrategy};
+
+    fn options(drift_threshold: Option<f64>, rebalance_every: Option<&str>) -> Options {
+        serde_json::from_value(serde_json::json!({
+            "name": "btc_eth",
+            "exchange": "binance",
+            "weights": {"BTC_USDT": 0.6, "ETH_USDT": 0.4},
+            "drift_threshold": drift_threshold,
+            "rebalance_every": rebalance_every,
+            "resolution": {"time_unit": "minute", "units": 1}
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn rebalance_drifted_legs() {
+        assert!(RebalancingStrategy::try_new("r".to_string(), &options(None, None)).is_err());
+        let mut strat = RebalancingStrategy::try_new("r".to_string(), &options(Some(0.05), None)).unwrap();
+        strat.prices = BTreeMap::from([("BTC_USDT".into(), 100.0), ("ETH_USDT".into(), 10.0)]);
+        let at = Utc.with_ymd_and_hms(2022, 1, 1, 0, 0, 0).unwrap();
+
+        // Nothing is held, both legs are opened at their target weight
+        let empty = Allocation {
+            holdings: BTreeMap::new(),
+            cash: 1000.0,
+        };
+        assert_eq!(strat.rebalance(&empty, at), vec![
+            RebalanceLeg {
+                pair: "BTC_USDT".into(),
+                op: OperationKind::Open,
+                qty: Some(6.0),
+            },
+            RebalanceLeg {
+                pair: "ETH_USDT".into(),
+                op: OperationKind::Open,
+                qty: Some(40.0),
+            },
+        ]);
+
+        // Within the threshold
+        let balanced = Allocation {
+            holdings: BTreeMap::from([("BTC_USDT".into(), 620.0), ("ETH_USDT".into(), 380.0)]),
+            cash: 0.0,
+        };
+        assert!(strat.rebalance(&balanced, at).is_empty());
+
+        // BTC rallied to 70% of the portfolio, both legs are closed then reopened
+        let drifted = Allocation {
+            holdings: BTreeMap::from([("BTC_USDT".into(), 700.0), ("ETH_USDT".into(), 300.0)]),
+            cash: 0.0,
+        };
+        let legs = strat.rebalance(&drifted, at);
+        assert_eq!(legs.len(), 2);
+        assert!(legs.iter().all(|leg| leg.op == OperationKind::Close));
+        // Still held, waiting for the closes
+        assert!(strat.rebalance(&drifted, at).is_empty());
+        let closed = Allocation {
+            holdings: BTreeMap::new(),
+            cash: 1000.0,
+        };
+        let reopened = strat.rebalance(&closed, at);
+        assert_eq!(reopened.len(), 2);
+        assert!(reopened.iter().all(|leg| leg.op == OperationKind::Open));
+    }
+
+    #[test]
+    fn rebalance_on_schedule() {
+        let mut strat = RebalancingStrategy::try_new("r".to_string(), &options(None, Some("1d"))).unwrap();
+        strat.prices = BTreeMap::from([("BTC_USDT".into(), 100.0), ("ETH_USDT".into(), 10.0)]);
+        let at = Utc.with_ymd_and_hms(2022, 1, 1, 0, 0, 0).unwrap();
+        // Only the drifted leg that is not held is traded
+        let allocation = Allocation {
+            holdings: BTreeMap::from([("BTC_USDT".into(), 600.0)]),
+            cash: 400.0,
+        };
+        assert_eq!(strat.rebalance(&allocation, at), vec![RebalanceLeg {
+            pair: "ETH_USDT".into(),
+            op: OperationKind::Open,
+            qty: Some(40.0),
+        }]);
+        assert!(strat.rebalance(&allocation, at + Duration::hours(12)).is_empty());
+        assert_eq!(strat.rebalance(&allocation, at + Duration::days(1)).len(), 1);
+    }
+}
@@ -469,6 +469,8 @@ impl Strategy for StochRsiStrategy {
         .into_iter()
         .collect()
     }
+
+    fn order_conf(&self) -> Option<&OrderConf> { Some(&self.order_conf) }
 }
 
 fn convert_candle(broker_candle: &Candle) -> stats::kline::Candle {
@@ -0,0 +1,311 @@
+//! A triangular arbitrage strategy : the best quotes of three pairs of an exchange that form a cycle of assets are
+//! monitored, and when converting an amount of the start asset around the cycle returns more than the fees and a
+//! threshold, the three legs are emitted together as an order batch.
+//!
+//! Legs are immediate or cancel limit orders at the quoted prices, sized within the quoted quantities, the latencies
+//! of the batches reported by the driver tell whether opportunities can be caught before they vanish. Exchanges do
+//! not execute batches atomically, the driver unwinds the legs of a batch that did not execute in full.
+
+use std::collections::{HashMap, HashSet};
+
+use itertools::Itertools;
+use serde_json::Value;
+use uuid::Uuid;
+
+use brokers::prelude::*;
+use brokers::types::{MarketChannel, MarketChannelType, Quote, SecurityType, Symbol};
+use strategy::driver::{DefaultStrategyContext, Strategy, TradeSignals};
+use strategy::error::*;
+use strategy::models::io::SerializedModel;
+use strategy::plugin::{provide_options, StrategyPlugin, StrategyPluginContext};
+use strategy::settings::{StrategyOptions, StrategySettingsReplicator};
+use strategy::StrategyKey;
+use trading::signal::OrderBatch;
+use trading::types::OrderConf;
+
+pub fn provide_strat(_name: &str, _ctx: StrategyPluginContext, conf: serde_json::Value) -> Result<Box<dyn Strategy>> {
+    let options: Options = serde_json::from_value(conf)?;
+    Ok(Box::new(TriangularArbitrageStrategy::try_new(&options)?))
+}
+
+inventory::submit! {
+    StrategyPlugin::new("triangular_arbitrage", provide_options::<Options>, provide_strat)
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Options {
+    pub exchange: Exchange,
+    /// The three pairs of the cycle
+    pub pairs: Vec<Pair>,
+    /// Asset the cycle starts and ends with
+    pub start_asset: String,
+    /// Maximum amount of the start asset converted around the cycle, less is converted if the best quotes are thinner
+    pub amount: f64,
+    /// Taker fee rate of each leg
+    pub fee_rate: f64,
+    /// Minimum return of a cycle after fees
+    #[serde(default)]
+    pub threshold: f64,
+    #[serde(default)]
+    pub order_conf: OrderConf,
+}
+
+impl StrategySettingsReplicator for Options {
+    fn replicate_for_pairs(&self, _pairs: HashSet<Pair>) -> Vec<Value> { vec![serde_json::to_value(self).unwrap()] }
+}
+
+impl StrategyOptions for Options {
+    fn key(&self) -> StrategyKey { StrategyKey("triangular_arbitrage".to_string(), self.pairs.iter().join("-")) }
+}
+
+/// A conversion of a cycle, buying the base of the pair with its quote or selling the base for the quote
+#[derive(Clone, Debug, PartialEq)]
+struct Leg {
+    pair: Pair,
+    side: TradeType,
+}
+
+/// Price and base quantity of each leg of a cycle, and its return after fees
+#[derive(Clone, Debug, PartialEq)]
+struct Opportunity {
+    legs: Vec<(Pair, TradeType, f64, f64)>,
+    net_return: f64,
+}
+
+fn assets(pair: &Pair) -> Option<(&str, &str)> { pair.split_once('_') }
+
+pub struct TriangularArbitrageStrategy {
+    exchange: Exchange,
+    pairs: Vec<Pair>,
+    amount: f64,
+    fee_rate: f64,
+    threshold: f64,
+    order_conf: OrderConf,
+    /// Both directions of the cycle
+    cycles: Vec<Vec<Leg>>,
+    /// Best bid, ask and their quantities of each pair
+    quotes: HashMap<Pair, Quote>,
+    last_return: Option<f64>,
+}
+
+impl TriangularArbitrageStrategy {
+    pub fn try_new(options: &Options) -> Result<Self> {
+        if options.amount <= 0.0 {
+            return Err(Error::BadConfiguration("arbitrage needs a positive amount".to_string()));
+        }
+        let cycles = cycles(&options.pairs, &options.start_asset);
+        if options.pairs.len() != 3 || cycles.is_empty() {
+            return Err(Error::BadConfiguration(format!(
+                "pairs {:?} do not form a cycle from {}",
+                options.pairs, options.start_asset
+            )));
+        }
+        Ok(Self {
+            exchange: options.exchange,
+            pairs: options.pairs.clone(),
+            amount: options.amount,
+            fee_rate: options.fee_rate,
+            threshold: options.threshold,
+            order_conf: options.order_conf.clone(),
+            cycles,
+            quotes: HashMap::default(),
+            last_return: None,
+        })
+    }
+
+    /// Convert the amount around a cycle at the current quotes, none until every pair is quoted, the legs are scaled
+    /// down so that none exceeds the quantity of its quote
+    fn convert(&self, cycle: &[Leg]) -> Option<Opportunity> {
+        let mut amount = self.amount;
+        let mut legs = vec![];
+        let mut scale = 1.0_f64;
+        for leg in cycle {
+            let quote = self.quotes.get(&leg.pair)?;
+            let (price, qty, available) = match leg.side {
+                TradeType::Buy => (quote.ask, amount / quote.ask, quote.ask_qty),
+                TradeType::Sell => (quote.bid, amount, quote.bid_qty),
+            };
+            if price <= 0.0 || available <= 0.0 {
+                return None;
+            }
+            scale = scale.min(available / qty);
+            amount = match leg.side {
+                TradeType::Buy => qty,
+                TradeType::Sell => qty * price,
+            } * (1.0 - self.fee_rate);
+            legs.push((leg.pair.clone(), leg.side, price, qty));
+        }
+        // Conversions are linear, so the return of the cycle does not depend on its size
+        for leg in &mut legs {
+            leg.3 *= scale;
+        }
+        Some(Opportunity {
+            legs,
+            net_return: amount / self.amount - 1.0,
+        })
+    }
+
+    /// The most profitable direction of the cycle, if its return exceeds the threshold
+    fn opportunity(&mut self) -> Option<Opportunity> {
+        let best = self
+            .cycles
+            .iter()
+            .filter_map(|cycle| self.convert(cycle))
+            .max_by(|a, b| a.net_return.total_cmp(&b.net_return))?;
+        self.last_return = Some(best.net_return);
+        (best.net_return > self.threshold).then_some(best)
+    }
+}
+
+/// Orderings of the pairs that convert `start` back to itself, with the side of each conversion
+fn cycles(pairs: &[Pair], start: &str) -> Vec<Vec<Leg>> {
+    pairs
+        .iter()
+        .permutations(pairs.len())
+        .filter_map(|ordering| {
+            let mut asset = start;
+            let mut legs = vec![];
+            for pair in ordering {
+                let (base, quote) = assets(pair)?;
+                let side = if quote == asset {
+                    asset = base;
+                    TradeType::Buy
+                } else if base == asset {
+                    asset = quote;
+                    TradeType::Sell
+                } else {
+                    return None;
+                };
+                legs.push(Leg {
+                    pair: pair.clone(),
+                    side,
+                });
+            }
+            (asset == start).then_some(legs)
+        })
+        .collect()
+}
+
+#[async_trait]
+impl Strategy for TriangularArbitrageStrategy {
+    fn key(&self) -> String { format!("triangular_arbitrage_{}_{}", self.exchange, self.pairs.iter().join("-")) }
+
+    fn init(&mut self) -> Result<()> { Ok(()) }
+
+    async fn eval(&mut self, _e: &MarketEventEnvelope, _ctx: &DefaultStrategyContext) -> Result<Option<TradeSignals>> {
+        Ok(None)
+    }
+
+    fn order_batch(&mut self, le: &MarketEventEnvelope, _ctx: &DefaultStrategyContext) -> Option<OrderBatch> {
+        let MarketEvent::Quote(quote) = &le.e else {
+            return None;
+        };
+        if le.symbol.xch != self.exchange || !self.pairs.contains(&le.symbol.value) {
+            return None;
+        }
+        self.quotes.insert(le.symbol.value.clone(), quote.clone());
+        let opportunity = self.opportunity()?;
+        let orders = opportunity
+            .legs
+            .into_iter()
+            .map(|(pair, side, price, qty)| AddOrderRequest {
+                xch: self.exchange,
+                pair,
+                side,
+                order_type: OrderType::Limit,
+                enforcement: Some(OrderEnforcement::IOC),
+                quantity: Some(qty),
+                price: Some(price),
+                order_id: Uuid::new_v4().to_string(),
+                dry_run: self.order_conf.dry_mode,
+                asset_type: Some(self.order_conf.asset_type),
+                account: self.order_conf.account.clone(),
+                ..AddOrderRequest::default()
+            })
+            .collect();
+        Some(OrderBatch::new(le, orders))
+    }
+
+    fn model(&self) -> SerializedModel {
+        vec![(
+            "net_return".to_string(),
+            self.last_return.and_then(|r| serde_json::to_value(r).ok()),
+        )]
+    }
+
+    fn channels(&self) -> HashSet<MarketChannel> {
+        self.pairs
+            .iter()
+            .map(|pair| {
+                MarketChannel::builder()
+                    .symbol(Symbol::new(pair.clone(), SecurityType::Crypto, self.exchange))
+                    .r#type(MarketChannelType::Quotes)
+                    .build()
+            })
+            .collect()
+    }
+
+    fn order_conf(&self) -> Option<&OrderConf> { Some(&self.order_conf) }
+}
+
+#[cfg(test)]
+mod test {
+    use brokers::types::{Quote, TradeType};
+
+    use super::{Options, TriangularArbitrageStrategy};
+
+    fn quote(bid: f64, ask: f64, qty: f64) -> Quote {
+        Quote {
+            bid,
+            bid_qty: qty,
+            ask,
+            ask_qty: qty,
+            event_ms: 0,
+            pair: "BTC_USDT".into(),
+        }
+    }
+
+    #[test]
+    fn detect_profitable_cycles() {
+        let options: Options = serde_json::from_value(serde_json::json!({
+            "exchange": "binance",
+            "pairs": ["BTC_USDT", "ETH_BTC", "ETH_USDT"],
+            "start_asset": "USDT",
+            "amount": 1000.0,
+            "fee_rate": 0.001,
+            "threshold": 0.001
+        }))
+        .unwrap();
+        let mut arb = TriangularArbitrageStrategy::try_new(&options).unwrap();
+        assert_eq!(arb.cycles.len(), 2);
+        arb.quotes.insert("BTC_USDT".into(), quote(19999.0, 20000.0, 10.0));
+        arb.quotes.insert("ETH_BTC".into(), quote(0.0749, 0.075, 100.0));
+        // The implied cross rate is 1500, the direct rate does not cover the fees
+        arb.quotes.insert("ETH_USDT".into(), quote(1500.0, 1500.5, 100.0));
+        assert!(arb.opportunity().is_none());
+        assert!(arb.last_return.unwrap() < 0.0);
+
+        // ETH is bid well above its cross rate : USDT is converted to BTC, then ETH, then back
+        arb.quotes.insert("ETH_USDT".into(), quote(1510.0, 1510.5, 100.0));
+        let opportunity = arb.opportunity().unwrap();
+        let sides: Vec<TradeType> = opportunity.legs.iter().map(|l| l.1).collect();
+        assert_eq!(sides, vec![TradeType::Buy, TradeType::Buy, TradeType::Sell]);
+        assert!((opportunity.legs[0].3 - 0.05).abs() < 1e-9);
+        assert!((opportunity.net_return - (1510.0 / 1500.0 * 0.999f64.powi(3) - 1.0)).abs() < 1e-9);
+
+        // Only about a tenth of the amount can be sold at the best ETH bid, every leg is scaled down
+        arb.quotes.insert("ETH_USDT".into(), quote(1510.0, 1510.5, 0.0666));
+        let thin = arb.opportunity().unwrap();
+        let scale = 0.0666 / opportunity.legs[2].3;
+        for (thin_leg, leg) in thin.legs.iter().zip(&opportunity.legs) {
+            assert!((thin_leg.3 - leg.3 * scale).abs() < 1e-9);
+        }
+        assert!((thin.net_return - opportunity.net_return).abs() < 1e-12);
+
+        assert!(TriangularArbitrageStrategy::try_new(&Options {
+            pairs: vec!["BTC_USDT".into(), "ETH_BTC".into(), "SOL_USDT".into()],
+            ..options
+        })
+        .is_err());
+    }
+}
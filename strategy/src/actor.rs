@@ -1,4 +1,4 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 use std::time;
 
@@ -9,7 +9,10 @@ use time::Duration;
 use tokio::sync::RwLock;
 use uuid::Uuid;
 
+use brokers::prelude::Asset;
 use brokers::types::MarketEventEnvelope;
+use portfolio::balance::ExpectedBalances;
+use util::time::now;
 
 use crate::driver::StrategyDriver;
 use crate::query::{DataQuery, ModelReset, Mutation, StateFieldMutation};
@@ -104,8 +107,16 @@ impl Actor for StrategyActor {
                 async move {
                     let mut w = inner.write().await;
                     w.resolve_orders().await;
+                    w.check_schedule(now()).await;
+                    w.mark_to_market(now()).await;
+                    w.check_risk(now()).await
                 }
-                .into_actor(act),
+                .into_actor(act)
+                .map(|breached, _act, ctx| {
+                    if breached {
+                        ctx.notify(StrategyLifecycleCmd::StopTrading);
+                    }
+                }),
             );
             act.is_checking_orders = false;
         });
@@ -169,6 +180,21 @@ impl Handler<DataQuery> for StrategyActor {
     }
 }
 
+impl Handler<ExpectedBalances> for StrategyActor {
+    type Result = StratActorResponseFuture<HashMap<Asset, f64>>;
+
+    fn handle(&mut self, msg: ExpectedBalances, _ctx: &mut Self::Context) -> Self::Result {
+        let lock = self.inner.clone();
+        Box::pin(
+            async move {
+                let inner = lock.read().await;
+                inner.expected_balances(msg.xchg, &msg.account)
+            }
+            .into_actor(self),
+        )
+    }
+}
+
 impl Handler<StateFieldMutation> for StrategyActor {
     type Result = StratActorResponseFuture<<StateFieldMutation as actix::Message>::Result>;
 
@@ -231,6 +257,15 @@ impl Handler<StrategyLifecycleCmd> for StrategyActor {
                 }
                 .into_actor(self),
             ),
+            StrategyLifecycleCmd::Flatten => Box::pin(
+                async move {
+                    let mut guard = lock.write().await;
+                    guard.stop_trading()?;
+                    guard.flatten().await?;
+                    Ok(StrategyStatus::NotTrading)
+                }
+                .into_actor(self),
+            ),
             StrategyLifecycleCmd::ResumeTrading => Box::pin(
                 async move {
                     let mut guard = lock.write().await;
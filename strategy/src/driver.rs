@@ -1,15 +1,23 @@
+use chrono::{DateTime, Utc};
 use smallvec::SmallVec;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 
-use brokers::types::MarketEventEnvelope;
-use db::Storage;
+use brokers::prelude::{AccountType, Asset, Exchange};
+use brokers::types::{MarketEventEnvelope, Pair};
+use db::{Snapshot, Storage};
 use portfolio::portfolio::Portfolio;
+use stats::indicators::microstructure::Microstructure;
 use trading::engine::TradingEngine;
-use trading::signal::TradeSignal;
+use trading::quoting::{QuoteFill, Quoter, TwoSidedQuote};
+use trading::signal::{OrderBatch, TradeSignal};
+use trading::types::OrderConf;
 
 use crate::error::*;
+use crate::feature_store::FeatureSet;
+use crate::microstructure::BookIndicators;
 use crate::models::io::SerializedModel;
+use crate::models::ModelMigration;
 use crate::query::{DataQuery, DataResult, Mutation};
 use crate::{error, MarketChannel};
 
@@ -41,11 +49,42 @@ pub trait StrategyDriver: Send + Sync {
     /// Resume trading signals
     fn resume_trading(&mut self) -> Result<()>;
 
+    /// Market close all open positions
+    async fn flatten(&mut self) -> Result<()> { Ok(()) }
+
     /// When called upon, resolve previously emitted trading signals
     async fn resolve_orders(&mut self);
 
     /// Check if there are any pending locks
     async fn is_locked(&self) -> bool;
+
+    /// Mark held positions to market prices and poll funding payments, independently of market events
+    ///
+    /// # Arguments
+    ///
+    /// * `at`: the current time of the driver's clock
+    async fn mark_to_market(&mut self, _at: DateTime<Utc>) {}
+
+    /// Enforce the trading schedule of the strategy, independently of market events
+    ///
+    /// # Arguments
+    ///
+    /// * `at`: the current time of the driver's clock
+    async fn check_schedule(&mut self, _at: DateTime<Utc>) {}
+
+    /// Check the risk limits that apply to the whole strategy
+    ///
+    /// returns: whether a limit was breached and trading should be stopped
+    async fn check_risk(&mut self, _at: DateTime<Utc>) -> bool { false }
+
+    /// A snapshot of the database of the strategy, none if its state isn't persisted
+    fn snapshot(&self) -> Result<Option<Snapshot>> { Ok(None) }
+
+    /// Roll the database of the strategy back to `snapshot`, the strategy reloads its state from it
+    fn restore(&mut self, _snapshot: &Snapshot) -> Result<()> { Ok(()) }
+
+    /// Balances the strategy expects to hold in this account, by asset
+    fn expected_balances(&self, _xch: Exchange, _account: &AccountType) -> HashMap<Asset, f64> { HashMap::new() }
 }
 
 pub type TradeSignals = SmallVec<[TradeSignal; 10]>;
@@ -63,6 +102,17 @@ pub trait Strategy: Sync + Send {
     /// Evaluate this market event
     async fn eval(&mut self, e: &MarketEventEnvelope, ctx: &DefaultStrategyContext) -> Result<Option<TradeSignals>>;
 
+    /// Two sided quotes of a market making strategy, rested by the driver as limit orders, none to leave the
+    /// current quotes as they are
+    fn quote(&mut self, _e: &MarketEventEnvelope, _ctx: &DefaultStrategyContext) -> Option<TwoSidedQuote> { None }
+
+    /// Orders submitted together as a single batch by the driver, outside of the positions of the portfolio, a new
+    /// batch is only submitted once the orders of the previous one are resolved
+    fn order_batch(&mut self, _e: &MarketEventEnvelope, _ctx: &DefaultStrategyContext) -> Option<OrderBatch> { None }
+
+    /// Executions of an order of the batches of the strategy, reported by the driver as they are received
+    fn on_batch_fill(&mut self, _order: &AddOrderRequest, _fill: &QuoteFill) {}
+
     /// Warmup
     fn warmup(&mut self, _e: Vec<MarketEventEnvelope>) { todo!() }
 
@@ -72,12 +122,41 @@ pub trait Strategy: Sync + Send {
     /// Exports a serialized view of model constants for performance purposes
     fn constants(&self) -> SerializedModel { vec![] }
 
+    /// Migrations of the persisted models of the strategy, models persisted by older versions are migrated on init
+    fn migrations(&self) -> Vec<ModelMigration<'_>> { vec![] }
+
     /// Channels the strategy subscribes to
     fn channels(&self) -> HashSet<MarketChannel>;
+
+    /// The order configuration of trade signals, checked against the capabilities of exchanges
+    fn order_conf(&self) -> Option<&OrderConf> { None }
+
+    /// Features recorded by the driver after each evaluation, none by default
+    fn features(&self) -> FeatureSet { FeatureSet::default() }
 }
 
 pub struct DefaultStrategyContext<'a> {
     pub portfolio: &'a Portfolio,
+    pub book_indicators: &'a BookIndicators,
+    pub quoters: &'a HashMap<(Exchange, Pair), Quoter>,
+}
+
+impl<'a> DefaultStrategyContext<'a> {
+    /// Share of the pending order of a market executed so far, a partially filled order can be chased or
+    /// canceled by the strategy
+    pub fn fill_ratio(&self, xch: Exchange, pair: Pair) -> Option<f64> { self.portfolio.fill_ratio(xch, pair) }
+
+    /// Annualized volatility forecast of a market by the position sizer, none unless positions are volatility
+    /// targeted
+    pub fn volatility(&self, xch: Exchange, pair: Pair) -> Option<f64> { self.portfolio.volatility(xch, pair) }
+
+    /// Microstructure indicators of the order books of a market, none until the driver receives a book of the market
+    pub fn microstructure(&self, xch: Exchange, pair: Pair) -> Option<&Microstructure> {
+        self.book_indicators.get(xch, pair)
+    }
+
+    /// Base inventory accumulated by the fills of the quotes of a market, as accounted by the portfolio
+    pub fn inventory(&self, xch: Exchange, pair: Pair) -> f64 { self.portfolio.inventory(xch, pair) }
 }
 
 pub struct StrategyInitContext {
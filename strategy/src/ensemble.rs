@@ -0,0 +1,395 @@
+//! Ensemble strategy : several member strategies evaluate the same events, and their signals are combined by a vote
+//! before being passed to the driver, so that strategies can be blended without writing a new one.
+//!
+//! The last signal of a member on a market is its vote for that market, until a signal of the market is elected or
+//! the vote expires. Signals are voted per market, the legs of a multi-market signal are elected separately.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use chrono::{DateTime, Duration, Utc};
+use serde_json::Value;
+
+use brokers::prelude::*;
+use brokers::types::MarketChannel;
+use trading::position::{OperationKind, PositionKind};
+use trading::signal::TradeSignal;
+use trading::types::OrderConf;
+
+use crate::driver::{DefaultStrategyContext, Strategy, TradeSignals};
+use crate::error::*;
+use crate::models::io::SerializedModel;
+use crate::models::ModelMigration;
+use crate::plugin::{find_plugin, plugin_registry, provide_options, LoadedPlugin, StrategyPlugin, StrategyPluginContext};
+use crate::settings::{StrategyOptions, StrategySettingsReplicator};
+use crate::StrategyKey;
+
+type MarketKey = (Exchange, Pair);
+
+pub fn provide_strat(_name: &str, ctx: StrategyPluginContext, conf: Value) -> Result<Box<dyn Strategy>> {
+    let options: Options = serde_json::from_value(conf)?;
+    let mut plugins = vec![];
+    let members = options
+        .members
+        .iter()
+        .map(|member| {
+            let plugin =
+                find_plugin(plugin_registry(), member.strat_type.as_str()).ok_or(Error::StrategyPluginNotFound)?;
+            let key = plugin.options(member.options.clone())?.key().to_string();
+            let member_ctx = StrategyPluginContext::builder()
+                .db(ctx.db.clone())
+                .engine(ctx.engine.clone())
+                .logger(ctx.logger.clone())
+                .build();
+            let strategy = plugin.strat(&key, member_ctx, member.options.clone())?;
+            plugins.push(plugin);
+            Ok((strategy, member.weight))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(Box::new(
+        EnsembleStrategy::try_new(&options.name, members, options.vote, options.vote_ttl)?.with_plugins(plugins),
+    ))
+}
+
+inventory::submit! {
+    StrategyPlugin::new("ensemble", provide_options::<Options>, provide_strat)
+}
+
+fn default_weight() -> f64 { 1.0 }
+
+/// A member strategy, configured like a standalone strategy
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct MemberOptions {
+    #[serde(rename = "type")]
+    pub strat_type: String,
+    pub options: Value,
+    /// Weight of the votes of the member
+    #[serde(default = "default_weight")]
+    pub weight: f64,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Options {
+    pub name: String,
+    pub members: Vec<MemberOptions>,
+    pub vote: VotingRule,
+    /// Votes older than this are discarded, votes are kept until a signal is elected if unset
+    #[serde(
+        deserialize_with = "util::ser::string_duration_chrono_opt",
+        serialize_with = "util::ser::encode_duration_str_opt"
+    )]
+    #[serde(default)]
+    pub vote_ttl: Option<Duration>,
+}
+
+impl StrategySettingsReplicator for Options {
+    fn replicate_for_pairs(&self, pairs: HashSet<Pair>) -> Vec<Value> {
+        pairs
+            .into_iter()
+            .filter_map(|pair| {
+                let members = self
+                    .members
+                    .iter()
+                    .map(|member| {
+                        let plugin = find_plugin(plugin_registry(), member.strat_type.as_str())?;
+                        let options = plugin.options(member.options.clone()).ok()?;
+                        let replica = options.replicate_for_pairs(HashSet::from([pair.clone()])).pop()?;
+                        Some(MemberOptions {
+                            options: replica,
+                            ..member.clone()
+                        })
+                    })
+                    .collect::<Option<Vec<_>>>()?;
+                serde_json::to_value(Options {
+                    name: format!("{}_{}", self.name, pair),
+                    members,
+                    ..self.clone()
+                })
+                .ok()
+            })
+            .collect()
+    }
+}
+
+impl StrategyOptions for Options {
+    fn key(&self) -> StrategyKey { StrategyKey("ensemble".to_string(), self.name.clone()) }
+}
+
+/// How the votes of members elect a signal
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum VotingRule {
+    /// Every member votes for the signal
+    Unanimous,
+    /// More than half of the members vote for the signal
+    Majority,
+    /// The members voting for the signal hold at least `threshold` of the total weight
+    WeightedScore { threshold: f64 },
+}
+
+impl VotingRule {
+    fn passes(&self, votes: usize, members: usize, weight: f64, total_weight: f64) -> bool {
+        match self {
+            VotingRule::Unanimous => votes == members,
+            VotingRule::Majority => 2 * votes > members,
+            VotingRule::WeightedScore { threshold } => weight >= threshold * total_weight,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Vote {
+    at: DateTime<Utc>,
+    signal: TradeSignal,
+}
+
+/// The current votes of the members of an ensemble
+#[derive(Debug)]
+struct Ballot {
+    rule: VotingRule,
+    ttl: Option<Duration>,
+    weights: Vec<f64>,
+    votes: Vec<HashMap<MarketKey, Vote>>,
+}
+
+impl Ballot {
+    fn new(rule: VotingRule, ttl: Option<Duration>, weights: Vec<f64>) -> Self {
+        Self {
+            rule,
+            ttl,
+            votes: vec![HashMap::default(); weights.len()],
+            weights,
+        }
+    }
+
+    /// The signal of a member replaces its previous vote on the market of the signal
+    fn cast(&mut self, member: usize, signal: TradeSignal, at: DateTime<Utc>) {
+        self.votes[member].insert(signal.xch_and_pair(), Vote { at, signal });
+    }
+
+    /// Elect a signal on a market from the votes at `at`, votes of the market are cleared once a signal is elected
+    ///
+    /// returns: the last signal voted for the elected operation
+    fn elect(&mut self, market: &MarketKey, at: DateTime<Utc>) -> Option<TradeSignal> {
+        if let Some(ttl) = self.ttl {
+            for votes in &mut self.votes {
+                votes.retain(|_, vote| at - vote.at <= ttl);
+            }
+        }
+        // Number of votes, weight and last vote of each operation
+        let mut candidates: Vec<((OperationKind, PositionKind), usize, f64, &Vote)> = vec![];
+        for (votes, weight) in self.votes.iter().zip(&self.weights) {
+            let Some(vote) = votes.get(market) else {
+                continue;
+            };
+            let operation = (vote.signal.op_kind, vote.signal.pos_kind);
+            match candidates.iter_mut().find(|c| c.0 == operation) {
+                Some(candidate) => {
+                    candidate.1 += 1;
+                    candidate.2 += weight;
+                    if vote.at > candidate.3.at {
+                        candidate.3 = vote;
+                    }
+                }
+                None => candidates.push((operation, 1, *weight, vote)),
+            }
+        }
+        let total_weight: f64 = self.weights.iter().sum();
+        let elected = candidates
+            .into_iter()
+            .filter(|(_, votes, weight, _)| self.rule.passes(*votes, self.weights.len(), *weight, total_weight))
+            .max_by(|a, b| a.2.total_cmp(&b.2))
+            .map(|(_, _, _, vote)| vote.signal.clone())?;
+        for votes in &mut self.votes {
+            votes.remove(market);
+        }
+        Some(elected)
+    }
+}
+
+struct Member {
+    strategy: Box<dyn Strategy>,
+    /// Exported models are prefixed with the key of the member
+    key: String,
+}
+
+pub struct EnsembleStrategy {
+    name: String,
+    members: Vec<Member>,
+    ballot: Ballot,
+    /// Plugins of the members, dropped after them so that the libraries they run from stay loaded
+    plugins: Vec<Arc<LoadedPlugin>>,
+}
+
+impl EnsembleStrategy {
+    /// * `members`: member strategies with the weight of their votes
+    pub fn try_new(
+        name: &str,
+        members: Vec<(Box<dyn Strategy>, f64)>,
+        rule: VotingRule,
+        ttl: Option<Duration>,
+    ) -> Result<Self> {
+        if members.is_empty() {
+            return Err(Error::BadConfiguration(
+                "an ensemble has at least one member".to_string(),
+            ));
+        }
+        if members.iter().any(|(_, weight)| *weight <= 0.0) {
+            return Err(Error::BadConfiguration("member weights should be > 0".to_string()));
+        }
+        if matches!(rule, VotingRule::WeightedScore { threshold } if threshold <= 0.0 || threshold > 1.0) {
+            return Err(Error::BadConfiguration(
+                "the threshold of a weighted score is in (0, 1]".to_string(),
+            ));
+        }
+        let weights = members.iter().map(|(_, weight)| *weight).collect();
+        Ok(Self {
+            name: name.to_string(),
+            members: members
+                .into_iter()
+                .map(|(strategy, _)| Member {
+                    key: strategy.key(),
+                    strategy,
+                })
+                .collect(),
+            ballot: Ballot::new(rule, ttl, weights),
+            plugins: vec![],
+        })
+    }
+
+    /// Hold the plugins the members were built with for as long as the ensemble runs
+    #[must_use]
+    pub fn with_plugins(mut self, plugins: Vec<Arc<LoadedPlugin>>) -> Self {
+        self.plugins = plugins;
+        self
+    }
+}
+
+#[async_trait]
+impl Strategy for EnsembleStrategy {
+    fn key(&self) -> String { format!("ensemble_{}", self.name) }
+
+    fn init(&mut self) -> Result<()> {
+        for member in &mut self.members {
+            member.strategy.init()?;
+        }
+        Ok(())
+    }
+
+    async fn eval(&mut self, le: &MarketEventEnvelope, ctx: &DefaultStrategyContext) -> Result<Option<TradeSignals>> {
+        let at = le.e.time();
+        let mut markets: Vec<MarketKey> = vec![];
+        for (i, member) in self.members.iter_mut().enumerate() {
+            let Some(signals) = member.strategy.eval(le, ctx).await? else {
+                continue;
+            };
+            for signal in signals {
+                let market = signal.xch_and_pair();
+                if !markets.contains(&market) {
+                    markets.push(market);
+                }
+                self.ballot.cast(i, signal, at);
+            }
+        }
+        let signals: TradeSignals = markets
+            .iter()
+            .filter_map(|market| self.ballot.elect(market, at))
+            .collect();
+        Ok((!signals.is_empty()).then_some(signals))
+    }
+
+    fn model(&self) -> SerializedModel {
+        self.members
+            .iter()
+            .flat_map(|member| {
+                member
+                    .strategy
+                    .model()
+                    .into_iter()
+                    .map(|(k, v)| (format!("{}.{}", member.key, k), v))
+            })
+            .collect()
+    }
+
+    fn constants(&self) -> SerializedModel {
+        self.members
+            .iter()
+            .flat_map(|member| {
+                member
+                    .strategy
+                    .constants()
+                    .into_iter()
+                    .map(|(k, v)| (format!("{}.{}", member.key, k), v))
+            })
+            .collect()
+    }
+
+    fn migrations(&self) -> Vec<ModelMigration<'_>> {
+        self.members
+            .iter()
+            .flat_map(|member| member.strategy.migrations())
+            .collect()
+    }
+
+    fn channels(&self) -> HashSet<MarketChannel> {
+        self.members
+            .iter()
+            .flat_map(|member| member.strategy.channels())
+            .collect()
+    }
+
+    fn order_conf(&self) -> Option<&OrderConf> { self.members.iter().find_map(|member| member.strategy.order_conf()) }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{Duration, TimeZone, Utc};
+
+    use trading::position::{OperationKind, PositionKind};
+    use trading::signal::TradeSignal;
+
+    use super::{Ballot, VotingRule};
+
+    fn signal(op_kind: OperationKind, price: f64) -> TradeSignal {
+        TradeSignal {
+            op_kind,
+            pos_kind: PositionKind::Long,
+            price,
+            ..TradeSignal::default()
+        }
+    }
+
+    #[test]
+    fn elect_signals() {
+        let t0 = Utc.with_ymd_and_hms(2022, 1, 1, 0, 0, 0).unwrap();
+        let at = |minutes: i64| t0 + Duration::minutes(minutes);
+        let market = signal(OperationKind::Open, 0.0).xch_and_pair();
+
+        let mut majority = Ballot::new(VotingRule::Majority, None, vec![1.0; 3]);
+        majority.cast(0, signal(OperationKind::Open, 100.0), at(0));
+        assert!(majority.elect(&market, at(0)).is_none());
+        // Votes persist across events
+        majority.cast(1, signal(OperationKind::Open, 101.0), at(1));
+        let elected = majority.elect(&market, at(1)).unwrap();
+        assert_eq!(elected.price, 101.0);
+        // Votes are cleared once elected
+        majority.cast(2, signal(OperationKind::Open, 102.0), at(2));
+        assert!(majority.elect(&market, at(2)).is_none());
+
+        let mut unanimous = Ballot::new(VotingRule::Unanimous, Some(Duration::minutes(5)), vec![1.0; 2]);
+        unanimous.cast(0, signal(OperationKind::Open, 100.0), at(0));
+        unanimous.cast(1, signal(OperationKind::Open, 100.0), at(10));
+        // The first vote expired
+        assert!(unanimous.elect(&market, at(10)).is_none());
+
+        let mut weighted = Ballot::new(VotingRule::WeightedScore { threshold: 0.6 }, None, vec![3.0, 1.0, 1.0]);
+        weighted.cast(1, signal(OperationKind::Open, 100.0), at(0));
+        weighted.cast(2, signal(OperationKind::Open, 100.0), at(0));
+        assert!(weighted.elect(&market, at(0)).is_none());
+        weighted.cast(0, signal(OperationKind::Close, 100.0), at(0));
+        assert_eq!(
+            weighted.elect(&market, at(0)).map(|s| s.op_kind),
+            Some(OperationKind::Close)
+        );
+    }
+}
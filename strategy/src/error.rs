@@ -21,6 +21,12 @@ pub enum Error {
     Portfolio(#[from] portfolio::Error),
     #[error("model not loaded : {0}")]
     ModelLoadError(String),
+    #[error("no migration path from version {from} to version {to}")]
+    NoMigrationPath { from: u32, to: u32 },
+    #[error("failed to migrate model {0} : {1}")]
+    ModelMigration(String, Box<Error>),
+    #[error("model inference : {0}")]
+    ModelInference(String),
     #[error("there are pending operations")]
     PendingOperation,
     #[error("no transaction found in operation")]
@@ -49,6 +55,10 @@ pub enum Error {
     MailboxError(#[from] actix::MailboxError),
     #[error("strategy plugin not found")]
     StrategyPluginNotFound,
+    #[error("plugin library {0}")]
+    PluginLibrary(#[from] libloading::Error),
+    #[error("signal publisher {0}")]
+    SignalPublisher(String),
     #[cfg(feature = "python")]
     #[error("error running python code")]
     Python(#[from] pyo3::PyErr),
@@ -61,6 +71,9 @@ impl Error {
             Error::Broker(_) => "broker",
             Error::Db(_) => "db",
             Error::ModelLoadError(_) => "model_load",
+            Error::NoMigrationPath { .. } => "no_migration_path",
+            Error::ModelMigration(_, _) => "model_migration",
+            Error::ModelInference(_) => "model_inference",
             Error::NoTransactionChange => "no_transaction_change",
             Error::NoTransactionInOperation => "no_transaction_in_operation",
             Error::OperationRejected => "operation_restaged",
@@ -80,7 +93,9 @@ impl Error {
             Error::Portfolio(_) => "portfolio",
             Error::NoSignal => "no_signal",
             Error::StrategyPluginNotFound => "strategy_plugin_not_found",
+            Error::PluginLibrary(_) => "plugin_library",
             Error::BadConfiguration(_) => "bad_configuration",
+            Error::SignalPublisher(_) => "signal_publisher",
         }
     }
 }
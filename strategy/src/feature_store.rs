@@ -0,0 +1,314 @@
+//! Feature store : strategies declare named features computed from market events or from the values of their model,
+//! the driver records them after each evaluation as a time series in the database of the strategy, and recorded
+//! features can be exported with a forward looking label as a dataset aligned in time for offline training.
+
+use std::io::Write;
+use std::sync::Arc;
+
+use chrono::{DateTime, TimeZone, Utc};
+use itertools::Itertools;
+
+use brokers::types::{MarketEventEnvelope, Pair};
+use db::{Storage, StorageExt};
+
+use crate::error::*;
+use crate::models::io::SerializedModel;
+
+pub(crate) const FEATURES_TABLE: &str = "features";
+
+/// A field of a market event
+#[derive(Copy, Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EventField {
+    High,
+    Low,
+    Close,
+    Volume,
+    Vwap,
+}
+
+/// Where the value of a feature comes from
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum FeatureSource {
+    /// A field of the evaluated market event
+    Event { field: EventField },
+    /// A numeric value of the model exported by the strategy, see [`crate::driver::Strategy::model`]
+    Model { key: String },
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct FeatureDef {
+    pub name: String,
+    pub source: FeatureSource,
+}
+
+impl FeatureDef {
+    pub fn event(name: &str, field: EventField) -> Self {
+        Self {
+            name: name.to_string(),
+            source: FeatureSource::Event { field },
+        }
+    }
+
+    pub fn model(name: &str, key: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            source: FeatureSource::Model { key: key.to_string() },
+        }
+    }
+
+    fn value(&self, le: &MarketEventEnvelope, model: &SerializedModel) -> Option<f64> {
+        let value = match &self.source {
+            FeatureSource::Event { field } => Some(match field {
+                EventField::High => le.e.high(),
+                EventField::Low => le.e.low(),
+                EventField::Close => le.e.close(),
+                EventField::Volume => le.e.vol(),
+                EventField::Vwap => le.e.vwap(),
+            }),
+            FeatureSource::Model { key } => model
+                .iter()
+                .find(|(k, _)| k == key)
+                .and_then(|(_, v)| v.as_ref())
+                .and_then(serde_json::Value::as_f64),
+        };
+        value.filter(|v| v.is_finite())
+    }
+}
+
+/// The label of a dataset, the relative change of a feature `horizon` rows of the same pair later
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct LabelSpec {
+    /// Name of the labeled feature
+    pub feature: String,
+    pub horizon: usize,
+}
+
+/// The features recorded by a strategy
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct FeatureSet {
+    pub features: Vec<FeatureDef>,
+    /// Label of exported datasets, datasets are exported without labels if unset
+    #[serde(default)]
+    pub label: Option<LabelSpec>,
+}
+
+impl FeatureSet {
+    pub fn is_empty(&self) -> bool { self.features.is_empty() }
+
+    pub fn names(&self) -> Vec<String> { self.features.iter().map(|f| f.name.clone()).collect() }
+
+    /// Compute the features after the evaluation of a market event, undefined features are none
+    pub fn compute(&self, le: &MarketEventEnvelope, model: &SerializedModel) -> FeatureRow {
+        FeatureRow {
+            at: le.e.time(),
+            pair: le.symbol.value.clone(),
+            values: self.features.iter().map(|f| f.value(le, model)).collect(),
+        }
+    }
+}
+
+/// Values of the features of a set, in the order of the set
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct FeatureRow {
+    pub at: DateTime<Utc>,
+    pub pair: Pair,
+    pub values: Vec<Option<f64>>,
+}
+
+/// Features of a strategy persisted by event time and pair, the last row of a millisecond is kept for each pair
+#[derive(Debug)]
+pub struct FeatureStore {
+    db: Arc<dyn Storage>,
+    set: FeatureSet,
+}
+
+impl FeatureStore {
+    pub fn new(db: Arc<dyn Storage>, set: FeatureSet) -> Result<Self> {
+        db.ensure_table(FEATURES_TABLE)?;
+        Ok(Self { db, set })
+    }
+
+    /// Compute and persist the features after the evaluation of a market event
+    pub fn record(&self, le: &MarketEventEnvelope, model: &SerializedModel) -> Result<()> {
+        let row = self.set.compute(le, model);
+        Ok(self.db.put(FEATURES_TABLE, &row_key(row.at, &row.pair), &row.values)?)
+    }
+
+    /// All recorded rows, in time order
+    pub fn rows(&self) -> Result<Vec<FeatureRow>> {
+        Ok(self
+            .db
+            .get_all::<Vec<Option<f64>>>(FEATURES_TABLE)?
+            .into_iter()
+            .filter_map(|(k, values)| {
+                let (millis, pair) = std::str::from_utf8(&k).ok()?.split_once(':')?;
+                Some(FeatureRow {
+                    at: Utc.timestamp_millis_opt(millis.parse::<i64>().ok()?).single()?,
+                    pair: pair.into(),
+                    values,
+                })
+            })
+            .sorted_by(|a, b| a.at.cmp(&b.at).then_with(|| a.pair.cmp(&b.pair)))
+            .collect())
+    }
+
+    /// The recorded rows with their labels
+    pub fn dataset(&self) -> Result<FeatureDataset> { Ok(FeatureDataset::new(&self.set, self.rows()?)) }
+}
+
+/// Keys are zero padded so that rows sort by time
+fn row_key(at: DateTime<Utc>, pair: &Pair) -> String { format!("{:020}:{}", at.timestamp_millis(), pair) }
+
+/// Recorded features aligned in time with their labels
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+pub struct FeatureDataset {
+    pub names: Vec<String>,
+    pub rows: Vec<FeatureRow>,
+    /// Label of each row, none if the dataset has no label or if the label is not known yet
+    pub labels: Vec<Option<f64>>,
+}
+
+impl FeatureDataset {
+    pub fn new(set: &FeatureSet, rows: Vec<FeatureRow>) -> Self {
+        let labels = match set.label.as_ref().and_then(|label| {
+            set.features
+                .iter()
+                .position(|f| f.name == label.feature)
+                .map(|i| (i, label.horizon))
+        }) {
+            Some((i, horizon)) => {
+                let mut labels = vec![None; rows.len()];
+                let by_pair = (0..rows.len()).into_group_map_by(|row| rows[*row].pair.clone());
+                for indices in by_pair.values() {
+                    for (n, row) in indices.iter().enumerate() {
+                        labels[*row] = indices.get(n + horizon).and_then(|later| {
+                            let now = rows[*row].values.get(i).copied().flatten()?;
+                            let later = rows[*later].values.get(i).copied().flatten()?;
+                            (now != 0.0).then(|| later / now - 1.0)
+                        });
+                    }
+                }
+                labels
+            }
+            None => vec![None; rows.len()],
+        };
+        Self {
+            names: set.names(),
+            rows,
+            labels,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool { self.rows.is_empty() }
+
+    /// Write the dataset as csv, with the time in milliseconds and the pair as first columns and the label as last
+    /// column, undefined values are empty
+    pub fn write_csv<W: Write>(&self, mut out: W) -> Result<()> {
+        writeln!(out, "at,pair,{},label", self.names.join(","))?;
+        let cell = |v: &Option<f64>| v.map(|v| v.to_string()).unwrap_or_default();
+        for (row, label) in self.rows.iter().zip(self.labels.iter()) {
+            writeln!(
+                out,
+                "{},{},{},{}",
+                row.at.timestamp_millis(),
+                row.pair,
+                row.values.iter().map(cell).join(","),
+                cell(label)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{TimeZone, Utc};
+
+    use brokers::exchange::Exchange;
+    use brokers::types::{Candle, MarketEvent, MarketEventEnvelope, SecurityType, Symbol};
+
+    use crate::test_util::test_db;
+
+    use super::{EventField, FeatureDef, FeatureSet, FeatureStore, LabelSpec};
+
+    fn candle_event(minute: u32, close: f64) -> MarketEventEnvelope { pair_candle_event("BTC_USDT", minute, close) }
+
+    fn pair_candle_event(pair: &str, minute: u32, close: f64) -> MarketEventEnvelope {
+        let at = Utc.with_ymd_and_hms(2022, 1, 1, 0, minute, 0).unwrap();
+        MarketEventEnvelope::new(
+            Symbol::new(pair.into(), SecurityType::Crypto, Exchange::Binance),
+            MarketEvent::TradeCandle(Candle {
+                event_time: at,
+                pair: pair.into(),
+                start_time: at,
+                end_time: at,
+                open: close,
+                high: close,
+                low: close,
+                close,
+                volume: 1.0,
+                quote_volume: close,
+                trade_count: 1,
+                is_final: true,
+            }),
+        )
+    }
+
+    #[test]
+    fn record_and_export_features() {
+        let set = FeatureSet {
+            features: vec![
+                FeatureDef::event("close", EventField::Close),
+                FeatureDef::model("z", "zscore"),
+            ],
+            label: Some(LabelSpec {
+                feature: "close".to_string(),
+                horizon: 1,
+            }),
+        };
+        let store = FeatureStore::new(test_db(), set).unwrap();
+        let model = vec![("zscore".to_string(), Some(serde_json::json!(1.5)))];
+        // Recorded out of order
+        store.record(&candle_event(1, 110.0), &model).unwrap();
+        store.record(&candle_event(0, 100.0), &vec![]).unwrap();
+        let dataset = store.dataset().unwrap();
+        assert_eq!(dataset.rows.len(), 2);
+        assert_eq!(dataset.rows[0].values, vec![Some(100.0), None]);
+        assert_eq!(dataset.rows[1].values, vec![Some(110.0), Some(1.5)]);
+        assert_eq!(dataset.labels.len(), 2);
+        assert!((dataset.labels[0].unwrap() - 0.1).abs() < 1e-9);
+        assert_eq!(dataset.labels[1], None);
+        let mut csv = vec![];
+        dataset.write_csv(&mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[0], "at,pair,close,z,label");
+        assert_eq!(lines[2], "1640995260000,BTC_USDT,110,1.5,");
+    }
+
+    #[test]
+    fn features_of_pairs_in_the_same_millisecond() {
+        let set = FeatureSet {
+            features: vec![FeatureDef::event("close", EventField::Close)],
+            label: Some(LabelSpec {
+                feature: "close".to_string(),
+                horizon: 1,
+            }),
+        };
+        let store = FeatureStore::new(test_db(), set).unwrap();
+        store.record(&pair_candle_event("BTC_USDT", 0, 100.0), &vec![]).unwrap();
+        store.record(&pair_candle_event("ETH_USDT", 0, 10.0), &vec![]).unwrap();
+        store.record(&pair_candle_event("BTC_USDT", 1, 110.0), &vec![]).unwrap();
+        store.record(&pair_candle_event("ETH_USDT", 1, 12.0), &vec![]).unwrap();
+        let dataset = store.dataset().unwrap();
+        assert_eq!(dataset.rows.len(), 4);
+        assert_eq!(dataset.rows[1].pair, "ETH_USDT".into());
+        assert_eq!(dataset.rows[1].values, vec![Some(10.0)]);
+        // Labels are the changes of the same pair
+        assert!((dataset.labels[0].unwrap() - 0.1).abs() < 1e-9);
+        assert!((dataset.labels[1].unwrap() - 0.2).abs() < 1e-9);
+        assert_eq!(dataset.labels[2], None);
+    }
+}
@@ -0,0 +1,69 @@
+use brokers::prelude::*;
+use trading::order_manager::OrderExecutor;
+use trading::quoting::QuoteFill;
+use trading::signal::BatchLeg;
+
+use crate::generic::metrics;
+
+/// Orders of the last batch of a strategy, until they are all resolved
+#[derive(Debug, Default)]
+pub(super) struct PendingBatch {
+    legs: Vec<BatchLeg>,
+}
+
+impl PendingBatch {
+    pub(super) fn restore(&mut self, legs: Vec<BatchLeg>) { self.legs = legs; }
+
+    pub(super) fn legs(&self) -> &[BatchLeg] { &self.legs }
+
+    /// Whether the orders of the last batch are not all resolved
+    pub(super) fn is_pending(&self) -> bool { !self.legs.is_empty() }
+
+    /// Track the legs of a staged batch
+    pub(super) fn start(&mut self, legs: Vec<BatchLeg>) { self.legs = legs; }
+
+    /// Query the orders of the unresolved legs
+    ///
+    /// returns: the executions of the legs since the last poll
+    pub(super) async fn poll(&mut self, executor: &dyn OrderExecutor) -> Vec<(AddOrderRequest, QuoteFill)> {
+        let mut fills = vec![];
+        for leg in self.legs.iter_mut().filter(|leg| !leg.resolved) {
+            let order_id = leg.request.order_id.as_str();
+            let order = match executor.get_order(order_id).await {
+                Ok((order, _)) => order,
+                Err(e) => {
+                    metrics::get().log_error(e.short_name());
+                    debug!(err = %e, order_id = %order_id, "failed to query batch order");
+                    continue;
+                }
+            };
+            if let Some(fill) = leg.on_order(&order) {
+                fills.push((leg.request.clone(), fill));
+            }
+        }
+        fills
+    }
+
+    /// Forget the batch once its orders are all resolved, batches are not atomic on exchanges so the executions of a
+    /// batch that did not fill in full are unwound
+    ///
+    /// returns: the orders unwinding the executions of the batch, None while its orders are not resolved
+    pub(super) fn settle(&mut self, key: &str) -> Option<Vec<AddOrderRequest>> {
+        if self.legs.iter().any(|leg| !leg.resolved) {
+            return None;
+        }
+        let legs = std::mem::take(&mut self.legs);
+        if legs.iter().any(|leg| !leg.unwind && !leg.filled) {
+            return Some(
+                legs.iter()
+                    .filter(|leg| !leg.unwind)
+                    .filter_map(BatchLeg::unwind)
+                    .collect(),
+            );
+        }
+        if legs.iter().any(|leg| leg.unwind && !leg.filled) {
+            error!(key = %key, "failed to unwind an order batch, inventories are left open");
+        }
+        Some(vec![])
+    }
+}
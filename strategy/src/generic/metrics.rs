@@ -1,5 +1,6 @@
 use std::collections::HashMap;
 
+use chrono::Duration;
 use prometheus::{default_registry, CounterVec, GaugeVec, Registry};
 
 use brokers::exchange::Exchange;
@@ -9,6 +10,8 @@ use portfolio::portfolio::Portfolio;
 use trading::position::Position;
 use trading::signal::TradeSignal;
 
+use crate::generic::shadow::ExecutionDivergence;
+
 lazy_static! {
     static ref METRICS: GenericDriverMetrics = { GenericDriverMetrics::new(default_registry()) };
 }
@@ -16,12 +19,14 @@ lazy_static! {
 type PortfolioIndicatorFn = MetricProviderFn<Portfolio>;
 type PositionIndicatorFn = MetricProviderFn<Position>;
 type SignalIndicatorFn = MetricProviderFn<TradeSignal>;
+type DivergenceIndicatorFn = MetricProviderFn<ExecutionDivergence>;
 
 #[derive(Clone)]
 pub struct GenericDriverMetrics {
     lock_counters: GaugeVec,
     failed_position_counters: GaugeVec,
     signal_errors: CounterVec,
+    stale_signals: CounterVec,
     errors: CounterVec,
     signal_fns: Vec<SignalIndicatorFn>,
     signal_gauges: HashMap<String, GaugeVec>,
@@ -29,7 +34,10 @@ pub struct GenericDriverMetrics {
     portfolio_gauges: HashMap<String, GaugeVec>,
     position_fns: Vec<PositionIndicatorFn>,
     position_gauges: HashMap<String, GaugeVec>,
+    divergence_fns: Vec<DivergenceIndicatorFn>,
+    divergence_gauges: HashMap<String, GaugeVec>,
     status_gauge: GaugeVec,
+    batch_latencies: GaugeVec,
 }
 
 impl GenericDriverMetrics {
@@ -65,6 +73,16 @@ impl GenericDriverMetrics {
             .unwrap()
         };
 
+        let stale_signals = {
+            let pos_labels = &["skey", "xch", "pair"];
+            let vec_name = "dr_sig_stale";
+            register_counter_vec!(
+                opts!(vec_name, format!("counter for {}", vec_name), const_labels),
+                pos_labels
+            )
+            .unwrap()
+        };
+
         let errors = {
             let pos_labels = &["err"];
             let vec_name = "dr_all_err";
@@ -100,16 +118,36 @@ impl GenericDriverMetrics {
 
         let position_gauges = make_gauges(const_labels.clone(), &["skey", "xch", "mkt"], &position_fns);
 
+        #[allow(clippy::cast_precision_loss)]
+        let divergence_fns: Vec<DivergenceIndicatorFn> = vec![
+            ("shadow_fills".to_string(), |x| x.fills as f64),
+            ("shadow_missed_fills".to_string(), |x| x.missed_fills as f64),
+            ("shadow_avg_slippage".to_string(), |x| x.avg_slippage),
+            ("shadow_value_divergence".to_string(), |x| x.value_divergence),
+        ];
+        let divergence_gauges = make_gauges(const_labels.clone(), &["skey"], &divergence_fns);
+
         let status_gauge = register_gauge_vec!(
             opts!("is_trading", "Whether the strategy is trading or not.", const_labels),
             &["skey"]
         )
         .unwrap();
 
+        let batch_latencies = register_gauge_vec!(
+            opts!(
+                "dr_batch_latency_ms",
+                "Latency of the stages of the last order batch, in milliseconds.",
+                const_labels
+            ),
+            &["skey", "stage"]
+        )
+        .unwrap();
+
         Self {
             lock_counters,
             failed_position_counters,
             signal_errors,
+            stale_signals,
             errors,
             signal_fns,
             signal_gauges,
@@ -117,7 +155,10 @@ impl GenericDriverMetrics {
             portfolio_gauges,
             position_fns,
             position_gauges,
+            divergence_fns,
+            divergence_gauges,
             status_gauge,
+            batch_latencies,
         }
     }
 
@@ -139,6 +180,12 @@ impl GenericDriverMetrics {
             .inc();
     }
 
+    pub(super) fn stale_signal(&self, strat_key: &str, xch: Exchange, pair: &Pair) {
+        self.stale_signals
+            .with_label_values(&[strat_key, xch.as_ref(), pair.as_ref()])
+            .inc();
+    }
+
     pub(super) fn log_error(&self, e: &str) { self.errors.with_label_values(&[e]).inc(); }
 
     pub(super) fn log_signals(&self, strat_key: &str, signals: &[TradeSignal]) {
@@ -164,11 +211,25 @@ impl GenericDriverMetrics {
         }
     }
 
+    pub(super) fn log_divergence(&self, strat_key: &str, divergence: &ExecutionDivergence) {
+        self.log_all_with_providers(&self.divergence_fns, divergence, &[strat_key]);
+    }
+
     pub(super) fn log_is_trading(&self, strat_key: &str, trading: bool) {
         self.status_gauge
             .with_label_values(&[strat_key])
             .set(if trading { 1.0 } else { 0.0 });
     }
+
+    /// Latencies of an order batch, from the exchange to the driver, to the strategy and to the order manager
+    pub(super) fn log_batch_latency(&self, strat_key: &str, feed: Duration, detection: Duration, submission: Duration) {
+        for (stage, latency) in [("feed", feed), ("detection", detection), ("submission", submission)] {
+            #[allow(clippy::cast_precision_loss)]
+            self.batch_latencies
+                .with_label_values(&[strat_key, stage])
+                .set(latency.num_microseconds().unwrap_or(i64::MAX) as f64 / 1000.0);
+        }
+    }
 }
 
 impl MetricGaugeProvider<Portfolio> for GenericDriverMetrics {
@@ -183,6 +244,10 @@ impl MetricGaugeProvider<Position> for GenericDriverMetrics {
     fn gauges(&self) -> &HashMap<String, GaugeVec> { &self.position_gauges }
 }
 
+impl MetricGaugeProvider<ExecutionDivergence> for GenericDriverMetrics {
+    fn gauges(&self) -> &HashMap<String, GaugeVec> { &self.divergence_gauges }
+}
+
 pub fn get() -> &'static GenericDriverMetrics {
     lazy_static::initialize(&METRICS);
     &METRICS
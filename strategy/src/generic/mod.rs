@@ -1,59 +1,287 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
 use std::sync::Arc;
 
+use actix::Addr;
+use chrono::{DateTime, Duration, Utc};
 use tokio::sync::RwLock;
 
+use brokers::maintenance::MaintenanceRegistry;
 use brokers::prelude::*;
-use db::Storage;
-use portfolio::portfolio::{Portfolio, PortfolioRepoImpl};
-use portfolio::risk::DefaultMarketRiskEvaluator;
+use brokers::types::{CandleAggregations, MarketChannelTopic, MarketChannelType, OrderQuery, TradeFill};
+use db::{Snapshot, Storage};
+use portfolio::portfolio::{Portfolio, PortfolioRepoImpl, PositionMode};
+use portfolio::risk::{DefaultMarketRiskEvaluator, DrawdownThrottle, DrawdownThrottleOptions, MarketVolatility,
+                      RiskEngine, RiskEvaluator, RiskLimits, VolatilityTargetSizer};
+use stats::indicators::volatility::VolatilityModel;
 use trading::engine::TradingEngine;
-use trading::order_manager::types::StagedOrder;
-use trading::position::Position;
-use trading::signal::TradeSignal;
+use trading::execution::{Execute, ExecutionActor, ExecutionAlgo, ExecutionReport};
+use trading::order_manager::types::{OrderDetail, StagedOrder};
+use trading::position::{OperationKind, Position};
+use trading::quoting::{QuoteAction, QuotingOptions, TwoSidedQuote};
+use trading::signal::{BatchLeg, OrderBatch, TradeSignal};
+use trading::sizing::{PositionSizer, PositionSizerOptions, SizingLeg};
+use trading::stop::TrailingStopOptions;
+use trading::types::{OrderConf, TradeKind};
 use util::time::{now, TimedData};
 
 use crate::driver::{DefaultStrategyContext, Strategy, StrategyDriver};
-use crate::error::Result;
+use crate::error::{Error, Result};
+use crate::feature_store::{FeatureDataset, FeatureStore};
+use crate::generic::batch::PendingBatch;
+use crate::generic::quotes::QuotingState;
 use crate::generic::repo::{DriverRepository, GenericDriverRepository};
+use crate::generic::session::{SessionChange, TradingSession};
+use crate::generic::shadow::ShadowComparison;
+use crate::generic::stops::TrailingStops;
+use crate::microstructure::BookIndicators;
+use crate::plugin::LoadedPlugin;
+use crate::publish::{PublishedSignal, SignalPublisher, SignalPublisherOptions};
 use crate::query::{DataQuery, DataResult, ModelReset, MutableField, Mutation, PortfolioSnapshot};
+use crate::schedule::TradingSchedule;
+use crate::types::StratEvent;
 use crate::{MarketChannel, StratEventLoggerRef, StrategyStatus};
 
-mod metrics;
+mod batch;
+pub(crate) mod metrics;
+mod quotes;
 mod repo;
+mod session;
+mod shadow;
+mod stops;
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct PortfolioOptions {
     /// The initial cash allocation
     pub initial_quote_cash: f64,
-    /// Fees to anticipate order return
-    // TODO: replace by getting it from the exchange conf
+    /// Fees to anticipate order return, on exchanges that do not provide the fee tier of the account
     pub fees_rate: f64,
+    /// If set, opened positions are sized to this annualized volatility of returns
+    #[serde(default)]
+    pub target_volatility: Option<f64>,
+    /// Forecasts the volatility of volatility targeted positions with an EWMA or GARCH(1,1) estimator instead of
+    /// the rolling volatility of returns
+    #[serde(default)]
+    pub volatility_model: Option<VolatilityModel>,
+    /// If set, opened positions are reduced then stopped as realized equity draws down
+    #[serde(default)]
+    pub drawdown_throttle: Option<DrawdownThrottleOptions>,
+    /// Whether positions are netted per market or held on both sides for futures accounts in hedge mode
+    #[serde(default)]
+    pub position_mode: PositionMode,
+    /// Settle funding from funding rate events rather than from the payments reported by the exchange
+    #[serde(default)]
+    pub simulate_funding: bool,
+    /// Mark positions to the prices of the replayed market events rather than to the exchange tickers
+    #[serde(default)]
+    pub simulate_marks: bool,
+    /// If set, orders exceeding the exposure limits are not placed and trading stops past the daily loss limit
+    #[serde(default)]
+    pub risk_limits: Option<RiskLimits>,
 }
 
+const DEFAULT_MAINTENANCE_PAUSE_MINS: i64 = 5;
+
+const DEFAULT_FUNDING_POLL_INTERVAL_MINS: i64 = 10;
+
+const OBSERVE_PORTFOLIO_SUFFIX: &str = "observe";
+
+const SHADOW_PORTFOLIO_SUFFIX: &str = "shadow";
+
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct GenericDriverOptions {
     /// Options for [Portfolio]
     pub portfolio: PortfolioOptions,
     /// Start trading after first start
     pub start_trading: Option<bool>,
-    /// Orders will be simulated
+    /// Orders will be simulated, whatever the order settings of the strategy
     pub dry_mode: Option<bool>,
+    /// Signals older than this, relative to the last market event, are dropped instead of executed
+    #[serde(
+        deserialize_with = "util::ser::string_duration_chrono_opt",
+        serialize_with = "util::ser::encode_duration_str_opt"
+    )]
+    #[serde(default)]
+    pub max_signal_age: Option<Duration>,
+    /// How long to pause trading on an exchange after an order was rejected because of maintenance
+    #[serde(
+        deserialize_with = "util::ser::string_duration_chrono_opt",
+        serialize_with = "util::ser::encode_duration_str_opt"
+    )]
+    #[serde(default)]
+    pub maintenance_pause: Option<Duration>,
+    /// If set, held positions are marked to ticker prices at this interval, even when their pair has no market feed
+    #[serde(
+        deserialize_with = "util::ser::string_duration_chrono_opt",
+        serialize_with = "util::ser::encode_duration_str_opt"
+    )]
+    #[serde(default)]
+    pub mark_to_market_interval: Option<Duration>,
+    /// Interval at which the funding payments of perpetual contracts are polled from the exchanges
+    #[serde(
+        deserialize_with = "util::ser::string_duration_chrono_opt",
+        serialize_with = "util::ser::encode_duration_str_opt"
+    )]
+    #[serde(default)]
+    pub funding_poll_interval: Option<Duration>,
+    /// The first orders are held until they are confirmed through the API, subsequent orders are executed normally
+    #[serde(default)]
+    pub require_confirmation: Option<bool>,
+    /// Orders are logged and filled in a shadow portfolio instead of being placed, the real portfolio is untouched
+    #[serde(default)]
+    pub observe: Option<bool>,
+    /// Track a shadow portfolio filled at the intended prices alongside live trading, to measure execution quality
+    #[serde(default)]
+    pub shadow: Option<bool>,
+    /// Sizes the positions opened by signals without a quantity, instead of allocating the whole portfolio value
+    #[serde(default)]
+    pub position_sizer: Option<PositionSizerOptions>,
+    /// Trading sessions of the strategy, the strategy trades at any time if unset
+    #[serde(default)]
+    pub schedule: Option<TradingSchedule>,
+    /// Limits of the quotes of market making strategies, quotes are ignored if unset
+    #[serde(default)]
+    pub quoting: Option<QuotingOptions>,
+    /// Signals are published there and recorded instead of being converted into orders, nothing is traded
+    #[serde(default)]
+    pub signal_only: Option<SignalPublisherOptions>,
+    /// Orders of the positions of the strategy are split into child orders by this algorithm, instead of being
+    /// placed at once
+    #[serde(default)]
+    pub execution: Option<ExecutionAlgo>,
+    /// Open positions are closed by this trailing stop, executed by the exchange if it supports trailing stop orders
+    #[serde(default)]
+    pub trailing_stop: Option<TrailingStopOptions>,
 }
 
 impl GenericDriverOptions {
     pub fn dry_mode(&self) -> bool { self.dry_mode.unwrap_or(false) }
+
+    pub fn observe(&self) -> bool { self.observe.unwrap_or(false) }
+
+    pub fn shadow(&self) -> bool { self.shadow.unwrap_or(false) }
+
+    /// These options, with the risk limits replaced by the ones set in `overrides`
+    pub fn with_risk_overrides(&self, overrides: Option<&RiskLimits>) -> Self {
+        let mut options = self.clone();
+        if let Some(overrides) = overrides {
+            options.portfolio.risk_limits = Some(
+                options
+                    .portfolio
+                    .risk_limits
+                    .unwrap_or_default()
+                    .with_overrides(overrides),
+            );
+        }
+        options
+    }
+
+    /// These options, with the position sizer replaced by `sizer` if any
+    pub fn with_position_sizer(mut self, sizer: Option<PositionSizerOptions>) -> Self {
+        if sizer.is_some() {
+            self.position_sizer = sizer;
+        }
+        self
+    }
+
+    /// These options, with the trading schedule replaced by `schedule` if any
+    pub fn with_schedule(mut self, schedule: Option<TradingSchedule>) -> Self {
+        if schedule.is_some() {
+            self.schedule = schedule;
+        }
+        self
+    }
+
+    /// These options, with the quoting limits replaced by `quoting` if any
+    pub fn with_quoting(mut self, quoting: Option<QuotingOptions>) -> Self {
+        if quoting.is_some() {
+            self.quoting = quoting;
+        }
+        self
+    }
+
+    /// These options, with the signal publisher replaced by `signal_only` if any
+    pub fn with_signal_only(mut self, signal_only: Option<SignalPublisherOptions>) -> Self {
+        if signal_only.is_some() {
+            self.signal_only = signal_only;
+        }
+        self
+    }
+
+    pub fn funding_poll_interval(&self) -> Duration {
+        self.funding_poll_interval
+            .unwrap_or_else(|| Duration::minutes(DEFAULT_FUNDING_POLL_INTERVAL_MINS))
+    }
+
+    pub fn maintenance_pause(&self) -> Duration {
+        self.maintenance_pause
+            .unwrap_or_else(|| Duration::minutes(DEFAULT_MAINTENANCE_PAUSE_MINS))
+    }
 }
 
 pub struct GenericDriver {
     /// The list of channels the driver is subscribed to
     channels: HashSet<MarketChannel>,
+    /// Candles of the strategy channels that exchanges do not stream, built from the subscribed channels
+    aggregations: CandleAggregations,
+    /// Microstructure indicators of the received order books
+    book_indicators: BookIndicators,
+    /// Records the features declared by the strategy, if any
+    features: Option<FeatureStore>,
+    /// Sizes the positions opened by signals without a quantity, if any
+    position_sizer: Option<Box<dyn PositionSizer>>,
+    /// Volatility of the markets of the strategy, updated if positions are sized by the driver
+    volatility: MarketVolatility,
+    /// Trading sessions outside of which the strategy does not trade
+    session: TradingSession,
+    /// Resting quotes of the strategy
+    quotes: QuotingState,
+    /// Orders of the last batch of the strategy, until they are all resolved
+    batch: PendingBatch,
+    /// Splits the orders of positions into child orders, with its algorithm, orders are placed at once if unset
+    execution: Option<(Addr<ExecutionActor>, ExecutionAlgo)>,
+    /// Trade channels only subscribed to pace VWAP executions, their events are not evaluated by the strategy
+    execution_channels: HashSet<MarketChannel>,
+    /// Trailing stops of the open positions
+    stops: TrailingStops,
     /// The inner algorithm to run
     pub(crate) inner: RwLock<Box<dyn Strategy>>,
     /// If the driver has been initialized
     initialized: bool,
     /// Whether or not to start trading after initializing, defaults to true
     start_trading: Option<bool>,
+    /// Maximum age of a signal before it is considered stale
+    max_signal_age: Option<Duration>,
+    /// How long to pause trading on an exchange under maintenance
+    maintenance_pause: Duration,
+    /// Interval between two mark to market sweeps of held positions
+    mark_to_market_interval: Option<Duration>,
+    /// Time of the last mark to market sweep
+    last_mark: Option<DateTime<Utc>>,
+    /// Interval between two polls of the funding payments of held perpetual contracts
+    funding_poll_interval: Duration,
+    /// Time of the last poll of funding payments
+    last_funding_poll: Option<DateTime<Utc>>,
+    /// Last logged multiplier of the size of opened positions
+    size_multiplier: f64,
+    /// Whether the circuit breaker tripped since trading last resumed
+    breaker_tripped: bool,
+    /// Account trades reported by the broker for filled locked orders, fetched once per order
+    reported_trades: HashMap<String, Vec<TradeFill>>,
+    /// Whether orders are held until confirmed, until the first confirmation
+    require_confirmation: bool,
+    /// Orders held until confirmed, their positions stay locked
+    pending_orders: Vec<AddOrderRequest>,
+    /// Whether orders are only observed, the portfolio is then a shadow portfolio
+    observe: bool,
+    /// Publishes the signals of the strategy in signal only mode, nothing is traded then
+    publisher: Option<Arc<dyn SignalPublisher>>,
+    /// Whether all orders are simulated by the order manager
+    dry_mode: bool,
+    /// Compares live executions to a shadow portfolio
+    shadow: Option<ShadowComparison>,
+    /// Enforces the risk limits of the portfolio, if any
+    risk: Option<Arc<RiskEngine>>,
     /// Current driver status
     status: StrategyStatus,
     /// The portfolio managing order allocation
@@ -68,6 +296,11 @@ pub struct GenericDriver {
     logger: Option<StratEventLoggerRef>,
     /// A repository to manage driver state
     repo: GenericDriverRepository,
+    /// The database of the strategy, snapshotted by checkpoints
+    db: Arc<dyn Storage>,
+    /// The plugin the inner strategy was built with, declared last so that the library it runs from is unloaded
+    /// after the strategy is dropped
+    plugin: Option<Arc<LoadedPlugin>>,
 }
 
 impl GenericDriver {
@@ -79,22 +312,144 @@ impl GenericDriver {
         engine: Arc<TradingEngine>,
         logger: Option<StratEventLoggerRef>,
     ) -> Result<Self> {
+        let aggregations = CandleAggregations::new(&channels, |xch| {
+            engine.exchange_manager.get_api(xch).map(|api| api.capabilities())
+        });
+        let mut channels = aggregations.source_channels();
+        let execution_channels = execution_channels(&channels, driver_options.execution.as_ref());
+        channels.extend(execution_channels.iter().cloned());
+        check_capabilities(&engine, &channels, strat.order_conf())?;
         let portfolio_options = &driver_options.portfolio;
         let strat_key = strat.key();
-        let portfolio = Portfolio::try_new(
+        // The shadow portfolio of observe mode is stored apart from the real one
+        let portfolio_key = if driver_options.observe() {
+            format!("{}_{}", strat_key, OBSERVE_PORTFOLIO_SUFFIX)
+        } else {
+            strat_key.clone()
+        };
+        let risk = portfolio_options
+            .risk_limits
+            .map(|limits| Arc::new(RiskEngine::new(limits).with_storage(db.clone(), &portfolio_key)));
+        let risk_evaluator: Arc<dyn RiskEvaluator> = match risk.as_ref() {
+            Some(risk) => risk.clone(),
+            None => Arc::new(DefaultMarketRiskEvaluator::default()),
+        };
+        let mut portfolio = Portfolio::try_new(
             portfolio_options.initial_quote_cash,
             portfolio_options.fees_rate,
-            strat_key.clone(),
+            portfolio_key,
             Arc::new(PortfolioRepoImpl::new(db.clone())),
-            Arc::new(DefaultMarketRiskEvaluator::default()),
+            risk_evaluator,
             engine.interest_rate_provider.clone(),
-        )?;
-        let repo = GenericDriverRepository::new(db);
+        )?
+        .with_position_mode(portfolio_options.position_mode);
+        let account = strat.order_conf().and_then(|conf| conf.account.clone());
+        portfolio = with_fee_providers(portfolio, &engine, &channels, account.as_deref());
+        if let Some(pool) = engine.shared_capital.clone() {
+            portfolio = portfolio.with_shared_capital(pool);
+        }
+        if portfolio_options.simulate_funding {
+            portfolio = portfolio.with_simulated_funding();
+        } else {
+            portfolio = portfolio.with_funding_ledger(engine.funding_ledger.clone());
+        }
+        if portfolio_options.simulate_marks {
+            portfolio = portfolio.with_simulated_marks();
+        }
+        let estimator = portfolio_options
+            .volatility_model
+            .map(|model| model.estimator())
+            .transpose()
+            .map_err(|e| Error::BadConfiguration(e.to_string()))?;
+        let mut volatility = MarketVolatility::default();
+        if let Some(estimator) = estimator {
+            volatility = volatility.with_estimator(estimator);
+        }
+        if let Some(target_vol) = portfolio_options.target_volatility {
+            portfolio = portfolio.with_sizer(VolatilityTargetSizer::with_volatility(target_vol, volatility.clone()));
+        }
+        let position_sizer = driver_options
+            .position_sizer
+            .map(|options| options.sizer())
+            .transpose()
+            .map_err(|e| Error::BadConfiguration(e.to_string()))?;
+        if let Some(options) = portfolio_options.drawdown_throttle {
+            let throttle = DrawdownThrottle::new(options, portfolio.pnl());
+            portfolio = portfolio.with_drawdown_throttle(throttle);
+        }
+        let shadow = if driver_options.shadow() && !driver_options.observe() {
+            let shadow_portfolio = Portfolio::try_new(
+                portfolio_options.initial_quote_cash,
+                portfolio_options.fees_rate,
+                format!("{}_{}", strat_key, SHADOW_PORTFOLIO_SUFFIX),
+                Arc::new(PortfolioRepoImpl::new(db.clone())),
+                Arc::new(DefaultMarketRiskEvaluator::default()),
+                engine.interest_rate_provider.clone(),
+            )?
+            .with_position_mode(portfolio_options.position_mode);
+            let shadow_portfolio = with_fee_providers(shadow_portfolio, &engine, &channels, account.as_deref());
+            let shadow_portfolio = if portfolio_options.simulate_funding {
+                shadow_portfolio.with_simulated_funding()
+            } else {
+                shadow_portfolio
+            };
+            let shadow_portfolio = if portfolio_options.simulate_marks {
+                shadow_portfolio.with_simulated_marks()
+            } else {
+                shadow_portfolio
+            };
+            Some(ShadowComparison::new(shadow_portfolio))
+        } else {
+            None
+        };
+        let feature_set = strat.features();
+        let features = if feature_set.is_empty() {
+            None
+        } else {
+            Some(FeatureStore::new(db.clone(), feature_set)?)
+        };
+        let publisher = driver_options
+            .signal_only
+            .as_ref()
+            .map(SignalPublisherOptions::publisher)
+            .transpose()?;
+        let execution = driver_options
+            .execution
+            .clone()
+            .map(|algo| (ExecutionActor::actor(engine.order_executor.clone()), algo));
+        let repo = GenericDriverRepository::new(db.clone());
         Ok(Self {
             channels,
+            aggregations,
+            book_indicators: BookIndicators::default(),
+            features,
+            position_sizer,
+            volatility,
+            session: TradingSession::new(driver_options.schedule.clone()),
+            quotes: QuotingState::new(driver_options.quoting.clone()),
+            batch: PendingBatch::default(),
+            execution,
+            execution_channels,
+            stops: TrailingStops::new(driver_options.trailing_stop),
             inner: RwLock::new(strat),
             initialized: false,
             start_trading: driver_options.start_trading,
+            max_signal_age: driver_options.max_signal_age,
+            maintenance_pause: driver_options.maintenance_pause(),
+            mark_to_market_interval: driver_options.mark_to_market_interval,
+            last_mark: None,
+            funding_poll_interval: driver_options.funding_poll_interval(),
+            last_funding_poll: None,
+            size_multiplier: portfolio.size_multiplier(),
+            breaker_tripped: false,
+            reported_trades: HashMap::new(),
+            require_confirmation: driver_options.require_confirmation.unwrap_or(false),
+            pending_orders: vec![],
+            observe: driver_options.observe(),
+            publisher,
+            dry_mode: driver_options.dry_mode(),
+            shadow,
+            risk,
             status: StrategyStatus::default(),
             portfolio,
             engine,
@@ -102,9 +457,18 @@ impl GenericDriver {
             last_event: None,
             logger,
             repo,
+            db,
+            plugin: None,
         })
     }
 
+    /// Hold the plugin the inner strategy was built with for as long as the driver runs
+    #[must_use]
+    pub fn with_plugin(mut self, plugin: Arc<LoadedPlugin>) -> Self {
+        self.plugin = Some(plugin);
+        self
+    }
+
     pub(crate) fn status(&self) -> StrategyStatus { self.status }
 
     pub(crate) fn set_status(&mut self, status: StrategyStatus) -> Result<()> {
@@ -113,11 +477,39 @@ impl GenericDriver {
         Ok(())
     }
 
-    async fn process_signals(&mut self, signals: &[TradeSignal]) -> Result<()> {
+    async fn process_signals(&mut self, signals: &[TradeSignal], at: DateTime<Utc>) -> Result<()> {
         metrics::get().log_signals(self.name.as_str(), signals);
+        if self.publisher.is_some() {
+            self.publish_signals(signals).await;
+            return Ok(());
+        }
+        if under_maintenance(self.engine.exchange_manager.maintenance(), signals, at) {
+            metrics::get().log_error("exchange_maintenance");
+            debug!(key = %self.name, "exchange under maintenance, trading is paused");
+            return Ok(());
+        }
+        let fresh;
+        let signals = if let Some(max_age) = self.max_signal_age {
+            fresh = fresh_signals(self.name.as_str(), signals, at, max_age);
+            if fresh.is_empty() {
+                return Ok(());
+            }
+            fresh.as_slice()
+        } else {
+            signals
+        };
+        let signals = &self.size_signals(signals);
         let mut orders = vec![];
         for signal in signals {
-            let conversion = self.portfolio.maybe_convert(signal).await;
+            let conversion = if self.dry_mode && !signal.dry_mode {
+                let signal = TradeSignal {
+                    dry_mode: true,
+                    ..signal.clone()
+                };
+                self.portfolio.maybe_convert(&signal).await
+            } else {
+                self.portfolio.maybe_convert(signal).await
+            };
             match conversion {
                 Ok(Some(order)) => orders.push(order),
                 Err(e) => error!(err = %e, key = %self.name, pair = %signal.pair, "failed to convert order"),
@@ -127,33 +519,349 @@ impl GenericDriver {
         if orders.len() != signals.len() {
             return Ok(());
         }
+        if let Some(shadow) = self.shadow.as_mut() {
+            for (signal, order) in signals.iter().zip(&orders) {
+                if let Err(e) = shadow.track(signal, order).await {
+                    metrics::get().log_error(e.short_name());
+                    error!(err = %e, "failed to track signal in the shadow portfolio");
+                }
+            }
+        }
+        if self.observe {
+            self.observe_orders(orders).await;
+            return Ok(());
+        }
+        if self.require_confirmation {
+            info!(key = %self.name, orders = ?orders, "orders are pending confirmation");
+            // Replaced orders will never be staged
+            let replaced = std::mem::replace(&mut self.pending_orders, orders);
+            self.release_pending_orders(&replaced);
+            self.persist_pending_orders();
+            return Ok(());
+        }
+        self.stage_orders(orders).await;
+        Ok(())
+    }
+
+    /// Set the quantities of the signals opening positions without a quantity with the position sizer, if any
+    fn size_signals(&self, signals: &[TradeSignal]) -> Vec<TradeSignal> {
+        let mut signals = signals.to_vec();
+        let Some(sizer) = self.position_sizer.as_ref() else {
+            return signals;
+        };
+        let unsized_legs: Vec<usize> = (0..signals.len())
+            .filter(|i| signals[*i].op_kind.is_open() && signals[*i].qty.is_none())
+            .collect();
+        if unsized_legs.is_empty() {
+            return signals;
+        }
+        let legs: Vec<SizingLeg> = unsized_legs
+            .iter()
+            .map(|i| SizingLeg {
+                price: signals[*i].price,
+                volatility: self.volatility.volatility(&signals[*i].xch_and_pair()),
+            })
+            .collect();
+        let quantities = sizer.quantities(self.portfolio.allocatable_value(), &legs);
+        for (i, qty) in unsized_legs.into_iter().zip(quantities) {
+            signals[i].qty = Some(qty);
+        }
+        signals
+    }
+
+    /// Stop trading when the trading session closes, flattening positions if the schedule requires it, and resume
+    /// trading when the next session opens, strategies stopped otherwise are not resumed.
+    /// Positions left open without a closing order while the session is closed, because closing them failed, are
+    /// flattened again.
+    async fn enforce_schedule(&mut self, at: DateTime<Utc>) -> Result<()> {
+        let Some(change) = self
+            .session
+            .change(at, self.is_trading(), self.has_unlocked_positions())
+        else {
+            return Ok(());
+        };
+        match change {
+            SessionChange::Closed { flatten } => {
+                info!(key = %self.name, "trading session closed, trading stops");
+                self.set_status(StrategyStatus::NotTrading)?;
+                self.repo.set_suspended_by_schedule(true)?;
+                self.session.set_suspended(true);
+                if flatten {
+                    self.flatten().await?;
+                }
+            }
+            SessionChange::Reflatten => {
+                warn!(key = %self.name, "positions remain open after the trading session closed, flattening again");
+                self.flatten().await?;
+            }
+            SessionChange::Opened => {
+                self.repo.set_suspended_by_schedule(false)?;
+                self.session.set_suspended(false);
+                if self.status == StrategyStatus::NotTrading {
+                    info!(key = %self.name, "trading session opened, trading resumes");
+                    self.set_status(StrategyStatus::Running)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether an opened position has no order closing it
+    fn has_unlocked_positions(&self) -> bool {
+        self.portfolio
+            .open_positions()
+            .iter()
+            .any(|(key, pos)| pos.is_opened() && !self.portfolio.is_position_locked(key))
+    }
+
+    async fn stage_orders(&mut self, orders: Vec<AddOrderRequest>) {
+        let mut staged = vec![];
         for order in orders {
-            let exchange = order.xch;
-            let pair = order.pair.clone();
-            if let Err(e) = self
-                .engine
-                .order_executor
-                .stage_order(StagedOrder { request: order })
-                .await
-            {
-                // TODO : keep result and immediatly try to close (or retry) failed orders
+            // Assets are borrowed before the order that sells them is placed
+            let borrow = self.portfolio.borrow_query(&order.order_id);
+            if let Some(borrow) = borrow.clone() {
+                if !self.pass_loan(borrow).await {
+                    self.unlock_order(&order);
+                    continue;
+                }
+            }
+            staged.push((order, borrow));
+        }
+        // Orders placed with the same account are sent as one batch, so that the legs of a signal are not placed one
+        // after the other, execution algorithms place their child orders themselves
+        let batched = self.execution.is_none()
+            && staged.len() > 1
+            && staged.iter().all(|(order, _)| {
+                let first = &staged[0].0;
+                order.xch == first.xch && order.account == first.account && order.transaction_id == first.transaction_id
+            });
+        if batched {
+            let orders = staged.iter().map(|(order, _)| order.clone()).collect();
+            if let Err(e) = self.engine.order_executor.stage_orders(orders).await {
                 metrics::get().log_error(e.short_name());
-                error!(err = %e, "failed to stage order");
-                if let Err(e) = self.portfolio.unlock_position(exchange, pair) {
+                error!(err = %e, key = %self.name, "failed to stage order batch");
+                for (order, borrow) in staged {
+                    self.release_order(&order, borrow).await;
+                }
+            }
+        } else {
+            for (order, borrow) in staged {
+                if let Err(e) = self.place_order(order.clone()).await {
+                    // TODO : keep result and immediatly try to close (or retry) failed orders
                     metrics::get().log_error(e.short_name());
-                    error!(err = %e, "failed to unlock position");
+                    error!(err = %e, "failed to stage order");
+                    self.release_order(&order, borrow).await;
                 }
             }
         }
         metrics::get().log_portfolio(self.name.as_str(), &self.portfolio);
+    }
+
+    /// Unlock the position of an order that could not be staged
+    fn unlock_order(&mut self, order: &AddOrderRequest) {
+        let side = order.position_side.unwrap_or_default();
+        if let Err(e) = self.portfolio.unlock_position(order.xch, order.pair.clone(), side) {
+            metrics::get().log_error(e.short_name());
+            error!(err = %e, "failed to unlock position");
+        }
+    }
+
+    /// Unlock the position of an order that could not be staged, and repay what was borrowed for it
+    async fn release_order(&mut self, order: &AddOrderRequest, borrow: Option<OrderQuery>) {
+        self.unlock_order(order);
+        if let Some(OrderQuery::Borrow(loan)) = borrow {
+            self.pass_loan(OrderQuery::Repay(loan)).await;
+        }
+    }
+
+    /// Borrow or repay with the order executor
+    ///
+    /// returns: whether the loan was passed
+    async fn pass_loan(&self, query: OrderQuery) -> bool {
+        match self.engine.order_executor.pass_loan(query.clone()).await {
+            Ok(()) => true,
+            Err(e) => {
+                metrics::get().log_error(e.short_name());
+                error!(err = %e, key = %self.name, query = ?query, "failed to pass margin loan");
+                false
+            }
+        }
+    }
+
+    /// Repay the loans of the positions that were closed, observed positions never borrowed
+    async fn repay_loans(&mut self) {
+        let repayments = match self.portfolio.take_repayments().await {
+            Ok(repayments) => repayments,
+            Err(e) => {
+                metrics::get().log_error(e.short_name());
+                error!(err = %e, key = %self.name, "failed to size loan repayments");
+                return;
+            }
+        };
+        if self.observe {
+            return;
+        }
+        for repayment in repayments {
+            if self.pass_loan(repayment.clone()).await {
+                continue;
+            }
+            // Retried the next time loans are repaid
+            if let OrderQuery::Repay(loan) = repayment {
+                if let Err(e) = self.portfolio.requeue_repayment(loan) {
+                    metrics::get().log_error(e.short_name());
+                    error!(err = %e, key = %self.name, "failed to requeue loan repayment");
+                }
+            }
+        }
+    }
+
+    /// Stage an order with the order manager, or execute it with the execution algorithm of the driver
+    async fn place_order(&self, order: AddOrderRequest) -> Result<()> {
+        match self.execution.as_ref() {
+            Some((executions, algo)) => {
+                executions
+                    .send(Execute {
+                        request: order,
+                        algo: algo.clone(),
+                    })
+                    .await?;
+            }
+            None => {
+                self.engine
+                    .order_executor
+                    .stage_order(StagedOrder { request: order })
+                    .await?;
+            }
+        }
         Ok(())
     }
 
+    /// The latest state of a locked order, aggregated from its child orders if it is executed by an algorithm
+    async fn locked_order(&self, order_id: &str) -> Result<OrderDetail> {
+        if let Some((executions, _)) = self.execution.as_ref() {
+            if let Some(order) = executions.send(ExecutionReport(order_id.to_string())).await? {
+                return Ok(order);
+            }
+        }
+        let (order, _) = self.engine.order_executor.get_order(order_id).await?;
+        Ok(order)
+    }
+
+    /// Log the orders the strategy would place, and fill them in the shadow portfolio
+    async fn observe_orders(&mut self, orders: Vec<AddOrderRequest>) {
+        for order in orders {
+            info!(key = %self.name, order = ?order, "observed order");
+            if let Some(logger) = self.logger.as_ref() {
+                logger
+                    .log(TimedData::new(now(), StratEvent::ObservedOrder(order.clone())))
+                    .await;
+            }
+            let mut detail = OrderDetail::from_query(order.clone());
+            let fees_rate = self
+                .portfolio
+                .fees_rate_for(order.xch, order.asset_type, Some(order.order_type));
+            detail.from_submission(order.simulate_submission(fees_rate));
+            match self.portfolio.update_position(&detail) {
+                Ok(Some(pos)) => {
+                    if let Some(logger) = self.logger.as_ref() {
+                        if let Ok(strat_event) = pos.try_into() {
+                            logger.log(TimedData::new(now(), strat_event)).await;
+                        }
+                    }
+                }
+                Err(e) => {
+                    metrics::get().log_error(e.short_name());
+                    error!(err = %e, "failed to fill observed order");
+                }
+                _ => {}
+            }
+        }
+        self.repay_loans().await;
+        metrics::get().log_portfolio(self.name.as_str(), &self.portfolio);
+    }
+
+    /// Publish and record signals instead of trading them
+    async fn publish_signals(&self, signals: &[TradeSignal]) {
+        let Some(publisher) = self.publisher.as_ref() else {
+            return;
+        };
+        let signals: Vec<PublishedSignal> = signals
+            .iter()
+            .map(|signal| PublishedSignal::new(self.name.as_str(), signal))
+            .collect();
+        if let Err(e) = publisher.publish(&signals).await {
+            metrics::get().log_error(e.short_name());
+            error!(err = %e, key = %self.name, "failed to publish signals");
+        }
+        if let Some(logger) = self.logger.as_ref() {
+            logger
+                .log(TimedData::new(now(), StratEvent::PublishedSignals(signals)))
+                .await;
+        }
+    }
+
+    /// Stage the orders pending confirmation, orders are no longer held afterwards
+    ///
+    /// returns: whether there were orders to confirm
+    async fn confirm_pending_orders(&mut self) -> bool {
+        if self.pending_orders.is_empty() {
+            return false;
+        }
+        info!(key = %self.name, "pending orders confirmed");
+        self.require_confirmation = false;
+        let orders = std::mem::take(&mut self.pending_orders);
+        self.persist_pending_orders();
+        self.stage_orders(orders).await;
+        true
+    }
+
+    /// Drop the orders pending confirmation, subsequent orders are still held
+    ///
+    /// returns: whether there were orders to reject
+    fn reject_pending_orders(&mut self) -> bool {
+        if self.pending_orders.is_empty() {
+            return false;
+        }
+        info!(key = %self.name, "pending orders rejected");
+        let orders = std::mem::take(&mut self.pending_orders);
+        self.release_pending_orders(&orders);
+        self.persist_pending_orders();
+        true
+    }
+
+    /// Release the positions locked by orders that will not be staged
+    fn release_pending_orders(&mut self, orders: &[AddOrderRequest]) {
+        for order in orders {
+            let side = order.position_side.unwrap_or_default();
+            let locked_by_order = self
+                .portfolio
+                .locks()
+                .get(&(order.xch, order.pair.clone(), side))
+                .map_or(false, |lock| lock.order_id == order.order_id);
+            if !locked_by_order {
+                continue;
+            }
+            if let Err(e) = self.portfolio.unlock_position(order.xch, order.pair.clone(), side) {
+                metrics::get().log_error(e.short_name());
+                error!(err = %e, key = %self.name, order_id = %order.order_id, "failed to release pending order");
+            }
+        }
+    }
+
+    fn persist_pending_orders(&self) {
+        if let Err(e) = self.repo.set_pending_orders(&self.pending_orders) {
+            metrics::get().log_error(e.short_name());
+            error!(err = %e, key = %self.name, "failed to persist pending orders");
+        }
+    }
+
     fn indicators(&self) -> PortfolioSnapshot {
         PortfolioSnapshot {
             value: self.portfolio.value(),
             pnl: self.portfolio.pnl(),
             current_return: self.portfolio.current_return(),
+            funding: self.portfolio.funding(),
+            fees: self.portfolio.fees(),
         }
     }
 
@@ -162,17 +870,45 @@ impl GenericDriver {
             metrics::get().log_error(e.short_name());
             error!(err = %e, "failed to update portfolio from market");
         }
-        let signals = {
+        if let Some(shadow) = self.shadow.as_mut() {
+            if let Err(e) = shadow.update_from_market(le).await {
+                metrics::get().log_error(e.short_name());
+                error!(err = %e, "failed to update shadow portfolio from market");
+            }
+        }
+        self.book_indicators.update(le);
+        if self.position_sizer.is_some() {
+            self.volatility
+                .update(&(le.symbol.xch, le.symbol.value.clone()), le.e.vwap(), le.e.time());
+        }
+        let (signals, quote, batch) = {
             let mut inner = self.inner.write().await;
-            inner.eval(le, &self.ctx()).await?
+            let signals = inner.eval(le, &self.ctx()).await?;
+            if let Some(features) = self.features.as_ref() {
+                if let Err(e) = features.record(le, &inner.model()) {
+                    metrics::get().log_error(e.short_name());
+                    error!(err = %e, "failed to record features");
+                }
+            }
+            let ctx = self.ctx();
+            (signals, inner.quote(le, &ctx), inner.order_batch(le, &ctx))
         };
         metrics::get().log_is_trading(self.name.as_str(), self.is_trading());
+        // Quotes rest independently of the positions of the portfolio
+        self.update_quotes(le, quote).await;
+        self.sync_batch().await;
+        if let Some(batch) = batch {
+            self.submit_batch(batch).await;
+        }
         let xch = le.symbol.xch;
         let pair = &le.symbol.value;
         if self.portfolio.has_any_failed_position() {
             metrics::get().log_failed_position(xch, pair);
             return Ok(());
         }
+        if self.is_trading() {
+            self.check_trailing_stops(le.e.time()).await;
+        }
         if !self.portfolio.locks().is_empty() {
             metrics::get().log_lock(xch, pair);
             return Ok(());
@@ -180,7 +916,7 @@ impl GenericDriver {
         if self.is_trading() {
             if let Some(signals) = signals {
                 if !signals.is_empty() {
-                    if let Err(e) = self.process_signals(signals.as_slice()).await {
+                    if let Err(e) = self.process_signals(signals.as_slice(), le.e.time()).await {
                         metrics::get().signal_error(xch, pair);
                         metrics::get().log_error(e.short_name());
                         error!(err = %e, "error processing signals");
@@ -191,81 +927,591 @@ impl GenericDriver {
         Ok(())
     }
 
-    pub fn ctx(&self) -> DefaultStrategyContext {
-        DefaultStrategyContext {
-            portfolio: &self.portfolio,
+    /// Close the open positions whose trailing stop triggered, trailing stop orders are placed for the positions
+    /// trailed by the exchange, and canceled if the stop loss triggers first
+    async fn check_trailing_stops(&mut self, at: DateTime<Utc>) {
+        if !self.stops.is_enabled() {
+            return;
+        }
+        let exchange_manager = &self.engine.exchange_manager;
+        let (exits, cancels) = self
+            .stops
+            .check(self.portfolio.open_positions(), self.portfolio.locks(), |xch| {
+                exchange_manager
+                    .get_api(xch)
+                    .map(|api| api.capabilities())
+                    .unwrap_or_default()
+            });
+        for order_id in cancels {
+            if let Err(e) = self.engine.order_executor.cancel_order(order_id.as_str()).await {
+                metrics::get().log_error(e.short_name());
+                warn!(err = %e, key = %self.name, order_id = %order_id, "failed to cancel trailing stop order");
+            }
+        }
+        let mut orders = vec![];
+        for (key, pos, event) in exits {
+            if let Some(event) = event {
+                info!(key = %self.name, pair = %pos.symbol, event = ?event, "stop triggered, closing position");
+                if let Some(logger) = self.logger.as_ref() {
+                    logger.log(TimedData::new(at, event.into())).await;
+                }
+            }
+            let order = match self.portfolio.maybe_convert(&close_signal(&pos, at)).await {
+                Ok(Some(order)) => order,
+                Err(e) => {
+                    error!(err = %e, key = %self.name, pair = %pos.symbol, "failed to close position");
+                    continue;
+                }
+                Ok(None) => {
+                    warn!(key = %self.name, pair = %pos.symbol, "position could not be closed");
+                    continue;
+                }
+            };
+            let order = match event {
+                // The exchange trails the position until it closes it
+                None => self.stops.exit_order(&key, &pos, order),
+                Some(_) => order,
+            };
+            orders.push(order);
+        }
+        if orders.is_empty() {
+            return;
+        }
+        if self.observe {
+            self.observe_orders(orders).await;
+        } else {
+            self.stage_orders(orders).await;
         }
     }
 
-    fn is_trading(&self) -> bool { matches!(self.status, StrategyStatus::Running) }
-}
-
-#[async_trait]
-impl StrategyDriver for GenericDriver {
-    async fn init(&mut self) -> Result<()> {
-        self.status = match self.repo.get_status()? {
-            None => {
-                if self.start_trading.unwrap_or(true) {
-                    StrategyStatus::Running
-                } else {
-                    StrategyStatus::NotTrading
-                }
+    /// Rest the quotes of the strategy within the quoting limits, quotes are pulled while the strategy is not trading
+    async fn update_quotes(&mut self, le: &MarketEventEnvelope, quote: Option<TwoSidedQuote>) {
+        let Some(options) = self.quotes.options().cloned() else {
+            if quote.is_some() {
+                warn!(key = %self.name, "quotes are ignored without quoting options");
             }
-            Some(s) => s,
+            return;
         };
-        let mut strat = self.inner.write().await;
-        strat.init()?;
-        Ok(())
+        self.sync_quotes().await;
+        if matches!(
+            le.e,
+            MarketEvent::Orderbook(_) | MarketEvent::OrderbookL3(_) | MarketEvent::Quote(_)
+        ) {
+            self.quotes
+                .quoter(&(le.symbol.xch, le.symbol.value.clone()))
+                .on_book(le.e.time());
+        }
+        let Some(quote) = quote else {
+            return;
+        };
+        if self.observe || self.publisher.is_some() {
+            return;
+        }
+        let market = (quote.xch, quote.pair.clone());
+        let trading = self.is_trading();
+        let inventory = self.portfolio.inventory(market.0, market.1.clone());
+        let quoter = self.quotes.quoter(&market);
+        let quote = if trading {
+            options.check(quote, inventory, quoter.book_age(le.e.time()))
+        } else {
+            TwoSidedQuote::pulled(quote.xch, quote.pair)
+        };
+        let actions = quoter.reconcile(&quote, options.reprice_bps, self.dry_mode);
+        self.execute_quote_actions(&market, actions).await;
     }
 
-    async fn key(&self) -> String {
-        let r = self.inner.read().await;
-        r.key()
+    /// Report the executions of the quote orders to the portfolio, from the order updates of the order manager,
+    /// quote orders are only queried once subscribed or if updates were missed
+    async fn sync_quotes(&mut self) {
+        if self.quotes.options().is_none() {
+            return;
+        }
+        let orders = self
+            .quotes
+            .updated_orders(self.engine.order_executor.as_ref(), self.name.as_str())
+            .await;
+        if orders.is_empty() {
+            return;
+        }
+        for (market, fill) in self.quotes.on_orders(&orders) {
+            if let Err(e) = self
+                .portfolio
+                .record_inventory_fill(market, fill.side, fill.qty, fill.price, fill.fees)
+            {
+                metrics::get().log_error(e.short_name());
+                error!(err = %e, key = %self.name, order_id = %fill.order_id, "failed to record quote fill");
+            }
+        }
+        self.persist_quoters();
     }
 
-    async fn on_market_event(&mut self, le: &MarketEventEnvelope) -> Result<()> {
-        // TODO: return an error instead if not initialized ?
-        if !self.initialized {
-            self.init().await.unwrap();
-            self.initialized = true;
-        }
-        self.last_event = Some(le.clone());
-        self.process_event(le).await.map_err(|e| {
+    fn persist_quoters(&self) {
+        if let Err(e) = self.repo.set_quoters(self.quotes.quoters()) {
             metrics::get().log_error(e.short_name());
-            e
-        })
+            error!(err = %e, key = %self.name, "failed to persist quotes");
+        }
     }
 
-    async fn query(&mut self, q: DataQuery) -> Result<DataResult> {
-        match q {
-            DataQuery::CancelOngoingOp => Ok(DataResult::Success(false)),
-            DataQuery::Models => {
-                let inner = self.inner.read().await;
-                Ok(DataResult::Models(inner.model()))
+    /// Place, amend or cancel the quote orders of a market, failed actions are retried on the next quote
+    async fn execute_quote_actions(&mut self, market: &(Exchange, Pair), actions: Vec<QuoteAction>) {
+        if actions.is_empty() {
+            return;
+        }
+        for action in actions {
+            let executor = &self.engine.order_executor;
+            let result = match &action {
+                QuoteAction::Place(request) => executor
+                    .stage_order(StagedOrder {
+                        request: request.clone(),
+                    })
+                    .await
+                    .map(|_| ()),
+                QuoteAction::Amend(request) => executor.amend_order(request.clone()).await.map(|_| ()),
+                QuoteAction::Cancel(order_id) => executor.cancel_order(order_id.as_str()).await,
+            };
+            match result {
+                Ok(()) => {
+                    if let Some(quoter) = self.quotes.quoter_mut(market) {
+                        quoter.apply(&action);
+                    }
+                }
+                Err(e) => {
+                    metrics::get().log_error(e.short_name());
+                    warn!(key = %self.name, err = %e, "failed to update quote");
+                }
             }
-            DataQuery::Status => Ok(DataResult::Status(self.status())),
-            DataQuery::Indicators => Ok(DataResult::Indicators(self.indicators())),
-            DataQuery::PositionHistory => Ok(DataResult::PositionHistory(self.portfolio.positions_history()?)),
-            DataQuery::OpenPositions => Ok(DataResult::OpenPositions(
-                self.portfolio
-                    .open_positions()
-                    .values()
-                    .cloned()
-                    .collect::<Vec<Position>>(),
-            )),
         }
+        self.persist_quoters();
     }
 
-    fn mutate(&mut self, m: Mutation) -> Result<()> {
-        match m {
-            Mutation::State(m) => {
-                match m.field {
-                    MutableField::ValueStrat => self.portfolio.set_value(m.value)?,
-                    MutableField::Pnl => self.portfolio.set_pnl(m.value)?,
-                }
-                Ok(())
-            }
-            Mutation::Model(ModelReset { name: _, .. }) => {
+    /// Flatten the inventories accumulated by the quotes with market orders
+    async fn unwind_inventories(&mut self) {
+        let inventories: Vec<((Exchange, Pair), f64)> = self
+            .portfolio
+            .inventories()
+            .filter(|i| i.qty != 0.0)
+            .map(|i| ((i.xch, i.pair.clone()), i.qty))
+            .collect();
+        for (market, qty) in inventories {
+            let unwind = self
+                .quotes
+                .quoter(&market)
+                .unwind(market.0, market.1.clone(), qty, self.dry_mode);
+            self.execute_quote_actions(&market, unwind.into_iter().collect()).await;
+        }
+    }
+
+    /// Cancel the resting quotes of every market
+    async fn pull_quotes(&mut self) {
+        for market in self.quotes.markets() {
+            let pulled = TwoSidedQuote::pulled(market.0, market.1.clone());
+            let actions = self.quotes.quoter(&market).reconcile(&pulled, 0.0, self.dry_mode);
+            self.execute_quote_actions(&market, actions).await;
+        }
+    }
+
+    /// Submit the orders of a batch together, once the orders of the previous batch are resolved
+    async fn submit_batch(&mut self, mut batch: OrderBatch) {
+        if !self.is_trading() || self.observe || self.publisher.is_some() || batch.orders.is_empty() {
+            return;
+        }
+        if self.batch.is_pending() {
+            debug!(key = %self.name, "the previous order batch is not resolved, batch dropped");
+            return;
+        }
+        if self.dry_mode {
+            for order in &mut batch.orders {
+                order.dry_run = true;
+            }
+        }
+        let legs = batch
+            .orders
+            .iter()
+            .map(|order| BatchLeg::new(order.clone(), false))
+            .collect();
+        if self.stage_batch(batch.orders, legs).await {
+            metrics::get().log_batch_latency(
+                self.name.as_str(),
+                batch.received_at - batch.event_time,
+                batch.signal_time - batch.received_at,
+                now() - batch.signal_time,
+            );
+        }
+    }
+
+    /// Stage the orders of a batch, the legs are persisted until they are all resolved
+    async fn stage_batch(&mut self, orders: Vec<AddOrderRequest>, legs: Vec<BatchLeg>) -> bool {
+        match self.engine.order_executor.stage_orders(orders).await {
+            Ok(_) => {
+                self.batch.start(legs);
+                self.persist_batch();
+                true
+            }
+            Err(e) => {
+                metrics::get().log_error(e.short_name());
+                error!(err = %e, key = %self.name, "failed to stage order batch");
+                false
+            }
+        }
+    }
+
+    /// Report the executions of the last batch to the portfolio, and forget the batch once its orders are resolved,
+    /// batches are not atomic on exchanges so the executions of a batch that did not fill in full are unwound
+    async fn sync_batch(&mut self) {
+        if !self.batch.is_pending() {
+            return;
+        }
+        for (request, fill) in self.batch.poll(self.engine.order_executor.as_ref()).await {
+            let market = (request.xch, request.pair.clone());
+            if let Err(e) = self
+                .portfolio
+                .record_inventory_fill(market, fill.side, fill.qty, fill.price, fill.fees)
+            {
+                metrics::get().log_error(e.short_name());
+                error!(err = %e, key = %self.name, order_id = %fill.order_id, "failed to record batch fill");
+            }
+            self.inner.write().await.on_batch_fill(&request, &fill);
+        }
+        let unwinds = self.batch.settle(self.name.as_str());
+        self.persist_batch();
+        let Some(unwinds) = unwinds.filter(|unwinds| !unwinds.is_empty()) else {
+            return;
+        };
+        warn!(key = %self.name, legs = unwinds.len(), "order batch did not execute in full, unwinding its executions");
+        let legs = unwinds.iter().map(|order| BatchLeg::new(order.clone(), true)).collect();
+        self.stage_batch(unwinds, legs).await;
+    }
+
+    fn persist_batch(&self) {
+        if let Err(e) = self.repo.set_batch_orders(self.batch.legs()) {
+            metrics::get().log_error(e.short_name());
+            error!(err = %e, key = %self.name, "failed to persist order batch");
+        }
+    }
+
+    /// Account trades reported by the broker for a filled order, empty if the broker cannot report them
+    async fn reported_trades(&mut self, order: &OrderDetail) -> Vec<TradeFill> {
+        if !order.is_filled() {
+            return vec![];
+        }
+        if let Some(trades) = self.reported_trades.get(&order.id) {
+            return trades.clone();
+        }
+        let Some(api) = Exchange::from_str(&order.exchange)
+            .ok()
+            .and_then(|xchg| self.engine.exchange_manager.get_api(xchg))
+            .filter(|api| api.uses_account())
+        else {
+            return vec![];
+        };
+        let trades = match api.my_trades(order.symbol.clone().into(), order.open_at).await {
+            Ok(trades) => trades,
+            Err(brokers::error::Error::BrokerFeatureNotImplemented) => vec![],
+            Err(e) => {
+                warn!(err = %e, order_id = %order.id, "failed to fetch account trades, using estimated fees");
+                return vec![];
+            }
+        };
+        self.reported_trades.insert(order.id.clone(), trades.clone());
+        trades
+    }
+
+    pub fn ctx(&self) -> DefaultStrategyContext {
+        DefaultStrategyContext {
+            portfolio: &self.portfolio,
+            book_indicators: &self.book_indicators,
+            quoters: self.quotes.quoters(),
+        }
+    }
+
+    fn is_trading(&self) -> bool { matches!(self.status, StrategyStatus::Running) }
+}
+
+/// Keep signals that are at most `max_age` old at time `at`, stale signals are logged and dropped
+///
+/// Signals sharing a trace id or an event with a stale signal are legs of the same batch,
+/// they are dropped as well so that a fresh leg is never staged unhedged
+fn fresh_signals(strat_key: &str, signals: &[TradeSignal], at: DateTime<Utc>, max_age: Duration) -> Vec<TradeSignal> {
+    let mut stale_traces = HashSet::new();
+    let mut stale_events = HashSet::new();
+    for signal in signals.iter().filter(|signal| signal.is_stale(at, max_age)) {
+        metrics::get().stale_signal(strat_key, signal.exchange, &signal.pair);
+        warn!(key = %strat_key, pair = %signal.pair, age = %signal.age(at), "dropping stale signal");
+        if !signal.trace_id.is_nil() {
+            stale_traces.insert(signal.trace_id);
+        }
+        stale_events.insert(signal.event_time);
+    }
+    signals
+        .iter()
+        .filter(|signal| {
+            let batched = stale_traces.contains(&signal.trace_id) || stale_events.contains(&signal.event_time);
+            if batched && !signal.is_stale(at, max_age) {
+                warn!(key = %strat_key, pair = %signal.pair, trace_id = %signal.trace_id, "dropping leg of a stale batch");
+            }
+            !batched
+        })
+        .cloned()
+        .collect()
+}
+
+/// A signal to close a position with a market order
+fn close_signal(pos: &Position, at: DateTime<Utc>) -> TradeSignal {
+    TradeSignal {
+        event_time: at,
+        signal_time: at,
+        pos_kind: pos.kind,
+        op_kind: OperationKind::Close,
+        trade_kind: if pos.is_long() { TradeKind::Sell } else { TradeKind::Buy },
+        price: pos.current_symbol_price,
+        pair: pos.symbol.clone(),
+        exchange: pos.exchange,
+        order_type: OrderType::Market,
+        dry_mode: pos.open_order.as_ref().map_or(false, |o| o.is_test),
+        asset_type: pos.open_order.as_ref().map(|o| o.asset_type),
+        account: pos.open_order.as_ref().and_then(|o| o.account.clone()),
+        ..TradeSignal::default()
+    }
+}
+
+/// Pause trading on the exchange of an order that was rejected because of maintenance
+///
+/// returns: whether trading was paused
+fn pause_on_maintenance(
+    maintenance: &MaintenanceRegistry,
+    order: &OrderDetail,
+    at: DateTime<Utc>,
+    pause: Duration,
+) -> bool {
+    if !order.is_maintenance() {
+        return false;
+    }
+    let Ok(xchg) = Exchange::from_str(&order.exchange) else {
+        return false;
+    };
+    warn!(xchg = %xchg, order_id = %order.id, until = %(at + pause), "exchange under maintenance, pausing trading");
+    maintenance.start(xchg, at + pause);
+    true
+}
+
+/// Log a [`StratEvent::CircuitBreakerReset`] event if trading resumed after a trip, and a
+/// [`StratEvent::CircuitBreakerTripped`] event if a risk limit is breached
+///
+/// returns: whether the breaker tripped
+async fn log_circuit_breaker(
+    logger: Option<&StratEventLoggerRef>,
+    tripped: &mut bool,
+    breach: Option<String>,
+    at: DateTime<Utc>,
+) -> bool {
+    let mut events = vec![];
+    if *tripped {
+        *tripped = false;
+        info!("trading resumed after the circuit breaker tripped");
+        events.push(StratEvent::CircuitBreakerReset);
+    }
+    if let Some(reason) = breach {
+        *tripped = true;
+        warn!(reason = %reason, "circuit breaker tripped, trading will stop");
+        events.push(StratEvent::CircuitBreakerTripped { reason });
+    }
+    if let Some(logger) = logger {
+        for event in events {
+            logger.log(TimedData::new(at, event)).await;
+        }
+    }
+    *tripped
+}
+
+/// Log a [`StratEvent::RiskThrottled`] event when the size multiplier differs from the `logged` one
+///
+/// returns: whether the event was logged
+async fn log_risk_throttle(
+    logger: Option<&StratEventLoggerRef>,
+    logged: &mut f64,
+    multiplier: f64,
+    at: DateTime<Utc>,
+) -> bool {
+    if (multiplier - *logged).abs() < f64::EPSILON {
+        return false;
+    }
+    *logged = multiplier;
+    info!(multiplier = multiplier, "position sizing changed");
+    if let Some(logger) = logger {
+        logger
+            .log(TimedData::new(at, StratEvent::RiskThrottled { multiplier }))
+            .await;
+    }
+    true
+}
+
+/// Reject channels and order configurations that the exchanges of the driver do not support
+fn check_capabilities(
+    engine: &TradingEngine,
+    channels: &HashSet<MarketChannel>,
+    order_conf: Option<&OrderConf>,
+) -> Result<()> {
+    for channel in channels {
+        let Some(api) = engine.exchange_manager.get_api(channel.exchange()) else {
+            continue;
+        };
+        let capabilities = api.capabilities();
+        capabilities.check_channel(channel)?;
+        if let Some(order_conf) = order_conf {
+            order_conf.check_capabilities(&capabilities)?;
+        }
+    }
+    Ok(())
+}
+
+/// Trade channels of the markets of `channels` that VWAP executions need for their volume, and that the strategy
+/// did not subscribe to
+fn execution_channels(channels: &HashSet<MarketChannel>, algo: Option<&ExecutionAlgo>) -> HashSet<MarketChannel> {
+    if !matches!(algo, Some(ExecutionAlgo::Vwap { .. })) {
+        return HashSet::new();
+    }
+    channels
+        .iter()
+        .filter(|c| {
+            !channels
+                .iter()
+                .any(|other| other.symbol == c.symbol && other.r#type == MarketChannelType::Trades)
+        })
+        .map(|c| {
+            MarketChannel::builder()
+                .symbol(c.symbol.clone())
+                .r#type(MarketChannelType::Trades)
+                .build()
+        })
+        .collect()
+}
+
+/// Anticipate fees with the fee tiers of the account the strategy trades with on the exchanges of the driver
+fn with_fee_providers(
+    mut portfolio: Portfolio,
+    engine: &TradingEngine,
+    channels: &HashSet<MarketChannel>,
+    account: Option<&str>,
+) -> Portfolio {
+    let exchanges: HashSet<Exchange> = channels.iter().map(MarketChannel::exchange).collect();
+    for xch in exchanges {
+        if let Some(provider) = engine.exchange_manager.get_account_fees_provider(xch, account) {
+            portfolio = portfolio.with_fee_provider(xch, provider);
+        }
+    }
+    portfolio
+}
+
+/// Whether any of the signals targets an exchange that is under maintenance
+fn under_maintenance(maintenance: &MaintenanceRegistry, signals: &[TradeSignal], at: DateTime<Utc>) -> bool {
+    signals
+        .iter()
+        .any(|signal| maintenance.is_under_maintenance(signal.exchange, at))
+}
+
+#[async_trait]
+impl StrategyDriver for GenericDriver {
+    async fn init(&mut self) -> Result<()> {
+        self.status = match self.repo.get_status()? {
+            None => {
+                if self.start_trading.unwrap_or(true) {
+                    StrategyStatus::Running
+                } else {
+                    StrategyStatus::NotTrading
+                }
+            }
+            Some(s) => s,
+        };
+        self.session.set_suspended(self.repo.is_suspended_by_schedule()?);
+        self.quotes.restore(self.repo.get_quoters()?);
+        self.pending_orders = self.repo.get_pending_orders()?;
+        self.batch.restore(self.repo.get_batch_orders()?);
+        let mut strat = self.inner.write().await;
+        self.repo.migrate_models(&strat.migrations())?;
+        strat.init()?;
+        Ok(())
+    }
+
+    async fn key(&self) -> String {
+        let r = self.inner.read().await;
+        r.key()
+    }
+
+    async fn on_market_event(&mut self, le: &MarketEventEnvelope) -> Result<()> {
+        // TODO: return an error instead if not initialized ?
+        if !self.initialized {
+            self.init().await.unwrap();
+            self.initialized = true;
+        }
+        self.last_event = Some(le.clone());
+        if let (Some((executions, _)), MarketEvent::Trade(_)) = (self.execution.as_ref(), &le.e) {
+            executions.do_send(Arc::new(le.clone()));
+        }
+        let topic = MarketChannelTopic::from(le);
+        if self
+            .execution_channels
+            .iter()
+            .any(|c| MarketChannelTopic::from(c) == topic)
+        {
+            return Ok(());
+        }
+        self.check_schedule(le.e.time()).await;
+        let aggregated = self.aggregations.aggregate(le);
+        let mut result = if self.aggregations.passes_through(le) {
+            self.process_event(le).await
+        } else {
+            Ok(())
+        };
+        for candle in &aggregated {
+            let candle_result = self.process_event(candle).await;
+            result = result.and(candle_result);
+        }
+        let result = result.map_err(|e| {
+            metrics::get().log_error(e.short_name());
+            e
+        });
+        // Advance marks on the event clock, which is the only clock in backtests
+        self.mark_to_market(le.e.time()).await;
+        result
+    }
+
+    async fn query(&mut self, q: DataQuery) -> Result<DataResult> {
+        match q {
+            DataQuery::CancelOngoingOp => Ok(DataResult::Success(false)),
+            DataQuery::PendingOrders => Ok(DataResult::PendingOrders(self.pending_orders.clone())),
+            DataQuery::ConfirmPendingOrders => Ok(DataResult::Success(self.confirm_pending_orders().await)),
+            DataQuery::RejectPendingOrders => Ok(DataResult::Success(self.reject_pending_orders())),
+            DataQuery::Models => {
+                let inner = self.inner.read().await;
+                Ok(DataResult::Models(inner.model()))
+            }
+            DataQuery::Features => Ok(DataResult::Features(match self.features.as_ref() {
+                Some(features) => features.dataset()?,
+                None => FeatureDataset::default(),
+            })),
+            DataQuery::Status => Ok(DataResult::Status(self.status())),
+            DataQuery::Indicators => Ok(DataResult::Indicators(self.indicators())),
+            DataQuery::PositionHistory => Ok(DataResult::PositionHistory(self.portfolio.positions_history()?)),
+            DataQuery::OpenPositions => Ok(DataResult::OpenPositions(
+                self.portfolio
+                    .open_positions()
+                    .values()
+                    .cloned()
+                    .collect::<Vec<Position>>(),
+            )),
+        }
+    }
+
+    fn mutate(&mut self, m: Mutation) -> Result<()> {
+        match m {
+            Mutation::State(m) => {
+                match m.field {
+                    MutableField::ValueStrat => self.portfolio.set_value(m.value)?,
+                    MutableField::Pnl => self.portfolio.set_pnl(m.value)?,
+                }
+                Ok(())
+            }
+            Mutation::Model(ModelReset { name: _, .. }) => {
                 unimplemented!()
             }
         }
@@ -277,34 +1523,107 @@ impl StrategyDriver for GenericDriver {
 
     fn resume_trading(&mut self) -> Result<()> { self.set_status(StrategyStatus::Running) }
 
+    async fn flatten(&mut self) -> Result<()> {
+        // Positions locked by a resting order are released by canceling the order first
+        self.resolve_orders().await;
+        let locking_orders: Vec<String> = self.portfolio.locks().values().map(|l| l.order_id.clone()).collect();
+        if !locking_orders.is_empty() {
+            for order_id in locking_orders {
+                if let Err(e) = self.engine.order_executor.cancel_order(order_id.as_str()).await {
+                    metrics::get().log_error(e.short_name());
+                    warn!(err = %e, key = %self.name, order_id = %order_id, "failed to cancel locking order");
+                }
+            }
+            self.resolve_orders().await;
+        }
+        let at = now();
+        let signals: Vec<TradeSignal> = self
+            .portfolio
+            .open_positions()
+            .values()
+            .filter(|pos| pos.is_opened())
+            .map(|pos| close_signal(pos, at))
+            .collect();
+        info!(key = %self.name, positions = signals.len(), "flattening open positions");
+        self.sync_quotes().await;
+        self.sync_batch().await;
+        self.pull_quotes().await;
+        self.unwind_inventories().await;
+        let mut orders = vec![];
+        for signal in &signals {
+            match self.portfolio.maybe_convert(signal).await {
+                Ok(Some(order)) => orders.push(order),
+                Err(e) => error!(err = %e, key = %self.name, pair = %signal.pair, "failed to close position"),
+                _ => warn!(key = %self.name, pair = %signal.pair, "position could not be closed"),
+            }
+        }
+        if self.observe {
+            self.observe_orders(orders).await;
+        } else {
+            self.stage_orders(orders).await;
+        }
+        Ok(())
+    }
+
     async fn resolve_orders(&mut self) {
         if self.portfolio.locks().is_empty() {
             return;
         }
         // TODO : bad performance overall
-        let locked_ids: Vec<String> = self.portfolio.locks().values().map(|v| v.order_id.clone()).collect();
+        let locked_ids: Vec<String> = self
+            .portfolio
+            .locks()
+            .values()
+            .map(|v| v.order_id.clone())
+            // Orders pending confirmation were never staged
+            .filter(|id| !self.pending_orders.iter().any(|o| &o.order_id == id))
+            .collect();
         for lock in &locked_ids {
-            match self.engine.order_executor.get_order(lock.as_str()).await {
-                Ok((order, _)) => match self.portfolio.update_position(&order) {
-                    Ok(Some(pos)) => {
-                        if let Some(logger) = self.logger.as_ref() {
-                            if let Ok(strat_event) = pos.try_into() {
-                                logger.log(TimedData::new(now(), strat_event)).await;
+            match self.locked_order(lock.as_str()).await {
+                Ok(order) => {
+                    pause_on_maintenance(
+                        self.engine.exchange_manager.maintenance(),
+                        &order,
+                        now(),
+                        self.maintenance_pause,
+                    );
+                    if let Some(shadow) = self.shadow.as_mut() {
+                        shadow.resolve(&order);
+                        metrics::get().log_divergence(self.name.as_str(), &shadow.divergence(&self.portfolio));
+                    }
+                    let trades = self.reported_trades(&order).await;
+                    match self.portfolio.update_position_with_trades(&order, &trades) {
+                        Ok(Some(pos)) => {
+                            if let Some(logger) = self.logger.as_ref() {
+                                if let Ok(strat_event) = pos.try_into() {
+                                    logger.log(TimedData::new(now(), strat_event)).await;
+                                }
                             }
                         }
+                        Err(e) => {
+                            metrics::get().log_error(e.short_name());
+                            debug!(err = %e, "failed to update portfolio position");
+                        }
+                        _ => {}
                     }
-                    Err(e) => {
-                        metrics::get().log_error(e.short_name());
-                        debug!(err = %e, "failed to update portfolio position");
-                    }
-                    _ => {}
-                },
+                }
                 Err(e) => {
                     metrics::get().log_error(e.short_name());
                     debug!(err = %e, "failed to query locked order");
                 }
             }
         }
+        let locks = self.portfolio.locks();
+        self.reported_trades
+            .retain(|id, _| locks.values().any(|lock| &lock.order_id == id));
+        self.repay_loans().await;
+        log_risk_throttle(
+            self.logger.as_ref(),
+            &mut self.size_multiplier,
+            self.portfolio.size_multiplier(),
+            now(),
+        )
+        .await;
         if !locked_ids.is_empty() && self.portfolio.locks().is_empty() {
             let mut inner_w = self.inner.write().await;
             if let Some(event) = self.last_event.as_ref() {
@@ -317,4 +1636,1072 @@ impl StrategyDriver for GenericDriver {
     }
 
     async fn is_locked(&self) -> bool { !self.portfolio.locks().is_empty() }
+
+    fn snapshot(&self) -> Result<Option<Snapshot>> { Ok(Some(Snapshot::take(self.db.as_ref())?)) }
+
+    fn restore(&mut self, snapshot: &Snapshot) -> Result<()> {
+        snapshot.restore(self.db.as_ref())?;
+        self.portfolio.reload()?;
+        // Driver state and models persisted by the strategy are loaded from the restored database on init
+        self.initialized = false;
+        Ok(())
+    }
+
+    fn expected_balances(&self, xch: Exchange, account: &AccountType) -> HashMap<Asset, f64> {
+        self.portfolio.expected_balances(xch, account)
+    }
+
+    async fn check_schedule(&mut self, at: DateTime<Utc>) {
+        if let Err(e) = self.enforce_schedule(at).await {
+            metrics::get().log_error(e.short_name());
+            error!(err = %e, key = %self.name, "failed to enforce the trading schedule");
+        }
+    }
+
+    async fn check_risk(&mut self, at: DateTime<Utc>) -> bool {
+        if !self.is_trading() {
+            return false;
+        }
+        let breach = self
+            .risk
+            .as_ref()
+            .and_then(|risk| risk.check_daily_loss(&self.portfolio, at))
+            .map(|breach| breach.to_string());
+        let tripped = log_circuit_breaker(self.logger.as_ref(), &mut self.breaker_tripped, breach, at).await;
+        if tripped {
+            metrics::get().log_error("circuit_breaker_tripped");
+        }
+        tripped
+    }
+
+    async fn mark_to_market(&mut self, at: DateTime<Utc>) {
+        if self.portfolio.has_any_open_position()
+            && self
+                .last_funding_poll
+                .map_or(true, |last| at - last >= self.funding_poll_interval)
+        {
+            self.last_funding_poll = Some(at);
+            if let Err(e) = self.portfolio.poll_funding(&self.engine.exchange_manager).await {
+                metrics::get().log_error(e.short_name());
+                error!(err = %e, "failed to poll funding payments");
+            }
+        }
+        let Some(interval) = self.mark_to_market_interval else {
+            return;
+        };
+        if !self.portfolio.has_any_open_position() || self.last_mark.map_or(false, |last| at - last < interval) {
+            return;
+        }
+        self.last_mark = Some(at);
+        if let Err(e) = self.portfolio.mark_to_market(&self.engine.exchange_manager, at).await {
+            metrics::get().log_error(e.short_name());
+            error!(err = %e, "failed to mark positions to market");
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::{HashMap, HashSet};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    use chrono::{Duration, TimeZone, Utc};
+    use tokio::sync::broadcast;
+
+    use brokers::maintenance::MaintenanceRegistry;
+    use brokers::manager::BrokerageManager;
+    use brokers::prelude::*;
+    use brokers::types::{AmendOrderRequest, MarginLoanRequest, MarketChannelType, SecurityType, Symbol};
+    use trading::engine::TradingEngine;
+    use trading::execution::{ExecutionAlgo, ExecutionReport, ExecutionTick};
+    use trading::interest::FlatInterestRateProvider;
+    use trading::order_manager::types::{OrderDetail, OrderStatus, Rejection, StagedOrder, Transaction};
+    use trading::order_manager::{OrderExecutor, OrderResolution};
+    use trading::quoting::{QuoteLevel, TwoSidedQuote};
+    use trading::signal::{OrderBatch, TradeSignal};
+    use trading::sizing::PositionSizerOptions;
+    use trading::stop::{StopEvent, TrailingStopOptions};
+    use trading::types::TradeOperation;
+    use util::time::{now, TimedData};
+
+    use super::{execution_channels, fresh_signals, log_circuit_breaker, log_risk_throttle, pause_on_maintenance,
+                under_maintenance, GenericDriver, GenericDriverOptions, PortfolioOptions};
+    use crate::driver::{DefaultStrategyContext, Strategy, StrategyDriver, TradeSignals};
+    use crate::generic::repo::DriverRepository;
+    use crate::models::io::SerializedModel;
+    use crate::publish::{PublishedSignal, SignalPublisher};
+    use crate::query::{DataQuery, DataResult};
+    use crate::test_util::fixtures::default_order_book_event;
+    use crate::test_util::test_db;
+    use crate::types::StratEvent;
+    use crate::MarketChannel;
+    use crate::{EventLogger, StratEventLoggerRef, StrategyStatus};
+
+    #[derive(Debug, Default)]
+    struct CapturingLogger {
+        events: Mutex<Vec<TimedData<StratEvent>>>,
+    }
+
+    #[async_trait]
+    impl EventLogger<TimedData<StratEvent>> for CapturingLogger {
+        async fn log(&self, event: TimedData<StratEvent>) { self.events.lock().unwrap().push(event); }
+    }
+
+    #[derive(Default)]
+    struct RecordingPublisher {
+        published: Mutex<Vec<Vec<PublishedSignal>>>,
+    }
+
+    #[async_trait]
+    impl SignalPublisher for RecordingPublisher {
+        async fn publish(&self, signals: &[PublishedSignal]) -> crate::error::Result<()> {
+            self.published.lock().unwrap().push(signals.to_vec());
+            Ok(())
+        }
+    }
+
+    #[derive(Debug, Default)]
+    struct RecordingExecutor {
+        staged: Mutex<Vec<AddOrderRequest>>,
+        /// Order ids of the batches staged together
+        batches: Mutex<Vec<Vec<String>>>,
+        amended: Mutex<Vec<AmendOrderRequest>>,
+        canceled: Mutex<Vec<String>>,
+        loans: Mutex<Vec<OrderQuery>>,
+        /// Reject margin loans, as an unreachable exchange would
+        reject_loans: AtomicBool,
+        updates: Mutex<Option<broadcast::Sender<OrderDetail>>>,
+        /// Latest published state of the orders
+        orders: Mutex<HashMap<String, OrderDetail>>,
+    }
+
+    impl RecordingExecutor {
+        /// Publish an order update to the subscribers
+        fn publish(&self, order: OrderDetail) {
+            self.orders.lock().unwrap().insert(order.id.clone(), order.clone());
+            if let Some(updates) = self.updates.lock().unwrap().as_ref() {
+                updates.send(order).unwrap();
+            }
+        }
+    }
+
+    #[async_trait]
+    impl OrderExecutor for RecordingExecutor {
+        async fn stage_order(&self, staged_order: StagedOrder) -> trading::order_manager::error::Result<OrderDetail> {
+            self.staged.lock().unwrap().push(staged_order.request.clone());
+            Ok(OrderDetail::from_query(staged_order.request))
+        }
+
+        async fn stage_orders(
+            &self,
+            orders: Vec<AddOrderRequest>,
+        ) -> trading::order_manager::error::Result<Vec<OrderDetail>> {
+            self.staged.lock().unwrap().extend(orders.iter().cloned());
+            self.batches
+                .lock()
+                .unwrap()
+                .push(orders.iter().map(|order| order.order_id.clone()).collect());
+            Ok(orders.into_iter().map(OrderDetail::from_query).collect())
+        }
+
+        async fn stage_trade(&self, _trade: &TradeOperation) -> trading::order_manager::error::Result<OrderDetail> {
+            unimplemented!()
+        }
+
+        async fn resolve_pending_order(
+            &self,
+            _order: &OrderDetail,
+        ) -> trading::order_manager::error::Result<(OrderDetail, Option<Transaction>, OrderResolution)> {
+            unimplemented!()
+        }
+
+        async fn get_order(
+            &self,
+            order_id: &str,
+        ) -> trading::order_manager::error::Result<(OrderDetail, Option<Transaction>)> {
+            if let Some(order) = self.orders.lock().unwrap().get(order_id) {
+                return Ok((order.clone(), None));
+            }
+            let staged = self.staged.lock().unwrap();
+            let request = staged.iter().find(|o| o.order_id == order_id).unwrap();
+            Ok((OrderDetail::from_query(request.clone()), None))
+        }
+
+        async fn amend_order(&self, request: AmendOrderRequest) -> trading::order_manager::error::Result<OrderDetail> {
+            self.amended.lock().unwrap().push(request.clone());
+            let (mut order, _) = self.get_order(&request.order_id).await?;
+            order.from_amendment(request);
+            Ok(order)
+        }
+
+        async fn cancel_order(&self, order_id: &str) -> trading::order_manager::error::Result<()> {
+            self.canceled.lock().unwrap().push(order_id.to_string());
+            let staged = self
+                .staged
+                .lock()
+                .unwrap()
+                .iter()
+                .find(|o| o.order_id == order_id)
+                .cloned();
+            if let Some(request) = staged {
+                let mut canceled = OrderDetail::from_query(request);
+                canceled.status = OrderStatus::Canceled;
+                self.orders.lock().unwrap().insert(order_id.to_string(), canceled);
+            }
+            Ok(())
+        }
+
+        async fn pass_loan(&self, query: OrderQuery) -> trading::order_manager::error::Result<()> {
+            if self.reject_loans.load(Ordering::Relaxed) {
+                return Err(trading::order_manager::error::Error::OrderManagerMailboxError);
+            }
+            self.loans.lock().unwrap().push(query);
+            Ok(())
+        }
+
+        async fn subscribe_orders(&self) -> trading::order_manager::error::Result<broadcast::Receiver<OrderDetail>> {
+            Ok(self
+                .updates
+                .lock()
+                .unwrap()
+                .get_or_insert_with(|| broadcast::channel(16).0)
+                .subscribe())
+        }
+    }
+
+    fn test_options() -> GenericDriverOptions {
+        GenericDriverOptions {
+            portfolio: PortfolioOptions {
+                initial_quote_cash: 100.0,
+                fees_rate: 0.001,
+                target_volatility: None,
+                volatility_model: None,
+                drawdown_throttle: None,
+                position_mode: PositionMode::default(),
+                simulate_funding: false,
+                simulate_marks: false,
+                risk_limits: None,
+            },
+            start_trading: None,
+            dry_mode: None,
+            max_signal_age: None,
+            maintenance_pause: None,
+            mark_to_market_interval: None,
+            funding_poll_interval: None,
+            require_confirmation: None,
+            observe: None,
+            shadow: None,
+            position_sizer: None,
+            schedule: None,
+            quoting: None,
+            signal_only: None,
+            execution: None,
+            trailing_stop: None,
+        }
+    }
+
+    /// Emits no signals, and quotes or submits order batches if configured to
+    #[derive(Default)]
+    struct TestStrategy {
+        /// Quote around the mid price of order books, by a spread that widens on each book
+        quoting: bool,
+        /// Submit a batch of a buy and a sell on every event
+        batching: bool,
+        books: u32,
+    }
+
+    impl TestStrategy {
+        fn quoting() -> Self {
+            Self {
+                quoting: true,
+                ..Self::default()
+            }
+        }
+
+        fn batching() -> Self {
+            Self {
+                batching: true,
+                ..Self::default()
+            }
+        }
+    }
+
+    #[async_trait]
+    impl Strategy for TestStrategy {
+        fn key(&self) -> String { "test".to_string() }
+
+        fn init(&mut self) -> crate::error::Result<()> { Ok(()) }
+
+        async fn eval(
+            &mut self,
+            _e: &MarketEventEnvelope,
+            _ctx: &DefaultStrategyContext,
+        ) -> crate::error::Result<Option<TradeSignals>> {
+            Ok(None)
+        }
+
+        fn quote(&mut self, e: &MarketEventEnvelope, _ctx: &DefaultStrategyContext) -> Option<TwoSidedQuote> {
+            let MarketEvent::Orderbook(book) = &e.e else {
+                return None;
+            };
+            if !self.quoting {
+                return None;
+            }
+            self.books += 1;
+            let mid = (book.asks[0].0 + book.bids[0].0) / 2.0;
+            let spread = 0.125 * f64::from(self.books);
+            Some(TwoSidedQuote {
+                xch: e.symbol.xch,
+                pair: e.symbol.value.clone(),
+                bid: Some(QuoteLevel {
+                    price: mid - spread,
+                    qty: 1.0,
+                }),
+                ask: Some(QuoteLevel {
+                    price: mid + spread,
+                    qty: 1.0,
+                }),
+            })
+        }
+
+        fn order_batch(&mut self, e: &MarketEventEnvelope, _ctx: &DefaultStrategyContext) -> Option<OrderBatch> {
+            if !self.batching {
+                return None;
+            }
+            let leg = |side: TradeType| AddOrderRequest {
+                xch: e.symbol.xch,
+                pair: e.symbol.value.clone(),
+                side,
+                order_id: uuid::Uuid::new_v4().to_string(),
+                ..AddOrderRequest::default()
+            };
+            Some(OrderBatch::new(e, vec![leg(TradeType::Buy), leg(TradeType::Sell)]))
+        }
+
+        fn model(&self) -> SerializedModel { vec![] }
+
+        fn channels(&self) -> HashSet<MarketChannel> { HashSet::new() }
+    }
+
+    /// The order of `request`, filled in full
+    fn filled_order(request: AddOrderRequest, qty: f64, price: f64) -> OrderDetail {
+        let mut filled = OrderDetail::from_query(request);
+        filled.status = OrderStatus::Filled;
+        filled.executed_qty = Some(qty);
+        filled.total_executed_qty = qty;
+        filled.weighted_price = price;
+        filled
+    }
+
+    fn test_driver(
+        executor: Arc<RecordingExecutor>,
+        options: &GenericDriverOptions,
+        logger: Option<StratEventLoggerRef>,
+    ) -> GenericDriver {
+        test_driver_with(executor, options, logger, Box::<TestStrategy>::default())
+    }
+
+    fn test_driver_with(
+        executor: Arc<RecordingExecutor>,
+        options: &GenericDriverOptions,
+        logger: Option<StratEventLoggerRef>,
+        strat: Box<dyn Strategy>,
+    ) -> GenericDriver {
+        let engine = TradingEngine::builder()
+            .order_executor(executor)
+            .interest_rate_provider(Arc::new(FlatInterestRateProvider::new(0.0)))
+            .exchange_manager(Arc::new(BrokerageManager::new()))
+            .build();
+        GenericDriver::try_new(HashSet::new(), test_db(), options, strat, Arc::new(engine), logger).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_flatten_closes_open_positions() {
+        let executor = Arc::new(RecordingExecutor::default());
+        let mut driver = test_driver(executor.clone(), &test_options(), None);
+        let signal = TradeSignal {
+            price: 100.0,
+            qty: Some(0.1),
+            ..TradeSignal::default()
+        };
+        driver.process_signals(&[signal], now()).await.unwrap();
+        let open = executor.staged.lock().unwrap()[0].clone();
+        driver
+            .portfolio
+            .update_position(&filled_order(open, 0.1, 100.0))
+            .unwrap();
+        assert!(driver.portfolio.has_any_open_position());
+
+        driver.flatten().await.unwrap();
+        let staged = executor.staged.lock().unwrap();
+        assert_eq!(staged.len(), 2);
+        assert_eq!(staged[1].side, TradeType::Sell);
+        assert_eq!(staged[1].order_type, OrderType::Market);
+    }
+
+    #[tokio::test]
+    async fn test_trailing_stops_close_positions() {
+        let executor = Arc::new(RecordingExecutor::default());
+        let capturing = Arc::new(CapturingLogger::default());
+        let options = GenericDriverOptions {
+            trailing_stop: Some(TrailingStopOptions {
+                trailing_stop_start: 0.05,
+                trailing_stop_loss: 0.02,
+                stop_loss: -0.1,
+            }),
+            ..test_options()
+        };
+        let logger: StratEventLoggerRef = capturing.clone();
+        let mut driver = test_driver(executor.clone(), &options, Some(logger));
+        let signal = TradeSignal {
+            price: 100.0,
+            qty: Some(0.1),
+            ..TradeSignal::default()
+        };
+        driver.process_signals(&[signal], now()).await.unwrap();
+        let open = executor.staged.lock().unwrap()[0].clone();
+        driver
+            .portfolio
+            .update_position(&filled_order(open, 0.1, 100.0))
+            .unwrap();
+
+        let symbol = Symbol::new("BTC_USDT".into(), SecurityType::Crypto, Exchange::Binance);
+        for price in [110.0, 120.0, 119.0] {
+            let trade = MarketEventEnvelope::trade_event(symbol.clone(), 0, price, 1.0, TradeType::Buy, None);
+            driver.portfolio.update_from_market(&trade).await.unwrap();
+            driver.check_trailing_stops(now()).await;
+            assert_eq!(executor.staged.lock().unwrap().len(), 1);
+        }
+        // The return dropped by more than the trailing stop loss from its top
+        let trade = MarketEventEnvelope::trade_event(symbol, 0, 115.0, 1.0, TradeType::Buy, None);
+        driver.portfolio.update_from_market(&trade).await.unwrap();
+        driver.check_trailing_stops(now()).await;
+        let staged = executor.staged.lock().unwrap();
+        assert_eq!(staged.len(), 2);
+        assert_eq!(staged[1].side, TradeType::Sell);
+        assert_eq!(staged[1].order_type, OrderType::Market);
+        let events = capturing.events.lock().unwrap();
+        assert!(matches!(events[..], [TimedData {
+            value: StratEvent::Stop(StopEvent::TrailingStop),
+            ..
+        }]));
+    }
+
+    #[tokio::test]
+    async fn test_signal_legs_are_staged_as_one_batch() {
+        let executor = Arc::new(RecordingExecutor::default());
+        let mut driver = test_driver(executor.clone(), &test_options(), None);
+        let leg = |pair: &str| TradeSignal {
+            price: 100.0,
+            qty: Some(0.1),
+            pair: pair.into(),
+            ..TradeSignal::default()
+        };
+        driver
+            .process_signals(&[leg("BTC_USDT"), leg("ETH_USDT")], now())
+            .await
+            .unwrap();
+        let staged = executor.staged.lock().unwrap();
+        assert_eq!(staged.len(), 2);
+        let batches = executor.batches.lock().unwrap();
+        assert_eq!(*batches, vec![vec![
+            staged[0].order_id.clone(),
+            staged[1].order_id.clone()
+        ]]);
+    }
+
+    #[tokio::test]
+    async fn test_flatten_cancels_locking_orders() {
+        let executor = Arc::new(RecordingExecutor::default());
+        let mut driver = test_driver(executor.clone(), &test_options(), None);
+        let signal = TradeSignal {
+            price: 100.0,
+            qty: Some(0.1),
+            ..TradeSignal::default()
+        };
+        driver.process_signals(&[signal], now()).await.unwrap();
+        let open = executor.staged.lock().unwrap()[0].clone();
+        assert!(!driver.portfolio.locks().is_empty());
+
+        driver.flatten().await.unwrap();
+        assert_eq!(*executor.canceled.lock().unwrap(), vec![open.order_id]);
+        assert!(driver.portfolio.locks().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_restore_discards_positions_opened_after_the_snapshot() {
+        let executor = Arc::new(RecordingExecutor::default());
+        let mut driver = test_driver(executor.clone(), &test_options(), None);
+        let snapshot = driver.snapshot().unwrap().unwrap();
+        let signal = TradeSignal {
+            price: 100.0,
+            qty: Some(0.1),
+            ..TradeSignal::default()
+        };
+        driver.process_signals(&[signal], now()).await.unwrap();
+        let open = executor.staged.lock().unwrap()[0].clone();
+        driver
+            .portfolio
+            .update_position(&filled_order(open, 0.1, 100.0))
+            .unwrap();
+        assert!(driver.portfolio.has_any_open_position());
+
+        driver.restore(&snapshot).unwrap();
+        assert!(!driver.portfolio.has_any_open_position());
+        assert!(driver.portfolio.locks().is_empty());
+        assert!(!driver.initialized);
+    }
+
+    #[actix::test]
+    async fn test_orders_are_split_by_the_execution_algo() {
+        let executor = Arc::new(RecordingExecutor::default());
+        let options = GenericDriverOptions {
+            execution: Some(ExecutionAlgo::Iceberg { display_qty: 0.05 }),
+            ..test_options()
+        };
+        let mut driver = test_driver(executor.clone(), &options, None);
+        let signal = TradeSignal {
+            price: 100.0,
+            qty: Some(0.1),
+            ..TradeSignal::default()
+        };
+        driver.process_signals(&[signal], now()).await.unwrap();
+        let parent_id = driver.portfolio.locks().values().next().unwrap().order_id.clone();
+        let fill_child = |i: usize| {
+            let child = executor.staged.lock().unwrap()[i].clone();
+            assert_eq!(child.transaction_id.as_deref(), Some(parent_id.as_str()));
+            assert_eq!(child.quantity, Some(0.05));
+            executor.publish(filled_order(child, 0.05, 100.0));
+        };
+        let executions = driver.execution.as_ref().unwrap().0.clone();
+
+        fill_child(0);
+        executions.send(ExecutionTick(now())).await.unwrap();
+        driver.resolve_orders().await;
+        // The fills of the first chunk are reported to the portfolio as the parent order
+        let position = driver.portfolio.open_positions().values().next().unwrap().clone();
+        assert!((position.quantity - 0.05).abs() < 1e-9);
+        assert!(!driver.portfolio.locks().is_empty());
+
+        fill_child(1);
+        executions.send(ExecutionTick(now())).await.unwrap();
+        driver.resolve_orders().await;
+        let position = driver.portfolio.open_positions().values().next().unwrap().clone();
+        assert!((position.quantity - 0.1).abs() < 1e-9);
+        assert!(driver.portfolio.locks().is_empty());
+        assert_eq!(executor.staged.lock().unwrap().len(), 2);
+        // Finished executions are forgotten once reported
+        assert!(executions.send(ExecutionReport(parent_id)).await.unwrap().is_none());
+    }
+
+    #[test]
+    fn test_vwap_executions_subscribe_to_trades() {
+        let symbol = Symbol::new("BTC_USDT".into(), SecurityType::Crypto, Exchange::Binance);
+        let channels: HashSet<MarketChannel> = vec![MarketChannel::builder()
+            .symbol(symbol.clone())
+            .r#type(MarketChannelType::Orderbooks)
+            .build()]
+        .into_iter()
+        .collect();
+        let vwap = ExecutionAlgo::Vwap {
+            participation: 0.1,
+            deadline: Duration::minutes(5),
+        };
+        let trades = execution_channels(&channels, Some(&vwap));
+        assert_eq!(trades.len(), 1);
+        let trade_channel = trades.iter().next().unwrap();
+        assert_eq!(trade_channel.symbol, symbol);
+        assert_eq!(trade_channel.r#type, MarketChannelType::Trades);
+        // Trades the strategy subscribed to already pace executions
+        let mut with_trades = channels.clone();
+        with_trades.extend(trades);
+        assert!(execution_channels(&with_trades, Some(&vwap)).is_empty());
+        assert!(execution_channels(&channels, Some(&ExecutionAlgo::Iceberg { display_qty: 1.0 })).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_failed_repayments_are_retried() {
+        let executor = Arc::new(RecordingExecutor::default());
+        let mut driver = test_driver(executor.clone(), &test_options(), None);
+        let loan = MarginLoanRequest {
+            asset: "USDT".into(),
+            amount: 10.0,
+            ..MarginLoanRequest::default()
+        };
+        driver.portfolio.requeue_repayment(loan.clone()).unwrap();
+        executor.reject_loans.store(true, Ordering::Relaxed);
+        driver.repay_loans().await;
+        assert!(executor.loans.lock().unwrap().is_empty());
+
+        executor.reject_loans.store(false, Ordering::Relaxed);
+        driver.repay_loans().await;
+        assert_eq!(*executor.loans.lock().unwrap(), vec![OrderQuery::Repay(loan)]);
+        driver.repay_loans().await;
+        assert_eq!(executor.loans.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_signals_are_sized_by_the_position_sizer() {
+        let executor = Arc::new(RecordingExecutor::default());
+        let options = GenericDriverOptions {
+            position_sizer: Some(PositionSizerOptions::FixedFractional { fraction: 0.5 }),
+            ..test_options()
+        };
+        let mut driver = test_driver(executor.clone(), &options, None);
+        let signal = TradeSignal {
+            price: 100.0,
+            qty: None,
+            ..TradeSignal::default()
+        };
+        driver.process_signals(&[signal], now()).await.unwrap();
+        let staged = executor.staged.lock().unwrap();
+        assert_eq!(staged[0].quantity, Some(0.5));
+    }
+
+    #[tokio::test]
+    async fn test_trading_follows_the_schedule() {
+        let executor = Arc::new(RecordingExecutor::default());
+        let options = GenericDriverOptions {
+            schedule: Some(
+                serde_json::from_value(serde_json::json!({
+                    "windows": [{"days": ["Mon", "Tue", "Wed", "Thu", "Fri"], "start": "00:00:00", "end": "23:59:59"}]
+                }))
+                .unwrap(),
+            ),
+            ..test_options()
+        };
+        let mut driver = test_driver(executor, &options, None);
+        driver.init().await.unwrap();
+        let saturday = Utc.with_ymd_and_hms(2022, 1, 8, 12, 0, 0).unwrap();
+        let monday = Utc.with_ymd_and_hms(2022, 1, 10, 12, 0, 0).unwrap();
+        driver.enforce_schedule(saturday).await.unwrap();
+        assert_eq!(driver.status(), StrategyStatus::NotTrading);
+        driver.enforce_schedule(monday).await.unwrap();
+        assert_eq!(driver.status(), StrategyStatus::Running);
+        // Strategies stopped manually are not resumed by the next session
+        driver.stop_trading().unwrap();
+        driver.enforce_schedule(monday).await.unwrap();
+        assert_eq!(driver.status(), StrategyStatus::NotTrading);
+    }
+
+    #[tokio::test]
+    async fn test_failed_flatten_is_retried_while_the_session_is_closed() {
+        let executor = Arc::new(RecordingExecutor::default());
+        let options = GenericDriverOptions {
+            schedule: Some(
+                serde_json::from_value(serde_json::json!({
+                    "windows": [{"days": ["Mon", "Tue", "Wed", "Thu", "Fri"], "start": "00:00:00", "end": "23:59:59"}],
+                    "flatten": true
+                }))
+                .unwrap(),
+            ),
+            ..test_options()
+        };
+        let mut driver = test_driver(executor.clone(), &options, None);
+        driver.init().await.unwrap();
+        let signal = TradeSignal {
+            price: 100.0,
+            qty: Some(0.1),
+            ..TradeSignal::default()
+        };
+        driver.process_signals(&[signal], now()).await.unwrap();
+        let open = executor.staged.lock().unwrap()[0].clone();
+        driver
+            .portfolio
+            .update_position(&filled_order(open, 0.1, 100.0))
+            .unwrap();
+
+        let saturday = Utc.with_ymd_and_hms(2022, 1, 8, 12, 0, 0).unwrap();
+        driver.check_schedule(saturday).await;
+        assert_eq!(driver.status(), StrategyStatus::NotTrading);
+        assert_eq!(executor.staged.lock().unwrap().len(), 2);
+        // The closing order is resting, nothing to retry
+        driver.check_schedule(saturday + Duration::minutes(1)).await;
+        assert_eq!(executor.staged.lock().unwrap().len(), 2);
+        // The closing order failed and released the position
+        let (xch, pair, side) = driver.portfolio.open_positions().keys().next().unwrap().clone();
+        driver.portfolio.unlock_position(xch, pair, side).unwrap();
+        driver.check_schedule(saturday + Duration::minutes(2)).await;
+        let staged = executor.staged.lock().unwrap();
+        assert_eq!(staged.len(), 3);
+        assert_eq!(staged[2].side, TradeType::Sell);
+    }
+
+    #[tokio::test]
+    async fn test_quotes_rest_as_amended_orders() {
+        let executor = Arc::new(RecordingExecutor::default());
+        let options = GenericDriverOptions {
+            quoting: Some(serde_json::from_value(serde_json::json!({"max_inventory": 5.0})).unwrap()),
+            ..test_options()
+        };
+        let mut driver = test_driver_with(executor.clone(), &options, None, Box::new(TestStrategy::quoting()));
+        driver.on_market_event(&default_order_book_event()).await.unwrap();
+        let placed: Vec<(TradeType, Option<f64>)> = executor
+            .staged
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|o| (o.side, o.price))
+            .collect();
+        assert_eq!(placed, vec![
+            (TradeType::Buy, Some(0.875)),
+            (TradeType::Sell, Some(1.125))
+        ]);
+        // Moved quotes are amended in place
+        driver.on_market_event(&default_order_book_event()).await.unwrap();
+        assert_eq!(executor.staged.lock().unwrap().len(), 2);
+        let amended: Vec<Option<f64>> = executor.amended.lock().unwrap().iter().map(|a| a.price).collect();
+        assert_eq!(amended, vec![Some(0.75), Some(1.25)]);
+        assert_eq!(driver.ctx().inventory(Exchange::Binance, "BTC_USDT".into()), 0.0);
+        // Fills are received from the order updates and accounted by the portfolio
+        let mut bid = OrderDetail::from_query(executor.staged.lock().unwrap()[0].clone());
+        bid.status = OrderStatus::PartiallyFilled;
+        bid.total_executed_qty = 0.5;
+        bid.weighted_price = 0.75;
+        executor.publish(bid);
+        driver.on_market_event(&default_order_book_event()).await.unwrap();
+        assert_eq!(driver.ctx().inventory(Exchange::Binance, "BTC_USDT".into()), 0.5);
+        let persisted = driver.repo.get_quoters().unwrap();
+        let market = (Exchange::Binance, Pair::from("BTC_USDT"));
+        assert_eq!(persisted[&market].bid().map(|q| q.executed), Some(0.5));
+        // Quotes are pulled once trading stops
+        driver.stop_trading().unwrap();
+        driver.on_market_event(&default_order_book_event()).await.unwrap();
+        assert_eq!(executor.canceled.lock().unwrap().len(), 2);
+        assert!(driver.quotes.quoters().values().all(|q| q.resting_orders().is_empty()));
+    }
+
+    #[tokio::test]
+    async fn test_order_batches_wait_for_the_previous_one() {
+        let executor = Arc::new(RecordingExecutor::default());
+        let options = GenericDriverOptions {
+            dry_mode: Some(true),
+            ..test_options()
+        };
+        let mut driver = test_driver_with(executor.clone(), &options, None, Box::new(TestStrategy::batching()));
+        driver.on_market_event(&default_order_book_event()).await.unwrap();
+        assert_eq!(executor.staged.lock().unwrap().len(), 2);
+        assert!(executor.staged.lock().unwrap().iter().all(|o| o.dry_run));
+        // The legs of the first batch are still staged
+        driver.on_market_event(&default_order_book_event()).await.unwrap();
+        assert_eq!(executor.staged.lock().unwrap().len(), 2);
+        assert_eq!(driver.batch.legs().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_partially_executed_batches_are_unwound() {
+        let executor = Arc::new(RecordingExecutor::default());
+        let mut driver = test_driver_with(
+            executor.clone(),
+            &test_options(),
+            None,
+            Box::new(TestStrategy::batching()),
+        );
+        driver.on_market_event(&default_order_book_event()).await.unwrap();
+        let legs = executor.staged.lock().unwrap().clone();
+        // The buy leg is filled and the sell leg is rejected
+        executor.publish(filled_order(legs[0].clone(), 1.0, 100.0));
+        let mut sell = OrderDetail::from_query(legs[1].clone());
+        sell.status = OrderStatus::Rejected;
+        executor.publish(sell);
+        driver.on_market_event(&default_order_book_event()).await.unwrap();
+        assert_eq!(driver.ctx().inventory(Exchange::Binance, "BTC_USDT".into()), 1.0);
+        let unwind = executor.staged.lock().unwrap()[2].clone();
+        assert_eq!(executor.staged.lock().unwrap().len(), 3);
+        assert_eq!(
+            (unwind.side, unwind.order_type, unwind.quantity),
+            (TradeType::Sell, OrderType::Market, Some(1.0))
+        );
+        let persisted = driver.repo.get_batch_orders().unwrap();
+        assert!(persisted.len() == 1 && persisted[0].unwind);
+        // Once unwound, the strategy submits batches again
+        executor.publish(filled_order(unwind, 1.0, 99.0));
+        driver.on_market_event(&default_order_book_event()).await.unwrap();
+        assert_eq!(driver.ctx().inventory(Exchange::Binance, "BTC_USDT".into()), 0.0);
+        assert_eq!(executor.staged.lock().unwrap().len(), 5);
+    }
+
+    #[tokio::test]
+    async fn test_first_orders_await_confirmation() {
+        let executor = Arc::new(RecordingExecutor::default());
+        let options = GenericDriverOptions {
+            require_confirmation: Some(true),
+            ..test_options()
+        };
+        let mut driver = test_driver(executor.clone(), &options, None);
+        let signal = TradeSignal {
+            price: 100.0,
+            qty: Some(0.1),
+            ..TradeSignal::default()
+        };
+        driver.process_signals(&[signal.clone()], now()).await.unwrap();
+        assert!(executor.staged.lock().unwrap().is_empty());
+        let pending = match driver.query(DataQuery::PendingOrders).await.unwrap() {
+            DataResult::PendingOrders(orders) => orders,
+            r => panic!("unexpected result {:?}", r),
+        };
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].pair, signal.pair);
+
+        assert_eq!(
+            driver.query(DataQuery::ConfirmPendingOrders).await.unwrap(),
+            DataResult::Success(true)
+        );
+        assert_eq!(*executor.staged.lock().unwrap(), pending);
+        assert_eq!(
+            driver.query(DataQuery::PendingOrders).await.unwrap(),
+            DataResult::PendingOrders(vec![])
+        );
+
+        // Subsequent orders are executed without confirmation
+        let next_signal = TradeSignal {
+            pair: "ETH_USDT".into(),
+            ..signal
+        };
+        driver.process_signals(&[next_signal], now()).await.unwrap();
+        assert_eq!(executor.staged.lock().unwrap().len(), 2);
+        assert_eq!(
+            driver.query(DataQuery::ConfirmPendingOrders).await.unwrap(),
+            DataResult::Success(false)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_pending_orders_are_released_when_replaced_or_rejected() {
+        let executor = Arc::new(RecordingExecutor::default());
+        let options = GenericDriverOptions {
+            require_confirmation: Some(true),
+            ..test_options()
+        };
+        let mut driver = test_driver(executor.clone(), &options, None);
+        let signal = TradeSignal {
+            price: 100.0,
+            qty: Some(0.1),
+            ..TradeSignal::default()
+        };
+        driver.process_signals(&[signal.clone()], now()).await.unwrap();
+        assert_eq!(driver.portfolio.locks().len(), 1);
+        // Newer orders replace the pending ones, whose positions are released
+        let next_signal = TradeSignal {
+            pair: "ETH_USDT".into(),
+            ..signal
+        };
+        driver.process_signals(&[next_signal], now()).await.unwrap();
+        let locked: Vec<Pair> = driver.portfolio.locks().keys().map(|k| k.1.clone()).collect();
+        assert_eq!(locked, vec![Pair::from("ETH_USDT")]);
+        assert_eq!(driver.repo.get_pending_orders().unwrap(), driver.pending_orders);
+
+        assert_eq!(
+            driver.query(DataQuery::RejectPendingOrders).await.unwrap(),
+            DataResult::Success(true)
+        );
+        assert!(driver.portfolio.locks().is_empty());
+        assert!(driver.repo.get_pending_orders().unwrap().is_empty());
+        assert!(executor.staged.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_stale_signals_are_dropped() {
+        let at = now();
+        let fresh = TradeSignal {
+            event_time: at - Duration::seconds(1),
+            ..TradeSignal::default()
+        };
+        let aged = TradeSignal {
+            event_time: at - Duration::minutes(5),
+            ..TradeSignal::default()
+        };
+        let kept = fresh_signals("test", &[fresh.clone(), aged], at, Duration::seconds(30));
+        assert_eq!(kept.len(), 1);
+        assert_eq!(kept[0].event_time, fresh.event_time);
+    }
+
+    #[tokio::test]
+    async fn test_stale_signals_do_not_drop_fresh_ones() {
+        let executor = Arc::new(RecordingExecutor::default());
+        let options = GenericDriverOptions {
+            max_signal_age: Some(Duration::seconds(30)),
+            ..test_options()
+        };
+        let mut driver = test_driver(executor.clone(), &options, None);
+        let at = now();
+        let fresh = TradeSignal {
+            price: 100.0,
+            qty: Some(0.1),
+            event_time: at - Duration::seconds(1),
+            ..TradeSignal::default()
+        };
+        let aged = TradeSignal {
+            pair: "ETH_USDT".into(),
+            event_time: at - Duration::minutes(5),
+            ..fresh.clone()
+        };
+        driver.process_signals(&[fresh.clone(), aged], at).await.unwrap();
+        let staged = executor.staged.lock().unwrap();
+        assert_eq!(staged.len(), 1);
+        assert_eq!(staged[0].pair, fresh.pair);
+    }
+
+    #[tokio::test]
+    async fn test_stale_leg_drops_its_batch() {
+        let executor = Arc::new(RecordingExecutor::default());
+        let options = GenericDriverOptions {
+            max_signal_age: Some(Duration::seconds(30)),
+            ..test_options()
+        };
+        let mut driver = test_driver(executor.clone(), &options, None);
+        let at = now();
+        let trace_id = uuid::Uuid::new_v4();
+        let fresh_leg = TradeSignal {
+            trace_id,
+            price: 100.0,
+            qty: Some(0.1),
+            event_time: at - Duration::seconds(1),
+            ..TradeSignal::default()
+        };
+        let stale_leg = TradeSignal {
+            pair: "ETH_USDT".into(),
+            event_time: at - Duration::minutes(5),
+            ..fresh_leg.clone()
+        };
+        let unrelated = TradeSignal {
+            trace_id: uuid::Uuid::new_v4(),
+            pair: "BNB_USDT".into(),
+            ..fresh_leg.clone()
+        };
+        let kept = fresh_signals(
+            "test",
+            &[fresh_leg.clone(), stale_leg.clone()],
+            at,
+            Duration::seconds(30),
+        );
+        assert!(kept.is_empty());
+
+        driver
+            .process_signals(&[fresh_leg, stale_leg, unrelated.clone()], at)
+            .await
+            .unwrap();
+        let staged = executor.staged.lock().unwrap();
+        assert_eq!(staged.len(), 1);
+        assert_eq!(staged[0].pair, unrelated.pair);
+    }
+
+    #[test]
+    fn test_trading_pauses_during_maintenance() {
+        let maintenance = MaintenanceRegistry::default();
+        let at = now();
+        let signals = vec![TradeSignal {
+            exchange: Exchange::Binance,
+            ..TradeSignal::default()
+        }];
+        let mut order = OrderDetail::from_query(AddOrderRequest {
+            xch: Exchange::Binance,
+            pair: "BTC_USDT".into(),
+            ..AddOrderRequest::default()
+        });
+        assert!(!pause_on_maintenance(&maintenance, &order, at, Duration::minutes(5)));
+        assert!(!under_maintenance(&maintenance, &signals, at));
+
+        order.from_rejected(Rejection::Maintenance);
+        assert!(pause_on_maintenance(&maintenance, &order, at, Duration::minutes(5)));
+        assert!(under_maintenance(&maintenance, &signals, at + Duration::minutes(1)));
+        // Trading resumes once the maintenance window is over
+        assert!(!under_maintenance(&maintenance, &signals, at + Duration::minutes(5)));
+
+        // or as soon as the exchange is known to be back
+        assert!(pause_on_maintenance(&maintenance, &order, at, Duration::minutes(5)));
+        maintenance.end(Exchange::Binance);
+        assert!(!under_maintenance(&maintenance, &signals, at + Duration::minutes(1)));
+    }
+
+    #[tokio::test]
+    async fn test_throttle_is_logged() {
+        let capturing = Arc::new(CapturingLogger::default());
+        let logger: StratEventLoggerRef = capturing.clone();
+        let mut logged = 1.0;
+        assert!(!log_risk_throttle(Some(&logger), &mut logged, 1.0, now()).await);
+        assert!(capturing.events.lock().unwrap().is_empty());
+
+        assert!(log_risk_throttle(Some(&logger), &mut logged, 0.5, now()).await);
+        assert!(!log_risk_throttle(Some(&logger), &mut logged, 0.5, now()).await);
+        let events = capturing.events.lock().unwrap();
+        assert_eq!(events.len(), 1);
+        assert!(matches!(events[0].value, StratEvent::RiskThrottled { multiplier } if multiplier == 0.5));
+    }
+
+    #[tokio::test]
+    async fn test_circuit_breaker_is_logged() {
+        let capturing = Arc::new(CapturingLogger::default());
+        let logger: StratEventLoggerRef = capturing.clone();
+        let mut tripped = false;
+        assert!(!log_circuit_breaker(Some(&logger), &mut tripped, None, now()).await);
+        assert!(capturing.events.lock().unwrap().is_empty());
+
+        assert!(log_circuit_breaker(Some(&logger), &mut tripped, Some("daily loss".to_string()), now()).await);
+        // Trading resumed and the limit holds
+        assert!(!log_circuit_breaker(Some(&logger), &mut tripped, None, now()).await);
+        assert!(!log_circuit_breaker(Some(&logger), &mut tripped, None, now()).await);
+        let events = capturing.events.lock().unwrap();
+        assert_eq!(events.len(), 2);
+        assert!(matches!(&events[0].value, StratEvent::CircuitBreakerTripped { reason } if reason == "daily loss"));
+        assert!(matches!(events[1].value, StratEvent::CircuitBreakerReset));
+    }
+
+    #[tokio::test]
+    async fn test_observe_mode_places_nothing() {
+        let executor = Arc::new(RecordingExecutor::default());
+        let capturing = Arc::new(CapturingLogger::default());
+        let options = GenericDriverOptions {
+            observe: Some(true),
+            ..test_options()
+        };
+        let logger: StratEventLoggerRef = capturing.clone();
+        let mut driver = test_driver(executor.clone(), &options, Some(logger));
+        let signal = TradeSignal {
+            price: 100.0,
+            qty: Some(0.1),
+            ..TradeSignal::default()
+        };
+        driver.process_signals(&[signal.clone()], now()).await.unwrap();
+        assert!(executor.staged.lock().unwrap().is_empty());
+        let events = capturing.events.lock().unwrap();
+        assert!(matches!(&events[0].value, StratEvent::ObservedOrder(order) if order.pair == signal.pair));
+        assert!(matches!(&events[1].value, StratEvent::OpenPosition(_)));
+        // Would-be fills are tracked in the shadow portfolio
+        assert!(driver
+            .portfolio
+            .open_position(signal.exchange, signal.pair.clone())
+            .is_some());
+        assert!(driver.portfolio.locks().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_signal_only_mode_publishes_signals() {
+        let executor = Arc::new(RecordingExecutor::default());
+        let capturing = Arc::new(CapturingLogger::default());
+        let logger: StratEventLoggerRef = capturing.clone();
+        let mut driver = test_driver(executor.clone(), &test_options(), Some(logger));
+        let publisher = Arc::new(RecordingPublisher::default());
+        driver.publisher = Some(publisher.clone());
+        let signal = TradeSignal {
+            price: 100.0,
+            qty: Some(0.1),
+            ..TradeSignal::default()
+        };
+        driver.process_signals(&[signal.clone()], now()).await.unwrap();
+        assert!(executor.staged.lock().unwrap().is_empty());
+        let published = publisher.published.lock().unwrap();
+        assert_eq!(published.len(), 1);
+        assert_eq!(published[0][0].trace_id, signal.trace_id);
+        assert_eq!(published[0][0].qty, Some(0.1));
+        let events = capturing.events.lock().unwrap();
+        assert!(matches!(&events[0].value, StratEvent::PublishedSignals(signals) if signals.len() == 1));
+        // Nothing is traded, not even in a shadow portfolio
+        assert!(driver
+            .portfolio
+            .open_position(signal.exchange, signal.pair.clone())
+            .is_none());
+        assert!(driver.portfolio.locks().is_empty());
+    }
 }
@@ -0,0 +1,116 @@
+use std::collections::HashMap;
+
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::TryRecvError;
+
+use brokers::prelude::*;
+use trading::order_manager::types::OrderDetail;
+use trading::order_manager::OrderExecutor;
+use trading::quoting::{QuoteFill, Quoter, QuotingOptions};
+
+use crate::generic::metrics;
+
+/// Resting quotes of a market making strategy, and the order updates their fills are accounted from
+#[derive(Debug, Default)]
+pub(super) struct QuotingState {
+    /// Limits of the quotes of the strategy, quotes are ignored if unset
+    options: Option<QuotingOptions>,
+    /// Resting quotes of the strategy by market
+    quoters: HashMap<(Exchange, Pair), Quoter>,
+    /// Orders updated by the order manager, quote fills are accounted from them
+    order_updates: Option<broadcast::Receiver<OrderDetail>>,
+}
+
+impl QuotingState {
+    pub(super) fn new(options: Option<QuotingOptions>) -> Self {
+        Self {
+            options,
+            ..Self::default()
+        }
+    }
+
+    pub(super) fn options(&self) -> Option<&QuotingOptions> { self.options.as_ref() }
+
+    pub(super) fn quoters(&self) -> &HashMap<(Exchange, Pair), Quoter> { &self.quoters }
+
+    pub(super) fn restore(&mut self, quoters: HashMap<(Exchange, Pair), Quoter>) { self.quoters = quoters; }
+
+    /// The quoter of a market, created if the market was never quoted
+    pub(super) fn quoter(&mut self, market: &(Exchange, Pair)) -> &mut Quoter {
+        self.quoters.entry(market.clone()).or_default()
+    }
+
+    pub(super) fn quoter_mut(&mut self, market: &(Exchange, Pair)) -> Option<&mut Quoter> {
+        self.quoters.get_mut(market)
+    }
+
+    pub(super) fn markets(&self) -> Vec<(Exchange, Pair)> { self.quoters.keys().cloned().collect() }
+
+    /// The quote orders updated by the order manager since the last call, quote orders are only queried once
+    /// subscribed or if updates were missed
+    pub(super) async fn updated_orders(&mut self, executor: &dyn OrderExecutor, key: &str) -> Vec<OrderDetail> {
+        let mut resync = false;
+        if self.order_updates.is_none() {
+            match executor.subscribe_orders().await {
+                Ok(updates) => self.order_updates = Some(updates),
+                Err(e) => {
+                    metrics::get().log_error(e.short_name());
+                    debug!(err = %e, key = %key, "failed to subscribe to order updates");
+                }
+            }
+            resync = true;
+        }
+        let mut orders = vec![];
+        if let Some(updates) = self.order_updates.as_mut() {
+            loop {
+                match updates.try_recv() {
+                    Ok(order) => {
+                        if self.quoters.values().any(|q| q.has_order(&order.id)) {
+                            orders.push(order);
+                        }
+                    }
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Lagged(missed)) => {
+                        warn!(key = %key, missed = missed, "missed order updates, querying quote orders");
+                        resync = true;
+                    }
+                    Err(TryRecvError::Closed) => {
+                        self.order_updates = None;
+                        resync = true;
+                        break;
+                    }
+                }
+            }
+        }
+        if resync {
+            for order_id in self
+                .quoters
+                .values()
+                .flat_map(Quoter::resting_orders)
+                .collect::<Vec<_>>()
+            {
+                match executor.get_order(order_id.as_str()).await {
+                    Ok((order, _)) => orders.push(order),
+                    Err(e) => {
+                        metrics::get().log_error(e.short_name());
+                        debug!(err = %e, order_id = %order_id, "failed to query quote order");
+                    }
+                }
+            }
+        }
+        orders
+    }
+
+    /// The fills of the quotes executed by these orders, by market
+    pub(super) fn on_orders(&mut self, orders: &[OrderDetail]) -> Vec<((Exchange, Pair), QuoteFill)> {
+        let mut fills = vec![];
+        for order in orders {
+            for (market, quoter) in &mut self.quoters {
+                if let Some(fill) = quoter.on_order(order) {
+                    fills.push((market.clone(), fill));
+                }
+            }
+        }
+        fills
+    }
+}
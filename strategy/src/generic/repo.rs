@@ -1,14 +1,47 @@
+use std::collections::HashMap;
 use std::sync::Arc;
 
+use brokers::prelude::*;
 use db::{Storage, StorageExt};
+use trading::quoting::Quoter;
+use trading::signal::BatchLeg;
 
 use crate::error::*;
+use crate::models::persist::{ModelValue, MODELS_TABLE_NAME};
+use crate::models::ModelMigration;
 use crate::StrategyStatus;
 
 pub trait DriverRepository {
     fn set_status(&self, status: StrategyStatus) -> Result<()>;
 
     fn get_status(&self) -> Result<Option<StrategyStatus>>;
+
+    /// Record whether trading was stopped because the trading session closed, so that it resumes in the next one
+    fn set_suspended_by_schedule(&self, suspended: bool) -> Result<()>;
+
+    fn is_suspended_by_schedule(&self) -> Result<bool>;
+
+    /// Record the resting quotes of every market, so that their fills are still accounted after a restart
+    fn set_quoters(&self, quoters: &HashMap<(Exchange, Pair), Quoter>) -> Result<()>;
+
+    fn get_quoters(&self) -> Result<HashMap<(Exchange, Pair), Quoter>>;
+
+    /// Record the orders held until confirmed, so that they can still be confirmed or rejected after a restart
+    fn set_pending_orders(&self, orders: &[AddOrderRequest]) -> Result<()>;
+
+    fn get_pending_orders(&self) -> Result<Vec<AddOrderRequest>>;
+
+    /// Record the orders of the last batch, so that their executions are still accounted after a restart
+    fn set_batch_orders(&self, legs: &[BatchLeg]) -> Result<()>;
+
+    fn get_batch_orders(&self) -> Result<Vec<BatchLeg>>;
+
+    /// Migrate the models persisted by older versions of a strategy to their current version
+    ///
+    /// # Errors
+    ///
+    /// A model was persisted by a newer version, or has no migration path from its persisted version
+    fn migrate_models(&self, migrations: &[ModelMigration<'_>]) -> Result<()>;
 }
 
 pub(crate) struct GenericDriverRepository {
@@ -37,4 +70,172 @@ impl DriverRepository for GenericDriverRepository {
             Err(r) => Err(r.into()),
         }
     }
+
+    fn set_suspended_by_schedule(&self, suspended: bool) -> Result<()> {
+        self.db.put(DRIVER_TABLE, "suspended_by_schedule", suspended)?;
+        Ok(())
+    }
+
+    fn is_suspended_by_schedule(&self) -> Result<bool> {
+        match self.db.get(DRIVER_TABLE, "suspended_by_schedule") {
+            Ok(r) => Ok(r),
+            Err(db::Error::NotFound(_)) => Ok(false),
+            Err(r) => Err(r.into()),
+        }
+    }
+
+    fn set_quoters(&self, quoters: &HashMap<(Exchange, Pair), Quoter>) -> Result<()> {
+        let quoters: Vec<(&(Exchange, Pair), &Quoter)> = quoters.iter().collect();
+        self.db.put(DRIVER_TABLE, "quoters", quoters)?;
+        Ok(())
+    }
+
+    fn get_quoters(&self) -> Result<HashMap<(Exchange, Pair), Quoter>> {
+        match self
+            .db
+            .get::<_, Vec<((Exchange, Pair), Quoter)>>(DRIVER_TABLE, "quoters")
+        {
+            Ok(r) => Ok(r.into_iter().collect()),
+            Err(db::Error::NotFound(_)) => Ok(HashMap::new()),
+            Err(r) => Err(r.into()),
+        }
+    }
+
+    fn set_pending_orders(&self, orders: &[AddOrderRequest]) -> Result<()> {
+        self.db.put(DRIVER_TABLE, "pending_orders", orders)?;
+        Ok(())
+    }
+
+    fn get_pending_orders(&self) -> Result<Vec<AddOrderRequest>> {
+        match self.db.get(DRIVER_TABLE, "pending_orders") {
+            Ok(r) => Ok(r),
+            Err(db::Error::NotFound(_)) => Ok(vec![]),
+            Err(r) => Err(r.into()),
+        }
+    }
+
+    fn set_batch_orders(&self, legs: &[BatchLeg]) -> Result<()> {
+        self.db.put(DRIVER_TABLE, "batch_orders", legs)?;
+        Ok(())
+    }
+
+    fn get_batch_orders(&self) -> Result<Vec<BatchLeg>> {
+        match self.db.get(DRIVER_TABLE, "batch_orders") {
+            Ok(r) => Ok(r),
+            Err(db::Error::NotFound(_)) => Ok(vec![]),
+            Err(r) => Err(r.into()),
+        }
+    }
+
+    fn migrate_models(&self, migrations: &[ModelMigration<'_>]) -> Result<()> {
+        if migrations.is_empty() {
+            return Ok(());
+        }
+        self.db.ensure_table(MODELS_TABLE_NAME)?;
+        for migration in migrations {
+            let persisted: Option<ModelValue<serde_json::Value>> = match self.db.get(MODELS_TABLE_NAME, &migration.key)
+            {
+                Ok(r) => r,
+                Err(db::Error::NotFound(_)) => None,
+                Err(r) => return Err(r.into()),
+            };
+            let Some(persisted) = persisted else {
+                continue;
+            };
+            if persisted.version == migration.version {
+                continue;
+            }
+            let migrated = if persisted.version > migration.version {
+                // Models cannot be downgraded
+                Err(Error::NoMigrationPath {
+                    from: persisted.version,
+                    to: migration.version,
+                })
+            } else {
+                serde_json::to_vec(&persisted.value)
+                    .map_err(Error::from)
+                    .and_then(|bytes| migration.migrate(persisted.version, &bytes))
+            };
+            let value = migrated.map_err(|e| Error::ModelMigration(migration.key.clone(), Box::new(e)))?;
+            info!(model = %migration.key, from = persisted.version, to = migration.version, "migrated persisted model");
+            self.db.put(
+                MODELS_TABLE_NAME,
+                &migration.key,
+                Some(ModelValue {
+                    value,
+                    at: persisted.at,
+                    version: migration.version,
+                }),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{DateTime, Utc};
+
+    use db::StorageExt;
+
+    use crate::error::{Error, Result};
+    use crate::models::persist::{ModelValue, PersistentValue};
+    use crate::models::{Model, ModelMigration};
+    use crate::test_util::test_db;
+
+    use super::{DriverRepository, GenericDriverRepository, MODELS_TABLE_NAME};
+
+    /// A model whose values were halved in version 1
+    struct HalvedModel;
+
+    impl Model<f64> for HalvedModel {
+        fn json(&self) -> Option<serde_json::Value> { None }
+
+        fn try_load(&mut self) -> Result<()> { Ok(()) }
+
+        fn is_loaded(&self) -> bool { true }
+
+        fn wipe(&mut self) -> Result<()> { Ok(()) }
+
+        fn last_value_time(&self) -> Option<DateTime<Utc>> { None }
+
+        fn has_value(&self) -> bool { false }
+
+        fn value(&self) -> Option<f64> { None }
+
+        fn version(&self) -> u32 { 1 }
+
+        fn migrate(&self, old_version: u32, bytes: &[u8]) -> Result<serde_json::Value> {
+            match old_version {
+                0 => Ok(serde_json::json!(serde_json::from_slice::<f64>(bytes)? / 2.0)),
+                _ => Err(Error::NoMigrationPath {
+                    from: old_version,
+                    to: 1,
+                }),
+            }
+        }
+    }
+
+    #[test]
+    fn migrate_persisted_models() {
+        let db = test_db();
+        let repo = GenericDriverRepository::new(db.clone());
+        let mut value = PersistentValue::new(db.clone(), "halved", Some(ModelValue::new(10.0)));
+        value.persist().unwrap();
+        let model = HalvedModel;
+        repo.migrate_models(&[ModelMigration::of("halved", &model)]).unwrap();
+        let migrated: Option<ModelValue<f64>> = db.get(MODELS_TABLE_NAME, "halved").unwrap();
+        let migrated = migrated.unwrap();
+        assert_eq!((migrated.value, migrated.version), (5.0, 1));
+        // Already migrated models are left as is
+        repo.migrate_models(&[ModelMigration::of("halved", &model)]).unwrap();
+        let migrated: Option<ModelValue<f64>> = db.get(MODELS_TABLE_NAME, "halved").unwrap();
+        assert_eq!(migrated.unwrap().value, 5.0);
+        let mut newer = PersistentValue::new(db, "newer", Some(ModelValue::new(1.0))).with_version(2);
+        newer.persist().unwrap();
+        let err = repo.migrate_models(&[ModelMigration::of("newer", &model)]).unwrap_err();
+        assert!(
+            matches!(err, Error::ModelMigration(key, e) if key == "newer" && matches!(*e, Error::NoMigrationPath { from: 2, to: 1 }))
+        );
+    }
 }
@@ -0,0 +1,87 @@
+use chrono::{DateTime, Utc};
+
+use crate::schedule::TradingSchedule;
+
+/// How the trading session of a driver changed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum SessionChange {
+    /// The session closed while trading, trading stops and positions are flattened if `flatten` is set
+    Closed { flatten: bool },
+    /// Positions were left open without a closing order while the session is closed, they are flattened again
+    Reflatten,
+    /// The next session opened after trading was suspended by the schedule
+    Opened,
+}
+
+/// The trading sessions of a driver, and whether trading was suspended because a session closed
+#[derive(Debug, Default)]
+pub(super) struct TradingSession {
+    /// Trading sessions outside of which the strategy does not trade, if any
+    schedule: Option<TradingSchedule>,
+    /// Whether trading was stopped because the trading session closed
+    suspended: bool,
+}
+
+impl TradingSession {
+    pub(super) fn new(schedule: Option<TradingSchedule>) -> Self {
+        Self {
+            schedule,
+            suspended: false,
+        }
+    }
+
+    pub(super) fn set_suspended(&mut self, suspended: bool) { self.suspended = suspended; }
+
+    /// How the session changes at `at` for a driver that is `trading`, strategies stopped otherwise are not resumed
+    ///
+    /// `unlocked_positions`: whether an opened position has no order closing it
+    pub(super) fn change(&self, at: DateTime<Utc>, trading: bool, unlocked_positions: bool) -> Option<SessionChange> {
+        let schedule = self.schedule.as_ref()?;
+        let open = schedule.is_open(at);
+        if !open && trading {
+            Some(SessionChange::Closed {
+                flatten: schedule.flatten,
+            })
+        } else if !open && schedule.flatten && self.suspended && unlocked_positions {
+            Some(SessionChange::Reflatten)
+        } else if open && self.suspended {
+            Some(SessionChange::Opened)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{TimeZone, Utc};
+
+    use super::{SessionChange, TradingSession};
+
+    #[test]
+    fn session_changes() {
+        let schedule = serde_json::from_value(serde_json::json!({
+            "windows": [{"start": "09:00:00", "end": "17:00:00"}],
+            "flatten": true
+        }))
+        .unwrap();
+        let mut session = TradingSession::new(Some(schedule));
+        let open = Utc.with_ymd_and_hms(2022, 1, 3, 10, 0, 0).unwrap();
+        let closed = Utc.with_ymd_and_hms(2022, 1, 3, 18, 0, 0).unwrap();
+        assert_eq!(session.change(open, true, true), None);
+        assert_eq!(
+            session.change(closed, true, true),
+            Some(SessionChange::Closed { flatten: true })
+        );
+        // Strategies stopped outside of the schedule are not resumed
+        assert_eq!(session.change(closed, false, true), None);
+        assert_eq!(session.change(open, false, false), None);
+
+        session.set_suspended(true);
+        assert_eq!(session.change(closed, false, true), Some(SessionChange::Reflatten));
+        assert_eq!(session.change(closed, false, false), None);
+        assert_eq!(session.change(open, false, false), Some(SessionChange::Opened));
+
+        assert_eq!(TradingSession::default().change(closed, true, true), None);
+    }
+}
@@ -0,0 +1,220 @@
+use std::collections::HashMap;
+
+use brokers::prelude::*;
+use portfolio::portfolio::Portfolio;
+use trading::order_manager::types::OrderDetail;
+use trading::signal::TradeSignal;
+
+use crate::error::Result;
+
+/// Divergence of live execution from the executions intended by the shadow portfolio
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ExecutionDivergence {
+    /// Live orders filled
+    pub fills: usize,
+    /// Live orders that were not filled while the shadow portfolio filled them
+    pub missed_fills: usize,
+    /// Average cost of live fills relative to the intended price, positive when paying more than intended
+    pub avg_slippage: f64,
+    /// Live portfolio value minus the shadow portfolio value
+    pub value_divergence: f64,
+}
+
+/// Runs a shadow portfolio which fills the signals of a live strategy at their intended price,
+/// and compares it to the live portfolio to measure the quality of executions.
+#[derive(Debug)]
+pub struct ShadowComparison {
+    portfolio: Portfolio,
+    /// Intended orders of the shadow portfolio, by live order id
+    intents: HashMap<String, AddOrderRequest>,
+    fills: usize,
+    missed_fills: usize,
+    slippage_sum: f64,
+}
+
+impl ShadowComparison {
+    pub fn new(portfolio: Portfolio) -> Self {
+        Self {
+            portfolio,
+            intents: HashMap::default(),
+            fills: 0,
+            missed_fills: 0,
+            slippage_sum: 0.0,
+        }
+    }
+
+    pub fn portfolio(&self) -> &Portfolio { &self.portfolio }
+
+    /// Update the shadow portfolio with the latest market event
+    ///
+    /// # Errors
+    ///
+    /// Interest rates could not be fetched
+    pub async fn update_from_market(&mut self, event: &MarketEventEnvelope) -> Result<()> {
+        self.portfolio.update_from_market(event).await?;
+        Ok(())
+    }
+
+    /// Fill the signal in the shadow portfolio at its intended price, as the intended execution of the `live` order
+    ///
+    /// # Errors
+    ///
+    /// The signal cannot be converted or filled by the shadow portfolio
+    pub async fn track(&mut self, signal: &TradeSignal, live: &AddOrderRequest) -> Result<()> {
+        let Some(request) = self.portfolio.maybe_convert(signal).await? else {
+            return Ok(());
+        };
+        let mut order = OrderDetail::from_query(request.clone());
+        let fees_rate = self
+            .portfolio
+            .fees_rate_for(request.xch, request.asset_type, Some(request.order_type));
+        order.from_submission(request.simulate_submission(fees_rate));
+        self.portfolio.update_position(&order)?;
+        // The shadow portfolio never borrows, so there is nothing to repay
+        self.portfolio.take_repayments().await?;
+        self.intents.insert(live.order_id.clone(), request);
+        Ok(())
+    }
+
+    /// Compare a live order to its intended execution once it is resolved
+    pub fn resolve(&mut self, live: &OrderDetail) {
+        if !live.is_resolved() {
+            return;
+        }
+        let Some(intent) = self.intents.remove(&live.id) else {
+            return;
+        };
+        if !live.is_filled() {
+            self.missed_fills += 1;
+            return;
+        }
+        let intended_price = intent.price.unwrap_or(live.weighted_price);
+        if intended_price > 0.0 {
+            let slippage = (live.weighted_price - intended_price) / intended_price;
+            self.slippage_sum += match live.side {
+                TradeType::Buy => slippage,
+                TradeType::Sell => -slippage,
+            };
+        }
+        self.fills += 1;
+    }
+
+    /// Divergence of the `live` portfolio from the shadow portfolio
+    #[allow(clippy::cast_precision_loss)]
+    pub fn divergence(&self, live: &Portfolio) -> ExecutionDivergence {
+        ExecutionDivergence {
+            fills: self.fills,
+            missed_fills: self.missed_fills,
+            avg_slippage: if self.fills == 0 {
+                0.0
+            } else {
+                self.slippage_sum / self.fills as f64
+            },
+            value_divergence: live.value() - self.portfolio.value(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use brokers::prelude::*;
+    use portfolio::portfolio::{Portfolio, PortfolioRepoImpl};
+    use portfolio::risk::DefaultMarketRiskEvaluator;
+    use trading::interest::FlatInterestRateProvider;
+    use trading::order_manager::types::{OrderDetail, Rejection};
+    use trading::position::{OperationKind, PositionKind};
+    use trading::signal::TradeSignal;
+
+    use crate::generic::shadow::ShadowComparison;
+    use crate::test_util::test_db;
+
+    fn test_portfolio(key: &str) -> Portfolio {
+        Portfolio::try_new(
+            100.0,
+            0.001,
+            key.to_string(),
+            Arc::new(PortfolioRepoImpl::new(test_db())),
+            Arc::new(DefaultMarketRiskEvaluator::default()),
+            Arc::new(FlatInterestRateProvider::new(0.0)),
+        )
+        .unwrap()
+    }
+
+    fn signals() -> Vec<TradeSignal> {
+        let open = TradeSignal {
+            price: 100.0,
+            qty: Some(0.5),
+            pos_kind: PositionKind::Long,
+            op_kind: OperationKind::Open,
+            ..TradeSignal::default()
+        };
+        let close = TradeSignal {
+            price: 110.0,
+            qty: None,
+            op_kind: OperationKind::Close,
+            ..open.clone()
+        };
+        vec![open, close]
+    }
+
+    /// Feed the same signals to the live and shadow portfolios, live orders are filled at the intended price
+    /// plus `slippage`, or rejected if `miss_last` is set
+    async fn run(slippage: f64, miss_last: bool) -> (Portfolio, ShadowComparison) {
+        let mut live = test_portfolio("live");
+        let mut shadow = ShadowComparison::new(test_portfolio("shadow"));
+        let signals = signals();
+        for (i, signal) in signals.iter().enumerate() {
+            let request = live.maybe_convert(signal).await.unwrap().unwrap();
+            shadow.track(signal, &request).await.unwrap();
+            let mut order = OrderDetail::from_query(request.clone());
+            if miss_last && i == signals.len() - 1 {
+                order.from_rejected(Rejection::BadRequest("rejected".to_string()));
+            } else {
+                let mut submission = request.simulate_submission(live.fees_rate());
+                let price = submission.price
+                    * match request.side {
+                        TradeType::Buy => 1.0 + slippage,
+                        TradeType::Sell => 1.0 - slippage,
+                    };
+                submission.price = price;
+                submission.cummulative_quote_qty = price * submission.executed_qty;
+                submission.trades[0].price = price;
+                order.from_submission(submission);
+            }
+            live.update_position(&order).unwrap();
+            shadow.resolve(&order);
+        }
+        (live, shadow)
+    }
+
+    #[tokio::test]
+    async fn perfect_fills_do_not_diverge() {
+        let (live, shadow) = run(0.0, false).await;
+        let divergence = shadow.divergence(&live);
+        assert_eq!(divergence.fills, 2);
+        assert_eq!(divergence.missed_fills, 0);
+        assert!(divergence.avg_slippage.abs() < 1e-12);
+        assert!(divergence.value_divergence.abs() < 1e-9);
+    }
+
+    #[tokio::test]
+    async fn slippage_diverges() {
+        let (live, shadow) = run(0.01, false).await;
+        let divergence = shadow.divergence(&live);
+        assert_eq!(divergence.fills, 2);
+        assert!((divergence.avg_slippage - 0.01).abs() < 1e-9);
+        assert!(divergence.value_divergence < 0.0);
+    }
+
+    #[tokio::test]
+    async fn missed_fills_are_counted() {
+        let (live, shadow) = run(0.0, true).await;
+        let divergence = shadow.divergence(&live);
+        assert_eq!(divergence.fills, 1);
+        assert_eq!(divergence.missed_fills, 1);
+        assert!(!shadow.portfolio().has_any_open_position());
+        assert!(live.has_any_open_position());
+    }
+}
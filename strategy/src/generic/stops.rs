@@ -0,0 +1,89 @@
+use std::collections::{BTreeMap, HashMap};
+
+use brokers::prelude::*;
+use brokers::types::ExchangeCapabilities;
+use portfolio::portfolio::{PositionKey, PositionLock};
+use trading::position::Position;
+use trading::stop::{StopEvent, TrailingStop, TrailingStopOptions};
+
+/// Open positions whose trailing stop triggered, with the triggering event, exchange side trailing stops are
+/// placed for positions without an event
+pub(super) type StopExits = Vec<(PositionKey, Position, Option<StopEvent>)>;
+
+/// Trailing stops of the open positions of a driver
+#[derive(Debug, Default)]
+pub(super) struct TrailingStops {
+    /// Trailing stop of the open positions, positions are only closed by signals if unset
+    options: Option<TrailingStopOptions>,
+    /// Trailing stops by position
+    stops: HashMap<PositionKey, TrailingStop>,
+}
+
+impl TrailingStops {
+    pub(super) fn new(options: Option<TrailingStopOptions>) -> Self {
+        Self {
+            options,
+            stops: HashMap::default(),
+        }
+    }
+
+    pub(super) fn is_enabled(&self) -> bool { self.options.is_some() }
+
+    /// Check the stops of the open positions, stops are set on the positions once opened with the `capabilities`
+    /// of their exchange, and dropped once closed
+    ///
+    /// returns: the positions to close, and the resting trailing stop orders to cancel because the stop loss
+    /// triggered first
+    pub(super) fn check<F>(
+        &mut self,
+        positions: &BTreeMap<PositionKey, Position>,
+        locks: &BTreeMap<PositionKey, PositionLock>,
+        capabilities: F,
+    ) -> (StopExits, Vec<String>)
+    where
+        F: Fn(Exchange) -> ExchangeCapabilities,
+    {
+        let mut exits = vec![];
+        let mut cancels = vec![];
+        let Some(options) = self.options else {
+            return (exits, cancels);
+        };
+        // Stops of the positions closed since are dropped
+        self.stops
+            .retain(|key, _| positions.get(key).map_or(false, Position::is_opened));
+        for (key, pos) in positions.iter().filter(|(_, pos)| pos.is_opened()) {
+            let lock = locks.get(key);
+            if !self.stops.contains_key(key) {
+                // The position is still being opened
+                if lock.is_some() {
+                    continue;
+                }
+                self.stops
+                    .insert(key.clone(), options.stop(&capabilities(pos.exchange)));
+            }
+            let Some(stop) = self.stops.get_mut(key) else {
+                continue;
+            };
+            match (stop.should_stop(pos.unreal_profit_loss), lock) {
+                // The resting trailing stop order is canceled first, the position is closed once it is released
+                (Some(_), Some(lock)) if stop.is_exchange_side() => cancels.push(lock.order_id.clone()),
+                (Some(event), None) => exits.push((key.clone(), pos.clone(), Some(event))),
+                (None, None) if stop.is_exchange_side() => exits.push((key.clone(), pos.clone(), None)),
+                _ => {}
+            }
+        }
+        (exits, cancels)
+    }
+
+    /// The exchange side trailing stop order closing a position with `close`, `close` if the stop is local
+    pub(super) fn exit_order(&self, key: &PositionKey, pos: &Position, close: AddOrderRequest) -> AddOrderRequest {
+        let Some(stop) = self.stops.get(key) else {
+            return close;
+        };
+        let open_price = pos
+            .open_order
+            .as_ref()
+            .map_or(pos.current_symbol_price, |o| o.weighted_price);
+        stop.exit_order(pos.kind, open_price, close.clone()).unwrap_or(close)
+    }
+}
@@ -27,6 +27,28 @@ Through a single method, drivers can receive queries and mutations to get the sp
 
 Specific APIs exist to facilitate persisting time based statistical models.
 
+## Ensembles
+
+The `ensemble` strategy blends the signals of several member strategies with a vote.
+
+## Features
+
+Strategies can declare the features recorded by their driver, which are exported as datasets for offline training.
+
+## Schedules
+
+Drivers only let strategies trade within the weekly windows of their trading schedule, if any.
+
+## Quotes
+
+Market making strategies quote both sides of a book, the driver rests their quotes as limit orders that are amended
+in place as quotes move, within the quoting limits of the driver options.
+
+## Signal only mode
+
+Drivers can publish the signals of their strategy to a webhook or a NATS subject instead of trading them, to feed an
+external execution stack.
+
  */
 
 #![deny(unused_must_use, unused_mut, unused_imports, unused_import_braces)]
@@ -75,11 +97,12 @@ use brokers::types::MarketChannel;
 use db::DbOptions;
 use error::*;
 use ext::ResultExt;
+use portfolio::balance::ExpectedBalances;
 use trading::engine::TradingEngine;
 use util::time::TimedData;
 
 use crate::actor::StrategyActorOptions;
-use crate::plugin::{StrategyPlugin, StrategyPluginRegistry};
+use crate::plugin::{find_plugin, StrategyPluginRegistry};
 use crate::prelude::StrategyDriverSettings;
 use crate::types::StratEvent;
 
@@ -89,16 +112,22 @@ pub mod prelude {
     pub use super::settings::{StrategyCopySettings, StrategyDriverSettings, StrategySettings};
     pub use super::types::StratEvent;
     pub use super::EventLogger;
+    pub use portfolio::portfolio::PositionMode;
 }
 
 pub mod actor;
 pub mod driver;
+pub mod ensemble;
 pub mod error;
 pub mod event;
+pub mod feature_store;
 mod generic;
+pub mod microstructure;
 pub mod models;
 pub mod plugin;
+pub mod publish;
 pub mod query;
+pub mod schedule;
 pub mod settings;
 #[cfg(test)]
 mod test_util;
@@ -137,6 +166,8 @@ pub enum StrategyLifecycleCmd {
     Restart,
     StopTrading,
     ResumeTrading,
+    /// Stop trading and market close all open positions
+    Flatten,
 }
 
 /// Strategy type, followed by a unique key
@@ -178,14 +209,16 @@ impl Trader {
         logger: Option<StratEventLoggerRef>,
     ) -> Result<Self> {
         let strat_type = settings.strat.strat_type.clone();
-        let plugin: &'static StrategyPlugin = plugins.get(strat_type.as_str()).ok_or(Error::StrategyPluginNotFound)?;
+        let plugin = find_plugin(plugins, &strat_type).ok_or(Error::StrategyPluginNotFound)?;
         let uuid = Uuid::new_v4();
-        let key = plugin.options(settings.strat.options.clone())?.key();
+        let key = strategy_key(plugins, settings)?;
         let settings = settings.clone();
         let db_opts = db_opts.clone();
         let actor = StrategyActor::new_with_uuid(
             Box::new(move || {
-                settings::from_driver_settings(plugin, &db_opts, &settings, engine.clone(), logger.clone()).unwrap()
+                // Restarted strategies run the plugin as reloaded at runtime
+                let plugin = find_plugin(plugins, &strat_type).unwrap_or_else(|| plugin.clone());
+                settings::from_driver_settings(&plugin, &db_opts, &settings, engine.clone(), logger.clone()).unwrap()
             }),
             actor_settings,
             uuid,
@@ -201,6 +234,8 @@ impl Trader {
 
     pub fn market_event_recipient(&self) -> Recipient<MarketEventEnvelopeRef> { self.actor.clone().recipient() }
 
+    pub fn expected_balances_recipient(&self) -> Recipient<ExpectedBalances> { self.actor.clone().recipient() }
+
     pub async fn send<M: 'static>(&self, m: M) -> Result<<M as Message>::Result>
     where
         M: Message + Send,
@@ -211,6 +246,19 @@ impl Trader {
     }
 }
 
+/// The key of the strategy of driver settings, as the key of the trader it runs in
+///
+/// # Errors
+///
+/// If the plugin of the strategy is not found or its options are invalid
+pub fn strategy_key(
+    plugins: &StrategyPluginRegistry<'static>,
+    settings: &StrategyDriverSettings,
+) -> Result<StrategyKey> {
+    let plugin = find_plugin(plugins, &settings.strat.strat_type).ok_or(Error::StrategyPluginNotFound)?;
+    Ok(plugin.options(settings.strat.options.clone())?.key())
+}
+
 #[async_trait]
 pub trait EventLogger<T>: Sync + Send + Debug {
     async fn log(&self, event: T);
@@ -0,0 +1,78 @@
+//! Microstructure indicators of the order books received by a driver, computed once per book and shared with its
+//! strategy through the strategy context.
+
+use std::collections::HashMap;
+
+use brokers::prelude::Exchange;
+use brokers::types::{MarketEvent, MarketEventEnvelope, Pair};
+use stats::indicators::microstructure::Microstructure;
+use stats::Next;
+
+use crate::error::*;
+
+/// Bound of the weighted depth of books, in basis points from the mid price
+const DEFAULT_DEPTH_BPS: f64 = 10.0;
+/// Number of books of the order flow imbalance and spread percentiles
+const DEFAULT_PERIOD: usize = 100;
+
+/// Microstructure indicators by market
+#[derive(Debug, Clone)]
+pub struct BookIndicators {
+    /// Indicators of a market that did not receive a book yet
+    template: Microstructure,
+    books: HashMap<(Exchange, Pair), Microstructure>,
+}
+
+impl BookIndicators {
+    /// * `depth_bps`: bound of the weighted depth, in basis points from the mid price
+    /// * `period`: number of books of the order flow imbalance and spread percentiles
+    pub fn new(depth_bps: f64, period: usize) -> Result<Self> {
+        Ok(Self {
+            template: Microstructure::new(depth_bps, period).map_err(|e| Error::BadConfiguration(e.to_string()))?,
+            books: HashMap::default(),
+        })
+    }
+
+    /// Update the indicators of the market of an order book event, other events are ignored
+    pub fn update(&mut self, le: &MarketEventEnvelope) {
+        if let MarketEvent::Orderbook(book) = &le.e {
+            let template = &self.template;
+            self.books
+                .entry((le.symbol.xch, le.symbol.value.clone()))
+                .or_insert_with(|| template.clone())
+                .next((book.bids.as_slice(), book.asks.as_slice()));
+        }
+    }
+
+    pub fn get(&self, xch: Exchange, pair: Pair) -> Option<&Microstructure> { self.books.get(&(xch, pair)) }
+}
+
+impl Default for BookIndicators {
+    fn default() -> Self {
+        Self {
+            template: Microstructure::new(DEFAULT_DEPTH_BPS, DEFAULT_PERIOD).unwrap(),
+            books: HashMap::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use brokers::exchange::Exchange;
+
+    use crate::test_util::fixtures::default_order_book_event;
+
+    use super::BookIndicators;
+
+    #[test]
+    fn update_from_order_books() {
+        let mut indicators = BookIndicators::default();
+        indicators.update(&default_order_book_event());
+        let values = indicators
+            .get(Exchange::Binance, "BTC_USDT".into())
+            .and_then(|m| m.current)
+            .unwrap();
+        assert_eq!(values.mid, 1.0);
+        assert!(indicators.get(Exchange::Kraken, "BTC_USDT".into()).is_none());
+    }
+}
@@ -0,0 +1,120 @@
+//! Feature vectors of recent candles, the inputs of models trained outside of strategies
+
+use std::collections::VecDeque;
+
+use brokers::types::Candle;
+use stats::{Next, Reset};
+
+/// Features computed for each candle
+pub const FEATURES_PER_CANDLE: usize = 3;
+
+/// Features of the last `lookback` final candles, oldest first, with for each candle :
+/// - the log return of the close price
+/// - the range of the candle relative to its close price
+/// - the log change of the traded volume
+///
+/// Models must be trained with the same layout.
+#[derive(Debug, Clone)]
+pub struct CandleFeatures {
+    lookback: usize,
+    last: Option<(f64, f64)>,
+    features: VecDeque<[f32; FEATURES_PER_CANDLE]>,
+}
+
+impl CandleFeatures {
+    pub fn new(lookback: usize) -> Self {
+        Self {
+            lookback,
+            last: None,
+            features: VecDeque::with_capacity(lookback),
+        }
+    }
+
+    /// Length of the feature vectors
+    pub fn len(&self) -> usize { self.lookback * FEATURES_PER_CANDLE }
+
+    pub fn is_empty(&self) -> bool { self.lookback == 0 }
+}
+
+impl Next<&Candle> for CandleFeatures {
+    /// The feature vector, once `lookback` candles followed a first candle
+    type Output = Option<Vec<f32>>;
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn next(&mut self, candle: &Candle) -> Self::Output {
+        if !candle.is_final || candle.close <= 0.0 {
+            return None;
+        }
+        if let Some((last_close, last_volume)) = self.last {
+            let log_change = |current: f64, previous: f64| {
+                if current > 0.0 && previous > 0.0 {
+                    (current / previous).ln()
+                } else {
+                    0.0
+                }
+            };
+            if self.features.len() == self.lookback {
+                self.features.pop_front();
+            }
+            self.features.push_back([
+                log_change(candle.close, last_close) as f32,
+                ((candle.high - candle.low) / candle.close) as f32,
+                log_change(candle.volume, last_volume) as f32,
+            ]);
+        }
+        self.last = Some((candle.close, candle.volume));
+        (self.lookback > 0 && self.features.len() == self.lookback)
+            .then(|| self.features.iter().flatten().copied().collect())
+    }
+}
+
+impl Reset for CandleFeatures {
+    fn reset(&mut self) {
+        self.last = None;
+        self.features.clear();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::Utc;
+
+    use brokers::types::Candle;
+    use stats::Next;
+
+    use super::CandleFeatures;
+
+    fn candle(close: f64, volume: f64, is_final: bool) -> Candle {
+        Candle {
+            event_time: Utc::now(),
+            pair: "BTC_USDT".into(),
+            start_time: Utc::now(),
+            end_time: Utc::now(),
+            open: close,
+            high: close * 1.1,
+            low: close * 0.9,
+            close,
+            volume,
+            quote_volume: close * volume,
+            trade_count: 1,
+            is_final,
+        }
+    }
+
+    #[test]
+    fn features_of_recent_candles() {
+        let mut features = CandleFeatures::new(2);
+        assert_eq!(features.len(), 6);
+        assert!(features.next(&candle(100.0, 10.0, true)).is_none());
+        assert!(features.next(&candle(110.0, 20.0, true)).is_none());
+        // Candles that are not final are ignored
+        assert!(features.next(&candle(1.0, 1.0, false)).is_none());
+        let vector = features.next(&candle(99.0, 20.0, true)).unwrap();
+        assert_eq!(vector.len(), 6);
+        assert!((vector[0] - 1.1f32.ln()).abs() < 1e-6);
+        assert!((vector[1] - 0.2).abs() < 1e-6);
+        assert!((vector[2] - 2f32.ln()).abs() < 1e-6);
+        assert!((vector[3] - 0.9f32.ln()).abs() < 1e-6);
+        assert!(vector[5].abs() < 1e-6);
+    }
+}
@@ -8,8 +8,8 @@ use db::Storage;
 use ext::ResultExt;
 use stats::Next;
 
-use crate::error::Result;
-use crate::models::persist::UpdateFn;
+use crate::error::{Error, Result};
+use crate::models::persist::{MigrateFn, UpdateFn};
 use crate::models::Model;
 
 use super::persist::{ModelValue, PersistentValue};
@@ -20,6 +20,8 @@ pub struct IndicatorModel<T, R> {
     model: PersistentValue<T>,
     #[derivative(Debug = "ignore")]
     update_fn: UpdateFn<T, R>,
+    #[derivative(Debug = "ignore")]
+    migration: Option<MigrateFn<T>>,
 }
 
 impl<T: Serialize + DeserializeOwned + Copy + Next<R>, R> IndicatorModel<T, R> {
@@ -30,9 +32,19 @@ impl<T: Serialize + DeserializeOwned + Copy + Next<R>, R> IndicatorModel<T, R> {
                 m.next(args);
                 m
             },
+            migration: None,
         }
     }
 
+    /// Version the persisted model, values persisted by older versions are migrated with `migrate`
+    pub fn with_migration(mut self, version: u32, migrate: MigrateFn<T>) -> Self {
+        self.model = self.model.with_version(version);
+        self.migration = Some(migrate);
+        self
+    }
+
+    pub fn key(&self) -> &str { &self.model.key }
+
     pub fn update(&mut self, next_value: R) -> Result<()> { self.model.update(self.update_fn, next_value).err_into() }
 
     pub fn import(&mut self, v: serde_json::Value) -> Result<()> {
@@ -57,6 +69,18 @@ impl<T: Serialize + DeserializeOwned + Copy + Next<R>, R> Model<T> for Indicator
     fn has_value(&self) -> bool { self.model.has_model() }
 
     fn value(&self) -> Option<T> { self.model.value() }
+
+    fn version(&self) -> u32 { self.model.version() }
+
+    fn migrate(&self, old_version: u32, bytes: &[u8]) -> Result<serde_json::Value> {
+        match self.migration {
+            Some(migrate) => Ok(serde_json::to_value(migrate(old_version, bytes)?)?),
+            None => Err(Error::NoMigrationPath {
+                from: old_version,
+                to: self.version(),
+            }),
+        }
+    }
 }
 
 #[cfg(test)]
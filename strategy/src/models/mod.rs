@@ -7,11 +7,14 @@ use serde::Serialize;
 pub use indicator_model::IndicatorModel;
 pub use reducer::PersistentReducer;
 
-use crate::error::Result;
+use crate::error::{Error, Result};
 
+pub mod features;
 pub mod indicator_model;
 pub mod indicator_reducer;
 pub mod io;
+#[cfg(feature = "onnx")]
+pub mod onnx;
 pub mod persist;
 pub mod reducer;
 
@@ -23,6 +26,50 @@ pub trait Model<T> {
     fn last_value_time(&self) -> Option<DateTime<Utc>>;
     fn has_value(&self) -> bool;
     fn value(&self) -> Option<T>;
+
+    /// Version of the persisted state of the model, bumped when its layout changes
+    fn version(&self) -> u32 { 0 }
+
+    /// Migrate the state persisted by an older version of the model to the current version
+    ///
+    /// # Arguments
+    ///
+    /// * `old_version`: the version of the model that persisted the state
+    /// * `bytes`: the persisted state as json
+    ///
+    /// returns: the state of the current version
+    fn migrate(&self, old_version: u32, _bytes: &[u8]) -> Result<serde_json::Value> {
+        Err(Error::NoMigrationPath {
+            from: old_version,
+            to: self.version(),
+        })
+    }
+}
+
+/// The migration of a persisted model to its current version, see [`Model::migrate`]
+#[derive(Derivative)]
+#[derivative(Debug)]
+pub struct ModelMigration<'a> {
+    /// Key of the model in the models table
+    pub key: String,
+    /// Current version of the model
+    pub version: u32,
+    #[derivative(Debug = "ignore")]
+    migrate: Box<dyn Fn(u32, &[u8]) -> Result<serde_json::Value> + 'a>,
+}
+
+impl<'a> ModelMigration<'a> {
+    pub fn of<T, M: Model<T>>(key: &str, model: &'a M) -> Self {
+        Self {
+            key: key.to_string(),
+            version: model.version(),
+            migrate: Box::new(move |old_version, bytes| model.migrate(old_version, bytes)),
+        }
+    }
+
+    pub fn migrate(&self, old_version: u32, bytes: &[u8]) -> Result<serde_json::Value> {
+        (self.migrate)(old_version, bytes)
+    }
 }
 
 pub type Window<'a, T: 'a + Serialize + DeserializeOwned> = impl Iterator<Item = &'a T>;
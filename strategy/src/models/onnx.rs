@@ -0,0 +1,88 @@
+//! Inference of ONNX models, so that models trained in Python can run in strategies without the Python runtime
+
+use std::path::{Path, PathBuf};
+
+use tract_onnx::prelude::*;
+
+use crate::error::{Error, Result};
+
+/// An ONNX model with a single `[1, input_len]` input of `f32` features, optimized for inference when loaded
+#[derive(Derivative)]
+#[derivative(Debug)]
+pub struct OnnxModel {
+    path: PathBuf,
+    input_len: usize,
+    #[derivative(Debug = "ignore")]
+    plan: TypedRunnableModel<TypedModel>,
+}
+
+fn inference_err(e: impl std::fmt::Display) -> Error { Error::ModelInference(e.to_string()) }
+
+impl OnnxModel {
+    /// Load the model at `path`
+    ///
+    /// # Errors
+    ///
+    /// The file is not a valid ONNX model, or its input cannot be a `[1, input_len]` tensor
+    pub fn load<P: AsRef<Path>>(path: P, input_len: usize) -> Result<Self> {
+        let plan = tract_onnx::onnx()
+            .model_for_path(path.as_ref())
+            .and_then(|m| m.with_input_fact(0, f32::fact([1, input_len]).into()))
+            .and_then(|m| m.into_optimized())
+            .and_then(|m| m.into_runnable())
+            .map_err(|e| inference_err(format!("{} : {}", path.as_ref().display(), e)))?;
+        Ok(Self {
+            path: path.as_ref().to_path_buf(),
+            input_len,
+            plan,
+        })
+    }
+
+    pub fn path(&self) -> &Path { &self.path }
+
+    /// Run the model on a feature vector
+    ///
+    /// returns: the values of the first output of the model
+    pub fn predict(&self, features: &[f32]) -> Result<Vec<f32>> {
+        if features.len() != self.input_len {
+            return Err(inference_err(format!(
+                "expected {} features, got {}",
+                self.input_len,
+                features.len()
+            )));
+        }
+        let input = Tensor::from_shape(&[1, self.input_len], features).map_err(inference_err)?;
+        let outputs = self.plan.run(tvec!(input.into())).map_err(inference_err)?;
+        let output = outputs
+            .first()
+            .ok_or_else(|| inference_err("the model has no output"))?
+            .to_array_view::<f32>()
+            .map_err(inference_err)?;
+        Ok(output.iter().copied().collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::OnnxModel;
+
+    #[test]
+    fn fail_to_load_missing_models() {
+        let dir = util::test::test_dir();
+        let err = OnnxModel::load(dir.path().join("missing.onnx"), 3).unwrap_err();
+        assert_eq!(err.short_name(), "model_inference");
+    }
+
+    /// `y = x . [1, 2, 3] + 0.5`, for a `[1, 3]` input `x`
+    fn linear_model_path() -> String { format!("{}/test_models/linear.onnx", env!("CARGO_MANIFEST_DIR")) }
+
+    #[test]
+    fn predict_with_fixture_model() {
+        let model = OnnxModel::load(linear_model_path(), 3).unwrap();
+        let output = model.predict(&[1.0, 2.0, -1.0]).unwrap();
+        assert_eq!(output.len(), 1);
+        assert!((output[0] - 2.5).abs() < 1e-6);
+        let err = model.predict(&[1.0, 2.0]).unwrap_err();
+        assert_eq!(err.short_name(), "model_inference");
+    }
+}
@@ -15,18 +15,30 @@ use util::time::now;
 use crate::error::Result;
 use crate::models::{TimedValue, TimedWindow, Window};
 
-static MODELS_TABLE_NAME: &str = "models";
+pub(crate) static MODELS_TABLE_NAME: &str = "models";
 
 pub type UpdateFn<T, A> = for<'a> fn(&'a mut T, A) -> &'a T;
 
+/// Migrates the persisted state of an older version, as json, see [`crate::models::Model::migrate`]
+pub type MigrateFn<T> = fn(u32, &[u8]) -> Result<T>;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ModelValue<T> {
     pub value: T,
     pub at: DateTime<Utc>,
+    /// Version of the model that persisted the value, values persisted before versioning are version 0
+    #[serde(default)]
+    pub version: u32,
 }
 
 impl<T> ModelValue<T> {
-    pub fn new(value: T) -> Self { Self { value, at: now() } }
+    pub fn new(value: T) -> Self {
+        Self {
+            value,
+            at: now(),
+            version: 0,
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -36,6 +48,7 @@ pub struct PersistentValue<T> {
     pub db: Arc<dyn Storage>,
     pub key: String,
     is_loaded: bool,
+    version: u32,
 }
 
 impl<T: Serialize + DeserializeOwned + Copy> PersistentValue<T> {
@@ -51,9 +64,18 @@ impl<T: Serialize + DeserializeOwned + Copy> PersistentValue<T> {
             last_known: init,
             last_load_attempt: None,
             is_loaded: false,
+            version: 0,
         }
     }
 
+    /// Persist values with this version of the model
+    pub fn with_version(mut self, version: u32) -> Self {
+        self.version = version;
+        self
+    }
+
+    pub fn version(&self) -> u32 { self.version }
+
     pub fn load(&mut self) -> Result<()> {
         self.last_load_attempt = Some(Utc::now());
         let result = self.db.get(MODELS_TABLE_NAME, &self.key);
@@ -78,7 +100,12 @@ impl<T: Serialize + DeserializeOwned + Copy> PersistentValue<T> {
         Ok(())
     }
 
-    pub fn persist(&mut self) -> Result<()> { self.db.put(MODELS_TABLE_NAME, &self.key, &self.last_known).err_into() }
+    pub fn persist(&mut self) -> Result<()> {
+        if let Some(model) = self.last_known.as_mut() {
+            model.version = self.version;
+        }
+        self.db.put(MODELS_TABLE_NAME, &self.key, &self.last_known).err_into()
+    }
 
     pub fn last_value_time(&self) -> Option<DateTime<Utc>> { self.last_known.as_ref().map(|m| m.at) }
 
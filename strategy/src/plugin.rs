@@ -1,7 +1,12 @@
-use once_cell::sync::OnceCell;
+use once_cell::sync::{Lazy, OnceCell};
 use std::collections::HashMap;
 use std::fmt::{Debug, Formatter};
-use std::sync::Arc;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+use libloading::Library;
+use uuid::Uuid;
 
 use serde::de::DeserializeOwned;
 use serde_json::Value;
@@ -10,7 +15,7 @@ use db::Storage;
 use trading::engine::TradingEngine;
 
 use crate::driver::Strategy;
-use crate::error::Result;
+use crate::error::{Error, Result};
 use crate::settings::StrategyOptions;
 use crate::StratEventLoggerRef;
 
@@ -31,6 +36,7 @@ pub struct StrategyPluginContext {
     pub logger: Option<StratEventLoggerRef>,
 }
 
+#[derive(Clone)]
 pub struct StrategyPlugin {
     name: &'static str,
     provider: StratProvider,
@@ -73,3 +79,197 @@ pub fn gather_plugins() -> StrategyPluginRegistry<'static> {
 static PLUGIN_REGISTRY: OnceCell<StrategyPluginRegistry<'static>> = OnceCell::new();
 
 pub fn plugin_registry() -> &'static StrategyPluginRegistry<'static> { PLUGIN_REGISTRY.get_or_init(gather_plugins) }
+
+/// Symbol of the function returning the plugins of a strategy library, see [`export_strategy_plugins`]
+pub const PLUGIN_ENTRY_SYMBOL: &[u8] = b"tradai_strategy_plugins";
+
+type PluginEntry = fn() -> &'static [StrategyPlugin];
+
+/// Export the plugins of a strategy library built as a `cdylib`, to be loaded at runtime with
+/// [`load_plugin_library`]
+///
+/// The library must be built with the same compiler and version of this crate as the loading process.
+#[macro_export]
+macro_rules! export_strategy_plugins {
+    ($($plugin:expr),+ $(,)?) => {
+        #[no_mangle]
+        pub fn tradai_strategy_plugins() -> &'static [$crate::plugin::StrategyPlugin] {
+            static PLUGINS: &[$crate::plugin::StrategyPlugin] = &[$($plugin),+];
+            PLUGINS
+        }
+    };
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", content = "path", rename_all = "snake_case")]
+pub enum PluginSource {
+    /// Compiled in the binary
+    Builtin,
+    /// Loaded at runtime from a library
+    Library(PathBuf),
+}
+
+/// A strategy plugin, with the library it was loaded from if any
+pub struct LoadedPlugin {
+    plugin: StrategyPlugin,
+    source: PluginSource,
+    // Dropped last, the library stays loaded as long as a strategy holds one of its plugins
+    _library: Option<Arc<Library>>,
+}
+
+impl LoadedPlugin {
+    fn builtin(plugin: &StrategyPlugin) -> Self {
+        Self {
+            plugin: plugin.clone(),
+            source: PluginSource::Builtin,
+            _library: None,
+        }
+    }
+
+    pub fn name(&self) -> &str { self.plugin.name }
+
+    pub fn source(&self) -> &PluginSource { &self.source }
+}
+
+impl Deref for LoadedPlugin {
+    type Target = StrategyPlugin;
+
+    fn deref(&self) -> &Self::Target { &self.plugin }
+}
+
+impl Debug for LoadedPlugin {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LoadedPlugin")
+            .field("name", &self.plugin.name)
+            .field("source", &self.source)
+            .finish()
+    }
+}
+
+/// Plugins loaded at runtime, they take precedence over builtin plugins of the same name
+static RUNTIME_PLUGINS: Lazy<RwLock<HashMap<String, Arc<LoadedPlugin>>>> = Lazy::new(RwLock::default);
+
+/// Find a plugin loaded at runtime, or a builtin plugin of `plugins`
+pub fn find_plugin(plugins: &StrategyPluginRegistry<'static>, name: &str) -> Option<Arc<LoadedPlugin>> {
+    RUNTIME_PLUGINS
+        .read()
+        .unwrap()
+        .get(name)
+        .cloned()
+        .or_else(|| plugins.get(name).map(|p| Arc::new(LoadedPlugin::builtin(p))))
+}
+
+/// All plugins by name, builtin plugins replaced at runtime are listed once with their runtime source
+pub fn list_plugins() -> Vec<(String, PluginSource)> {
+    let mut plugins: HashMap<String, PluginSource> = plugin_registry()
+        .keys()
+        .map(|name| ((*name).to_string(), PluginSource::Builtin))
+        .collect();
+    for (name, plugin) in RUNTIME_PLUGINS.read().unwrap().iter() {
+        plugins.insert(name.clone(), plugin.source.clone());
+    }
+    let mut plugins: Vec<_> = plugins.into_iter().collect();
+    plugins.sort_by(|a, b| a.0.cmp(&b.0));
+    plugins
+}
+
+/// Load the plugins of a strategy library, replacing plugins of the same names
+///
+/// The library is copied before being loaded, so that reloading a rebuilt library loads its new code even while
+/// strategies still run the previous one.
+///
+/// returns: the names of the loaded plugins
+///
+/// # Errors
+///
+/// The library cannot be copied or loaded, or does not export its plugins with [`export_strategy_plugins`]
+pub fn load_plugin_library<P: AsRef<Path>>(path: P) -> Result<Vec<String>> {
+    let path = path.as_ref();
+    let copy = std::env::temp_dir().join(format!(
+        "{}-{}",
+        Uuid::new_v4(),
+        path.file_name()
+            .map_or_else(|| "plugin".into(), |n| n.to_string_lossy())
+    ));
+    std::fs::copy(path, &copy)?;
+    // Safety: libraries are trusted to export plugins built against this crate, see `export_strategy_plugins`
+    let library = Arc::new(unsafe { Library::new(&copy) }?);
+    let plugins = unsafe { (*library.get::<PluginEntry>(PLUGIN_ENTRY_SYMBOL)?)() };
+    let mut runtime_plugins = RUNTIME_PLUGINS.write().unwrap();
+    let names = plugins
+        .iter()
+        .map(|plugin| {
+            info!(plugin = plugin.name, library = ?path, "loaded strategy plugin");
+            runtime_plugins.insert(
+                plugin.name.to_string(),
+                Arc::new(LoadedPlugin {
+                    plugin: plugin.clone(),
+                    source: PluginSource::Library(path.to_path_buf()),
+                    _library: Some(library.clone()),
+                }),
+            );
+            plugin.name.to_string()
+        })
+        .collect();
+    Ok(names)
+}
+
+/// Unload a plugin loaded at runtime, strategies that still run it keep its library loaded until they stop
+///
+/// # Errors
+///
+/// No plugin of this name was loaded at runtime
+pub fn unload_plugin(name: &str) -> Result<()> {
+    RUNTIME_PLUGINS
+        .write()
+        .unwrap()
+        .remove(name)
+        .map(|_| info!(plugin = name, "unloaded strategy plugin"))
+        .ok_or(Error::StrategyPluginNotFound)
+}
+
+/// Reload the library of a plugin loaded at runtime, builtin plugins are left as they are
+///
+/// returns: the names of the reloaded plugins
+///
+/// # Errors
+///
+/// The plugin does not exist, or its library cannot be loaded
+pub fn reload_plugin(name: &str) -> Result<Vec<String>> {
+    let source = RUNTIME_PLUGINS.read().unwrap().get(name).map(|p| p.source.clone());
+    match source {
+        Some(PluginSource::Library(path)) => load_plugin_library(path),
+        Some(PluginSource::Builtin) => Ok(vec![]),
+        None if plugin_registry().contains_key(name) => Ok(vec![]),
+        None => Err(Error::StrategyPluginNotFound),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use super::{find_plugin, load_plugin_library, reload_plugin, unload_plugin, PluginSource, StrategyPlugin};
+    use crate::error::Error;
+
+    static TEST_PLUGIN: StrategyPlugin = StrategyPlugin::new(
+        "test_plugin",
+        |_| Err(Error::FeatureNotImplemented),
+        |_, _, _| Err(Error::FeatureNotImplemented),
+    );
+
+    #[test]
+    fn find_builtin_and_runtime_plugins() {
+        let plugins = HashMap::from([("test_plugin", &TEST_PLUGIN)]);
+        let plugin = find_plugin(&plugins, "test_plugin").unwrap();
+        assert_eq!(plugin.name(), "test_plugin");
+        assert_eq!(plugin.source(), &PluginSource::Builtin);
+        assert!(find_plugin(&plugins, "missing").is_none());
+        assert!(matches!(
+            unload_plugin("test_plugin"),
+            Err(Error::StrategyPluginNotFound)
+        ));
+        assert!(matches!(reload_plugin("missing"), Err(Error::StrategyPluginNotFound)));
+        assert!(load_plugin_library("/nonexistent/libplugin.so").is_err());
+    }
+}
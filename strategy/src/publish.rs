@@ -0,0 +1,267 @@
+//! Signal publishing : in signal only mode, the driver does not convert the signals of a strategy into orders but
+//! publishes them to a webhook or a NATS subject, so that the platform can feed an external execution stack.
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use tokio::sync::{mpsc, OnceCell};
+use uuid::Uuid;
+
+use brokers::prelude::*;
+use trading::position::{OperationKind, PositionKind};
+use trading::signal::TradeSignal;
+use trading::types::TradeKind;
+
+use crate::error::{Error, Result};
+use crate::generic::metrics;
+
+/// Signals waiting to be published, publishing fails once the queue is full
+const PUBLISH_QUEUE_SIZE: usize = 1024;
+/// Attempts to publish the signals of an event before they are dropped
+const PUBLISH_ATTEMPTS: u32 = 5;
+const PUBLISH_RETRY_DELAY: Duration = Duration::from_millis(200);
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Where the signals of a strategy are published
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SignalPublisherOptions {
+    /// Signals are posted as json to `url`
+    Webhook { url: String },
+    /// Signals are published as json to `subject`
+    Nats {
+        host: String,
+        subject: String,
+        #[serde(default)]
+        username: Option<String>,
+        #[serde(default)]
+        password: Option<String>,
+    },
+}
+
+impl SignalPublisherOptions {
+    /// A publisher queuing signals in the background, the NATS server is only connected to once signals are published
+    ///
+    /// # Errors
+    ///
+    /// If the webhook client cannot be built
+    pub fn publisher(&self) -> Result<Arc<dyn SignalPublisher>> {
+        let publisher: Arc<dyn SignalPublisher> = match self {
+            SignalPublisherOptions::Webhook { url } => Arc::new(WebhookPublisher {
+                client: reqwest::Client::builder()
+                    .timeout(WEBHOOK_TIMEOUT)
+                    .build()
+                    .map_err(|e| Error::SignalPublisher(e.to_string()))?,
+                url: url.clone(),
+            }),
+            SignalPublisherOptions::Nats {
+                host,
+                subject,
+                username,
+                password,
+            } => Arc::new(NatsPublisher {
+                host: host.clone(),
+                username: username.clone(),
+                password: password.clone(),
+                conn: OnceCell::new(),
+                subject: subject.clone(),
+            }),
+        };
+        Ok(Arc::new(QueuedPublisher::new(publisher, PUBLISH_RETRY_DELAY)))
+    }
+}
+
+/// A signal as published, emitted together with the other signals of the same event
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PublishedSignal {
+    pub strategy: String,
+    pub trace_id: Uuid,
+    pub event_time: DateTime<Utc>,
+    pub signal_time: DateTime<Utc>,
+    pub exchange: Exchange,
+    pub pair: String,
+    pub pos_kind: PositionKind,
+    pub op_kind: OperationKind,
+    pub trade_kind: TradeKind,
+    pub price: f64,
+    pub qty: Option<f64>,
+    pub order_type: OrderType,
+}
+
+impl PublishedSignal {
+    pub fn new(strategy: &str, signal: &TradeSignal) -> Self {
+        Self {
+            strategy: strategy.to_string(),
+            trace_id: signal.trace_id,
+            event_time: signal.event_time,
+            signal_time: signal.signal_time,
+            exchange: signal.exchange,
+            pair: signal.pair.to_string(),
+            pos_kind: signal.pos_kind,
+            op_kind: signal.op_kind,
+            trade_kind: signal.trade_kind.clone(),
+            price: signal.price,
+            qty: signal.qty,
+            order_type: signal.order_type,
+        }
+    }
+}
+
+#[async_trait]
+pub trait SignalPublisher: Send + Sync {
+    /// Publish the signals emitted for an event, as a single message
+    async fn publish(&self, signals: &[PublishedSignal]) -> Result<()>;
+}
+
+pub struct WebhookPublisher {
+    client: reqwest::Client,
+    url: String,
+}
+
+#[async_trait]
+impl SignalPublisher for WebhookPublisher {
+    async fn publish(&self, signals: &[PublishedSignal]) -> Result<()> {
+        self.client
+            .post(self.url.as_str())
+            .json(signals)
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .map(|_| ())
+            .map_err(|e| Error::SignalPublisher(e.to_string()))
+    }
+}
+
+pub struct NatsPublisher {
+    host: String,
+    username: Option<String>,
+    password: Option<String>,
+    conn: OnceCell<nats::asynk::Connection>,
+    subject: String,
+}
+
+impl NatsPublisher {
+    /// The connection to the NATS server, connected to on first use
+    async fn connection(&self) -> Result<&nats::asynk::Connection> {
+        self.conn
+            .get_or_try_init(|| async {
+                let options = match (&self.username, &self.password) {
+                    (Some(username), Some(password)) => nats::asynk::Options::with_user_pass(username, password),
+                    _ => nats::asynk::Options::new(),
+                };
+                options.with_name("signal_publisher").connect(self.host.as_str()).await
+            })
+            .await
+            .map_err(Error::from)
+    }
+}
+
+#[async_trait]
+impl SignalPublisher for NatsPublisher {
+    async fn publish(&self, signals: &[PublishedSignal]) -> Result<()> {
+        let payload = serde_json::to_vec(signals)?;
+        self.connection().await?.publish(self.subject.as_str(), payload).await?;
+        Ok(())
+    }
+}
+
+/// Publishes signals from a bounded queue in the background, so that publishing never holds the driver, signals are
+/// retried a few times before they are dropped
+pub struct QueuedPublisher {
+    queue: mpsc::Sender<Vec<PublishedSignal>>,
+    /// The publishing task is spawned with the first signals
+    worker: Mutex<Option<(Arc<dyn SignalPublisher>, mpsc::Receiver<Vec<PublishedSignal>>)>>,
+    retry_delay: Duration,
+}
+
+impl QueuedPublisher {
+    pub fn new(publisher: Arc<dyn SignalPublisher>, retry_delay: Duration) -> Self {
+        let (queue, rx) = mpsc::channel(PUBLISH_QUEUE_SIZE);
+        Self {
+            queue,
+            worker: Mutex::new(Some((publisher, rx))),
+            retry_delay,
+        }
+    }
+
+    async fn run(
+        publisher: Arc<dyn SignalPublisher>,
+        mut rx: mpsc::Receiver<Vec<PublishedSignal>>,
+        retry_delay: Duration,
+    ) {
+        while let Some(signals) = rx.recv().await {
+            let mut attempt = 0;
+            while let Err(e) = publisher.publish(&signals).await {
+                attempt += 1;
+                metrics::get().log_error(e.short_name());
+                if attempt >= PUBLISH_ATTEMPTS {
+                    error!(err = %e, signals = signals.len(), "failed to publish signals, dropping them");
+                    break;
+                }
+                warn!(err = %e, attempt = attempt, "failed to publish signals, retrying");
+                tokio::time::sleep(retry_delay * 2_u32.pow(attempt - 1)).await;
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl SignalPublisher for QueuedPublisher {
+    async fn publish(&self, signals: &[PublishedSignal]) -> Result<()> {
+        if let Some((publisher, rx)) = self.worker.lock().unwrap().take() {
+            tokio::spawn(Self::run(publisher, rx, self.retry_delay));
+        }
+        self.queue
+            .try_send(signals.to_vec())
+            .map_err(|e| Error::SignalPublisher(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use tokio::sync::Notify;
+
+    use super::{PublishedSignal, QueuedPublisher, SignalPublisher};
+    use crate::error::{Error, Result};
+
+    /// Fails the first `failures` attempts
+    #[derive(Default)]
+    struct FlakyPublisher {
+        failures: usize,
+        attempts: AtomicUsize,
+        published: Mutex<Vec<Vec<PublishedSignal>>>,
+        done: Notify,
+    }
+
+    #[async_trait]
+    impl SignalPublisher for FlakyPublisher {
+        async fn publish(&self, signals: &[PublishedSignal]) -> Result<()> {
+            if self.attempts.fetch_add(1, Ordering::SeqCst) < self.failures {
+                return Err(Error::SignalPublisher("unavailable".to_string()));
+            }
+            self.published.lock().unwrap().push(signals.to_vec());
+            self.done.notify_one();
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn queued_signals_are_retried() {
+        let flaky = Arc::new(FlakyPublisher {
+            failures: 2,
+            ..FlakyPublisher::default()
+        });
+        let queued = QueuedPublisher::new(flaky.clone(), Duration::from_millis(1));
+        queued.publish(&[]).await.unwrap();
+        tokio::time::timeout(Duration::from_secs(5), flaky.done.notified())
+            .await
+            .unwrap();
+        assert_eq!(flaky.attempts.load(Ordering::SeqCst), 3);
+        assert_eq!(flaky.published.lock().unwrap().len(), 1);
+    }
+}
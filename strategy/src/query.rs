@@ -2,10 +2,12 @@ use actix::Message;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
+use brokers::types::AddOrderRequest;
 use trading::position::Position;
 use trading::types::TradeOperation;
 
 use crate::error::*;
+use crate::feature_store::FeatureDataset;
 use crate::StrategyStatus;
 
 // TODO: Use GraphQLUnion to refactor this ugly bit of code
@@ -19,6 +21,8 @@ pub enum DataResult {
     Status(StrategyStatus),
     Operations(Vec<TradeOperation>),
     Indicators(PortfolioSnapshot),
+    PendingOrders(Vec<AddOrderRequest>),
+    Features(FeatureDataset),
 }
 
 #[derive(Deserialize, Serialize, actix::Message)]
@@ -36,6 +40,14 @@ pub enum DataQuery {
     Status,
     /// Indicators
     Indicators,
+    /// Orders pending confirmation
+    PendingOrders,
+    /// Execute the orders pending confirmation
+    ConfirmPendingOrders,
+    /// Drop the orders pending confirmation, releasing their positions
+    RejectPendingOrders,
+    /// Recorded features with their labels
+    Features,
 }
 
 #[derive(Deserialize, Serialize, juniper::GraphQLEnum)]
@@ -78,4 +90,10 @@ pub struct PortfolioSnapshot {
     pub pnl: f64,
     pub current_return: f64,
     pub value: f64,
+    /// Cumulative funding received by perpetual contract positions, negative when paid
+    #[serde(default)]
+    pub funding: f64,
+    /// Cumulative fees paid by executed orders, in the quote asset
+    #[serde(default)]
+    pub fees: f64,
 }
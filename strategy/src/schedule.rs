@@ -0,0 +1,84 @@
+//! Trading sessions : strategies only trade within the weekly windows of their schedule, the driver stops trading
+//! outside of them and optionally flattens positions, so that strategies can avoid weekends or specific hours.
+
+use chrono::{DateTime, Datelike, NaiveTime, Utc, Weekday};
+use chrono_tz::Tz;
+
+/// A daily trading window on some days of the week, in the timezone of the schedule
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct TradingWindow {
+    /// Days the window opens, every day if empty
+    #[serde(default)]
+    pub days: Vec<Weekday>,
+    pub start: NaiveTime,
+    /// The window closes the next day if the end is before the start
+    pub end: NaiveTime,
+}
+
+impl TradingWindow {
+    fn opens_on(&self, day: Weekday) -> bool { self.days.is_empty() || self.days.contains(&day) }
+
+    /// Whether the window is open on `day` at `time`, windows that wrap past midnight are open the next morning
+    fn contains(&self, day: Weekday, time: NaiveTime) -> bool {
+        if self.start <= self.end {
+            self.opens_on(day) && self.start <= time && time < self.end
+        } else {
+            (self.opens_on(day) && time >= self.start) || (self.opens_on(day.pred()) && time < self.end)
+        }
+    }
+}
+
+/// The trading sessions of a strategy
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct TradingSchedule {
+    /// Timezone of the windows, UTC by default
+    #[serde(default = "default_timezone")]
+    pub timezone: Tz,
+    pub windows: Vec<TradingWindow>,
+    /// Close open positions when a window closes
+    #[serde(default)]
+    pub flatten: bool,
+}
+
+fn default_timezone() -> Tz { Tz::UTC }
+
+impl TradingSchedule {
+    /// Whether any window is open at `at`
+    pub fn is_open(&self, at: DateTime<Utc>) -> bool {
+        let local = at.with_timezone(&self.timezone);
+        let (day, time) = (local.weekday(), local.time());
+        self.windows.iter().any(|w| w.contains(day, time))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{TimeZone, Utc};
+
+    use super::TradingSchedule;
+
+    #[test]
+    fn trade_within_windows() {
+        let schedule: TradingSchedule = serde_json::from_value(serde_json::json!({
+            "timezone": "Europe/Paris",
+            "windows": [
+                {"days": ["Mon", "Tue", "Wed", "Thu", "Fri"], "start": "09:00:00", "end": "17:30:00"},
+                {"days": ["Sun"], "start": "22:00:00", "end": "02:00:00"}
+            ],
+            "flatten": true
+        }))
+        .unwrap();
+        // Monday 2022-01-03, Paris is UTC+1 in winter
+        let at = |day: u32, hour: u32, minute: u32| Utc.with_ymd_and_hms(2022, 1, day, hour, minute, 0).unwrap();
+        assert!(schedule.is_open(at(3, 8, 0)));
+        assert!(!schedule.is_open(at(3, 7, 59)));
+        assert!(!schedule.is_open(at(3, 16, 30)));
+        // Saturday
+        assert!(!schedule.is_open(at(8, 10, 0)));
+        // Sunday night until Monday 2:00 in Paris
+        assert!(schedule.is_open(at(2, 21, 0)));
+        assert!(schedule.is_open(at(3, 0, 59)));
+        assert!(!schedule.is_open(at(3, 1, 0)));
+        assert!(schedule.flatten);
+    }
+}
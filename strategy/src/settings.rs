@@ -8,11 +8,17 @@ use serde_json::Value;
 use brokers::pair::filter_pairs;
 use brokers::prelude::*;
 use db::{get_or_create, DbOptions};
+use portfolio::risk::RiskLimits;
 use trading::engine::TradingEngine;
+use trading::quoting::QuotingOptions;
+use trading::screener::{load_universe, screener_db};
+use trading::sizing::PositionSizerOptions;
 
 use crate::driver::StrategyDriver;
 use crate::generic::GenericDriverOptions;
-use crate::plugin::{plugin_registry, StrategyPlugin, StrategyPluginContext};
+use crate::plugin::{find_plugin, plugin_registry, LoadedPlugin, StrategyPluginContext};
+use crate::publish::SignalPublisherOptions;
+use crate::schedule::TradingSchedule;
 use crate::{error::Result, Error, StratEventLoggerRef, StrategyKey};
 
 /// Strategy configuration
@@ -66,7 +72,12 @@ impl StrategyOptions for DefaultOptions {
 pub enum StrategyCopySettings {
     /// Replicates the strategy for all available markets on the target exchange
     MarketReplica {
+        #[serde(default)]
         pairs: Vec<String>,
+        /// Replicate for the pairs of the latest universe of this screener instead of `pairs`, see
+        /// [`trading::screener`]
+        #[serde(default)]
+        screener: Option<String>,
         exchanges: Vec<String>,
         base: StrategyDriverSettings,
     },
@@ -85,43 +96,103 @@ impl StrategyCopySettings {
         }
     }
 
+    /// The screener whose universe the strategy is replicated for, if any
+    pub fn screener(&self) -> Option<&str> {
+        match self {
+            StrategyCopySettings::MarketReplica { screener, .. } => screener.as_deref(),
+        }
+    }
+
+    /// Replicas for static pairs, see [`Self::all_in`] to replicate for the universe of a screener
+    ///
     /// # Panics
     ///
     /// if pair filtering breaks
     pub fn all(&self) -> Result<Vec<StrategyDriverSettings>> {
         match self {
             StrategyCopySettings::MarketReplica {
-                pairs,
+                screener: Some(name), ..
+            } => Err(Error::BadConfiguration(format!(
+                "replicas of the universe of screener {} need its database",
+                name
+            ))),
+            StrategyCopySettings::MarketReplica { pairs, .. } => {
+                self.replicate(|exchange| Ok(filter_pairs(&exchange, pairs).unwrap()))
+            }
+        }
+    }
+
+    /// Replicas for static pairs, or for the pairs of the latest universe of the screener, so that strategies
+    /// created after a scan follow the current universe, no replica is created before the first scan
+    ///
+    /// # Panics
+    ///
+    /// if pair filtering breaks
+    pub fn all_in<S: AsRef<Path>>(&self, db_opts: &DbOptions<S>) -> Result<Vec<StrategyDriverSettings>> {
+        match self {
+            StrategyCopySettings::MarketReplica {
+                screener: Some(name), ..
+            } => {
+                let universe = load_universe(screener_db(db_opts).as_ref(), name)
+                    .map_err(|e| Error::BadConfiguration(e.to_string()))?;
+                if universe.is_none() {
+                    warn!(screener = %name, "no universe screened yet, strategies are not replicated");
+                }
+                self.replicate(|exchange| {
+                    Ok(universe
+                        .iter()
+                        .filter(|u| u.exchange == exchange)
+                        .flat_map(|u| u.pairs())
+                        .collect())
+                })
+            }
+            StrategyCopySettings::MarketReplica { .. } => self.all(),
+        }
+    }
+
+    fn replicate<F>(&self, pairs_of: F) -> Result<Vec<StrategyDriverSettings>>
+    where
+        F: Fn(Exchange) -> Result<HashSet<Pair>>,
+    {
+        match self {
+            StrategyCopySettings::MarketReplica {
                 exchanges,
                 base:
                     StrategyDriverSettings {
                         strat,
                         driver,
                         report_name,
+                        risk_limits,
+                        position_sizer,
+                        schedule,
+                        quoting,
+                        signal_only,
                     },
+                ..
             } => {
-                let plugin = plugin_registry()
-                    .get(strat.strat_type.as_str())
-                    .ok_or(Error::StrategyPluginNotFound)?;
+                let plugin =
+                    find_plugin(plugin_registry(), strat.strat_type.as_str()).ok_or(Error::StrategyPluginNotFound)?;
                 let conf = plugin.options(strat.options.clone())?;
-                let known_exchanges = exchanges.iter().filter_map(|s| Exchange::from_str(s.as_str()).ok());
-                let strats = known_exchanges
-                    .into_iter()
-                    .flat_map(|exchange| {
-                        let pairs = filter_pairs(&exchange, pairs).unwrap();
-                        conf.replicate_for_pairs(pairs)
+                let mut strats = vec![];
+                for exchange in exchanges.iter().filter_map(|s| Exchange::from_str(s.as_str()).ok()) {
+                    strats.extend(
+                        conf.replicate_for_pairs(pairs_of(exchange)?)
                             .into_iter()
                             .map(|replica| StrategyDriverSettings {
                                 report_name: report_name.clone(),
                                 driver: driver.clone(),
+                                risk_limits: *risk_limits,
+                                position_sizer: *position_sizer,
+                                schedule: schedule.clone(),
+                                quoting: quoting.clone(),
+                                signal_only: signal_only.clone(),
                                 strat: Box::new(StrategySettings {
                                     options: replica,
                                     strat_type: strat.strat_type.clone(),
                                 }),
-                            })
-                    })
-                    .collect();
-
+                            }),
+                    );
+                }
                 Ok(strats)
             }
         }
@@ -144,10 +215,25 @@ pub struct StrategyDriverSettings {
     pub strat: Box<StrategySettings>,
     pub driver: StrategyDriverOptions,
     pub report_name: Option<String>,
+    /// Overrides the risk limits of the driver options
+    #[serde(default)]
+    pub risk_limits: Option<RiskLimits>,
+    /// Overrides the position sizer of the driver options
+    #[serde(default)]
+    pub position_sizer: Option<PositionSizerOptions>,
+    /// Overrides the trading schedule of the driver options
+    #[serde(default)]
+    pub schedule: Option<TradingSchedule>,
+    /// Overrides the quoting limits of the driver options
+    #[serde(default)]
+    pub quoting: Option<QuotingOptions>,
+    /// Publishes the signals of the strategy instead of trading them, overriding the driver options
+    #[serde(default)]
+    pub signal_only: Option<SignalPublisherOptions>,
 }
 
 pub fn from_driver_settings<S: AsRef<Path>>(
-    plugin: &StrategyPlugin,
+    plugin: &Arc<LoadedPlugin>,
     db_opts: &DbOptions<S>,
     s: &StrategyDriverSettings,
     engine: Arc<TradingEngine>,
@@ -164,14 +250,22 @@ pub fn from_driver_settings<S: AsRef<Path>>(
     let inner: Box<dyn crate::driver::Strategy> = plugin.strat(&strat_key, ctx, s.strat.options.clone())?;
 
     let driver = match &s.driver {
-        StrategyDriverOptions::Generic(options) => Box::new(crate::generic::GenericDriver::try_new(
-            inner.channels().into_iter().collect(),
-            db,
-            options,
-            inner,
-            engine,
-            logger,
-        )?),
+        StrategyDriverOptions::Generic(options) => Box::new(
+            crate::generic::GenericDriver::try_new(
+                inner.channels().into_iter().collect(),
+                db,
+                &options
+                    .with_risk_overrides(s.risk_limits.as_ref())
+                    .with_position_sizer(s.position_sizer)
+                    .with_schedule(s.schedule.clone())
+                    .with_quoting(s.quoting.clone())
+                    .with_signal_only(s.signal_only.clone()),
+                inner,
+                engine,
+                logger,
+            )?
+            .with_plugin(plugin.clone()),
+        ),
     };
     info!("Created strategy : {}", strat_key);
     Ok(driver)
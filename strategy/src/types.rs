@@ -1,9 +1,13 @@
 use chrono::{DateTime, Utc};
 
+use brokers::exchange::Exchange;
+use brokers::types::AddOrderRequest;
 use trading::position::{OperationKind, Position, PositionKind};
 use trading::stop::StopEvent;
 use trading::types::TradeKind;
 
+use crate::publish::PublishedSignal;
+
 // ------------ Behavioral Types ---------
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -42,6 +46,27 @@ pub enum StratEvent {
     OpenPosition(Position),
     ClosePosition(Position),
     PositionSummary(PositionSummary),
+    /// The size of opened positions was scaled by `multiplier` to reduce risk, or restored when it is 1
+    RiskThrottled {
+        multiplier: f64,
+    },
+    /// A risk limit of the portfolio was breached, trading was halted by the circuit breaker
+    CircuitBreakerTripped {
+        reason: String,
+    },
+    /// Trading resumed after the circuit breaker was tripped
+    CircuitBreakerReset,
+    /// An order that would have been placed in observe mode
+    ObservedOrder(AddOrderRequest),
+    /// Signals that were published instead of being converted into orders, in signal only mode
+    PublishedSignals(Vec<PublishedSignal>),
+    /// Moving `amount` of `asset` from one exchange to another would bring the inventories of a strategy back in balance
+    RebalanceSuggested {
+        asset: String,
+        from: Exchange,
+        to: Exchange,
+        amount: f64,
+    },
 }
 
 impl StratEvent {
@@ -7,7 +7,7 @@ use tokio::time::Duration;
 use brokers::prelude::*;
 use strategy::driver::{StratProviderRef, Strategy, StrategyDriver, StrategyInitContext};
 use strategy::event::trades_history;
-use strategy::prelude::{GenericDriver, GenericDriverOptions, PortfolioOptions};
+use strategy::prelude::{GenericDriver, GenericDriverOptions, PortfolioOptions, PositionMode};
 use strategy::query::{DataQuery, DataResult, PortfolioSnapshot};
 use trading::engine::mock_engine;
 use trading::position::Position;
@@ -49,9 +49,29 @@ where
         portfolio: PortfolioOptions {
             fees_rate,
             initial_quote_cash: starting_cash,
+            target_volatility: None,
+            volatility_model: None,
+            drawdown_throttle: None,
+            position_mode: PositionMode::default(),
+            simulate_funding: true,
+            simulate_marks: true,
+            risk_limits: None,
         },
         start_trading: None,
         dry_mode: None,
+        max_signal_age: None,
+        maintenance_pause: None,
+        mark_to_market_interval: None,
+        funding_poll_interval: None,
+        require_confirmation: None,
+        observe: None,
+        shadow: None,
+        position_sizer: None,
+        schedule: None,
+        quoting: None,
+        signal_only: None,
+        execution: None,
+        trailing_stop: None,
     };
     let mut driver = GenericDriver::try_new(
         <dyn Strategy>::channels(strat.as_ref()),
@@ -129,6 +149,8 @@ where
                     value: portfolio.value(),
                     pnl: portfolio.pnl(),
                     current_return: portfolio.current_return(),
+                    funding: portfolio.funding(),
+                    fees: portfolio.fees(),
                 },
                 nominal_positions,
             ));
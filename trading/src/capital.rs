@@ -0,0 +1,264 @@
+//! Capital shared by several strategies : each one is allocated a part of the free capital of the pool according
+//! to an allocation policy, so that strategies compete for capital instead of each trading a capital of its own.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+
+/// How the free capital of a pool is split between its strategies
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AllocationPolicy {
+    /// Relative weights by strategy key, strategies without a weight have a weight of 1
+    FixedWeights {
+        #[serde(default)]
+        weights: HashMap<String, f64>,
+    },
+    /// Weights inversely proportional to the volatility of the returns of each strategy, strategies with too few
+    /// returns have the mean weight of the others
+    VolatilityScaled {
+        /// Returns required before the volatility of a strategy is used
+        #[serde(default = "default_min_samples")]
+        min_samples: u64,
+    },
+}
+
+fn default_min_samples() -> u64 { 5 }
+
+impl Default for AllocationPolicy {
+    fn default() -> Self {
+        Self::FixedWeights {
+            weights: HashMap::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SharedCapitalSettings {
+    /// Initial capital of the pool, in the quote asset
+    pub capital: f64,
+    #[serde(default)]
+    pub allocation: AllocationPolicy,
+}
+
+/// Running mean and variance of the returns of a strategy
+#[derive(Debug, Default, Clone, Copy)]
+struct ReturnStats {
+    count: u64,
+    mean: f64,
+    m2: f64,
+}
+
+impl ReturnStats {
+    fn push(&mut self, value: f64) {
+        self.count += 1;
+        let delta = value - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (value - self.mean);
+    }
+
+    fn stddev(&self) -> Option<f64> { (self.count > 1).then(|| (self.m2 / (self.count - 1) as f64).sqrt()) }
+}
+
+#[derive(Debug, Default, Clone)]
+struct Allocation {
+    /// Equity of the strategy when it joined the pool
+    initial_equity: f64,
+    /// Equity of the strategy, its value with open positions at their cost
+    equity: f64,
+    /// Capital held by the open positions of the strategy
+    committed: f64,
+    returns: ReturnStats,
+}
+
+#[derive(Debug)]
+struct PoolState {
+    allocations: BTreeMap<String, Allocation>,
+    peak: f64,
+    max_drawdown: f64,
+}
+
+/// A capital pool shared by strategies, their realized gains and losses are pooled
+#[derive(Debug)]
+pub struct SharedCapital {
+    initial_capital: f64,
+    policy: AllocationPolicy,
+    state: Mutex<PoolState>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SharedCapitalSnapshot {
+    pub initial_capital: f64,
+    pub capital: f64,
+    /// Capital not held by open positions
+    pub free_capital: f64,
+    /// Maximum drawdown of the realized capital of the pool
+    pub max_drawdown: f64,
+    /// Current normalized weights by strategy key
+    pub weights: BTreeMap<String, f64>,
+    /// Realized gains and losses by strategy key
+    pub pnl: BTreeMap<String, f64>,
+}
+
+impl SharedCapital {
+    pub fn new(settings: &SharedCapitalSettings) -> Self {
+        Self {
+            initial_capital: settings.capital,
+            policy: settings.allocation.clone(),
+            state: Mutex::new(PoolState {
+                allocations: BTreeMap::default(),
+                peak: settings.capital,
+                max_drawdown: 0.0,
+            }),
+        }
+    }
+
+    /// Add a strategy to the pool, with its current equity
+    pub fn register(&self, key: &str, equity: f64) {
+        let mut state = self.state.lock().unwrap();
+        state.allocations.entry(key.to_string()).or_insert(Allocation {
+            initial_equity: equity,
+            equity,
+            ..Allocation::default()
+        });
+    }
+
+    /// Capital of the pool, the initial capital with the realized gains and losses of all strategies
+    pub fn capital(&self) -> f64 { self.capital_of(&self.state.lock().unwrap()) }
+
+    fn capital_of(&self, state: &PoolState) -> f64 {
+        self.initial_capital
+            + state
+                .allocations
+                .values()
+                .map(|a| a.equity - a.initial_equity)
+                .sum::<f64>()
+    }
+
+    /// The capital a strategy can use to open a position, its share of the free capital of the pool
+    pub fn budget(&self, key: &str) -> f64 {
+        let state = self.state.lock().unwrap();
+        let committed: f64 = state.allocations.values().map(|a| a.committed).sum();
+        let free = (self.capital_of(&state) - committed).max(0.0);
+        free * self.weights(&state).get(key).copied().unwrap_or(0.0)
+    }
+
+    /// Update the capital held by the open positions of a strategy and its equity, a change of equity is a
+    /// realized return
+    pub fn update(&self, key: &str, committed: f64, equity: f64) {
+        let mut state = self.state.lock().unwrap();
+        let Some(allocation) = state.allocations.get_mut(key) else {
+            return;
+        };
+        allocation.committed = committed.max(0.0);
+        if (equity - allocation.equity).abs() > f64::EPSILON {
+            if allocation.equity > 0.0 {
+                allocation
+                    .returns
+                    .push((equity - allocation.equity) / allocation.equity);
+            }
+            allocation.equity = equity;
+        }
+        let capital = self.capital_of(&state);
+        state.peak = state.peak.max(capital);
+        if state.peak > 0.0 {
+            state.max_drawdown = state.max_drawdown.max((state.peak - capital) / state.peak);
+        }
+    }
+
+    /// Normalized weights of the strategies of the pool
+    fn weights(&self, state: &PoolState) -> BTreeMap<String, f64> {
+        let raw: BTreeMap<String, f64> = match &self.policy {
+            AllocationPolicy::FixedWeights { weights } => state
+                .allocations
+                .keys()
+                .map(|k| (k.clone(), weights.get(k).copied().unwrap_or(1.0).max(0.0)))
+                .collect(),
+            AllocationPolicy::VolatilityScaled { min_samples } => {
+                let inverse_vols: BTreeMap<&String, f64> = state
+                    .allocations
+                    .iter()
+                    .filter(|(_, a)| a.returns.count >= *min_samples)
+                    .filter_map(|(k, a)| a.returns.stddev().filter(|s| *s > 0.0).map(|s| (k, 1.0 / s)))
+                    .collect();
+                let mean = if inverse_vols.is_empty() {
+                    1.0
+                } else {
+                    inverse_vols.values().sum::<f64>() / inverse_vols.len() as f64
+                };
+                state
+                    .allocations
+                    .keys()
+                    .map(|k| (k.clone(), inverse_vols.get(k).copied().unwrap_or(mean)))
+                    .collect()
+            }
+        };
+        let total: f64 = raw.values().sum();
+        raw.into_iter()
+            .map(|(k, w)| (k, if total > 0.0 { w / total } else { 0.0 }))
+            .collect()
+    }
+
+    pub fn snapshot(&self) -> SharedCapitalSnapshot {
+        let state = self.state.lock().unwrap();
+        let capital = self.capital_of(&state);
+        SharedCapitalSnapshot {
+            initial_capital: self.initial_capital,
+            capital,
+            free_capital: capital - state.allocations.values().map(|a| a.committed).sum::<f64>(),
+            max_drawdown: state.max_drawdown,
+            weights: self.weights(&state),
+            pnl: state
+                .allocations
+                .iter()
+                .map(|(k, a)| (k.clone(), a.equity - a.initial_equity))
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use super::{AllocationPolicy, SharedCapital, SharedCapitalSettings};
+
+    #[test]
+    fn share_free_capital() {
+        let pool = SharedCapital::new(&SharedCapitalSettings {
+            capital: 1000.0,
+            allocation: AllocationPolicy::FixedWeights {
+                weights: HashMap::from([("a".to_string(), 3.0)]),
+            },
+        });
+        pool.register("a", 1000.0);
+        pool.register("b", 1000.0);
+        assert_eq!(pool.budget("a"), 750.0);
+        assert_eq!(pool.budget("b"), 250.0);
+        // a holds 600, only 400 are left to share
+        pool.update("a", 600.0, 1000.0);
+        assert_eq!(pool.budget("b"), 100.0);
+        // a closes at a loss of 200
+        pool.update("a", 0.0, 800.0);
+        assert_eq!(pool.capital(), 800.0);
+        assert_eq!(pool.budget("b"), 200.0);
+        let snapshot = pool.snapshot();
+        assert!((snapshot.max_drawdown - 0.2).abs() < 1e-9);
+        assert_eq!(snapshot.pnl["a"], -200.0);
+        assert_eq!(pool.budget("unknown"), 0.0);
+    }
+
+    #[test]
+    fn scale_weights_to_volatility() {
+        let pool = SharedCapital::new(&SharedCapitalSettings {
+            capital: 1000.0,
+            allocation: AllocationPolicy::VolatilityScaled { min_samples: 2 },
+        });
+        pool.register("calm", 100.0);
+        pool.register("wild", 100.0);
+        for (calm, wild) in [(101.0, 110.0), (100.0, 90.0), (101.0, 110.0), (100.0, 90.0)] {
+            pool.update("calm", 0.0, calm);
+            pool.update("wild", 0.0, wild);
+        }
+        assert!(pool.budget("calm") > pool.budget("wild") * 5.0);
+    }
+}
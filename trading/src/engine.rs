@@ -9,8 +9,10 @@ use brokers::manager::BrokerageManager;
     feature = "live_e2e_tests",
     feature = "manual_e2e_tests"
 ))]
-pub use mock::mock_engine;
+pub use mock::{mock_engine, mock_engine_with_api};
 
+use crate::capital::SharedCapital;
+use crate::funding::FundingLedger;
 use crate::interest::{InterestRateProvider, MarginInterestRateProvider, MarginInterestRateProviderClient};
 use crate::order_manager::{OrderExecutor, OrderManager, OrderManagerClient};
 
@@ -19,6 +21,12 @@ pub struct TradingEngine {
     pub order_executor: Arc<dyn OrderExecutor>,
     pub interest_rate_provider: Arc<dyn InterestRateProvider>,
     pub exchange_manager: Arc<BrokerageManager>,
+    /// Capital shared by the strategies of the engine, strategies trade their own capital when unset
+    #[builder(default)]
+    pub shared_capital: Option<Arc<SharedCapital>>,
+    /// Funded contracts held by the strategies of the engine, account funding payments are split by it
+    #[builder(default)]
+    pub funding_ledger: Arc<FundingLedger>,
 }
 
 pub fn new_trading_engine(
@@ -32,6 +40,8 @@ pub fn new_trading_engine(
         order_executor: executor,
         interest_rate_provider,
         exchange_manager: manager,
+        shared_capital: None,
+        funding_ledger: Arc::default(),
     }
 }
 
@@ -44,12 +54,13 @@ pub fn new_trading_engine(
 mod mock {
     use std::path::Path;
 
+    use brokers::api::Brokerage;
     use brokers::exchange::Exchange;
-    use brokers::manager::BrokerageManager;
+    use brokers::manager::{BrokerageManager, BrokerageRegistry};
 
     use crate::interest::test_util::mock_interest_rate_provider;
     use crate::interest::MarginInterestRateProviderClient;
-    use crate::order_manager::test_util::mock_manager;
+    use crate::order_manager::test_util::{mock_manager, mock_manager_with_api};
     use crate::order_manager::OrderManagerClient;
 
     use super::*;
@@ -69,6 +80,32 @@ mod mock {
             order_executor: executor,
             interest_rate_provider,
             exchange_manager: Arc::new(manager),
+            shared_capital: None,
+            funding_ledger: Arc::default(),
         }
     }
+
+    /// A mock engine passing orders to `api`, also returns the order manager so that it can be sent account events
+    pub fn mock_engine_with_api<S: AsRef<Path>>(
+        db_path: S,
+        api: Arc<dyn Brokerage>,
+    ) -> (TradingEngine, Addr<OrderManager>) {
+        let xch = api.exchange();
+        let apis = BrokerageRegistry::new();
+        apis.insert(xch, api.clone());
+        let manager = BrokerageManager::new_with_reg(apis);
+        let order_manager_addr = mock_manager_with_api(db_path.as_ref().join("om"), api);
+        let executor = Arc::new(OrderManagerClient::new(order_manager_addr.clone()));
+        let interest_rate_provider = Arc::new(MarginInterestRateProviderClient::new(mock_interest_rate_provider(&[
+            xch,
+        ])));
+        let engine = TradingEngine {
+            order_executor: executor,
+            interest_rate_provider,
+            exchange_manager: Arc::new(manager),
+            shared_capital: None,
+            funding_ledger: Arc::default(),
+        };
+        (engine, order_manager_addr)
+    }
 }
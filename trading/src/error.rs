@@ -6,6 +6,8 @@ pub enum Error {
     InterestRateProviderMailboxError,
     #[error("Broker {0}")]
     Broker(#[from] brokers::error::Error),
+    #[error("db {0}")]
+    Db(#[from] db::Error),
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
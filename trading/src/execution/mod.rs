@@ -0,0 +1,504 @@
+//! Execution algorithms split a parent order into child orders placed over time, the fills of the child
+//! orders are aggregated into a single order detail for the parent, so that the portfolio accounts for them
+//! as one position.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use actix::{Actor, Addr, AsyncContext, Context, Handler, ResponseFuture};
+use chrono::{DateTime, Duration, Utc};
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+use brokers::types::{AddOrderRequest, MarketEvent, MarketEventEnvelope, Trade};
+
+use crate::order_manager::types::{OrderDetail, OrderStatus, Rejection, StagedOrder};
+use crate::order_manager::OrderExecutor;
+
+/// How often executions are checked for fills and due slices
+const EXECUTION_TICK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// How a parent order is split into child orders
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ExecutionAlgo {
+    /// Equal slices placed at regular intervals over `duration`, a slice still resting when the next one is due
+    /// expires and its quantity is carried over to the last slice
+    Twap {
+        slices: u32,
+        #[serde(
+            deserialize_with = "util::ser::string_duration_chrono",
+            serialize_with = "util::ser::encode_duration_str"
+        )]
+        duration: Duration,
+    },
+    /// Chunks of at most `display_qty`, the next chunk is placed once the previous one is resolved
+    Iceberg { display_qty: f64 },
+    /// Child orders sized to at most `participation` of the volume traded in the market since the start, the
+    /// remaining quantity is placed at once when `deadline` is reached
+    Vwap {
+        participation: f64,
+        #[serde(
+            deserialize_with = "util::ser::string_duration_chrono",
+            serialize_with = "util::ser::encode_duration_str"
+        )]
+        deadline: Duration,
+    },
+}
+
+/// Progress of a parent order
+#[derive(Debug, Clone)]
+pub struct Execution {
+    parent: AddOrderRequest,
+    algo: ExecutionAlgo,
+    started_at: DateTime<Utc>,
+    slices_sent: u32,
+    /// Latest detail of every child order, in the order they were placed
+    children: Vec<OrderDetail>,
+    /// Market volume traded on the pair since the start
+    observed_volume: f64,
+    failed: bool,
+}
+
+impl Execution {
+    pub fn new(parent: AddOrderRequest, algo: ExecutionAlgo, started_at: DateTime<Utc>) -> Self {
+        Self {
+            parent,
+            algo,
+            started_at,
+            slices_sent: 0,
+            children: vec![],
+            observed_volume: 0.0,
+            failed: false,
+        }
+    }
+
+    pub fn id(&self) -> &str { &self.parent.order_id }
+
+    pub fn total_qty(&self) -> f64 { self.parent.quantity.unwrap_or(0.0) }
+
+    pub fn executed_qty(&self) -> f64 { self.children.iter().map(|c| c.total_executed_qty).sum() }
+
+    /// Quantity not yet executed nor resting in a child order
+    pub fn remaining_qty(&self) -> f64 {
+        let committed: f64 = self
+            .children
+            .iter()
+            .map(|c| {
+                if c.is_resolved() {
+                    c.total_executed_qty
+                } else {
+                    c.base_qty.unwrap_or(0.0)
+                }
+            })
+            .sum();
+        (self.total_qty() - committed).max(0.0)
+    }
+
+    fn has_resting_child(&self) -> bool { self.children.iter().any(|c| !c.is_resolved()) }
+
+    /// Ids of the child orders that are not resolved yet
+    pub fn resting_children(&self) -> Vec<String> {
+        self.children
+            .iter()
+            .filter(|c| !c.is_resolved())
+            .map(|c| c.id.clone())
+            .collect()
+    }
+
+    /// The execution failed, or every slice was placed and resolved
+    pub fn is_finished(&self) -> bool {
+        if self.failed {
+            return true;
+        }
+        if self.has_resting_child() {
+            return false;
+        }
+        match self.algo {
+            ExecutionAlgo::Twap { slices, .. } => self.slices_sent >= slices || self.remaining_qty() <= f64::EPSILON,
+            ExecutionAlgo::Iceberg { .. } | ExecutionAlgo::Vwap { .. } => self.remaining_qty() <= f64::EPSILON,
+        }
+    }
+
+    /// Record the volume of a market trade, used to pace VWAP executions
+    pub fn on_trade(&mut self, trade: &Trade) {
+        if matches!(self.algo, ExecutionAlgo::Vwap { .. }) && trade.pair == self.parent.pair {
+            self.observed_volume += trade.amount;
+        }
+    }
+
+    /// Stop placing child orders
+    pub fn fail(&mut self) { self.failed = true; }
+
+    /// The next child order, if one is due at `at`
+    #[allow(clippy::cast_possible_wrap)]
+    pub fn next_slice(&mut self, at: DateTime<Utc>) -> Option<AddOrderRequest> {
+        if self.is_finished() || self.has_resting_child() {
+            return None;
+        }
+        let remaining = self.remaining_qty();
+        if remaining <= f64::EPSILON {
+            return None;
+        }
+        let (qty, max_age_ms) = match self.algo {
+            ExecutionAlgo::Twap { slices, duration } => {
+                let interval = (duration / slices.max(1) as i32).num_milliseconds().max(1);
+                let due = ((at - self.started_at).num_milliseconds() / interval + 1).min(i64::from(slices));
+                if i64::from(self.slices_sent) >= due {
+                    return None;
+                }
+                let qty = if self.slices_sent + 1 >= slices {
+                    remaining
+                } else {
+                    (self.total_qty() / f64::from(slices)).min(remaining)
+                };
+                (qty, Some(interval))
+            }
+            ExecutionAlgo::Iceberg { display_qty } => (display_qty.min(remaining), self.parent.max_age_ms),
+            ExecutionAlgo::Vwap {
+                participation,
+                deadline,
+            } => {
+                let deadline_at = self.started_at + deadline;
+                if at >= deadline_at {
+                    (remaining, self.parent.max_age_ms)
+                } else {
+                    let committed = self.total_qty() - remaining;
+                    let allowed = self.observed_volume * participation - committed;
+                    if allowed <= f64::EPSILON {
+                        return None;
+                    }
+                    // Resting children expire at the deadline so that the remainder can be placed
+                    (allowed.min(remaining), Some((deadline_at - at).num_milliseconds()))
+                }
+            }
+        };
+        self.slices_sent += 1;
+        Some(AddOrderRequest {
+            order_id: Uuid::new_v4().to_string(),
+            transaction_id: Some(self.parent.order_id.clone()),
+            quantity: Some(qty),
+            max_age_ms,
+            ..self.parent.clone()
+        })
+    }
+
+    /// Record the latest detail of a child order, a child rejected for another reason than its age stops the
+    /// execution
+    pub fn update_child(&mut self, order: OrderDetail) {
+        if order.is_rejected() && !matches!(order.rejection_reason, Some(Rejection::Expired)) {
+            self.failed = true;
+        }
+        match self.children.iter_mut().find(|c| c.id == order.id) {
+            Some(child) => *child = order,
+            None => self.children.push(order),
+        }
+    }
+
+    /// The parent order, with the fills of all child orders
+    pub fn report(&self) -> OrderDetail {
+        let mut order = OrderDetail::from_query(self.parent.clone());
+        let executed_qty = self.executed_qty();
+        order.total_executed_qty = executed_qty;
+        order.executed_qty = Some(executed_qty);
+        if executed_qty > 0.0 {
+            order.weighted_price = self
+                .children
+                .iter()
+                .map(|c| c.total_executed_qty * c.weighted_price)
+                .sum::<f64>()
+                / executed_qty;
+        }
+        order.cummulative_quote_qty = Some(order.quote_value());
+        order.fills = self.children.iter().flat_map(|c| c.fills.clone()).collect();
+        order.open_at = Some(self.started_at);
+        order.status = if executed_qty >= self.total_qty() - f64::EPSILON && executed_qty > 0.0 {
+            OrderStatus::Filled
+        } else if self.is_finished() {
+            OrderStatus::Canceled
+        } else if executed_qty > 0.0 {
+            OrderStatus::PartiallyFilled
+        } else {
+            OrderStatus::Created
+        };
+        order
+    }
+}
+
+/// Places the child orders of parent orders according to their execution algorithm
+#[derive(Debug, Clone)]
+pub struct ExecutionActor {
+    executor: Arc<dyn OrderExecutor>,
+    executions: Arc<RwLock<HashMap<String, Execution>>>,
+    /// Set while a tick runs, overlapping ticks are skipped so that a slice is never placed twice
+    ticking: Arc<AtomicBool>,
+}
+
+impl ExecutionActor {
+    pub fn new(executor: Arc<dyn OrderExecutor>) -> Self {
+        Self {
+            executor,
+            executions: Arc::new(RwLock::new(HashMap::new())),
+            ticking: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    pub fn actor(executor: Arc<dyn OrderExecutor>) -> Addr<Self> { Self::start(Self::new(executor)) }
+
+    /// Refresh the resting child orders of every execution, then place the slices that are due, the executions
+    /// are not locked while the order manager is queried
+    async fn tick(&self, at: DateTime<Utc>) {
+        if self.ticking.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        let resting: Vec<(String, Vec<String>)> = self
+            .executions
+            .read()
+            .await
+            .values()
+            .filter(|e| !e.is_finished())
+            .map(|e| (e.id().to_string(), e.resting_children()))
+            .collect();
+        let mut refreshed = vec![];
+        for (parent_id, children) in resting {
+            for child_id in children {
+                match self.executor.get_order(&child_id).await {
+                    Ok((order, _)) => refreshed.push((parent_id.clone(), order)),
+                    Err(e) => debug!(order_id = %child_id, err = %e, "failed to refresh child order"),
+                }
+            }
+        }
+        let due: Vec<(String, AddOrderRequest)> = {
+            let mut executions = self.executions.write().await;
+            for (parent_id, order) in refreshed {
+                if let Some(execution) = executions.get_mut(&parent_id) {
+                    execution.update_child(order);
+                }
+            }
+            executions
+                .values_mut()
+                .filter_map(|e| e.next_slice(at).map(|request| (e.id().to_string(), request)))
+                .collect()
+        };
+        for (parent_id, request) in due {
+            let staged = self.executor.stage_order(StagedOrder { request }).await;
+            let mut executions = self.executions.write().await;
+            let Some(execution) = executions.get_mut(&parent_id) else {
+                continue;
+            };
+            match staged {
+                Ok(order) => execution.update_child(order),
+                Err(e) => {
+                    error!(parent_id = %parent_id, err = %e, "failed to stage child order");
+                    execution.fail();
+                }
+            }
+        }
+        self.ticking.store(false, Ordering::Release);
+    }
+}
+
+impl Actor for ExecutionActor {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        ctx.run_interval(EXECUTION_TICK_INTERVAL, |_act, ctx| {
+            ctx.notify(ExecutionTick(Utc::now()));
+        });
+    }
+}
+
+/// Start executing a parent order
+#[derive(actix::Message)]
+#[rtype(result = "()")]
+pub struct Execute {
+    pub request: AddOrderRequest,
+    pub algo: ExecutionAlgo,
+}
+
+/// The aggregated fill state of a parent order, finished executions are forgotten once reported
+#[derive(actix::Message)]
+#[rtype(result = "Option<OrderDetail>")]
+pub struct ExecutionReport(pub String);
+
+/// Place the slices due at the given time
+#[derive(actix::Message)]
+#[rtype(result = "()")]
+pub struct ExecutionTick(pub DateTime<Utc>);
+
+impl Handler<Execute> for ExecutionActor {
+    type Result = ResponseFuture<()>;
+
+    fn handle(&mut self, msg: Execute, _ctx: &mut Self::Context) -> Self::Result {
+        let actor = self.clone();
+        Box::pin(async move {
+            let at = Utc::now();
+            let execution = Execution::new(msg.request, msg.algo, at);
+            actor
+                .executions
+                .write()
+                .await
+                .insert(execution.id().to_string(), execution);
+            actor.tick(at).await;
+        })
+    }
+}
+
+impl Handler<ExecutionReport> for ExecutionActor {
+    type Result = ResponseFuture<Option<OrderDetail>>;
+
+    fn handle(&mut self, msg: ExecutionReport, _ctx: &mut Self::Context) -> Self::Result {
+        let executions = self.executions.clone();
+        Box::pin(async move {
+            let mut executions = executions.write().await;
+            let execution = executions.get(&msg.0)?;
+            let report = execution.report();
+            if execution.is_finished() {
+                executions.remove(&msg.0);
+            }
+            Some(report)
+        })
+    }
+}
+
+impl Handler<Arc<MarketEventEnvelope>> for ExecutionActor {
+    type Result = ResponseFuture<<MarketEventEnvelope as actix::Message>::Result>;
+
+    fn handle(&mut self, msg: Arc<MarketEventEnvelope>, _ctx: &mut Self::Context) -> Self::Result {
+        let executions = self.executions.clone();
+        Box::pin(async move {
+            if let MarketEvent::Trade(trade) = &msg.e {
+                for execution in executions
+                    .write()
+                    .await
+                    .values_mut()
+                    .filter(|e| e.parent.xch == msg.symbol.xch && !e.is_finished())
+                {
+                    execution.on_trade(trade);
+                }
+            }
+            Ok(())
+        })
+    }
+}
+
+impl Handler<ExecutionTick> for ExecutionActor {
+    type Result = ResponseFuture<()>;
+
+    fn handle(&mut self, msg: ExecutionTick, _ctx: &mut Self::Context) -> Self::Result {
+        let actor = self.clone();
+        Box::pin(async move { actor.tick(msg.0).await })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{Duration, Utc};
+
+    use brokers::types::{AddOrderRequest, Trade, TradeType};
+
+    use crate::order_manager::types::{OrderDetail, OrderStatus};
+
+    use super::{Execution, ExecutionAlgo};
+
+    fn fill(request: &AddOrderRequest, price: f64) -> OrderDetail {
+        let mut order = OrderDetail::from_query(request.clone());
+        order.status = OrderStatus::Filled;
+        order.total_executed_qty = request.quantity.unwrap();
+        order.weighted_price = price;
+        order
+    }
+
+    fn parent() -> AddOrderRequest {
+        AddOrderRequest {
+            order_id: "parent".to_string(),
+            pair: "BTC_USDT".into(),
+            quantity: Some(3.0),
+            price: Some(100.0),
+            ..AddOrderRequest::default()
+        }
+    }
+
+    #[test]
+    fn twap_slices_over_time() {
+        let start = Utc::now();
+        let mut execution = Execution::new(
+            parent(),
+            ExecutionAlgo::Twap {
+                slices: 3,
+                duration: Duration::seconds(30),
+            },
+            start,
+        );
+        let first = execution.next_slice(start).unwrap();
+        assert_eq!(first.quantity, Some(1.0));
+        assert_eq!(first.transaction_id.as_deref(), Some("parent"));
+        execution.update_child(fill(&first, 100.0));
+        // The second slice is only due after a third of the duration
+        assert!(execution.next_slice(start + Duration::seconds(5)).is_none());
+        let second = execution.next_slice(start + Duration::seconds(10)).unwrap();
+        execution.update_child(fill(&second, 101.0));
+        let report = execution.report();
+        assert_eq!(report.status, OrderStatus::PartiallyFilled);
+        assert!((report.weighted_price - 100.5).abs() < 1e-9);
+        let third = execution.next_slice(start + Duration::seconds(20)).unwrap();
+        execution.update_child(fill(&third, 102.0));
+        assert!(execution.is_finished());
+        let report = execution.report();
+        assert_eq!(report.id, "parent");
+        assert_eq!(report.status, OrderStatus::Filled);
+        assert!((report.total_executed_qty - 3.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn iceberg_waits_for_each_chunk() {
+        let start = Utc::now();
+        let mut execution = Execution::new(parent(), ExecutionAlgo::Iceberg { display_qty: 2.0 }, start);
+        let first = execution.next_slice(start).unwrap();
+        assert_eq!(first.quantity, Some(2.0));
+        execution.update_child(OrderDetail::from_query(first.clone()));
+        // The chunk is still resting
+        assert!(execution.next_slice(start).is_none());
+        execution.update_child(fill(&first, 100.0));
+        let second = execution.next_slice(start).unwrap();
+        assert_eq!(second.quantity, Some(1.0));
+        execution.update_child(fill(&second, 100.0));
+        assert!(execution.is_finished());
+        assert_eq!(execution.report().status, OrderStatus::Filled);
+    }
+
+    #[test]
+    fn vwap_follows_market_volume() {
+        let start = Utc::now();
+        let mut execution = Execution::new(
+            parent(),
+            ExecutionAlgo::Vwap {
+                participation: 0.1,
+                deadline: Duration::minutes(1),
+            },
+            start,
+        );
+        // Nothing traded yet
+        assert!(execution.next_slice(start).is_none());
+        execution.on_trade(&Trade {
+            event_ms: start.timestamp_millis(),
+            pair: "BTC_USDT".into(),
+            amount: 10.0,
+            price: 100.0,
+            tt: TradeType::Buy,
+            aggressor: None,
+        });
+        let first = execution.next_slice(start + Duration::seconds(1)).unwrap();
+        assert!((first.quantity.unwrap() - 1.0).abs() < 1e-9);
+        assert!(first.max_age_ms.unwrap() <= Duration::minutes(1).num_milliseconds());
+        execution.update_child(fill(&first, 100.0));
+        // The participation cap is reached until more volume trades
+        assert!(execution.next_slice(start + Duration::seconds(2)).is_none());
+        let last = execution.next_slice(start + Duration::minutes(1)).unwrap();
+        assert!((last.quantity.unwrap() - 2.0).abs() < 1e-9);
+        execution.update_child(fill(&last, 100.0));
+        assert!(execution.is_finished());
+        assert_eq!(execution.report().status, OrderStatus::Filled);
+    }
+}
@@ -0,0 +1,73 @@
+//! Funding payments of perpetual contracts are reported for a whole account : strategies trading the same account
+//! split each payment by their share of the contracts held on its market, so that it is only accounted once.
+
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use brokers::exchange::Exchange;
+use brokers::types::Pair;
+
+/// Funded contracts held by the strategies of an engine, by market
+#[derive(Debug, Default)]
+pub struct FundingLedger {
+    holdings: Mutex<BTreeMap<(Exchange, Pair), BTreeMap<String, f64>>>,
+}
+
+impl FundingLedger {
+    /// Replace the funded contracts held by the strategy `key` with `held`, absolute quantities by market
+    pub fn update(&self, key: &str, held: &BTreeMap<(Exchange, Pair), f64>) {
+        let mut holdings = self.holdings.lock().unwrap();
+        for holders in holdings.values_mut() {
+            holders.remove(key);
+        }
+        for (market, qty) in held {
+            if *qty > 0.0 {
+                holdings
+                    .entry(market.clone())
+                    .or_default()
+                    .insert(key.to_string(), *qty);
+            }
+        }
+        holdings.retain(|_, holders| !holders.is_empty());
+    }
+
+    /// Share of the funding of a market owed to the strategy `key`, the whole funding if no holder is known
+    pub fn share(&self, key: &str, xch: Exchange, pair: &Pair) -> f64 {
+        let holdings = self.holdings.lock().unwrap();
+        let Some(holders) = holdings.get(&(xch, pair.clone())) else {
+            return 1.0;
+        };
+        let total: f64 = holders.values().sum();
+        match holders.get(key) {
+            Some(qty) if total > 0.0 => qty / total,
+            _ => 0.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use brokers::exchange::Exchange;
+    use brokers::types::Pair;
+
+    use crate::funding::FundingLedger;
+
+    #[test]
+    fn funding_is_split_between_holders() {
+        let ledger = FundingLedger::default();
+        let pair: Pair = "BTC_USDT".into();
+        assert_eq!(ledger.share("a", Exchange::Binance, &pair), 1.0);
+
+        ledger.update("a", &BTreeMap::from([((Exchange::Binance, pair.clone()), 1.0)]));
+        ledger.update("b", &BTreeMap::from([((Exchange::Binance, pair.clone()), 3.0)]));
+        assert_eq!(ledger.share("a", Exchange::Binance, &pair), 0.25);
+        assert_eq!(ledger.share("b", Exchange::Binance, &pair), 0.75);
+        assert_eq!(ledger.share("c", Exchange::Binance, &pair), 0.0);
+
+        // Closed positions no longer share the funding
+        ledger.update("b", &BTreeMap::new());
+        assert_eq!(ledger.share("a", Exchange::Binance, &pair), 1.0);
+    }
+}
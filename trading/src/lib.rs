@@ -31,12 +31,18 @@ extern crate strum_macros;
 extern crate tracing;
 
 pub mod book;
+pub mod capital;
 pub mod engine;
 pub mod error;
+pub mod execution;
+pub mod funding;
 pub mod interest;
 pub mod order_manager;
 pub mod position;
+pub mod quoting;
+pub mod screener;
 pub mod signal;
+pub mod sizing;
 pub mod stop;
 mod test_util;
 pub mod types;
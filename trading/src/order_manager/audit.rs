@@ -0,0 +1,306 @@
+//! Append-only audit log of order lifecycle events, kept apart from the transactions WAL.
+//! Each entry is hash-chained to the previous one so that any alteration of the log can be detected.
+
+use std::fmt::{Debug, Formatter};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Utc};
+use data_encoding::HEXLOWER;
+use sha2::{Digest, Sha256};
+
+use util::compress::Compression;
+use util::ser::{JsonSerde, NdJsonSerde, StreamSerializerWriter};
+use util::time::now;
+
+use super::types::TransactionStatus;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AuditLogConfig {
+    /// Directory in which audit logs are written, one file per order manager session
+    pub dir: PathBuf,
+    /// If set, audit logs are uploaded to this S3 bucket when closed
+    pub s3_bucket: Option<String>,
+    /// Prefix of the audit logs uploaded to `s3_bucket`
+    pub s3_prefix: Option<String>,
+}
+
+/// What caused an order lifecycle event
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, AsRefStr)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditTrigger {
+    /// A strategy staged the order
+    #[strum(serialize = "strategy")]
+    Strategy,
+    /// The exchange answered or updated the order
+    #[strum(serialize = "exchange")]
+    Exchange,
+    /// An operator acted directly on the order
+    #[strum(serialize = "operator")]
+    Operator,
+    /// The order manager canceled the order after its maximum age
+    #[strum(serialize = "expiry")]
+    Expiry,
+}
+
+impl From<&TransactionStatus> for AuditTrigger {
+    fn from(tr: &TransactionStatus) -> Self {
+        match tr {
+            TransactionStatus::Staged(_) | TransactionStatus::Amended(_) => Self::Strategy,
+            _ => Self::Exchange,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct AuditEntry {
+    /// Position of the entry in the log
+    pub seq: u64,
+    pub ts: DateTime<Utc>,
+    pub order_id: String,
+    /// Key of the strategy that emitted the order
+    pub strategy_key: Option<String>,
+    pub trigger: AuditTrigger,
+    /// The full request or response
+    pub transaction: TransactionStatus,
+    /// Hash of the previous entry, empty for the first entry
+    pub prev_hash: String,
+    /// Hash of this entry's content chained with `prev_hash`
+    pub hash: String,
+}
+
+impl AuditEntry {
+    /// # Panics
+    ///
+    /// if the transaction cannot be serialized
+    pub fn compute_hash(&self) -> String {
+        let content = serde_json::to_vec(&(
+            self.seq,
+            self.ts,
+            &self.order_id,
+            &self.strategy_key,
+            self.trigger,
+            &self.transaction,
+        ))
+        .unwrap();
+        let mut hasher = Sha256::new();
+        hasher.update(self.prev_hash.as_bytes());
+        hasher.update(&content);
+        HEXLOWER.encode(&hasher.finalize())
+    }
+}
+
+/// Check that every entry's hash matches its content and chains to the previous entry, the first entry chaining to
+/// `prev_hash`, the hash of the last entry of the previous log, empty for the first log
+///
+/// returns: the position of the first entry breaking the chain, if any
+pub fn verify_chain(entries: &[AuditEntry], prev_hash: &str) -> Option<usize> {
+    let mut prev_hash = prev_hash.to_string();
+    for (i, entry) in entries.iter().enumerate() {
+        if entry.prev_hash != prev_hash || entry.hash != entry.compute_hash() {
+            return Some(i);
+        }
+        prev_hash = entry.hash.clone();
+    }
+    None
+}
+
+pub struct AuditLog {
+    writer: Arc<StreamSerializerWriter<AuditEntry, NdJsonSerde>>,
+    /// Sequence number and hash of the last entry
+    last: Mutex<(u64, String)>,
+    s3_bucket: Option<String>,
+    s3_prefix: Option<String>,
+}
+
+impl Debug for AuditLog {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AuditLog")
+            .field("file", &self.writer.out_file)
+            .field("s3_bucket", &self.s3_bucket)
+            .field("s3_prefix", &self.s3_prefix)
+            .finish()
+    }
+}
+
+/// Sequence number and hash of the last entry of the latest audit log in `dir`, if any
+fn last_logged_entry(dir: &Path) -> Option<(u64, String)> {
+    let mut files: Vec<PathBuf> = std::fs::read_dir(dir)
+        .ok()?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .map_or(false, |name| name.starts_with("audit_") && name.ends_with(".ndjson"))
+        })
+        .collect();
+    files.sort();
+    files.iter().rev().find_map(|path| {
+        let entries: Vec<AuditEntry> = match File::open(path)
+            .map_err(|e| e.to_string())
+            .and_then(|file| NdJsonSerde::deserialize::<_, AuditEntry>(BufReader::new(file)).map_err(|e| e.to_string()))
+        {
+            Ok(entries) => entries,
+            Err(e) => {
+                error!(err = %e, file = ?path, "failed to read the previous audit log, its chain cannot be continued");
+                return None;
+            }
+        };
+        entries.last().map(|entry| (entry.seq, entry.hash.clone()))
+    })
+}
+
+impl AuditLog {
+    /// A new audit log file in the configured directory, chained to the last entry of the previous log file
+    pub fn new(config: &AuditLogConfig) -> Self {
+        let last = last_logged_entry(&config.dir).map_or((0, String::new()), |(seq, hash)| (seq + 1, hash));
+        let file = config.dir.join(format!(
+            "audit_{}_{:010}.ndjson",
+            now().format("%Y%m%d%H%M%S%3f"),
+            last.0
+        ));
+        Self {
+            writer: Arc::new(StreamSerializerWriter::new_with_compression(file, Compression::none())),
+            last: Mutex::new(last),
+            s3_bucket: config.s3_bucket.clone(),
+            s3_prefix: config.s3_prefix.clone(),
+        }
+    }
+
+    /// Write entries to the audit log file until closed
+    pub async fn run(&self) { self.writer.start().await; }
+
+    /// Append an entry chained to the previous one
+    ///
+    /// # Panics
+    ///
+    /// if the log lock is poisoned
+    pub fn append(
+        &self,
+        order_id: &str,
+        strategy_key: Option<String>,
+        trigger: AuditTrigger,
+        transaction: TransactionStatus,
+    ) -> AuditEntry {
+        let mut last = self.last.lock().unwrap();
+        let mut entry = AuditEntry {
+            seq: last.0,
+            ts: now(),
+            order_id: order_id.to_string(),
+            strategy_key,
+            trigger,
+            transaction,
+            prev_hash: last.1.clone(),
+            hash: String::new(),
+        };
+        entry.hash = entry.compute_hash();
+        *last = (last.0 + 1, entry.hash.clone());
+        if let Err(e) = self.writer.push(entry.clone()) {
+            error!(err = %e, order_id = %order_id, "failed to write audit entry");
+        }
+        entry
+    }
+
+    /// Read back all entries written so far
+    pub fn entries(&self) -> Result<Vec<AuditEntry>, serde_json::Error> { self.writer.read_all() }
+
+    /// Flush and close the log, shipping it to S3 if configured
+    pub async fn close(&self) {
+        self.writer.close().await;
+        if let Some(bucket) = self.s3_bucket.as_ref() {
+            let file = self.writer.out_file.clone();
+            let name = file.file_name().map(|f| f.to_string_lossy()).unwrap_or_default();
+            let key = match self.s3_prefix.as_ref() {
+                Some(prefix) => format!("{}/{}", prefix, name),
+                None => name.into_owned(),
+            };
+            if let Err(e) = util::s3::upload_file(&file, bucket, &key) {
+                error!(err = %e, key = %key, "failed to ship audit log to s3");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use brokers::types::{AddOrderRequest, OrderQuery};
+    use util::test::test_dir;
+
+    use super::{verify_chain, AuditLog, AuditLogConfig, AuditTrigger};
+    use crate::order_manager::types::{Rejection, TransactionStatus};
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn audit_entries_are_hash_chained() {
+        let dir = test_dir();
+        let config = AuditLogConfig {
+            dir: dir.path().to_path_buf(),
+            s3_bucket: None,
+            s3_prefix: None,
+        };
+        let log = Arc::new(AuditLog::new(&config));
+        let log_ref = log.clone();
+        tokio::spawn(async move { log_ref.run().await });
+        let request = AddOrderRequest {
+            order_id: "order".to_string(),
+            emitter_id: Some("strat".to_string()),
+            ..AddOrderRequest::default()
+        };
+        log.append(
+            "order",
+            request.emitter_id.clone(),
+            AuditTrigger::Strategy,
+            TransactionStatus::Staged(OrderQuery::AddOrder(request)),
+        );
+        log.append(
+            "order",
+            Some("strat".to_string()),
+            AuditTrigger::Exchange,
+            TransactionStatus::Rejected(Rejection::InvalidPrice),
+        );
+        log.append(
+            "order",
+            Some("strat".to_string()),
+            AuditTrigger::Operator,
+            TransactionStatus::Rejected(Rejection::Cancelled(None)),
+        );
+        // Closing drains the pushed entries before finishing the file
+        let closed = tokio::time::timeout(Duration::from_millis(200), log.close()).await;
+        assert!(closed.is_ok());
+
+        let mut entries = log.entries().unwrap();
+        assert_eq!(entries.len(), 3);
+        assert!(entries[0].prev_hash.is_empty());
+        for pair in entries.windows(2) {
+            assert_eq!(pair[1].prev_hash, pair[0].hash);
+            assert_eq!(pair[1].seq, pair[0].seq + 1);
+        }
+        assert_eq!(verify_chain(&entries, ""), None);
+
+        // The next log continues the chain of the previous file
+        let next_log = Arc::new(AuditLog::new(&config));
+        let next_log_ref = next_log.clone();
+        tokio::spawn(async move { next_log_ref.run().await });
+        next_log.append(
+            "order",
+            Some("strat".to_string()),
+            AuditTrigger::Expiry,
+            TransactionStatus::Rejected(Rejection::Cancelled(None)),
+        );
+        let closed = tokio::time::timeout(Duration::from_millis(200), next_log.close()).await;
+        assert!(closed.is_ok());
+        let next_entries = next_log.entries().unwrap();
+        assert_eq!(next_entries.len(), 1);
+        assert_eq!(next_entries[0].seq, 3);
+        assert_eq!(next_entries[0].prev_hash, entries[2].hash);
+        assert_eq!(verify_chain(&next_entries, &entries[2].hash), None);
+        assert_eq!(verify_chain(&next_entries, ""), Some(0));
+
+        entries[1].transaction = TransactionStatus::Rejected(Rejection::Timeout);
+        assert_eq!(verify_chain(&entries, ""), Some(1));
+    }
+}
@@ -8,12 +8,18 @@ pub enum Error {
     OrderManagerMailboxError,
     #[error("staged order required")]
     StagedOrderRequired,
+    #[error("order is not resting on the exchange : {0}")]
+    OrderNotResting(String),
     #[error("Db {0}")]
     Db(#[from] db::Error),
     #[error("Broker {0}")]
     Broker(#[from] brokers::error::Error),
     #[error("enum parse error : {0}")]
     EnumParseError(#[from] strum::ParseError),
+    #[error("io error : {0}")]
+    Io(#[from] std::io::Error),
+    #[error("serde error : {0}")]
+    Serde(#[from] serde_json::Error),
 }
 
 impl Error {
@@ -24,7 +30,10 @@ impl Error {
             Error::OrderNotFound(_) => "order_not_found",
             Error::OrderManagerMailboxError => "order_mailbox",
             Error::StagedOrderRequired => "staged_order_required",
+            Error::OrderNotResting(_) => "order_not_resting",
             Error::EnumParseError(_) => "enum_parse_error",
+            Error::Io(_) => "io",
+            Error::Serde(_) => "serde",
         }
     }
 }
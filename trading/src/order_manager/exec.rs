@@ -1,9 +1,12 @@
 use super::error::*;
-use crate::order_manager::types::{OrderDetail, OrderId, StagedOrder, Transaction};
+use crate::order_manager::types::{AmendOrder, CancelOrder, OrderDetail, OrderId, PassLoan, StagedOrder,
+                                  StagedOrderList, SubscribeOrders, Transaction};
 use crate::order_manager::OrderManager;
 use crate::types::TradeOperation;
 use actix::Addr;
+use brokers::types::{AddOrderRequest, AmendOrderRequest, OrderQuery};
 use std::fmt::Debug;
+use tokio::sync::broadcast;
 
 #[derive(Debug, AsRefStr, PartialEq)]
 pub enum OrderResolution {
@@ -26,6 +29,8 @@ pub enum OrderResolution {
 pub trait OrderExecutor: Send + Sync + Debug {
     /// Stage an order
     async fn stage_order(&self, staged_order: StagedOrder) -> Result<OrderDetail>;
+    /// Stage several orders to be submitted together, under a shared group id
+    async fn stage_orders(&self, orders: Vec<AddOrderRequest>) -> Result<Vec<OrderDetail>>;
     /// Retry staging an order if it was rejected
     async fn stage_trade(&self, trade: &TradeOperation) -> Result<OrderDetail>;
     /// Resolves the order against the latest known order detail and transaction,
@@ -37,6 +42,14 @@ pub trait OrderExecutor: Send + Sync + Debug {
     ) -> Result<(OrderDetail, Option<Transaction>, OrderResolution)>;
     /// Returns the latest known detail and transaction for this order id
     async fn get_order(&self, order_id: &str) -> Result<(OrderDetail, Option<Transaction>)>;
+    /// Changes the price or quantity of a resting order without cancelling it
+    async fn amend_order(&self, request: AmendOrderRequest) -> Result<OrderDetail>;
+    /// Cancels a resting order
+    async fn cancel_order(&self, order_id: &str) -> Result<()>;
+    /// Borrows or repays an asset on a margin account
+    async fn pass_loan(&self, query: OrderQuery) -> Result<()>;
+    /// Receives every order once it is updated
+    async fn subscribe_orders(&self) -> Result<broadcast::Receiver<OrderDetail>>;
 }
 
 #[derive(Debug, Clone)]
@@ -57,6 +70,15 @@ impl OrderExecutor for OrderManagerClient {
             .map_err(|_| super::Error::OrderManagerMailboxError)?
     }
 
+    async fn stage_orders(&self, orders: Vec<AddOrderRequest>) -> Result<Vec<OrderDetail>> {
+        self.om
+            .send(StagedOrderList {
+                query: OrderQuery::AddOrders(orders),
+            })
+            .await
+            .map_err(|_| Error::OrderManagerMailboxError)?
+    }
+
     async fn stage_trade(&self, trade: &TradeOperation) -> Result<OrderDetail> {
         let staged_order = StagedOrder {
             request: trade.clone().into(),
@@ -75,6 +97,34 @@ impl OrderExecutor for OrderManagerClient {
             .and_then(|(or, t)| or.map(|o| (o, t.ok())))
     }
 
+    async fn amend_order(&self, request: AmendOrderRequest) -> Result<OrderDetail> {
+        self.om
+            .send(AmendOrder { request })
+            .await
+            .map_err(|_| Error::OrderManagerMailboxError)?
+    }
+
+    async fn cancel_order(&self, order_id: &str) -> Result<()> {
+        self.om
+            .send(CancelOrder(order_id.to_string()))
+            .await
+            .map_err(|_| Error::OrderManagerMailboxError)?
+    }
+
+    async fn pass_loan(&self, query: OrderQuery) -> Result<()> {
+        self.om
+            .send(PassLoan(query))
+            .await
+            .map_err(|_| Error::OrderManagerMailboxError)?
+    }
+
+    async fn subscribe_orders(&self) -> Result<broadcast::Receiver<OrderDetail>> {
+        self.om
+            .send(SubscribeOrders)
+            .await
+            .map_err(|_| Error::OrderManagerMailboxError)
+    }
+
     async fn resolve_pending_order(
         &self,
         order: &OrderDetail,
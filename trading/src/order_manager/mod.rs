@@ -1,30 +1,38 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fmt::Debug;
 use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 
-use actix::{Actor, ActorFutureExt, Addr, AsyncContext, Context, Handler, ResponseActFuture, ResponseFuture, WrapFuture};
+use actix::{Actor, ActorFutureExt, Addr, AsyncContext, Context, Handler, MessageResult, ResponseActFuture,
+            ResponseFuture, WrapFuture};
 use actix_derive::{Message, MessageResponse};
 use backoff::{ExponentialBackoff, ExponentialBackoffBuilder};
+use chrono::{DateTime, Utc};
 use futures::FutureExt;
 use itertools::Itertools;
 use std::time::Duration;
-use tokio::sync::RwLock;
+use tokio::sync::{broadcast, RwLock};
 
 use brokers::bot::Ping;
 use brokers::error::Error as BrokerError;
 use brokers::manager::{BrokerageManager, BrokerageManagerRef};
+use brokers::pair::PairConfChanges;
 use brokers::prelude::*;
-use brokers::types::{Order, OrderQuery, OrderStatus, OrderUpdate};
+use brokers::types::{AmendOrderRequest, OcoOrderRequest, Order, OrderQuery, OrderStatus, OrderUpdate};
 use db::{get_or_create, DbOptions, Storage};
 use ext::ResultExt;
+pub use wal::WalArchiveConfig;
 use wal::{Wal, WalCmp};
 
 use crate::order_manager::repo::OrderRepository;
 
+use self::audit::{AuditLog, AuditLogConfig, AuditTrigger};
 use self::error::{Error, Result};
-use self::types::{OrderDetail, OrderId, PassOrder, Rejection, StagedOrder, Transaction, TransactionStatus};
+use self::types::{AmendOrder, CancelAllOrders, CancelOrder, OrderDetail, OrderId, PassLoan, PassOrder, Rejection,
+                  StagedOrder, StagedOrderList, SubscribeOrders, Transaction, TransactionStatus};
 
+pub mod audit;
 pub mod error;
 mod exec;
 pub use exec::*;
@@ -41,7 +49,10 @@ mod tests;
 pub mod types;
 mod wal;
 
-#[derive(Serialize, Deserialize)]
+/// How often resting orders are checked against their maximum age
+const ORDER_EXPIRY_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BackoffConfig {
     #[serde(deserialize_with = "util::ser::string_duration_opt")]
     initial_interval: Option<Duration>,
@@ -74,9 +85,15 @@ impl BackoffConfig {
     }
 }
 
-#[derive(Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct OrderManagerConfig {
     order_retry_backoff: Option<BackoffConfig>,
+    /// Where to write the audit log of order lifecycle events, disabled if unset
+    #[serde(default)]
+    pub audit_log: Option<AuditLogConfig>,
+    /// Archive older transactions out of the WAL, all transactions are kept online if unset
+    #[serde(default)]
+    pub wal_archive: Option<WalArchiveConfig>,
 }
 
 impl OrderManagerConfig {
@@ -96,27 +113,44 @@ pub enum DataQuery {
     /// All transactions history
     AllTransactions,
     OrderTransactions(String),
+    /// Transactions of an order list and of all its orders
+    OrderListTransactions(String),
 }
 
 #[derive(Debug, Clone)]
 pub struct OrderManager {
     xchg_manager: BrokerageManagerRef,
     orders: Arc<RwLock<HashMap<String, TransactionStatus>>>,
+    /// Ids of the resting orders that have a maximum age
+    expiring: Arc<RwLock<HashSet<String>>>,
     pub transactions_wal: Arc<Wal>,
     pub repo: OrderRepository,
     pub order_retry_backoff: Option<ExponentialBackoff>,
+    audit_log: Option<Arc<AuditLog>>,
+    /// Publishes every order once it is updated
+    order_updates: broadcast::Sender<OrderDetail>,
 }
 
 impl OrderManager {
     const TRANSACTIONS_TABLE: &'static str = "transactions_wal";
+    /// Order updates buffered for each subscriber, slower subscribers miss the oldest updates
+    const ORDER_UPDATES_CAPACITY: usize = 1024;
 
     pub fn new(apis: BrokerageManagerRef, storage: Arc<dyn Storage>) -> Self {
         Self::new_with_options(apis, storage, OrderManagerConfig::default())
     }
 
     pub async fn actor(db: &DbOptions<String>, exchange_manager: Arc<BrokerageManager>) -> Addr<Self> {
+        Self::actor_with_options(db, exchange_manager, OrderManagerConfig::default()).await
+    }
+
+    pub async fn actor_with_options(
+        db: &DbOptions<String>,
+        exchange_manager: Arc<BrokerageManager>,
+        config: OrderManagerConfig,
+    ) -> Addr<Self> {
         let storage = get_or_create(db, "order_manager", vec![]);
-        let order_manager = Self::new_with_options(exchange_manager, storage, OrderManagerConfig::default());
+        let order_manager = Self::new_with_options(exchange_manager, storage, config);
         Self::start(order_manager)
     }
 
@@ -125,14 +159,21 @@ impl OrderManager {
         storage: Arc<dyn Storage>,
         config: OrderManagerConfig,
     ) -> Self {
-        let wal = Arc::new(Wal::new(storage.clone(), Self::TRANSACTIONS_TABLE.to_string()));
+        let wal = Arc::new(Wal::new_with_archive(
+            storage.clone(),
+            Self::TRANSACTIONS_TABLE.to_string(),
+            config.wal_archive.clone(),
+        ));
         let orders = Arc::new(RwLock::new(HashMap::new()));
         OrderManager {
             xchg_manager: exchange_manager,
             orders,
+            expiring: Arc::new(RwLock::new(HashSet::new())),
             transactions_wal: wal,
             repo: OrderRepository::new(storage),
             order_retry_backoff: config.backoff(),
+            audit_log: config.audit_log.as_ref().map(|c| Arc::new(AuditLog::new(c))),
+            order_updates: broadcast::channel(Self::ORDER_UPDATES_CAPACITY).0,
         }
     }
 
@@ -151,7 +192,8 @@ impl OrderManager {
         } else {
             return Ok(());
         };
-        self.register(order_id, tr).await
+        self.register(order_id.clone(), tr).await?;
+        self.follow_order_list(&order_id).await
     }
 
     /// Registers an order, and passes it to be later processed
@@ -164,42 +206,414 @@ impl OrderManager {
         Ok((request, self.repo.get(&order_id)?))
     }
 
+    /// Registers every order of an order list, and the list itself as a single transaction under its list id
+    pub(crate) async fn stage_order_list(
+        &mut self,
+        staged_list: StagedOrderList,
+    ) -> Result<(OrderQuery, Vec<OrderDetail>)> {
+        staged_list.query.validate()?;
+        let query = staged_list.query.link_legs();
+        for leg in query.legs() {
+            let staged_transaction = TransactionStatus::Staged(OrderQuery::AddOrder(leg.clone()));
+            self.register(leg.order_id.clone(), staged_transaction).await?;
+        }
+        self.register(query.id(), TransactionStatus::Staged(query.clone()))
+            .await?;
+        let orders = query
+            .legs()
+            .into_iter()
+            .map(|leg| self.get_order_from_storage(&leg.order_id))
+            .collect::<Result<Vec<_>>>()?;
+        Ok((query, orders))
+    }
+
     /// Directly passes an order query
     pub(crate) async fn pass_order(&mut self, order: PassOrder) -> Result<()> {
+        // Orders canceled while staged are not passed
+        if matches!(
+            self.get_order(order.id.clone()).await,
+            Some(TransactionStatus::Rejected(_))
+        ) {
+            return Ok(());
+        }
+        match order.query {
+            OrderQuery::AddOrder(request) => {
+                let written_transaction = self.submit_order(request).await?;
+                self.register(order.id.clone(), written_transaction).await
+            }
+            OrderQuery::AddOcoOrder(oco) => self.pass_oco_order(oco).await,
+            // Only the entry is passed, the exits follow once it is filled
+            OrderQuery::AddBracketOrder(bracket) => {
+                let entry_id = bracket.entry.order_id.clone();
+                let written_transaction = self.submit_order(bracket.entry).await?;
+                self.register(entry_id.clone(), written_transaction).await?;
+                self.follow_order_list(&entry_id).await
+            }
+            OrderQuery::AmendOrder(request) => self.amend_order(request).await.map(|_| ()),
+            OrderQuery::AddOrders(requests) => self.pass_batch(requests).await,
+            OrderQuery::Borrow(_) | OrderQuery::Repay(_) => self.pass_loan(order.query).await,
+        }
+    }
+
+    /// Borrows or repays an asset on a margin account, loans are settled at once and are not registered
+    pub(crate) async fn pass_loan(&self, query: OrderQuery) -> Result<()> {
+        query.validate()?;
+        let api = self.xchg_manager.account_api(query.xch(), query.account())?;
+        let (kind, tx_id) = match &query {
+            OrderQuery::Borrow(loan) => ("borrow", api.borrow(loan.clone()).await?),
+            OrderQuery::Repay(loan) => ("repay", api.repay(loan.clone()).await?),
+            _ => return Err(brokers::error::Error::InvalidArguments.into()),
+        };
+        info!(kind = kind, order_id = %query.id(), tx_id = %tx_id, query = ?query, "margin loan passed");
+        Ok(())
+    }
+
+    /// Passes every order of a batch at once, each order is accepted or rejected on its own
+    async fn pass_batch(&mut self, requests: Vec<AddOrderRequest>) -> Result<()> {
+        let mut batch = vec![];
+        for request in requests {
+            if matches!(
+                self.get_order(request.order_id.clone()).await,
+                Some(TransactionStatus::Rejected(_))
+            ) {
+                continue;
+            }
+            // Dry mode simulates transactions as filled
+            if request.dry_run {
+                let written_transaction = self.submit_order(request.clone()).await?;
+                self.register(request.order_id.clone(), written_transaction).await?;
+                continue;
+            }
+            let validated = brokers::pair::pair_conf(&request.xch, &request.pair).and_then(|pair_conf| {
+                let request = request.truncate(&pair_conf);
+                request.validate_with_conf(&pair_conf).map(|_| request)
+            });
+            match validated {
+                Ok(request) => batch.push(request),
+                Err(e) => {
+                    self.register(request.order_id.clone(), TransactionStatus::Rejected(rejection(e)))
+                        .await?;
+                }
+            }
+        }
+        let (xch, account) = match batch.first() {
+            Some(request) => (request.xch, request.account.clone()),
+            None => return Ok(()),
+        };
+        let submissions = match self.xchg_manager.account_api(xch, account.as_deref()) {
+            Ok(api) => api.add_orders(batch.clone()).await,
+            Err(e) => Err(e),
+        };
+        let transactions: Vec<TransactionStatus> = match submissions {
+            Ok(submissions) => submissions
+                .into_iter()
+                .map(|submission| match submission {
+                    Ok(submission) => TransactionStatus::New(submission),
+                    Err(e) => TransactionStatus::Rejected(rejection(e)),
+                })
+                .collect(),
+            Err(e) => {
+                let rejection = rejection(e);
+                batch
+                    .iter()
+                    .map(|_| TransactionStatus::Rejected(rejection.clone()))
+                    .collect()
+            }
+        };
+        for (request, transaction) in batch.iter().zip(transactions) {
+            self.register(request.order_id.clone(), transaction).await?;
+        }
+        Ok(())
+    }
+
+    /// Changes the price or quantity of a resting order in place, the order is left untouched if the exchange
+    /// refuses the amendment
+    pub(crate) async fn amend_order(&mut self, request: AmendOrderRequest) -> Result<OrderDetail> {
+        let order_id = request.order_id.clone();
+        match self.get_order(order_id.clone()).await {
+            Some(TransactionStatus::New(_) | TransactionStatus::Amended(_) | TransactionStatus::PartiallyFilled(_)) => {
+            }
+            Some(_) => return Err(Error::OrderNotResting(order_id)),
+            None => return Err(Error::OrderNotFound(order_id)),
+        }
+        let order = self.get_order_from_storage(&order_id)?;
+        let pair_conf = brokers::pair::pair_conf(&request.xch, &request.pair)?;
+        let request = request.truncate(&pair_conf);
+        let query = OrderQuery::AmendOrder(request.clone());
+        query.validate_with_conf(&pair_conf)?;
+        // Dry mode orders never reach the exchange
+        if !order.is_test {
+            self.xchg_manager
+                .account_api(query.xch(), query.account())?
+                .order(query)
+                .await?;
+        }
+        self.register(order_id.clone(), TransactionStatus::Amended(request))
+            .await?;
+        self.get_order_from_storage(&order_id)
+    }
+
+    /// Cancel the resting orders that outlived their maximum age, so that strategies do not leak stale orders
+    ///
+    /// Only the orders that have a maximum age are read, orders that fail to be read or canceled are skipped
+    pub(crate) async fn expire_orders(&mut self, at: DateTime<Utc>) -> Result<()> {
+        let expiring: Vec<String> = self.expiring.read().await.iter().cloned().collect();
+        for order_id in expiring {
+            let order = match self.get_order_from_storage(&order_id) {
+                Ok(order) => order,
+                Err(e) => {
+                    warn!(order_id = %order_id, err = %e, "failed to read expiring order");
+                    self.expiring.write().await.remove(&order_id);
+                    continue;
+                }
+            };
+            if order.is_resolved() {
+                self.expiring.write().await.remove(&order_id);
+                continue;
+            }
+            if !order.is_expired(at) {
+                continue;
+            }
+            if let Err(e) = self.cancel_on_exchange(&order).await {
+                warn!(order_id = %order_id, err = %e, "failed to cancel expired order");
+                continue;
+            }
+            info!(order_id = %order_id, "canceled expired order");
+            if let Err(e) = self
+                .register_as(
+                    order_id.clone(),
+                    TransactionStatus::Rejected(Rejection::Expired),
+                    AuditTrigger::Expiry,
+                )
+                .await
+            {
+                warn!(order_id = %order_id, err = %e, "failed to register expired order");
+            }
+        }
+        Ok(())
+    }
+
+    /// Track the orders that have a maximum age while they rest, so that expiry passes only read these
+    async fn index_expiry(&self, order_id: &str, order: &OrderDetail) {
+        if order.max_age_ms.is_none() {
+            return;
+        }
+        let mut expiring = self.expiring.write().await;
+        if order.is_resolved() {
+            expiring.remove(order_id);
+        } else {
+            expiring.insert(order_id.to_string());
+        }
+    }
+
+    /// Cancel every resting order on its exchange
+    ///
+    /// returns: the number of canceled orders, orders that failed to cancel are left resting
+    pub(crate) async fn cancel_all_orders(&mut self) -> Result<usize> {
+        let mut canceled = 0;
+        for order_id in self.resting_orders().await {
+            let order = match self.get_order_from_storage(&order_id) {
+                Ok(order) => order,
+                Err(e) => {
+                    warn!(order_id = %order_id, err = %e, "failed to read order to cancel");
+                    continue;
+                }
+            };
+            if let Err(e) = self.cancel_on_exchange(&order).await {
+                warn!(order_id = %order_id, err = %e, "failed to cancel order");
+                continue;
+            }
+            self.register_as(
+                order_id,
+                TransactionStatus::Rejected(Rejection::Cancelled(Some("All orders canceled".to_string()))),
+                AuditTrigger::Operator,
+            )
+            .await?;
+            canceled += 1;
+        }
+        info!(canceled = canceled, "canceled all resting orders");
+        Ok(canceled)
+    }
+
+    /// Ids of the orders resting on exchanges, and of the orders staged to be passed
+    async fn resting_orders(&self) -> Vec<String> {
+        let reader = self.orders.read().await;
+        reader
+            .iter()
+            .filter(|(_, status)| {
+                matches!(
+                    status,
+                    TransactionStatus::Staged(_)
+                        | TransactionStatus::New(_)
+                        | TransactionStatus::Amended(_)
+                        | TransactionStatus::PartiallyFilled(_)
+                )
+            })
+            .map(|(order_id, _)| order_id.clone())
+            .collect()
+    }
+
+    /// Ids of the orders resting on pairs that were delisted or whose trading rules changed, new orders are truncated
+    /// with the refreshed rules but these orders were placed with the previous ones
+    pub(crate) async fn orders_affected_by(&self, changes: &PairConfChanges) -> Vec<String> {
+        let mut affected = vec![];
+        for order_id in self.resting_orders().await {
+            let Ok(order) = self.get_order_from_storage(&order_id) else {
+                continue;
+            };
+            if Exchange::from_str(&order.exchange).ok() == Some(changes.xchg)
+                && changes.affects(&Pair::from(order.symbol.as_str()))
+            {
+                affected.push(order_id);
+            }
+        }
+        affected
+    }
+
+    /// Cancel an order on its exchange, dry mode and staged orders never reached the exchange
+    async fn cancel_on_exchange(&self, order: &OrderDetail) -> Result<()> {
+        if order.is_test || matches!(order.status, types::OrderStatus::Staged) {
+            return Ok(());
+        }
+        let xch = Exchange::from_str(&order.exchange)?;
+        self.xchg_manager
+            .account_api(xch, order.account.as_deref())?
+            .cancel_order(order.id.clone(), order.symbol.clone().into(), order.asset_type)
+            .await?;
+        Ok(())
+    }
+
+    /// Submits a single order to the exchange
+    async fn submit_order(&self, request: AddOrderRequest) -> Result<TransactionStatus> {
         // Dry mode simulates transactions as filled
-        let written_transaction = if let PassOrder {
-            query: OrderQuery::AddOrder(request @ AddOrderRequest { dry_run: true, .. }),
-            ..
-        } = order
-        {
+        if request.dry_run {
             let fees = self
                 .xchg_manager
                 .get_fees_rate(request.xch, request.asset_type, Some(request.order_type))
                 .unwrap();
-            TransactionStatus::New(request.simulate_submission(fees))
-        } else {
-            // Here the order is truncated according to the exchange configuration
-            let pair_conf = brokers::pair::pair_conf(&order.query.xch(), &order.query.pair())?;
-            let query = order.query.truncate(&pair_conf);
-            let order_info = self.xchg_manager.expect_api(query.xch()).order(query).await;
-            match order_info {
-                Ok(o) => TransactionStatus::New(o),
-                Err(e) => TransactionStatus::Rejected(match e {
-                    BrokerError::InvalidPrice => Rejection::InvalidPrice,
-                    _ => Rejection::BadRequest(format!("{}", e)),
-                }),
-            }
+            return Ok(TransactionStatus::New(request.simulate_submission(fees)));
+        }
+        // Here the order is truncated according to the exchange configuration
+        let pair_conf = brokers::pair::pair_conf(&request.xch, &request.pair)?;
+        let query = OrderQuery::AddOrder(request).truncate(&pair_conf);
+        let order_info = match query.validate_with_conf(&pair_conf) {
+            Ok(_) => match self.xchg_manager.account_api(query.xch(), query.account()) {
+                Ok(api) => api.order(query).await,
+                Err(e) => Err(e),
+            },
+            Err(e) => Err(e),
         };
-        self.register(order.id.clone(), written_transaction.clone()).await?;
+        Ok(match order_info {
+            Ok(o) => TransactionStatus::New(o),
+            Err(e) => TransactionStatus::Rejected(rejection(e)),
+        })
+    }
+
+    /// Passes both orders of a one-cancels-other order at once
+    async fn pass_oco_order(&mut self, oco: OcoOrderRequest) -> Result<()> {
+        // Dry mode simulates the limit order as filled, which cancels the stop order
+        if oco.limit.dry_run {
+            let fees = self
+                .xchg_manager
+                .get_fees_rate(oco.limit.xch, oco.limit.asset_type, Some(oco.limit.order_type))
+                .unwrap();
+            let submission = oco.limit.simulate_submission(fees);
+            self.register(oco.limit.order_id.clone(), TransactionStatus::New(submission))
+                .await?;
+            return self
+                .register(
+                    oco.stop.order_id.clone(),
+                    TransactionStatus::Rejected(Rejection::Cancelled(Some(
+                        "Other order of the list was filled".to_string(),
+                    ))),
+                )
+                .await;
+        }
+        let pair_conf = brokers::pair::pair_conf(&oco.limit.xch, &oco.limit.pair)?;
+        let oco = oco.truncate(&pair_conf);
+        let query = OrderQuery::AddOcoOrder(oco.clone());
+        let submissions = match query.validate_with_conf(&pair_conf) {
+            Ok(_) => match self.xchg_manager.account_api(query.xch(), query.account()) {
+                Ok(api) => api.add_oco_order(oco.clone()).await,
+                Err(e) => Err(e),
+            },
+            Err(e) => Err(e),
+        };
+        match submissions {
+            Ok(submissions) => {
+                for submission in submissions {
+                    self.register(submission.client_id.clone(), TransactionStatus::New(submission))
+                        .await?;
+                }
+            }
+            Err(e) => {
+                let rejection = rejection(e);
+                for leg in [&oco.limit, &oco.stop] {
+                    self.register(leg.order_id.clone(), TransactionStatus::Rejected(rejection.clone()))
+                        .await?;
+                }
+            }
+        }
         Ok(())
     }
 
-    /// Cancel an order
-    #[allow(dead_code)]
+    /// Passes the exits of a bracket order once its entry is filled, or cancels them if the entry was rejected
+    async fn follow_order_list(&mut self, order_id: &str) -> Result<()> {
+        let list_id = match self
+            .get_order_from_storage(order_id)
+            .ok()
+            .and_then(|o| o.transaction_id)
+        {
+            Some(list_id) => list_id,
+            None => return Ok(()),
+        };
+        let bracket = match self.get_order(list_id).await {
+            Some(TransactionStatus::Staged(OrderQuery::AddBracketOrder(bracket)))
+                if bracket.entry.order_id == order_id =>
+            {
+                bracket
+            }
+            _ => return Ok(()),
+        };
+        // The exits are only followed up once
+        if !matches!(
+            self.get_order(bracket.take_profit.order_id.clone()).await,
+            Some(TransactionStatus::Staged(_))
+        ) {
+            return Ok(());
+        }
+        match self.get_order(order_id.to_string()).await {
+            Some(status) if status.is_filled() => self.pass_oco_order(bracket.exits()).await,
+            Some(TransactionStatus::Rejected(_)) => {
+                for leg in [&bracket.take_profit, &bracket.stop_loss] {
+                    self.register(
+                        leg.order_id.clone(),
+                        TransactionStatus::Rejected(Rejection::Cancelled(Some(
+                            "Entry order of the list was rejected".to_string(),
+                        ))),
+                    )
+                    .await?;
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Cancel a resting order on its exchange
     pub(crate) async fn cancel_order(&mut self, order_id: String) -> Result<()> {
-        self.register(
+        match self.get_order(order_id.clone()).await {
+            Some(TransactionStatus::New(_) | TransactionStatus::Amended(_) | TransactionStatus::PartiallyFilled(_)) => {
+            }
+            Some(_) => return Err(Error::OrderNotResting(order_id)),
+            None => return Err(Error::OrderNotFound(order_id)),
+        }
+        let order = self.get_order_from_storage(&order_id)?;
+        self.cancel_on_exchange(&order).await?;
+        self.register_as(
             order_id,
             TransactionStatus::Rejected(Rejection::Cancelled(Some("Order canceled directly".to_string()))),
+            AuditTrigger::Strategy,
         )
         .await
     }
@@ -220,45 +634,80 @@ impl OrderManager {
         &self,
         order_id: String,
         xch: Exchange,
+        account: Option<&str>,
         pair: Pair,
         asset_type: AssetType,
     ) -> Result<Order> {
         Ok(self
             .xchg_manager
-            .expect_api(xch)
+            .account_api(xch, account)?
             .get_order(order_id, pair, asset_type)
             .await?)
     }
 
     /// Registers a transaction
-    #[tracing::instrument(skip(self), level = "debug")]
     pub(crate) async fn register(&mut self, order_id: String, tr: TransactionStatus) -> Result<()> {
+        let trigger = AuditTrigger::from(&tr);
+        self.register_as(order_id, tr, trigger).await
+    }
+
+    /// Registers a transaction, recording what triggered it in the audit log
+    #[tracing::instrument(skip(self), level = "debug")]
+    pub(crate) async fn register_as(
+        &mut self,
+        order_id: String,
+        tr: TransactionStatus,
+        trigger: AuditTrigger,
+    ) -> Result<()> {
         self.transactions_wal.append(order_id.as_str(), tr.clone())?;
         let should_write = {
             let orders = self.orders.read().await;
             orders.get(&order_id).map_or(true, |status| status.is_before(&tr))
         };
         let order = self.get_order_from_storage(&order_id);
-        let result = match (tr.clone(), order) {
+        if let Some(audit_log) = self.audit_log.as_ref() {
+            let strategy_key = match &tr {
+                TransactionStatus::Staged(query) => query.legs().first().and_then(|r| r.emitter_id.clone()),
+                _ => order.as_ref().ok().and_then(|o| o.emitter_id.clone()),
+            };
+            audit_log.append(&order_id, strategy_key, trigger, tr.clone());
+        }
+        let updated = match (tr.clone(), order) {
             (TransactionStatus::Staged(OrderQuery::AddOrder(add_order)), _) => {
-                self.repo.put(OrderDetail::from_query(add_order))
+                Ok(Some(OrderDetail::from_query(add_order)))
             }
+            // Order lists have no detail of their own, only their orders do
+            (TransactionStatus::Staged(_), _) => Ok(None),
             (TransactionStatus::New(submission), Ok(mut order)) => {
                 order.from_submission(submission);
-                self.repo.put(order)
+                Ok(Some(order))
             }
             (TransactionStatus::Filled(update) | TransactionStatus::PartiallyFilled(update), Ok(mut order)) => {
                 order.from_fill_update(update);
-                self.repo.put(order)
+                Ok(Some(order))
             }
             (TransactionStatus::Rejected(rejection), Ok(mut order)) => {
                 order.from_rejected(rejection);
-                self.repo.put(order)
+                Ok(Some(order))
+            }
+            (TransactionStatus::Amended(amendment), Ok(mut order)) => {
+                order.from_amendment(amendment);
+                Ok(Some(order))
             }
             _ => Err(Error::OrderNotFound(order_id.clone())),
         };
-        if let Err(e) = result {
-            tracing::error!(order_id = %order_id, error = %e, "Failed to update order in order table");
+        match updated.and_then(|order| {
+            order
+                .map(|order| self.repo.put(order.clone()).map(|_| order))
+                .transpose()
+        }) {
+            Ok(Some(order)) => {
+                self.index_expiry(&order_id, &order).await;
+                // Sending only fails without subscribers
+                drop(self.order_updates.send(order));
+            }
+            Ok(None) => {}
+            Err(e) => tracing::error!(order_id = %order_id, error = %e, "Failed to update order in order table"),
         }
         if should_write {
             let mut writer = self.orders.write().await;
@@ -298,18 +747,82 @@ impl OrderManager {
         }
     }
 
+    /// The transactions of an order list followed by the ones of its orders, in chronological order
+    pub(crate) fn order_list_transactions(&self, list_id: &str) -> Result<Vec<Transaction>> {
+        let list_transactions: Vec<(i64, TransactionStatus)> = self.transactions_wal.get_all_k(list_id)?;
+        let order_ids: Vec<String> = list_transactions
+            .iter()
+            .find_map(|(_, tr)| match tr {
+                TransactionStatus::Staged(query) if query.is_order_list() => {
+                    Some(query.legs().into_iter().map(|leg| leg.order_id.clone()).collect())
+                }
+                _ => None,
+            })
+            .ok_or_else(|| Error::OrderNotFound(list_id.to_string()))?;
+        let mut transactions = vec![];
+        for id in std::iter::once(list_id.to_string()).chain(order_ids) {
+            let order_transactions: Vec<(i64, TransactionStatus)> = self.transactions_wal.get_all_k(&id)?;
+            transactions.extend(order_transactions.into_iter().map(|(ts, status)| Transaction {
+                id: id.clone(),
+                status,
+                ts: Some(ts),
+            }));
+        }
+        transactions.sort_by_key(|tr| tr.ts);
+        Ok(transactions)
+    }
+
     pub fn transactions_wal(&self) -> Arc<Wal> { self.transactions_wal.clone() }
 
+    /// Rebuilds the order detail by replaying the transactions of this order, and stores it
+    pub(crate) fn rebuild_order(&self, order_id: &str) -> Result<OrderDetail> {
+        let mut transactions: Vec<(i64, TransactionStatus)> = self.transactions_wal.get_all_k(order_id)?;
+        if !transactions
+            .iter()
+            .any(|(_, tr)| matches!(tr, TransactionStatus::Staged(_)))
+        {
+            // The staged request may have been archived along with an older segment
+            transactions = self.transactions_wal.get_all_k_with_archive(order_id)?;
+        }
+        let (mut iter, iter2) = transactions.into_iter().map(|t| t.1).tee();
+        let staged_order_predicate = |ts: &TransactionStatus| matches!(ts, TransactionStatus::Staged(_));
+        let staged_tr = iter.find(staged_order_predicate);
+        let other_trs = iter2.filter(|ts| !staged_order_predicate(ts));
+        if let Some(TransactionStatus::Staged(OrderQuery::AddOrder(request))) = staged_tr {
+            let mut od = OrderDetail::from_query(request);
+            for tr in other_trs {
+                od.from_status(tr);
+            }
+            self.repo.put(od.clone())?;
+            Ok(od)
+        } else {
+            Err(Error::StagedOrderRequired)
+        }
+    }
+
     /// Checks that any transactions have corresponding order detail,
     /// and refresh any unfinished order from remote
     ///
     pub async fn repair_orders(&self) -> Vec<AccountEventEnveloppe> {
         {
             let mut writer = self.orders.write().await;
-            if let Ok(wal_transactions) = self.transactions_wal.get_all_compacted() {
+            if let Ok(wal_transactions) = self.transactions_wal.iter_compacted::<TransactionStatus>() {
                 writer.extend(wal_transactions);
             }
         }
+        let resting: Vec<String> = self
+            .orders
+            .read()
+            .await
+            .iter()
+            .filter(|(_, status)| status.is_incomplete())
+            .map(|(order_id, _)| order_id.clone())
+            .collect();
+        for order_id in resting {
+            if let Ok(order) = self.repo.get(&order_id) {
+                self.index_expiry(&order_id, &order).await;
+            }
+        }
         let orders_read_lock = self.orders.read().await;
         // Fetch all latest orders
         info!("fetching remote orders for all unfilled transactions");
@@ -319,31 +832,20 @@ impl OrderManager {
                 |(tr_id, tr_status)| {
                     let pair = tr_status.get_pair(Exchange::Binance);
                     info!(order_id = ?tr_id.clone(), pair = ?pair, "fetching remote for unresolved order");
-                    let order = self.repo.get(tr_id).or_else(|_| {
-                        // If not found, try to rebuild the order detail from the transactions
-                        let transactions: Vec<(i64, TransactionStatus)> = self.transactions_wal.get_all_k(tr_id)?;
-                        let (mut iter, iter2) = transactions.into_iter().map(|t| t.1).tee();
-                        let staged_order_predicate =
-                            |ts: &TransactionStatus| matches!(ts, TransactionStatus::Staged(_));
-                        let staged_tr = iter.find(staged_order_predicate);
-                        let other_trs = iter2.filter(|ts| !staged_order_predicate(ts));
-                        if let Some(TransactionStatus::Staged(OrderQuery::AddOrder(request))) = staged_tr {
-                            let mut od = OrderDetail::from_query(request);
-                            for tr in other_trs {
-                                od.from_status(tr);
-                            }
-                            self.repo.put(od.clone())?;
-                            Ok(od)
-                        } else {
-                            Err(Error::StagedOrderRequired)
-                        }
-                    });
+                    // If not found, try to rebuild the order detail from the transactions
+                    let order = self.repo.get(tr_id).or_else(|_| self.rebuild_order(tr_id));
                     order
                         .and_then(|o| {
                             pair.and_then(|pair| {
-                                Ok(self
-                                    .fetch_order(tr_id.clone(), Exchange::from_str(&o.exchange)?, pair, o.asset_type)
-                                    .boxed())
+                                let xch = Exchange::from_str(&o.exchange)?;
+                                let account = o.account.clone();
+                                let fut = async move {
+                                    let order = self
+                                        .fetch_order(tr_id.clone(), xch, account.as_deref(), pair, o.asset_type)
+                                        .await?;
+                                    Ok::<_, Error>((account, order))
+                                };
+                                Ok(fut.boxed())
                             })
                         })
                         .unwrap_or_else(|e| {
@@ -356,7 +858,7 @@ impl OrderManager {
         let mut notifications = vec![];
         for order in non_filled_order_futs {
             match order {
-                Ok(order) => {
+                Ok((account, order)) => {
                     let account_type = if order.asset_type.is_margin() {
                         AccountType::Margin
                     } else {
@@ -368,6 +870,7 @@ impl OrderManager {
                                 xchg: order.xch,
                                 event: AccountEvent::OrderUpdate(order.into()),
                                 account_type,
+                                account,
                             });
                         }
                     } else {
@@ -375,6 +878,7 @@ impl OrderManager {
                             xchg: order.xch,
                             event: AccountEvent::OrderUpdate(order.into()),
                             account_type,
+                            account,
                         });
                     }
                 }
@@ -385,6 +889,14 @@ impl OrderManager {
     }
 }
 
+fn rejection(e: BrokerError) -> Rejection {
+    match e {
+        BrokerError::InvalidPrice => Rejection::InvalidPrice,
+        BrokerError::ExchangeMaintenance => Rejection::Maintenance,
+        _ => Rejection::BadRequest(format!("{}", e)),
+    }
+}
+
 #[allow(clippy::unnested_or_patterns)]
 fn equivalent_status(trs: &TransactionStatus, os: &OrderStatus) -> bool {
     matches!(
@@ -395,6 +907,7 @@ fn equivalent_status(trs: &TransactionStatus, os: &OrderStatus) -> bool {
             | (TransactionStatus::Rejected(_), OrderStatus::Canceled)
             | (TransactionStatus::Rejected(_), OrderStatus::Expired)
             | (TransactionStatus::Staged(_), OrderStatus::New)
+            | (TransactionStatus::Amended(_), OrderStatus::New)
             | (TransactionStatus::PartiallyFilled(_), OrderStatus::PartiallyFilled)
     )
 }
@@ -414,9 +927,52 @@ impl Actor for OrderManager {
                     }
                 });
         ctx.spawn(Box::pin(refresh_orders));
+        if let Some(segment_duration) = self.transactions_wal.segment_duration() {
+            // Rotations write and upload archives, so they run on the blocking pool, one at a time
+            let rotating = Arc::new(AtomicBool::new(false));
+            ctx.run_interval(segment_duration, move |act, _ctx| {
+                if rotating.swap(true, Ordering::SeqCst) {
+                    return;
+                }
+                let wal = act.transactions_wal.clone();
+                let rotating = rotating.clone();
+                actix::spawn(async move {
+                    let rotation =
+                        tokio::task::spawn_blocking(move || wal.rotate(Utc::now(), TransactionStatus::is_incomplete))
+                            .await;
+                    match rotation {
+                        Ok(Err(e)) => error!(err = %e, "failed to rotate transactions wal"),
+                        Err(e) => error!(err = %e, "transactions wal rotation panicked"),
+                        Ok(Ok(_)) => {}
+                    }
+                    rotating.store(false, Ordering::SeqCst);
+                });
+            });
+        }
+        // Passes canceling on exchanges can outlast the interval, they run one at a time
+        let expiring = Arc::new(AtomicBool::new(false));
+        ctx.run_interval(ORDER_EXPIRY_INTERVAL, move |act, _ctx| {
+            if expiring.swap(true, Ordering::SeqCst) {
+                return;
+            }
+            let mut manager = act.clone();
+            let expiring = expiring.clone();
+            actix::spawn(async move {
+                if let Err(e) = manager.expire_orders(Utc::now()).await {
+                    error!(err = %e, "failed to expire orders");
+                }
+                expiring.store(false, Ordering::SeqCst);
+            });
+        });
+        if let Some(audit_log) = self.audit_log.clone() {
+            actix::spawn(async move { audit_log.run().await });
+        }
     }
 
     fn stopped(&mut self, _ctx: &mut Self::Context) {
+        if let Some(audit_log) = self.audit_log.clone() {
+            actix::spawn(async move { audit_log.close().await });
+        }
         info!("order manager actor stopped...");
     }
 }
@@ -457,6 +1013,54 @@ impl Handler<StagedOrder> for OrderManager {
     }
 }
 
+impl Handler<StagedOrderList> for OrderManager {
+    type Result = ResponseActFuture<Self, Result<Vec<OrderDetail>>>;
+
+    fn handle(&mut self, order_list: StagedOrderList, _ctx: &mut Self::Context) -> Self::Result {
+        let mut zis = self.clone();
+        Box::pin(
+            async move { zis.stage_order_list(order_list).await }
+                .into_actor(self)
+                .map(|tr, _act, ctx| {
+                    if let Ok((query, _)) = &tr {
+                        ctx.notify(PassOrder {
+                            id: query.id(),
+                            query: query.clone(),
+                        });
+                    }
+                    tr.map(|r| r.1)
+                }),
+        )
+    }
+}
+
+impl Handler<AmendOrder> for OrderManager {
+    type Result = ResponseActFuture<Self, Result<OrderDetail>>;
+
+    fn handle(&mut self, msg: AmendOrder, _ctx: &mut Self::Context) -> Self::Result {
+        let mut zis = self.clone();
+        Box::pin(async move { zis.amend_order(msg.request).await }.into_actor(self))
+    }
+}
+
+impl Handler<CancelOrder> for OrderManager {
+    type Result = ResponseActFuture<Self, Result<()>>;
+
+    fn handle(&mut self, msg: CancelOrder, _ctx: &mut Self::Context) -> Self::Result {
+        let mut zis = self.clone();
+        Box::pin(async move { zis.cancel_order(msg.0).await }.into_actor(self))
+    }
+}
+
+impl Handler<PassLoan> for OrderManager {
+    type Result = ResponseActFuture<Self, Result<()>>;
+
+    fn handle(&mut self, msg: PassLoan, _ctx: &mut Self::Context) -> Self::Result {
+        let zis = self.clone();
+        Box::pin(async move { zis.pass_loan(msg.0).await }.into_actor(self))
+    }
+}
+
 impl Handler<PassOrder> for OrderManager {
     type Result = ResponseActFuture<Self, Result<()>>;
 
@@ -484,6 +1088,15 @@ impl Handler<PassOrder> for OrderManager {
     }
 }
 
+impl Handler<CancelAllOrders> for OrderManager {
+    type Result = ResponseActFuture<Self, Result<usize>>;
+
+    fn handle(&mut self, _msg: CancelAllOrders, _ctx: &mut Self::Context) -> Self::Result {
+        let mut zis = self.clone();
+        Box::pin(async move { zis.cancel_all_orders().await }.into_actor(self))
+    }
+}
+
 impl Handler<OrderId> for OrderManager {
     type Result = ResponseFuture<(Result<OrderDetail>, Result<Transaction>)>;
 
@@ -513,11 +1126,40 @@ impl Handler<DataQuery> for OrderManager {
         match query {
             DataQuery::AllTransactions => self.transactions(None),
             DataQuery::OrderTransactions(id) => self.transactions(Some(id)),
+            DataQuery::OrderListTransactions(id) => self.order_list_transactions(&id),
         }
         .map(|r| Some(DataResult::Transactions(r)))
     }
 }
 
+impl Handler<PairConfChanges> for OrderManager {
+    type Result = ResponseFuture<()>;
+
+    fn handle(&mut self, changes: PairConfChanges, _ctx: &mut Self::Context) -> Self::Result {
+        let zis = self.clone();
+        Box::pin(async move {
+            info!(
+                xchg = %changes.xchg,
+                listed = changes.listed.len(),
+                delisted = changes.delisted.len(),
+                updated = changes.updated.len(),
+                "pair configurations changed"
+            );
+            for order_id in zis.orders_affected_by(&changes).await {
+                warn!(xchg = %changes.xchg, order_id = %order_id, "resting order on a pair that was delisted or whose trading rules changed");
+            }
+        })
+    }
+}
+
+impl Handler<SubscribeOrders> for OrderManager {
+    type Result = MessageResult<SubscribeOrders>;
+
+    fn handle(&mut self, _msg: SubscribeOrders, _ctx: &mut Self::Context) -> Self::Result {
+        MessageResult(self.order_updates.subscribe())
+    }
+}
+
 impl Handler<Ping> for OrderManager {
     type Result = ();
 
@@ -17,10 +17,18 @@ impl OrderRepository {
         Self { db }
     }
 
-    pub(crate) fn get(&self, id: &str) -> Result<OrderDetail> { self.db.get(ORDERS_TABLE, id).err_into() }
+    /// Stored orders are migrated to the current format as they are read
+    pub(crate) fn get(&self, id: &str) -> Result<OrderDetail> {
+        self.db.get(ORDERS_TABLE, id).map(OrderDetail::migrate).err_into()
+    }
 
     #[allow(dead_code)]
-    pub(crate) fn all(&self) -> Result<Vec<(Box<[u8]>, OrderDetail)>> { self.db.get_all(ORDERS_TABLE).err_into() }
+    pub(crate) fn all(&self) -> Result<Vec<(Box<[u8]>, OrderDetail)>> {
+        self.db
+            .get_all(ORDERS_TABLE)
+            .map(|orders: Vec<(Box<[u8]>, OrderDetail)>| orders.into_iter().map(|(k, o)| (k, o.migrate())).collect())
+            .err_into()
+    }
 
     #[tracing::instrument(skip(self), level = "info")]
     pub(crate) fn put(&self, order: OrderDetail) -> Result<()> {
@@ -12,11 +12,11 @@ use brokers::api::MockBrokerage;
 use brokers::manager::{BrokerageManager, BrokerageManagerRef, BrokerageRegistry};
 use brokers::pair::register_pair_default;
 use brokers::prelude::*;
-use brokers::types::{OrderEnforcement, OrderType, TradeType};
+use brokers::types::{OrderEnforcement, OrderQuery, OrderType, TradeType};
 
 use db::{get_or_create, DbOptions};
 
-use crate::order_manager::types::OrderDetail;
+use crate::order_manager::types::{OrderDetail, OrderStatus, TransactionStatus};
 use crate::order_manager::{OrderManager, OrderManagerClient};
 
 /// # Panics
@@ -163,7 +163,11 @@ pub fn create_ok_margin_order_mock(server: &MockServer, order: OrderDetail) -> M
 }
 
 pub fn new_mock_manager<S: AsRef<Path>>(path: S) -> OrderManager {
-    let api: Arc<dyn Brokerage> = Arc::new(MockBrokerage::default());
+    new_mock_manager_with_api(path, Arc::new(MockBrokerage::default()))
+}
+
+/// An order manager passing orders to `api`, with the fees of its exchange
+pub fn new_mock_manager_with_api<S: AsRef<Path>>(path: S, api: Arc<dyn Brokerage>) -> OrderManager {
     let apis = BrokerageRegistry::new();
     let xch = api.exchange();
     apis.insert(xch, api);
@@ -174,7 +178,11 @@ pub fn new_mock_manager<S: AsRef<Path>>(path: S) -> OrderManager {
 }
 
 pub fn mock_manager<S: AsRef<Path>>(path: S) -> Addr<OrderManager> {
-    let order_manager = new_mock_manager(path);
+    mock_manager_with_api(path, Arc::new(MockBrokerage::default()))
+}
+
+pub fn mock_manager_with_api<S: AsRef<Path>>(path: S, api: Arc<dyn Brokerage>) -> Addr<OrderManager> {
+    let order_manager = new_mock_manager_with_api(path, api);
     let act = OrderManager::start(order_manager);
     loop {
         if act.connected() {
@@ -188,3 +196,89 @@ pub fn mock_manager_client<S: AsRef<Path>>(path: S) -> OrderManagerClient {
     let om = mock_manager(path);
     OrderManagerClient::new(om)
 }
+
+/// A sequence of transactions for a single order, as written to the transactions WAL
+#[derive(Debug, Clone)]
+pub struct RecoveryScenario {
+    pub request: AddOrderRequest,
+    /// Transactions following the staged request, in the order they were received
+    pub transactions: Vec<TransactionStatus>,
+}
+
+/// State of an order manager rebuilt from the transactions WAL
+#[derive(Debug)]
+pub struct RecoveredOrder {
+    /// Compacted transaction status for the order
+    pub status: Option<TransactionStatus>,
+    /// Order detail replayed from all transactions
+    pub detail: OrderDetail,
+    /// Order updates emitted while repairing unfinished orders
+    pub notifications: Vec<AccountEventEnveloppe>,
+}
+
+/// Persists the scenario to the WAL of an order manager at `path`, then reconstructs a new manager
+/// from the same storage the way it happens at startup
+///
+/// # Panics
+///
+/// if the transactions cannot be written or the order cannot be rebuilt
+#[allow(clippy::cast_possible_wrap)]
+pub async fn replay_recovery<S: AsRef<Path>>(path: S, scenario: &RecoveryScenario) -> RecoveredOrder {
+    let order_id = scenario.request.order_id.clone();
+    {
+        let order_manager = new_mock_manager(path.as_ref());
+        let wal = order_manager.transactions_wal();
+        let ts = Utc::now().timestamp_nanos();
+        let staged = TransactionStatus::Staged(OrderQuery::AddOrder(scenario.request.clone()));
+        for (i, tr) in std::iter::once(&staged).chain(scenario.transactions.iter()).enumerate() {
+            wal.append_raw(&order_id, ts + i as i64, tr).unwrap();
+        }
+    }
+    let order_manager = new_mock_manager(path.as_ref());
+    let notifications = order_manager.repair_orders().await;
+    let status = order_manager.get_order(order_id.clone()).await;
+    let detail = order_manager
+        .get_order_from_storage(&order_id)
+        .or_else(|_| order_manager.rebuild_order(&order_id))
+        .unwrap();
+    RecoveredOrder {
+        status,
+        detail,
+        notifications,
+    }
+}
+
+/// Replays the scenario and asserts the recovered order reached the expected terminal state
+///
+/// # Panics
+///
+/// if the recovered state differs from the expected state
+pub async fn assert_recovers<S: AsRef<Path>>(
+    path: S,
+    scenario: &RecoveryScenario,
+    expected_status: &TransactionStatus,
+    expected_order_status: OrderStatus,
+    expected_executed_qty: f64,
+) -> RecoveredOrder {
+    let recovered = replay_recovery(path, scenario).await;
+    assert_eq!(
+        recovered.status.as_ref(),
+        Some(expected_status),
+        "compacted transaction status"
+    );
+    assert_eq!(recovered.detail.status, expected_order_status, "{:?}", recovered.detail);
+    assert!(
+        (recovered.detail.total_executed_qty - expected_executed_qty).abs() < f64::EPSILON,
+        "expected executed qty {}, got {:?}",
+        expected_executed_qty,
+        recovered.detail
+    );
+    let fills_qty: f64 = recovered.detail.fills.iter().map(|f| f.qty).sum();
+    assert!(
+        (fills_qty - expected_executed_qty).abs() < f64::EPSILON,
+        "fills should add up to the executed qty {}, got {:?}",
+        expected_executed_qty,
+        recovered.detail.fills
+    );
+    recovered
+}
@@ -4,16 +4,18 @@ use std::time::Duration;
 use uuid::Uuid;
 
 use super::error::*;
-use super::test_util::{create_ok_margin_order_mock, create_ok_order_mock};
+use super::test_util::{assert_recovers, create_ok_margin_order_mock, create_ok_order_mock, RecoveryScenario};
 use crate::order_manager::test_util::{it_order_manager, new_mock_manager};
 use crate::order_manager::types::OrderId;
 use crate::order_manager::OrderManager;
 use broker_test_util::binance::{account_ws as binance_account_ws, local_api};
+use brokers::pair::{register_pair_default, PairConf, PairConfChanges};
 use brokers::prelude::*;
-use brokers::types::{MarginSideEffect, OrderSubmission, OrderUpdate};
+use brokers::types::{AmendOrderRequest, BracketOrderRequest, MarginLoanRequest, MarginSideEffect,
+                     OrderStatus as BrokerOrderStatus, OrderSubmission, OrderUpdate};
 use util::test::test_dir;
 
-use super::types::{OrderDetail, OrderStatus, Rejection, StagedOrder, TransactionStatus};
+use super::types::{OrderDetail, OrderStatus, PassOrder, Rejection, StagedOrder, StagedOrderList, TransactionStatus};
 
 #[actix::test]
 async fn test_append_rejected() {
@@ -28,8 +30,439 @@ async fn test_append_rejected() {
     assert!(registered.is_ok(), "{:?}", registered);
 }
 
+#[actix::test]
+async fn test_dry_bracket_order() {
+    let test_dir = test_dir();
+    let mut order_manager = new_mock_manager(test_dir);
+    let leg = AddOrderRequest {
+        pair: test_pair().into(),
+        quantity: Some(1.0),
+        dry_run: true,
+        ..AddOrderRequest::default()
+    };
+    let bracket = BracketOrderRequest {
+        list_id: "list".to_string(),
+        entry: AddOrderRequest {
+            order_id: "entry".to_string(),
+            side: TradeType::Buy,
+            order_type: OrderType::Market,
+            price: Some(100.0),
+            ..leg.clone()
+        },
+        take_profit: AddOrderRequest {
+            order_id: "take_profit".to_string(),
+            side: TradeType::Sell,
+            order_type: OrderType::Limit,
+            price: Some(110.0),
+            ..leg.clone()
+        },
+        stop_loss: AddOrderRequest {
+            order_id: "stop_loss".to_string(),
+            side: TradeType::Sell,
+            order_type: OrderType::StopLossLimit,
+            price: Some(90.0),
+            stop_price: Some(90.0),
+            ..leg
+        },
+    };
+    let (query, orders) = order_manager
+        .stage_order_list(StagedOrderList {
+            query: OrderQuery::AddBracketOrder(bracket),
+        })
+        .await
+        .unwrap();
+    assert_eq!(orders.len(), 3);
+    assert!(orders.iter().all(|o| o.transaction_id.as_deref() == Some("list")));
+    order_manager
+        .pass_order(PassOrder { id: query.id(), query })
+        .await
+        .unwrap();
+    // The entry fills, which passes the exits, and the take profit fills, which cancels the stop loss
+    assert!(order_manager.get_order("entry".to_string()).await.unwrap().is_filled());
+    assert!(order_manager
+        .get_order("take_profit".to_string())
+        .await
+        .unwrap()
+        .is_filled());
+    assert!(matches!(
+        order_manager.get_order("stop_loss".to_string()).await,
+        Some(TransactionStatus::Rejected(Rejection::Cancelled(_)))
+    ));
+    let transactions = order_manager.order_list_transactions("list").unwrap();
+    assert_eq!(transactions.len(), 7);
+}
+
 fn test_keys() -> String { "../config/keys_real_test.json".to_string() }
 
+#[actix::test]
+async fn test_expire_resting_order() {
+    let test_dir = test_dir();
+    let mut order_manager = new_mock_manager(test_dir);
+    let order_id = "expiring".to_string();
+    let request = AddOrderRequest {
+        pair: test_pair().into(),
+        order_id: order_id.clone(),
+        order_type: OrderType::Limit,
+        price: Some(100.0),
+        quantity: Some(1.0),
+        max_age_ms: Some(1000),
+        ..AddOrderRequest::default()
+    };
+    order_manager
+        .register(
+            order_id.clone(),
+            TransactionStatus::Staged(OrderQuery::AddOrder(request)),
+        )
+        .await
+        .unwrap();
+    let now = chrono::Utc::now();
+    order_manager
+        .register(
+            order_id.clone(),
+            TransactionStatus::New(OrderSubmission {
+                pair: test_pair().into(),
+                client_id: order_id.clone(),
+                price: 100.0,
+                qty: 1.0,
+                timestamp: now.timestamp_millis(),
+                ..OrderSubmission::default()
+            }),
+        )
+        .await
+        .unwrap();
+    order_manager.expire_orders(now).await.unwrap();
+    assert!(matches!(
+        order_manager.get_order(order_id.clone()).await,
+        Some(TransactionStatus::New(_))
+    ));
+    // Orders that cannot be read are skipped without aborting the pass
+    order_manager.expiring.write().await.insert("unreadable".to_string());
+    order_manager
+        .expire_orders(now + chrono::Duration::seconds(2))
+        .await
+        .unwrap();
+    assert_eq!(
+        order_manager.get_order(order_id.clone()).await,
+        Some(TransactionStatus::Rejected(Rejection::Expired))
+    );
+    let order = order_manager.get_order_from_storage(&order_id).unwrap();
+    assert!(order.is_rejected());
+    assert!(order_manager.expiring.read().await.is_empty());
+}
+
+#[actix::test]
+async fn test_cancel_all_orders() {
+    let test_dir = test_dir();
+    let mut order_manager = new_mock_manager(test_dir);
+    for (order_id, price) in [("resting", 100.0), ("other_resting", 101.0)] {
+        let request = AddOrderRequest {
+            pair: test_pair().into(),
+            order_id: order_id.to_string(),
+            order_type: OrderType::Limit,
+            price: Some(price),
+            quantity: Some(1.0),
+            ..AddOrderRequest::default()
+        };
+        order_manager
+            .register(
+                order_id.to_string(),
+                TransactionStatus::Staged(OrderQuery::AddOrder(request)),
+            )
+            .await
+            .unwrap();
+        order_manager
+            .register(
+                order_id.to_string(),
+                TransactionStatus::New(OrderSubmission {
+                    pair: test_pair().into(),
+                    client_id: order_id.to_string(),
+                    price,
+                    qty: 1.0,
+                    ..OrderSubmission::default()
+                }),
+            )
+            .await
+            .unwrap();
+    }
+    let staged = AddOrderRequest {
+        pair: test_pair().into(),
+        order_id: "staged".to_string(),
+        order_type: OrderType::Limit,
+        price: Some(99.0),
+        quantity: Some(1.0),
+        ..AddOrderRequest::default()
+    };
+    order_manager
+        .register(
+            staged.order_id.clone(),
+            TransactionStatus::Staged(OrderQuery::AddOrder(staged.clone())),
+        )
+        .await
+        .unwrap();
+    assert_eq!(order_manager.cancel_all_orders().await.unwrap(), 3);
+    for order_id in ["resting", "staged"] {
+        assert!(matches!(
+            order_manager.get_order(order_id.to_string()).await,
+            Some(TransactionStatus::Rejected(Rejection::Cancelled(_)))
+        ));
+    }
+    // Staged orders are no longer passed once canceled
+    order_manager
+        .pass_order(PassOrder {
+            id: staged.order_id.clone(),
+            query: OrderQuery::AddOrder(staged),
+        })
+        .await
+        .unwrap();
+    assert!(matches!(
+        order_manager.get_order("staged".to_string()).await,
+        Some(TransactionStatus::Rejected(Rejection::Cancelled(_)))
+    ));
+    // Nothing is left to cancel
+    assert_eq!(order_manager.cancel_all_orders().await.unwrap(), 0);
+}
+
+#[actix::test]
+async fn test_orders_affected_by_pair_changes() {
+    let test_dir = test_dir();
+    let mut order_manager = new_mock_manager(test_dir);
+    let request = AddOrderRequest {
+        pair: test_pair().into(),
+        order_id: "resting".to_string(),
+        order_type: OrderType::Limit,
+        price: Some(100.0),
+        quantity: Some(1.0),
+        ..AddOrderRequest::default()
+    };
+    let xchg = request.xch;
+    order_manager
+        .register(
+            "resting".to_string(),
+            TransactionStatus::Staged(OrderQuery::AddOrder(request)),
+        )
+        .await
+        .unwrap();
+    order_manager
+        .register(
+            "resting".to_string(),
+            TransactionStatus::New(OrderSubmission {
+                pair: test_pair().into(),
+                client_id: "resting".to_string(),
+                price: 100.0,
+                qty: 1.0,
+                ..OrderSubmission::default()
+            }),
+        )
+        .await
+        .unwrap();
+    let mut changes = PairConfChanges::new(xchg);
+    changes.listed.push(PairConf {
+        pair: test_pair().into(),
+        ..PairConf::default()
+    });
+    // New listings do not affect resting orders
+    assert!(order_manager.orders_affected_by(&changes).await.is_empty());
+    changes.updated = changes.listed.clone();
+    assert_eq!(order_manager.orders_affected_by(&changes).await, vec![
+        "resting".to_string()
+    ]);
+    changes.xchg = Exchange::Kraken;
+    assert!(order_manager.orders_affected_by(&changes).await.is_empty());
+}
+
+#[actix::test]
+async fn test_pass_loan() {
+    let test_dir = test_dir();
+    let mut order_manager = new_mock_manager(test_dir);
+    let loan = MarginLoanRequest {
+        xch: Exchange::Binance,
+        asset: "BTC".into(),
+        amount: 0.1,
+        order_id: "short".to_string(),
+        ..MarginLoanRequest::default()
+    };
+    order_manager
+        .pass_order(PassOrder {
+            id: loan.order_id.clone(),
+            query: OrderQuery::Borrow(loan.clone()),
+        })
+        .await
+        .unwrap();
+    order_manager.pass_loan(OrderQuery::Repay(loan.clone())).await.unwrap();
+    // Loans are settled at once and are not tracked as orders
+    assert!(order_manager.get_order(loan.order_id.clone()).await.is_none());
+    let empty_loan = MarginLoanRequest {
+        amount: 0.0,
+        ..loan.clone()
+    };
+    assert!(order_manager.pass_loan(OrderQuery::Borrow(empty_loan)).await.is_err());
+    let unknown_account = MarginLoanRequest {
+        account: Some("unknown".to_string()),
+        ..loan
+    };
+    assert!(matches!(
+        order_manager.pass_loan(OrderQuery::Borrow(unknown_account)).await,
+        Err(Error::Broker(brokers::error::Error::AccountNotFound(
+            Exchange::Binance,
+            _
+        )))
+    ));
+}
+
+#[actix::test]
+async fn test_orders_of_unknown_accounts_are_rejected() {
+    let test_dir = test_dir();
+    let mut order_manager = new_mock_manager(test_dir);
+    register_pair_default(Exchange::Binance, "BTCUSDT", "BTC_USDT");
+    let request = AddOrderRequest {
+        order_id: "unknown_account".to_string(),
+        pair: test_pair().into(),
+        order_type: OrderType::Market,
+        quantity: Some(1.0),
+        account: Some("unknown".to_string()),
+        ..AddOrderRequest::default()
+    };
+    order_manager
+        .pass_order(PassOrder {
+            id: request.order_id.clone(),
+            query: OrderQuery::AddOrder(request.clone()),
+        })
+        .await
+        .unwrap();
+    assert!(matches!(
+        order_manager.get_order(request.order_id).await,
+        Some(TransactionStatus::Rejected(_))
+    ));
+}
+
+#[actix::test]
+async fn test_amend_resting_order() {
+    let test_dir = test_dir();
+    let mut order_manager = new_mock_manager(test_dir);
+    register_pair_default(Exchange::Binance, "BTCUSDT", "BTC_USDT");
+    let order_id = "resting".to_string();
+    let request = AddOrderRequest {
+        pair: test_pair().into(),
+        order_id: order_id.clone(),
+        order_type: OrderType::Limit,
+        price: Some(100.0),
+        quantity: Some(1.0),
+        ..AddOrderRequest::default()
+    };
+    order_manager
+        .register(
+            order_id.clone(),
+            TransactionStatus::Staged(OrderQuery::AddOrder(request)),
+        )
+        .await
+        .unwrap();
+    let amendment = AmendOrderRequest {
+        xch: Exchange::Binance,
+        pair: test_pair().into(),
+        order_id: order_id.clone(),
+        price: Some(101.0),
+        ..AmendOrderRequest::default()
+    };
+    // Only resting orders can be amended
+    assert!(matches!(
+        order_manager.amend_order(amendment.clone()).await,
+        Err(Error::OrderNotResting(_))
+    ));
+    order_manager
+        .register(
+            order_id.clone(),
+            TransactionStatus::New(OrderSubmission {
+                pair: test_pair().into(),
+                client_id: order_id.clone(),
+                price: 100.0,
+                qty: 1.0,
+                ..OrderSubmission::default()
+            }),
+        )
+        .await
+        .unwrap();
+    let order = order_manager.amend_order(amendment).await.unwrap();
+    assert_eq!(order.price, Some(101.0));
+    assert_eq!(order.base_qty, Some(1.0));
+    assert!(matches!(
+        order_manager.get_order(order_id).await,
+        Some(TransactionStatus::Amended(_))
+    ));
+}
+
+#[actix::test]
+async fn test_cancel_only_resting_orders() {
+    let test_dir = test_dir();
+    let mut order_manager = new_mock_manager(test_dir);
+    assert!(matches!(
+        order_manager.cancel_order("unknown".to_string()).await,
+        Err(Error::OrderNotFound(_))
+    ));
+    let order_id = "rejected".to_string();
+    order_manager
+        .register(
+            order_id.clone(),
+            TransactionStatus::Rejected(Rejection::BadRequest("bad request".to_string())),
+        )
+        .await
+        .unwrap();
+    assert!(matches!(
+        order_manager.cancel_order(order_id).await,
+        Err(Error::OrderNotResting(_))
+    ));
+}
+
+#[actix::test]
+async fn test_batch_orders() {
+    let test_dir = test_dir();
+    let mut order_manager = new_mock_manager(test_dir);
+    register_pair_default(Exchange::Binance, "BTCUSDT", "BTC_USDT");
+    register_pair_default(Exchange::Binance, "ETHUSDT", "ETH_USDT");
+    let leg = AddOrderRequest {
+        order_type: OrderType::Market,
+        quantity: Some(1.0),
+        ..AddOrderRequest::default()
+    };
+    let orders = vec![
+        AddOrderRequest {
+            order_id: "long".to_string(),
+            pair: test_pair().into(),
+            side: TradeType::Buy,
+            ..leg.clone()
+        },
+        AddOrderRequest {
+            order_id: "short".to_string(),
+            pair: "ETH_USDT".into(),
+            side: TradeType::Sell,
+            ..leg
+        },
+    ];
+    let (query, details) = order_manager
+        .stage_order_list(StagedOrderList {
+            query: OrderQuery::AddOrders(orders),
+        })
+        .await
+        .unwrap();
+    let group_id = query.id();
+    assert!(details.iter().all(|o| o.transaction_id.as_ref() == Some(&group_id)));
+    order_manager
+        .pass_order(PassOrder {
+            id: group_id.clone(),
+            query,
+        })
+        .await
+        .unwrap();
+    for order_id in ["long", "short"] {
+        assert!(matches!(
+            order_manager.get_order(order_id.to_string()).await,
+            Some(TransactionStatus::New(_))
+        ));
+    }
+    // The group and both of its orders are staged, then both orders are submitted
+    let transactions = order_manager.order_list_transactions(&group_id).unwrap();
+    assert_eq!(transactions.len(), 5);
+}
+
 fn test_pair() -> String { "BTC_USDT".to_string() }
 
 #[actix::test]
@@ -376,3 +809,107 @@ async fn test_live() -> Result<()> {
     eprintln!("order_detail = {:?}", order_detail);
     Ok(())
 }
+
+fn recovery_request(order_id: &str) -> AddOrderRequest {
+    register_pair_default(Exchange::Binance, "BTCUSDT", "BTC_USDT");
+    AddOrderRequest {
+        pair: "BTC_USDT".into(),
+        order_id: order_id.to_string(),
+        price: Some(100.0),
+        quantity: Some(1.0),
+        side: TradeType::Buy,
+        order_type: OrderType::Limit,
+        ..AddOrderRequest::default()
+    }
+}
+
+fn fill_update(order_id: &str, status: BrokerOrderStatus, qty: f64, cumulative_qty: f64) -> OrderUpdate {
+    OrderUpdate {
+        orig_order_id: Some(order_id.to_string()),
+        symbol: "BTCUSDT".to_string(),
+        new_status: status,
+        last_executed_qty: qty,
+        last_executed_price: 100.0,
+        cummulative_filled_qty: cumulative_qty,
+        cummulative_quote_asset_transacted_qty: cumulative_qty * 100.0,
+        commission: qty * 0.1,
+        commission_asset: Some("USDT".to_string()),
+        ..OrderUpdate::default()
+    }
+}
+
+#[actix::test]
+async fn test_recover_partial_fills() {
+    let order_id = Uuid::new_v4().to_string();
+    let filled = TransactionStatus::Filled(fill_update(&order_id, BrokerOrderStatus::Filled, 0.5, 1.0));
+    let scenario = RecoveryScenario {
+        request: recovery_request(&order_id),
+        transactions: vec![
+            TransactionStatus::New(OrderSubmission {
+                id: "remote".to_string(),
+                pair: "BTC_USDT".into(),
+                status: BrokerOrderStatus::New,
+                ..OrderSubmission::default()
+            }),
+            TransactionStatus::PartiallyFilled(fill_update(&order_id, BrokerOrderStatus::PartiallyFilled, 0.2, 0.2)),
+            TransactionStatus::PartiallyFilled(fill_update(&order_id, BrokerOrderStatus::PartiallyFilled, 0.3, 0.5)),
+            filled.clone(),
+        ],
+    };
+    let recovered = assert_recovers(test_dir(), &scenario, &filled, OrderStatus::Filled, 1.0).await;
+    assert_eq!(recovered.detail.fills.len(), 3);
+    assert!(recovered.notifications.is_empty());
+}
+
+#[actix::test]
+async fn test_recover_unfinished_partial_fill() {
+    let order_id = Uuid::new_v4().to_string();
+    let partially_filled =
+        TransactionStatus::PartiallyFilled(fill_update(&order_id, BrokerOrderStatus::PartiallyFilled, 0.4, 0.4));
+    let scenario = RecoveryScenario {
+        request: recovery_request(&order_id),
+        transactions: vec![partially_filled.clone()],
+    };
+    let recovered = assert_recovers(
+        test_dir(),
+        &scenario,
+        &partially_filled,
+        OrderStatus::PartiallyFilled,
+        0.4,
+    )
+    .await;
+    // The unfinished order is refreshed from the exchange
+    assert_eq!(recovered.notifications.len(), 1);
+}
+
+#[actix::test]
+async fn test_recover_rejection_after_fill() {
+    let order_id = Uuid::new_v4().to_string();
+    let rejected = TransactionStatus::Rejected(Rejection::Cancelled(Some("canceled after fill".to_string())));
+    let scenario = RecoveryScenario {
+        request: recovery_request(&order_id),
+        transactions: vec![
+            TransactionStatus::PartiallyFilled(fill_update(&order_id, BrokerOrderStatus::PartiallyFilled, 0.5, 0.5)),
+            rejected.clone(),
+        ],
+    };
+    let recovered = assert_recovers(test_dir(), &scenario, &rejected, OrderStatus::Rejected, 0.5).await;
+    assert_eq!(
+        recovered.detail.rejection_reason,
+        Some(Rejection::Cancelled(Some("canceled after fill".to_string())))
+    );
+}
+
+#[actix::test]
+async fn test_recover_duplicate_fills() {
+    let order_id = Uuid::new_v4().to_string();
+    let partial =
+        TransactionStatus::PartiallyFilled(fill_update(&order_id, BrokerOrderStatus::PartiallyFilled, 0.5, 0.5));
+    let filled = TransactionStatus::Filled(fill_update(&order_id, BrokerOrderStatus::Filled, 0.5, 1.0));
+    let scenario = RecoveryScenario {
+        request: recovery_request(&order_id),
+        transactions: vec![partial.clone(), partial, filled.clone(), filled.clone()],
+    };
+    let recovered = assert_recovers(test_dir(), &scenario, &filled, OrderStatus::Filled, 1.0).await;
+    assert_eq!(recovered.detail.fills.len(), 2);
+}
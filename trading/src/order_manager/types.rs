@@ -7,9 +7,10 @@ use serde::{Deserialize, Serialize};
 
 use brokers::exchange::Exchange;
 use brokers::pair::symbol_to_pair;
-use brokers::types::{AddOrderRequest, AssetType, InterestRate, MarginSideEffect, OrderEnforcement, OrderQuery,
-                     OrderStatus as BrokerOrderStatus, OrderSubmission, OrderType, OrderUpdate, Pair, TradeType};
-use util::time::now;
+use brokers::types::{AddOrderRequest, AmendOrderRequest, AssetType, InterestRate, MarginSideEffect, OrderEnforcement,
+                     OrderQuery, OrderStatus as BrokerOrderStatus, OrderSubmission, OrderType, OrderUpdate, Pair,
+                     PositionSide, TradeFill, TradeType};
+use util::time::{now, utc_zero};
 
 use super::error::*;
 use super::wal::WalCmp;
@@ -24,6 +25,10 @@ pub enum Rejection {
     Other(String),
     Unknown(String),
     InvalidPrice,
+    /// The exchange was under maintenance
+    Maintenance,
+    /// The order rested longer than its maximum age and was canceled
+    Expired,
 }
 
 impl Rejection {
@@ -47,6 +52,9 @@ pub enum TransactionStatus {
     Staged(OrderQuery),
     #[display(fmt = "new")]
     New(OrderSubmission),
+    /// The price or quantity of the resting order was changed in place
+    #[display(fmt = "amended")]
+    Amended(AmendOrderRequest),
     #[display(fmt = "filled")]
     Filled(OrderUpdate),
     #[display(fmt = "partially_filled")]
@@ -56,8 +64,23 @@ pub enum TransactionStatus {
 }
 
 impl TransactionStatus {
+    /// Order lists are staged once, their orders carry the remaining transactions
     pub(crate) fn is_incomplete(&self) -> bool {
-        matches!(self, Self::PartiallyFilled(_) | Self::Staged(_) | Self::New(_))
+        match self {
+            Self::Staged(query) => !query.is_order_list(),
+            _ => matches!(self, Self::PartiallyFilled(_) | Self::New(_) | Self::Amended(_)),
+        }
+    }
+
+    pub(crate) fn is_filled(&self) -> bool {
+        matches!(
+            self,
+            Self::Filled(_)
+                | Self::New(OrderSubmission {
+                    status: BrokerOrderStatus::Filled,
+                    ..
+                })
+        )
     }
 
     pub(crate) fn get_pair(&self, xchg: Exchange) -> Result<Pair> {
@@ -66,30 +89,30 @@ impl TransactionStatus {
                 Ok(symbol_to_pair(&xchg, &ou.symbol.clone().into())?)
             }
             TransactionStatus::New(os) => Ok(os.pair.clone()),
-            TransactionStatus::Staged(OrderQuery::AddOrder(ao)) => Ok(ao.pair.clone()),
+            TransactionStatus::Staged(query) => Ok(query.pair()),
+            TransactionStatus::Amended(amendment) => Ok(amendment.pair.clone()),
             _ => Err(brokers::error::Error::PairUnsupported.into()),
         }
     }
-}
 
-impl WalCmp for TransactionStatus {
-    fn is_before(&self, v: &Self) -> bool {
-        if std::mem::discriminant(self) == std::mem::discriminant(v) {
-            return false;
-        }
+    /// Progress of the order in its lifecycle, amendments rank below fills so that an amendment of a partially
+    /// filled order never replaces its fill state
+    fn rank(&self) -> u8 {
         match self {
-            Self::Staged(_) => matches!(
-                v,
-                Self::New(_) | Self::PartiallyFilled(_) | Self::Rejected(_) | Self::Filled(_)
-            ),
-            Self::New(_) => matches!(v, Self::PartiallyFilled(_) | Self::Rejected(_) | Self::Filled(_)),
-            Self::PartiallyFilled(_) => matches!(v, Self::Rejected(_) | Self::Filled(_)),
-            Self::Filled(_) => matches!(v, Self::Rejected(_)),
-            Self::Rejected(_) => false,
+            Self::Staged(_) => 0,
+            Self::New(_) => 1,
+            Self::Amended(_) => 2,
+            Self::PartiallyFilled(_) => 3,
+            Self::Filled(_) => 4,
+            Self::Rejected(_) => 5,
         }
     }
 }
 
+impl WalCmp for TransactionStatus {
+    fn is_before(&self, v: &Self) -> bool { self.rank() < v.rank() }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct Transaction {
     pub id: String,
@@ -98,16 +121,7 @@ pub struct Transaction {
 }
 
 impl Transaction {
-    pub fn is_filled(&self) -> bool {
-        matches!(
-            self.status,
-            TransactionStatus::Filled(_)
-                | TransactionStatus::New(OrderSubmission {
-                    status: BrokerOrderStatus::Filled,
-                    ..
-                })
-        )
-    }
+    pub fn is_filled(&self) -> bool { self.status.is_filled() }
 
     pub fn is_bad_request(&self) -> bool {
         matches!(self.status, TransactionStatus::Rejected(Rejection::BadRequest(_)))
@@ -128,6 +142,25 @@ pub struct StagedOrder {
     pub request: AddOrderRequest,
 }
 
+/// Stages every order of an order list, the list is then passed as a single query
+#[derive(Message, Debug)]
+#[rtype(result = "Result<Vec<OrderDetail>>")]
+pub struct StagedOrderList {
+    pub query: OrderQuery,
+}
+
+/// Changes the price or quantity of a resting order in place
+#[derive(Message, Debug)]
+#[rtype(result = "Result<OrderDetail>")]
+pub struct AmendOrder {
+    pub request: AmendOrderRequest,
+}
+
+/// Cancels a resting order
+#[derive(Message, Debug)]
+#[rtype(result = "Result<()>")]
+pub struct CancelOrder(pub String);
+
 #[derive(Message, Debug)]
 #[rtype(result = "Result<()>")]
 pub struct PassOrder {
@@ -139,6 +172,21 @@ pub struct PassOrder {
 #[rtype(result = "(Result<OrderDetail>, Result<Transaction>)")]
 pub struct OrderId(pub String);
 
+/// Subscribes to the orders updated by the order manager
+#[derive(Message, Debug)]
+#[rtype(result = "tokio::sync::broadcast::Receiver<OrderDetail>")]
+pub struct SubscribeOrders;
+
+/// Cancels every resting order, returns how many were canceled
+#[derive(Message, Debug)]
+#[rtype(result = "Result<usize>")]
+pub struct CancelAllOrders;
+
+/// Borrows or repays an asset on a margin account, the query must be a loan
+#[derive(Message, Debug, Clone)]
+#[rtype(result = "Result<()>")]
+pub struct PassLoan(pub OrderQuery);
+
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 #[serde(rename_all = "snake_case")]
 pub enum OrderStatus {
@@ -162,8 +210,16 @@ impl From<BrokerOrderStatus> for OrderStatus {
     }
 }
 
+/// Current version of the stored [`OrderDetail`] format, records without a version predate it
+pub const ORDER_DETAIL_VERSION: u32 = 1;
+
+/// Fields missing from older stored records take their value from [`OrderDetail::default`]
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
 pub struct OrderDetail {
+    /// Version of the stored format, upgraded by [`OrderDetail::migrate`]
+    #[serde(default)]
+    pub version: u32,
     /// Order id
     pub id: String,
     /// Optional transaction id, if this order was part of a larger transaction
@@ -201,6 +257,15 @@ pub struct OrderDetail {
     pub updated_at: DateTime<Utc>,
     pub closed_at: Option<DateTime<Utc>>,
     pub open_at: Option<DateTime<Utc>>,
+    /// Named account the order was placed with, the main account if not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub account: Option<String>,
+    /// Position the order applies to for futures accounts in hedge mode, the net position if not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub position_side: Option<PositionSide>,
+    /// Maximum time in ms the order can rest before it is canceled
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_age_ms: Option<i64>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
@@ -212,13 +277,107 @@ pub struct OrderFill {
     pub ts: DateTime<Utc>,
 }
 
+impl Default for OrderDetail {
+    fn default() -> Self {
+        Self {
+            version: ORDER_DETAIL_VERSION,
+            id: String::new(),
+            transaction_id: None,
+            emitter_id: None,
+            remote_id: None,
+            status: OrderStatus::Staged,
+            exchange: String::new(),
+            symbol: String::new(),
+            base_asset: String::new(),
+            quote_asset: String::new(),
+            side: TradeType::default(),
+            order_type: OrderType::default(),
+            enforcement: None,
+            base_qty: None,
+            quote_qty: None,
+            price: None,
+            stop_price: None,
+            iceberg_qty: None,
+            is_test: false,
+            asset_type: AssetType::default(),
+            executed_qty: None,
+            cummulative_quote_qty: None,
+            margin_side_effect: None,
+            borrowed_amount: None,
+            borrowed_asset: None,
+            fills: vec![],
+            weighted_price: 0.0,
+            total_executed_qty: 0.0,
+            rejection_reason: None,
+            created_at: utc_zero(),
+            updated_at: utc_zero(),
+            closed_at: None,
+            open_at: None,
+            account: None,
+            position_side: None,
+            max_age_ms: None,
+        }
+    }
+}
+
 impl OrderDetail {
+    /// Upgrade a record deserialized from an older format to [`ORDER_DETAIL_VERSION`]
+    #[must_use]
+    pub fn migrate(mut self) -> Self {
+        if self.version < 1 {
+            // Unversioned records did not track the executed quantity and weighted price of fills
+            if self.total_executed_qty == 0.0 {
+                self.total_executed_qty = self.fills.iter().map(|f| f.qty).sum();
+            }
+            if self.weighted_price == 0.0 {
+                self.update_weighted_price();
+            }
+            if self.updated_at == utc_zero() {
+                self.updated_at = self.created_at;
+            }
+        }
+        self.version = ORDER_DETAIL_VERSION;
+        self
+    }
+
     pub fn is_same_status(&self, os: &OrderStatus) -> bool {
         std::mem::discriminant(&self.status) == std::mem::discriminant(os)
     }
 
     pub fn is_filled(&self) -> bool { matches!(self.status, OrderStatus::Filled) }
 
+    /// The order is resting and has outlived its maximum age at `at`
+    pub fn is_expired(&self, at: DateTime<Utc>) -> bool {
+        if self.is_resolved() {
+            return false;
+        }
+        self.max_age_ms.map_or(false, |max_age_ms| {
+            (at - self.open_at.unwrap_or(self.created_at)).num_milliseconds() > max_age_ms
+        })
+    }
+
+    pub fn is_partially_filled(&self) -> bool { matches!(self.status, OrderStatus::PartiallyFilled) }
+
+    /// Share of the base quantity executed so far, between 0 and 1
+    pub fn fill_ratio(&self) -> f64 {
+        if self.is_filled() {
+            return 1.0;
+        }
+        match self.base_qty {
+            Some(qty) if qty > 0.0 => (self.total_executed_qty / qty).min(1.0),
+            _ => 0.0,
+        }
+    }
+
+    /// Base quantity left to execute, zero once the order is resolved
+    pub fn remaining_qty(&self) -> f64 {
+        if self.is_resolved() {
+            return 0.0;
+        }
+        self.base_qty
+            .map_or(0.0, |qty| (qty - self.total_executed_qty).max(0.0))
+    }
+
     pub fn is_bad_request(&self) -> bool {
         self.is_rejected() && matches!(self.rejection_reason, Some(Rejection::BadRequest(_)))
     }
@@ -236,6 +395,10 @@ impl OrderDetail {
         self.is_rejected() && matches!(self.rejection_reason, Some(Rejection::Cancelled(_)))
     }
 
+    pub fn is_maintenance(&self) -> bool {
+        self.is_rejected() && matches!(self.rejection_reason, Some(Rejection::Maintenance))
+    }
+
     pub fn is_resolved(&self) -> bool {
         matches!(
             self.status,
@@ -249,6 +412,7 @@ impl OrderDetail {
         let base_asset = base_asset.to_string();
         let quote_asset = quote_asset.to_string();
         Self {
+            version: ORDER_DETAIL_VERSION,
             id: add_order.order_id,
             transaction_id: add_order.transaction_id,
             emitter_id: add_order.emitter_id,
@@ -259,7 +423,7 @@ impl OrderDetail {
             base_asset,
             quote_asset,
             side: add_order.side,
-            order_type: add_order.order_type,
+            order_type: add_order.effective_order_type(),
             enforcement: add_order.enforcement,
             base_qty: add_order.quantity,
             quote_qty: add_order.quote_order_qty,
@@ -281,6 +445,9 @@ impl OrderDetail {
             updated_at: Utc::now(),
             closed_at: None,
             open_at: None,
+            account: add_order.account,
+            position_side: add_order.position_side,
+            max_age_ms: add_order.max_age_ms,
         }
     }
 
@@ -313,11 +480,26 @@ impl OrderDetail {
         }
     }
 
+    pub fn from_amendment(&mut self, amendment: AmendOrderRequest) {
+        if let Some(price) = amendment.price {
+            self.price = Some(price);
+        }
+        if let Some(qty) = amendment.quantity {
+            self.base_qty = Some(qty);
+        }
+        self.updated_at = Utc::now();
+    }
+
     #[allow(clippy::cast_possible_wrap)]
     pub fn from_fill_update(&mut self, update: OrderUpdate) {
         if self.status == OrderStatus::Filled {
             return;
         }
+        // Duplicate updates, e.g. replayed from the transaction log, must not add fills twice
+        let new_status: OrderStatus = update.new_status.into();
+        if self.status == new_status && update.cummulative_filled_qty <= self.total_executed_qty {
+            return;
+        }
         let time = Utc.timestamp_millis_opt(update.timestamp as i64).unwrap();
         let fill = OrderFill {
             price: update.last_executed_price,
@@ -329,7 +511,7 @@ impl OrderDetail {
         self.fills.push(fill);
         self.cummulative_quote_qty = Some(update.cummulative_quote_asset_transacted_qty);
         self.total_executed_qty = update.cummulative_filled_qty;
-        self.status = update.new_status.into();
+        self.status = new_status;
         if self.status == OrderStatus::Filled {
             self.closed_at = Some(time);
         }
@@ -343,6 +525,43 @@ impl OrderDetail {
         self.updated_at = Utc::now();
     }
 
+    /// Replace fills with the account trades reported by the broker for this order, so that fees
+    /// are the commissions actually paid instead of estimates.
+    /// Fills are kept as is unless the reported trades cover the whole executed quantity.
+    ///
+    /// returns: whether fills were replaced
+    pub fn with_trade_fills(&mut self, trades: &[TradeFill]) -> bool {
+        let Some(remote_id) = self.remote_id.as_ref() else {
+            return false;
+        };
+        // Trades with an invalid time cannot be trusted, fills are then kept as is
+        let Some(fills) = trades
+            .iter()
+            .filter(|t| &t.order_id == remote_id)
+            .map(|t| {
+                Utc.timestamp_millis_opt(t.time).single().map(|ts| OrderFill {
+                    price: t.price,
+                    qty: t.qty,
+                    fee: t.fee,
+                    fee_asset: Some(t.fee_asset.to_string()),
+                    ts,
+                })
+            })
+            .collect::<Option<Vec<OrderFill>>>()
+        else {
+            return false;
+        };
+        let reported_qty: f64 = fills.iter().map(|f| f.qty).sum();
+        if fills.is_empty() || reported_qty < self.total_executed_qty - f64::EPSILON {
+            return false;
+        }
+        self.fills = fills;
+        self.total_executed_qty = reported_qty;
+        self.update_weighted_price();
+        self.updated_at = Utc::now();
+        true
+    }
+
     pub fn update_weighted_price(&mut self) {
         let total_qty = self.fills.iter().map(|fill| fill.qty).sum::<f64>();
         if self.fills.is_empty() || total_qty == 0.0 {
@@ -386,6 +605,7 @@ impl OrderDetail {
                 self.from_fill_update(update);
             }
             TransactionStatus::Rejected(rejection) => self.from_rejected(rejection),
+            TransactionStatus::Amended(amendment) => self.from_amendment(amendment),
             TransactionStatus::Staged(_) => {}
         }
     }
@@ -447,10 +667,14 @@ mod test {
 
     use chrono::{Duration, Utc};
 
-    use brokers::types::{AddOrderRequest, InterestRate, InterestRatePeriod, OrderFill, OrderQuery,
-                         OrderStatus as CoinOrderStatus, OrderSubmission, OrderUpdate};
+    use brokers::exchange::Exchange;
+    use brokers::types::{AddOrderRequest, AmendOrderRequest, AssetType, InterestRate, InterestRatePeriod,
+                         OrderEnforcement, OrderFill, OrderQuery, OrderStatus as CoinOrderStatus, OrderSubmission,
+                         OrderUpdate, TradeType};
+
+    use crate::order_manager::wal::WalCmp;
 
-    use super::{OrderDetail, OrderStatus, Rejection, Transaction, TransactionStatus};
+    use super::{OrderDetail, OrderStatus, Rejection, Transaction, TransactionStatus, ORDER_DETAIL_VERSION};
 
     #[test]
     fn test_variant_eq() {
@@ -479,6 +703,19 @@ mod test {
         assert!(!tr0.variant_eq(&tr1));
     }
 
+    #[test]
+    fn test_amendments_do_not_replace_fills() {
+        let new = TransactionStatus::New(OrderSubmission::default());
+        let amended = TransactionStatus::Amended(AmendOrderRequest::default());
+        let partially_filled = TransactionStatus::PartiallyFilled(OrderUpdate::default());
+        let filled = TransactionStatus::Filled(OrderUpdate::default());
+        assert!(new.is_before(&amended));
+        assert!(amended.is_before(&partially_filled));
+        assert!(!partially_filled.is_before(&amended));
+        assert!(!filled.is_before(&amended));
+        assert!(!amended.is_before(&amended));
+    }
+
     #[test]
     #[should_panic]
     fn test_order_detail_bad_pair() {
@@ -685,4 +922,132 @@ mod test {
 
         assert!(!order.is_same_status(&next_order.status));
     }
+
+    /// Every transaction status variant along with its pinned JSON shape
+    fn golden_statuses() -> Vec<(TransactionStatus, &'static str)> {
+        let fill = OrderUpdate {
+            enforcement: OrderEnforcement::GTC,
+            side: TradeType::Buy,
+            orig_order_id: Some("1".to_string()),
+            order_id: 42,
+            symbol: "BTCUSDT".to_string(),
+            timestamp: 0,
+            new_status: CoinOrderStatus::Filled,
+            orig_status: CoinOrderStatus::New,
+            is_on_the_book: false,
+            qty: 0.0,
+            quote_qty: 0.0,
+            price: 0.0,
+            stop_price: 0.0,
+            iceberg_qty: 0.0,
+            commission: 0.0,
+            commission_asset: None,
+            last_executed_qty: 0.0,
+            cummulative_filled_qty: 0.0,
+            last_executed_price: 0.0,
+            cummulative_quote_asset_transacted_qty: 0.0,
+            last_quote_asset_transacted_qty: 0.0,
+            quote_order_qty: 0.0,
+            rejection_reason: None,
+        };
+        vec![
+            (
+                TransactionStatus::Staged(OrderQuery::AddOrder(AddOrderRequest {
+                    xch: Exchange::Binance,
+                    pair: "BTC_USDT".into(),
+                    side: TradeType::Buy,
+                    enforcement: Some(OrderEnforcement::GTC),
+                    quantity: Some(1.0),
+                    price: Some(100.5),
+                    order_id: "1".to_string(),
+                    emitter_id: Some("strat".to_string()),
+                    asset_type: Some(AssetType::Spot),
+                    ..AddOrderRequest::default()
+                })),
+                r#"{"type":"Staged","AddOrder":{"xch":"binance","pair":"BTC_USDT","side":"Buy","order_type":"Limit","enforcement":"GTC","quantity":1.0,"quote_order_qty":null,"price":100.5,"order_id":"1","transaction_id":null,"emitter_id":"strat","stop_price":null,"iceberg_qty":null,"dry_run":false,"asset_type":"spot","side_effect_type":null,"post_only":false}}"#,
+            ),
+            (
+                TransactionStatus::New(OrderSubmission {
+                    timestamp: 1_600_000_000_000,
+                    id: "remote".to_string(),
+                    pair: "BTC_USDT".into(),
+                    client_id: "1".to_string(),
+                    price: 100.5,
+                    qty: 1.0,
+                    side: TradeType::Buy,
+                    ..OrderSubmission::default()
+                }),
+                r#"{"type":"New","timestamp":1600000000000,"id":"remote","pair":"BTC_USDT","client_id":"1","price":100.5,"qty":1.0,"executed_qty":0.0,"cummulative_quote_qty":0.0,"status":"New","enforcement":"GTC","order_type":"Limit","side":"Buy","asset_type":"spot","trades":[],"borrowed_amount":null,"borrow_asset":null}"#,
+            ),
+            (
+                TransactionStatus::PartiallyFilled(OrderUpdate {
+                    new_status: CoinOrderStatus::PartiallyFilled,
+                    ..fill.clone()
+                }),
+                r#"{"type":"PartiallyFilled","enforcement":"GTC","side":"Buy","orig_order_id":"1","order_id":42,"symbol":"BTCUSDT","timestamp":0,"new_status":"PartiallyFilled","orig_status":"New","is_on_the_book":false,"qty":0.0,"quote_qty":0.0,"price":0.0,"stop_price":0.0,"iceberg_qty":0.0,"commission":0.0,"commission_asset":null,"last_executed_qty":0.0,"cummulative_filled_qty":0.0,"last_executed_price":0.0,"cummulative_quote_asset_transacted_qty":0.0,"last_quote_asset_transacted_qty":0.0,"quote_order_qty":0.0,"rejection_reason":null}"#,
+            ),
+            (
+                TransactionStatus::Filled(fill),
+                r#"{"type":"Filled","enforcement":"GTC","side":"Buy","orig_order_id":"1","order_id":42,"symbol":"BTCUSDT","timestamp":0,"new_status":"Filled","orig_status":"New","is_on_the_book":false,"qty":0.0,"quote_qty":0.0,"price":0.0,"stop_price":0.0,"iceberg_qty":0.0,"commission":0.0,"commission_asset":null,"last_executed_qty":0.0,"cummulative_filled_qty":0.0,"last_executed_price":0.0,"cummulative_quote_asset_transacted_qty":0.0,"last_quote_asset_transacted_qty":0.0,"quote_order_qty":0.0,"rejection_reason":null}"#,
+            ),
+            (
+                TransactionStatus::Rejected(Rejection::BadRequest("bad request".to_string())),
+                r#"{"type":"Rejected","reject_type":"BadRequest","__field0":"bad request"}"#,
+            ),
+            (
+                TransactionStatus::Rejected(Rejection::Timeout),
+                r#"{"type":"Rejected","reject_type":"Timeout"}"#,
+            ),
+            (
+                TransactionStatus::Rejected(Rejection::Cancelled(None)),
+                r#"{"type":"Rejected","reject_type":"Cancelled","__field0":null}"#,
+            ),
+        ]
+    }
+
+    #[test]
+    fn transaction_status_wire_format() {
+        for (status, golden) in golden_statuses() {
+            assert_eq!(serde_json::to_string(&status).unwrap(), golden);
+            let decoded: TransactionStatus = serde_json::from_str(golden).unwrap();
+            assert_eq!(decoded, status);
+        }
+    }
+
+    #[test]
+    fn transaction_wire_format() {
+        let transaction = Transaction {
+            id: "1".to_string(),
+            status: TransactionStatus::Rejected(Rejection::Timeout),
+            ts: Some(1_600_000_000_000),
+        };
+        let golden = r#"{"id":"1","status":{"type":"Rejected","reject_type":"Timeout"},"ts":1600000000000}"#;
+        assert_eq!(serde_json::to_string(&transaction).unwrap(), golden);
+        let decoded: Transaction = serde_json::from_str(golden).unwrap();
+        assert_eq!(decoded, transaction);
+    }
+
+    /// An order stored before `OrderDetail` was versioned, without weighted price, executed quantity,
+    /// rejection or lifecycle timestamps
+    const LEGACY_ORDER_DETAIL: &str = r#"{"id":"1","transaction_id":null,"emitter_id":"strat","remote_id":"42","status":"filled","exchange":"binance","symbol":"BTC_USDT","base_asset":"BTC","quote_asset":"USDT","side":"Buy","order_type":"Limit","enforcement":"GTC","base_qty":2.0,"quote_qty":null,"price":100.0,"stop_price":null,"iceberg_qty":null,"is_test":false,"executed_qty":2.0,"fills":[{"price":100.0,"qty":1.0,"fee":0.1,"fee_asset":"BNB","ts":"2020-09-13T12:26:40Z"},{"price":102.0,"qty":1.0,"fee":0.1,"fee_asset":"BNB","ts":"2020-09-13T12:26:41Z"}],"created_at":"2020-09-13T12:26:39Z"}"#;
+
+    #[test]
+    fn legacy_order_detail_upgrades() {
+        let legacy: OrderDetail = serde_json::from_str(LEGACY_ORDER_DETAIL).unwrap();
+        assert_eq!(legacy.version, 0);
+        let order = legacy.migrate();
+        assert_eq!(order.version, ORDER_DETAIL_VERSION);
+        assert_eq!(order.status, OrderStatus::Filled);
+        assert_eq!(order.asset_type, AssetType::Spot);
+        assert_eq!(order.fills.len(), 2);
+        assert!((order.total_executed_qty - 2.0).abs() < f64::EPSILON);
+        assert!((order.weighted_price - 101.0).abs() < f64::EPSILON);
+        assert_eq!(order.updated_at, order.created_at);
+        assert_eq!(order.rejection_reason, None);
+        assert_eq!(order.closed_at, None);
+        // Upgraded records round trip in the current format
+        let stored = serde_json::to_string(&order).unwrap();
+        let reloaded: OrderDetail = serde_json::from_str(&stored).unwrap();
+        assert_eq!(reloaded.clone().migrate(), reloaded);
+    }
 }
@@ -1,8 +1,13 @@
 use std::collections::hash_map::Entry;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Duration;
 
-use chrono::Utc;
+use chrono::{DateTime, Utc};
+use itertools::Itertools;
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 
@@ -11,21 +16,200 @@ use db::{Storage, StorageExt};
 use super::error::*;
 
 static WAL_KEY_SEP: &str = "|";
+static SEGMENTS_TABLE_SUFFIX: &str = "_segments";
 
 pub trait WalCmp {
     fn is_before(&self, variant: &Self) -> bool;
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WalArchiveConfig {
+    /// Records older than the current segment are archived when rotating
+    #[serde(deserialize_with = "util::ser::string_duration")]
+    pub segment_duration: Duration,
+    /// Directory in which archived segments are written
+    pub dir: PathBuf,
+    /// If set, archived segments are uploaded to this S3 prefix, and downloaded back when missing locally
+    pub s3_prefix: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ArchivedRecord {
+    key: String,
+    ts: i64,
+    value: serde_json::Value,
+}
+
+/// An archived segment file, registered once its records are written
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+struct ArchivedSegment {
+    cutoff: i64,
+    /// The segment only exists locally until it is uploaded
+    pending_upload: bool,
+}
+
 #[derive(Debug)]
 pub struct Wal {
     backend: Arc<dyn Storage>,
     table: String,
+    archive: Option<WalArchiveConfig>,
 }
 
 impl Wal {
-    pub fn new(backend: Arc<dyn Storage>, table: String) -> Self {
+    pub fn new(backend: Arc<dyn Storage>, table: String) -> Self { Self::new_with_archive(backend, table, None) }
+
+    pub fn new_with_archive(backend: Arc<dyn Storage>, table: String, archive: Option<WalArchiveConfig>) -> Self {
         backend.ensure_table(&table).unwrap();
-        Self { backend, table }
+        backend.ensure_table(&Self::segments_table_name(&table)).unwrap();
+        Self {
+            backend,
+            table,
+            archive,
+        }
+    }
+
+    fn segments_table_name(table: &str) -> String { format!("{}{}", table, SEGMENTS_TABLE_SUFFIX) }
+
+    pub fn segment_duration(&self) -> Option<Duration> { self.archive.as_ref().map(|a| a.segment_duration) }
+
+    /// Archive the records older than the segment containing `at` of keys whose latest record is not open, and drop
+    /// them from the hot store, records of open keys are carried forward so that they can still be repaired
+    ///
+    /// Archives are named uniquely and never deleted, failed uploads are retried on the next rotation.
+    ///
+    /// returns: the archived segment file, if any record was archived
+    pub fn rotate<T: DeserializeOwned + WalCmp>(
+        &self,
+        at: DateTime<Utc>,
+        is_open: impl Fn(&T) -> bool,
+    ) -> Result<Option<PathBuf>> {
+        let Some(archive) = self.archive.as_ref() else {
+            return Ok(None);
+        };
+        self.upload_pending_segments(archive)?;
+        let segment_nanos = i64::try_from(archive.segment_duration.as_nanos()).unwrap_or(i64::MAX);
+        let at_nanos = at.timestamp_nanos();
+        let cutoff = at_nanos - at_nanos.rem_euclid(segment_nanos.max(1));
+        let open: HashSet<String> = self
+            .iter_compacted::<T>()?
+            .filter(|(_, v)| is_open(v))
+            .map(|(k, _)| k)
+            .collect();
+        let records: Vec<(i64, (String, serde_json::Value))> = self
+            .iter::<serde_json::Value>()?
+            .filter(|(ts, (key, _))| *ts < cutoff && !open.contains(key))
+            .collect();
+        if records.is_empty() {
+            return Ok(None);
+        }
+        std::fs::create_dir_all(&archive.dir)?;
+        let name = format!("{}_{}_{}.ndjson", self.table, cutoff, Utc::now().timestamp_nanos());
+        let path = archive.dir.join(&name);
+        let mut archived_keys = vec![];
+        {
+            let mut writer = BufWriter::new(File::create(&path)?);
+            for (ts, (key, value)) in records {
+                archived_keys.push(format!("{}{}{}", key, WAL_KEY_SEP, ts));
+                serde_json::to_writer(&mut writer, &ArchivedRecord { key, ts, value })?;
+                writer.write_all(b"\n")?;
+            }
+            writer.flush()?;
+            writer.get_ref().sync_all()?;
+        }
+        let pending_upload = match Self::upload_segment(archive, &name) {
+            Ok(()) => false,
+            Err(e) => {
+                warn!(err = %e, segment = %name, "failed to upload wal segment, retrying on the next rotation");
+                true
+            }
+        };
+        self.backend
+            .put(&Self::segments_table_name(&self.table), &name, ArchivedSegment {
+                cutoff,
+                pending_upload,
+            })?;
+        let deletions: Vec<(&str, &[u8], Option<Vec<u8>>)> = archived_keys
+            .iter()
+            .map(|k| (self.table.as_str(), k.as_bytes(), None))
+            .collect();
+        self.backend._batch(&deletions)?;
+        Ok(Some(path))
+    }
+
+    /// Upload an archived segment to the S3 prefix of the archive, if any
+    fn upload_segment(archive: &WalArchiveConfig, name: &str) -> Result<()> {
+        let Some(prefix) = archive.s3_prefix.as_ref() else {
+            return Ok(());
+        };
+        let output = util::s3::upload_file(
+            &archive.dir.join(name),
+            util::s3::DEFAULT_BUCKET,
+            &format!("{}/{}", prefix, name),
+        )?;
+        if !output.status.success() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                String::from_utf8_lossy(&output.stderr).into_owned(),
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Retry the uploads of the segments that failed to upload
+    fn upload_pending_segments(&self, archive: &WalArchiveConfig) -> Result<()> {
+        let segments_table = Self::segments_table_name(&self.table);
+        for (name, segment) in self.backend.get_all::<ArchivedSegment>(&segments_table)? {
+            if !segment.pending_upload {
+                continue;
+            }
+            let name = String::from_utf8_lossy(&name).into_owned();
+            match Self::upload_segment(archive, &name) {
+                Ok(()) => self.backend.put(&segments_table, &name, ArchivedSegment {
+                    pending_upload: false,
+                    ..segment
+                })?,
+                Err(e) => warn!(err = %e, segment = %name, "failed to upload wal segment"),
+            }
+        }
+        Ok(())
+    }
+
+    /// Return all archived values for this key sorted by time, segments missing locally are downloaded first
+    pub fn get_archived_k<T: DeserializeOwned>(&self, key: &str) -> Result<Vec<(i64, T)>> {
+        let Some(archive) = self.archive.as_ref() else {
+            return Ok(vec![]);
+        };
+        let mut res = vec![];
+        for (name, segment) in self
+            .backend
+            .get_all::<ArchivedSegment>(&Self::segments_table_name(&self.table))?
+        {
+            let name = String::from_utf8_lossy(&name).into_owned();
+            let path = archive.dir.join(&name);
+            if !path.exists() && !segment.pending_upload {
+                if let Some(prefix) = archive.s3_prefix.as_ref() {
+                    util::s3::download_file(&format!("{}/{}", prefix, name), path.clone())?;
+                }
+            }
+            for line in BufReader::new(File::open(&path)?).lines() {
+                let record: ArchivedRecord = serde_json::from_str(&line?)?;
+                if record.key == key {
+                    if let Ok(v) = serde_json::from_value(record.value) {
+                        res.push((record.ts, v));
+                    }
+                }
+            }
+        }
+        res.sort_by_key(|(ts, _)| *ts);
+        Ok(res)
+    }
+
+    /// Return all values for this key sorted by time, including archived ones
+    pub fn get_all_k_with_archive<T: DeserializeOwned>(&self, key: &str) -> Result<Vec<(i64, T)>> {
+        let mut res = self.get_archived_k(key)?;
+        res.extend(self.get_all_k(key)?);
+        Ok(res)
     }
 
     pub fn get_all_compacted<T: DeserializeOwned + WalCmp>(&self) -> Result<HashMap<String, T>> {
@@ -56,6 +240,34 @@ impl Wal {
         Ok(records)
     }
 
+    /// Lazily iterate over all records, ordered by key then time
+    pub fn iter<'a, T: DeserializeOwned + 'a>(&'a self) -> Result<impl Iterator<Item = (i64, (String, T))> + 'a> {
+        let records = self.backend.scan_prefix::<_, T>(&self.table, "")?;
+        Ok(records.filter_map(|r| {
+            let (key, v) = r.ok()?;
+            let (k, t): (String, i64) = Wal::wal_key(String::from_utf8(key.to_vec()).ok()?);
+            Some((t, (k, v)))
+        }))
+    }
+
+    /// Lazily iterate over the latest record of each key, records of a key being contiguous
+    pub fn iter_compacted<'a, T: DeserializeOwned + WalCmp + 'a>(
+        &'a self,
+    ) -> Result<impl Iterator<Item = (String, T)> + 'a> {
+        Ok(self
+            .iter::<T>()?
+            .map(|(_t, record)| record)
+            .coalesce(|(k1, v1), (k2, v2)| {
+                if k1 != k2 {
+                    Err(((k1, v1), (k2, v2)))
+                } else if v1.is_before(&v2) {
+                    Ok((k2, v2))
+                } else {
+                    Ok((k1, v1))
+                }
+            }))
+    }
+
     pub fn get_all<T: DeserializeOwned>(&self) -> Result<Vec<(i64, (String, T))>> {
         let v = self.backend.get_all::<serde_json::Value>(&self.table)?;
         let res = v
@@ -101,3 +313,92 @@ impl Wal {
         Ok(self.backend.put(&self.table, &key, t)?)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use std::time::Duration;
+
+    use chrono::Utc;
+
+    use db::{get_or_create, DbOptions};
+    use util::test::test_dir;
+
+    use super::{Wal, WalArchiveConfig, WalCmp};
+
+    #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+    struct Step(u32);
+
+    impl WalCmp for Step {
+        fn is_before(&self, v: &Self) -> bool { self.0 < v.0 }
+    }
+
+    #[test]
+    fn iter_compacted_matches_get_all_compacted() {
+        let dir = test_dir();
+        let db = get_or_create(&DbOptions::new(dir.path()), "", vec![]);
+        let wal = Wal::new(db, "wal".to_string());
+        for i in 0..100_u32 {
+            wal.append_raw(&format!("order{}", i % 7), 1000 + i64::from(i), Step(i * 31 % 11))
+                .unwrap();
+        }
+        let compacted: HashMap<String, Step> = wal.get_all_compacted().unwrap();
+        let mut iter = wal.iter_compacted::<Step>().unwrap();
+        // Each key is yielded as soon as its records have been read
+        let first = iter.next();
+        assert!(first.is_some());
+        let streamed: HashMap<String, Step> = first.into_iter().chain(iter).collect();
+        assert_eq!(streamed.len(), 7);
+        assert_eq!(streamed, compacted);
+        assert_eq!(wal.iter::<Step>().unwrap().count(), 100);
+    }
+
+    #[test]
+    fn rotated_segments_remain_retrievable() {
+        let dir = test_dir();
+        let db = get_or_create(&DbOptions::new(dir.path().join("db")), "", vec![]);
+        let wal = Wal::new_with_archive(
+            db,
+            "wal".to_string(),
+            Some(WalArchiveConfig {
+                segment_duration: Duration::from_secs(3600),
+                dir: dir.path().join("archive"),
+                s3_prefix: None,
+            }),
+        );
+        let now = Utc::now();
+        let old = (now - chrono::Duration::hours(2)).timestamp_nanos();
+        wal.append_raw("order", old, Step(0)).unwrap();
+        wal.append_raw("order", old + 1, Step(1)).unwrap();
+        wal.append_raw("other", old, Step(0)).unwrap();
+        wal.append_raw("order", now.timestamp_nanos(), Step(2)).unwrap();
+        // Keys whose latest record is open are never archived
+        wal.append_raw("open", old, Step(9)).unwrap();
+        let is_open = |step: &Step| step.0 == 9;
+
+        let archived = wal.rotate(now, is_open).unwrap().unwrap();
+        assert!(archived.exists());
+        // Only the current segment and the open key remain in the hot store
+        assert_eq!(wal.iter::<Step>().unwrap().count(), 2);
+        assert_eq!(wal.get_all_k::<Step>("open").unwrap().len(), 1);
+        assert_eq!(wal.get_all_k::<Step>("order").unwrap().len(), 1);
+        let all: Vec<Step> = wal
+            .get_all_k_with_archive::<Step>("order")
+            .unwrap()
+            .into_iter()
+            .map(|(_, step)| step)
+            .collect();
+        assert_eq!(all, vec![Step(0), Step(1), Step(2)]);
+        assert_eq!(wal.get_archived_k::<Step>("other").unwrap().len(), 1);
+        // Nothing is left to archive
+        assert_eq!(wal.rotate(now, is_open).unwrap(), None);
+
+        // Another rotation of the same segment writes a new archive and keeps the previous one
+        wal.append_raw("other", old + 2, Step(1)).unwrap();
+        let second = wal.rotate(now, is_open).unwrap().unwrap();
+        assert_ne!(second, archived);
+        assert!(archived.exists());
+        assert_eq!(wal.get_archived_k::<Step>("other").unwrap().len(), 2);
+        assert_eq!(wal.get_all_k_with_archive::<Step>("order").unwrap().len(), 3);
+    }
+}
@@ -107,7 +107,7 @@ pub struct Position {
     /// Long or Short.
     pub kind: PositionKind,
 
-    /// +ve or -ve quantity of symbol contracts opened.
+    /// +ve or -ve quantity of symbol contracts opened, less what closing orders resolved before filling executed.
     pub quantity: f64,
 
     pub open_order: Option<OrderDetail>,
@@ -123,6 +123,10 @@ pub struct Position {
     /// Realised PnL after the [Position] has closed.
     pub result_profit_loss: f64,
 
+    /// Realised PnL of the closing orders resolved before filling, the position stays open for the rest.
+    #[serde(default)]
+    pub partial_profit_loss: f64,
+
     /// Accrued Interest
     pub interests: f64,
 }
@@ -164,6 +168,7 @@ impl Default for Position {
             current_symbol_price: 0.0,
             unreal_profit_loss: 0.0,
             result_profit_loss: 0.0,
+            partial_profit_loss: 0.0,
             interests: 0.0,
         }
     }
@@ -201,7 +206,24 @@ impl Position {
         }
     }
 
+    /// Record further fills of the opening order
+    pub fn fill_open(&mut self, order: &OrderDetail) {
+        self.meta.last_update = now();
+        self.quantity = order.total_executed_qty;
+        self.open_order = Some(order.clone());
+    }
+
+    /// Record fills of a closing order, the profit or loss is realized pro rata of the closed quantity.
+    /// A closing order resolved before filling closes part of the position, which stays open for the rest.
     pub fn close(&mut self, value: f64, order: &OrderDetail) {
+        let already_resolved = self
+            .close_order
+            .as_ref()
+            .filter(|o| o.id == order.id)
+            .map_or(false, OrderDetail::is_resolved);
+        if already_resolved {
+            return;
+        }
         let trace_id = Uuid::new_v4();
         let now = now();
         self.meta.close_trace_id = Some(trace_id);
@@ -215,6 +237,10 @@ impl Position {
         self.current_symbol_price = order.price.unwrap_or(0.0);
         self.result_profit_loss = self.calculate_result_profit_loss();
         self.unreal_profit_loss = self.result_profit_loss;
+        if order.is_resolved() && !order.is_filled() {
+            self.partial_profit_loss = self.result_profit_loss;
+            self.quantity = (self.quantity - order.total_executed_qty).max(0.0);
+        }
     }
 
     pub fn update(&mut self, event: &MarketEventEnvelope, fees_rate: f64, interests: f64) {
@@ -223,18 +249,29 @@ impl Position {
             MarketEvent::Orderbook(ref o) => o.vwap().unwrap_or(0.0),
             MarketEvent::TradeCandle(ref ct) => ct.close,
             MarketEvent::BookCandle(ref bc) => bc.mid.close,
+            MarketEvent::Quote(ref q) => q.vwap(),
+            // Funding rates only carry a price when the exchange sends the mark price along
+            MarketEvent::FundingRate(ref fr) => match fr.mark_price {
+                Some(price) => price,
+                None => return,
+            },
+            // Book changes and open interest carry no market price
+            MarketEvent::OrderbookL3(_) | MarketEvent::OpenInterest(_) => return,
         };
         self.meta.last_update_trace_id = event.trace_id;
-        self.meta.last_update = event.e.time();
+        self.mark(price, event.e.time(), fees_rate, interests);
+    }
+
+    /// Mark the position to `price` at `at`, for instance from a ticker when its pair has no market feed
+    pub fn mark(&mut self, price: f64, at: DateTime<Utc>, fees_rate: f64, interests: f64) {
+        self.meta.last_update = at;
         self.current_symbol_price = price;
         self.unreal_profit_loss = self.calculate_unreal_profit_loss(fees_rate, interests);
         //eprintln!("self.unreal_profit_loss = {:?}", self.unreal_profit_loss);
         self.interests = interests;
     }
 
-    pub fn current_value_gross(&self) -> f64 {
-        self.open_order.as_ref().map_or(0.0, |o| o.total_executed_qty).abs() * self.current_symbol_price
-    }
+    pub fn current_value_gross(&self) -> f64 { self.quantity.abs() * self.current_symbol_price }
 
     /// Calculate the approximate [`Position::unreal_profit_loss`] of a [`Position`].
     ///
@@ -242,8 +279,8 @@ impl Position {
     ///
     /// if there is no open order (this should not happen as an open order is required to create a position)
     pub fn calculate_unreal_profit_loss(&self, fees_rate: f64, interests: f64) -> f64 {
-        // (open_qty * price) - fees
-        let enter_value = self.open_quote_value();
+        // (open_qty * price) - fees, for the quantity still open
+        let enter_value = self.open_quote_value() * self.open_ratio();
         // (open_qty * current_price)
         let current_value = self.current_value_gross();
         match self.kind {
@@ -270,24 +307,44 @@ impl Position {
             .unwrap()
     }
 
-    /// Calculate the exact [`Position::result_profit_loss`] of a [`Position`].
+    /// Share of the opened quantity still open
+    fn open_ratio(&self) -> f64 {
+        let opened = self.open_order.as_ref().map_or(0.0, |o| o.total_executed_qty);
+        if opened > 0.0 {
+            (self.quantity / opened).min(1.0)
+        } else {
+            0.0
+        }
+    }
+
+    /// Calculate the exact [`Position::result_profit_loss`] of a [`Position`], the entry value is
+    /// accounted pro rata of the quantity executed by the closing order.
     pub fn calculate_result_profit_loss(&self) -> f64 {
         let exit_value = self.close_quote_value();
-        let enter_value = self.open_quote_value();
-        match self.kind {
-            PositionKind::Long => exit_value - enter_value,
-            PositionKind::Short => enter_value - exit_value,
-        }
+        let close_ratio = self.close_order.as_ref().map_or(0.0, |o| {
+            if o.is_filled() || self.quantity <= 0.0 {
+                1.0
+            } else {
+                (o.total_executed_qty / self.quantity).min(1.0)
+            }
+        });
+        let enter_value = self.open_quote_value() * self.open_ratio() * close_ratio;
+        self.partial_profit_loss
+            + match self.kind {
+                PositionKind::Long => exit_value - enter_value,
+                PositionKind::Short => enter_value - exit_value,
+            }
     }
 
     /// Calculate the `PnL` return of a closed [`Position`] - assumed [`Position::result_profit_loss`] is
     /// appropriately calculated.
     pub fn calculate_profit_loss_return(&self) -> f64 { self.result_profit_loss / self.open_quote_value() }
 
+    /// The opening order failed without executing anything
     pub fn is_failed_open(&self) -> bool {
-        self.open_order
-            .as_ref()
-            .map_or(false, |o| o.is_rejected() || o.is_bad_request())
+        self.open_order.as_ref().map_or(false, |o| {
+            (o.is_rejected() || o.is_bad_request()) && o.total_executed_qty <= 0.0
+        })
     }
 
     pub fn is_failed_close(&self) -> bool {
@@ -296,23 +353,42 @@ impl Position {
             .map_or(false, |o| o.is_rejected() || o.is_bad_request())
     }
 
-    pub fn is_opened(&self) -> bool { self.open_order.as_ref().map_or(false, OrderDetail::is_filled) }
+    /// The opening order is filled, or was resolved after executing part of its quantity
+    pub fn is_opened(&self) -> bool {
+        self.open_order.as_ref().map_or(false, |o| {
+            o.is_filled() || (o.is_resolved() && o.total_executed_qty > 0.0)
+        })
+    }
+
+    /// The pending order of the position, the closing order once there is one
+    fn latest_order(&self) -> Option<&OrderDetail> { self.close_order.as_ref().or(self.open_order.as_ref()) }
+
+    /// Share of the latest order of the position executed so far
+    pub fn fill_ratio(&self) -> f64 { self.latest_order().map_or(0.0, OrderDetail::fill_ratio) }
+
+    /// Base quantity the latest order of the position has yet to execute
+    pub fn unfilled_qty(&self) -> f64 { self.latest_order().map_or(0.0, OrderDetail::remaining_qty) }
 
     pub fn is_closed(&self) -> bool { self.close_order.as_ref().map_or(false, OrderDetail::is_filled) }
 
-    pub fn quantity(&self) -> f64 { self.open_order.as_ref().map_or(0.0, |o| o.total_executed_qty) }
+    /// Quantity of the position still open
+    pub fn quantity(&self) -> f64 { self.quantity }
 
     pub fn is_short(&self) -> bool { self.kind == PositionKind::Short }
 
     pub fn is_long(&self) -> bool { self.kind == PositionKind::Long }
 
+    /// Quantity to close the rest of the position with
     pub fn close_qty(&self, fees_rate: f64, interests: f64) -> Option<f64> {
+        let open_ratio = self.open_ratio();
         self.open_order.as_ref().map(|o| match self.kind {
             PositionKind::Short => match o.asset_type {
-                AssetType::IsolatedMargin | AssetType::Margin => (o.total_executed_qty / (1.0 - fees_rate)) + interests,
-                _ => o.total_executed_qty + o.base_fees(),
+                AssetType::IsolatedMargin | AssetType::Margin => {
+                    (o.total_executed_qty * open_ratio / (1.0 - fees_rate)) + interests
+                }
+                _ => (o.total_executed_qty + o.base_fees()) * open_ratio,
             },
-            PositionKind::Long => o.total_executed_qty - o.base_fees(),
+            PositionKind::Long => (o.total_executed_qty - o.base_fees()) * open_ratio,
         })
     }
 }
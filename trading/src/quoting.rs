@@ -0,0 +1,410 @@
+//! Two sided quotes of market making strategies : the quotes a strategy wants on a market are reconciled with the
+//! limit orders resting for it, which are placed, amended in place or canceled. Quotes bypass the portfolio, they
+//! are checked against [`QuotingOptions`] on every tick instead, but their fills are reported to it so that the
+//! inventory they accumulate is accounted and can be flattened.
+
+use chrono::{DateTime, Duration, Utc};
+use uuid::Uuid;
+
+use brokers::exchange::Exchange;
+use brokers::types::{AddOrderRequest, AmendOrderRequest, OrderEnforcement, OrderType, Pair, TradeType};
+
+use crate::order_manager::types::OrderDetail;
+
+const BPS: f64 = 10_000.0;
+
+fn default_reprice_bps() -> f64 { 1.0 }
+
+/// A limit order a strategy wants resting on one side of a book
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct QuoteLevel {
+    pub price: f64,
+    pub qty: f64,
+}
+
+/// The bid and ask a strategy wants resting on a market, a missing side is not quoted
+#[derive(Clone, Debug, PartialEq)]
+pub struct TwoSidedQuote {
+    pub xch: Exchange,
+    pub pair: Pair,
+    pub bid: Option<QuoteLevel>,
+    pub ask: Option<QuoteLevel>,
+}
+
+impl TwoSidedQuote {
+    /// No quote on either side, the resting quotes of the market are canceled
+    pub fn pulled(xch: Exchange, pair: Pair) -> Self {
+        Self {
+            xch,
+            pair,
+            bid: None,
+            ask: None,
+        }
+    }
+}
+
+/// Risk limits of quotes, checked on every tick before quotes are placed
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct QuotingOptions {
+    /// Absolute base inventory that fills of the quotes cannot exceed
+    pub max_inventory: f64,
+    /// Maximum quantity of a quote
+    #[serde(default)]
+    pub max_quote_qty: Option<f64>,
+    /// Quotes are pulled when the last book of the market is older than this
+    #[serde(
+        default,
+        deserialize_with = "util::ser::string_duration_chrono_opt",
+        serialize_with = "util::ser::encode_duration_str_opt"
+    )]
+    pub max_book_age: Option<Duration>,
+    /// Quotes are pulled when the quoted spread is below this, in basis points of the mid price
+    #[serde(default)]
+    pub min_spread_bps: f64,
+    /// Resting quotes are amended once their price or quantity moves by more than this, in basis points
+    #[serde(default = "default_reprice_bps")]
+    pub reprice_bps: f64,
+}
+
+impl QuotingOptions {
+    /// The part of a quote that passes the limits, given the current inventory and the age of the last book
+    pub fn check(&self, quote: TwoSidedQuote, inventory: f64, book_age: Option<Duration>) -> TwoSidedQuote {
+        let stale = match (self.max_book_age, book_age) {
+            (Some(max_age), Some(age)) => age > max_age,
+            (Some(_), None) => true,
+            (None, _) => false,
+        };
+        let too_tight = match (quote.bid, quote.ask) {
+            (Some(bid), Some(ask)) => {
+                let mid = (bid.price + ask.price) / 2.0;
+                ask.price <= bid.price || (ask.price - bid.price) / mid * BPS < self.min_spread_bps
+            }
+            _ => false,
+        };
+        if stale || too_tight {
+            return TwoSidedQuote::pulled(quote.xch, quote.pair);
+        }
+        // Fills of a quote never take the inventory past its limit
+        let limit = |level: QuoteLevel, room: f64| {
+            let qty = self.max_quote_qty.map_or(level.qty, |max| level.qty.min(max)).min(room);
+            Some(QuoteLevel { qty, ..level }).filter(|l| l.qty > 0.0 && l.price > 0.0)
+        };
+        TwoSidedQuote {
+            bid: quote.bid.and_then(|bid| limit(bid, self.max_inventory - inventory)),
+            ask: quote.ask.and_then(|ask| limit(ask, self.max_inventory + inventory)),
+            ..quote
+        }
+    }
+}
+
+/// A quote resting as a limit order
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RestingQuote {
+    pub order_id: String,
+    pub side: TradeType,
+    pub price: f64,
+    /// Quantity of the order, including its executed part
+    pub qty: f64,
+    pub executed: f64,
+    /// Quote value of the executed part already reported as fills
+    #[serde(default)]
+    pub executed_value: f64,
+    /// Fees of the executed part already reported as fills
+    #[serde(default)]
+    pub fees: f64,
+}
+
+impl RestingQuote {
+    fn remaining(&self) -> f64 { (self.qty - self.executed).max(0.0) }
+}
+
+/// What to do with the orders of a market so that its target quotes rest
+#[derive(Clone, Debug, PartialEq)]
+pub enum QuoteAction {
+    Place(AddOrderRequest),
+    Amend(AmendOrderRequest),
+    Cancel(String),
+}
+
+/// A new execution of a quote order, since the previous one
+#[derive(Clone, Debug, PartialEq)]
+pub struct QuoteFill {
+    pub order_id: String,
+    pub side: TradeType,
+    pub qty: f64,
+    /// Average price of the new execution
+    pub price: f64,
+    /// Fees of the new execution, in the quote asset
+    pub fees: f64,
+}
+
+/// The resting quotes of a market, and the market order unwinding its inventory if any
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Quoter {
+    bid: Option<RestingQuote>,
+    ask: Option<RestingQuote>,
+    #[serde(default)]
+    unwind: Option<RestingQuote>,
+    #[serde(skip)]
+    last_book: Option<DateTime<Utc>>,
+}
+
+impl Quoter {
+    pub fn bid(&self) -> Option<&RestingQuote> { self.bid.as_ref() }
+
+    pub fn ask(&self) -> Option<&RestingQuote> { self.ask.as_ref() }
+
+    /// Record the time of a book of the market
+    pub fn on_book(&mut self, at: DateTime<Utc>) { self.last_book = Some(at); }
+
+    /// Time since the last book of the market, none until a book is received
+    pub fn book_age(&self, at: DateTime<Utc>) -> Option<Duration> { self.last_book.map(|last| at - last) }
+
+    /// Ids of the resting orders, including the unwinding order
+    pub fn resting_orders(&self) -> Vec<String> {
+        self.bid
+            .iter()
+            .chain(self.ask.iter())
+            .chain(self.unwind.iter())
+            .map(|q| q.order_id.clone())
+            .collect()
+    }
+
+    /// Whether `order_id` is one of the orders of this quoter
+    pub fn has_order(&self, order_id: &str) -> bool {
+        self.bid
+            .iter()
+            .chain(self.ask.iter())
+            .chain(self.unwind.iter())
+            .any(|q| q.order_id == order_id)
+    }
+
+    /// The new execution of an order of this quoter if any, orders are forgotten once they are resolved
+    pub fn on_order(&mut self, order: &OrderDetail) -> Option<QuoteFill> {
+        let mut fill = None;
+        for side in [&mut self.bid, &mut self.ask, &mut self.unwind] {
+            let Some(quote) = side.as_mut().filter(|q| q.order_id == order.id) else {
+                continue;
+            };
+            if order.total_executed_qty > quote.executed {
+                let qty = order.total_executed_qty - quote.executed;
+                let executed_value = order.weighted_price * order.total_executed_qty;
+                let fees = order.quote_fees();
+                fill = Some(QuoteFill {
+                    order_id: order.id.clone(),
+                    side: quote.side,
+                    qty,
+                    price: (executed_value - quote.executed_value) / qty,
+                    fees: (fees - quote.fees).max(0.0),
+                });
+                quote.executed = order.total_executed_qty;
+                quote.executed_value = executed_value;
+                quote.fees = fees.max(quote.fees);
+            }
+            if order.is_resolved() {
+                *side = None;
+            }
+        }
+        fill
+    }
+
+    /// A market order flattening `inventory`, unless one is already resting
+    pub fn unwind(&self, xch: Exchange, pair: Pair, inventory: f64, dry_run: bool) -> Option<QuoteAction> {
+        if self.unwind.is_some() || inventory == 0.0 {
+            return None;
+        }
+        Some(QuoteAction::Place(AddOrderRequest {
+            xch,
+            pair,
+            side: if inventory > 0.0 {
+                TradeType::Sell
+            } else {
+                TradeType::Buy
+            },
+            order_type: OrderType::Market,
+            quantity: Some(inventory.abs()),
+            order_id: Uuid::new_v4().to_string(),
+            dry_run,
+            ..AddOrderRequest::default()
+        }))
+    }
+
+    /// Actions that rest `target` in place of the current quotes
+    pub fn reconcile(&self, target: &TwoSidedQuote, reprice_bps: f64, dry_run: bool) -> Vec<QuoteAction> {
+        [
+            (TradeType::Buy, self.bid.as_ref(), target.bid),
+            (TradeType::Sell, self.ask.as_ref(), target.ask),
+        ]
+        .into_iter()
+        .filter_map(|(side, resting, level)| match (resting, level) {
+            (None, None) => None,
+            (Some(resting), None) => Some(QuoteAction::Cancel(resting.order_id.clone())),
+            (None, Some(level)) => Some(QuoteAction::Place(AddOrderRequest {
+                xch: target.xch,
+                pair: target.pair.clone(),
+                side,
+                order_type: OrderType::Limit,
+                enforcement: Some(OrderEnforcement::GTC),
+                quantity: Some(level.qty),
+                price: Some(level.price),
+                order_id: Uuid::new_v4().to_string(),
+                dry_run,
+                post_only: true,
+                ..AddOrderRequest::default()
+            })),
+            (Some(resting), Some(level)) => {
+                let moved = |from: f64, to: f64| (to - from).abs() > from * reprice_bps / BPS;
+                (moved(resting.price, level.price) || moved(resting.remaining(), level.qty)).then(|| {
+                    QuoteAction::Amend(AmendOrderRequest {
+                        xch: target.xch,
+                        pair: target.pair.clone(),
+                        order_id: resting.order_id.clone(),
+                        price: Some(level.price),
+                        quantity: Some(resting.executed + level.qty),
+                        ..AmendOrderRequest::default()
+                    })
+                })
+            }
+        })
+        .collect()
+    }
+
+    /// Record an action once it succeeded
+    pub fn apply(&mut self, action: &QuoteAction) {
+        match action {
+            QuoteAction::Place(request) => {
+                let quote = RestingQuote {
+                    order_id: request.order_id.clone(),
+                    side: request.side,
+                    price: request.price.unwrap_or_default(),
+                    qty: request.quantity.unwrap_or_default(),
+                    executed: 0.0,
+                    executed_value: 0.0,
+                    fees: 0.0,
+                };
+                match (request.order_type, request.side) {
+                    (OrderType::Market, _) => self.unwind = Some(quote),
+                    (_, TradeType::Buy) => self.bid = Some(quote),
+                    (_, TradeType::Sell) => self.ask = Some(quote),
+                }
+            }
+            QuoteAction::Amend(request) => {
+                for quote in self.bid.iter_mut().chain(self.ask.iter_mut()) {
+                    if quote.order_id == request.order_id {
+                        quote.price = request.price.unwrap_or(quote.price);
+                        quote.qty = request.quantity.unwrap_or(quote.qty);
+                    }
+                }
+            }
+            QuoteAction::Cancel(order_id) => {
+                for side in [&mut self.bid, &mut self.ask, &mut self.unwind] {
+                    if side.as_ref().map_or(false, |q| &q.order_id == order_id) {
+                        *side = None;
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::Duration;
+
+    use brokers::exchange::Exchange;
+    use brokers::types::{AddOrderRequest, OrderType, TradeType};
+
+    use crate::order_manager::types::{OrderDetail, OrderStatus};
+
+    use super::{QuoteAction, QuoteLevel, Quoter, QuotingOptions, TwoSidedQuote};
+
+    fn quote(bid: f64, ask: f64, qty: f64) -> TwoSidedQuote {
+        TwoSidedQuote {
+            xch: Exchange::Binance,
+            pair: "BTC_USDT".into(),
+            bid: Some(QuoteLevel { price: bid, qty }),
+            ask: Some(QuoteLevel { price: ask, qty }),
+        }
+    }
+
+    #[test]
+    fn check_quote_limits() {
+        let options: QuotingOptions = serde_json::from_value(serde_json::json!({
+            "max_inventory": 2.0,
+            "max_quote_qty": 1.5,
+            "max_book_age": "5s",
+            "min_spread_bps": 5.0,
+        }))
+        .unwrap();
+        let fresh = Some(Duration::seconds(1));
+        assert_eq!(
+            options.check(quote(99.0, 101.0, 1.0), 0.0, fresh),
+            quote(99.0, 101.0, 1.0)
+        );
+        // Stale, missing or crossed books pull both sides
+        assert_eq!(options.check(quote(99.0, 101.0, 1.0), 0.0, None).bid, None);
+        assert_eq!(
+            options
+                .check(quote(99.0, 101.0, 1.0), 0.0, Some(Duration::seconds(6)))
+                .ask,
+            None
+        );
+        assert_eq!(options.check(quote(100.0, 100.01, 1.0), 0.0, fresh).bid, None);
+        // Quantities are capped and fills cannot take the inventory past its limit
+        let checked = options.check(quote(99.0, 101.0, 3.0), 1.0, fresh);
+        assert_eq!(checked.bid.unwrap().qty, 1.0);
+        assert_eq!(checked.ask.unwrap().qty, 1.5);
+        assert_eq!(options.check(quote(99.0, 101.0, 1.0), -2.0, fresh).ask, None);
+    }
+
+    #[test]
+    fn reconcile_resting_quotes() {
+        let mut quoter = Quoter::default();
+        let actions = quoter.reconcile(&quote(99.0, 101.0, 1.0), 1.0, true);
+        assert_eq!(actions.len(), 2);
+        assert!(
+            matches!(&actions[0], QuoteAction::Place(AddOrderRequest { side: TradeType::Buy, price: Some(p), post_only: true, .. }) if *p == 99.0)
+        );
+        actions.iter().for_each(|a| quoter.apply(a));
+        assert!(quoter.reconcile(&quote(99.0, 101.0, 1.0), 1.0, true).is_empty());
+
+        // Half of the bid is filled, the remainder is amended to the new price
+        let bid_id = quoter.bid().unwrap().order_id.clone();
+        let mut order = OrderDetail::from_query(AddOrderRequest {
+            order_id: bid_id.clone(),
+            pair: "BTC_USDT".into(),
+            ..AddOrderRequest::default()
+        });
+        order.status = OrderStatus::PartiallyFilled;
+        order.total_executed_qty = 0.5;
+        order.weighted_price = 99.0;
+        let fill = quoter.on_order(&order).unwrap();
+        assert_eq!((fill.side, fill.qty, fill.price), (TradeType::Buy, 0.5, 99.0));
+        // Executions are reported once
+        assert_eq!(quoter.on_order(&order), None);
+        let mut target = quote(98.0, 101.0, 0.5);
+        target.ask = None;
+        let actions = quoter.reconcile(&target, 1.0, true);
+        assert!(matches!(&actions[0], QuoteAction::Amend(a) if a.price == Some(98.0) && a.quantity == Some(1.0)));
+        assert!(matches!(&actions[1], QuoteAction::Cancel(_)));
+        actions.iter().for_each(|a| quoter.apply(a));
+        assert_eq!(quoter.resting_orders(), vec![bid_id]);
+
+        // Filled quotes are forgotten
+        order.status = OrderStatus::Filled;
+        order.total_executed_qty = 1.0;
+        order.weighted_price = 98.5;
+        let fill = quoter.on_order(&order).unwrap();
+        assert_eq!((fill.qty, fill.price), (0.5, 98.0));
+        assert!(quoter.bid().is_none());
+
+        // The inventory is unwound by a single market order
+        let unwind = quoter.unwind(Exchange::Binance, "BTC_USDT".into(), 1.0, true).unwrap();
+        assert!(
+            matches!(&unwind, QuoteAction::Place(AddOrderRequest { side: TradeType::Sell, order_type: OrderType::Market, quantity: Some(q), .. }) if *q == 1.0)
+        );
+        quoter.apply(&unwind);
+        assert_eq!(quoter.unwind(Exchange::Binance, "BTC_USDT".into(), 1.0, true), None);
+        assert_eq!(quoter.resting_orders().len(), 1);
+    }
+}
@@ -0,0 +1,303 @@
+//! Pair screener : scans the pairs of an exchange for their volume, volatility, spread and correlation to a base
+//! pair, and persists the pairs that pass the filters ranked by volume, so that replicated strategies can follow
+//! the current liquid universe rather than a static list of pairs.
+
+use std::sync::Arc;
+
+use chrono::{DateTime, Duration, Utc};
+use itertools::Itertools;
+
+use brokers::api::Brokerage;
+use brokers::exchange::Exchange;
+use brokers::pair::filter_pairs;
+use brokers::types::{Candle, Pair, Ticker};
+use db::{get_or_create, DbOptions, Storage, StorageExt};
+use stats::correlation::{align_on_timestamps, correlation_matrix};
+use stats::kline::{Resolution, TimeUnit};
+
+use crate::error::*;
+
+/// Name of the database of screened universes
+pub const SCREENER_DB: &str = "screener";
+
+const UNIVERSES_TABLE: &str = "universes";
+
+/// Number of hourly returns in a year, to annualize volatilities
+const HOURS_PER_YEAR: f64 = 365.0 * 24.0;
+
+fn default_pairs() -> Vec<String> { vec![".*".to_string()] }
+
+fn default_lookback() -> Duration { Duration::days(7) }
+
+fn default_refresh_secs() -> u64 { 3600 }
+
+/// Configuration of a screener
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ScreenerOptions {
+    /// Name of the screened universe, referenced by strategy copy settings
+    pub name: String,
+    pub exchange: Exchange,
+    /// Expressions of the pairs to scan, see [`filter_pairs`], all pairs of the exchange by default
+    #[serde(default = "default_pairs")]
+    pub pairs: Vec<String>,
+    /// Pair to which the correlation of returns is measured
+    #[serde(default)]
+    pub base_pair: Option<Pair>,
+    /// Period of the hourly candles from which volatility and correlation are measured
+    #[serde(
+        deserialize_with = "util::ser::string_duration_chrono",
+        serialize_with = "util::ser::encode_duration_str"
+    )]
+    #[serde(default = "default_lookback")]
+    pub lookback: Duration,
+    /// Minimum 24h quote volume
+    #[serde(default)]
+    pub min_volume: f64,
+    /// Maximum spread relative to the mid price
+    #[serde(default)]
+    pub max_spread: Option<f64>,
+    /// Maximum annualized volatility
+    #[serde(default)]
+    pub max_volatility: Option<f64>,
+    /// Maximum absolute correlation to the base pair
+    #[serde(default)]
+    pub max_correlation: Option<f64>,
+    /// Number of pairs kept in the universe, all pairs that pass the filters if unset
+    #[serde(default)]
+    pub top: Option<usize>,
+    /// How often the exchange is scanned
+    #[serde(default = "default_refresh_secs")]
+    pub refresh_secs: u64,
+}
+
+/// Metrics of a scanned pair
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct PairMetrics {
+    pub pair: Pair,
+    /// 24h quote volume
+    pub volume: f64,
+    /// Annualized volatility of hourly returns, NaN without enough candles
+    pub volatility: f64,
+    /// Spread relative to the mid price
+    pub spread: f64,
+    /// Correlation of hourly returns to the base pair
+    pub correlation: Option<f64>,
+}
+
+impl PairMetrics {
+    /// Measure a pair from its ticker, its hourly candles and the hourly candles of the base pair
+    pub fn new(ticker: &Ticker, candles: &[Candle], base_candles: Option<&[Candle]>) -> Self {
+        let mid = (ticker.lowest_ask + ticker.highest_bid) / 2.0;
+        let closes: Vec<f64> = candles.iter().map(|c| c.close).collect();
+        let correlation = base_candles.map(|base| {
+            let timed = |candles: &[Candle]| candles.iter().map(|c| (c.start_time, c.close)).collect::<Vec<_>>();
+            let (pair, base) = (timed(candles), timed(base));
+            let aligned = align_on_timestamps(&[(0, pair.as_slice()), (1, base.as_slice())]);
+            correlation_matrix(&[(0, aligned[0].1.as_slice()), (1, aligned[1].1.as_slice())]).get(0, 1)
+        });
+        Self {
+            pair: ticker.pair.clone(),
+            volume: ticker.volume.unwrap_or(0.0),
+            volatility: annualized_volatility(&closes),
+            spread: if mid > 0.0 {
+                (ticker.lowest_ask - ticker.highest_bid) / mid
+            } else {
+                f64::NAN
+            },
+            correlation: correlation.filter(|c| c.is_finite()),
+        }
+    }
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn annualized_volatility(closes: &[f64]) -> f64 {
+    let returns: Vec<f64> = closes.windows(2).map(|w| (w[1] / w[0]).ln()).collect();
+    if returns.len() < 2 {
+        return f64::NAN;
+    }
+    let n = returns.len() as f64;
+    let mean = returns.iter().sum::<f64>() / n;
+    let variance = returns.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / (n - 1.0);
+    (variance * HOURS_PER_YEAR).sqrt()
+}
+
+/// Pairs of an exchange that passed the filters of a screener, by decreasing volume
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct Universe {
+    pub name: String,
+    pub exchange: Exchange,
+    pub at: DateTime<Utc>,
+    pub pairs: Vec<PairMetrics>,
+}
+
+impl Universe {
+    pub fn pairs(&self) -> Vec<Pair> { self.pairs.iter().map(|m| m.pair.clone()).collect() }
+}
+
+#[derive(Debug)]
+pub struct Screener {
+    options: ScreenerOptions,
+    db: Arc<dyn Storage>,
+}
+
+impl Screener {
+    pub fn new(options: ScreenerOptions, db: Arc<dyn Storage>) -> Result<Self> {
+        db.ensure_table(UNIVERSES_TABLE)?;
+        Ok(Self { options, db })
+    }
+
+    pub fn options(&self) -> &ScreenerOptions { &self.options }
+
+    /// Filter and rank measured pairs
+    pub fn rank(&self, metrics: Vec<PairMetrics>) -> Vec<PairMetrics> {
+        let o = &self.options;
+        let passes = |m: &PairMetrics| {
+            m.volume >= o.min_volume
+                && o.max_spread.map_or(true, |max| m.spread <= max)
+                && o.max_volatility.map_or(true, |max| m.volatility <= max)
+                && o.max_correlation
+                    .map_or(true, |max| m.correlation.map_or(true, |c| c.abs() <= max))
+        };
+        metrics
+            .into_iter()
+            .filter(passes)
+            .sorted_by(|a, b| b.volume.total_cmp(&a.volume))
+            .take(o.top.unwrap_or(usize::MAX))
+            .collect()
+    }
+
+    /// Scan the pairs of the exchange and persist the ranked universe, pairs that cannot be measured are skipped
+    pub async fn scan(&self, api: &dyn Brokerage) -> Result<Universe> {
+        let now = Utc::now();
+        let start = now - self.options.lookback;
+        let hourly = Resolution::new(TimeUnit::Hour, 1);
+        let base_candles = match &self.options.base_pair {
+            Some(base) => Some(api.candles(base.clone(), hourly, start, now).await?),
+            None => None,
+        };
+        let mut metrics = vec![];
+        for pair in filter_pairs(&self.options.exchange, &self.options.pairs)? {
+            let measured = async {
+                let ticker = api.ticker(pair.clone()).await?;
+                let candles = api.candles(pair.clone(), hourly, start, now).await?;
+                Ok::<_, Error>(PairMetrics::new(&ticker, &candles, base_candles.as_deref()))
+            };
+            match measured.await {
+                Ok(m) => metrics.push(m),
+                Err(e) => debug!(pair = %pair, err = %e, "failed to screen pair"),
+            }
+        }
+        let universe = Universe {
+            name: self.options.name.clone(),
+            exchange: self.options.exchange,
+            at: now,
+            pairs: self.rank(metrics),
+        };
+        self.db.put(UNIVERSES_TABLE, universe.name.as_str(), &universe)?;
+        Ok(universe)
+    }
+}
+
+/// The latest universe of a screener
+pub fn load_universe(db: &dyn Storage, name: &str) -> Result<Option<Universe>> {
+    db.ensure_table(UNIVERSES_TABLE)?;
+    match db.get(UNIVERSES_TABLE, name) {
+        Ok(universe) => Ok(Some(universe)),
+        Err(db::Error::NotFound(_)) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// The database of screened universes
+pub fn screener_db<S: AsRef<std::path::Path>>(db_opts: &DbOptions<S>) -> Arc<dyn Storage> {
+    get_or_create(db_opts, SCREENER_DB, vec![])
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use chrono::{Duration, TimeZone, Utc};
+
+    use brokers::exchange::Exchange;
+    use brokers::types::{Candle, Ticker};
+    use db::MemoryKVStore;
+
+    use super::{load_universe, PairMetrics, Screener, ScreenerOptions};
+
+    fn candles(closes: &[f64]) -> Vec<Candle> {
+        let t0 = Utc.with_ymd_and_hms(2022, 1, 1, 0, 0, 0).unwrap();
+        closes
+            .iter()
+            .enumerate()
+            .map(|(i, close)| {
+                let at = t0 + Duration::hours(i as i64);
+                Candle {
+                    event_time: at,
+                    pair: "BTC_USDT".into(),
+                    start_time: at,
+                    end_time: at + Duration::hours(1),
+                    open: *close,
+                    high: *close,
+                    low: *close,
+                    close: *close,
+                    volume: 1.0,
+                    quote_volume: *close,
+                    trade_count: 1,
+                    is_final: true,
+                }
+            })
+            .collect()
+    }
+
+    fn ticker(pair: &str, bid: f64, ask: f64, volume: f64) -> Ticker {
+        Ticker {
+            timestamp: 0,
+            pair: pair.into(),
+            last_trade_price: bid,
+            lowest_ask: ask,
+            highest_bid: bid,
+            volume: Some(volume),
+        }
+    }
+
+    #[test]
+    fn measure_and_rank_pairs() {
+        let base = candles(&[100.0, 101.0, 99.0, 102.0, 103.0]);
+        let eth = PairMetrics::new(
+            &ticker("ETH_USDT", 99.0, 101.0, 5000.0),
+            &candles(&[10.0, 10.1, 9.9, 10.2, 10.3]),
+            Some(base.as_slice()),
+        );
+        assert!((eth.spread - 0.02).abs() < 1e-9);
+        assert!((eth.correlation.unwrap() - 1.0).abs() < 1e-9);
+        assert!(eth.volatility > 0.0);
+        let sol = PairMetrics::new(
+            &ticker("SOL_USDT", 9.99, 10.01, 8000.0),
+            &candles(&[10.0, 10.1, 10.2, 10.1, 10.0]),
+            Some(base.as_slice()),
+        );
+        let ada = PairMetrics::new(&ticker("ADA_USDT", 1.0, 1.001, 100.0), &candles(&[1.0, 1.0]), None);
+        assert!(ada.volatility.is_nan());
+
+        let db = Arc::new(MemoryKVStore::new());
+        let screener = Screener::new(
+            serde_json::from_value(serde_json::json!({
+                "name": "liquid",
+                "exchange": "binance",
+                "min_volume": 1000.0,
+                "max_correlation": 0.9,
+            }))
+            .unwrap(),
+            db.clone(),
+        )
+        .unwrap();
+        let ranked = screener.rank(vec![eth, ada, sol.clone()]);
+        assert_eq!(ranked, vec![sol]);
+
+        assert_eq!(load_universe(db.as_ref(), "liquid").unwrap(), None);
+        let options: &ScreenerOptions = screener.options();
+        assert_eq!(options.exchange, Exchange::Binance);
+        assert_eq!(options.lookback, Duration::days(7));
+    }
+}
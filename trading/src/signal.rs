@@ -1,12 +1,14 @@
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Duration, Utc};
 use uuid::Uuid;
 
 use brokers::prelude::*;
 use brokers::types::MarginSideEffect;
 use util::time::now;
 
+use crate::order_manager::types::OrderDetail;
 use crate::position::{OperationKind, PositionKind};
-use crate::types::{OrderConf, OrderMode, TradeKind};
+use crate::quoting::QuoteFill;
+use crate::types::{OrderConf, SpreadOrderPolicy, TradeKind};
 
 #[derive(Debug, Clone)]
 pub struct TradeSignal {
@@ -42,6 +44,14 @@ pub struct TradeSignal {
     pub asset_type: Option<AssetType>,
     /// Margin side effect type, only set if using [`AssetType::Margin`] or  [`AssetType::IsolatedMargin`]
     pub side_effect: Option<MarginSideEffect>,
+    /// Overrides the order type depending on the spread when converted to an order
+    pub spread_policy: Option<SpreadOrderPolicy>,
+    /// Limit orders will be rejected rather than crossing the book
+    pub post_only: bool,
+    /// Named account to trade with, the main account of the exchange if not set
+    pub account: Option<String>,
+    /// The order is canceled if it is still resting after this long
+    pub max_order_age: Option<Duration>,
 }
 
 impl Default for TradeSignal {
@@ -63,12 +73,22 @@ impl Default for TradeSignal {
             enforcement: None,
             asset_type: None,
             side_effect: None,
+            spread_policy: None,
+            post_only: false,
+            account: None,
+            max_order_age: None,
         }
     }
 }
 
 impl TradeSignal {
     pub fn xch_and_pair(&self) -> (Exchange, Pair) { (self.exchange, self.pair.clone()) }
+
+    /// Age of the signal relative to the event clock `at`
+    pub fn age(&self, at: DateTime<Utc>) -> Duration { at - self.event_time }
+
+    /// Whether the signal is older than `max_age` at time `at`
+    pub fn is_stale(&self, at: DateTime<Utc>, max_age: Duration) -> bool { self.age(at) > max_age }
 }
 
 impl<'a> From<&'a TradeSignal> for AddOrderRequest {
@@ -88,6 +108,9 @@ impl<'a> From<&'a TradeSignal> for AddOrderRequest {
             dry_run: t.dry_mode,
             asset_type: t.asset_type,
             side_effect_type: t.side_effect,
+            post_only: t.post_only,
+            account: t.account.clone(),
+            max_age_ms: t.max_order_age.map(|age| age.num_milliseconds()),
             ..AddOrderRequest::default()
         }
     }
@@ -102,6 +125,99 @@ pub enum ExecutionInstruction {
     LastPrice,
 }
 
+/// Orders emitted together by a strategy and submitted as a single batch, outside of the positions of the portfolio,
+/// such as the legs of an arbitrage
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrderBatch {
+    /// Time of the market event that triggered the batch, on the clock of the exchange
+    pub event_time: DateTime<Utc>,
+    /// Time the market event was received
+    pub received_at: DateTime<Utc>,
+    /// Time the strategy emitted the batch
+    pub signal_time: DateTime<Utc>,
+    pub orders: Vec<AddOrderRequest>,
+}
+
+impl OrderBatch {
+    /// A batch emitted on the market event `le`
+    pub fn new(le: &MarketEventEnvelope, orders: Vec<AddOrderRequest>) -> Self {
+        Self {
+            event_time: le.e.time(),
+            received_at: le.ts,
+            signal_time: now(),
+            orders,
+        }
+    }
+}
+
+/// An order of a submitted batch, with its executions already accounted in the portfolio
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct BatchLeg {
+    pub request: AddOrderRequest,
+    /// Executed base quantity
+    pub executed: f64,
+    /// Executed quote quantity
+    pub executed_value: f64,
+    pub fees: f64,
+    pub filled: bool,
+    pub resolved: bool,
+    /// The leg closes the executions of a batch that did not execute in full
+    pub unwind: bool,
+}
+
+impl BatchLeg {
+    pub fn new(request: AddOrderRequest, unwind: bool) -> Self {
+        Self {
+            request,
+            executed: 0.0,
+            executed_value: 0.0,
+            fees: 0.0,
+            filled: false,
+            resolved: false,
+            unwind,
+        }
+    }
+
+    /// Account the latest state of the order of the leg, returning the executions since the last update
+    pub fn on_order(&mut self, order: &OrderDetail) -> Option<QuoteFill> {
+        let mut fill = None;
+        if order.total_executed_qty > self.executed {
+            let qty = order.total_executed_qty - self.executed;
+            let executed_value = order.weighted_price * order.total_executed_qty;
+            let fees = order.quote_fees();
+            fill = Some(QuoteFill {
+                order_id: order.id.clone(),
+                side: self.request.side,
+                qty,
+                price: (executed_value - self.executed_value) / qty,
+                fees: (fees - self.fees).max(0.0),
+            });
+            self.executed = order.total_executed_qty;
+            self.executed_value = executed_value;
+            self.fees = fees.max(self.fees);
+        }
+        self.filled = order.is_filled();
+        self.resolved = order.is_resolved();
+        fill
+    }
+
+    /// A market order reversing the executions of this leg, if any
+    pub fn unwind(&self) -> Option<AddOrderRequest> {
+        (self.executed > 0.0).then(|| AddOrderRequest {
+            side: match self.request.side {
+                TradeType::Buy => TradeType::Sell,
+                TradeType::Sell => TradeType::Buy,
+            },
+            order_type: OrderType::Market,
+            enforcement: None,
+            quantity: Some(self.executed),
+            price: None,
+            order_id: Uuid::new_v4().to_string(),
+            ..self.request.clone()
+        })
+    }
+}
+
 #[allow(clippy::too_many_arguments)]
 pub fn new_trade_signal(
     pair: Pair,
@@ -127,10 +243,7 @@ pub fn new_trade_signal(
     } else {
         None
     };
-    let (order_type, enforcement) = match order_conf.order_mode {
-        OrderMode::Limit => (OrderType::Limit, Some(OrderEnforcement::FOK)),
-        OrderMode::Market => (OrderType::Market, None),
-    };
+    let (order_type, enforcement) = order_conf.order_mode.order_type();
     TradeSignal {
         trace_id,
         pos_kind,
@@ -148,5 +261,9 @@ pub fn new_trade_signal(
         enforcement,
         asset_type: Some(order_conf.asset_type),
         side_effect: margin_side_effect,
+        spread_policy: order_conf.spread_policy,
+        post_only: order_conf.post_only,
+        account: order_conf.account.clone(),
+        max_order_age: order_conf.max_order_age,
     }
 }
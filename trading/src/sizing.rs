@@ -0,0 +1,233 @@
+//! Position sizing : the quantities of the orders opening positions are computed from the value allocated to a
+//! strategy, rather than by each strategy, for signals that do not set a quantity.
+
+use std::fmt::Debug;
+
+/// A position opened by a batch of signals emitted together, such as the legs of a spread
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SizingLeg {
+    pub price: f64,
+    /// Annualized volatility of the market, if it could be estimated
+    pub volatility: Option<f64>,
+}
+
+/// Sizes the legs of the positions opened together by a strategy
+pub trait PositionSizer: Debug + Send + Sync {
+    /// Base quantity of each leg, given the value allocated to the strategy
+    fn quantities(&self, value: f64, legs: &[SizingLeg]) -> Vec<f64>;
+}
+
+/// Allocates a fixed fraction of the value to each leg
+#[derive(Debug, Clone, Copy)]
+pub struct FixedFractional {
+    fraction: f64,
+}
+
+impl PositionSizer for FixedFractional {
+    fn quantities(&self, value: f64, legs: &[SizingLeg]) -> Vec<f64> {
+        legs.iter().map(|leg| value * self.fraction / leg.price).collect()
+    }
+}
+
+/// Allocates a fraction of the Kelly criterion `win_rate - (1 - win_rate) / payoff_ratio` to each leg,
+/// nothing when the edge is negative
+#[derive(Debug, Clone, Copy)]
+pub struct KellyFraction {
+    win_rate: f64,
+    payoff_ratio: f64,
+    fraction: f64,
+}
+
+impl KellyFraction {
+    /// Fraction of the value allocated to each leg
+    pub fn allocation(&self) -> f64 {
+        let kelly = self.win_rate - (1.0 - self.win_rate) / self.payoff_ratio;
+        (kelly * self.fraction).clamp(0.0, 1.0)
+    }
+}
+
+impl PositionSizer for KellyFraction {
+    fn quantities(&self, value: f64, legs: &[SizingLeg]) -> Vec<f64> {
+        let allocation = self.allocation();
+        legs.iter().map(|leg| value * allocation / leg.price).collect()
+    }
+}
+
+/// Allocates to each leg the fraction of the value that targets an annualized volatility, `max_leverage` is split
+/// across the legs and caps the allocation of each, legs without a volatility estimate are allocated their cap
+#[derive(Debug, Clone, Copy)]
+pub struct VolatilityTargeted {
+    target_volatility: f64,
+    max_leverage: f64,
+}
+
+impl VolatilityTargeted {
+    pub fn new(target_volatility: f64, max_leverage: f64) -> Self {
+        Self {
+            target_volatility,
+            max_leverage,
+        }
+    }
+
+    /// Fraction of the value allocated to one of `legs` legs with an annualized `volatility`
+    #[allow(clippy::cast_precision_loss)]
+    pub fn allocation(&self, volatility: Option<f64>, legs: usize) -> f64 {
+        let max_leverage = self.max_leverage / legs.max(1) as f64;
+        match volatility {
+            Some(vol) if vol > 0.0 => (self.target_volatility / vol).min(max_leverage),
+            _ => max_leverage,
+        }
+    }
+}
+
+impl PositionSizer for VolatilityTargeted {
+    fn quantities(&self, value: f64, legs: &[SizingLeg]) -> Vec<f64> {
+        legs.iter()
+            .map(|leg| value * self.allocation(leg.volatility, legs.len()) / leg.price)
+            .collect()
+    }
+}
+
+/// Splits the value across legs in inverse proportion to their volatility, so that each leg contributes the
+/// same risk, the value is split equally unless the volatility of every leg is estimated
+#[derive(Debug, Clone, Copy)]
+pub struct RiskParity;
+
+impl PositionSizer for RiskParity {
+    #[allow(clippy::cast_precision_loss)]
+    fn quantities(&self, value: f64, legs: &[SizingLeg]) -> Vec<f64> {
+        let inverse_vols: Option<Vec<f64>> = legs
+            .iter()
+            .map(|leg| leg.volatility.filter(|vol| *vol > 0.0).map(|vol| 1.0 / vol))
+            .collect();
+        let weights = match inverse_vols {
+            Some(inverse_vols) => {
+                let total: f64 = inverse_vols.iter().sum();
+                inverse_vols.into_iter().map(|w| w / total).collect()
+            }
+            None => vec![1.0 / legs.len() as f64; legs.len()],
+        };
+        legs.iter()
+            .zip(weights)
+            .map(|(leg, weight)| value * weight / leg.price)
+            .collect()
+    }
+}
+
+fn default_kelly_fraction() -> f64 { 0.5 }
+
+fn default_max_leverage() -> f64 { 1.0 }
+
+/// Configuration of a position sizer
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PositionSizerOptions {
+    FixedFractional {
+        fraction: f64,
+    },
+    Kelly {
+        win_rate: f64,
+        payoff_ratio: f64,
+        /// Fraction of the Kelly criterion, half Kelly by default
+        #[serde(default = "default_kelly_fraction")]
+        fraction: f64,
+    },
+    VolatilityTargeted {
+        target_volatility: f64,
+        #[serde(default = "default_max_leverage")]
+        max_leverage: f64,
+    },
+    RiskParity,
+}
+
+impl PositionSizerOptions {
+    pub fn sizer(&self) -> anyhow::Result<Box<dyn PositionSizer>> {
+        Ok(match *self {
+            PositionSizerOptions::FixedFractional { fraction } => {
+                if fraction <= 0.0 {
+                    return Err(anyhow!("the fraction of a fixed fractional sizer is positive"));
+                }
+                Box::new(FixedFractional { fraction })
+            }
+            PositionSizerOptions::Kelly {
+                win_rate,
+                payoff_ratio,
+                fraction,
+            } => {
+                if !(0.0..=1.0).contains(&win_rate) || payoff_ratio <= 0.0 || fraction <= 0.0 {
+                    return Err(anyhow!(
+                        "a kelly sizer has a win rate in [0, 1], a positive payoff ratio and a positive fraction"
+                    ));
+                }
+                Box::new(KellyFraction {
+                    win_rate,
+                    payoff_ratio,
+                    fraction,
+                })
+            }
+            PositionSizerOptions::VolatilityTargeted {
+                target_volatility,
+                max_leverage,
+            } => {
+                if target_volatility <= 0.0 || max_leverage <= 0.0 {
+                    return Err(anyhow!(
+                        "a volatility targeted sizer has a positive target volatility and maximum leverage"
+                    ));
+                }
+                Box::new(VolatilityTargeted::new(target_volatility, max_leverage))
+            }
+            PositionSizerOptions::RiskParity => Box::new(RiskParity),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{PositionSizerOptions, SizingLeg};
+
+    #[test]
+    fn size_legs() {
+        let legs = [
+            SizingLeg {
+                price: 10.0,
+                volatility: Some(0.2),
+            },
+            SizingLeg {
+                price: 100.0,
+                volatility: Some(0.6),
+            },
+        ];
+        let sizer = |options: PositionSizerOptions| options.sizer().unwrap();
+        assert_eq!(
+            sizer(PositionSizerOptions::FixedFractional { fraction: 0.5 }).quantities(1000.0, &legs),
+            vec![50.0, 5.0]
+        );
+        // Kelly of 0.6 - 0.4 / 2 = 0.4, halved
+        let kelly = sizer(PositionSizerOptions::Kelly {
+            win_rate: 0.6,
+            payoff_ratio: 2.0,
+            fraction: 0.5,
+        })
+        .quantities(1000.0, &legs);
+        assert!((kelly[0] - 20.0).abs() < 1e-9);
+        // The maximum leverage of 2 is split across both legs
+        let targeted = sizer(PositionSizerOptions::VolatilityTargeted {
+            target_volatility: 0.3,
+            max_leverage: 2.0,
+        })
+        .quantities(1000.0, &legs);
+        assert!((targeted[0] - 100.0).abs() < 1e-9);
+        assert!((targeted[1] - 5.0).abs() < 1e-9);
+        let single = sizer(PositionSizerOptions::VolatilityTargeted {
+            target_volatility: 0.3,
+            max_leverage: 2.0,
+        })
+        .quantities(1000.0, &legs[..1]);
+        assert!((single[0] - 150.0).abs() < 1e-9);
+        // Weights of 3/4 and 1/4
+        let parity = sizer(PositionSizerOptions::RiskParity).quantities(1000.0, &legs);
+        assert!((parity[0] - 75.0).abs() < 1e-9);
+        assert!((parity[1] - 2.5).abs() < 1e-9);
+        assert!(PositionSizerOptions::FixedFractional { fraction: 0.0 }.sizer().is_err());
+    }
+}
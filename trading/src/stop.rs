@@ -1,3 +1,5 @@
+use brokers::types::{AddOrderRequest, ExchangeCapabilities, OrderType};
+
 use crate::position::PositionKind;
 use util::time::now;
 
@@ -112,10 +114,113 @@ impl TrailingStopper<f64> {
     pub fn reset(&mut self) { self.last_top = None; }
 }
 
+/// Trailing stop of positions, returns are relative to the value of positions when they opened
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub struct TrailingStopOptions {
+    /// Return from which the stop trails the position
+    pub trailing_stop_start: f64,
+    /// Drop of the return from its top that closes the position
+    pub trailing_stop_loss: f64,
+    /// Return below which the position is closed
+    pub stop_loss: f64,
+}
+
+impl TrailingStopOptions {
+    /// The trailing stop of a position opened on an exchange with these `capabilities`
+    pub fn stop(&self, capabilities: &ExchangeCapabilities) -> TrailingStop {
+        TrailingStop::new(
+            capabilities,
+            self.trailing_stop_start,
+            self.trailing_stop_loss,
+            self.stop_loss,
+        )
+    }
+}
+
+/// A trailing stop executed by the exchange when it supports trailing stop orders,
+/// emulated locally with a [`TrailingStopper`] otherwise
+#[derive(Debug)]
+pub enum TrailingStop {
+    Exchange {
+        trailing_stop_start: f64,
+        trailing_stop_loss: f64,
+        stop_loss: f64,
+    },
+    Local(TrailingStopper<f64>),
+}
+
+impl TrailingStop {
+    pub fn new(
+        capabilities: &ExchangeCapabilities,
+        trailing_stop_start: f64,
+        trailing_stop_loss: f64,
+        stop_loss: f64,
+    ) -> Self {
+        if capabilities.supports_order_type(OrderType::TrailingStop) {
+            Self::Exchange {
+                trailing_stop_start,
+                trailing_stop_loss,
+                stop_loss,
+            }
+        } else {
+            Self::Local(TrailingStopper::new(trailing_stop_start, trailing_stop_loss, stop_loss))
+        }
+    }
+
+    pub fn is_exchange_side(&self) -> bool { matches!(self, Self::Exchange { .. }) }
+
+    /// Returns `Some(StopEvent)` if the stop conditions are matched, `None` otherwise,
+    /// exchange side trailing stops only leave the stop loss to check
+    pub fn should_stop(&mut self, ret: f64) -> Option<StopEvent> {
+        match self {
+            Self::Exchange { stop_loss, .. } => (ret < *stop_loss).then(|| StopEvent::Loss),
+            Self::Local(stopper) => stopper.should_stop(ret),
+        }
+    }
+
+    pub fn reset(&mut self) {
+        if let Self::Local(stopper) = self {
+            stopper.reset();
+        }
+    }
+
+    /// The exchange side trailing stop closing `close`, a request to close a position opened at `open_price`,
+    /// activated once the position returns the trailing stop start. None if trailing stops are emulated locally.
+    pub fn exit_order(
+        &self,
+        pos_kind: PositionKind,
+        open_price: f64,
+        close: AddOrderRequest,
+    ) -> Option<AddOrderRequest> {
+        match self {
+            Self::Exchange {
+                trailing_stop_start,
+                trailing_stop_loss,
+                ..
+            } => {
+                let activation_price = match pos_kind {
+                    PositionKind::Long => open_price * (1.0 + trailing_stop_start),
+                    PositionKind::Short => open_price * (1.0 - trailing_stop_start),
+                };
+                Some(AddOrderRequest {
+                    order_type: OrderType::TrailingStop,
+                    price: None,
+                    stop_price: Some(activation_price),
+                    trailing_delta: Some(*trailing_stop_loss),
+                    ..close
+                })
+            }
+            Self::Local(_) => None,
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
+    use brokers::types::{AddOrderRequest, ExchangeCapabilities, OrderType};
+
     use crate::position::PositionKind;
-    use crate::stop::{FixedStopper, PositionStopper, StopEvent, TrailingStopper};
+    use crate::stop::{FixedStopper, PositionStopper, StopEvent, TrailingStop, TrailingStopper};
 
     #[test]
     fn test_fixed_stopper() {
@@ -146,4 +251,32 @@ mod test {
         // reaches the stop loss
         assert_eq!(stopper.should_stop(-0.2), Some(StopEvent::Loss));
     }
+
+    #[test]
+    fn test_trailing_stop_selection() {
+        let mut local = TrailingStop::new(&ExchangeCapabilities::default(), 0.5, 0.01, -0.1);
+        assert!(!local.is_exchange_side());
+        assert_eq!(local.should_stop(0.6), None);
+        assert_eq!(local.should_stop(0.58), Some(StopEvent::TrailingStop));
+        assert!(local
+            .exit_order(PositionKind::Long, 100.0, AddOrderRequest::default())
+            .is_none());
+
+        let capabilities = ExchangeCapabilities {
+            order_types: vec![OrderType::Market, OrderType::TrailingStop],
+            ..ExchangeCapabilities::default()
+        };
+        let mut exchange = TrailingStop::new(&capabilities, 0.5, 0.01, -0.1);
+        assert!(exchange.is_exchange_side());
+        // The exchange trails the price, only the stop loss is left
+        assert_eq!(exchange.should_stop(0.6), None);
+        assert_eq!(exchange.should_stop(0.58), None);
+        assert_eq!(exchange.should_stop(-0.2), Some(StopEvent::Loss));
+        let order = exchange
+            .exit_order(PositionKind::Long, 100.0, AddOrderRequest::default())
+            .unwrap();
+        assert_eq!(order.order_type, OrderType::TrailingStop);
+        assert_eq!(order.stop_price, Some(150.0));
+        assert_eq!(order.trailing_delta, Some(0.01));
+    }
 }
@@ -2,10 +2,11 @@
 
 use brokers::broker::Subject;
 use brokers::exchange::Exchange;
+use chrono::Duration;
 use uuid::Uuid;
 
-use brokers::types::{AccountEventEnveloppe, AccountType, AddOrderRequest, AssetType, MarginSideEffect, MarketEvent,
-                     OrderEnforcement, OrderType, TradeType};
+use brokers::types::{AccountEventEnveloppe, AccountType, AddOrderRequest, AssetType, ExchangeCapabilities,
+                     MarginSideEffect, MarketEvent, OrderEnforcement, OrderType, TradeType};
 
 use crate::signal::ExecutionInstruction;
 
@@ -56,6 +57,57 @@ impl Default for OrderMode {
     fn default() -> Self { Self::Limit }
 }
 
+impl OrderMode {
+    /// The order type and enforcement used for this mode
+    pub fn order_type(&self) -> (OrderType, Option<OrderEnforcement>) {
+        match self {
+            OrderMode::Limit => (OrderType::Limit, Some(OrderEnforcement::FOK)),
+            OrderMode::Market => (OrderType::Market, None),
+        }
+    }
+}
+
+/// Chooses between market and limit orders depending on the current spread,
+/// crossing the spread is only worth it if it is tight enough, otherwise a limit order rests at the mid price
+#[derive(Copy, Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct SpreadOrderPolicy {
+    /// relative spread `(ask - bid) / mid` under which market orders are used
+    pub max_market_spread: f64,
+}
+
+impl SpreadOrderPolicy {
+    pub fn new(max_market_spread: f64) -> Self { Self { max_market_spread } }
+
+    /// Market if the spread between `bid` and `ask` is tight, limit otherwise
+    pub fn order_mode(&self, bid: f64, ask: f64) -> OrderMode {
+        let mid = (ask + bid) / 2.0;
+        if mid > 0.0 && (ask - bid) / mid < self.max_market_spread {
+            OrderMode::Market
+        } else {
+            OrderMode::Limit
+        }
+    }
+
+    /// Apply the policy to `request` with the spread between `bid` and `ask`, a market order if it is tight,
+    /// otherwise a limit order repriced at the mid price and left resting, the request is left as is without a book
+    pub fn apply(&self, request: &mut AddOrderRequest, bid: f64, ask: f64) {
+        if bid <= 0.0 || ask < bid {
+            return;
+        }
+        match self.order_mode(bid, ask) {
+            OrderMode::Market => {
+                request.order_type = OrderType::Market;
+                request.enforcement = None;
+            }
+            OrderMode::Limit => {
+                request.order_type = OrderType::Limit;
+                request.enforcement = Some(OrderEnforcement::GTC);
+                request.price = Some((ask + bid) / 2.0);
+            }
+        }
+    }
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
 pub struct TradeOperation {
     pub id: String,
@@ -99,15 +151,9 @@ impl From<TradeOperation> for AddOrderRequest {
             side_effect_type: to.side_effect,
             ..AddOrderRequest::default()
         };
-        match to.mode {
-            OrderMode::Limit => {
-                request.order_type = OrderType::Limit;
-                request.enforcement = Some(OrderEnforcement::FOK);
-            }
-            OrderMode::Market => {
-                request.order_type = OrderType::Market;
-            }
-        }
+        let (order_type, enforcement) = to.mode.order_type();
+        request.order_type = order_type;
+        request.enforcement = enforcement;
 
         request.asset_type = Some(to.asset_type);
         request
@@ -148,6 +194,22 @@ pub struct OrderConf {
     /// execution instructions for the portfolio, default is None
     #[allow(dead_code)]
     pub execution_instruction: Option<ExecutionInstruction>,
+    /// if set, overrides `order_mode` depending on the spread of the latest book, default is None
+    #[serde(default)]
+    pub spread_policy: Option<SpreadOrderPolicy>,
+    /// limit orders are rejected rather than crossing the book, to only pay maker fees, default is false
+    #[serde(default)]
+    pub post_only: bool,
+    /// named account of the exchange to trade with, default is the main account
+    #[serde(default)]
+    pub account: Option<String>,
+    /// resting orders older than this are canceled, default is None
+    #[serde(
+        default,
+        deserialize_with = "util::ser::string_duration_chrono_opt",
+        serialize_with = "util::ser::encode_duration_str_opt"
+    )]
+    pub max_order_age: Option<Duration>,
 }
 
 impl Default for OrderConf {
@@ -157,10 +219,38 @@ impl Default for OrderConf {
             order_mode: OrderMode::Limit,
             asset_type: AssetType::Spot,
             execution_instruction: None,
+            spread_policy: None,
+            post_only: false,
+            account: None,
+            max_order_age: None,
         }
     }
 }
 
+impl OrderConf {
+    /// # Errors
+    ///
+    /// if the exchange cannot place the orders of this configuration
+    pub fn check_capabilities(&self, capabilities: &ExchangeCapabilities) -> brokers::error::Result<()> {
+        capabilities.check_order_type(self.order_mode.order_type().0)?;
+        if self.spread_policy.is_some() {
+            capabilities.check_order_type(OrderType::Market)?;
+            capabilities.check_order_type(OrderType::Limit)?;
+        }
+        if self.post_only && !capabilities.post_only {
+            return Err(brokers::error::Error::UnsupportedCapability(
+                "post only orders".to_string(),
+            ));
+        }
+        if matches!(self.asset_type, AssetType::Margin | AssetType::IsolatedMargin) && !capabilities.margin {
+            return Err(brokers::error::Error::UnsupportedCapability(
+                "margin trading".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct MarketStat {
     #[serde(deserialize_with = "util::ser::parse_null_to_f64")]
@@ -191,20 +281,72 @@ impl From<&MarketEvent> for MarketStat {
 #[derive(Debug, Hash, Eq, PartialEq)]
 pub struct AccountChannel {
     pub xch: Exchange,
+    /// Named account, the main account of the exchange if not set
+    pub account: Option<String>,
     pub account_type: AccountType,
 }
 
 impl AccountChannel {
-    pub fn new(xch: Exchange, account_type: AccountType) -> Self { AccountChannel { xch, account_type } }
+    pub fn new(xch: Exchange, account_type: AccountType) -> Self { Self::for_account(xch, None, account_type) }
+
+    pub fn for_account(xch: Exchange, account: Option<String>, account_type: AccountType) -> Self {
+        AccountChannel {
+            xch,
+            account,
+            account_type,
+        }
+    }
 }
 
 impl From<AccountEventEnveloppe> for AccountChannel {
     fn from(msg: AccountEventEnveloppe) -> Self {
         Self {
             xch: msg.xchg,
+            account: msg.account,
             account_type: msg.account_type,
         }
     }
 }
 
 impl Subject<AccountEventEnveloppe> for AccountChannel {}
+
+#[cfg(test)]
+mod test {
+    use brokers::types::{AssetType, ExchangeCapabilities, OrderEnforcement, OrderType};
+
+    use crate::types::{OrderConf, OrderMode, SpreadOrderPolicy};
+
+    #[test]
+    fn test_spread_order_policy() {
+        let policy = SpreadOrderPolicy::new(0.001);
+        assert_eq!(policy.order_mode(100.0, 100.05), OrderMode::Market);
+        assert_eq!(policy.order_mode(100.0, 101.0), OrderMode::Limit);
+        assert_eq!(policy.order_mode(0.0, 0.0), OrderMode::Limit);
+        assert_eq!(OrderMode::Market.order_type(), (OrderType::Market, None));
+        assert_eq!(
+            OrderMode::Limit.order_type(),
+            (OrderType::Limit, Some(OrderEnforcement::FOK))
+        );
+    }
+
+    #[test]
+    fn test_order_conf_capabilities() {
+        let capabilities = ExchangeCapabilities::default();
+        assert!(OrderConf::default().check_capabilities(&capabilities).is_ok());
+        let post_only = OrderConf {
+            post_only: true,
+            ..OrderConf::default()
+        };
+        assert!(post_only.check_capabilities(&capabilities).is_err());
+        let margin = OrderConf {
+            asset_type: AssetType::Margin,
+            ..OrderConf::default()
+        };
+        assert!(margin.check_capabilities(&capabilities).is_err());
+        let margin_capabilities = ExchangeCapabilities {
+            margin: true,
+            ..ExchangeCapabilities::default()
+        };
+        assert!(margin.check_capabilities(&margin_capabilities).is_ok());
+    }
+}
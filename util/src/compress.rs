@@ -51,7 +51,7 @@ impl Compression {
 
     pub fn wrap_reader<R: 'static + BufRead + Send>(&self, r: R) -> Box<dyn BufRead + Send> {
         match self.algorithm {
-            CompressionType::Gz => Box::new(BufReader::new(flate2::bufread::GzDecoder::new(r))),
+            CompressionType::Gz => Box::new(BufReader::new(flate2::bufread::MultiGzDecoder::new(r))),
             CompressionType::Z => Box::new(BufReader::new(flate2::bufread::ZlibDecoder::new(r))),
             CompressionType::Deflate => Box::new(BufReader::new(flate2::bufread::DeflateDecoder::new(r))),
             CompressionType::None => Box::new(r),
@@ -60,6 +60,9 @@ impl Compression {
         }
     }
 
+    /// Whether compressed streams can be appended to a file and read back as one stream
+    pub fn is_appendable(&self) -> bool { !matches!(self.algorithm, CompressionType::Z | CompressionType::Deflate) }
+
     pub fn wrap_ext<P: AsRef<Path>>(&self, path: P) -> PathBuf {
         let current_path = path.as_ref();
         let ext = match self.algorithm {
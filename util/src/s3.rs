@@ -1,17 +1,30 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::{Command, Output};
 
-pub fn download_file(key: &str, dest: PathBuf) -> std::io::Result<Output> {
-    let profile = "btcfeed";
-    let from_path = format!("s3://btcfeed/{}", key);
+/// Bucket of the market data feeds
+pub const DEFAULT_BUCKET: &str = "btcfeed";
+const PROFILE: &str = "btcfeed";
+const ENDPOINT: &str = "https://nyc3.digitaloceanspaces.com";
+
+fn s3_cp<S: AsRef<std::ffi::OsStr>, D: AsRef<std::ffi::OsStr>>(from: S, to: D) -> std::io::Result<Output> {
     Command::new("aws")
         .arg("s3")
         .arg("cp")
         .arg("--profile")
-        .arg(profile)
+        .arg(PROFILE)
         .arg("--endpoint")
-        .arg("https://nyc3.digitaloceanspaces.com")
-        .arg(from_path)
-        .arg(dest)
+        .arg(ENDPOINT)
+        .arg(from)
+        .arg(to)
         .output()
 }
+
+pub fn download_file(key: &str, dest: PathBuf) -> std::io::Result<Output> {
+    let from_path = format!("s3://{}/{}", DEFAULT_BUCKET, key);
+    s3_cp(from_path, dest)
+}
+
+pub fn upload_file(src: &Path, bucket: &str, key: &str) -> std::io::Result<Output> {
+    let to_path = format!("s3://{}/{}", bucket, key);
+    s3_cp(src, to_path)
+}
@@ -1,5 +1,5 @@
 use std::fmt::Debug;
-use std::fs::File;
+use std::fs::{File, OpenOptions};
 use std::io::{BufRead, BufReader, BufWriter, Read, Write};
 use std::marker::PhantomData;
 use std::path::{Path, PathBuf};
@@ -175,6 +175,7 @@ pub fn write_as_seq<P: AsRef<Path>, T: Serialize>(out_file: P, data: &[T]) -> Re
 pub struct StreamSerializerWriter<T, S> {
     pub out_file: PathBuf,
     pub compression: Compression,
+    append: bool,
     sink: UnboundedSender<T>,
     stream: RwLock<UnboundedReceiverStream<T>>,
     finish_token: CancellationToken,
@@ -194,6 +195,7 @@ impl<T: 'static + DeserializeOwned + Serialize + Debug + Send, S: JsonSerde + Se
         Self {
             out_file: out_file.as_ref().to_path_buf(),
             compression,
+            append: false,
             sink,
             stream: RwLock::new(UnboundedReceiverStream::new(rcv)),
             finish_token: CancellationToken::new(),
@@ -203,6 +205,13 @@ impl<T: 'static + DeserializeOwned + Serialize + Debug + Send, S: JsonSerde + Se
         }
     }
 
+    /// Append values to the file if it exists instead of replacing it
+    #[must_use]
+    pub fn appending(mut self) -> Self {
+        self.append = true;
+        self
+    }
+
     /// Push a new value to be serialized and written
     pub fn push(&self, value: T) -> Result<(), SendError<T>> { self.sink.send(value) }
 
@@ -225,7 +234,12 @@ impl<T: 'static + DeserializeOwned + Serialize + Debug + Send, S: JsonSerde + Se
     /// Will panic if `out_file` cannot be opened and written to
     pub async fn start(&self) {
         let out_file = self.compression.wrap_ext(&self.out_file);
-        let logs_f = BufWriter::new(std::fs::File::create(out_file).unwrap());
+        let file = if self.append {
+            OpenOptions::new().create(true).append(true).open(out_file)
+        } else {
+            File::create(out_file)
+        };
+        let logs_f = BufWriter::new(file.unwrap());
         let mut writer = self.compression.wrap_writer(logs_f);
         let mut lock = self.stream.write().await;
         S::serialize_stream(&mut writer, &mut lock, self.finish_token.clone()).await;